@@ -0,0 +1,106 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The benchmarks of parsing, evaluating and printing, run them by
+// `cargo bench --bench query`, and compare the reports of two commits
+// (criterion keeps the last one in `target/criterion`) to catch
+// the regressions, e.g.
+//
+// ```
+// git checkout main && cargo bench --bench query -- --save-baseline main
+// git checkout topic && cargo bench --bench query -- --baseline main
+// ```
+//
+// The documents are generated, so they are the same on every machine.
+// The queries are also valid jq programs, so they can be compared with
+// jq on the same data, i.e. the document of `users_text()` converted to
+// JSON by `aq --to json`:
+//
+// - iterate: `.[] | .name`
+// - select: `map(select(.age > 30)) | length`
+// - group: `group_by(.city) | map(length)`
+// - reduce: `reduce .[] as $u (0; . + $u.age)`
+// - update: `map(.age += 1)`
+// - unique: `[.[].tags[]] | unique`
+// - missing: `map(.email)`, i.e. a list of the nulls
+
+use ason_query::{
+    input::{parse_input, ParseOptions},
+    printer::{print_value, PrintOptions},
+    Query, Value,
+};
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const USERS: usize = 10_000;
+
+const QUERIES: &[(&str, &str)] = &[
+    ("iterate", ".[] | .name"),
+    ("select", "map(select(.age > 30)) | length"),
+    ("group", "group_by(.city) | map(length)"),
+    ("reduce", "reduce .[] as $u (0; . + $u.age)"),
+    ("update", "map(.age += 1)"),
+    ("unique", "[.[].tags[]] | unique"),
+    ("missing", "map(.email)"),
+];
+
+// a list of the user records, e.g.
+// `{id: 1, name: "user1", age: 21, city: "city1", tags: ["t1", "t2"], active: true}`
+fn users_text(count: usize) -> String {
+    let mut text = String::from("[\n");
+    for id in 0..count {
+        text.push_str(&format!(
+            "    {{id: {}, name: \"user{}\", age: {}, city: \"city{}\", tags: [\"t{}\", \"t{}\"], active: {}}}\n",
+            id,
+            id,
+            20 + id % 50,
+            id % 100,
+            id % 7,
+            id % 13,
+            id % 3 == 0
+        ));
+    }
+    text.push(']');
+    text
+}
+
+fn parse(text: &str) -> Value {
+    parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let text = users_text(USERS);
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_function("users", |b| b.iter(|| parse(black_box(&text))));
+    group.finish();
+}
+
+fn bench_query(c: &mut Criterion) {
+    let document = parse(&users_text(USERS));
+    let mut group = c.benchmark_group("query");
+    group.throughput(Throughput::Elements(USERS as u64));
+    for (name, text) in QUERIES {
+        let query = Query::compile(text).unwrap();
+        group.bench_function(*name, |b| {
+            b.iter(|| query.run_value(black_box(document.clone())).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn bench_print(c: &mut Criterion) {
+    let document = parse(&users_text(USERS));
+    let options = PrintOptions::default();
+    let mut group = c.benchmark_group("print");
+    group.throughput(Throughput::Elements(USERS as u64));
+    group.bench_function("users", |b| {
+        b.iter(|| print_value(black_box(&document), &options))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse, bench_query, bench_print);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+/*
+ * Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+ *
+ * This Source Code Form is subject to the terms of
+ * the Mozilla Public License version 2.0 and additional exceptions,
+ * more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+ */
+
+/*
+ * The C interface of the ASON query engine, the library is built by
+ * `cargo build --release --lib --no-default-features --features ffi`.
+ *
+ * Each function which can fail returns an `AqStatus`, `AQ_OK` on success,
+ * and on failure the message of the error is written to `*error` (if
+ * `error` is not NULL), which is freed by `aq_free_string`. The output
+ * arguments are written only on success, e.g.
+ *
+ *     AqQuery *query;
+ *     AqResult *result;
+ *     char *error = NULL;
+ *
+ *     if (aq_compile(".server.port", &query, &error) != AQ_OK) {
+ *         fprintf(stderr, "%s\n", error);
+ *         aq_free_string(error);
+ *         return 1;
+ *     }
+ *
+ *     if (aq_run_on_string(query, "{server: {port: 8080}}", NULL, &result, &error) == AQ_OK) {
+ *         for (size_t i = 0; i < aq_result_count(result); i++) {
+ *             printf("%s\n", aq_result_get(result, i));
+ *         }
+ *         aq_free_result(result);
+ *     } else {
+ *         fprintf(stderr, "%s\n", error);
+ *         aq_free_string(error);
+ *     }
+ *     aq_free_query(query);
+ *
+ * The strings are NUL-terminated UTF-8. A compiled query can be run
+ * many times, but not by multiple threads at the same time.
+ */
+
+#ifndef ASON_QUERY_H
+#define ASON_QUERY_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef int32_t AqStatus;
+
+#define AQ_OK 0
+
+/* A NULL pointer, a string which is not valid UTF-8, or an unknown format. */
+#define AQ_INVALID_ARGUMENT 1
+
+/* The query cannot be compiled. */
+#define AQ_QUERY_ERROR 2
+
+/* The document cannot be parsed. */
+#define AQ_DOCUMENT_ERROR 3
+
+/* The evaluation fails, or a result cannot be written in the format. */
+#define AQ_EVALUATION_ERROR 4
+
+#define AQ_INTERNAL_ERROR 5
+
+typedef struct AqQuery AqQuery;
+typedef struct AqResult AqResult;
+
+/* Compile the query, the compiled query is written to `*query`. */
+AqStatus aq_compile(const char *source, AqQuery **query, char **error);
+
+/*
+ * Run the query on the document, the results are written to `*result`.
+ *
+ * `format` is the format of the document, e.g. "json", or NULL for ASON,
+ * and the results are written in the same format (JSON for JSON5 and JSONC).
+ */
+AqStatus aq_run_on_string(const AqQuery *query, const char *document, const char *format,
+                          AqResult **result, char **error);
+
+/* The number of the results. */
+size_t aq_result_count(const AqResult *result);
+
+/*
+ * The text of the result at the index, or NULL if the index is out of
+ * range, the text is owned by the result.
+ */
+const char *aq_result_get(const AqResult *result, size_t index);
+
+void aq_free_result(AqResult *result);
+void aq_free_query(AqQuery *query);
+
+/* Free the message of an error. */
+void aq_free_string(char *text);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ASON_QUERY_H */
@@ -0,0 +1,195 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use crate::value::Value;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expression {
+    /// `.`
+    Identity,
+
+    /// `..`, i.e. `recurse`
+    RecurseDefault,
+
+    /// Number, string, `true`, `false` and `null`.
+    Literal(Value),
+
+    /// A string literal with interpolations, e.g. `"Order \(.id)"`.
+    Interpolation(Vec<InterpolationPart>),
+
+    /// `@name` formats the input as string, e.g. `@ason(4)`,
+    /// and `@name "template"` formats the interpolations in the template.
+    Format {
+        name: String,
+        argument: Option<Box<Expression>>,
+        template: Option<Vec<InterpolationPart>>,
+    },
+
+    /// `.name`, `.["name"]`, `.[0]`, `.0`, `$var[0]` etc.
+    Index(Box<Expression>, Box<Expression>),
+
+    /// `.[from:to]`, both ends are optional.
+    Slice(
+        Box<Expression>,
+        Option<Box<Expression>>,
+        Option<Box<Expression>>,
+    ),
+
+    /// `.[]`
+    Iterate(Box<Expression>),
+
+    /// `try body catch handler`, and the postfix `?` which has no handler.
+    Try(Box<Expression>, Option<Box<Expression>>),
+
+    /// `left | right`
+    Pipe(Box<Expression>, Box<Expression>),
+
+    /// `left, right`
+    Comma(Box<Expression>, Box<Expression>),
+
+    /// `-expr`
+    Negate(Box<Expression>),
+
+    Binary(BinaryOperator, Box<Expression>, Box<Expression>),
+
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+
+    /// `left // right`
+    Alternative(Box<Expression>, Box<Expression>),
+
+    /// `path = value`, `path |= update`, `path += value` etc.,
+    /// the left side must be a path expression.
+    Assign(AssignOperator, Box<Expression>, Box<Expression>),
+
+    /// `[expr]`, the inner expression is absent for `[]`.
+    List(Option<Box<Expression>>),
+
+    /// `{key: value, ...}`, the shorthand entries (e.g. `{id, $name}`)
+    /// are expanded by the parser.
+    Object(Vec<(Expression, Expression)>),
+
+    /// `Type::Member`, `Type::Member(value)`, `Type::Member(a, b)` and
+    /// `Type::Member{key: value, ...}`, e.g. `Option::Some(.id)`.
+    Variant {
+        type_name: String,
+        member_name: String,
+        payload: VariantPayload,
+    },
+
+    /// `if cond then body elif cond then body else body end`,
+    /// the `else` branch is absent when omitted.
+    If(Vec<(Expression, Expression)>, Option<Box<Expression>>),
+
+    /// `reduce source as $x (init; update)`
+    Reduce {
+        source: Box<Expression>,
+        pattern: Pattern,
+        init: Box<Expression>,
+        update: Box<Expression>,
+    },
+
+    /// `foreach source as $x (init; update; extract)`
+    Foreach {
+        source: Box<Expression>,
+        pattern: Pattern,
+        init: Box<Expression>,
+        update: Box<Expression>,
+        extract: Option<Box<Expression>>,
+    },
+
+    /// `source as $x | body`
+    Binding {
+        source: Box<Expression>,
+        pattern: Pattern,
+        body: Box<Expression>,
+    },
+
+    /// `def name(params): body; rest`
+    FunctionDefinition(FunctionDefinition, Box<Expression>),
+
+    /// `name`, `name(arg1; arg2)`
+    Call(String, Vec<Expression>),
+
+    /// `$name`
+    Variable(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum VariantPayload {
+    Empty,
+    Value(Box<Expression>),
+
+    /// The items are separated by commas, e.g. `Point::P(.x, .y)`.
+    Tuple(Vec<Expression>),
+
+    /// An `Expression::Object`.
+    Object(Box<Expression>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum InterpolationPart {
+    Literal(String),
+    Expression(Expression),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AssignOperator {
+    /// `=`, the right side is evaluated with the input of the whole expression.
+    Set,
+
+    /// `|=`, the right side is evaluated with the old value at each path.
+    Update,
+
+    /// `+=`, `-=`, `*=`, `/=` and `%=`, the right side is evaluated
+    /// with the input of the whole expression.
+    Arithmetic(BinaryOperator),
+
+    /// `//=`
+    Alternative,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Variable(String),
+
+    /// `[$a, $b]` or `($a, $b)`, it destructures both lists and tuples,
+    /// the missing elements are bound to `null`.
+    Elements(Vec<Pattern>),
+}
+
+/// The parameters are filters (closures), the value parameters
+/// (e.g. `def f($a): ...`) are expanded by the parser into
+/// `def f(a): a as $a | ...`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Box<Expression>,
+}
+
+/// `import "lib/strings" as str;`, the functions of the module
+/// are called with the alias, e.g. `str::trim`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub path: String,
+    pub alias: String,
+}
@@ -0,0 +1,549 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The interactive browser of a document, i.e. `aq browse FILE`.
+//
+// The document is shown as a tree, and the path expression of the
+// selected node is shown in the status line, e.g. `.users[3].email`,
+// so it can be the start of the query. The keys:
+//
+// - `↑`/`k`, `↓`/`j`, `PageUp`, `PageDown`, `Home` and `End` move the selection,
+// - `→`/`l` expands the container, `←`/`h` collapses it (or selects the parent),
+//   and `Enter`/`Space` toggles it,
+// - `/` searches the keys and the values as typing, `n`/`N` finds the
+//   next/previous match,
+// - `:` edits the query (it starts with the path of the selected node),
+//   the results are shown as typing,
+// - `q` and `Esc` quit.
+
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+
+use crate::{
+    error::Error,
+    path::path_to_string,
+    query::Query,
+    value::{escape_string, is_identifier, Value},
+};
+
+/// A line of the tree.
+pub struct Row {
+    pub path: Vec<Value>,
+    pub depth: usize,
+    pub text: String,
+
+    // the number of the children, `None` for the scalars.
+    pub children: Option<usize>,
+    pub expanded: bool,
+}
+
+enum Mode {
+    Normal,
+    Search(String),
+    Query(String),
+}
+
+pub struct Browser {
+    root: Value,
+
+    // the path expressions of the expanded containers.
+    expanded: HashSet<String>,
+    rows: Vec<Row>,
+    selected: usize,
+    offset: usize,
+
+    // the number of the rows in a screen.
+    page: usize,
+    mode: Mode,
+    last_search: String,
+
+    // the results of the query, or the message of the search.
+    message: String,
+}
+
+impl Browser {
+    pub fn new(root: Value) -> Self {
+        let mut browser = Self {
+            root,
+            expanded: HashSet::from([".".to_owned()]),
+            rows: vec![],
+            selected: 0,
+            offset: 0,
+            page: 20,
+            mode: Mode::Normal,
+            last_search: String::new(),
+            message: String::new(),
+        };
+        browser.rebuild();
+        browser
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn selected_path(&self) -> String {
+        path_to_string(&self.rows[self.selected].path)
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // rebuild the visible rows, the selection stays on the same path
+    // if it is still visible.
+    fn rebuild(&mut self) {
+        let selected = self.rows.get(self.selected).map(|r| r.path.clone());
+        let mut rows = vec![];
+        self.push_rows(&self.root, vec![], 0, &mut rows);
+        self.rows = rows;
+        self.selected = selected
+            .and_then(|p| self.rows.iter().position(|r| r.path == p))
+            .unwrap_or(0);
+    }
+
+    fn push_rows(&self, value: &Value, path: Vec<Value>, depth: usize, rows: &mut Vec<Row>) {
+        let children = children(value);
+        let expanded = self.expanded.contains(&path_to_string(&path));
+        rows.push(Row {
+            text: row_text(path.last(), value),
+            depth,
+            children: is_container(value).then_some(children.len()),
+            expanded,
+            path: path.clone(),
+        });
+
+        if expanded {
+            for (key, child) in children {
+                let mut child_path = path.clone();
+                child_path.push(key);
+                self.push_rows(child, child_path, depth + 1, rows);
+            }
+        }
+    }
+
+    fn set_expanded(&mut self, expanded: bool) {
+        let row = &self.rows[self.selected];
+        if row.children.is_some() {
+            let key = path_to_string(&row.path);
+            if expanded {
+                self.expanded.insert(key);
+            } else {
+                self.expanded.remove(&key);
+            }
+            self.rebuild();
+        }
+    }
+
+    fn move_selection(&mut self, delta: isize) {
+        let last = self.rows.len() as isize - 1;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    /// Handle the key, returns `true` to quit.
+    pub fn handle_key(&mut self, code: KeyCode) -> bool {
+        match &mut self.mode {
+            Mode::Search(text) => {
+                match code {
+                    KeyCode::Esc | KeyCode::Enter => {
+                        self.mode = Mode::Normal;
+                    }
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Char(c) => text.push(c),
+                    _ => {}
+                }
+                if let Mode::Search(text) = &self.mode {
+                    let text = text.clone();
+                    self.search(&text, true, false);
+                    self.last_search = text;
+                }
+                false
+            }
+            Mode::Query(text) => {
+                match code {
+                    KeyCode::Esc => {
+                        self.mode = Mode::Normal;
+                        self.message.clear();
+                        return false;
+                    }
+                    KeyCode::Enter => {
+                        self.mode = Mode::Normal;
+                        return false;
+                    }
+                    KeyCode::Backspace => {
+                        text.pop();
+                    }
+                    KeyCode::Char(c) => text.push(c),
+                    _ => {}
+                }
+                let text = text.clone();
+                self.message = self.evaluate(&text);
+                false
+            }
+            Mode::Normal => self.handle_normal_key(code),
+        }
+    }
+
+    fn handle_normal_key(&mut self, code: KeyCode) -> bool {
+        let page = self.page as isize;
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::PageUp => self.move_selection(-page),
+            KeyCode::PageDown => self.move_selection(page),
+            KeyCode::Home => self.selected = 0,
+            KeyCode::End => self.selected = self.rows.len() - 1,
+            KeyCode::Right | KeyCode::Char('l') => self.set_expanded(true),
+            KeyCode::Left | KeyCode::Char('h') => {
+                let row = &self.rows[self.selected];
+                if row.expanded {
+                    self.set_expanded(false);
+                } else if let Some(parent) = row.path.split_last().map(|(_, p)| p.to_vec()) {
+                    self.selected = self.rows.iter().position(|r| r.path == parent).unwrap();
+                }
+            }
+            KeyCode::Enter | KeyCode::Char(' ') => {
+                let expanded = self.rows[self.selected].expanded;
+                self.set_expanded(!expanded);
+            }
+            KeyCode::Char('/') => {
+                self.mode = Mode::Search(String::new());
+                self.message.clear();
+            }
+            KeyCode::Char('n') => {
+                let text = self.last_search.clone();
+                self.search(&text, true, true);
+            }
+            KeyCode::Char('N') => {
+                let text = self.last_search.clone();
+                self.search(&text, false, true);
+            }
+            KeyCode::Char(':') => {
+                let text = self.selected_path();
+                self.message = self.evaluate(&text);
+                self.mode = Mode::Query(text);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    // find the node whose key or value contains the text (case-insensitively),
+    // in the whole document rather than the visible rows, the containers
+    // of the found node are expanded.
+    fn search(&mut self, text: &str, forward: bool, skip_current: bool) -> bool {
+        if text.is_empty() {
+            self.message.clear();
+            return false;
+        }
+
+        let mut nodes = vec![];
+        collect_nodes(&self.root, vec![], &mut nodes);
+        let current = &self.rows[self.selected].path;
+        let start = nodes.iter().position(|(p, _)| p == current).unwrap_or(0);
+        let needle = text.to_lowercase();
+        let count = nodes.len();
+
+        let found = (0..count)
+            .map(|step| {
+                let step = step + skip_current as usize;
+                if forward {
+                    (start + step) % count
+                } else {
+                    (start + count * 2 - step) % count
+                }
+            })
+            .find(|idx| nodes[*idx].1.to_lowercase().contains(&needle));
+
+        let Some(idx) = found else {
+            self.message = format!("Not found: \"{}\"", text);
+            return false;
+        };
+        let path = nodes.swap_remove(idx).0;
+        for depth in 0..path.len() {
+            self.expanded.insert(path_to_string(&path[..depth]));
+        }
+        self.rebuild();
+        self.selected = self.rows.iter().position(|r| r.path == path).unwrap();
+        self.message.clear();
+        true
+    }
+
+    // the results of the query in one line.
+    fn evaluate(&self, text: &str) -> String {
+        let outcome = Query::compile(text).and_then(|q| q.run_value(self.root.clone()));
+        match outcome {
+            Ok(results) if results.is_empty() => "(no output)".to_owned(),
+            Ok(results) => results
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Err(e) => e.to_string().lines().next().unwrap_or_default().to_owned(),
+        }
+    }
+
+    fn draw(&mut self, out: &mut impl Write, width: usize, height: usize) -> io::Result<()> {
+        // the rows, the status line, the message line and the prompt line.
+        self.page = height.saturating_sub(3).max(1);
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + self.page {
+            self.offset = self.selected + 1 - self.page;
+        }
+
+        queue!(out, Clear(ClearType::All))?;
+        for (line, row) in self
+            .rows()
+            .iter()
+            .skip(self.offset)
+            .take(self.page)
+            .enumerate()
+        {
+            let marker = match (row.children, row.expanded) {
+                (None, _) => "  ",
+                (Some(_), true) => "▾ ",
+                (Some(_), false) => "▸ ",
+            };
+            let text = format!("{}{}{}", "  ".repeat(row.depth), marker, row.text);
+            queue!(out, MoveTo(0, line as u16))?;
+            if self.offset + line == self.selected {
+                queue!(out, SetAttribute(Attribute::Reverse))?;
+            }
+            queue!(
+                out,
+                Print(truncate(&text, width)),
+                SetAttribute(Attribute::Reset)
+            )?;
+        }
+
+        let prompt = match &self.mode {
+            Mode::Normal => "↑↓ move  →← expand/collapse  / search  : query  q quit".to_owned(),
+            Mode::Search(text) => format!("/{}", text),
+            Mode::Query(text) => format!(":{}", text),
+        };
+        queue!(
+            out,
+            MoveTo(0, self.page as u16),
+            SetAttribute(Attribute::Reverse),
+            Print(format!("{:width$}", truncate(&self.selected_path(), width))),
+            SetAttribute(Attribute::Reset),
+            MoveTo(0, self.page as u16 + 1),
+            Print(truncate(self.message(), width)),
+            MoveTo(0, self.page as u16 + 2),
+            Print(truncate(&prompt, width)),
+        )?;
+        out.flush()
+    }
+
+    fn event_loop(&mut self, out: &mut impl Write) -> io::Result<()> {
+        loop {
+            let (width, height) = terminal::size()?;
+            self.draw(out, width as usize, height as usize)?;
+            if let Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press,
+                ..
+            }) = read()?
+            {
+                let interrupted =
+                    modifiers.contains(KeyModifiers::CONTROL) && code == KeyCode::Char('c');
+                if interrupted || self.handle_key(code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Open the browser in the terminal until it quits.
+pub fn browse(root: Value) -> Result<(), Error> {
+    let terminal_error =
+        |e: io::Error| Error::Message(format!("Fail to control the terminal: {}", e));
+
+    let mut browser = Browser::new(root);
+    let mut out = io::stdout();
+    terminal::enable_raw_mode().map_err(terminal_error)?;
+    let outcome =
+        execute!(out, EnterAlternateScreen, Hide).and_then(|_| browser.event_loop(&mut out));
+
+    // restore the terminal even if the loop fails
+    let _ = execute!(out, Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+    outcome.map_err(terminal_error)
+}
+
+fn is_container(value: &Value) -> bool {
+    matches!(value, Value::List(_) | Value::Tuple(_) | Value::Object(_))
+}
+
+fn children(value: &Value) -> Vec<(Value, &Value)> {
+    match value {
+        Value::List(items) | Value::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(idx, v)| (Value::from(idx), v))
+            .collect(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| (Value::String(k.clone()), v))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+// the key and the summary of the value, e.g. `name: "Alice"`
+// and `tags: [3]`.
+fn row_text(key: Option<&Value>, value: &Value) -> String {
+    let summary = match value {
+        Value::List(items) => format!("[{}]", items.len()),
+        Value::Tuple(items) => format!("({})", items.len()),
+        Value::Object(map) => format!("{{{}}}", map.len()),
+        _ => value.to_string(),
+    };
+    match key {
+        None => summary,
+        Some(Value::String(k)) if is_identifier(k) => format!("{}: {}", k, summary),
+        Some(Value::String(k)) => format!("{}: {}", escape_string(k, '"'), summary),
+        Some(index) => format!("[{}]: {}", index, summary),
+    }
+}
+
+// the paths and the searchable texts of all nodes in the order of the tree.
+fn collect_nodes(value: &Value, path: Vec<Value>, nodes: &mut Vec<(Vec<Value>, String)>) {
+    let key = match path.last() {
+        Some(Value::String(k)) => k.clone(),
+        _ => String::new(),
+    };
+    let text = if is_container(value) {
+        key
+    } else {
+        format!("{} {}", key, value.to_text())
+    };
+    nodes.push((path.clone(), text));
+
+    for (key, child) in children(value) {
+        let mut child_path = path.clone();
+        child_path.push(key);
+        collect_nodes(child, child_path, nodes);
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyCode;
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::{Map, Value},
+    };
+
+    use super::Browser;
+
+    fn texts(browser: &Browser) -> Vec<String> {
+        browser
+            .rows()
+            .iter()
+            .map(|row| format!("{}{}", "  ".repeat(row.depth), row.text))
+            .collect()
+    }
+
+    fn type_text(browser: &mut Browser, text: &str) {
+        for c in text.chars() {
+            browser.handle_key(KeyCode::Char(c));
+        }
+    }
+
+    #[test]
+    fn test_navigation() {
+        let text = |s: &str| Value::String(s.to_owned());
+        let mut alice = Map::new();
+        alice.insert("name".to_owned(), text("Alice"));
+        alice.insert("e-mail".to_owned(), text("a@x"));
+        let mut bob = Map::new();
+        bob.insert("name".to_owned(), text("Bob"));
+        let mut root = Map::new();
+        root.insert(
+            "users".to_owned(),
+            Value::List(vec![Value::Object(alice), Value::Object(bob)].into()),
+        );
+        root.insert("n".to_owned(), Value::from(1));
+
+        let mut browser = Browser::new(Value::Object(root));
+        assert_eq!(texts(&browser), ["{2}", "  users: [2]", "  n: 1"]);
+
+        // expand `.users` and `.users[0]`
+        browser.handle_key(KeyCode::Down);
+        browser.handle_key(KeyCode::Right);
+        browser.handle_key(KeyCode::Down);
+        browser.handle_key(KeyCode::Enter);
+        assert_eq!(
+            texts(&browser),
+            [
+                "{2}",
+                "  users: [2]",
+                "    [0]: {2}",
+                "      name: \"Alice\"",
+                "      \"e-mail\": \"a@x\"",
+                "    [1]: {1}",
+                "  n: 1"
+            ]
+        );
+        assert_eq!(browser.selected_path(), ".users[0]");
+
+        // collapse, then move to the parent
+        browser.handle_key(KeyCode::Left);
+        browser.handle_key(KeyCode::Left);
+        assert_eq!(browser.selected_path(), ".users");
+    }
+
+    #[test]
+    fn test_search_and_query() {
+        let root = parse_input(
+            "{users: [{name: \"Alice\"}, {name: \"Bob\"}], n: 1}",
+            None,
+            &ParseOptions::unlimited(),
+        )
+        .unwrap();
+        let mut browser = Browser::new(root);
+
+        // the collapsed nodes are searched too
+        type_text(&mut browser, "/bob");
+        assert_eq!(browser.selected_path(), ".users[1].name");
+        browser.handle_key(KeyCode::Enter);
+        browser.handle_key(KeyCode::Char('n'));
+        assert_eq!(browser.selected_path(), ".users[1].name");
+
+        // the query bar starts with the path of the selected node
+        browser.handle_key(KeyCode::Char(':'));
+        assert_eq!(browser.message(), "\"Bob\"");
+        type_text(&mut browser, " | length");
+        assert_eq!(browser.message(), "3");
+        browser.handle_key(KeyCode::Esc);
+
+        assert!(!browser.handle_key(KeyCode::Home));
+        assert!(browser.handle_key(KeyCode::Char('q')));
+    }
+}
@@ -0,0 +1,446 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Building queries programmatically.
+//
+// The builder constructs the syntax tree directly, so the user input
+// (e.g. the keys and literals) never needs to be escaped into the
+// query text, e.g.
+//
+// ```
+// let query = Query::builder()
+//     .field("orders")
+//     .each()
+//     .select(cmp::gt(path("total"), lit(100)))
+//     .build()?;
+// ```
+//
+// is equivalent to `.orders[] | select(.total > 100)`.
+//
+// The filters can also be composed from the paths and the builtins, e.g.
+// `Path::field("users").index(0).pipe(Builtin::Keys)` is `.users[0] | keys`,
+// and the syntax trees are printed back to the query text by `to_string()`.
+
+use crate::{
+    ast::{BinaryOperator, Expression},
+    error::Error,
+    query::Query,
+    value::Value,
+};
+
+/// Builds a pipeline of filters, each method appends a filter
+/// to the end of the pipeline.
+pub struct QueryBuilder {
+    expression: Expression,
+}
+
+impl QueryBuilder {
+    pub fn new() -> Self {
+        Self {
+            expression: Expression::Identity,
+        }
+    }
+
+    /// `.name`
+    pub fn field(self, name: &str) -> Self {
+        let index = Expression::Literal(Value::from(name));
+        self.index_by(index)
+    }
+
+    /// `.[index]`, negative indices count from the end.
+    pub fn index(self, index: i64) -> Self {
+        self.index_by(integer(index))
+    }
+
+    /// `.[from:to]`, both ends are optional.
+    pub fn slice(self, from: Option<i64>, to: Option<i64>) -> Self {
+        let bound = |i: Option<i64>| i.map(|i| Box::new(integer(i)));
+        self.map_expression(|e| Expression::Slice(Box::new(e), bound(from), bound(to)))
+    }
+
+    /// `.[]`
+    pub fn each(self) -> Self {
+        self.map_expression(|e| Expression::Iterate(Box::new(e)))
+    }
+
+    /// `select(condition)`
+    pub fn select(self, condition: Expression) -> Self {
+        self.pipe(call("select", vec![condition]))
+    }
+
+    /// `map(filter)`
+    pub fn map(self, filter: Expression) -> Self {
+        self.pipe(call("map", vec![filter]))
+    }
+
+    /// `... | filter`
+    pub fn pipe(self, filter: impl Into<Expression>) -> Self {
+        let filter = filter.into();
+        self.map_expression(|e| match e {
+            Expression::Identity => filter,
+            e => Expression::Pipe(Box::new(e), Box::new(filter)),
+        })
+    }
+
+    /// Collect the results into a list, i.e. `[...]`.
+    pub fn collect(self) -> Self {
+        self.map_expression(|e| Expression::List(Some(Box::new(e))))
+    }
+
+    pub fn into_expression(self) -> Expression {
+        self.expression
+    }
+
+    pub fn build(self) -> Result<Query, Error> {
+        Query::from_expression(self.expression)
+    }
+
+    // the index applies to the output of the pipeline directly,
+    // e.g. `.orders` then `.[0]` builds `.orders[0]`.
+    fn index_by(self, index: Expression) -> Self {
+        self.map_expression(|e| Expression::Index(Box::new(e), Box::new(index)))
+    }
+
+    fn map_expression(mut self, f: impl FnOnce(Expression) -> Expression) -> Self {
+        self.expression = f(self.expression);
+        self
+    }
+}
+
+impl Default for QueryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A path of fields and indices, e.g. `Path::field("users").index(0)`
+/// is `.users[0]`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Path {
+    expression: Expression,
+}
+
+impl Path {
+    /// `.`
+    pub fn identity() -> Self {
+        Self {
+            expression: Expression::Identity,
+        }
+    }
+
+    /// `.name`
+    pub fn field(name: &str) -> Self {
+        Self::identity().key(name)
+    }
+
+    /// `.name` after the path, e.g. `Path::field("a").key("b")` is `.a.b`.
+    pub fn key(self, name: &str) -> Self {
+        self.index_by(lit(name))
+    }
+
+    /// `.[index]`, negative indices count from the end.
+    pub fn index(self, index: i64) -> Self {
+        self.index_by(integer(index))
+    }
+
+    /// `.[from:to]`, both ends are optional.
+    pub fn slice(self, from: Option<i64>, to: Option<i64>) -> Self {
+        let bound = |i: Option<i64>| i.map(|i| Box::new(integer(i)));
+        Self {
+            expression: Expression::Slice(Box::new(self.expression), bound(from), bound(to)),
+        }
+    }
+
+    /// `.[]`
+    pub fn each(self) -> Self {
+        Self {
+            expression: Expression::Iterate(Box::new(self.expression)),
+        }
+    }
+
+    /// `path | filter`
+    pub fn pipe(self, filter: impl Into<Expression>) -> Expression {
+        Expression::Pipe(Box::new(self.expression), Box::new(filter.into()))
+    }
+
+    fn index_by(self, index: Expression) -> Self {
+        Self {
+            expression: Expression::Index(Box::new(self.expression), Box::new(index)),
+        }
+    }
+}
+
+impl From<Path> for Expression {
+    fn from(path: Path) -> Self {
+        path.expression
+    }
+}
+
+/// The builtin filters without arguments, see `call` for the others.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Builtin {
+    Add,
+    Empty,
+    First,
+    Flatten,
+    FromEntries,
+    Keys,
+    Last,
+    Length,
+    Max,
+    Min,
+    Not,
+    Paths,
+    Reverse,
+    Sort,
+    ToEntries,
+    ToNumber,
+    ToString,
+    Type,
+    Unique,
+    Values,
+}
+
+impl Builtin {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Add => "add",
+            Builtin::Empty => "empty",
+            Builtin::First => "first",
+            Builtin::Flatten => "flatten",
+            Builtin::FromEntries => "from_entries",
+            Builtin::Keys => "keys",
+            Builtin::Last => "last",
+            Builtin::Length => "length",
+            Builtin::Max => "max",
+            Builtin::Min => "min",
+            Builtin::Not => "not",
+            Builtin::Paths => "paths",
+            Builtin::Reverse => "reverse",
+            Builtin::Sort => "sort",
+            Builtin::ToEntries => "to_entries",
+            Builtin::ToNumber => "tonumber",
+            Builtin::ToString => "tostring",
+            Builtin::Type => "type",
+            Builtin::Unique => "unique",
+            Builtin::Values => "values",
+        }
+    }
+}
+
+impl From<Builtin> for Expression {
+    fn from(builtin: Builtin) -> Self {
+        call(builtin.name(), vec![])
+    }
+}
+
+/// `left | right`
+pub fn pipe(left: impl Into<Expression>, right: impl Into<Expression>) -> Expression {
+    Expression::Pipe(Box::new(left.into()), Box::new(right.into()))
+}
+
+/// `left, right`
+pub fn comma(left: impl Into<Expression>, right: impl Into<Expression>) -> Expression {
+    Expression::Comma(Box::new(left.into()), Box::new(right.into()))
+}
+
+/// `.name`, see `field_path` for the nested fields.
+pub fn path(name: &str) -> Expression {
+    field_path(&[name])
+}
+
+/// `.a.b.c`, the names are not split at the dots.
+pub fn field_path(names: &[&str]) -> Expression {
+    names.iter().fold(Expression::Identity, |e, name| {
+        Expression::Index(Box::new(e), Box::new(lit(*name)))
+    })
+}
+
+/// A literal value.
+pub fn lit(value: impl Into<Value>) -> Expression {
+    Expression::Literal(value.into())
+}
+
+/// `$name`
+pub fn var(name: &str) -> Expression {
+    Expression::Variable(name.to_owned())
+}
+
+/// `name(arg1; arg2; ...)`
+pub fn call(name: &str, args: Vec<Expression>) -> Expression {
+    Expression::Call(name.to_owned(), args)
+}
+
+pub fn and(left: Expression, right: Expression) -> Expression {
+    Expression::And(Box::new(left), Box::new(right))
+}
+
+pub fn or(left: Expression, right: Expression) -> Expression {
+    Expression::Or(Box::new(left), Box::new(right))
+}
+
+pub fn not(operand: Expression) -> Expression {
+    Expression::Pipe(Box::new(operand), Box::new(call("not", vec![])))
+}
+
+/// The comparison operators.
+pub mod cmp {
+    use super::{binary, BinaryOperator, Expression};
+
+    pub fn eq(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Equal, left, right)
+    }
+
+    pub fn ne(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::NotEqual, left, right)
+    }
+
+    pub fn lt(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Less, left, right)
+    }
+
+    pub fn le(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::LessOrEqual, left, right)
+    }
+
+    pub fn gt(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Greater, left, right)
+    }
+
+    pub fn ge(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::GreaterOrEqual, left, right)
+    }
+}
+
+/// The arithmetic operators.
+pub mod ops {
+    use super::{binary, BinaryOperator, Expression};
+
+    pub fn add(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Add, left, right)
+    }
+
+    pub fn sub(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Subtract, left, right)
+    }
+
+    pub fn mul(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Multiply, left, right)
+    }
+
+    pub fn div(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Divide, left, right)
+    }
+
+    pub fn rem(left: Expression, right: Expression) -> Expression {
+        binary(BinaryOperator::Remainder, left, right)
+    }
+}
+
+// the integer literal, it is `i32` (the type of the integer literals of
+// the query) when it is in the range, e.g. `.[0]` rather than `.[0_i64]`.
+fn integer(i: i64) -> Expression {
+    match i32::try_from(i) {
+        Ok(i) => lit(i),
+        Err(_) => lit(i),
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Expression, right: Expression) -> Expression {
+    Expression::Binary(operator, Box::new(left), Box::new(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        ast::Expression,
+        input::{parse_input, ParseOptions},
+        query::Query,
+        value::Value,
+    };
+
+    use super::{
+        and, call, cmp, comma, field_path, lit, not, ops, or, path, pipe, var, Builtin, Path,
+    };
+
+    fn document(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_build_query() {
+        let builder = Query::builder()
+            .field("orders")
+            .each()
+            .select(cmp::gt(path("total"), lit(100)))
+            .pipe(path("id"))
+            .collect();
+        assert_eq!(
+            builder.into_expression().to_string(),
+            "[(.orders[] | select(.total > 100)) | .id]"
+        );
+
+        let query = Query::builder()
+            .field("orders")
+            .each()
+            .select(cmp::gt(path("total"), lit(100)))
+            .pipe(path("id"))
+            .collect()
+            .build()
+            .unwrap();
+        let input =
+            document("{orders: [{id: 1, total: 50}, {id: 2, total: 150}, {id: 3, total: 101}]}");
+        assert_eq!(query.run_value(input).unwrap(), [document("[2, 3]")]);
+    }
+
+    #[test]
+    fn test_build_query_with_unescaped_keys() {
+        // the key is not escaped into the query text
+        let query = Query::builder().field("a \"b\"").index(-1).build().unwrap();
+        let input = document(r#"{"a \"b\"": [1, 2]}"#);
+        assert_eq!(query.run_value(input).unwrap(), [Value::from(2)]);
+
+        let query = Query::builder()
+            .slice(Some(1), None)
+            .map(ops::mul(Path::identity().into(), lit(2)));
+        assert_eq!(query.into_expression().to_string(), ".[1:] | map(. * 2)");
+    }
+
+    #[test]
+    fn test_compose_filters() {
+        let filter = Path::field("users").index(0).pipe(Builtin::Keys);
+        assert_eq!(filter.to_string(), ".users[0] | keys");
+
+        let filter = comma(
+            and(var("x"), not(path("done"))),
+            call("has", vec![lit("id")]),
+        );
+        assert_eq!(filter.to_string(), "$x and (.done | not), has(\"id\")");
+    }
+
+    #[test]
+    fn test_build_paths() {
+        assert_eq!(field_path(&["a", "b"]).to_string(), ".a.b");
+        assert_eq!(
+            Expression::from(Path::field("a").key("b").each()).to_string(),
+            ".a.b[]"
+        );
+        assert_eq!(
+            pipe(path("items"), or(lit(true), ops::rem(lit(3), lit(2)))).to_string(),
+            ".items | true or 3 % 2"
+        );
+
+        // the names are not split at the dots
+        let query = pipe(field_path(&["a.b"]), lit(1));
+        let results = Query::from_expression(query)
+            .unwrap()
+            .run_value(document(r#"{"a.b": 0}"#))
+            .unwrap();
+        assert_eq!(results, [Value::from(1)]);
+    }
+}
@@ -0,0 +1,1992 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The built-in functions implemented in Rust.
+//
+// The functions that can be expressed in the query language itself
+// are defined in the file `prelude.aql`.
+
+use std::{cmp::Ordering, rc::Rc, sync::Arc};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Locale,
+};
+
+use crate::{
+    ast::{BinaryOperator, Expression},
+    cast::{cast_number, CastMode, CAST_TYPES},
+    digest::hmac_sha256,
+    error::Error,
+    evaluator::{
+        binary, environment_variables, invalid_path_error, recurse_paths, recurse_value,
+        Environment, Evaluator, Output, PathOutput,
+    },
+    format::{decode_base64, decode_hex, encode_hex},
+    input::{parse_input, ParseOptions},
+    json::{parse_json_from_str, to_tagged_json, JsonDialect},
+    memo::MemoKey,
+    path::{delpaths, getpath, path_from_value, setpath},
+    provenance::DocumentSource,
+    regex::Regex,
+    schema::Schema,
+    value::{Items, Number, Value, VariantValue},
+    xml::{parse_xml, to_xml},
+};
+
+/// The native functions and their numbers of arguments, the cast
+/// functions (e.g. `to_u8`) are not included, see `is_native_function()`.
+pub const NATIVE_FUNCTIONS: &[(&str, usize)] = &[
+    ("empty", 0),
+    ("not", 0),
+    ("type", 0),
+    ("error", 0),
+    ("error", 1),
+    ("halt", 0),
+    ("halt_error", 0),
+    ("halt_error", 1),
+    ("input", 0),
+    ("inputs", 0),
+    ("input_filename", 0),
+    ("input_line_number", 0),
+    ("env", 0),
+    ("debug", 0),
+    ("stderr", 0),
+    ("tostring", 0),
+    ("tonumber", 0),
+    ("toason", 0),
+    ("fromason", 0),
+    ("is_variant", 1),
+    ("variant_name", 0),
+    ("unwrap", 0),
+    ("tohex", 0),
+    ("fromhex", 0),
+    ("frombase64", 0),
+    ("tobytes", 0),
+    ("tolist", 0),
+    ("paths_with_key", 1),
+    ("paths_with_key", 2),
+    ("length", 0),
+    ("keys", 0),
+    ("keys_unsorted", 0),
+    ("has", 1),
+    ("add", 0),
+    ("recurse", 0),
+    ("reverse", 0),
+    ("sort", 0),
+    ("sort_by", 1),
+    ("group_by", 1),
+    ("unique", 0),
+    ("min", 0),
+    ("max", 0),
+    ("min_by", 1),
+    ("max_by", 1),
+    ("join", 1),
+    ("split", 1),
+    ("ascii_downcase", 0),
+    ("ascii_upcase", 0),
+    ("tochar", 0),
+    ("tocodepoint", 0),
+    ("explode", 0),
+    ("implode", 0),
+    ("format_date", 1),
+    ("format_date", 2),
+    ("ltrimstr", 1),
+    ("rtrimstr", 1),
+    ("tojson", 0),
+    ("fromjson", 0),
+    ("toxml", 0),
+    ("fromxml", 0),
+    ("pseudonym", 1),
+    ("contains", 1),
+    ("indices", 1),
+    ("startswith", 1),
+    ("endswith", 1),
+    ("flatten", 0),
+    ("flatten", 1),
+    ("transpose", 0),
+    ("zip", 1),
+    ("range", 1),
+    ("range", 2),
+    ("range", 3),
+    ("limit", 2),
+    ("last", 1),
+    ("nth", 2),
+    ("memo", 1),
+    ("path", 1),
+    ("provenance", 1),
+    ("getpath", 1),
+    ("setpath", 2),
+    ("delpaths", 1),
+    ("validates", 1),
+];
+
+/// Whether the native function, including the cast functions, exists.
+pub fn is_native_function(name: &str, arity: usize) -> bool {
+    NATIVE_FUNCTIONS.contains(&(name, arity)) || (arity <= 1 && cast_target(name).is_some())
+}
+
+impl Evaluator {
+    pub(crate) fn call_native<'a>(
+        &self,
+        name: &str,
+        args: &'a [Expression],
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        match (name, args.len()) {
+            ("empty", 0) => Ok(()),
+            ("not", 0) => output(Value::Boolean(!input.is_truthy())),
+            ("type", 0) => output(Value::String(input.type_name().to_owned())),
+            ("error", 0) => Err(Error::Raised(input)),
+            ("error", 1) => self.eval(&args[0], input, env, &mut |message| {
+                Err(Error::Raised(message))
+            }),
+            ("halt", 0) => Err(Error::Halted(0, None)),
+            // the same exit status as jq
+            ("halt_error", 0) => Err(Error::Halted(5, Some(input))),
+            ("halt_error", 1) => {
+                self.eval(&args[0], input.clone(), env, &mut |status| match &status {
+                    Value::Number(n) => match n.as_integer().and_then(|i| i32::try_from(i).ok()) {
+                        Some(status) => Err(Error::Halted(status, Some(input.clone()))),
+                        None => Err(expect_type_error(
+                            "halt_error",
+                            "an integer status",
+                            &status,
+                        )),
+                    },
+                    _ => Err(expect_type_error(
+                        "halt_error",
+                        "an integer status",
+                        &status,
+                    )),
+                })
+            }
+            ("input", 0) => match self.next_input()? {
+                Some(v) => output(v),
+                None => Err(Error::Message("No more inputs.".to_owned())),
+            },
+            ("inputs", 0) => loop {
+                // take one document at a time, so the inner `input`
+                // calls of the receiver keep working.
+                match self.next_input()? {
+                    Some(v) => output(v)?,
+                    None => break Ok(()),
+                }
+            },
+            ("input_filename", 0) => output(
+                self.current_source()
+                    .and_then(|s| s.filename.clone())
+                    .map_or_else(Value::null, Value::String),
+            ),
+            ("input_line_number", 0) => output(
+                self.current_source()
+                    .map_or_else(Value::null, |s| Value::from(s.line)),
+            ),
+            ("env", 0) => output(environment_variables()),
+            ("debug", 0) => {
+                // the same as jq, e.g. `["DEBUG:",{id:1}]`
+                eprintln!("[\"DEBUG:\",{}]", input);
+                output(input)
+            }
+            ("stderr", 0) => {
+                // without the line feed, so the messages can be concatenated
+                eprint!("{}", input);
+                output(input)
+            }
+            ("tostring", 0) => output(Value::String(input.to_text())),
+            ("tonumber", 0) => match &input {
+                Value::Number(_) => output(input),
+                Value::String(s) => match parse_input(s.trim(), None, &ParseOptions::unlimited()) {
+                    Ok(n @ Value::Number(_)) => output(n),
+                    _ => Err(Error::Message(format!(
+                        "Cannot parse {} as a number.",
+                        input
+                    ))),
+                },
+                _ => Err(expect_type_error("tonumber", "number or string", &input)),
+            },
+            ("toason", 0) => output(Value::String(input.to_string())),
+            ("fromason", 0) => match &input {
+                Value::String(s) => output(parse_input(s, None, &ParseOptions::unlimited())?),
+                _ => Err(expect_type_error("fromason", "string", &input)),
+            },
+            ("is_variant", 1) => self.eval(&args[0], input.clone(), env, &mut |name| match &name {
+                Value::String(name) => output(Value::Boolean(is_variant(&input, name))),
+                _ => Err(expect_type_error("is_variant", "a string name", &name)),
+            }),
+            ("variant_name", 0) => match &input {
+                Value::Variant(v) => {
+                    output(Value::String(format!("{}::{}", v.type_name, v.member_name)))
+                }
+                _ => Err(expect_type_error("variant_name", "variant", &input)),
+            },
+            ("unwrap", 0) => output(unwrap(input)?),
+            ("tohex", 0) => match &input {
+                Value::ByteData(bytes) => output(Value::String(encode_hex(bytes))),
+                _ => Err(expect_type_error("tohex", "bytedata", &input)),
+            },
+            ("fromhex", 0) => match &input {
+                Value::String(s) => output(Value::ByteData(decode_hex(s)?)),
+                _ => Err(expect_type_error("fromhex", "string", &input)),
+            },
+            ("frombase64", 0) => match &input {
+                Value::String(s) => output(Value::ByteData(decode_base64(s)?)),
+                _ => Err(expect_type_error("frombase64", "string", &input)),
+            },
+            ("tobytes", 0) => output(Value::ByteData(to_bytes(input)?)),
+            ("tolist", 0) => match input {
+                Value::ByteData(bytes) => output(Value::List(
+                    bytes
+                        .into_iter()
+                        .map(|b| Value::Number(Number::U8(b)))
+                        .collect(),
+                )),
+                Value::List(items) | Value::Tuple(items) => output(Value::List(items)),
+                _ => Err(expect_type_error(
+                    "tolist",
+                    "bytedata, list or tuple",
+                    &input,
+                )),
+            },
+            ("paths_with_key", 1) => {
+                self.eval(&args[0], input.clone(), env, &mut |key| match &key {
+                    Value::String(key) => paths_with_key(&input, |k| k == key, output),
+                    _ => Err(expect_type_error("paths_with_key", "a string key", &key)),
+                })
+            }
+            ("paths_with_key", 2) => self.eval(&args[1], input.clone(), env, &mut |flags| {
+                let ignore_case = match &flags {
+                    Value::String(f) if f.is_empty() => false,
+                    Value::String(f) if f == "i" => true,
+                    _ => {
+                        return Err(Error::Message(format!(
+                            "The flags of regular expression must be \"\" or \"i\", found {}.",
+                            flags
+                        )))
+                    }
+                };
+                self.eval(
+                    &args[0],
+                    input.clone(),
+                    env,
+                    &mut |pattern| match &pattern {
+                        Value::String(p) => {
+                            let regex = Regex::new(p, ignore_case)?;
+                            paths_with_key(&input, |k| regex.is_match(k), output)
+                        }
+                        _ => Err(expect_type_error(
+                            "paths_with_key",
+                            "a regular expression string",
+                            &pattern,
+                        )),
+                    },
+                )
+            }),
+            ("length", 0) => output(length(&input)?),
+            ("keys", 0) => output(keys(&input, true)?),
+            ("keys_unsorted", 0) => output(keys(&input, false)?),
+            ("has", 1) => self.eval(&args[0], input.clone(), env, &mut |key| {
+                output(Value::Boolean(has(&input, &key)?))
+            }),
+            ("add", 0) => {
+                let mut sum = Value::null();
+                for item in elements(input, "add")? {
+                    sum = binary(BinaryOperator::Add, sum, &item)?;
+                }
+                output(sum)
+            }
+            ("recurse", 0) => recurse_value(input, output),
+            ("reverse", 0) => match input {
+                Value::String(s) => output(Value::String(s.chars().rev().collect())),
+                _ if input.is_null() => output(Value::List(Items::new())),
+                _ => {
+                    let mut items = elements(input, "reverse")?;
+                    items.reverse();
+                    output(Value::List(items.into()))
+                }
+            },
+            ("sort", 0) => {
+                let mut items = elements(input, "sort")?;
+                items.sort();
+                output(Value::List(items.into()))
+            }
+            ("sort_by", 1) => {
+                let keyed = self.keyed_elements(&args[0], input, env, "sort_by")?;
+                output(Value::List(keyed.into_iter().map(|(_, v)| v).collect()))
+            }
+            ("group_by", 1) => {
+                let keyed = self.keyed_elements(&args[0], input, env, "group_by")?;
+                let mut groups: Vec<(Vec<Value>, Vec<Value>)> = vec![];
+                for (key, value) in keyed {
+                    match groups.last_mut() {
+                        Some((last_key, group)) if *last_key == key => group.push(value),
+                        _ => groups.push((key, vec![value])),
+                    }
+                }
+                output(Value::List(
+                    groups
+                        .into_iter()
+                        .map(|(_, g)| Value::List(g.into()))
+                        .collect(),
+                ))
+            }
+            ("unique", 0) => {
+                let mut items = elements(input, "unique")?;
+                items.sort();
+                items.dedup();
+                output(Value::List(items.into()))
+            }
+            ("min", 0) => {
+                let items = elements(input, "min")?;
+                output(items.into_iter().min().unwrap_or_else(Value::null))
+            }
+            ("max", 0) => {
+                let items = elements(input, "max")?;
+                output(items.into_iter().max().unwrap_or_else(Value::null))
+            }
+            ("min_by", 1) => {
+                let keyed = self.keyed_elements(&args[0], input, env, "min_by")?;
+                output(
+                    keyed
+                        .into_iter()
+                        .next()
+                        .map_or_else(Value::null, |(_, v)| v),
+                )
+            }
+            ("max_by", 1) => {
+                let keyed = self.keyed_elements(&args[0], input, env, "max_by")?;
+                output(
+                    keyed
+                        .into_iter()
+                        .last()
+                        .map_or_else(Value::null, |(_, v)| v),
+                )
+            }
+            ("join", 1) => self.eval(&args[0], input.clone(), env, &mut |separator| {
+                output(join(&input, &separator)?)
+            }),
+            ("split", 1) => self.eval(&args[0], input.clone(), env, &mut |separator| match (
+                &input, &separator,
+            ) {
+                (Value::String(s), Value::String(sep)) => output(Value::List(split(s, sep).into())),
+                _ => Err(Error::Message(format!(
+                    "Cannot split {} with {}.",
+                    input.type_name(),
+                    separator.type_name()
+                ))),
+            }),
+            ("ascii_downcase", 0) => match input {
+                Value::String(s) => output(Value::String(s.to_ascii_lowercase())),
+                _ => Err(expect_type_error("ascii_downcase", "string", &input)),
+            },
+            ("ascii_upcase", 0) => match input {
+                Value::String(s) => output(Value::String(s.to_ascii_uppercase())),
+                _ => Err(expect_type_error("ascii_upcase", "string", &input)),
+            },
+            ("tochar", 0) => output(Value::Char(to_char(&input, "tochar")?)),
+            ("tocodepoint", 0) => {
+                match &input {
+                    Value::Char(_) | Value::String(_) => output(Value::Number(Number::U32(
+                        to_char(&input, "tocodepoint")? as u32,
+                    ))),
+                    _ => Err(expect_type_error(
+                        "tocodepoint",
+                        "char or single-character string",
+                        &input,
+                    )),
+                }
+            }
+            ("explode", 0) => match input {
+                Value::String(s) => output(Value::List(s.chars().map(Value::Char).collect())),
+                _ => Err(expect_type_error("explode", "string", &input)),
+            },
+            ("implode", 0) => match input {
+                Value::List(items) | Value::Tuple(items) => output(Value::String(
+                    items
+                        .iter()
+                        .map(|item| to_char(item, "implode"))
+                        .collect::<Result<String, _>>()?,
+                )),
+                _ => Err(expect_type_error("implode", "list", &input)),
+            },
+            ("format_date", 1) => self.eval(&args[0], input.clone(), env, &mut |format| {
+                output(format_date(&input, &format, None)?)
+            }),
+            ("format_date", 2) => self.eval(&args[1], input.clone(), env, &mut |locale| {
+                self.eval(&args[0], input.clone(), env, &mut |format| {
+                    output(format_date(&input, &format, Some(&locale))?)
+                })
+            }),
+            ("ltrimstr", 1) => self.eval(&args[0], input.clone(), env, &mut |prefix| match (
+                &input, &prefix,
+            ) {
+                (Value::String(s), Value::String(p)) if s.starts_with(p.as_str()) => {
+                    output(Value::String(s[p.len()..].to_owned()))
+                }
+                _ => output(input.clone()),
+            }),
+            ("rtrimstr", 1) => self.eval(&args[0], input.clone(), env, &mut |suffix| match (
+                &input, &suffix,
+            ) {
+                (Value::String(s), Value::String(p)) if s.ends_with(p.as_str()) => {
+                    output(Value::String(s[..s.len() - p.len()].to_owned()))
+                }
+                _ => output(input.clone()),
+            }),
+            ("tojson", 0) => output(Value::String(to_tagged_json(&input, None))),
+            ("fromjson", 0) => match &input {
+                Value::String(s) => output(parse_json_from_str(s, JsonDialect::Json)?),
+                _ => Err(expect_type_error("fromjson", "string", &input)),
+            },
+            ("toxml", 0) => output(Value::String(to_xml(&input)?)),
+            ("fromxml", 0) => match &input {
+                Value::String(s) => output(parse_xml(s)?),
+                _ => Err(expect_type_error("fromxml", "string", &input)),
+            },
+            ("pseudonym", 1) => self.eval(&args[0], input.clone(), env, &mut |salt| match (
+                &input, &salt,
+            ) {
+                (Value::String(s), Value::String(salt)) => {
+                    output(Value::String(pseudonym(s, salt)))
+                }
+                (Value::String(_), _) => Err(Error::Message(format!(
+                    "The salt of \"pseudonym\" expects string, found {}.",
+                    salt.type_name()
+                ))),
+                _ => Err(expect_type_error("pseudonym", "string", &input)),
+            }),
+            ("contains", 1) => self.eval(&args[0], input.clone(), env, &mut |other| {
+                output(Value::Boolean(contains(&input, &other)?))
+            }),
+            ("indices", 1) => self.eval(&args[0], input.clone(), env, &mut |target| {
+                output(indices(&input, &target)?)
+            }),
+            ("startswith", 1) => self.eval(&args[0], input.clone(), env, &mut |prefix| match (
+                &input, &prefix,
+            ) {
+                (Value::String(s), Value::String(p)) => {
+                    output(Value::Boolean(s.starts_with(p.as_str())))
+                }
+                _ => Err(Error::Message(
+                    "Function \"startswith\" expects strings.".to_owned(),
+                )),
+            }),
+            ("endswith", 1) => self.eval(&args[0], input.clone(), env, &mut |suffix| match (
+                &input, &suffix,
+            ) {
+                (Value::String(s), Value::String(p)) => {
+                    output(Value::Boolean(s.ends_with(p.as_str())))
+                }
+                _ => Err(Error::Message(
+                    "Function \"endswith\" expects strings.".to_owned(),
+                )),
+            }),
+            ("flatten", 0) => output(Value::List(
+                flatten(elements(input, "flatten")?, None).into(),
+            )),
+            ("flatten", 1) => self.eval(&args[0], input.clone(), env, &mut |depth| {
+                let depth = match &depth {
+                    Value::Number(n) if n.as_f64() >= 0.0 => n.as_f64() as usize,
+                    _ => {
+                        return Err(Error::Message(format!(
+                            "The depth of flatten must be a non-negative number, found {}.",
+                            depth
+                        )))
+                    }
+                };
+                let items = elements(input.clone(), "flatten")?;
+                output(Value::List(flatten(items, Some(depth)).into()))
+            }),
+            ("transpose", 0) => {
+                let rows = elements(input, "transpose")?
+                    .into_iter()
+                    .map(|row| elements(row, "transpose"))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // the shorter rows are padded with `null`
+                let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+                let columns = (0..width)
+                    .map(|i| {
+                        Value::List(
+                            rows.iter()
+                                .map(|row| row.get(i).cloned().unwrap_or_else(Value::null))
+                                .collect(),
+                        )
+                    })
+                    .collect();
+                output(Value::List(columns))
+            }
+            ("zip", 1) => self.eval(&args[0], input.clone(), env, &mut |other| {
+                // the pairs are tuples, and the longer list is truncated.
+                let left = elements(input.clone(), "zip")?;
+                let right = elements(other, "zip")?;
+                output(Value::List(
+                    left.into_iter()
+                        .zip(right)
+                        .map(|(a, b)| Value::Tuple(vec![a, b].into()))
+                        .collect(),
+                ))
+            }),
+            ("range", 1) => self.eval(&args[0], input, env, &mut |upto| {
+                range(
+                    Value::Number(Number::I32(0)),
+                    &upto,
+                    &Value::Number(Number::I32(1)),
+                    output,
+                )
+            }),
+            ("range", 2) => self.eval(&args[0], input.clone(), env, &mut |from| {
+                self.eval(&args[1], input.clone(), env, &mut |upto| {
+                    range(from.clone(), &upto, &Value::Number(Number::I32(1)), output)
+                })
+            }),
+            ("range", 3) => self.eval(&args[0], input.clone(), env, &mut |from| {
+                self.eval(&args[1], input.clone(), env, &mut |upto| {
+                    self.eval(&args[2], input.clone(), env, &mut |by| {
+                        range(from.clone(), &upto, &by, output)
+                    })
+                })
+            }),
+            ("limit", 2) => self.eval(&args[0], input.clone(), env, &mut |n| {
+                let mut remaining = count(&n, "limit")?;
+                if remaining == 0 {
+                    return Ok(());
+                }
+                self.catch_break(|label| {
+                    self.eval(&args[1], input.clone(), env, &mut |v| {
+                        output(v)?;
+                        remaining -= 1;
+                        if remaining == 0 {
+                            Err(Error::Break(label))
+                        } else {
+                            Ok(())
+                        }
+                    })
+                })
+            }),
+            ("last", 1) => {
+                let mut last = None;
+                self.eval(&args[0], input, env, &mut |v| {
+                    last = Some(v);
+                    Ok(())
+                })?;
+                match last {
+                    Some(v) => output(v),
+                    None => Ok(()),
+                }
+            }
+            ("nth", 2) => self.eval(&args[0], input.clone(), env, &mut |n| {
+                let mut skipped = count(&n, "nth")?;
+                self.catch_break(|label| {
+                    self.eval(&args[1], input.clone(), env, &mut |v| {
+                        if skipped > 0 {
+                            skipped -= 1;
+                            return Ok(());
+                        }
+                        output(v)?;
+                        Err(Error::Break(label))
+                    })
+                })
+            }),
+            ("memo", 1) => {
+                // the results of `f` are collected before they are output, so
+                // `f` is evaluated entirely even if it is short-circuited,
+                // e.g. `first(memo(f))`.
+                // the environment is a part of the key only if `f` depends on it,
+                // so the entries are shared by the calls of the same function.
+                let environment = Environment::is_captured_by(env, &args[0]).then(|| env.id());
+                let key = MemoKey::new(
+                    &args[0] as *const Expression as usize,
+                    environment,
+                    input.clone(),
+                );
+
+                let cached = self.memo.borrow_mut().get(&key);
+                let results = match cached {
+                    Some(results) => results,
+                    None => {
+                        let mut results = vec![];
+                        self.eval(&args[0], input, env, &mut |v| {
+                            results.push(v);
+                            Ok(())
+                        })?;
+                        self.memo.borrow_mut().insert(key, results.clone());
+                        results
+                    }
+                };
+
+                for value in results {
+                    output(value)?;
+                }
+                Ok(())
+            }
+            ("path", 1) => self.eval_path(&args[0], vec![], input, env, &mut |p, _| {
+                output(Value::List(p.into()))
+            }),
+            ("provenance", 1) => match &self.provenance {
+                // the paths are relative to the input of the whole query,
+                // since the values do not know where they are.
+                Some(provenance) => self.eval_path(
+                    &args[0],
+                    vec![],
+                    provenance.root.clone(),
+                    env,
+                    &mut |p, v| output(provenance.describe(p, v)),
+                ),
+                None => Err(Error::Message(
+                    "The provenance is not tracked, use the option \"--provenance\" to enable it."
+                        .to_owned(),
+                )),
+            },
+            ("getpath", 1) => self.eval(&args[0], input.clone(), env, &mut |p| {
+                let p = path_from_value(p)?;
+                output(getpath(&input, &p)?)
+            }),
+            ("setpath", 2) => self.eval(&args[1], input.clone(), env, &mut |v| {
+                self.eval(&args[0], input.clone(), env, &mut |p| {
+                    let p = path_from_value(p)?;
+                    output(setpath(input.clone(), &p, v.clone())?)
+                })
+            }),
+            ("delpaths", 1) => self.eval(&args[0], input.clone(), env, &mut |ps| {
+                let paths = elements(ps, "delpaths")?
+                    .into_iter()
+                    .map(path_from_value)
+                    .collect::<Result<Vec<_>, _>>()?;
+                output(delpaths(input.clone(), paths)?)
+            }),
+            ("validates", 1) => self.eval(&args[0], input.clone(), env, &mut |schema| {
+                let schema = Schema::from_value(&schema)?;
+                output(Value::Boolean(schema.validate(&input).is_empty()))
+            }),
+            (_, 0 | 1) if cast_target(name).is_some() => {
+                let target = cast_target(name).unwrap();
+                let Value::Number(n) = &input else {
+                    return Err(expect_type_error(name, "number", &input));
+                };
+
+                match args.first() {
+                    None => output(Value::Number(cast_number(n, target, CastMode::Checked)?)),
+                    Some(mode) => self.eval(mode, input.clone(), env, &mut |mode| match &mode {
+                        Value::String(s) => {
+                            let mode = CastMode::from_name(s)?;
+                            output(Value::Number(cast_number(n, target, mode)?))
+                        }
+                        _ => Err(expect_type_error(name, "a string cast mode", &mode)),
+                    }),
+                }
+            }
+            _ => Err(Error::Message(format!(
+                "Function \"{}/{}\" is not defined.",
+                name,
+                args.len()
+            ))),
+        }
+    }
+
+    // The native functions which can be used as path expressions.
+    pub(crate) fn call_native_path<'a>(
+        &self,
+        name: &str,
+        args: &'a [Expression],
+        path: Vec<Value>,
+        value: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut PathOutput,
+    ) -> Result<(), Error> {
+        match (name, args.len()) {
+            ("empty", 0) => Ok(()),
+            ("error", 0) => Err(Error::Raised(value)),
+            ("error", 1) => self.eval(&args[0], value, env, &mut |message| {
+                Err(Error::Raised(message))
+            }),
+            ("recurse", 0) => recurse_paths(path, value, output),
+            ("getpath", 1) => self.eval(&args[0], value.clone(), env, &mut |p| {
+                let p = path_from_value(p)?;
+                let child = getpath(&value, &p)?;
+                let mut child_path = path.clone();
+                child_path.extend(p);
+                output(child_path, child)
+            }),
+            ("limit", 2) => self.eval(&args[0], value.clone(), env, &mut |n| {
+                let mut remaining = count(&n, "limit")?;
+                if remaining == 0 {
+                    return Ok(());
+                }
+                self.catch_break(|label| {
+                    self.eval_path(&args[1], path.clone(), value.clone(), env, &mut |p, v| {
+                        output(p, v)?;
+                        remaining -= 1;
+                        if remaining == 0 {
+                            Err(Error::Break(label))
+                        } else {
+                            Ok(())
+                        }
+                    })
+                })
+            }),
+            _ => self.call_native(name, args, value, env, &mut |v| Err(invalid_path_error(&v))),
+        }
+    }
+
+    // take the next input document, and make it the current one.
+    fn next_input(&self) -> Result<Option<Value>, Error> {
+        let mut inputs = self.inputs.borrow_mut();
+        let Some(next) = inputs.pop_front() else {
+            return match &self.input_stream {
+                Some(stream) => stream.borrow_mut().next().transpose(),
+                None => Ok(None),
+            };
+        };
+        if let Some(idx) = self.input_sources.len().checked_sub(inputs.len() + 1) {
+            self.current_input.set(Some(idx));
+        }
+        Ok(Some(next))
+    }
+
+    fn current_source(&self) -> Option<&DocumentSource> {
+        self.current_input
+            .get()
+            .and_then(|idx| self.input_sources.get(idx))
+    }
+
+    // the metadata of the current input document, i.e. `$__input__`.
+    pub(crate) fn input_metadata(&self) -> Value {
+        match self.current_input.get() {
+            Some(idx) => self
+                .input_sources
+                .get(idx)
+                .map_or_else(Value::null, |source| source.metadata(idx)),
+            None => Value::null(),
+        }
+    }
+
+    // The elements paired with the sort keys (all results of `f`),
+    // sorted by the keys, the sort is stable.
+    fn keyed_elements<'a>(
+        &self,
+        f: &'a Expression,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        function_name: &str,
+    ) -> Result<Vec<(Vec<Value>, Value)>, Error> {
+        let mut keyed = vec![];
+        for item in elements(input, function_name)? {
+            let key = self.collect(f, item.clone(), env)?;
+            keyed.push((key, item));
+        }
+        keyed.sort_by(|(a, _), (b, _)| a.cmp(b));
+        Ok(keyed)
+    }
+}
+
+// the name is either "Type::Member", or "Type" which matches all members.
+fn is_variant(value: &Value, name: &str) -> bool {
+    let Value::Variant(v) = value else {
+        return false;
+    };
+
+    match name.split_once("::") {
+        Some((type_name, member_name)) => v.type_name == type_name && v.member_name == member_name,
+        None => v.type_name == name,
+    }
+}
+
+// the payload of the variant, like Rust, `Option::None` and
+// `Result::Err(...)` cannot be unwrapped.
+fn unwrap(value: Value) -> Result<Value, Error> {
+    let Value::Variant(v) = value else {
+        return Err(expect_type_error("unwrap", "variant", &value));
+    };
+
+    if v.type_name == "Result" && v.member_name == "Err" {
+        return Err(Error::Message(format!(
+            "Cannot unwrap {}.",
+            Value::Variant(v)
+        )));
+    }
+
+    let v = Arc::unwrap_or_clone(v);
+    match v.value {
+        VariantValue::Value(inner) => Ok(*inner),
+        VariantValue::Tuple(items) => Ok(Value::Tuple(items)),
+        VariantValue::Object(map) => Ok(Value::Object(map)),
+        VariantValue::Empty => Err(Error::Message(format!(
+            "Cannot unwrap {}::{}, it has no value.",
+            v.type_name, v.member_name
+        ))),
+    }
+}
+
+// the byte data from a string (the UTF-8 bytes), or from a list
+// (or a tuple) of integers between 0 and 255.
+fn to_bytes(value: Value) -> Result<Vec<u8>, Error> {
+    match value {
+        Value::ByteData(bytes) => Ok(bytes),
+        Value::String(s) => Ok(s.into_bytes()),
+        Value::List(items) | Value::Tuple(items) => items
+            .iter()
+            .map(|item| {
+                match item {
+                    Value::Number(n) => n.as_integer().and_then(|i| u8::try_from(i).ok()),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    Error::Message(format!(
+                        "The byte must be an integer between 0 and 255, found {}.",
+                        item
+                    ))
+                })
+            })
+            .collect(),
+        _ => Err(expect_type_error(
+            "tobytes",
+            "string, list or tuple",
+            &value,
+        )),
+    }
+}
+
+// output the paths whose last component is a key that matches.
+fn paths_with_key(
+    value: &Value,
+    matches: impl Fn(&str) -> bool,
+    output: &mut Output,
+) -> Result<(), Error> {
+    recurse_paths(vec![], value.clone(), &mut |path, _| match path.last() {
+        Some(Value::String(key)) if matches(key) => output(Value::List(path.into())),
+        _ => Ok(()),
+    })
+}
+
+// the chars, single-character strings and code points are converted to char.
+fn to_char(value: &Value, function_name: &str) -> Result<char, Error> {
+    match value {
+        Value::Char(c) => Ok(*c),
+        Value::String(s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(c),
+                _ => Err(Error::Message(format!(
+                    "Function \"{}\" expects a single-character string, found {}.",
+                    function_name, value
+                ))),
+            }
+        }
+        Value::Number(n) => n
+            .as_integer()
+            .and_then(|i| u32::try_from(i).ok())
+            .and_then(char::from_u32)
+            .ok_or_else(|| Error::Message(format!("Number {} is not a valid code point.", value))),
+        _ => Err(expect_type_error(
+            function_name,
+            "char, single-character string or code point",
+            value,
+        )),
+    }
+}
+
+// format the datetime (or the seconds since the Unix epoch) with the
+// `strftime` specifiers, the names of the weekdays and months are
+// translated if the locale (e.g. "fr-FR") is specified.
+fn format_date(input: &Value, format: &Value, locale: Option<&Value>) -> Result<Value, Error> {
+    let datetime = match input {
+        Value::DateTime(d) => *d,
+        Value::Number(n) => {
+            let seconds = n.as_f64();
+            let nanos = ((seconds - seconds.floor()) * 1e9) as u32;
+            DateTime::from_timestamp(seconds.floor() as i64, nanos)
+                .map(|d| d.fixed_offset())
+                .ok_or_else(|| {
+                    Error::Message(format!("Number {} is out of the range of datetime.", input))
+                })?
+        }
+        _ => return Err(expect_type_error("format_date", "datetime", input)),
+    };
+
+    let Value::String(format) = format else {
+        return Err(expect_type_error("format_date", "a format string", format));
+    };
+    if StrftimeItems::new(format).any(|item| item == Item::Error) {
+        return Err(Error::Message(format!(
+            "Invalid date format \"{}\".",
+            format
+        )));
+    }
+
+    let text = match locale {
+        None => datetime.format(format).to_string(),
+        Some(Value::String(name)) => {
+            // the locale names are in the form of "fr_FR"
+            let locale = Locale::try_from(name.replace('-', "_").as_str())
+                .map_err(|_| Error::Message(format!("Unknown locale \"{}\".", name)))?;
+            datetime.format_localized(format, locale).to_string()
+        }
+        Some(other) => return Err(expect_type_error("format_date", "a locale string", other)),
+    };
+    Ok(Value::String(text))
+}
+
+// the target type of the cast functions, e.g. "u8" of `to_u8`.
+fn cast_target(name: &str) -> Option<&str> {
+    name.strip_prefix("to_")
+        .filter(|target| CAST_TYPES.contains(target))
+}
+
+// the salted HMAC of the string, the same string (with the same salt)
+// always has the same pseudonym, and the 64 bits make the collisions
+// unlikely within a dataset.
+fn pseudonym(text: &str, salt: &str) -> String {
+    let digest = hmac_sha256(salt.as_bytes(), text.as_bytes());
+    format!("anon_{}", encode_hex(&digest[..8]))
+}
+
+fn expect_type_error(function_name: &str, expected: &str, actual: &Value) -> Error {
+    Error::Message(format!(
+        "Function \"{}\" expects {}, found {}.",
+        function_name,
+        expected,
+        actual.type_name()
+    ))
+}
+
+// the elements of a list or a tuple
+fn elements(input: Value, function_name: &str) -> Result<Vec<Value>, Error> {
+    match input {
+        Value::List(items) | Value::Tuple(items) => Ok(items.into_vec()),
+        Value::Object(map) if function_name == "add" => {
+            Ok(map.into_iter().map(|(_, v)| v).collect())
+        }
+        _ => Err(expect_type_error(function_name, "list", &input)),
+    }
+}
+
+// the number of items for `limit` and `nth`.
+fn count(value: &Value, function_name: &str) -> Result<usize, Error> {
+    match value {
+        Value::Number(n) => match n.as_integer() {
+            Some(i) if i >= 0 => Ok(i as usize),
+            _ => Err(Error::Message(format!(
+                "The count of {} must be a non-negative integer, found {}.",
+                function_name, value
+            ))),
+        },
+        _ => Err(expect_type_error(function_name, "number", value)),
+    }
+}
+
+// flatten the nested lists, the tuples are kept as they are,
+// all levels are flattened if the depth is `None`.
+fn flatten(items: Vec<Value>, depth: Option<usize>) -> Vec<Value> {
+    let mut flat = vec![];
+    for item in items {
+        match item {
+            Value::List(inner) if depth != Some(0) => {
+                flat.extend(flatten(inner.into_vec(), depth.map(|d| d - 1)));
+            }
+            _ => flat.push(item),
+        }
+    }
+    flat
+}
+
+// output the numbers from `from` (inclusive) to `upto` (exclusive)
+// by `by`, it counts down if the step is negative.
+fn range(from: Value, upto: &Value, by: &Value, output: &mut Output) -> Result<(), Error> {
+    let step = match (&from, upto, by) {
+        (Value::Number(_), Value::Number(_), Value::Number(n)) => n.as_f64(),
+        _ => {
+            return Err(Error::Message(format!(
+                "The arguments of range must be numbers, found {}, {} and {}.",
+                from.type_name(),
+                upto.type_name(),
+                by.type_name()
+            )))
+        }
+    };
+
+    let expected = if step > 0.0 {
+        Ordering::Less
+    } else if step < 0.0 {
+        Ordering::Greater
+    } else {
+        return Err(Error::Message(
+            "The step of range cannot be zero.".to_owned(),
+        ));
+    };
+
+    let mut current = from;
+    while current.compare(upto) == expected {
+        let next = binary(BinaryOperator::Add, current.clone(), by)?;
+        output(current)?;
+        current = next;
+    }
+    Ok(())
+}
+
+fn length(input: &Value) -> Result<Value, Error> {
+    let value = match input {
+        Value::String(s) => Value::from(s.chars().count()),
+        Value::List(items) | Value::Tuple(items) => Value::from(items.len()),
+        Value::Object(map) => Value::from(map.len()),
+        Value::ByteData(bytes) => Value::from(bytes.len()),
+        Value::Number(n) => Value::Number(match n {
+            // the length of a number is the absolute value
+            Number::F32(v) => Number::F32(v.abs()),
+            Number::F64(v) => Number::F64(v.abs()),
+            _ => Number::from_i128_like(n.as_i128().unwrap().abs(), Some(n)),
+        }),
+        _ if input.is_null() => Value::from(0),
+        _ => {
+            return Err(Error::Message(format!(
+                "{} has no length.",
+                input.type_name()
+            )));
+        }
+    };
+    Ok(value)
+}
+
+fn keys(input: &Value, sorted: bool) -> Result<Value, Error> {
+    match input {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            if sorted {
+                keys.sort();
+            }
+            Ok(Value::List(
+                keys.into_iter().map(|k| Value::String(k.clone())).collect(),
+            ))
+        }
+        Value::List(items) | Value::Tuple(items) => {
+            Ok(Value::List((0..items.len()).map(Value::from).collect()))
+        }
+        _ => Err(Error::Message(format!(
+            "{} has no keys.",
+            input.type_name()
+        ))),
+    }
+}
+
+fn has(input: &Value, key: &Value) -> Result<bool, Error> {
+    match (input, key) {
+        (Value::Object(map), Value::String(k)) => Ok(map.contains_key(k)),
+        (Value::List(items) | Value::Tuple(items), Value::Number(n)) => {
+            Ok(matches!(n.as_integer(), Some(i) if i >= 0 && (i as usize) < items.len()))
+        }
+        _ => Err(Error::Message(format!(
+            "Cannot check whether {} has a key of {}.",
+            input.type_name(),
+            key.type_name()
+        ))),
+    }
+}
+
+// the structural containment, strings contain the substrings, lists contain
+// the lists whose items are all contained by any item, and objects contain
+// the objects whose values are all contained by the values of the same keys.
+fn contains(input: &Value, other: &Value) -> Result<bool, Error> {
+    if input.type_name() != other.type_name() {
+        return Err(Error::Message(format!(
+            "Cannot check whether {} contains {}.",
+            input.type_name(),
+            other.type_name()
+        )));
+    }
+    Ok(contains_value(input, other))
+}
+
+// the nested values of different types are not contained.
+fn contains_value(input: &Value, other: &Value) -> bool {
+    match (input, other) {
+        (Value::String(a), Value::String(b)) => a.contains(b.as_str()),
+        (Value::List(a) | Value::Tuple(a), Value::List(b) | Value::Tuple(b)) => b
+            .iter()
+            .all(|item| a.iter().any(|candidate| contains_value(candidate, item))),
+        (Value::Object(a), Value::Object(b)) => b
+            .iter()
+            .all(|(key, item)| a.get(key).is_some_and(|c| contains_value(c, item))),
+        _ => input == other,
+    }
+}
+
+// the positions of the target, the positions of strings are counted in chars,
+// a list target is searched as a sub-list.
+fn indices(input: &Value, target: &Value) -> Result<Value, Error> {
+    let positions: Vec<usize> = match (input, target) {
+        _ if input.is_null() => return Ok(Value::null()),
+        (Value::String(s), Value::String(t)) => {
+            if t.is_empty() {
+                vec![]
+            } else {
+                let chars: Vec<char> = s.chars().collect();
+                let pattern: Vec<char> = t.chars().collect();
+                (0..chars.len())
+                    .filter(|i| chars[*i..].starts_with(&pattern))
+                    .collect()
+            }
+        }
+        (
+            Value::List(items) | Value::Tuple(items),
+            Value::List(pattern) | Value::Tuple(pattern),
+        ) => {
+            if pattern.is_empty() {
+                vec![]
+            } else {
+                (0..items.len())
+                    .filter(|i| items[*i..].starts_with(pattern))
+                    .collect()
+            }
+        }
+        (Value::List(items) | Value::Tuple(items), _) => {
+            (0..items.len()).filter(|i| items[*i] == *target).collect()
+        }
+        _ => {
+            return Err(Error::Message(format!(
+                "Cannot search {} in {}.",
+                target.type_name(),
+                input.type_name()
+            )))
+        }
+    };
+    Ok(Value::List(
+        positions.into_iter().map(Value::from).collect(),
+    ))
+}
+
+fn join(input: &Value, separator: &Value) -> Result<Value, Error> {
+    let separator = match separator {
+        Value::String(s) => s,
+        _ => return Err(expect_type_error("join", "a string separator", separator)),
+    };
+
+    let items = match input {
+        Value::List(items) | Value::Tuple(items) => items,
+        _ => return Err(expect_type_error("join", "list", input)),
+    };
+
+    let mut parts = vec![];
+    for item in items {
+        let part = match item {
+            Value::String(s) => s.clone(),
+            Value::Number(_) | Value::Boolean(_) | Value::Char(_) => item.to_text(),
+            _ if item.is_null() => String::new(),
+            _ => {
+                return Err(Error::Message(format!("Cannot join {}.", item.type_name())));
+            }
+        };
+        parts.push(part);
+    }
+
+    Ok(Value::String(parts.join(separator)))
+}
+
+fn split(s: &str, separator: &str) -> Vec<Value> {
+    if separator.is_empty() {
+        s.chars().map(|c| Value::String(c.to_string())).collect()
+    } else {
+        s.split(separator)
+            .map(|part| Value::String(part.to_owned()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        error::Error,
+        input::{parse_input, ParseOptions},
+        provenance::DocumentSource,
+        query::Query,
+        value::Value,
+    };
+
+    // run the query with the input document (in ASON), the results are
+    // written in the compact form.
+    fn eval(query: &str, input: &str) -> Result<Vec<String>, Error> {
+        let input = parse_input(input, None, &ParseOptions::unlimited())?;
+        let results = Query::compile(query)?.run_value(input)?;
+        Ok(results.iter().map(|v| v.to_string()).collect())
+    }
+
+    fn eval_one(query: &str, input: &str) -> String {
+        eval(query, input).unwrap().join(", ")
+    }
+
+    #[test]
+    fn test_core_builtins() {
+        assert_eq!(eval_one("[1, 2] | length, add", "0"), "2, 3");
+        assert_eq!(
+            eval_one(
+                r#"keys, has("a"), (.a | type), (null | type)"#,
+                "{b: 1, a: 2}"
+            ),
+            r#"["a","b"], true, "number", "null""#
+        );
+        assert_eq!(
+            eval_one("[3, 1, 2] | sort, unique, min, max, reverse", "0"),
+            "[1,2,3], [1,2,3], 1, 3, [2,1,3]"
+        );
+        assert_eq!(
+            eval_one("sort_by(.a), group_by(.a), min_by(.a)", "[{a: 2}, {a: 1}]"),
+            "[{a:1},{a:2}], [[{a:1}],[{a:2}]], {a:1}"
+        );
+        assert_eq!(
+            eval_one(r#""a,b" | split(",") | join("-")"#, "0"),
+            r#""a-b""#
+        );
+        assert_eq!(
+            eval_one(
+                r#""Abc" | ascii_downcase, ascii_upcase, ltrimstr("A")"#,
+                "0"
+            ),
+            r#""abc", "ABC", "bc""#
+        );
+        assert_eq!(eval("[empty, 1] | length", "0").unwrap(), ["1"]);
+        assert_eq!(
+            eval(r#"error("boom")"#, "0").unwrap_err(),
+            Error::Raised("boom".into())
+        );
+    }
+
+    #[test]
+    fn test_prelude() {
+        let doc = "[{price: 10}, {price: 20}, {price: 10}]";
+        assert_eq!(eval_one("map(.price)", doc), "[10,20,10]");
+        assert_eq!(eval_one("filter(.price > 15)", doc), "[{price:20}]");
+        assert_eq!(eval_one("unique_by(.price) | length", doc), "2");
+        assert_eq!(eval_one("[.[] | .x | values]", "[{x: 1}, {}]"), "[1]");
+        assert_eq!(eval_one("[recurse(.[]?)] | length", "[[1], 2]"), "4");
+        assert_eq!(eval_one(".filter(.price > 15)", doc), "[{price:20}]");
+    }
+
+    #[test]
+    fn test_path_builtins() {
+        let doc = r#"{a: [1, {b: 2}], c: "x"}"#;
+        assert_eq!(
+            eval_one("[paths]", doc),
+            r#"[["a"],["a",0],["a",1],["a",1,"b"],["c"]]"#
+        );
+        assert_eq!(
+            eval_one("[leaf_paths]", doc),
+            r#"[["a",0],["a",1,"b"],["c"]]"#
+        );
+        assert_eq!(
+            eval_one(r#"[paths(type == "number")]"#, doc),
+            r#"[["a",0],["a",1,"b"]]"#
+        );
+        assert_eq!(eval_one("path(.a[1].b)", doc), r#"["a",1,"b"]"#);
+        assert_eq!(
+            eval_one(r#"[path(.a[] | select(type == "number"))]"#, doc),
+            r#"[["a",0]]"#
+        );
+        assert_eq!(
+            eval_one("path(.a[1:])", doc),
+            r#"["a",{start:1,end:Option::None}]"#
+        );
+        assert_eq!(
+            eval_one(r#"getpath(["a", 1, "b"]), getpath(["x", "y"])"#, doc),
+            "2, Option::None"
+        );
+        assert_eq!(eval_one(r#"setpath(["a", 1, "b"]; 9) | .a[1].b"#, doc), "9");
+        assert_eq!(
+            eval_one(r#"delpaths([["a", 0], ["c"]])"#, doc),
+            "{a:[{b:2}]}"
+        );
+        assert_eq!(
+            eval("path(1)", doc).unwrap_err().to_string(),
+            "Invalid path expression with result 1."
+        );
+    }
+
+    #[test]
+    fn test_del() {
+        let doc = r#"{secrets: 1, items: [1, 2, 3], users: [{n: "a", inactive: true}, {n: "b", inactive: false}]}"#;
+        assert_eq!(
+            eval_one("del(.secrets) | keys", doc),
+            r#"["items","users"]"#
+        );
+        assert_eq!(eval_one("del(.items[0]) | .items", doc), "[2,3]");
+        assert_eq!(eval_one("del(.items[0, 2]) | .items", doc), "[2]");
+        assert_eq!(eval_one("del(.items[1:]) | .items", doc), "[1]");
+        assert_eq!(
+            eval_one("del(.users[] | select(.inactive)) | .users", doc),
+            r#"[{n:"b",inactive:false}]"#
+        );
+        assert_eq!(eval_one("del(.missing) | keys | length", doc), "3");
+    }
+
+    #[test]
+    fn test_entries() {
+        let doc = "{a: 1, b: 2}";
+        assert_eq!(
+            eval_one("to_entries", doc),
+            r#"[{key:"a",value:1},{key:"b",value:2}]"#
+        );
+        assert_eq!(eval_one("to_entries | from_entries", doc), "{a:1,b:2}");
+        assert_eq!(
+            eval_one(r#"with_entries(.key |= "x_" + .)"#, doc),
+            "{x_a:1,x_b:2}"
+        );
+        assert_eq!(eval_one("with_entries(select(.value > 1))", doc), "{b:2}");
+        assert_eq!(
+            eval_one(r#"with_entries({key: "\(.value)", value: .key})"#, doc),
+            r#"{"1":"a","2":"b"}"#
+        );
+
+        // the alternative names of the key and the value
+        assert_eq!(
+            eval_one(
+                r#"[{name: "n", v: 3}, {k: 1, value: false}] | from_entries"#,
+                "0"
+            ),
+            r#"{n:3,"1":false}"#
+        );
+    }
+
+    #[test]
+    fn test_list_shapes() {
+        let doc = "[1, [2, [3, [4]]]]";
+        assert_eq!(eval_one("flatten", doc), "[1,2,3,4]");
+        assert_eq!(eval_one("flatten(1)", doc), "[1,2,[3,[4]]]");
+        assert_eq!(eval_one("flatten(0)", "[[1]]"), "[[1]]");
+        assert_eq!(
+            eval_one("try flatten(-1) catch .", "[]"),
+            r#""The depth of flatten must be a non-negative number, found -1.""#
+        );
+
+        assert_eq!(
+            eval_one("transpose", "[[1, 2], [3]]"),
+            "[[1,3],[2,Option::None]]"
+        );
+        assert_eq!(
+            eval_one(r#"zip(["a", "b", "c"])"#, "[1, 2]"),
+            r#"[(1,"a"),(2,"b")]"#
+        );
+
+        // the tuples are not flattened
+        assert_eq!(eval_one("zip([[3]]) | flatten", "[1, 2]"), "[(1,[3])]");
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(eval_one("[range(4)]", "0"), "[0,1,2,3]");
+        assert_eq!(eval_one("[range(2; 5)]", "0"), "[2,3,4]");
+        assert_eq!(eval_one("[range(0; 10; 3)]", "0"), "[0,3,6,9]");
+        assert_eq!(eval_one("[range(5; 0; -2)]", "0"), "[5,3,1]");
+        assert_eq!(eval_one("[range(0; 1; 0.5)]", "0"), "[0,0.5]");
+
+        // the arguments are generators
+        assert_eq!(eval_one("[range(0, 1; 2)]", "0"), "[0,1,1]");
+
+        assert_eq!(
+            eval_one("try range(0; 1; 0) catch .", "0"),
+            r#""The step of range cannot be zero.""#
+        );
+    }
+
+    #[test]
+    fn test_stream_selection() {
+        assert_eq!(eval_one("first(range(10; 0; -1))", "0"), "10");
+        assert_eq!(eval_one("[limit(3; range(100000000))]", "0"), "[0,1,2]");
+        assert_eq!(eval_one("[limit(0; 1, 2)]", "0"), "[]");
+        assert_eq!(eval_one("last(range(5))", "0"), "4");
+        assert_eq!(eval_one("[last(empty)]", "0"), "[]");
+        assert_eq!(eval_one("nth(2; range(100000000))", "0"), "2");
+        assert_eq!(eval_one("[nth(5; 1, 2)]", "0"), "[]");
+        assert_eq!(
+            eval_one("[first(range(3)), last, nth(1)]", "[7, 8, 9]"),
+            "[0,9,8]"
+        );
+        assert_eq!(
+            eval_one("[limit(2; limit(3; range(9)) | first(., 10))]", "0"),
+            "[0,1]"
+        );
+        assert_eq!(eval_one("[range(3) | limit(1; ., 9)]", "0"), "[0,1,2]");
+
+        // the stream stops before the error
+        assert_eq!(
+            eval_one(r#"try limit(2; 1, error("x")) catch ."#, "0"),
+            r#"1, "x""#
+        );
+        assert_eq!(eval_one("[path(limit(1; .a, .b))]", "{}"), r#"[["a"]]"#);
+        assert_eq!(eval_one(".[] |= (., 9)", "[1, 2]"), "[1,2]");
+        assert_eq!(
+            eval_one("try limit(-1; 1) catch .", "0"),
+            r#""The count of limit must be a non-negative integer, found -1.""#
+        );
+    }
+
+    #[test]
+    fn test_any_all() {
+        assert_eq!(
+            eval_one(
+                r#"[.[] | select(.tags | any(. == "urgent")) | .id]"#,
+                r#"[{id: 1, tags: ["urgent"]}, {id: 2, tags: []}]"#
+            ),
+            "[1]"
+        );
+        assert_eq!(eval_one("[any, all]", "[true, false]"), "[true,false]");
+        assert_eq!(eval_one("[any, all]", "[]"), "[false,true]");
+        assert_eq!(eval_one("all(. > 0)", "[1, 2]"), "true");
+
+        // the generators are short-circuited
+        assert_eq!(eval_one("any(range(100000000); . == 3)", "0"), "true");
+        assert_eq!(eval_one("all(range(100000000); . < 3)", "0"), "false");
+        assert_eq!(
+            eval_one(r#"[isempty(empty), isempty(1, error("x"))]"#, "0"),
+            "[true,false]"
+        );
+    }
+
+    #[test]
+    fn test_containment_and_search() {
+        assert_eq!(eval_one(r#"contains("bar")"#, r#""foobar""#), "true");
+        assert_eq!(
+            eval_one(
+                r#"contains(["baz", "bar"])"#,
+                r#"["foobar", "foobaz", "blarp"]"#
+            ),
+            "true"
+        );
+        let doc = "{foo: 12, bar: [1, 2, {barp: 12, blip: 13}]}";
+        assert_eq!(
+            eval_one("contains({foo: 12, bar: [{barp: 12}]})", doc),
+            "true"
+        );
+        assert_eq!(
+            eval_one("contains({foo: 12, bar: [{barp: 15}]})", doc),
+            "false"
+        );
+        assert_eq!(
+            eval_one("try contains(1) catch .", r#""a""#),
+            r#""Cannot check whether string contains number.""#
+        );
+        assert_eq!(eval_one(r#"inside("foobar")"#, r#""bar""#), "true");
+
+        assert_eq!(
+            eval_one(r#"indices(", ")"#, r#""a, b, cd, efg""#),
+            "[1,4,8]"
+        );
+        assert_eq!(eval_one("indices(1)", "[0, 1, 2, 1, 3]"), "[1,3]");
+        assert_eq!(
+            eval_one("indices([1, 2])", "[0, 1, 2, 3, 1, 4, 2, 5, 1, 2]"),
+            "[1,8]"
+        );
+        assert_eq!(
+            eval_one(r#"[index("b"), rindex("b"), index("z")]"#, r#""abcb""#),
+            "[1,3,Option::None]"
+        );
+
+        assert_eq!(
+            eval_one(r#"[startswith("ab"), endswith("ab")]"#, r#""abc""#),
+            "[true,false]"
+        );
+        assert_eq!(
+            eval_one("try startswith(1) catch .", r#""a""#),
+            r#""Function \"startswith\" expects strings.""#
+        );
+    }
+
+    #[test]
+    fn test_environment() {
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(eval_one("$ENV.PATH", "0"), format!("{:?}", path));
+        assert_eq!(eval_one("env.PATH", "0"), format!("{:?}", path));
+        assert_eq!(eval_one("env | type", "0"), r#""object""#);
+
+        // the variable can be shadowed
+        assert_eq!(eval_one("1 as $ENV | $ENV", "0"), "1");
+    }
+
+    #[test]
+    fn test_input_builtins() {
+        let inputs = |query: &str, count: usize| {
+            let documents = ["{a: 1}", "{b: 2}", "{c: 3}"]
+                .iter()
+                .take(count)
+                .map(|text| parse_input(text, None, &ParseOptions::unlimited()).unwrap())
+                .collect();
+            let results = Query::compile(query)
+                .unwrap()
+                .with_inputs(documents)
+                .run_value(Value::null())
+                .unwrap();
+            results.iter().map(|v| v.to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(inputs("[inputs] | add", 3), ["{a:1,b:2,c:3}"]);
+        assert_eq!(inputs("[inputs | [., input]]", 2), ["[[{a:1},{b:2}]]"]);
+        assert_eq!(
+            inputs("input, (try input catch .)", 1),
+            ["{a:1}", r#""No more inputs.""#]
+        );
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(
+            eval_one("[.[] | tostring]", r#"[1, "a", [true]]"#),
+            r#"["1","a","[true]"]"#
+        );
+        assert_eq!(
+            eval_one("[.[] | toason]", r#"[1, "a"]"#),
+            r#"["1","\"a\""]"#
+        );
+        assert_eq!(
+            eval_one(r#""[1, {a: 2_u8}]" | fromason"#, "0"),
+            "[1,{a:2_u8}]"
+        );
+        assert_eq!(eval_one("toason | fromason", "{a: [1_i64]}"), "{a:[1_i64]}");
+
+        assert_eq!(
+            eval_one("[.[] | tonumber]", r#"[7, 1.5, " 8 "]"#),
+            "[7,1.5,8]"
+        );
+        assert_eq!(
+            eval_one("try tonumber catch .", "true"),
+            r#""Function \"tonumber\" expects number or string, found boolean.""#
+        );
+        assert_eq!(
+            eval_one(r#"try ("abc" | tonumber) catch ."#, "0"),
+            r#""Cannot parse \"abc\" as a number.""#
+        );
+    }
+
+    #[test]
+    fn test_walk() {
+        assert_eq!(
+            eval_one(
+                r#"walk(if type == "string" then ascii_downcase else . end)"#,
+                r#"{A: ["X", {b: "Y"}], c: 1}"#
+            ),
+            r#"{A:["x",{b:"y"}],c:1}"#
+        );
+        assert_eq!(
+            eval_one(
+                r#"walk(if type == "object" then with_entries(select(.value != null)) else . end)"#,
+                "{a: Option::None, b: {c: Option::None, d: 1}}"
+            ),
+            "{b:{d:1}}"
+        );
+        assert_eq!(
+            eval_one(
+                r#"walk(if type == "list" then sort else . end)"#,
+                "[[3, 1], [2, 0]]"
+            ),
+            "[[0,2],[1,3]]"
+        );
+
+        // the tuples are walked as well
+        assert_eq!(
+            eval_one(
+                r#"walk(if type == "number" then . + 1 else . end)"#,
+                "(1, [2])"
+            ),
+            "(2,[3])"
+        );
+    }
+
+    #[test]
+    fn test_variant_matching() {
+        let doc = r#"[Option::Some(123), Option::None, Result::Err("boom"), Shape::Circle{radius: 2}, 1]"#;
+        assert_eq!(
+            eval_one(r#"map(is_variant("Option::Some"))"#, doc),
+            "[true,false,false,false,false]"
+        );
+        assert_eq!(
+            eval_one(r#"map(is_variant("Option"))"#, doc),
+            "[true,true,false,false,false]"
+        );
+        assert_eq!(
+            eval_one(".[0].unwrap(), .[3].unwrap().radius", doc),
+            "123, 2"
+        );
+        assert_eq!(
+            eval_one(".[:4] | map(unwrap_or(0))", doc),
+            "[123,0,0,{radius:2}]"
+        );
+        assert_eq!(
+            eval_one(".[:4] | map(variant_name)", doc),
+            r#"["Option::Some","Option::None","Result::Err","Shape::Circle"]"#
+        );
+        assert_eq!(
+            eval_one("try (.[1] | unwrap) catch .", doc),
+            r#""Cannot unwrap Option::None, it has no value.""#
+        );
+        assert_eq!(
+            eval_one("try (.[2] | unwrap) catch .", doc),
+            r#""Cannot unwrap Result::Err(\"boom\").""#
+        );
+    }
+
+    #[test]
+    fn test_bytedata_builtins() {
+        let data = r#"h"11 13 17 ff""#;
+        assert_eq!(
+            eval_one("length, .[0], .[-1], .[9]", data),
+            "4, 17_u8, 255_u8, Option::None"
+        );
+        assert_eq!(eval_one(".[1:3] | tohex", data), r#""1317""#);
+        assert_eq!(eval_one("[.[]] == tolist", data), "true");
+        assert_eq!(eval_one("tolist | tobytes | tohex", data), r#""111317ff""#);
+        assert_eq!(
+            eval_one(r#""11 13 17" | fromhex | tohex"#, "0"),
+            r#""111317""#
+        );
+        assert_eq!(
+            eval_one(r#"try ("123" | fromhex) catch ."#, "0"),
+            r#""The hex text \"123\" has an odd number of digits.""#
+        );
+        assert_eq!(
+            eval_one("try ([1, 256] | tobytes) catch .", "0"),
+            r#""The byte must be an integer between 0 and 255, found 256.""#
+        );
+
+        // the strings are encoded in UTF-8
+        assert_eq!(eval_one(r#""aé" | tobytes | length"#, "0"), "3");
+    }
+
+    #[test]
+    fn test_base64() {
+        let data = r#"h"11 13 17 ff""#;
+        assert_eq!(eval_one("@base64", data), r#""ERMX/w==""#);
+        assert_eq!(
+            eval_one("@base64 | frombase64 | tohex", data),
+            r#""111317ff""#
+        );
+        assert_eq!(
+            eval_one("[@base64, (@base64 | @base64d)]", r#""hello!?""#),
+            r#"["aGVsbG8hPw==","hello!?"]"#
+        );
+    }
+
+    #[test]
+    fn test_memo() {
+        // all results of `f` are cached
+        assert_eq!(
+            eval_one("[.[] | memo(. * 2, . + 1)]", "[1, 2, 1]"),
+            "[2,2,4,3,2,2]"
+        );
+        assert_eq!(eval_one("[.[] | memo(empty)]", "[1, 1]"), "[]");
+
+        // the results are not cached when `f` fails
+        assert_eq!(eval_one(r#"[first(memo(1, error("x")))?]"#, "0"), "[]");
+        assert_eq!(
+            eval_one(r#"[.[] | try memo(error("x")) catch .]"#, "[1, 1]"),
+            r#"["x","x"]"#
+        );
+    }
+
+    #[test]
+    fn test_char_builtins() {
+        assert_eq!(
+            eval_one(r#"[65, "é", ("x" | tochar)] | map(tochar)"#, "0"),
+            "['A','é','x']"
+        );
+        assert_eq!(
+            eval_one(r#"[("A" | tochar), "é"] | map(tocodepoint)"#, "0"),
+            "[65_u32,233_u32]"
+        );
+        assert_eq!(eval_one("explode", r#""aé""#), "['a','é']");
+        assert_eq!(
+            eval_one("explode | reverse | implode", r#""héllo""#),
+            r#""olléh""#
+        );
+        assert_eq!(
+            eval_one(r#"[("a" | tochar), "b", 99] | implode"#, "0"),
+            r#""abc""#
+        );
+        assert_eq!(eval_one("type", "'c'"), r#""char""#);
+
+        assert_eq!(
+            eval_one(r#"try ("ab" | tochar) catch ."#, "0"),
+            r#""Function \"tochar\" expects a single-character string, found \"ab\".""#
+        );
+        assert_eq!(
+            eval_one("try (55296 | tochar) catch .", "0"),
+            r#""Number 55296 is not a valid code point.""#
+        );
+        assert_eq!(
+            eval_one("try (1 | tocodepoint) catch .", "0"),
+            r#""Function \"tocodepoint\" expects char or single-character string, found number.""#
+        );
+    }
+
+    #[test]
+    fn test_debug_builtins() {
+        // the messages are written to the STDERR, the inputs pass through
+        assert_eq!(eval_one("[.[] | debug | . * 2]", "[1, 2]"), "[2,4]");
+        assert_eq!(
+            eval_one(r#"[.[] | debug("item", "x: \(.)")]"#, "[1, 2]"),
+            "[1,2]"
+        );
+        assert_eq!(eval_one(".a | stderr", r#"{a: "x"}"#), r#""x""#);
+    }
+
+    #[test]
+    fn test_input_sources() {
+        let source = |filename: Option<&str>, line| DocumentSource {
+            filename: filename.map(|n| n.to_owned()),
+            line,
+            size: 0,
+            modified: None,
+        };
+        let document = |text| parse_input(text, None, &ParseOptions::unlimited()).unwrap();
+
+        let query = Query::compile("{file: input_filename, line: input_line_number, id}")
+            .unwrap()
+            .with_input_sources(vec![source(Some("a.ason"), 3)]);
+        let mut results = vec![];
+        query
+            .run_document(0, document("{id: 1}"), &mut |v| {
+                results.push(v.to_string());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(results, [r#"{file:"a.ason",line:3,id:1}"#]);
+
+        // the source follows the documents taken by `inputs`
+        let query =
+            Query::compile("input_filename, [inputs | {id, file: input_filename}], input_filename")
+                .unwrap()
+                .with_inputs(vec![document("{id: 1}"), document("{id: 2}")])
+                .with_input_sources(vec![source(Some("a.ason"), 1), source(None, 1)]);
+        let results: Vec<String> = query
+            .run_value(Value::null())
+            .unwrap()
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            results,
+            [
+                "Option::None",
+                r#"[{id:1,file:"a.ason"},{id:2,file:Option::None}]"#,
+                "Option::None"
+            ]
+        );
+
+        assert_eq!(eval_one("input_line_number", "0"), "Option::None");
+    }
+
+    #[test]
+    fn test_input_metadata() {
+        let source = |filename: &str, size| DocumentSource {
+            filename: Some(filename.to_owned()),
+            line: 1,
+            size,
+            modified: None,
+        };
+        let query = Query::compile("$__input__ | {filename, index, size, mtime}")
+            .unwrap()
+            .with_input_sources(vec![source("a.ason", 10), source("b.ason", 312)]);
+        let mut results = vec![];
+        query
+            .run_document(1, Value::null(), &mut |v| {
+                results.push(v.to_string());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            results,
+            [r#"{filename:"b.ason",index:1,size:312,mtime:Option::None}"#]
+        );
+
+        assert_eq!(eval_one("$__input__", "0"), "Option::None");
+    }
+
+    #[test]
+    fn test_paths_with_key() {
+        let doc = r#"{id: 1, user: {id: 2, user_name: "a"}, items: [{ID: 3}]}"#;
+        assert_eq!(
+            eval_one(r#"[paths_with_key("id")]"#, doc),
+            r#"[["id"],["user","id"]]"#
+        );
+        assert_eq!(
+            eval_one(r#"[paths_with_key("^id$"; "i")]"#, doc),
+            r#"[["id"],["user","id"],["items",0,"ID"]]"#
+        );
+        assert_eq!(
+            eval_one(
+                r#". as $d | [paths_with_key("user"; "") as $p | $d | getpath($p) | type]"#,
+                doc
+            ),
+            r#"["object","string"]"#
+        );
+        assert_eq!(
+            eval_one(r#"try paths_with_key("a"; "g") catch ."#, doc),
+            r#""The flags of regular expression must be \"\" or \"i\", found \"g\".""#
+        );
+
+        // the long key and the pattern which backtracks exponentially
+        let doc = format!(
+            r#"{{"{}z": 1, "{}": 2}}"#,
+            "k".repeat(100_000),
+            "k".repeat(30)
+        );
+        assert_eq!(
+            eval_one(
+                r#"[paths_with_key("^k.*z$"; ""), paths_with_key("(k+)+z"; "i") | .[0] | length]"#,
+                &doc
+            ),
+            "[100001,100001]"
+        );
+        assert_eq!(eval_one(r#"[paths_with_key("^(k+)+y"; "")]"#, &doc), "[]");
+    }
+
+    #[test]
+    fn test_format_date() {
+        // 2024-03-01T00:00:00Z, in seconds since the Unix epoch
+        assert_eq!(
+            eval_one("format_date(\"%A %d %B %Y\")", "1709251200"),
+            "\"Friday 01 March 2024\""
+        );
+        assert_eq!(
+            eval_one("format_date(\"%A %d %B\"; \"fr-FR\")", "1709251200"),
+            "\"vendredi 01 mars\""
+        );
+        assert_eq!(
+            eval_one("format_date(\"%Y-%m-%d %H:%M:%S\")", "1709251200.5"),
+            "\"2024-03-01 00:00:00\""
+        );
+
+        assert_eq!(
+            eval("format_date(\"%Q\")", "0").unwrap_err().to_string(),
+            "Invalid date format \"%Q\"."
+        );
+        assert_eq!(
+            eval("format_date(\"%Y\"; \"xx-YY\")", "0")
+                .unwrap_err()
+                .to_string(),
+            "Unknown locale \"xx-YY\"."
+        );
+        assert_eq!(
+            eval("format_date(\"%Y\")", "\"a\"")
+                .unwrap_err()
+                .to_string(),
+            "Function \"format_date\" expects datetime, found string."
+        );
+    }
+
+    #[test]
+    fn test_pseudonym() {
+        assert_eq!(
+            eval_one(
+                "anonymize(\"s\") | [.a == .b, .a != .c, .n, (.a | startswith(\"anon_\")), (.a | length)]",
+                "{a: \"x@y\", b: \"x@y\", c: \"z\", n: 1}"
+            ),
+            "[true,true,1,true,21]"
+        );
+        assert_eq!(
+            eval_one(
+                "[pseudonym(\"a\"), pseudonym(\"b\")] | .[0] != .[1]",
+                "\"x\""
+            ),
+            "true"
+        );
+
+        assert_eq!(
+            eval("pseudonym(1)", "\"x\"").unwrap_err().to_string(),
+            "The salt of \"pseudonym\" expects string, found number."
+        );
+        assert_eq!(
+            eval("pseudonym(\"s\")", "1").unwrap_err().to_string(),
+            "Function \"pseudonym\" expects string, found number."
+        );
+    }
+
+    #[test]
+    fn test_xml_helpers() {
+        let input = "\"<catalog lang='en'><book id='1'><title>A</title></book><book id='2'>Note</book><n>x</n></catalog>\"";
+        assert_eq!(
+            eval_one("fromxml | .catalog | xml_attributes", input),
+            "{lang:\"en\"}"
+        );
+        assert_eq!(
+            eval_one("fromxml | .catalog | xml_children | keys", input),
+            "[\"book\",\"n\"]"
+        );
+        assert_eq!(
+            eval_one(
+                "fromxml | .catalog | [(.book[1] | xml_text), (.n | xml_text), (.book[0].title | xml_text)]",
+                input
+            ),
+            "[\"Note\",\"x\",\"A\"]"
+        );
+        assert_eq!(
+            eval_one(
+                "fromxml | .catalog | [(xml_list(.n) | .n), (xml_list(.book) | .book | length), (.book[0] | xml_list(.missing) | .missing)]",
+                input
+            ),
+            "[[\"x\"],2,[]]"
+        );
+        assert_eq!(
+            eval_one("{a: 1} | toxml", "1"),
+            "\"<?xml version=\\\"1.0\\\" encoding=\\\"UTF-8\\\"?>\\n<a>1</a>\\n\""
+        );
+
+        assert_eq!(
+            eval("fromxml", "1").unwrap_err().to_string(),
+            "Function \"fromxml\" expects string, found number."
+        );
+    }
+
+    #[test]
+    fn test_validates() {
+        assert_eq!(
+            eval(
+                "validates({type: \"object\", required: [\"name\"]}), validates(\"i8\"), \
+                (.age | validates({type: \"u8\", enum: [1, 2]}))",
+                "{age: 1}"
+            )
+            .unwrap(),
+            ["false", "false", "true"]
+        );
+        assert_eq!(
+            eval("validates({type: \"nope\"})", "{}")
+                .unwrap_err()
+                .to_string(),
+            "Invalid schema at .: the type \"nope\" is unknown."
+        );
+    }
+
+    #[test]
+    fn test_halt() {
+        assert_eq!(eval("1, halt, 2", "1"), Err(Error::Halted(0, None)));
+        assert_eq!(
+            eval("halt_error", "\"bad\""),
+            Err(Error::Halted(5, Some(Value::from("bad"))))
+        );
+        assert_eq!(
+            eval("halt_error(1, 2)", "{a: 1}"),
+            Err(Error::Halted(
+                1,
+                Some(parse_input("{a: 1}", None, &ParseOptions::unlimited()).unwrap())
+            ))
+        );
+
+        // it is not suppressed by `try` and `//`
+        assert_eq!(
+            eval("try halt_error(3) catch 0", "1"),
+            Err(Error::Halted(3, Some(Value::from(1))))
+        );
+        assert_eq!(eval("halt // 1", "1"), Err(Error::Halted(0, None)));
+
+        assert_eq!(
+            eval("halt_error(1.5)", "1").unwrap_err().to_string(),
+            "Function \"halt_error\" expects an integer status, found number."
+        );
+        assert_eq!(
+            eval("halt_error(\"1\")", "1").unwrap_err().to_string(),
+            "Function \"halt_error\" expects an integer status, found string."
+        );
+    }
+}
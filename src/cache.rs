@@ -0,0 +1,409 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The result cache, i.e. `aq --cache QUERY FILE`.
+//
+// The output (and the exit code) of an invocation is stored in the
+// cache directory (the first one that is specified):
+//
+// - the path of the environment variable `AQ_CACHE_DIR`
+// - `$XDG_CACHE_HOME/aq`
+// - `$HOME/.cache/aq`
+//
+// the key is the hash of the version of aq, the command line arguments,
+// the query text, the variables (including the content of the files of
+// `--rawfile`, `--slurpfile` and `--argfile`), the content of the imported
+// modules, the environment variables (for `$ENV` and `env`), and the
+// content and the modified time of the input documents, so the repeated
+// identical invocations (e.g. in the build systems and the shell prompts)
+// return the stored output without evaluating the query.
+//
+// The least recently used outputs are removed when the total size of
+// the stored outputs exceeds the limit, i.e. the bytes of the environment
+// variable `AQ_CACHE_SIZE`, or 64 MiB by default.
+//
+// The compile cache, i.e. `aq --compile-cache DIR QUERY FILE`, stores
+// the compiled program of the query (see `Query::to_program()`) in the
+// directory, the key is the hash of the version of aq, the query text
+// and the paths of the modules, and the content of each imported module
+// is checked on loading, so the changed modules are compiled again.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::query::Query;
+
+const MAGIC: &[u8; 4] = b"AQC1";
+const PROGRAM_MAGIC: &[u8; 4] = b"AQP1";
+
+const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+pub fn cache_dir() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("AQ_CACHE_DIR") {
+        return Some(PathBuf::from(path));
+    }
+
+    match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) => Some(PathBuf::from(dir).join("aq")),
+        None => Some(
+            PathBuf::from(std::env::var_os("HOME")?)
+                .join(".cache")
+                .join("aq"),
+        ),
+    }
+}
+
+pub fn cache_max_size() -> u64 {
+    std::env::var("AQ_CACHE_SIZE")
+        .ok()
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(DEFAULT_MAX_SIZE)
+}
+
+/// The stored output of an invocation.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CachedOutput {
+    pub exit_code: i32,
+
+    /// The data is written as raw bytes, e.g. MessagePack.
+    pub binary: bool,
+    pub data: Vec<u8>,
+}
+
+/// The entry of an invocation in the cache directory.
+pub struct ResultCache {
+    path: PathBuf,
+    max_size: u64,
+}
+
+impl ResultCache {
+    /// The parts of the key are hashed with their lengths,
+    /// so `["ab", "c"]` and `["a", "bc"]` are different keys.
+    pub fn new(dir: PathBuf, parts: &[&[u8]]) -> Self {
+        Self {
+            path: dir.join(format!("{:032x}", hash_parts(parts))),
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// The limit of the total size of the entries in the directory.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// The stored output, `None` if it is missing or damaged.
+    pub fn load(&self) -> Option<CachedOutput> {
+        let bytes = fs::read(&self.path).ok()?;
+        let rest = bytes.strip_prefix(MAGIC)?;
+        if rest.len() < 5 {
+            return None;
+        }
+
+        // the modified time is the last used time for the eviction.
+        let _ = fs::File::options()
+            .write(true)
+            .open(&self.path)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+
+        let exit_code = i32::from_le_bytes(rest[0..4].try_into().unwrap());
+        Some(CachedOutput {
+            exit_code,
+            binary: rest[4] != 0,
+            data: rest[5..].to_vec(),
+        })
+    }
+
+    /// The output is written to a temporary file and then renamed, so a
+    /// concurrent invocation never loads the partial output. The output
+    /// larger than the limit is not stored.
+    pub fn store(&self, output: &CachedOutput) -> io::Result<()> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&output.exit_code.to_le_bytes());
+        bytes.push(output.binary as u8);
+        bytes.extend_from_slice(&output.data);
+        if bytes.len() as u64 > self.max_size {
+            return Ok(());
+        }
+        write_atomically(&self.path, &bytes)?;
+        self.evict()
+    }
+
+    // remove the least recently used entries until the total size
+    // is within the limit, the other files in the directory are kept.
+    fn evict(&self) -> io::Result<()> {
+        let Some(dir) = self.path.parent() else {
+            return Ok(());
+        };
+
+        let mut entries = vec![];
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let is_entry = name.len() == 32
+                && name
+                    .to_str()
+                    .is_some_and(|n| n.bytes().all(|b| b.is_ascii_hexdigit()));
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if is_entry && metadata.is_file() {
+                let used = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((used, metadata.len(), entry.path()));
+            }
+        }
+
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort();
+        for (_, size, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            // the entry may have been removed by a concurrent invocation
+            if path != self.path && fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The paths and the hashes of the module files imported by the query.
+pub fn module_hashes(query: &Query) -> io::Result<Vec<(String, u128)>> {
+    query
+        .module_paths()
+        .iter()
+        .map(|path| {
+            let name = path.to_string_lossy().into_owned();
+            match hash_file(path) {
+                Some(hash) => Ok((name, hash)),
+                None => Err(io::Error::other(format!(
+                    "Fail to read the module \"{}\".",
+                    name
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// The entry of a compiled query in the compile cache directory.
+pub struct CompileCache {
+    path: PathBuf,
+}
+
+impl CompileCache {
+    /// The parts of the key are hashed as the `ResultCache`.
+    pub fn new(dir: PathBuf, parts: &[&[u8]]) -> Self {
+        Self {
+            path: dir.join(format!("{:032x}.aqp", hash_parts(parts))),
+        }
+    }
+
+    /// The stored query, `None` if it is missing or damaged,
+    /// or any of the imported modules is changed.
+    pub fn load(&self) -> Option<Query> {
+        let bytes = fs::read(&self.path).ok()?;
+        let mut rest = bytes.strip_prefix(PROGRAM_MAGIC)?;
+
+        // the paths and the hashes of the module files
+        let count = read_u32(&mut rest)?;
+        for _ in 0..count {
+            let length = read_u32(&mut rest)? as usize;
+            let path = std::str::from_utf8(rest.get(..length)?).ok()?;
+            rest = &rest[length..];
+            let hash = u128::from_le_bytes(rest.get(..16)?.try_into().unwrap());
+            rest = &rest[16..];
+            if hash_file(Path::new(path))? != hash {
+                return None;
+            }
+        }
+
+        Query::from_program(rest).ok()
+    }
+
+    pub fn store(&self, query: &Query) -> io::Result<()> {
+        let modules = module_hashes(query)?;
+        let mut bytes = PROGRAM_MAGIC.to_vec();
+        bytes.extend_from_slice(&(modules.len() as u32).to_le_bytes());
+        for (name, hash) in &modules {
+            bytes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(name.as_bytes());
+            bytes.extend_from_slice(&hash.to_le_bytes());
+        }
+        bytes.extend_from_slice(&query.to_program());
+        write_atomically(&self.path, &bytes)
+    }
+}
+
+// the parts are hashed with their lengths,
+// so `["ab", "c"]` and `["a", "bc"]` are different.
+fn hash_parts(parts: &[&[u8]]) -> u128 {
+    let mut hasher = Fnv128::new();
+    for part in parts {
+        hasher.write(&(part.len() as u64).to_le_bytes());
+        hasher.write(part);
+    }
+    hasher.finish()
+}
+
+fn hash_file(path: &Path) -> Option<u128> {
+    let mut hasher = Fnv128::new();
+    hasher.write(&fs::read(path).ok()?);
+    Some(hasher.finish())
+}
+
+fn read_u32(bytes: &mut &[u8]) -> Option<u32> {
+    let value = u32::from_le_bytes(bytes.get(..4)?.try_into().unwrap());
+    *bytes = &bytes[4..];
+    Some(value)
+}
+
+// the bytes are written to a temporary file and then renamed,
+// so a concurrent invocation never reads the partial file.
+fn write_atomically(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut temp_path = path.to_path_buf().into_os_string();
+    temp_path.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = PathBuf::from(temp_path);
+
+    let outcome = fs::File::create(&temp_path)
+        .and_then(|mut file| file.write_all(bytes))
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if outcome.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    outcome
+}
+
+// the 128-bit FNV-1a hash, it is stable across the versions
+// of Rust (unlike the `DefaultHasher`) and platforms.
+struct Fnv128(u128);
+
+impl Fnv128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u128;
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn finish(&self) -> u128 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{module::ModuleLoader, query::Query, value::Value};
+
+    use super::{CachedOutput, CompileCache, ResultCache};
+
+    #[test]
+    fn test_result_cache() {
+        let dir = std::env::temp_dir().join(format!("aq-result-cache-{}", std::process::id()));
+        let cache = ResultCache::new(dir.clone(), &[b"ab", b"c"]);
+        assert_eq!(cache.load(), None);
+
+        let output = CachedOutput {
+            exit_code: 4,
+            binary: true,
+            data: vec![1, 2, 3],
+        };
+        cache.store(&output).unwrap();
+        assert_eq!(cache.load(), Some(output));
+
+        // the parts are hashed with their lengths
+        assert_eq!(ResultCache::new(dir.clone(), &[b"a", b"bc"]).load(), None);
+
+        // the damaged entry is ignored
+        std::fs::write(&cache.path, b"AQC1\x00").unwrap();
+        assert_eq!(cache.load(), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_result_cache_eviction() {
+        let dir = std::env::temp_dir().join(format!("aq-cache-eviction-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("other.txt"), [0; 64]).unwrap();
+
+        // each entry is 19 bytes, the limit is of 2 entries
+        let output = |n: u8| CachedOutput {
+            exit_code: 0,
+            binary: false,
+            data: vec![n; 10],
+        };
+        let caches: Vec<ResultCache> = (0..3u8)
+            .map(|n| ResultCache::new(dir.clone(), &[&[n]]).with_max_size(40))
+            .collect();
+        for (n, cache) in caches.iter().enumerate().take(2) {
+            cache.store(&output(n as u8)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        // the loaded entry is the recently used one
+        assert_eq!(caches[0].load(), Some(output(0)));
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        caches[2].store(&output(2)).unwrap();
+        assert_eq!(caches[1].load(), None);
+        assert_eq!(caches[0].load(), Some(output(0)));
+        assert_eq!(caches[2].load(), Some(output(2)));
+
+        // the output larger than the limit is not stored
+        let large = ResultCache::new(dir.clone(), &[b"large"]).with_max_size(10);
+        large.store(&output(3)).unwrap();
+        assert_eq!(large.load(), None);
+
+        // the other files are kept
+        assert!(dir.join("other.txt").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_compile_cache() {
+        let dir = std::env::temp_dir().join(format!("aq-compile-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("m.aql"), "def inc: . + 1;").unwrap();
+
+        let loader = ModuleLoader::new(vec![]).with_base_dir(dir.clone());
+        let query = Query::compile_with_loader("import \"m\" as m; m::inc", &loader).unwrap();
+        let cache = CompileCache::new(dir.clone(), &[b"m::inc"]);
+        assert!(cache.load().is_none());
+
+        cache.store(&query).unwrap();
+        let loaded = cache.load().unwrap();
+        assert_eq!(loaded.to_program(), query.to_program());
+        assert_eq!(
+            loaded.run_value(Value::from(1)).unwrap(),
+            vec![Value::from(2)]
+        );
+
+        // the changed module is compiled again
+        std::fs::write(dir.join("m.aql"), "def inc: . + 2;").unwrap();
+        assert!(cache.load().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,120 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// A flag which aborts the evaluation of a query, e.g. from
+/// another thread of a GUI tool or a server.
+///
+/// The token is checked before each expression is evaluated, the
+/// aborted evaluation fails with `Error::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel all the evaluations that hold this token (or its clones).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{error::Error, query::Query, value::Value};
+
+    use super::CancellationToken;
+
+    #[test]
+    fn test_cancel_in_output() {
+        // the error is not caught by `try`
+        for text in [
+            "range(1000000000) | . * 2",
+            "try (range(1000000000) | . * 2) catch 0",
+        ] {
+            let query = Query::compile(text).unwrap();
+            let token = CancellationToken::new();
+            let mut results = vec![];
+            let outcome = query.run_with_cancellation(Value::null(), &token, &mut |v| {
+                results.push(v);
+                if results.len() == 3 {
+                    token.cancel();
+                }
+                Ok(())
+            });
+            assert_eq!(outcome, Err(Error::Cancelled));
+            assert_eq!(results, [Value::from(0), Value::from(2), Value::from(4)]);
+        }
+    }
+
+    #[test]
+    fn test_cancel_from_another_thread() {
+        // the query produces nothing, it is aborted while running
+        let query = Query::compile("reduce range(1000000000000) as $x (0; . + $x)").unwrap();
+        let token = CancellationToken::new();
+        let canceller = {
+            let token = token.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                token.cancel();
+            })
+        };
+
+        let outcome = query.run_with_cancellation(Value::null(), &token, &mut |_| Ok(()));
+        canceller.join().unwrap();
+        assert_eq!(outcome, Err(Error::Cancelled));
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancel_before_running() {
+        let query = Query::compile("1").unwrap();
+        let token = CancellationToken::new();
+        token.cancel();
+        assert_eq!(
+            query.run_with_cancellation(Value::null(), &token, &mut |_| Ok(())),
+            Err(Error::Cancelled)
+        );
+    }
+
+    #[test]
+    fn test_run_again_with_new_token() {
+        // the cancellation is not kept by the query
+        let query = Query::compile("[range(3)]").unwrap();
+        let token = CancellationToken::new();
+        token.clone().cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(
+            query.run_with_cancellation(Value::null(), &token, &mut |_| Ok(())),
+            Err(Error::Cancelled)
+        );
+
+        let mut results = vec![];
+        let outcome =
+            query.run_with_cancellation(Value::null(), &CancellationToken::new(), &mut |v| {
+                results.push(v.to_string());
+                Ok(())
+            });
+        assert_eq!(outcome, Ok(()));
+        assert_eq!(results, ["[0,1,2]"]);
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The numeric casts, i.e. `to_u8`, `to_i64`, `to_f32` etc.
+//
+// The casts produce the numbers with the type, so the output keeps
+// the type annotation, e.g. `300 | to_u16` outputs `300_u16`.
+
+use crate::{error::Error, value::Number};
+
+/// How to handle the value which does not fit in the target type.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CastMode {
+    /// Raise an error, and the floating point must have no fractional part
+    /// when it is cast to an integer.
+    Checked,
+
+    /// Clamp to the range of the target type, and the fractional part
+    /// of the floating point is truncated.
+    Saturating,
+
+    /// Wrap around at the boundary of the target type (i.e. keep the low
+    /// bits like the Rust `as`), and the fractional part is truncated.
+    Wrapping,
+}
+
+impl CastMode {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "checked" => Ok(CastMode::Checked),
+            "saturating" => Ok(CastMode::Saturating),
+            "wrapping" => Ok(CastMode::Wrapping),
+            _ => Err(Error::Message(format!(
+                "The cast mode must be \"checked\", \"saturating\" or \"wrapping\", found \"{}\".",
+                name
+            ))),
+        }
+    }
+}
+
+/// The target types of the casts, i.e. the ASON number suffixes.
+pub const CAST_TYPES: [&str; 10] = [
+    "i8", "u8", "i16", "u16", "i32", "u32", "i64", "u64", "f32", "f64",
+];
+
+pub fn cast_number(number: &Number, target: &str, mode: CastMode) -> Result<Number, Error> {
+    match target {
+        "f32" => cast_f32(number, mode),
+        "f64" => Ok(Number::F64(number.as_f64())),
+        _ => cast_integer(number, target, mode),
+    }
+}
+
+fn cast_f32(number: &Number, mode: CastMode) -> Result<Number, Error> {
+    let v = number.as_f64();
+    if v.is_finite() && v.abs() > f32::MAX as f64 {
+        match mode {
+            CastMode::Checked => return Err(out_of_range_error(number, "f32")),
+            CastMode::Saturating => return Ok(Number::F32(f32::MAX.copysign(v as f32))),
+            // the Rust `as` converts the overflowed value to infinity
+            CastMode::Wrapping => {}
+        }
+    }
+    Ok(Number::F32(v as f32))
+}
+
+fn cast_integer(number: &Number, target: &str, mode: CastMode) -> Result<Number, Error> {
+    let value = match (number.as_i128(), mode) {
+        (Some(i), _) => i,
+        (None, CastMode::Checked) => match number.as_integer() {
+            Some(i) => i,
+            None => {
+                return Err(Error::Message(format!(
+                    "Cannot cast {} to \"{}\", it has the fractional part.",
+                    number, target
+                )));
+            }
+        },
+        // the `as` saturates at the range of `i128`, and converts NaN to 0.
+        (None, _) => number.as_f64().trunc() as i128,
+    };
+
+    macro_rules! cast {
+        ($variant:ident, $t:ty) => {
+            match mode {
+                CastMode::Checked => <$t>::try_from(value)
+                    .map(Number::$variant)
+                    .map_err(|_| out_of_range_error(number, target)),
+                CastMode::Saturating => Ok(Number::$variant(
+                    value.clamp(<$t>::MIN as i128, <$t>::MAX as i128) as $t,
+                )),
+                CastMode::Wrapping => Ok(Number::$variant(value as $t)),
+            }
+        };
+    }
+
+    match target {
+        "i8" => cast!(I8, i8),
+        "u8" => cast!(U8, u8),
+        "i16" => cast!(I16, i16),
+        "u16" => cast!(U16, u16),
+        "i32" => cast!(I32, i32),
+        "u32" => cast!(U32, u32),
+        "i64" => cast!(I64, i64),
+        "u64" => cast!(U64, u64),
+        _ => Err(Error::Message(format!(
+            "Cannot cast to the unknown number type \"{}\".",
+            target
+        ))),
+    }
+}
+
+fn out_of_range_error(number: &Number, target: &str) -> Error {
+    Error::Message(format!(
+        "Number {} is out of the range of \"{}\".",
+        number, target
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        query::Query,
+        value::{Number, Value},
+    };
+
+    use super::{cast_number, CastMode};
+
+    // run the query without the input, the error is caught as the message.
+    fn eval(query: &str) -> String {
+        match Query::compile(query).and_then(|q| q.run_value(Value::null())) {
+            Ok(results) => results
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cast_number() {
+        assert_eq!(
+            cast_number(&Number::I32(300), "u16", CastMode::Checked).unwrap(),
+            Number::U16(300)
+        );
+        assert_eq!(
+            cast_number(&Number::I32(-1), "u8", CastMode::Wrapping).unwrap(),
+            Number::U8(255)
+        );
+        assert_eq!(
+            cast_number(&Number::F64(2.9), "i8", CastMode::Saturating).unwrap(),
+            Number::I8(2)
+        );
+        assert_eq!(
+            cast_number(&Number::I32(300), "u8", CastMode::Checked)
+                .unwrap_err()
+                .to_string(),
+            "Number 300 is out of the range of \"u8\"."
+        );
+        assert_eq!(
+            CastMode::from_name("rounding").unwrap_err().to_string(),
+            "The cast mode must be \"checked\", \"saturating\" or \"wrapping\", found \"rounding\"."
+        );
+    }
+
+    #[test]
+    fn test_cast_builtins() {
+        assert_eq!(
+            eval("[300 | to_u16, to_i64, to_f32, to_f64]"),
+            "[300_u16,300_i64,300.0_f32,300.0]"
+        );
+        assert_eq!(
+            eval(r#"[300, -5, 2.9, -2.9] | map(to_u8("saturating"))"#),
+            "[255_u8,0_u8,2_u8,0_u8]"
+        );
+        assert_eq!(
+            eval(r#"[300, -1, 2.9] | map(to_u8("wrapping"))"#),
+            "[44_u8,255_u8,2_u8]"
+        );
+        assert_eq!(
+            eval("[2.0 | to_i8], (try (2.5 | to_i8) catch .)"),
+            r#"[2_i8], "Cannot cast 2.5 to \"i8\", it has the fractional part.""#
+        );
+        assert_eq!(
+            eval(r#"1e40 | to_f32("saturating") == (3.4028234663852886e38 | to_f32)"#),
+            "true"
+        );
+        assert_eq!(
+            eval("try (1e40 | to_f32) catch ."),
+            r#""Number 1e40 is out of the range of \"f32\".""#
+        );
+        assert_eq!(
+            eval(r#"try ("1" | to_u8) catch ."#),
+            r#""Function \"to_u8\" expects number, found string.""#
+        );
+        assert_eq!(eval("to_u128"), r#"Function "to_u128/0" is not defined."#);
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Encoding values as CBOR (RFC 8949), and decoding them back.
+//
+// - `Option::None` is encoded as `null`, and `Option::Some(v)` as `v`.
+// - the integers are encoded with the shortest length, the floating-point
+//   numbers keep their width (single or double precision).
+// - chars are encoded as text strings, tuples as arrays.
+// - date times are encoded as the standard date/time string (tag 0).
+// - other variants are encoded as a map with a single entry,
+//   the key is the name of the variant (e.g. "Color::Red"), and the value is
+//   `null`, the inner value, the array of the tuple or the map of the object.
+//
+// The decoding is the inverse, the integers are decoded as `i32` (or `i64`,
+// `u64` if they do not fit), and the single entry maps with the variant
+// names are decoded as the variants. Both the date/time strings (tag 0)
+// and the epoch-based date/times (tag 1) are decoded as the datetimes,
+// the other tags are ignored.
+
+use chrono::DateTime;
+
+use crate::{
+    error::Error,
+    json::integer_number,
+    value::{decode_variant_entry, Map, Number, Value, VariantValue},
+};
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_NEGATIVE: u8 = 1;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_TAG: u8 = 6;
+const MAJOR_SIMPLE: u8 = 7;
+
+// the additional information of the indefinite length.
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xff;
+
+pub fn encode_cbor(value: &Value) -> Vec<u8> {
+    let mut buf = vec![];
+    write_value(&mut buf, value);
+    buf
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    if let Some(inner) = value.as_some() {
+        return write_value(buf, inner);
+    }
+
+    match value {
+        _ if value.is_null() => buf.push(0xf6),
+        Value::Boolean(b) => buf.push(if *b { 0xf5 } else { 0xf4 }),
+        Value::Number(n) => write_number(buf, n),
+        Value::Char(c) => write_text(buf, &c.to_string()),
+        Value::String(s) => write_text(buf, s),
+        Value::DateTime(d) => {
+            write_head(buf, MAJOR_TAG, 0);
+            write_text(buf, &d.to_rfc3339());
+        }
+        Value::ByteData(bytes) => {
+            write_head(buf, MAJOR_BYTES, bytes.len() as u64);
+            buf.extend(bytes);
+        }
+        Value::List(items) | Value::Tuple(items) => write_array(buf, items),
+        Value::Object(map) => write_map(buf, map),
+        Value::Variant(v) => {
+            // a single entry map
+            write_head(buf, MAJOR_MAP, 1);
+            write_text(buf, &format!("{}::{}", v.type_name, v.member_name));
+            match &v.value {
+                VariantValue::Empty => buf.push(0xf6),
+                VariantValue::Value(inner) => write_value(buf, inner),
+                VariantValue::Tuple(items) => write_array(buf, items),
+                VariantValue::Object(map) => write_map(buf, map),
+            }
+        }
+    }
+}
+
+fn write_number(buf: &mut Vec<u8>, n: &Number) {
+    match *n {
+        Number::F32(v) => {
+            buf.push(0xfa);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::F64(v) => {
+            buf.push(0xfb);
+            buf.extend(v.to_be_bytes());
+        }
+        _ => {
+            // all integer types fit in `i128`
+            let v = n.as_i128().unwrap();
+            if v >= 0 {
+                write_head(buf, MAJOR_UNSIGNED, v as u64);
+            } else {
+                write_head(buf, MAJOR_NEGATIVE, (-1 - v) as u64);
+            }
+        }
+    }
+}
+
+// write the major type and the argument with the shortest encoding.
+fn write_head(buf: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    if argument < 24 {
+        buf.push(major | argument as u8);
+    } else if argument <= u8::MAX as u64 {
+        buf.extend([major | 24, argument as u8]);
+    } else if argument <= u16::MAX as u64 {
+        buf.push(major | 25);
+        buf.extend((argument as u16).to_be_bytes());
+    } else if argument <= u32::MAX as u64 {
+        buf.push(major | 26);
+        buf.extend((argument as u32).to_be_bytes());
+    } else {
+        buf.push(major | 27);
+        buf.extend(argument.to_be_bytes());
+    }
+}
+
+fn write_text(buf: &mut Vec<u8>, s: &str) {
+    write_head(buf, MAJOR_TEXT, s.len() as u64);
+    buf.extend(s.as_bytes());
+}
+
+fn write_array(buf: &mut Vec<u8>, items: &[Value]) {
+    write_head(buf, MAJOR_ARRAY, items.len() as u64);
+    for item in items {
+        write_value(buf, item);
+    }
+}
+
+fn write_map(buf: &mut Vec<u8>, map: &Map) {
+    write_head(buf, MAJOR_MAP, map.len() as u64);
+    for (key, item) in map.iter() {
+        write_text(buf, key);
+        write_value(buf, item);
+    }
+}
+
+/// Decode the values of the CBOR sequence (RFC 8742).
+pub fn decode_cbor(bytes: &[u8]) -> Result<Vec<Value>, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut values = vec![];
+    while reader.pos < bytes.len() {
+        values.push(reader.read_value()?);
+    }
+    Ok(values)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::Message(format!("{} (at byte offset {}).", message, self.pos))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8], Error> {
+        if self.bytes.len() - self.pos < length {
+            return Err(self.error("Unexpected end of the CBOR data"));
+        }
+        let data = &self.bytes[self.pos..self.pos + length];
+        self.pos += length;
+        Ok(data)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    // the major type, the additional information and the argument,
+    // the argument of the indefinite length is `None`.
+    fn read_head(&mut self) -> Result<(u8, u8, Option<u64>), Error> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            0..=23 => Some(info as u64),
+            24 => Some(self.take(1)?[0] as u64),
+            25 => Some(u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64),
+            26 => Some(u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64),
+            27 => Some(u64::from_be_bytes(self.take(8)?.try_into().unwrap())),
+            INDEFINITE if matches!(major, MAJOR_BYTES..=MAJOR_MAP) => None,
+            _ => return Err(self.error(&format!("Invalid CBOR initial byte 0x{:02x}", initial))),
+        };
+        Ok((major, info, argument))
+    }
+
+    fn read_value(&mut self) -> Result<Value, Error> {
+        let (major, info, argument) = self.read_head()?;
+        let value = match (major, argument) {
+            (MAJOR_UNSIGNED, Some(n)) => Value::Number(integer_number(n as i128)),
+            (MAJOR_NEGATIVE, Some(n)) => Value::Number(integer_number(-1 - n as i128)),
+            (MAJOR_BYTES, _) => Value::ByteData(self.read_chunks(MAJOR_BYTES, argument)?),
+            (MAJOR_TEXT, _) => {
+                let data = self.read_chunks(MAJOR_TEXT, argument)?;
+                Value::String(
+                    String::from_utf8(data)
+                        .map_err(|_| self.error("Invalid UTF-8 text string of CBOR"))?,
+                )
+            }
+            (MAJOR_ARRAY, _) => {
+                let mut items = vec![];
+                while self.has_item(items.len(), argument)? {
+                    items.push(self.read_value()?);
+                }
+                Value::List(items.into())
+            }
+            (MAJOR_MAP, _) => {
+                let mut map = Map::new();
+                while self.has_item(map.len(), argument)? {
+                    let key = match self.read_value()? {
+                        Value::String(s) => s,
+                        Value::Number(n) if !n.is_float() => n.plain_text(),
+                        other => {
+                            return Err(self.error(&format!(
+                                "The map keys of CBOR must be strings or integers, found {}",
+                                other.type_name()
+                            )))
+                        }
+                    };
+                    let item = self.read_value()?;
+                    map.insert(key, item);
+                }
+                decode_variant_entry(map)
+            }
+            (MAJOR_TAG, Some(tag)) => {
+                let inner = self.read_value()?;
+                self.read_tagged(tag, inner)?
+            }
+            (MAJOR_SIMPLE, Some(n)) => match (info, n) {
+                (_, 20) if info < 24 => Value::Boolean(false),
+                (_, 21) if info < 24 => Value::Boolean(true),
+                // `undefined` is also `null`
+                (_, 22 | 23) if info < 24 => Value::null(),
+                (25, _) => Value::Number(Number::F32(half_to_f32(n as u16))),
+                (26, _) => Value::Number(Number::F32(f32::from_bits(n as u32))),
+                (27, _) => Value::Number(Number::F64(f64::from_bits(n))),
+                _ => return Err(self.error(&format!("Unsupported CBOR simple value {}", n))),
+            },
+            _ => return Err(self.error("Invalid CBOR data item")),
+        };
+        Ok(value)
+    }
+
+    // whether there is another item of the array or map, the
+    // indefinite length ones end with the "break" byte.
+    fn has_item(&mut self, count: usize, length: Option<u64>) -> Result<bool, Error> {
+        match length {
+            Some(length) => Ok((count as u64) < length),
+            None if self.peek() == Some(BREAK) => {
+                self.pos += 1;
+                Ok(false)
+            }
+            None if self.peek().is_none() => Err(self.error("Unexpected end of the CBOR data")),
+            None => Ok(true),
+        }
+    }
+
+    // the content of the byte or text string, the indefinite length
+    // ones are the chunks of the definite length strings.
+    fn read_chunks(&mut self, major: u8, length: Option<u64>) -> Result<Vec<u8>, Error> {
+        if let Some(length) = length {
+            return Ok(self.take(length as usize)?.to_vec());
+        }
+
+        let mut data = vec![];
+        while self.has_item(0, None)? {
+            match self.read_head()? {
+                (m, _, Some(length)) if m == major => {
+                    data.extend_from_slice(self.take(length as usize)?)
+                }
+                _ => return Err(self.error("Invalid chunk of the CBOR string")),
+            }
+        }
+        Ok(data)
+    }
+
+    fn read_tagged(&self, tag: u64, inner: Value) -> Result<Value, Error> {
+        let datetime = match (tag, &inner) {
+            (0, Value::String(s)) => DateTime::parse_from_rfc3339(s).ok(),
+            (1, Value::Number(n)) => {
+                let seconds = n.as_f64();
+                let nanos = ((seconds - seconds.floor()) * 1e9) as u32;
+                DateTime::from_timestamp(seconds.floor() as i64, nanos).map(|d| d.fixed_offset())
+            }
+            // other tags, e.g. the bignums and URIs
+            _ => return Ok(inner),
+        };
+        datetime
+            .map(Value::DateTime)
+            .ok_or_else(|| self.error("Invalid CBOR date/time"))
+    }
+}
+
+// the half-precision floating point (IEEE 754 binary16).
+fn half_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let fraction = (half & 0x3ff) as f32;
+    let magnitude = match exponent {
+        0 => fraction * 2f32.powi(-24),
+        31 if fraction == 0.0 => f32::INFINITY,
+        31 => f32::NAN,
+        _ => (1.0 + fraction / 1024.0) * 2f32.powi(exponent - 15),
+    };
+    sign * magnitude
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{decode_cbor, encode_cbor};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_encode_cbor() {
+        assert_eq!(
+            encode_cbor(&value("{a: [1, -1, 300, Option::None, true]}")),
+            vec![0xa1, 0x61, b'a', 0x85, 1, 0x20, 0x19, 1, 44, 0xf6, 0xf5]
+        );
+        assert_eq!(
+            encode_cbor(&value("1.5")),
+            vec![0xfb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_decode_cbor() {
+        let document = value(
+            r#"{a: [1, -40, 300, 70000, 5_000_000_000_i64, 1.5, "s", true, Option::None], b: 7_u16, c: -3_i8, e: Color::Red, f: Shape::Rect{w: 1}, g: Pair::P(1, 2), h: Opt::V(3), k: 2.5_f32}"#,
+        );
+        assert_eq!(
+            decode_cbor(&encode_cbor(&document)).unwrap()[0].to_string(),
+            r#"{a:[1,-40,300,70000,5000000000_i64,1.5,"s",true,Option::None],b:7,c:-3,e:Color::Red,f:Shape::Rect{w:1},g:Pair::P(1,2),h:Opt::V(3),k:2.5_f32}"#
+        );
+
+        let bytes = Value::ByteData(vec![1, 2, 3]);
+        assert_eq!(decode_cbor(&encode_cbor(&bytes)).unwrap(), [bytes]);
+
+        // the indefinite length text, the half float, and the tag is ignored
+        let documents: Vec<String> = decode_cbor(&[
+            0x7f, 0x61, b'a', 0x61, b'b', 0xff, 0xf9, 0x3c, 0x00, 0xc2, 0x41, 0x01,
+        ])
+        .unwrap()
+        .iter()
+        .map(|v| v.to_string())
+        .collect();
+        assert_eq!(documents, ["\"ab\"", "1.0_f32", "h\"01\""]);
+
+        // the array lacks an item
+        assert!(decode_cbor(&[0x82, 0x01]).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Validating the syntax of the documents, i.e. `aq check <file(s)>`.
+//
+// The documents are parsed without evaluating or printing anything,
+// the files after a failing file are still checked, and the malformed
+// entries of a list or object are reported one by one (by the tolerant
+// parsing), instead of only the first one.
+
+use std::fmt::Display;
+
+use crate::{
+    cbor::decode_cbor,
+    compress::decompress,
+    convert::{parse_documents, Format},
+    input::ParseOptions,
+    msgpack::decode_msgpack,
+    recover::parse_tolerant,
+    value::{Items, Map, Value},
+};
+
+/// A syntax error of a file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Problem {
+    /// The line number of the malformed entry, starts from 1,
+    /// `None` if the error is not located at an entry.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Check the data of a file, the compressed data is decompressed first.
+pub fn check_document(data: Vec<u8>, format: Format) -> Vec<Problem> {
+    let problem = |message: String| {
+        vec![Problem {
+            line: None,
+            message,
+        }]
+    };
+
+    let data = match decompress(data) {
+        Ok(d) => d,
+        Err(e) => return problem(format!("Fail to decompress the file: {}", e)),
+    };
+    let outcome = match format {
+        Format::MsgPack => decode_msgpack(&data).map(|_| ()),
+        Format::Cbor => decode_cbor(&data).map(|_| ()),
+        _ => {
+            let text = match std::str::from_utf8(&data) {
+                Ok(t) => t,
+                Err(e) => return problem(format!("The file is not valid UTF-8: {}.", e)),
+            };
+            let options = ParseOptions::unlimited();
+            if matches!(
+                format,
+                Format::Ason | Format::Json | Format::Json5 | Format::Jsonc
+            ) {
+                return match parse_tolerant(text, format.json_dialect(), &options) {
+                    Ok((_, malformed)) => malformed
+                        .into_iter()
+                        .map(|m| Problem {
+                            line: Some(m.line),
+                            message: m.message,
+                        })
+                        .collect(),
+                    Err(e) => problem(e.to_string()),
+                };
+            }
+            parse_documents(text, format, &options).map(|_| ())
+        }
+    };
+    match outcome {
+        Ok(_) => vec![],
+        Err(e) => problem(e.to_string()),
+    }
+}
+
+/// The summary of `--report`, i.e.
+///
+/// ```ason
+/// {
+///     files: 2
+///     valid: 1
+///     invalid: 1
+///     results: [
+///         {file: "a.ason", valid: true, problems: []}
+///         {file: "b.ason", valid: false, problems: [{line: 3, message: "..."}]}
+///     ]
+/// }
+/// ```
+pub fn check_report(results: &[(String, Vec<Problem>)]) -> Value {
+    let invalid = results.iter().filter(|(_, p)| !p.is_empty()).count();
+
+    let entries = results.iter().map(|(file, problems)| {
+        let valid = problems.is_empty();
+        let problems = problems.iter().map(|p| {
+            let mut map = Map::new();
+            if let Some(line) = p.line {
+                map.insert("line".to_owned(), Value::from(line));
+            }
+            map.insert("message".to_owned(), Value::String(p.message.clone()));
+            Value::Object(map)
+        });
+
+        let mut map = Map::new();
+        map.insert("file".to_owned(), Value::String(file.clone()));
+        map.insert("valid".to_owned(), Value::Boolean(valid));
+        map.insert(
+            "problems".to_owned(),
+            Value::List(Items::from(problems.collect::<Vec<_>>())),
+        );
+        Value::Object(map)
+    });
+
+    let mut map = Map::new();
+    map.insert("files".to_owned(), Value::from(results.len()));
+    map.insert("valid".to_owned(), Value::from(results.len() - invalid));
+    map.insert("invalid".to_owned(), Value::from(invalid));
+    map.insert(
+        "results".to_owned(),
+        Value::List(Items::from(entries.collect::<Vec<_>>())),
+    );
+    Value::Object(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::convert::Format;
+
+    use super::{check_document, check_report, Problem};
+
+    #[test]
+    fn test_check_document() {
+        assert_eq!(
+            check_document(b"{\"a\": [1, 2]}".to_vec(), Format::Json),
+            []
+        );
+
+        // the malformed entries are located
+        let problems = check_document(b"[1,\n 2,\n oops]".to_vec(), Format::Json);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].line, Some(3));
+
+        let problems = check_document(vec![0xff, 0xfe], Format::Yaml);
+        assert_eq!(problems[0].line, None);
+        assert!(problems[0]
+            .message
+            .starts_with("The file is not valid UTF-8: "));
+
+        assert_eq!(check_document(vec![0x92, 0x01], Format::MsgPack).len(), 1);
+    }
+
+    #[test]
+    fn test_check_report() {
+        let problem = Problem {
+            line: Some(3),
+            message: "Unexpected char.".to_owned(),
+        };
+        assert_eq!(problem.to_string(), "line 3: Unexpected char.");
+
+        let report = check_report(&[
+            ("a.json".to_owned(), vec![]),
+            ("b.json".to_owned(), vec![problem]),
+        ]);
+        assert_eq!(
+            report.to_string(),
+            "{files:2,valid:1,invalid:1,results:[{file:\"a.json\",valid:true,problems:[]},{file:\"b.json\",valid:false,problems:[{line:3,message:\"Unexpected char.\"}]}]}"
+        );
+    }
+}
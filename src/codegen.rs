@@ -0,0 +1,474 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Generating the Rust types from the documents, i.e. `aq codegen --lang rust <file(s)>`.
+//
+// The type of every value is inferred, then the types of the values at
+// the same place (e.g. the items of a list, or the same field of the documents)
+// are merged:
+//
+// - objects become structs, the fields which are absent or `null`
+//   in some objects become `Option<T>`.
+// - variants become enums, the members are collected from all values.
+// - integers of different types are widened to `i64` (or `u64` if all
+//   of them are unsigned), and the mixed integers and floats become `f64`.
+//
+// The values of incompatible types are reported in a comment,
+// and the type of the first value is used.
+
+use crate::value::{Map, Value, VariantValue};
+
+#[derive(Debug, PartialEq, Clone)]
+enum Inferred {
+    // the empty lists and `null`, the type is unknown.
+    Unknown,
+    Bool,
+    Char,
+    String,
+    Number(&'static str),
+    DateTime,
+    Bytes,
+    List(Box<Inferred>),
+    Tuple(Vec<Inferred>),
+    Object(Vec<InferredField>),
+    Enum(String, Vec<(String, Payload)>),
+    Option(Box<Inferred>),
+
+    // incompatible types, the first one is used.
+    Mixed(Vec<Inferred>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct InferredField {
+    key: String,
+    value_type: Inferred,
+    optional: bool,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Payload {
+    Unit,
+    Value(Inferred),
+    Tuple(Vec<Inferred>),
+    Fields(Vec<InferredField>),
+}
+
+fn infer(value: &Value) -> Inferred {
+    match value {
+        _ if value.is_null() => Inferred::Option(Box::new(Inferred::Unknown)),
+        Value::Boolean(_) => Inferred::Bool,
+        Value::Char(_) => Inferred::Char,
+        Value::String(_) => Inferred::String,
+        Value::Number(n) => Inferred::Number(n.type_suffix()),
+        Value::DateTime(_) => Inferred::DateTime,
+        Value::ByteData(_) => Inferred::Bytes,
+        Value::List(items) => Inferred::List(Box::new(
+            items.iter().map(infer).fold(Inferred::Unknown, merge),
+        )),
+        Value::Tuple(items) => Inferred::Tuple(items.iter().map(infer).collect()),
+        Value::Object(map) => Inferred::Object(infer_fields(map)),
+        Value::Variant(v) if v.type_name == "Option" && v.member_name == "Some" => match &v.value {
+            VariantValue::Value(inner) => Inferred::Option(Box::new(infer(inner))),
+            _ => Inferred::Unknown,
+        },
+        Value::Variant(v) => {
+            let payload = match &v.value {
+                VariantValue::Empty => Payload::Unit,
+                VariantValue::Value(inner) => Payload::Value(infer(inner)),
+                VariantValue::Tuple(items) => Payload::Tuple(items.iter().map(infer).collect()),
+                VariantValue::Object(map) => Payload::Fields(infer_fields(map)),
+            };
+            Inferred::Enum(v.type_name.clone(), vec![(v.member_name.clone(), payload)])
+        }
+    }
+}
+
+fn infer_fields(map: &Map) -> Vec<InferredField> {
+    map.iter()
+        .map(|(key, item)| InferredField {
+            key: key.clone(),
+            optional: false,
+            value_type: infer(item),
+        })
+        .collect()
+}
+
+fn merge(left: Inferred, right: Inferred) -> Inferred {
+    match (left, right) {
+        (Inferred::Unknown, other) | (other, Inferred::Unknown) => other,
+        (Inferred::Option(a), Inferred::Option(b)) => Inferred::Option(Box::new(merge(*a, *b))),
+        (Inferred::Option(a), other) | (other, Inferred::Option(a)) => {
+            Inferred::Option(Box::new(merge(*a, other)))
+        }
+        (Inferred::Number(a), Inferred::Number(b)) => Inferred::Number(merge_numbers(a, b)),
+        (Inferred::List(a), Inferred::List(b)) => Inferred::List(Box::new(merge(*a, *b))),
+        (Inferred::Tuple(a), Inferred::Tuple(b)) if a.len() == b.len() => {
+            Inferred::Tuple(a.into_iter().zip(b).map(|(x, y)| merge(x, y)).collect())
+        }
+        (Inferred::Object(a), Inferred::Object(b)) => Inferred::Object(merge_fields(a, b)),
+        (Inferred::Enum(name, a), Inferred::Enum(other_name, b)) if name == other_name => {
+            let mut members = a;
+            for (member, payload) in b {
+                match members.iter_mut().find(|(m, _)| *m == member) {
+                    Some((_, existing)) => {
+                        *existing = merge_payloads(existing.clone(), payload);
+                    }
+                    None => members.push((member, payload)),
+                }
+            }
+            Inferred::Enum(name, members)
+        }
+        (Inferred::Mixed(mut types), other) => {
+            if !types.contains(&other) {
+                types.push(other);
+            }
+            Inferred::Mixed(types)
+        }
+        (a, b) if a == b => a,
+        (a, b) => Inferred::Mixed(vec![a, b]),
+    }
+}
+
+fn merge_numbers(a: &'static str, b: &'static str) -> &'static str {
+    let is_float = |t: &str| t.starts_with('f');
+    let is_unsigned = |t: &str| t.starts_with('u');
+    if a == b {
+        a
+    } else if is_float(a) || is_float(b) {
+        "f64"
+    } else if is_unsigned(a) && is_unsigned(b) {
+        "u64"
+    } else {
+        "i64"
+    }
+}
+
+// the fields absent in either side become optional.
+fn merge_fields(left: Vec<InferredField>, right: Vec<InferredField>) -> Vec<InferredField> {
+    let mut fields = left;
+    for field in &mut fields {
+        if !right.iter().any(|f| f.key == field.key) {
+            field.optional = true;
+        }
+    }
+
+    for field in right {
+        match fields.iter_mut().find(|f| f.key == field.key) {
+            Some(existing) => {
+                existing.value_type = merge(existing.value_type.clone(), field.value_type);
+                existing.optional |= field.optional;
+            }
+            None => fields.push(InferredField {
+                optional: true,
+                ..field
+            }),
+        }
+    }
+    fields
+}
+
+fn merge_payloads(left: Payload, right: Payload) -> Payload {
+    match (left, right) {
+        (Payload::Value(a), Payload::Value(b)) => Payload::Value(merge(a, b)),
+        (Payload::Tuple(a), Payload::Tuple(b)) if a.len() == b.len() => {
+            Payload::Tuple(a.into_iter().zip(b).map(|(x, y)| merge(x, y)).collect())
+        }
+        (Payload::Fields(a), Payload::Fields(b)) => Payload::Fields(merge_fields(a, b)),
+        (left, _) => left,
+    }
+}
+
+/// Generate the Rust types of the documents,
+/// `root_name` is the name of the type of the documents.
+pub fn generate_rust(documents: &[Value], root_name: &str) -> String {
+    let root = documents.iter().map(infer).fold(Inferred::Unknown, merge);
+
+    let mut generator = RustGenerator {
+        items: vec![],
+        names: vec![],
+        uses_datetime: false,
+    };
+
+    let root_type = generator.type_of(&root, root_name);
+    if !matches!(root, Inferred::Object(_)) {
+        generator
+            .items
+            .insert(0, format!("pub type {} = {};\n", root_name, root_type));
+    }
+
+    let mut s = String::new();
+    if generator.uses_datetime {
+        s.push_str("use chrono::{DateTime, FixedOffset};\n");
+    }
+    s.push_str("use serde::{Deserialize, Serialize};\n");
+    for item in &generator.items {
+        s.push('\n');
+        s.push_str(item);
+    }
+    s
+}
+
+struct RustGenerator {
+    // the generated structs and enums
+    items: Vec<String>,
+
+    // the names of the generated types, and their bodies which
+    // are used for reusing the same type.
+    names: Vec<(String, String)>,
+    uses_datetime: bool,
+}
+
+impl RustGenerator {
+    // the Rust type of the inferred type, the structs and enums are
+    // generated and named after `name_hint`.
+    fn type_of(&mut self, inferred: &Inferred, name_hint: &str) -> String {
+        match inferred {
+            Inferred::Unknown => "()".to_owned(),
+            Inferred::Bool => "bool".to_owned(),
+            Inferred::Char => "char".to_owned(),
+            Inferred::String => "String".to_owned(),
+            Inferred::Number(suffix) => suffix.to_string(),
+            Inferred::DateTime => {
+                self.uses_datetime = true;
+                "DateTime<FixedOffset>".to_owned()
+            }
+            Inferred::Bytes => "Vec<u8>".to_owned(),
+            Inferred::List(item) => {
+                let singular = name_hint.strip_suffix('s').filter(|s| !s.ends_with('s'));
+                let item_name = match singular {
+                    Some(s) if !s.is_empty() => s.to_owned(),
+                    _ => format!("{}Item", name_hint),
+                };
+                format!("Vec<{}>", self.type_of(item, &item_name))
+            }
+            Inferred::Tuple(items) => {
+                let types: Vec<String> = items
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, t)| self.type_of(t, &format!("{}{}", name_hint, idx)))
+                    .collect();
+                if types.len() == 1 {
+                    format!("({},)", types[0])
+                } else {
+                    format!("({})", types.join(", "))
+                }
+            }
+            Inferred::Option(inner) => format!("Option<{}>", self.type_of(inner, name_hint)),
+            Inferred::Mixed(types) => self.type_of(&types[0], name_hint),
+            Inferred::Object(fields) => {
+                let body = format!(" {{\n{}}}\n", self.fields(fields, name_hint, "    pub "));
+                self.define(name_hint, "struct", body)
+            }
+            Inferred::Enum(name, members) => {
+                let mut body = String::from(" {\n");
+                for (member, payload) in members {
+                    let member_hint = format!("{}{}", name, member);
+                    body.push_str("    ");
+                    body.push_str(member);
+                    match payload {
+                        Payload::Unit => {}
+                        Payload::Value(t) => {
+                            body.push_str(&format!("({})", self.type_of(t, &member_hint)))
+                        }
+                        Payload::Tuple(types) => {
+                            let types: Vec<String> = types
+                                .iter()
+                                .enumerate()
+                                .map(|(idx, t)| self.type_of(t, &format!("{}{}", member_hint, idx)))
+                                .collect();
+                            body.push_str(&format!("({})", types.join(", ")));
+                        }
+                        Payload::Fields(fields) => {
+                            let fields = self.fields(fields, &member_hint, "        ");
+                            body.push_str(&format!(" {{\n{}    }}", fields));
+                        }
+                    }
+                    body.push_str(",\n");
+                }
+                body.push_str("}\n");
+                self.define(name, "enum", body)
+            }
+        }
+    }
+
+    fn fields(&mut self, fields: &[InferredField], struct_name: &str, prefix: &str) -> String {
+        let indent = &prefix[..prefix.len() - prefix.trim_start().len()];
+        let mut s = String::new();
+        for field in fields {
+            let mut field_type = self.type_of(&field.value_type, &to_pascal_case(&field.key));
+            if field.optional && !matches!(field.value_type, Inferred::Option(_)) {
+                field_type = format!("Option<{}>", field_type);
+            }
+
+            if let Inferred::Mixed(types) = &field.value_type {
+                let names: Vec<String> = types
+                    .iter()
+                    .map(|t| self.type_of(t, &format!("{}{}", struct_name, "Mixed")))
+                    .collect();
+                s.push_str(&format!(
+                    "{}// values of different types are found: {}\n",
+                    indent,
+                    names.join(", ")
+                ));
+            }
+
+            let name = to_field_name(&field.key);
+            if name.trim_start_matches("r#") != field.key {
+                s.push_str(&format!("{}#[serde(rename = \"{}\")]\n", indent, field.key));
+            }
+            s.push_str(&format!("{}{}: {},\n", prefix, name, field_type));
+        }
+        s
+    }
+
+    // add the definition, the name is numbered if it
+    // is already used by a different type.
+    fn define(&mut self, name: &str, keyword: &str, body: String) -> String {
+        let name = to_pascal_case(name);
+        let mut candidate = name.clone();
+        let mut number = 1;
+        loop {
+            match self.names.iter().find(|(n, _)| *n == candidate) {
+                Some((_, existing)) if *existing == body => return candidate,
+                Some(_) => {
+                    number += 1;
+                    candidate = format!("{}{}", name, number);
+                }
+                None => break,
+            }
+        }
+
+        self.names.push((candidate.clone(), body.clone()));
+        self.items.push(format!(
+            "#[derive(Debug, PartialEq, Serialize, Deserialize)]\npub {} {}{}",
+            keyword, candidate, body
+        ));
+        candidate
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "dyn", "else", "enum", "extern", "false",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "static", "struct", "trait", "true", "type", "unsafe", "use", "where", "while",
+    "abstract", "become", "box", "do", "final", "macro", "override", "priv", "typeof", "unsized",
+    "virtual", "yield", "try",
+];
+
+// convert the key to a snake case field name, e.g. `userName` to `user_name`.
+fn to_field_name(key: &str) -> String {
+    let mut name = String::new();
+    let mut previous_lower = false;
+    for c in key.chars() {
+        if c.is_ascii_alphanumeric() {
+            if c.is_ascii_uppercase() && previous_lower {
+                name.push('_');
+            }
+            name.push(c.to_ascii_lowercase());
+            previous_lower = c.is_ascii_lowercase() || c.is_ascii_digit();
+        } else if !name.ends_with('_') {
+            name.push('_');
+            previous_lower = false;
+        }
+    }
+
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+
+    if KEYWORDS.contains(&name.as_str()) {
+        // `self`, `Self`, `super` and `crate` cannot be raw identifiers
+        format!("r#{}", name)
+    } else if ["self", "super", "crate"].contains(&name.as_str()) {
+        format!("{}_", name)
+    } else {
+        name
+    }
+}
+
+// convert the name to a type name, e.g. `user_name` to `UserName`.
+fn to_pascal_case(name: &str) -> String {
+    let mut s = String::new();
+    let mut upper = true;
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() {
+            if upper {
+                s.push(c.to_ascii_uppercase());
+            } else {
+                s.push(c);
+            }
+            upper = false;
+        } else {
+            upper = true;
+        }
+    }
+
+    if s.is_empty() || s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.insert(0, 'T');
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::generate_rust;
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_generate_rust() {
+        let code = generate_rust(
+            &[
+                value(
+                    r#"{userName: "a", type: 1, servers: [{host: "x", port: 80_u16}],
+                        note: Option::None, color: Color::Red}"#,
+                ),
+                value(
+                    r#"{userName: "b", type: 2_i64, servers: [{host: "y"}],
+                        note: "n", extra: true}"#,
+                ),
+            ],
+            "Config",
+        );
+
+        // the fields are merged, the missing ones are optional
+        assert!(code.contains(
+            "pub struct Config {
+    #[serde(rename = \"userName\")]
+    pub user_name: String,
+    pub r#type: i64,
+    pub servers: Vec<Server>,
+    pub note: Option<String>,
+    pub color: Option<Color>,
+    pub extra: Option<bool>,
+}"
+        ));
+        assert!(code.contains(
+            "pub struct Server {
+    pub host: String,
+    pub port: Option<u16>,
+}"
+        ));
+        assert!(code.contains("pub enum Color {\n    Red,\n}"));
+    }
+
+    #[test]
+    fn test_generate_type_alias() {
+        assert_eq!(
+            generate_rust(&[value("[1, 2]")], "Root"),
+            "use serde::{Deserialize, Serialize};\n\npub type Root = Vec<i32>;\n"
+        );
+    }
+}
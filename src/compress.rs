@@ -0,0 +1,158 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The gzip and zstd compressed inputs and outputs.
+//
+// The compressed inputs are detected by the magic bytes, so that the
+// files (whatever the extensions are) and the STDIN are decompressed
+// transparently, and the output is compressed with `--compress-output`
+// or by the extension of the output file, e.g. `-o result.ason.gz`.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+use crate::error::Error;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => Err(Error::Message(format!(
+                "Compression \"{}\" is not supported, the available ones are: gzip and zstd.",
+                name
+            ))),
+        }
+    }
+
+    /// Detect the compression by the extension of the file name.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "gz" => Some(Compression::Gzip),
+            "zst" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+}
+
+/// Detect the compression by the magic bytes, the bytes are not consumed.
+pub fn detect(reader: &mut impl BufRead) -> io::Result<Option<Compression>> {
+    let head = reader.fill_buf()?;
+    Ok(if head.starts_with(GZIP_MAGIC) {
+        Some(Compression::Gzip)
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Some(Compression::Zstd)
+    } else {
+        None
+    })
+}
+
+/// The reader of the decompressed data if the data is compressed,
+/// otherwise the reader itself.
+pub fn decompress_reader<'a>(mut reader: impl BufRead + 'a) -> io::Result<Box<dyn BufRead + 'a>> {
+    Ok(match detect(&mut reader)? {
+        Some(Compression::Gzip) => {
+            Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+        }
+        #[cfg(feature = "zstd")]
+        Some(Compression::Zstd) => Box::new(BufReader::new(
+            zstd::stream::read::Decoder::with_buffer(reader)?,
+        )),
+        #[cfg(not(feature = "zstd"))]
+        Some(Compression::Zstd) => return Err(zstd_unsupported()),
+        None => Box::new(reader),
+    })
+}
+
+/// The decompressed data if the data is compressed, otherwise the data itself.
+pub fn decompress(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    if detect(&mut data.as_slice())?.is_none() {
+        return Ok(data);
+    }
+    let mut buf = vec![];
+    decompress_reader(data.as_slice())?.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+pub fn compress(data: &[u8], compression: Compression) -> io::Result<Vec<u8>> {
+    match compression {
+        Compression::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => zstd::stream::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL),
+        #[cfg(not(feature = "zstd"))]
+        Compression::Zstd => Err(zstd_unsupported()),
+    }
+}
+
+// the zstd library is written in C, which is excluded from some
+// builds, e.g. WebAssembly.
+#[cfg(not(feature = "zstd"))]
+fn zstd_unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "The zstd compression is not supported by this build.",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Read, path::Path};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{compress, decompress, decompress_reader, detect, Compression};
+
+    #[test]
+    fn test_compress_round_trip() {
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let data = compress(b"{a: 1}", compression).unwrap();
+            assert_eq!(detect(&mut data.as_slice()).unwrap(), Some(compression));
+            assert_eq!(decompress(data.clone()).unwrap(), b"{a: 1}");
+
+            let mut text = String::new();
+            decompress_reader(data.as_slice())
+                .unwrap()
+                .read_to_string(&mut text)
+                .unwrap();
+            assert_eq!(text, "{a: 1}");
+        }
+
+        // the plain data is kept
+        assert_eq!(detect(&mut &b"plain"[..]).unwrap(), None);
+        assert_eq!(decompress(b"plain".to_vec()).unwrap(), b"plain");
+    }
+
+    #[test]
+    fn test_compression_names() {
+        assert_eq!(Compression::from_name("zst").unwrap(), Compression::Zstd);
+        assert_eq!(Compression::from_name("GZIP").unwrap(), Compression::Gzip);
+        assert_eq!(
+            Compression::from_name("bz2").unwrap_err().to_string(),
+            "Compression \"bz2\" is not supported, the available ones are: gzip and zstd."
+        );
+
+        assert_eq!(
+            Compression::from_path(Path::new("x/data.ason.gz")),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::from_path(Path::new("data.ason")), None);
+    }
+}
@@ -0,0 +1,169 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The config file of aq, an ASON object at (the first one that exists):
+//
+// - the path of the environment variable `AQ_CONFIG`
+// - `$XDG_CONFIG_HOME/aq/config.ason`
+// - `$HOME/.config/aq/config.ason`
+//
+// the missing config file is the same as an empty object.
+
+use std::path::PathBuf;
+
+use crate::{
+    error::Error,
+    input::{parse_input, ParseOptions},
+    theme::{Theme, BUILTIN_THEMES},
+    value::Value,
+};
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Config {
+    /// The theme of the colored output, i.e. `theme: "name"`.
+    pub default_theme: Option<String>,
+
+    /// The themes defined by `themes: {name: {...}}`.
+    pub themes: Vec<(String, Theme)>,
+}
+
+impl Config {
+    pub fn from_value(value: &Value) -> Result<Self, Error> {
+        let Value::Object(map) = value else {
+            return Err(Error::Message(format!(
+                "The config expects object, found {}.",
+                value.type_name()
+            )));
+        };
+
+        let mut config = Config::default();
+        if let Some(theme) = map.get("theme") {
+            let Value::String(name) = theme else {
+                return Err(Error::Message(format!(
+                    "The \"theme\" of the config expects string, found {}.",
+                    theme.type_name()
+                )));
+            };
+            config.default_theme = Some(name.clone());
+        }
+
+        if let Some(themes) = map.get("themes") {
+            let Value::Object(themes) = themes else {
+                return Err(Error::Message(format!(
+                    "The \"themes\" of the config expects object, found {}.",
+                    themes.type_name()
+                )));
+            };
+            for (name, definition) in themes.iter() {
+                let theme = Theme::from_value(definition)
+                    .map_err(|e| Error::Message(format!("Invalid theme \"{}\": {}", name, e)))?;
+                config.themes.push((name.clone(), theme));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Find the theme by name, the themes defined in
+    /// the config take precedence over the builtin ones.
+    pub fn theme(&self, name: &str) -> Result<Theme, Error> {
+        if let Some((_, theme)) = self.themes.iter().find(|(n, _)| n == name) {
+            return Ok(theme.clone());
+        }
+
+        Theme::builtin(name).ok_or_else(|| {
+            let mut names: Vec<&str> = BUILTIN_THEMES.to_vec();
+            names.extend(self.themes.iter().map(|(n, _)| n.as_str()));
+            Error::Message(format!(
+                "Unknown theme \"{}\", the available themes are: {}.",
+                name,
+                names.join(", ")
+            ))
+        })
+    }
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("AQ_CONFIG") {
+        return Some(PathBuf::from(path));
+    }
+
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("aq").join("config.ason"))
+}
+
+/// Load the config file, the default config is returned
+/// if the file does not exist.
+pub fn load_config() -> Result<Config, Error> {
+    let Some(path) = config_path().filter(|p| p.is_file()) else {
+        return Ok(Config::default());
+    };
+
+    let text = std::fs::read_to_string(&path).map_err(|e| {
+        Error::Message(format!(
+            "Failed to read the config file \"{}\": {}",
+            path.display(),
+            e
+        ))
+    })?;
+    let value = parse_input(&text, None, &ParseOptions::unlimited())?;
+    Config::from_value(&value)
+        .map_err(|e| Error::Message(format!("Invalid config file \"{}\": {}", path.display(), e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        theme::{Style, Theme},
+        value::Value,
+    };
+
+    use super::Config;
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_config_themes() {
+        let config = Config::from_value(&value(
+            "{theme: \"mine\", themes: {mine: {string: \"green\"}}}",
+        ))
+        .unwrap();
+        assert_eq!(config.default_theme.as_deref(), Some("mine"));
+        assert_eq!(
+            config.theme("mine").unwrap().string,
+            Style::parse("green").unwrap()
+        );
+        assert_eq!(
+            config.theme("monokai"),
+            Ok(Theme::builtin("monokai").unwrap())
+        );
+        assert_eq!(
+            config.theme("x").unwrap_err().to_string(),
+            "Unknown theme \"x\", the available themes are: monokai, solarized, light, mine."
+        );
+
+        assert_eq!(Config::from_value(&value("{}")).unwrap(), Config::default());
+        assert!(
+            Config::from_value(&value("{themes: {bad: {keys: \"red\"}}}"))
+                .unwrap_err()
+                .to_string()
+                .starts_with("Invalid theme \"bad\": Unknown token type \"keys\"")
+        );
+        assert_eq!(
+            Config::from_value(&value("{theme: 1}"))
+                .unwrap_err()
+                .to_string(),
+            "The \"theme\" of the config expects string, found number."
+        );
+    }
+}
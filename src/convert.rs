@@ -0,0 +1,509 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Converting files between the formats, i.e. `aq convert`.
+//
+// When the source is a directory, all files with the extensions of the
+// source format are converted, and the relative structure of the
+// directory tree is kept in the destination directory, e.g.
+//
+// `aq convert --from json5 --to ason src/ -o dest/`
+//
+// converts `src/a/b.json5` to `dest/a/b.ason`.
+
+use std::path::{Path, PathBuf};
+
+use ason::ast::{printer::print_to_string, AsonNode};
+
+use crate::{
+    cbor::{decode_cbor, encode_cbor},
+    compress::{decompress, Compression},
+    error::Error,
+    input::{parse_input, ParseOptions},
+    json::{to_tagged_json, JsonDialect},
+    msgpack::{decode_msgpack, encode_msgpack},
+    tabular::{export_table, parse_table, TableFormat, TableOptions},
+    toml::{parse_toml, to_toml},
+    value::Value,
+    xml::{parse_xml, to_xml},
+    yaml::{parse_yaml_documents, to_yaml},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Ason,
+    Json,
+    Json5,
+    Jsonc,
+    Ndjson,
+    MsgPack,
+    Cbor,
+    Yaml,
+    Toml,
+    Xml,
+    Csv,
+    Tsv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name.to_ascii_lowercase().as_str() {
+            "ason" => Ok(Format::Ason),
+            "json" => Ok(Format::Json),
+            "json5" => Ok(Format::Json5),
+            "jsonc" => Ok(Format::Jsonc),
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "msgpack" => Ok(Format::MsgPack),
+            "cbor" => Ok(Format::Cbor),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "toml" => Ok(Format::Toml),
+            "xml" => Ok(Format::Xml),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            _ => Err(Error::Message(format!(
+                "Format \"{}\" is not supported, the available formats are: \
+                ason, json, json5, jsonc, ndjson, msgpack, cbor, yaml, toml, xml, csv and tsv.",
+                name
+            ))),
+        }
+    }
+
+    /// Detect the format by the extension of the file name, the extension
+    /// of the compression is skipped, e.g. `data.json.gz`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let path = match Compression::from_path(path) {
+            Some(_) => Path::new(path.file_stem()?),
+            None => path,
+        };
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ason" => Some(Format::Ason),
+            "json" => Some(Format::Json),
+            "json5" => Some(Format::Json5),
+            "jsonc" => Some(Format::Jsonc),
+            "ndjson" | "jsonl" => Some(Format::Ndjson),
+            "msgpack" => Some(Format::MsgPack),
+            "cbor" => Some(Format::Cbor),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "toml" => Some(Format::Toml),
+            "xml" => Some(Format::Xml),
+            "csv" => Some(Format::Csv),
+            "tsv" => Some(Format::Tsv),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Ason => "ason",
+            Format::Json => "json",
+            Format::Json5 => "json5",
+            Format::Jsonc => "jsonc",
+            Format::Ndjson => "ndjson",
+            Format::MsgPack => "msgpack",
+            Format::Cbor => "cbor",
+            Format::Yaml => "yaml",
+            Format::Toml => "toml",
+            Format::Xml => "xml",
+            Format::Csv => "csv",
+            Format::Tsv => "tsv",
+        }
+    }
+
+    /// The dialect of the JSON formats, `None` for others.
+    /// Each line of NDJSON is a JSON document.
+    pub fn json_dialect(&self) -> Option<JsonDialect> {
+        match self {
+            Format::Json | Format::Ndjson => Some(JsonDialect::Json),
+            Format::Json5 => Some(JsonDialect::Json5),
+            Format::Jsonc => Some(JsonDialect::Jsonc),
+            _ => None,
+        }
+    }
+
+    /// The extension of the converted files.
+    pub fn extension(&self) -> &'static str {
+        self.name()
+    }
+
+    /// The format of writing the results of the documents in this
+    /// format, i.e. the formats which can only be read are written
+    /// in the similar ones, e.g. JSON for JSON5.
+    pub fn result_format(&self) -> Format {
+        match self {
+            Format::Json5 | Format::Jsonc => Format::Json,
+            Format::Tsv => Format::Csv,
+            _ => *self,
+        }
+    }
+
+    // whether the file with the path is in this format, it is used
+    // for picking the files from the source directory.
+    fn matches(&self, path: &Path) -> bool {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match (self, extension.as_deref()) {
+            (Format::Json5 | Format::Jsonc, Some("json")) => true,
+            (Format::Yaml, Some("yml")) => true,
+            (Format::Ndjson, Some("jsonl")) => true,
+            (_, Some(e)) => e == self.extension(),
+            (_, None) => false,
+        }
+    }
+}
+
+pub fn read_document(bytes: &[u8], format: Format) -> Result<Value, Error> {
+    let mut documents = match format {
+        Format::MsgPack => decode_msgpack(bytes)?,
+        Format::Cbor => decode_cbor(bytes)?,
+        _ => {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|e| Error::Message(format!("The file is not valid UTF-8: {}.", e)))?;
+            parse_documents(text, format, &ParseOptions::unlimited())?
+        }
+    };
+    if matches!(format, Format::Ndjson | Format::Csv | Format::Tsv) {
+        // the lines (or the rows of the table) are converted together
+        return Ok(Value::List(documents.into()));
+    }
+    match documents.len() {
+        1 => Ok(documents.remove(0)),
+        n => Err(Error::Message(format!(
+            "The file contains {} documents, expect exactly one document.",
+            n
+        ))),
+    }
+}
+
+/// Parse the documents of the text, a YAML text can contain multiple
+/// documents (separated by `---`), each non-blank line of NDJSON and
+/// each row of CSV and TSV (with the header row) is a document,
+/// other formats contain one document.
+pub fn parse_documents(
+    text: &str,
+    format: Format,
+    options: &ParseOptions,
+) -> Result<Vec<Value>, Error> {
+    match format {
+        Format::Yaml => parse_yaml_documents(text),
+        Format::Toml => parse_toml(text).map(|v| vec![v]),
+        Format::Xml => parse_xml(text).map(|v| vec![v]),
+        Format::Ndjson => parse_lines(text, options),
+        Format::Csv => parse_table(text, ',', true),
+        Format::Tsv => parse_table(text, '\t', true),
+        Format::MsgPack | Format::Cbor => Err(Error::Message(format!(
+            "The format \"{}\" is not a text format.",
+            format.name()
+        ))),
+        _ => parse_input(text, format.json_dialect(), options).map(|v| vec![v]),
+    }
+}
+
+fn parse_lines(text: &str, options: &ParseOptions) -> Result<Vec<Value>, Error> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            parse_input(line, Some(JsonDialect::Json), options)
+                .map_err(|e| Error::Message(format!("Line {}: {}", index + 1, e)))
+        })
+        .collect()
+}
+
+pub fn write_document(value: &Value, format: Format) -> Result<Vec<u8>, Error> {
+    match format {
+        Format::Ason => Ok(print_to_string(&AsonNode::from(value.clone())).into_bytes()),
+        Format::Json => Ok(format!("{}\n", to_tagged_json(value, Some("    "))).into_bytes()),
+        Format::Ndjson => {
+            // a list is written as one line per element
+            let items = match value {
+                Value::List(items) => items.to_vec(),
+                _ => vec![value.clone()],
+            };
+            Ok(items
+                .iter()
+                .map(|v| format!("{}\n", to_tagged_json(v, None)))
+                .collect::<String>()
+                .into_bytes())
+        }
+        Format::MsgPack => Ok(encode_msgpack(value)),
+        Format::Cbor => Ok(encode_cbor(value)),
+        Format::Yaml => Ok(to_yaml(value).into_bytes()),
+        Format::Toml => to_toml(value).map(String::into_bytes),
+        Format::Xml => to_xml(value).map(String::into_bytes),
+        Format::Csv => {
+            export_table(value, TableFormat::Csv, &TableOptions::default()).map(String::into_bytes)
+        }
+        Format::Json5 | Format::Jsonc | Format::Tsv => Err(Error::Message(format!(
+            "Writing the format \"{}\" is not supported.",
+            format.name()
+        ))),
+    }
+}
+
+/// Write the value in a text format, without the trailing line break,
+/// e.g. for the results of the JavaScript and C bindings.
+pub fn write_text(value: &Value, format: Format) -> Result<String, Error> {
+    if matches!(format, Format::MsgPack | Format::Cbor) {
+        return Err(Error::Message(format!(
+            "The format \"{}\" is not a text format.",
+            format.name()
+        )));
+    }
+    let bytes = write_document(value, format)?;
+    Ok(String::from_utf8_lossy(&bytes).trim_end().to_owned())
+}
+
+/// A file to be converted.
+#[derive(Debug, PartialEq)]
+pub struct ConvertTask {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub from: Format,
+}
+
+/// List the files to be converted, the source is either a file or
+/// a directory. When the source format is not specified, it is detected
+/// by the extension of each file.
+pub fn plan_conversion(
+    source: &Path,
+    destination: &Path,
+    from: Option<Format>,
+    to: Format,
+) -> Result<Vec<ConvertTask>, Error> {
+    if !source.is_dir() {
+        let from = match from.or_else(|| Format::from_path(source)) {
+            Some(f) => f,
+            None => return Err(unknown_format_error(source)),
+        };
+
+        // the destination is taken as a directory if it exists,
+        // otherwise it is the path of the converted file.
+        let destination = if destination.is_dir() {
+            destination.join(rename(
+                Path::new(source.file_name().unwrap_or_default()),
+                to,
+            ))
+        } else {
+            destination.to_path_buf()
+        };
+
+        return Ok(vec![ConvertTask {
+            source: source.to_path_buf(),
+            destination,
+            from,
+        }]);
+    }
+
+    let mut files = vec![];
+    collect_files(source, &mut files)?;
+    files.sort();
+
+    let tasks = files
+        .into_iter()
+        .filter_map(|file| {
+            let from = match from {
+                Some(f) if f.matches(&file) => f,
+                Some(_) => return None,
+                None => Format::from_path(&file)?,
+            };
+
+            // the converted files are skipped when the destination
+            // directory is inside the source directory.
+            if file.starts_with(destination) {
+                return None;
+            }
+
+            let relative = file.strip_prefix(source).unwrap_or(&file);
+            Some(ConvertTask {
+                destination: destination.join(rename(relative, to)),
+                source: file,
+                from,
+            })
+        })
+        .collect();
+
+    Ok(tasks)
+}
+
+/// Convert a file, the parent directories of the destination are created.
+pub fn run_task(task: &ConvertTask, to: Format) -> Result<(), Error> {
+    let io_error = |path: &Path, e: std::io::Error| {
+        Error::Message(format!("Fail to access \"{}\": {}", path.display(), e))
+    };
+
+    let bytes = std::fs::read(&task.source)
+        .and_then(decompress)
+        .map_err(|e| io_error(&task.source, e))?;
+    let value = read_document(&bytes, task.from)?;
+    let output = write_document(&value, to)?;
+
+    if let Some(parent) = task.destination.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+    }
+    std::fs::write(&task.destination, output).map_err(|e| io_error(&task.destination, e))
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = std::fs::read_dir(dir).map_err(|e| {
+        Error::Message(format!(
+            "Fail to read the directory \"{}\": {}",
+            dir.display(),
+            e
+        ))
+    })?;
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// the extension of the compression of the source is removed too,
+// since the destination is not compressed.
+fn rename(path: &Path, to: Format) -> PathBuf {
+    match Compression::from_path(path) {
+        Some(_) => path.with_extension("").with_extension(to.extension()),
+        None => path.with_extension(to.extension()),
+    }
+}
+
+fn unknown_format_error(path: &Path) -> Error {
+    Error::Message(format!(
+        "Cannot detect the format of \"{}\", specify it by \"--from\".",
+        path.display()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::input::{parse_input, ParseOptions};
+
+    use super::{parse_documents, plan_conversion, read_document, write_document, Format};
+
+    #[test]
+    fn test_plan_conversion() {
+        let root = std::env::temp_dir().join(format!("aq-convert-{}", std::process::id()));
+        let source = root.join("src");
+        std::fs::create_dir_all(source.join("a/b")).unwrap();
+        for file in ["x.json", "a/y.json5", "a/b/z.jsonc", "a/readme.md"] {
+            std::fs::write(source.join(file), "{}").unwrap();
+        }
+        let destination = root.join("dest");
+
+        // the files of the unknown formats are skipped
+        let tasks = plan_conversion(&source, &destination, None, Format::MsgPack).unwrap();
+        let pairs: Vec<_> = tasks
+            .iter()
+            .map(|t| {
+                (
+                    t.source.strip_prefix(&source).unwrap().to_str().unwrap(),
+                    t.destination
+                        .strip_prefix(&destination)
+                        .unwrap()
+                        .to_str()
+                        .unwrap(),
+                    t.from,
+                )
+            })
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("a/b/z.jsonc", "a/b/z.msgpack", Format::Jsonc),
+                ("a/y.json5", "a/y.msgpack", Format::Json5),
+                ("x.json", "x.msgpack", Format::Json),
+            ]
+        );
+
+        // all files are read as the specified format
+        let tasks =
+            plan_conversion(&source, &destination, Some(Format::Jsonc), Format::Ason).unwrap();
+        assert_eq!(tasks.len(), 2);
+
+        let tasks = plan_conversion(
+            &source.join("x.json"),
+            Path::new("out.cbor"),
+            None,
+            Format::Cbor,
+        )
+        .unwrap();
+        assert_eq!(tasks[0].destination, Path::new("out.cbor"));
+        assert!(plan_conversion(
+            &source.join("a/readme.md"),
+            &destination,
+            None,
+            Format::Cbor
+        )
+        .is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_format_from_name() {
+        assert_eq!(Format::from_name("json5").unwrap(), Format::Json5);
+        assert!(Format::from_name("ini")
+            .unwrap_err()
+            .to_string()
+            .starts_with("Format \"ini\" is not supported, the available formats are: ason, json"));
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let options = ParseOptions::unlimited();
+        let documents: Vec<String> = parse_documents(
+            "{\"a\": 1}\n\n[1, {\"$u8\": 2}]\n\"s\"\n",
+            Format::Ndjson,
+            &options,
+        )
+        .unwrap()
+        .iter()
+        .map(|v| v.to_string())
+        .collect();
+        assert_eq!(documents, ["{a:1}", "[1,2_u8]", "\"s\""]);
+        assert!(parse_documents("1\n{\n", Format::Ndjson, &options)
+            .unwrap_err()
+            .to_string()
+            .starts_with("Line 2: "));
+
+        // a single document is the list of the lines
+        assert_eq!(
+            read_document(b"1\n2\n", Format::Ndjson)
+                .unwrap()
+                .to_string(),
+            "[1,2]"
+        );
+        let value = parse_input("[{a: 1}, 2_u8]", None, &options).unwrap();
+        assert_eq!(
+            write_document(&value, Format::Ndjson).unwrap(),
+            b"{\"a\":1}\n{\"$u8\":2}\n"
+        );
+
+        assert_eq!(Format::from_name("jsonl").unwrap(), Format::Ndjson);
+    }
+
+    #[test]
+    fn test_compressed_format_from_path() {
+        assert_eq!(
+            Format::from_path(Path::new("x/data.json.gz")),
+            Some(Format::Json)
+        );
+        assert_eq!(Format::from_path(Path::new("data.gz")), None);
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// A zipper over a value.
+//
+// The cursor focuses on a value inside the document, the containers
+// along the path are kept aside with the slot of the focused child
+// emptied. So moving and replacing are cheap, only the containers on
+// the path are rebuilt when the cursor moves up, and the rest of the
+// document is not copied.
+//
+// e.g. replacing the port of the second server:
+//
+// ```
+// let mut cursor = Cursor::new(document);
+// cursor.down(&Value::from("servers"))?;
+// cursor.down(&Value::from(1_usize))?;
+// cursor.down(&Value::from("port"))?;
+// cursor.replace(Value::from(Number::U16(8080)));
+// let document = cursor.into_root();
+// ```
+
+use crate::{error::Error, evaluator::to_index, value::Value};
+
+pub struct Cursor {
+    focus: Value,
+
+    // the parents from the outermost, the slot of
+    // the focused child is taken out.
+    crumbs: Vec<Crumb>,
+}
+
+struct Crumb {
+    parent: Value,
+    key: Value,
+}
+
+impl Cursor {
+    pub fn new(root: Value) -> Self {
+        Self {
+            focus: root,
+            crumbs: vec![],
+        }
+    }
+
+    pub fn focus(&self) -> &Value {
+        &self.focus
+    }
+
+    /// The path from the root to the focused value.
+    pub fn path(&self) -> Vec<Value> {
+        self.crumbs.iter().map(|c| c.key.clone()).collect()
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.crumbs.is_empty()
+    }
+
+    /// Move to the child, the key is a string for objects, or an
+    /// index for lists and tuples (negative indices count from the end).
+    /// The cursor is not moved if the child does not exist.
+    pub fn down(&mut self, key: &Value) -> Result<(), Error> {
+        let mut parent = std::mem::replace(&mut self.focus, Value::null());
+        let (child, key) = match take_child(&mut parent, key) {
+            Ok(taken) => taken,
+            Err(e) => {
+                self.focus = parent;
+                return Err(e);
+            }
+        };
+
+        self.focus = child;
+        self.crumbs.push(Crumb { parent, key });
+        Ok(())
+    }
+
+    /// Move to the parent, returns `false` if the cursor is at the root.
+    pub fn up(&mut self) -> bool {
+        let Some(Crumb { mut parent, key }) = self.crumbs.pop() else {
+            return false;
+        };
+
+        let child = std::mem::replace(&mut self.focus, Value::null());
+        put_child(&mut parent, &key, child);
+        self.focus = parent;
+        true
+    }
+
+    /// Move to the root.
+    pub fn top(&mut self) {
+        while self.up() {}
+    }
+
+    /// Move to the next item of the parent list or tuple, returns `false`
+    /// if there is no next item.
+    pub fn right(&mut self) -> bool {
+        self.sibling(1)
+    }
+
+    /// Move to the previous item of the parent list or tuple, returns `false`
+    /// if there is no previous item.
+    pub fn left(&mut self) -> bool {
+        self.sibling(-1)
+    }
+
+    fn sibling(&mut self, offset: i128) -> bool {
+        let index = match self.crumbs.last() {
+            Some(Crumb {
+                parent: Value::List(items) | Value::Tuple(items),
+                key: Value::Number(n),
+            }) => match n.as_i128().map(|i| i + offset) {
+                Some(i) if i >= 0 && (i as usize) < items.len() => i as usize,
+                _ => return false,
+            },
+            _ => return false,
+        };
+
+        self.up();
+        self.down(&Value::from(index)).is_ok()
+    }
+
+    /// Replace the focused value, returns the old one.
+    pub fn replace(&mut self, value: Value) -> Value {
+        std::mem::replace(&mut self.focus, value)
+    }
+
+    /// Update the focused value with the function.
+    pub fn update(&mut self, f: impl FnOnce(Value) -> Value) {
+        let value = std::mem::replace(&mut self.focus, Value::null());
+        self.focus = f(value);
+    }
+
+    /// Zip up the cursor and return the whole document.
+    pub fn into_root(mut self) -> Value {
+        self.top();
+        self.focus
+    }
+}
+
+// take the child out of the container, the key is normalized,
+// i.e. the negative indices are converted.
+fn take_child(parent: &mut Value, key: &Value) -> Result<(Value, Value), Error> {
+    match (parent, key) {
+        (Value::Object(map), Value::String(k)) => match map.get_mut(k) {
+            Some(slot) => Ok((std::mem::replace(slot, Value::null()), key.clone())),
+            None => Err(Error::Message(format!("Key \"{}\" does not exist.", k))),
+        },
+        (Value::List(items) | Value::Tuple(items), Value::Number(n)) => {
+            match to_index(n, items.len()) {
+                Some(i) => Ok((
+                    std::mem::replace(&mut items[i], Value::null()),
+                    Value::from(i),
+                )),
+                None => Err(Error::Message(format!("Index {} is out of bounds.", key))),
+            }
+        }
+        (parent, _) => Err(Error::Message(format!(
+            "Cannot move into {} with {}.",
+            parent.type_name(),
+            key.type_name()
+        ))),
+    }
+}
+
+fn put_child(parent: &mut Value, key: &Value, child: Value) {
+    match (parent, key) {
+        (Value::Object(map), Value::String(k)) => {
+            if let Some(slot) = map.get_mut(k) {
+                *slot = child;
+            }
+        }
+        (Value::List(items) | Value::Tuple(items), Value::Number(n)) => {
+            if let Some(i) = to_index(n, items.len()) {
+                items[i] = child;
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        path::setpath,
+        value::Value,
+    };
+
+    use super::Cursor;
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_move_and_replace() {
+        let mut cursor = Cursor::new(value("{servers: [{port: 1}, {port: 2}], x: 0}"));
+        assert!(cursor.is_root());
+
+        // the negative index counts from the end
+        cursor.down(&Value::from("servers")).unwrap();
+        cursor.down(&value("-1")).unwrap();
+        assert_eq!(
+            cursor.path(),
+            vec![Value::from("servers"), Value::from(1_usize)]
+        );
+
+        // moving between the siblings
+        assert!(cursor.left());
+        assert!(!cursor.left());
+        assert!(cursor.right());
+
+        cursor.down(&Value::from("port")).unwrap();
+        assert!(cursor.down(&Value::from("nope")).is_err());
+        assert_eq!(cursor.replace(value("8")), value("2"));
+
+        cursor.up();
+        cursor.update(|v| setpath(v, &[Value::from("host")], Value::from("h")).unwrap());
+        assert_eq!(cursor.focus().to_string(), r#"{port:8,host:"h"}"#);
+        assert!(cursor.down(&Value::from("zzz")).is_err());
+
+        assert_eq!(
+            cursor.into_root().to_string(),
+            r#"{servers:[{port:1},{port:8,host:"h"}],x:0}"#
+        );
+    }
+}
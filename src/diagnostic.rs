@@ -0,0 +1,241 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The diagnostics of the syntax errors of the query expression, the line
+// of the error is shown with a caret under the position, and a hint if
+// the cause is likely known, e.g.
+//
+// ```
+// Unexpected "]", expect ")".
+//  --> line 1, column 15
+//   |
+// 1 | .users[] | (.a]
+//   |               ^
+//   = hint: the "]" does not match the "(" at line 1, column 12.
+// ```
+
+use std::fmt::{self, Display};
+
+use crate::{
+    error::{Error, Location},
+    lexer::{lex_from_str, Token},
+};
+
+/// An error with the query expression it comes from, see `Error::with_source()`.
+pub struct QueryDiagnostic<'a> {
+    error: &'a Error,
+    source: &'a str,
+}
+
+impl Error {
+    /// The error with the position in the query expression `source`,
+    /// the errors other than the syntax errors are displayed as is.
+    pub fn with_source<'a>(&'a self, source: &'a str) -> QueryDiagnostic<'a> {
+        QueryDiagnostic {
+            error: self,
+            source,
+        }
+    }
+}
+
+impl Display for QueryDiagnostic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (message, location) = match self.error {
+            Error::MessageWithLocation(message, location) => (message.clone(), *location),
+            Error::UnexpectedEndOfQuery(_) => {
+                // the caret follows the last character of the query.
+                let end = self.source.trim_end().chars().count();
+                (self.error.to_string(), Location::new(end, 1))
+            }
+            _ => return write!(f, "{}", self.error),
+        };
+
+        let (line_number, column, line) = locate(self.source, location.index);
+        let gutter = " ".repeat(line_number.to_string().len());
+        let width = location
+            .length
+            .clamp(1, (line.chars().count() + 1).saturating_sub(column - 1));
+
+        writeln!(f, "{}", message)?;
+        writeln!(f, "{}--> line {}, column {}", gutter, line_number, column)?;
+        writeln!(f, "{} |", gutter)?;
+        writeln!(f, "{} | {}", line_number, line)?;
+        write!(
+            f,
+            "{} | {}{}",
+            gutter,
+            " ".repeat(column - 1),
+            "^".repeat(width)
+        )?;
+        if let Some(hint) = hint(self.error, self.source, location) {
+            write!(f, "\n{} = hint: {}", gutter, hint)?;
+        }
+        Ok(())
+    }
+}
+
+// the line number and the column (both are 1-based) of
+// the character index, and the text of the line.
+fn locate(source: &str, index: usize) -> (usize, usize, &str) {
+    let mut line_start = 0;
+    let mut line_number = 1;
+    for (count, (offset, c)) in source.char_indices().enumerate() {
+        if count == index {
+            break;
+        }
+        if c == '\n' {
+            line_start = offset + 1;
+            line_number += 1;
+        }
+    }
+
+    let line = source[line_start..].lines().next().unwrap_or("");
+    let column = index - source[..line_start].chars().count() + 1;
+    (line_number, column, line.trim_end_matches('\r'))
+}
+
+fn hint(error: &Error, source: &str, location: Location) -> Option<String> {
+    if let Error::UnexpectedEndOfQuery(message) = error {
+        if message.starts_with("Incomplete string") {
+            return Some("the string is closed by a \".".to_owned());
+        }
+    }
+
+    // the brackets are matched by the tokens, the tokens
+    // are not available if the lexer fails.
+    let tokens = lex_from_str(source).ok()?;
+    let mut opened: Vec<(&Token, Location)> = vec![];
+    for token in &tokens {
+        // the brackets after the error are not checked.
+        if token.location.index > location.index {
+            break;
+        }
+        match &token.token {
+            Token::LeftParen | Token::LeftBracket | Token::LeftBrace => {
+                opened.push((&token.token, token.location))
+            }
+            Token::RightParen | Token::RightBracket | Token::RightBrace => {
+                let closing = bracket(&token.token);
+                match opened.pop() {
+                    Some((open, _)) if pair(open) == closing => {}
+                    Some((open, open_location)) => {
+                        let (line, column, _) = locate(source, open_location.index);
+                        return Some(format!(
+                            "the \"{}\" does not match the \"{}\" at line {}, column {}.",
+                            closing,
+                            bracket(open),
+                            line,
+                            column
+                        ));
+                    }
+                    None if token.location.index == location.index => {
+                        return Some(format!("the \"{}\" has no opening bracket.", closing));
+                    }
+                    None => return None,
+                }
+            }
+            _ => {}
+        }
+    }
+
+    match (error, opened.last()) {
+        (Error::UnexpectedEndOfQuery(_), Some((open, open_location))) => {
+            let (line, column, _) = locate(source, open_location.index);
+            Some(format!(
+                "the \"{}\" at line {}, column {} is not closed by \"{}\".",
+                bracket(open),
+                line,
+                column,
+                pair(open)
+            ))
+        }
+        _ if error.to_string().to_lowercase().contains("expect \"end\"") => {
+            Some("each \"if\" is closed by \"end\", e.g. \"if . then 1 else 2 end\".".to_owned())
+        }
+        _ => None,
+    }
+}
+
+fn bracket(token: &Token) -> &'static str {
+    match token {
+        Token::LeftParen => "(",
+        Token::RightParen => ")",
+        Token::LeftBracket => "[",
+        Token::RightBracket => "]",
+        Token::LeftBrace => "{",
+        _ => "}",
+    }
+}
+
+fn pair(token: &Token) -> &'static str {
+    match token {
+        Token::LeftParen => ")",
+        Token::LeftBracket => "]",
+        _ => "}",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{error::Error, parser::parse_query_from_str};
+
+    fn diagnose(source: &str) -> String {
+        match parse_query_from_str(source) {
+            Ok(_) => panic!("the query should not parse: {}", source),
+            Err(e) => e.with_source(source).to_string(),
+        }
+    }
+
+    #[test]
+    fn test_caret_and_hint() {
+        assert_eq!(
+            diagnose(".users[] | (.a]"),
+            "Unexpected \"]\", expect \")\".
+ --> line 1, column 15
+  |
+1 | .users[] | (.a]
+  |               ^
+  = hint: the \"]\" does not match the \"(\" at line 1, column 12."
+        );
+        assert_eq!(
+            diagnose(".a\n| .b ]"),
+            "Unexpected \"]\".
+ --> line 2, column 6
+  |
+2 | | .b ]
+  |      ^
+  = hint: the \"]\" has no opening bracket."
+        );
+    }
+
+    #[test]
+    fn test_unexpected_end() {
+        assert_eq!(
+            diagnose("[1, 2"),
+            "Unexpected end of query expression: Expect \"]\".
+ --> line 1, column 6
+  |
+1 | [1, 2
+  |      ^
+  = hint: the \"[\" at line 1, column 1 is not closed by \"]\"."
+        );
+        assert!(diagnose("\"abc").ends_with("= hint: the string is closed by a \"."));
+        assert!(diagnose("if . then 1 else 2").ends_with(
+            "= hint: each \"if\" is closed by \"end\", e.g. \"if . then 1 else 2 end\"."
+        ));
+    }
+
+    #[test]
+    fn test_other_errors() {
+        let error = Error::Message("Fail to load the module.".to_owned());
+        assert_eq!(
+            error.with_source(".a").to_string(),
+            "Fail to load the module."
+        );
+    }
+}
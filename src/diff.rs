@@ -0,0 +1,365 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The structural differences of two documents, i.e. `aq diff a.ason b.ason`,
+// e.g.
+//
+// ```
+// ~ .server.port: 8080 => 8081
+// - .server.debug: true
+// + .server.workers: 4
+// ```
+//
+// The objects are compared by the keys and the lists by the indices, the
+// added items of a list are at its end, and the removed ones are listed
+// from the last, so the changes can be applied one by one. The other
+// values (including the tuples of different lengths and the variants)
+// are compared as a whole, and the numbers of different types, e.g.
+// `1_u8` and `1`, are different.
+//
+// With `--format patch`, the changes are written as a list of operations:
+//
+// ```
+// [
+//     {op: "replace", path: ["server", "port"], old: 8080, value: 8081}
+//     {op: "remove", path: ["server", "debug"], old: true}
+//     {op: "add", path: ["server", "workers"], value: 4}
+// ]
+// ```
+//
+// and the patch is applied by `aq patch a.ason changes.ason`, the `old`
+// values are optional, if present, they must match the document, so
+// a patch is not applied to a document which has drifted.
+
+use std::fmt::Display;
+
+use crate::{
+    error::Error,
+    path::{delpaths, getpath, path_from_value, path_to_string, setpath},
+    value::{Items, Map, Value},
+};
+
+/// A difference between the old and the new document.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Change {
+    Added {
+        path: Vec<Value>,
+        value: Value,
+    },
+    Removed {
+        path: Vec<Value>,
+        old: Value,
+    },
+    Replaced {
+        path: Vec<Value>,
+        old: Value,
+        value: Value,
+    },
+}
+
+impl Change {
+    pub fn path(&self) -> &[Value] {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Replaced { path, .. } => path,
+        }
+    }
+
+    /// The operation of the patch, i.e. `add`, `remove` or `replace`.
+    pub fn op(&self) -> &'static str {
+        match self {
+            Change::Added { .. } => "add",
+            Change::Removed { .. } => "remove",
+            Change::Replaced { .. } => "replace",
+        }
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {}: {}", path_to_string(path), value),
+            Change::Removed { path, old } => write!(f, "- {}: {}", path_to_string(path), old),
+            Change::Replaced { path, old, value } => {
+                write!(f, "~ {}: {} => {}", path_to_string(path), old, value)
+            }
+        }
+    }
+}
+
+/// The changes which turn the `old` document into the `new` one.
+pub fn diff_values(old: &Value, new: &Value) -> Vec<Change> {
+    let mut changes = vec![];
+    compare(&mut vec![], old, new, &mut changes);
+    changes
+}
+
+fn compare(path: &mut Vec<Value>, old: &Value, new: &Value, changes: &mut Vec<Change>) {
+    match (old, new) {
+        (Value::Object(xs), Value::Object(ys)) => {
+            for (key, x) in xs.iter() {
+                path.push(Value::String(key.clone()));
+                match ys.get(key) {
+                    Some(y) => compare(path, x, y, changes),
+                    None => changes.push(Change::Removed {
+                        path: path.clone(),
+                        old: x.clone(),
+                    }),
+                }
+                path.pop();
+            }
+            for (key, y) in ys.iter() {
+                if !xs.contains_key(key) {
+                    path.push(Value::String(key.clone()));
+                    changes.push(Change::Added {
+                        path: path.clone(),
+                        value: y.clone(),
+                    });
+                    path.pop();
+                }
+            }
+        }
+        (Value::List(xs), Value::List(ys)) => {
+            for (idx, (x, y)) in xs.iter().zip(ys.iter()).enumerate() {
+                path.push(Value::from(idx));
+                compare(path, x, y, changes);
+                path.pop();
+            }
+            for idx in (ys.len()..xs.len()).rev() {
+                path.push(Value::from(idx));
+                changes.push(Change::Removed {
+                    path: path.clone(),
+                    old: xs[idx].clone(),
+                });
+                path.pop();
+            }
+            for (idx, y) in ys.iter().enumerate().skip(xs.len()) {
+                path.push(Value::from(idx));
+                changes.push(Change::Added {
+                    path: path.clone(),
+                    value: y.clone(),
+                });
+                path.pop();
+            }
+        }
+        (Value::Tuple(xs), Value::Tuple(ys)) if xs.len() == ys.len() => {
+            for (idx, (x, y)) in xs.iter().zip(ys.iter()).enumerate() {
+                path.push(Value::from(idx));
+                compare(path, x, y, changes);
+                path.pop();
+            }
+        }
+        // the `NaN` is not equal to itself
+        (Value::Number(x), Value::Number(y))
+            if x.is_float() && x.as_f64().is_nan() && y.as_f64().is_nan() => {}
+        _ => {
+            if old != new || old.to_string() != new.to_string() {
+                changes.push(Change::Replaced {
+                    path: path.clone(),
+                    old: old.clone(),
+                    value: new.clone(),
+                });
+            }
+        }
+    }
+}
+
+/// The changes as the patch document (see the module).
+pub fn patch_document(changes: &[Change]) -> Value {
+    let operations = changes.iter().map(|change| {
+        let mut map = Map::new();
+        map.insert("op".to_owned(), Value::String(change.op().to_owned()));
+        map.insert(
+            "path".to_owned(),
+            Value::List(Items::from(change.path().to_vec())),
+        );
+        match change {
+            Change::Added { value, .. } => {
+                map.insert("value".to_owned(), value.clone());
+            }
+            Change::Removed { old, .. } => {
+                map.insert("old".to_owned(), old.clone());
+            }
+            Change::Replaced { old, value, .. } => {
+                map.insert("old".to_owned(), old.clone());
+                map.insert("value".to_owned(), value.clone());
+            }
+        }
+        Value::Object(map)
+    });
+    Value::List(Items::from(operations.collect::<Vec<_>>()))
+}
+
+/// Apply the patch document (see the module) to the document.
+pub fn apply_patch(document: Value, patch: &Value) -> Result<Value, Error> {
+    let Value::List(operations) = patch else {
+        return Err(Error::Message(format!(
+            "The patch must be a list of operations, found {}.",
+            patch.type_name()
+        )));
+    };
+
+    let mut document = document;
+    for (idx, operation) in operations.iter().enumerate() {
+        document = apply_operation(document, operation).map_err(|e| {
+            Error::Message(format!(
+                "Fail to apply the operation {} of the patch: {}",
+                idx, e
+            ))
+        })?;
+    }
+    Ok(document)
+}
+
+fn apply_operation(document: Value, operation: &Value) -> Result<Value, Error> {
+    let Value::Object(map) = operation else {
+        return Err(Error::Message(format!(
+            "expect an object, found {}.",
+            operation.type_name()
+        )));
+    };
+    let path = match map.get("path") {
+        Some(path) => path_from_value(path.clone())?,
+        None => return Err(Error::Message("the \"path\" is missing.".to_owned())),
+    };
+    let value = || {
+        map.get("value")
+            .cloned()
+            .ok_or_else(|| Error::Message("the \"value\" is missing.".to_owned()))
+    };
+
+    if let Some(old) = map.get("old") {
+        let current = getpath(&document, &path)?;
+        if current != *old {
+            return Err(Error::Message(format!(
+                "the value at {} is {}, but the patch expects {}.",
+                path_to_string(&path),
+                current,
+                old
+            )));
+        }
+    }
+
+    match map.get("op") {
+        Some(Value::String(op)) if op == "add" || op == "replace" => {
+            setpath(document, &path, value()?)
+        }
+        Some(Value::String(op)) if op == "remove" => delpaths(document, vec![path]),
+        Some(op) => Err(Error::Message(format!(
+            "the operation {} is unknown, expect \"add\", \"remove\" or \"replace\".",
+            op
+        ))),
+        None => Err(Error::Message("the \"op\" is missing.".to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{apply_patch, diff_values, patch_document};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_diff_values() {
+        let old = value("{server: {port: 8080, debug: true}, list: [1, 2, 3], n: 1_u8, t: (1, 2)}");
+        let new = value("{server: {port: 8081, workers: 4}, list: [1], n: 1, t: (1, 2, 3)}");
+        let changes: Vec<String> = diff_values(&old, &new)
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                "~ .server.port: 8080 => 8081",
+                "- .server.debug: true",
+                "+ .server.workers: 4",
+                "- .list[2]: 3",
+                "- .list[1]: 2",
+                "~ .n: 1_u8 => 1",
+                "~ .t: (1,2) => (1,2,3)"
+            ]
+        );
+
+        assert_eq!(diff_values(&old, &old), []);
+        let changes: Vec<String> = diff_values(&value("[1]"), &value("[1, 2]"))
+            .iter()
+            .map(|c| c.to_string())
+            .collect();
+        assert_eq!(changes, ["+ .[1]: 2"]);
+    }
+
+    #[test]
+    fn test_patch_document() {
+        let changes = diff_values(
+            &value("{a: 1, b: true, c: [1]}"),
+            &value("{a: 2, c: [1, \"x\"]}"),
+        );
+        assert_eq!(
+            patch_document(&changes),
+            value(
+                r#"[
+                    {op: "replace", path: ["a"], old: 1, value: 2}
+                    {op: "remove", path: ["b"], old: true}
+                    {op: "add", path: ["c", 1], value: "x"}
+                ]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_apply_patch() {
+        let old = value("{server: {port: 8080, debug: true}, list: [1, 2, 3]}");
+        let new = value("{server: {port: 8081, workers: 4}, list: [1]}");
+        let patch = patch_document(&diff_values(&old, &new));
+        assert_eq!(apply_patch(old.clone(), &patch).unwrap(), new);
+
+        // the `old` values are optional
+        let patch = value(
+            r#"[{op: "add", path: ["list", 3], value: 4}, {op: "remove", path: ["server"]}]"#,
+        );
+        assert_eq!(
+            apply_patch(old.clone(), &patch).unwrap(),
+            value("{list: [1, 2, 3, 4]}")
+        );
+    }
+
+    #[test]
+    fn test_apply_patch_errors() {
+        let error = |patch: &str| {
+            apply_patch(value("{a: 1}"), &value(patch))
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error(r#"[{op: "replace", path: ["a"], old: 2, value: 3}]"#),
+            "Fail to apply the operation 0 of the patch: \
+            the value at .a is 1, but the patch expects 2."
+        );
+        assert_eq!(
+            error(r#"[{op: "add", path: ["b"], value: 2}, {op: "move", path: ["a"]}]"#),
+            "Fail to apply the operation 1 of the patch: \
+            the operation \"move\" is unknown, expect \"add\", \"remove\" or \"replace\"."
+        );
+        assert_eq!(
+            error(r#"[{op: "add", path: ["b"]}]"#),
+            "Fail to apply the operation 0 of the patch: the \"value\" is missing."
+        );
+        assert_eq!(
+            error(r#"{op: "move"}"#),
+            "The patch must be a list of operations, found object."
+        );
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Deduplication of the query results, i.e. `aq --distinct`.
+//
+// Each result is reduced to a key text which is equal for the equal
+// values (the object keys are sorted and the numbers are normalized,
+// e.g. `1_u8` and `1.0` have the same key), the keys seen are kept in
+// memory until the limit is reached, then they are moved to bucket files
+// in a temporary directory, and only the hashes of the keys are kept in
+// memory, so the bucket file is read only when the hash is seen before.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashSet},
+    fs::{File, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    error::Error,
+    value::{Map, Number, Value, VariantValue},
+};
+
+/// The number of keys kept in memory before spilling to the disk.
+pub const DEFAULT_MEMORY_LIMIT: usize = 1_000_000;
+
+const BUCKET_COUNT: u64 = 256;
+
+static NEXT_SPILL_ID: AtomicUsize = AtomicUsize::new(0);
+
+pub struct DistinctSet {
+    keys: HashSet<String>,
+    memory_limit: usize,
+    spill: Option<Spill>,
+}
+
+// the keys on the disk, one key per line in the bucket file.
+struct Spill {
+    dir: PathBuf,
+    hashes: HashSet<u64>,
+}
+
+impl DistinctSet {
+    pub fn new() -> Self {
+        Self::with_memory_limit(DEFAULT_MEMORY_LIMIT)
+    }
+
+    pub fn with_memory_limit(memory_limit: usize) -> Self {
+        Self {
+            keys: HashSet::new(),
+            memory_limit,
+            spill: None,
+        }
+    }
+
+    /// Add the value to the set, returns `false` if an
+    /// equal value has been added before.
+    pub fn insert(&mut self, value: &Value) -> Result<bool, Error> {
+        let key = distinct_key(value);
+
+        match &mut self.spill {
+            Some(spill) => spill.insert(key),
+            None => {
+                if !self.keys.insert(key) {
+                    return Ok(false);
+                }
+                if self.keys.len() > self.memory_limit {
+                    self.spill_keys()?;
+                }
+                Ok(true)
+            }
+        }
+    }
+
+    fn spill_keys(&mut self) -> Result<(), Error> {
+        let id = NEXT_SPILL_ID.fetch_add(1, Ordering::Relaxed);
+        let dir = std::env::temp_dir().join(format!("aq-distinct-{}-{}", std::process::id(), id));
+        std::fs::create_dir_all(&dir).map_err(spill_error)?;
+
+        let mut spill = Spill {
+            dir,
+            hashes: HashSet::new(),
+        };
+        for key in std::mem::take(&mut self.keys) {
+            spill.append(&key, hash_key(&key))?;
+        }
+        self.spill = Some(spill);
+        Ok(())
+    }
+}
+
+impl Default for DistinctSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Spill {
+    fn insert(&mut self, key: String) -> Result<bool, Error> {
+        let hash = hash_key(&key);
+
+        // the hash which is not seen means a new key, otherwise
+        // the bucket is scanned to rule out the hash collision.
+        if self.hashes.contains(&hash) {
+            let file = File::open(self.bucket_path(hash)).map_err(spill_error)?;
+            for line in BufReader::new(file).lines() {
+                if line.map_err(spill_error)? == key {
+                    return Ok(false);
+                }
+            }
+        }
+
+        self.append(&key, hash)?;
+        Ok(true)
+    }
+
+    fn append(&mut self, key: &str, hash: u64) -> Result<(), Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.bucket_path(hash))
+            .map_err(spill_error)?;
+        writeln!(file, "{}", key).map_err(spill_error)?;
+        self.hashes.insert(hash);
+        Ok(())
+    }
+
+    fn bucket_path(&self, hash: u64) -> PathBuf {
+        self.dir.join(format!("{:02x}", hash % BUCKET_COUNT))
+    }
+}
+
+impl Drop for Spill {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn spill_error(e: std::io::Error) -> Error {
+    Error::Message(format!("Failed to access the distinct set on disk: {}", e))
+}
+
+fn hash_key(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The text which identifies the value for deduplication, the equal
+/// values have the same key, and the key does not contain line breaks.
+pub fn distinct_key(value: &Value) -> String {
+    let mut key = String::new();
+    write_key(value, &mut key);
+    key
+}
+
+fn write_key(value: &Value, key: &mut String) {
+    match value {
+        Value::Number(n) => write_number_key(n, key),
+        Value::Boolean(b) => key.push_str(if *b { "true" } else { "false" }),
+        Value::Char(c) => key.push_str(&format!("{:?}", c)),
+        Value::String(s) => key.push_str(&format!("{:?}", s)),
+        Value::DateTime(d) => key.push_str(&format!(
+            "d{}.{:09}",
+            d.timestamp(),
+            d.timestamp_subsec_nanos()
+        )),
+        Value::ByteData(bytes) => {
+            key.push_str("h\"");
+            for b in bytes {
+                key.push_str(&format!("{:02x}", b));
+            }
+            key.push('"');
+        }
+        Value::List(items) => write_items_key(items, '[', ']', key),
+        Value::Tuple(items) => write_items_key(items, '(', ')', key),
+        Value::Object(map) => write_map_key(map, key),
+        Value::Variant(variant) => {
+            key.push_str(&format!("{}::{}", variant.type_name, variant.member_name));
+            match &variant.value {
+                VariantValue::Empty => {}
+                VariantValue::Value(v) => {
+                    key.push('(');
+                    write_key(v, key);
+                    key.push(')');
+                }
+                VariantValue::Tuple(items) => write_items_key(items, '(', ')', key),
+                VariantValue::Object(map) => write_map_key(map, key),
+            }
+        }
+    }
+}
+
+// the integers and the integral floats are written as the decimal
+// integers, since they are compared by the value, except `-0.0`
+// which is ordered before `0`.
+fn write_number_key(n: &Number, key: &mut String) {
+    let f = n.as_f64();
+    match n.as_i128() {
+        Some(i) => key.push_str(&i.to_string()),
+        None if f.fract() == 0.0 && f.abs() < 1e38 && !(f == 0.0 && f.is_sign_negative()) => {
+            key.push_str(&(f as i128).to_string())
+        }
+        None => key.push_str(&format!("{:?}", f)),
+    }
+}
+
+fn write_items_key(items: &[Value], open: char, close: char, key: &mut String) {
+    key.push(open);
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            key.push(',');
+        }
+        write_key(item, key);
+    }
+    key.push(close);
+}
+
+fn write_map_key(map: &Map, key: &mut String) {
+    let mut entries: Vec<&(String, Value)> = map.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    key.push('{');
+    for (idx, (name, value)) in entries.into_iter().enumerate() {
+        if idx > 0 {
+            key.push(',');
+        }
+        key.push_str(&format!("{:?}:", name));
+        write_key(value, key);
+    }
+    key.push('}');
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{distinct_key, DistinctSet};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_distinct_key() {
+        // the same as `==`, i.e. the key order and the number types
+        // are not significant
+        assert_eq!(
+            distinct_key(&value(r#"{b: 1_u8, a: [1.0, "x\n"]}"#)),
+            distinct_key(&value(r#"{a: [1, "x\n"], b: 1}"#))
+        );
+        assert_ne!(distinct_key(&value("1")), distinct_key(&value(r#""1""#)));
+    }
+
+    #[test]
+    fn test_distinct_set() {
+        // the keys are spilled to the disk when the memory limit is reached
+        for limit in [100, 2] {
+            let mut set = DistinctSet::with_memory_limit(limit);
+            let kept: Vec<String> = [
+                "1",
+                "{a: 1, b: 2}",
+                "2",
+                "1.0",
+                "{b: 2, a: 1}",
+                "3",
+                "4",
+                r#""1""#,
+                "2",
+            ]
+            .iter()
+            .map(|text| value(text))
+            .filter(|v| set.insert(v).unwrap())
+            .map(|v| v.to_string())
+            .collect();
+            assert_eq!(kept, ["1", "{a:1,b:2}", "2", "3", "4", r#""1""#]);
+        }
+    }
+}
@@ -0,0 +1,398 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The diagnostics of the environment and the input files, i.e. `aq doctor <file(s)>`.
+//
+// The common issues which lead to the mysterious failures are reported
+// with hints, e.g. the encoding, BOM, mixed line endings, enormous lines,
+// duplicate keys and deep nesting.
+
+use std::collections::HashSet;
+
+use crate::input::{parse_input, ParseOptions};
+
+/// A line longer than this is reported, since the error
+/// locations and diffs of such line are hard to read.
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// A nesting deeper than this is reported, the untrusted input
+/// limits reject the documents deeper than 128 levels.
+const MAX_NESTING_DEPTH: usize = 64;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    /// The file cannot be processed.
+    Error,
+
+    /// The file can be processed, but the result may be unexpected.
+    Warning,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+
+    /// The line number starts from 1, `None` for the whole file.
+    pub line: Option<usize>,
+
+    pub message: String,
+    pub hint: String,
+}
+
+impl Finding {
+    fn new(severity: Severity, line: Option<usize>, message: String, hint: &str) -> Self {
+        Self {
+            severity,
+            line,
+            message,
+            hint: hint.to_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(f, "{}: {}\n  hint: {}", severity, self.message, self.hint)
+    }
+}
+
+/// Check the environment of the process, e.g. the locale.
+pub fn diagnose_environment() -> Vec<Finding> {
+    let mut findings = vec![];
+
+    // the first non-empty one of these variables takes effect.
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|value| !value.is_empty());
+
+    if let Some(locale) = locale {
+        let upper = locale.to_uppercase();
+        if !upper.contains("UTF-8") && !upper.contains("UTF8") && upper != "C" && upper != "POSIX" {
+            findings.push(Finding::new(
+                Severity::Warning,
+                None,
+                format!("the locale \"{}\" is not UTF-8", locale),
+                "the non-ASCII characters in the output may be garbled, \
+                set \"LANG\" to a UTF-8 locale, e.g. \"en_US.UTF-8\"",
+            ));
+        }
+    }
+
+    findings
+}
+
+/// Check the content of an input file.
+pub fn diagnose_file(bytes: &[u8]) -> Vec<Finding> {
+    let mut findings = vec![];
+
+    if bytes.starts_with(&[0xff, 0xfe]) || bytes.starts_with(&[0xfe, 0xff]) {
+        findings.push(Finding::new(
+            Severity::Error,
+            None,
+            "the file is encoded in UTF-16".to_owned(),
+            "convert the file to UTF-8, e.g. `iconv -f UTF-16 -t UTF-8`",
+        ));
+        return findings;
+    }
+
+    let text = match std::str::from_utf8(bytes) {
+        Ok(t) => t,
+        Err(e) => {
+            let valid = &bytes[..e.valid_up_to()];
+            let line = valid.iter().filter(|b| **b == b'\n').count() + 1;
+            findings.push(Finding::new(
+                Severity::Error,
+                Some(line),
+                format!(
+                    "the file is not valid UTF-8 (at byte offset {})",
+                    e.valid_up_to()
+                ),
+                "convert the file to UTF-8, e.g. `iconv -f <encoding> -t UTF-8`",
+            ));
+            return findings;
+        }
+    };
+
+    let text = match text.strip_prefix('\u{feff}') {
+        Some(t) => {
+            findings.push(Finding::new(
+                Severity::Warning,
+                Some(1),
+                "the file starts with a UTF-8 BOM".to_owned(),
+                "remove the BOM, it is not part of the ASON syntax",
+            ));
+            t
+        }
+        None => text,
+    };
+
+    check_line_endings(text, &mut findings);
+    check_line_lengths(text, &mut findings);
+    check_structure(text, &mut findings);
+
+    if let Err(e) = parse_input(text, None, &ParseOptions::unlimited()) {
+        findings.push(Finding::new(
+            Severity::Error,
+            None,
+            format!("the file cannot be parsed as ASON: {}", e),
+            "fix the syntax error, or specify the dialect if the file is JSON, \
+            e.g. \"--from-json5\"",
+        ));
+    }
+
+    findings
+}
+
+fn check_line_endings(text: &str, findings: &mut Vec<Finding>) {
+    let crlf = text.matches("\r\n").count();
+    let lf = text.matches('\n').count() - crlf;
+    let cr = text.matches('\r').count() - crlf;
+
+    let kinds = [crlf, lf, cr].iter().filter(|n| **n > 0).count();
+    if kinds > 1 {
+        findings.push(Finding::new(
+            Severity::Warning,
+            None,
+            format!(
+                "the line endings are mixed ({} CRLF, {} LF, {} CR)",
+                crlf, lf, cr
+            ),
+            "normalize the line endings, e.g. with `dos2unix`, otherwise \
+            the multi-line strings may contain unexpected \"\\r\"",
+        ));
+    } else if cr > 0 {
+        findings.push(Finding::new(
+            Severity::Warning,
+            None,
+            "the line endings are CR only".to_owned(),
+            "the line numbers in the error messages count LF only, \
+            convert the line endings to LF",
+        ));
+    }
+}
+
+fn check_line_lengths(text: &str, findings: &mut Vec<Finding>) {
+    let long_lines: Vec<(usize, usize)> = text
+        .split('\n')
+        .enumerate()
+        .filter(|(_, line)| line.len() > MAX_LINE_LENGTH)
+        .map(|(idx, line)| (idx + 1, line.len()))
+        .collect();
+
+    if let Some((line, length)) = long_lines.first() {
+        let others = match long_lines.len() {
+            1 => String::new(),
+            n => format!(" (and {} other lines)", n - 1),
+        };
+        findings.push(Finding::new(
+            Severity::Warning,
+            Some(*line),
+            format!("the line has {} bytes{}", length, others),
+            "the error locations of enormous lines are hard to read, \
+            reformat the file, e.g. `aq . file.ason`",
+        ));
+    }
+}
+
+// the containers being scanned, the keys of objects
+// are recorded for detecting the duplicate keys.
+enum Frame {
+    Object(HashSet<String>),
+    Other,
+}
+
+// the duplicate keys are lost after parsing (the last one wins),
+// so they are detected by scanning the text.
+fn check_structure(text: &str, findings: &mut Vec<Finding>) {
+    let chars: Vec<char> = text.chars().collect();
+    let mut frames: Vec<Frame> = vec![];
+    let mut max_depth = (0, 0); // (depth, line)
+    let mut line = 1;
+    let mut idx = 0;
+
+    // the key candidate (the name and the line of it) waiting for the colon.
+    let mut pending_key: Option<(String, usize)> = None;
+
+    while idx < chars.len() {
+        let c = chars[idx];
+        let next = chars.get(idx + 1).copied();
+        match c {
+            '\n' => line += 1,
+            ' ' | '\t' | '\r' => {}
+            '/' if next == Some('/') => {
+                while idx < chars.len() && chars[idx] != '\n' {
+                    idx += 1;
+                }
+                continue;
+            }
+            '/' if next == Some('*') => {
+                idx += 2;
+                while idx < chars.len() && !(chars[idx] == '*' && chars.get(idx + 1) == Some(&'/'))
+                {
+                    if chars[idx] == '\n' {
+                        line += 1;
+                    }
+                    idx += 1;
+                }
+                idx += 2;
+                continue;
+            }
+            '"' | '\'' => {
+                let start_line = line;
+                let raw = c == '"' && idx > 0 && chars[idx - 1] == 'r';
+                let mut content = String::new();
+                idx += 1;
+                while idx < chars.len() && chars[idx] != c {
+                    match chars[idx] {
+                        '\\' if !raw => {
+                            idx += 1;
+                            if let Some(escaped) = chars.get(idx) {
+                                content.push(*escaped);
+                            }
+                        }
+                        '\n' => {
+                            line += 1;
+                            content.push('\n');
+                        }
+                        other => content.push(other),
+                    }
+                    idx += 1;
+                }
+                pending_key = Some((content, start_line));
+            }
+            ':' if next == Some(':') => {
+                // the variant `Type::Member`
+                pending_key = None;
+                idx += 2;
+                continue;
+            }
+            ':' => {
+                if let (Some((key, key_line)), Some(Frame::Object(keys))) =
+                    (pending_key.take(), frames.last_mut())
+                {
+                    if !keys.insert(key.clone()) {
+                        findings.push(Finding::new(
+                            Severity::Warning,
+                            Some(key_line),
+                            format!("the key \"{}\" is duplicated", key),
+                            "only the last value of the duplicate keys is kept, \
+                            remove or rename the other ones",
+                        ));
+                    }
+                }
+            }
+            '[' | '{' | '(' => {
+                frames.push(if c == '{' {
+                    Frame::Object(HashSet::new())
+                } else {
+                    Frame::Other
+                });
+                if frames.len() > max_depth.0 {
+                    max_depth = (frames.len(), line);
+                }
+                pending_key = None;
+            }
+            ']' | '}' | ')' => {
+                frames.pop();
+                pending_key = None;
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let mut name = String::new();
+                while idx < chars.len() && (chars[idx].is_alphanumeric() || chars[idx] == '_') {
+                    name.push(chars[idx]);
+                    idx += 1;
+                }
+                pending_key = Some((name, line));
+                continue;
+            }
+            _ => pending_key = None,
+        }
+        idx += 1;
+    }
+
+    if max_depth.0 > MAX_NESTING_DEPTH {
+        findings.push(Finding::new(
+            Severity::Warning,
+            Some(max_depth.1),
+            format!("the nesting depth reaches {}", max_depth.0),
+            "the deeply nested documents are rejected by the untrusted input \
+            limits and slow to query, consider flattening the structure",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{diagnose_file, Severity};
+
+    // the first lines of the findings, except the syntax errors
+    fn messages(bytes: &[u8]) -> Vec<String> {
+        diagnose_file(bytes)
+            .iter()
+            .filter(|f| !f.message.contains("cannot be parsed"))
+            .map(|f| f.to_string().lines().next().unwrap().to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_diagnose_encoding() {
+        assert_eq!(
+            messages(b"\xff\xfe{\x00"),
+            ["error: the file is encoded in UTF-16"]
+        );
+        assert_eq!(
+            messages(b"{a: 1}\n\xc3("),
+            ["line 2: error: the file is not valid UTF-8 (at byte offset 7)"]
+        );
+        assert_eq!(
+            messages("\u{feff}{a: 1}".as_bytes()),
+            ["line 1: warning: the file starts with a UTF-8 BOM"]
+        );
+        assert_eq!(
+            messages(b"{\r\n a: 1\n}"),
+            ["warning: the line endings are mixed (1 CRLF, 1 LF, 0 CR)"]
+        );
+    }
+
+    #[test]
+    fn test_diagnose_content() {
+        assert_eq!(
+            messages(b"{\n a: 1\n b: {a: \"x: y\", a: Option::Some(1)}\n \"a\": 3\n}"),
+            [
+                "line 3: warning: the key \"a\" is duplicated",
+                "line 4: warning: the key \"a\" is duplicated",
+            ]
+        );
+
+        let deep = format!("{}{}", "[".repeat(70), "]".repeat(70));
+        assert_eq!(
+            messages(deep.as_bytes()),
+            ["line 1: warning: the nesting depth reaches 70"]
+        );
+
+        let long = format!("\"{}\"", "x".repeat(70000));
+        assert_eq!(
+            messages(long.as_bytes()),
+            ["line 1: warning: the line has 70002 bytes"]
+        );
+
+        assert!(diagnose_file(b"{a: }")
+            .iter()
+            .any(|f| f.severity == Severity::Error));
+        assert!(diagnose_file(b"{a: 1}").is_empty());
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Embedding the external files into a document, i.e. `aq embed FILE`,
+// for building the self-contained config bundles.
+//
+// The placeholders are the objects with the only key `file` (and the
+// optional key `as`), they are replaced by the content of the files:
+//
+// - `{file: "cert.pem"}` is replaced by the text of the file,
+// - `{file: "logo.png", as: "bytes"}` is replaced by the byte data.
+//
+// The relative paths are resolved against the directory of the document.
+//
+// The inverse is `aq extract FILE`, the large strings and byte data are
+// written to the individual files and replaced by the placeholders, the
+// names of the files are the paths of the values, e.g. `assets.0.data.bin`.
+
+use std::{path::Path, sync::Arc};
+
+use crate::{
+    error::Error,
+    value::{Map, Value, VariantValue},
+};
+
+/// A file of the extracted value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ExtractedFile {
+    pub name: String,
+    pub content: Vec<u8>,
+}
+
+/// Replace the strings and byte data which have at least `min_size` bytes
+/// by the placeholders, the file names of the placeholders are prefixed
+/// with `dir`, e.g. `blobs/logo.bin`.
+pub fn extract_files(value: Value, min_size: usize, dir: &str) -> (Value, Vec<ExtractedFile>) {
+    let mut files = vec![];
+    let value = extract(value, min_size, dir, &mut vec![], &mut files);
+    (value, files)
+}
+
+fn extract(
+    value: Value,
+    min_size: usize,
+    dir: &str,
+    path: &mut Vec<String>,
+    files: &mut Vec<ExtractedFile>,
+) -> Value {
+    let (content, extension, binary) = match value {
+        Value::String(s) if s.len() >= min_size => (s.into_bytes(), "txt", false),
+        Value::ByteData(b) if b.len() >= min_size => (b, "bin", true),
+        Value::Object(map) => {
+            let mut extracted = Map::new();
+            for (key, item) in map {
+                path.push(key.clone());
+                let item = extract(item, min_size, dir, path, files);
+                path.pop();
+                extracted.insert(key, item);
+            }
+            return Value::Object(extracted);
+        }
+        Value::List(items) => {
+            return Value::List(extract_items(items.into_vec(), min_size, dir, path, files).into())
+        }
+        Value::Tuple(items) => {
+            return Value::Tuple(extract_items(items.into_vec(), min_size, dir, path, files).into())
+        }
+        _ => return value,
+    };
+
+    let name = unique_name(&file_stem(path), extension, files);
+    let file = if dir.is_empty() {
+        name.clone()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), name)
+    };
+    files.push(ExtractedFile { name, content });
+
+    let mut placeholder = Map::new();
+    placeholder.insert("file".to_owned(), Value::String(file));
+    if binary {
+        placeholder.insert("as".to_owned(), Value::String("bytes".to_owned()));
+    }
+    Value::Object(placeholder)
+}
+
+fn extract_items(
+    items: Vec<Value>,
+    min_size: usize,
+    dir: &str,
+    path: &mut Vec<String>,
+    files: &mut Vec<ExtractedFile>,
+) -> Vec<Value> {
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(idx, item)| {
+            path.push(idx.to_string());
+            let item = extract(item, min_size, dir, path, files);
+            path.pop();
+            item
+        })
+        .collect()
+}
+
+// the path segments joined by dots, the characters which are
+// not safe in the file names are replaced by `_`.
+fn file_stem(path: &[String]) -> String {
+    if path.is_empty() {
+        return "root".to_owned();
+    }
+    path.iter()
+        .map(|segment| {
+            segment
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '-' || c == '_' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+// the different paths may have the same stem after the replacement,
+// e.g. `a b` and `a/b`, the later ones are suffixed by `-2`, `-3`, etc.
+fn unique_name(stem: &str, extension: &str, files: &[ExtractedFile]) -> String {
+    let mut name = format!("{}.{}", stem, extension);
+    let mut idx = 2;
+    while files.iter().any(|f| f.name == name) {
+        name = format!("{}-{}.{}", stem, idx, extension);
+        idx += 1;
+    }
+    name
+}
+
+/// Replace the placeholders of the value, and
+/// return the number of the embedded files.
+pub fn embed_files(value: Value, base_dir: &Path) -> Result<(Value, usize), Error> {
+    let mut count = 0;
+    let value = embed(value, base_dir, &mut vec![], &mut count)?;
+    Ok((value, count))
+}
+
+fn embed(
+    value: Value,
+    base_dir: &Path,
+    path: &mut Vec<String>,
+    count: &mut usize,
+) -> Result<Value, Error> {
+    match value {
+        Value::Object(map) if is_placeholder(&map) => {
+            let embedded = load_file(&map, base_dir, path)?;
+            *count += 1;
+            Ok(embedded)
+        }
+        Value::Object(map) => {
+            let mut embedded = Map::new();
+            for (key, item) in map {
+                path.push(key.clone());
+                let item = embed(item, base_dir, path, count)?;
+                path.pop();
+                embedded.insert(key, item);
+            }
+            Ok(Value::Object(embedded))
+        }
+        Value::List(items) => Ok(Value::List(
+            embed_items(items.into_vec(), base_dir, path, count)?.into(),
+        )),
+        Value::Tuple(items) => Ok(Value::Tuple(
+            embed_items(items.into_vec(), base_dir, path, count)?.into(),
+        )),
+        Value::Variant(mut variant) => {
+            if let VariantValue::Value(inner) = &mut Arc::make_mut(&mut variant).value {
+                let value = std::mem::replace(inner.as_mut(), Value::null());
+                **inner = embed(value, base_dir, path, count)?;
+            }
+            Ok(Value::Variant(variant))
+        }
+        _ => Ok(value),
+    }
+}
+
+fn embed_items(
+    items: Vec<Value>,
+    base_dir: &Path,
+    path: &mut Vec<String>,
+    count: &mut usize,
+) -> Result<Vec<Value>, Error> {
+    items
+        .into_iter()
+        .enumerate()
+        .map(|(idx, item)| {
+            path.push(idx.to_string());
+            let item = embed(item, base_dir, path, count);
+            path.pop();
+            item
+        })
+        .collect()
+}
+
+fn is_placeholder(map: &Map) -> bool {
+    matches!(map.get("file"), Some(Value::String(_)))
+        && map.keys().all(|k| k == "file" || k == "as")
+}
+
+fn load_file(map: &Map, base_dir: &Path, path: &[String]) -> Result<Value, Error> {
+    let Some(Value::String(file)) = map.get("file") else {
+        unreachable!("the placeholder has the file name")
+    };
+    let location = || format!("/{}", path.join("/"));
+
+    let kind = match map.get("as") {
+        None => "string",
+        Some(Value::String(s)) if s == "string" || s == "bytes" => s.as_str(),
+        Some(other) => {
+            return Err(Error::Message(format!(
+            "The \"as\" of the placeholder at \"{}\" expects \"string\" or \"bytes\", found {}.",
+            location(),
+            other
+        )))
+        }
+    };
+
+    let bytes = std::fs::read(base_dir.join(file)).map_err(|e| {
+        Error::Message(format!(
+            "Fail to read the file \"{}\" of the placeholder at \"{}\": {}",
+            file,
+            location(),
+            e
+        ))
+    })?;
+
+    if kind == "bytes" {
+        return Ok(Value::ByteData(bytes));
+    }
+    String::from_utf8(bytes).map(Value::String).map_err(|_| {
+        Error::Message(format!(
+            "The file \"{}\" of the placeholder at \"{}\" is not valid UTF-8 text, \
+            use `as: \"bytes\"` to embed it as byte data.",
+            file,
+            location()
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::{Map, Value},
+    };
+
+    use super::{embed_files, extract_files, ExtractedFile};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_embed_files() {
+        let dir = std::env::temp_dir().join(format!("aq-embed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.txt"), "hello").unwrap();
+        std::fs::write(dir.join("b.bin"), [0xff, 1]).unwrap();
+
+        let document = value(
+            "{x: {file: \"a.txt\"}, y: [1, {file: \"b.bin\", as: \"bytes\"}], \
+            z: {file: \"a.txt\", mode: 1}, v: Option::Some({file: \"a.txt\"})}",
+        );
+        let (embedded, count) = embed_files(document, &dir).unwrap();
+        assert_eq!(count, 3);
+
+        // the objects with other keys are not the placeholders
+        let Value::Object(map) = &embedded else {
+            panic!("the document is an object")
+        };
+        assert_eq!(map.get("x"), Some(&Value::String("hello".to_owned())));
+        assert_eq!(
+            map.get("y"),
+            Some(&Value::List(
+                vec![Value::from(1), Value::ByteData(vec![0xff, 1])].into()
+            ))
+        );
+        assert_eq!(map.get("z").unwrap().to_string(), "{file:\"a.txt\",mode:1}");
+        assert_eq!(map.get("v").unwrap().to_string(), "Option::Some(\"hello\")");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_placeholders() {
+        let dir = std::env::temp_dir().join(format!("aq-embed-invalid-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("b.bin"), [0xff, 1]).unwrap();
+        let error_of = |text: &str| embed_files(value(text), &dir).unwrap_err().to_string();
+
+        assert_eq!(
+            error_of("[{file: \"b.bin\"}]"),
+            "The file \"b.bin\" of the placeholder at \"/0\" is not valid UTF-8 text, \
+            use `as: \"bytes\"` to embed it as byte data."
+        );
+        assert_eq!(
+            error_of("{a: {file: \"b.bin\", as: \"text\"}}"),
+            "The \"as\" of the placeholder at \"/a\" expects \"string\" or \"bytes\", found \"text\"."
+        );
+        assert!(error_of("{a: {file: \"missing.txt\"}}")
+            .starts_with("Fail to read the file \"missing.txt\" of the placeholder at \"/a\": "));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_extract_files() {
+        let text = |s: &str| Value::String(s.to_owned());
+        let mut map = Map::new();
+        map.insert("a b".to_owned(), text("0123456789"));
+        map.insert("a/b".to_owned(), text("abcdefghij"));
+        map.insert("small".to_owned(), text("x"));
+        map.insert(
+            "list".to_owned(),
+            Value::List(vec![Value::from(1), Value::ByteData(vec![7; 8])].into()),
+        );
+        let document = Value::Object(map);
+
+        let (extracted, files) = extract_files(document.clone(), 8, "blobs");
+        assert_eq!(
+            files,
+            [
+                ExtractedFile {
+                    name: "a_b.txt".to_owned(),
+                    content: b"0123456789".to_vec()
+                },
+                ExtractedFile {
+                    name: "a_b-2.txt".to_owned(),
+                    content: b"abcdefghij".to_vec()
+                },
+                ExtractedFile {
+                    name: "list.1.bin".to_owned(),
+                    content: vec![7; 8]
+                }
+            ]
+        );
+        assert_eq!(
+            extracted.to_string(),
+            "{\"a b\":{file:\"blobs/a_b.txt\"},\"a/b\":{file:\"blobs/a_b-2.txt\"},small:\"x\",list:[1,{file:\"blobs/list.1.bin\",as:\"bytes\"}]}"
+        );
+
+        let (extracted, files) = extract_files(Value::ByteData(vec![0; 10]), 8, "");
+        assert_eq!(extracted.to_string(), "{file:\"root.bin\",as:\"bytes\"}");
+        assert_eq!(files[0].name, "root.bin");
+
+        // the extracted files are embedded back
+        let dir = std::env::temp_dir().join(format!("aq-extract-{}", std::process::id()));
+        let (extracted, files) = extract_files(document.clone(), 8, "blobs");
+        std::fs::create_dir_all(dir.join("blobs")).unwrap();
+        for file in &files {
+            std::fs::write(dir.join("blobs").join(&file.name), &file.content).unwrap();
+        }
+        assert_eq!(embed_files(extracted, &dir).unwrap(), (document, 3));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
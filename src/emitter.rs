@@ -0,0 +1,382 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Writing an ASON document incrementally.
+//
+// The emitter writes the text as soon as each part is added, so a huge
+// document can be generated without building the whole value tree, e.g.
+//
+// ```
+// let mut emitter = AsonEmitter::new(std::io::stdout(), PrintOptions::pretty("    "));
+// emitter.start_object()?;
+// emitter.key("orders")?;
+// emitter.start_list()?;
+// for order in orders {
+//     emitter.value(&order)?;
+// }
+// emitter.end_list()?;
+// emitter.end_object()?;
+// emitter.finish()?;
+// ```
+//
+// the layout is the same as `print_value` with the same options.
+
+use std::io::Write;
+
+use crate::{
+    error::Error,
+    printer::{print_value_at, PrintOptions},
+    theme::Theme,
+    value::{escape_string, is_identifier, Value},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Container {
+    List,
+    Tuple,
+    Object,
+}
+
+impl Container {
+    fn close(&self) -> &'static str {
+        match self {
+            Container::List => "]",
+            Container::Tuple => ")",
+            Container::Object => "}",
+        }
+    }
+}
+
+struct Frame {
+    container: Container,
+    empty: bool,
+
+    // the key of the object has been written, the value is expected.
+    has_key: bool,
+}
+
+pub struct AsonEmitter<W: Write> {
+    writer: W,
+    options: PrintOptions,
+    theme: Theme,
+    frames: Vec<Frame>,
+    finished_root: bool,
+}
+
+impl<W: Write> AsonEmitter<W> {
+    pub fn new(writer: W, options: PrintOptions) -> Self {
+        let theme = options.theme.clone().unwrap_or_default();
+        Self {
+            writer,
+            options,
+            theme,
+            frames: vec![],
+            finished_root: false,
+        }
+    }
+
+    pub fn start_list(&mut self) -> Result<(), Error> {
+        self.start(Container::List, "[")
+    }
+
+    pub fn end_list(&mut self) -> Result<(), Error> {
+        self.end(Container::List)
+    }
+
+    pub fn start_tuple(&mut self) -> Result<(), Error> {
+        self.start(Container::Tuple, "(")
+    }
+
+    pub fn end_tuple(&mut self) -> Result<(), Error> {
+        self.end(Container::Tuple)
+    }
+
+    pub fn start_object(&mut self) -> Result<(), Error> {
+        self.start(Container::Object, "{")
+    }
+
+    pub fn end_object(&mut self) -> Result<(), Error> {
+        self.end(Container::Object)
+    }
+
+    /// Write the key of the next entry of the object.
+    pub fn key(&mut self, key: &str) -> Result<(), Error> {
+        let level = self.frames.len();
+        let separator = match self.frames.last_mut() {
+            Some(frame) if frame.container == Container::Object && !frame.has_key => {
+                let first = frame.empty;
+                frame.empty = false;
+                frame.has_key = true;
+                self.separator(level, first)
+            }
+            Some(frame) if frame.container == Container::Object => {
+                return Err(Error::Message(format!(
+                    "The value of the key is expected before the key \"{}\".",
+                    key
+                )))
+            }
+            _ => {
+                return Err(Error::Message(format!(
+                    "The key \"{}\" is written outside of an object.",
+                    key
+                )))
+            }
+        };
+
+        let text = if is_identifier(key) {
+            key.to_owned()
+        } else {
+            escape_string(key, '"')
+        };
+        let colon = self.theme.punctuation.paint(":");
+        let space = if self.options.indent.is_some() {
+            " "
+        } else {
+            ""
+        };
+        let key_text = self.theme.key.paint(&text);
+        self.write(&format!("{}{}{}{}", separator, key_text, colon, space))
+    }
+
+    /// Write a complete value, e.g. an item of the list, or the
+    /// value of the key.
+    pub fn value(&mut self, value: &Value) -> Result<(), Error> {
+        self.before_item()?;
+        let text = print_value_at(value, &self.options, self.frames.len());
+        self.write(&text)?;
+        self.after_item();
+        Ok(())
+    }
+
+    /// Check that the document is complete, and take the writer back.
+    pub fn finish(mut self) -> Result<W, Error> {
+        if let Some(frame) = self.frames.last() {
+            return Err(Error::Message(format!(
+                "The document is incomplete, \"{}\" is expected.",
+                frame.container.close()
+            )));
+        }
+        self.writer
+            .flush()
+            .map_err(|e| Error::Message(format!("Fail to write the output: {}", e)))?;
+        Ok(self.writer)
+    }
+
+    fn start(&mut self, container: Container, open: &str) -> Result<(), Error> {
+        self.before_item()?;
+        let text = self.theme.punctuation.paint(open);
+        self.write(&text)?;
+        self.frames.push(Frame {
+            container,
+            empty: true,
+            has_key: false,
+        });
+        Ok(())
+    }
+
+    fn end(&mut self, container: Container) -> Result<(), Error> {
+        match self.frames.last() {
+            Some(frame) if frame.container == container && !frame.has_key => {}
+            Some(frame) if frame.container == container => {
+                return Err(Error::Message(
+                    "The value of the last key of the object is missing.".to_owned(),
+                ))
+            }
+            Some(frame) => {
+                return Err(Error::Message(format!(
+                    "Unexpected \"{}\", \"{}\" is expected.",
+                    container.close(),
+                    frame.container.close()
+                )))
+            }
+            None => {
+                return Err(Error::Message(format!(
+                    "Unexpected \"{}\", there is no open container.",
+                    container.close()
+                )))
+            }
+        }
+
+        let frame = self.frames.pop().unwrap();
+        let mut text = String::new();
+        if !frame.empty && self.options.indent.is_some() {
+            text.push_str(&self.separator(self.frames.len(), false));
+        }
+        text.push_str(&self.theme.punctuation.paint(container.close()));
+        self.write(&text)?;
+        self.after_item();
+        Ok(())
+    }
+
+    // write the separator before an item (a value or a container).
+    fn before_item(&mut self) -> Result<(), Error> {
+        let level = self.frames.len();
+        let separator = match self.frames.last_mut() {
+            None if self.finished_root => {
+                return Err(Error::Message(
+                    "The document has only one root value.".to_owned(),
+                ))
+            }
+            None => String::new(),
+            Some(frame) if frame.container == Container::Object => {
+                if !frame.has_key {
+                    return Err(Error::Message(
+                        "The key is expected before the value of the object.".to_owned(),
+                    ));
+                }
+                frame.has_key = false;
+                String::new()
+            }
+            Some(frame) => {
+                let first = frame.empty;
+                frame.empty = false;
+                self.separator(level, first)
+            }
+        };
+        self.write(&separator)
+    }
+
+    fn after_item(&mut self) {
+        if self.frames.is_empty() {
+            self.finished_root = true;
+        }
+    }
+
+    // the line break in the pretty layout, or the
+    // comma in the compact layout.
+    fn separator(&self, level: usize, first: bool) -> String {
+        match &self.options.indent {
+            Some(indent) => format!("\n{}", indent.repeat(level)),
+            None if !first => self.theme.punctuation.paint(","),
+            None => String::new(),
+        }
+    }
+
+    fn write(&mut self, text: &str) -> Result<(), Error> {
+        self.writer
+            .write_all(text.as_bytes())
+            .map_err(|e| Error::Message(format!("Fail to write the output: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        error::Error,
+        input::{parse_input, ParseOptions},
+        printer::{print_value, PrintOptions},
+        value::{Map, Value},
+    };
+
+    use super::AsonEmitter;
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    fn error_of(f: impl FnOnce(&mut AsonEmitter<Vec<u8>>) -> Result<(), Error>) -> String {
+        let mut emitter = AsonEmitter::new(vec![], PrintOptions::compact());
+        f(&mut emitter).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_emitter_matches_printer() {
+        // `{a: 1, "b c": [{x: [1, 2]}, [], (1, "s")]}`
+        let mut map = Map::new();
+        map.insert("a".to_owned(), Value::from(1));
+        map.insert(
+            "b c".to_owned(),
+            Value::List(
+                vec![
+                    value("{x: [1, 2]}"),
+                    Value::List(vec![].into()),
+                    value("(1, \"s\")"),
+                ]
+                .into(),
+            ),
+        );
+        let expected = Value::Object(map);
+
+        for options in [PrintOptions::compact(), PrintOptions::pretty("  ")] {
+            let mut emitter = AsonEmitter::new(vec![], options.clone());
+            emitter.start_object().unwrap();
+            emitter.key("a").unwrap();
+            emitter.value(&Value::from(1)).unwrap();
+            emitter.key("b c").unwrap();
+            emitter.start_list().unwrap();
+            emitter.value(&value("{x: [1, 2]}")).unwrap();
+            emitter.start_list().unwrap();
+            emitter.end_list().unwrap();
+            emitter.start_tuple().unwrap();
+            emitter.value(&Value::from(1)).unwrap();
+            emitter.value(&Value::String("s".to_owned())).unwrap();
+            emitter.end_tuple().unwrap();
+            emitter.end_list().unwrap();
+            emitter.end_object().unwrap();
+
+            let text = String::from_utf8(emitter.finish().unwrap()).unwrap();
+            assert_eq!(text, print_value(&expected, &options));
+        }
+    }
+
+    #[test]
+    fn test_emitter_errors() {
+        assert_eq!(
+            error_of(|e| {
+                e.start_object()?;
+                e.value(&Value::from(1))
+            }),
+            "The key is expected before the value of the object."
+        );
+        assert_eq!(
+            error_of(|e| {
+                e.start_object()?;
+                e.key("a")?;
+                e.key("b")
+            }),
+            "The value of the key is expected before the key \"b\"."
+        );
+        assert_eq!(
+            error_of(|e| {
+                e.start_object()?;
+                e.key("a")?;
+                e.end_object()
+            }),
+            "The value of the last key of the object is missing."
+        );
+        assert_eq!(
+            error_of(|e| {
+                e.start_object()?;
+                e.end_list()
+            }),
+            "Unexpected \"]\", \"}\" is expected."
+        );
+        assert_eq!(
+            error_of(|e| e.end_tuple()),
+            "Unexpected \")\", there is no open container."
+        );
+        assert_eq!(
+            error_of(|e| e.key("a")),
+            "The key \"a\" is written outside of an object."
+        );
+        assert_eq!(
+            error_of(|e| {
+                e.value(&Value::from(1))?;
+                e.value(&Value::from(2))
+            }),
+            "The document has only one root value."
+        );
+
+        let mut emitter = AsonEmitter::new(vec![], PrintOptions::compact());
+        emitter.start_list().unwrap();
+        assert_eq!(
+            emitter.finish().unwrap_err().to_string(),
+            "The document is incomplete, \"]\" is expected."
+        );
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use std::fmt::{self, Display};
+
+use crate::value::Value;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Error {
+    /// A general error, e.g. an evaluation error.
+    Message(String),
+
+    /// A syntax error of the query expression.
+    MessageWithLocation(String, Location),
+
+    /// The query expression ends prematurely.
+    UnexpectedEndOfQuery(String),
+
+    /// An error raised by the `error` function, the value
+    /// can be caught by `try ... catch`.
+    Raised(Value),
+
+    /// Stop the generator which has the label early, e.g. by `limit`,
+    /// it is not an error and cannot be caught by `try ... catch`.
+    Break(usize),
+
+    /// The evaluation is aborted by a `CancellationToken`,
+    /// it cannot be caught by `try ... catch`.
+    Cancelled,
+
+    /// The evaluation exceeds one of the `Limits`,
+    /// it cannot be caught by `try ... catch`.
+    LimitExceeded(String),
+
+    /// The query stops by `halt` or `halt_error`, with the exit status
+    /// of the process and the message (the input of `halt_error`),
+    /// it cannot be caught by `try ... catch`.
+    Halted(i32, Option<Value>),
+}
+
+/// The position of a token in the query expression,
+/// the index and length are counted in characters.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Location {
+    pub index: usize,
+    pub length: usize,
+}
+
+impl Location {
+    pub fn new(index: usize, length: usize) -> Self {
+        Self { index, length }
+    }
+}
+
+impl Error {
+    /// The value received by the `catch` handler.
+    pub fn to_value(&self) -> Value {
+        match self {
+            Error::Raised(v) => v.clone(),
+            _ => Value::String(self.to_string()),
+        }
+    }
+
+    /// Whether the error aborts the whole evaluation, i.e. it is
+    /// not suppressed by `try`, `?` and `//`.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Error::Cancelled | Error::LimitExceeded(_) | Error::Halted(..)
+        )
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Message(msg) => f.write_str(msg),
+            Error::MessageWithLocation(msg, location) => {
+                write!(f, "{} (at position {})", msg, location.index)
+            }
+            Error::UnexpectedEndOfQuery(msg) => {
+                write!(f, "Unexpected end of query expression: {}", msg)
+            }
+            Error::Raised(v) => write!(f, "{}", v.to_text()),
+            Error::Break(label) => write!(f, "Break out of the generator {}.", label),
+            Error::Cancelled => f.write_str("The evaluation is cancelled."),
+            Error::LimitExceeded(msg) => f.write_str(msg),
+            Error::Halted(status, _) => {
+                write!(f, "The query is halted with the exit status {}.", status)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,2082 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::VecDeque,
+    rc::Rc,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{
+    ast::{
+        AssignOperator, BinaryOperator, Expression, FunctionDefinition, InterpolationPart, Pattern,
+        VariantPayload,
+    },
+    cancel::CancellationToken,
+    error::Error,
+    failure::ErrorContext,
+    format::apply_format,
+    limits::Limits,
+    memo::MemoCache,
+    path::{delpaths, getpath, setpath},
+    provenance::{DocumentSource, Provenance},
+    value::{Items, Map, Number, Value, Variant, VariantValue},
+};
+
+// the number of the evaluated expressions between two checks of the deadline.
+const DEADLINE_CHECK_INTERVAL: usize = 1024;
+
+// the maximum length (in bytes) of the string built by the repetition,
+// e.g. `"abc" * 1e18` fails instead of exhausting the memory.
+const MAX_REPEATED_LENGTH: usize = 1 << 30;
+
+/// The receiver of the results of an expression.
+///
+/// An expression may produce zero, one or more results, each result is
+/// passed to the receiver as soon as it is produced. The receiver can
+/// stop the evaluation by returning `Error::Break` (see `catch_break`),
+/// e.g. `first(.items[] | select(.id == $x))` stops iterating the items
+/// after the first match, and the rest of the items are not copied.
+pub type Output<'o> = dyn FnMut(Value) -> Result<(), Error> + 'o;
+
+/// The receiver of the results of a path expression,
+/// i.e. the path and the value at that path.
+pub type PathOutput<'o> = dyn FnMut(Vec<Value>, Value) -> Result<(), Error> + 'o;
+
+/// The lexical scope of variables and functions,
+/// it is a linked list from the innermost binding to the root.
+pub struct Environment<'a> {
+    parent: Option<Rc<Environment<'a>>>,
+    binding: Binding<'a>,
+
+    // the unique id of the environment, unlike the address,
+    // it is never reused, so it can be a key of the memo cache.
+    id: usize,
+}
+
+static NEXT_ENVIRONMENT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn next_environment_id() -> usize {
+    NEXT_ENVIRONMENT_ID.fetch_add(1, atomic::Ordering::Relaxed)
+}
+
+enum Binding<'a> {
+    Root,
+    Variable(&'a str, Value),
+    Function(&'a FunctionDefinition),
+
+    // a filter argument, which is evaluated in the environment of the caller.
+    Closure {
+        name: &'a str,
+        expression: &'a Expression,
+        environment: Rc<Environment<'a>>,
+    },
+
+    // an imported module, the functions `alias::name` are
+    // looked up in the environment of the module.
+    Module {
+        alias: &'a str,
+        environment: Rc<Environment<'a>>,
+    },
+}
+
+enum Callable<'a> {
+    Function(&'a FunctionDefinition, Rc<Environment<'a>>),
+    Closure(&'a Expression, Rc<Environment<'a>>),
+}
+
+impl<'a> Environment<'a> {
+    pub(crate) fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn root() -> Rc<Self> {
+        Rc::new(Self {
+            parent: None,
+            binding: Binding::Root,
+            id: next_environment_id(),
+        })
+    }
+
+    fn push(parent: &Rc<Self>, binding: Binding<'a>) -> Rc<Self> {
+        Rc::new(Self {
+            parent: Some(parent.clone()),
+            binding,
+            id: next_environment_id(),
+        })
+    }
+
+    pub fn with_variable(parent: &Rc<Self>, name: &'a str, value: Value) -> Rc<Self> {
+        Self::push(parent, Binding::Variable(name, value))
+    }
+
+    pub fn with_function(parent: &Rc<Self>, definition: &'a FunctionDefinition) -> Rc<Self> {
+        Self::push(parent, Binding::Function(definition))
+    }
+
+    /// Bind an imported module, the `environment` contains
+    /// the definitions of the module.
+    pub fn with_module(parent: &Rc<Self>, alias: &'a str, environment: Rc<Self>) -> Rc<Self> {
+        Self::push(parent, Binding::Module { alias, environment })
+    }
+
+    fn lookup_variable(&self, name: &str) -> Option<&Value> {
+        let mut current = Some(self);
+        while let Some(env) = current {
+            if let Binding::Variable(n, v) = &env.binding {
+                if *n == name {
+                    return Some(v);
+                }
+            }
+            current = env.parent.as_deref();
+        }
+        None
+    }
+
+    fn lookup_function(env: &Rc<Self>, name: &str, arity: usize) -> Option<Callable<'a>> {
+        let mut current = Some(env);
+        while let Some(node) = current {
+            match &node.binding {
+                Binding::Function(definition)
+                    if definition.name == name && definition.params.len() == arity =>
+                {
+                    // the function body is evaluated in the environment where it is
+                    // defined, which includes the function itself, so that
+                    // recursive calls can be resolved.
+                    return Some(Callable::Function(definition, node.clone()));
+                }
+                Binding::Closure {
+                    name: n,
+                    expression,
+                    environment,
+                } if *n == name && arity == 0 => {
+                    return Some(Callable::Closure(expression, environment.clone()));
+                }
+                Binding::Module { alias, environment } => {
+                    let member = name
+                        .strip_prefix(*alias)
+                        .and_then(|rest| rest.strip_prefix("::"));
+                    if let Some(member) = member {
+                        return Self::lookup_function(environment, member, arity);
+                    }
+                }
+                _ => {}
+            }
+            current = node.parent.as_ref();
+        }
+        None
+    }
+
+    /// Whether the results of the expression may depend on the environment,
+    /// i.e. it refers to a variable or a filter argument, directly or
+    /// through the functions it calls. It is conservative, e.g. the
+    /// expressions which bind variables are always considered capturing.
+    pub(crate) fn is_captured_by(env: &Rc<Self>, expression: &'a Expression) -> bool {
+        captures(env, expression, &[], &mut vec![])
+    }
+}
+
+// `params` are the parameters of the function whose body is being checked,
+// the arguments are checked at the call sites instead. `visiting` are the
+// functions being checked, to stop at the recursive calls.
+fn captures<'a>(
+    env: &Rc<Environment<'a>>,
+    expression: &'a Expression,
+    params: &[String],
+    visiting: &mut Vec<*const FunctionDefinition>,
+) -> bool {
+    let parts = |parts: &'a [InterpolationPart]| {
+        parts.iter().filter_map(|p| match p {
+            InterpolationPart::Expression(e) => Some(e),
+            InterpolationPart::Literal(_) => None,
+        })
+    };
+
+    let children: Vec<&Expression> = match expression {
+        Expression::Identity | Expression::RecurseDefault | Expression::Literal(_) => vec![],
+        Expression::Variable(_)
+        | Expression::Reduce { .. }
+        | Expression::Foreach { .. }
+        | Expression::Binding { .. }
+        | Expression::FunctionDefinition(..) => return true,
+        Expression::Interpolation(items) => parts(items).collect(),
+        Expression::Format {
+            argument, template, ..
+        } => argument
+            .as_deref()
+            .into_iter()
+            .chain(template.iter().flat_map(|t| parts(t)))
+            .collect(),
+        Expression::Index(a, b)
+        | Expression::Pipe(a, b)
+        | Expression::Comma(a, b)
+        | Expression::Binary(_, a, b)
+        | Expression::And(a, b)
+        | Expression::Or(a, b)
+        | Expression::Alternative(a, b)
+        | Expression::Assign(_, a, b) => vec![a, b],
+        Expression::Slice(a, from, to) => std::iter::once(a.as_ref())
+            .chain(from.as_deref())
+            .chain(to.as_deref())
+            .collect(),
+        Expression::Iterate(a) | Expression::Negate(a) => vec![a],
+        Expression::Try(body, handler) => std::iter::once(body.as_ref())
+            .chain(handler.as_deref())
+            .collect(),
+        Expression::List(inner) => inner.as_deref().into_iter().collect(),
+        Expression::Object(entries) => entries.iter().flat_map(|(k, v)| [k, v]).collect(),
+        Expression::Variant { payload, .. } => match payload {
+            VariantPayload::Empty => vec![],
+            VariantPayload::Value(e) | VariantPayload::Object(e) => vec![e],
+            VariantPayload::Tuple(items) => items.iter().collect(),
+        },
+        Expression::If(branches, otherwise) => branches
+            .iter()
+            .flat_map(|(c, b)| [c, b])
+            .chain(otherwise.as_deref())
+            .collect(),
+        Expression::Call(name, args) => {
+            if args.iter().any(|e| captures(env, e, params, visiting)) {
+                return true;
+            }
+            if args.is_empty() && params.contains(name) {
+                return false;
+            }
+            return match Environment::lookup_function(env, name, args.len()) {
+                Some(Callable::Closure(..)) => true,
+                Some(Callable::Function(definition, node)) => {
+                    let pointer = definition as *const FunctionDefinition;
+                    if visiting.contains(&pointer) {
+                        return false;
+                    }
+                    visiting.push(pointer);
+                    let captured = captures(&node, &definition.body, &definition.params, visiting);
+                    visiting.pop();
+                    captured
+                }
+                // the native builtins
+                None => false,
+            };
+        }
+    };
+
+    children
+        .into_iter()
+        .any(|e| captures(env, e, params, visiting))
+}
+
+/// The documents which are produced on demand, e.g. the events of `--stream`.
+pub type InputStream = Rc<RefCell<dyn Iterator<Item = Result<Value, Error>>>>;
+
+#[derive(Default)]
+pub struct Evaluator {
+    pub(crate) provenance: Option<Provenance>,
+
+    // the documents for the `input` and `inputs` builtins, the stream
+    // is read (lazily) after the documents are consumed.
+    pub(crate) inputs: Rc<RefCell<VecDeque<Value>>>,
+    pub(crate) input_stream: Option<InputStream>,
+
+    // the sources of the input documents, and the index of the
+    // current document, i.e. the last one read by `input` and `inputs`,
+    // or the only input document.
+    pub(crate) input_sources: Vec<DocumentSource>,
+    pub(crate) current_input: Cell<Option<usize>>,
+
+    // the next label for `Error::Break`.
+    next_label: Cell<usize>,
+
+    pub(crate) cancellation: Option<CancellationToken>,
+
+    // the limits of the evaluation, the deadline is computed from
+    // the timeout when the evaluation starts.
+    pub(crate) limits: Limits,
+    pub(crate) deadline: Option<Instant>,
+
+    // the depth of the nested function calls, and the number of the
+    // evaluated expressions, the deadline is checked periodically.
+    depth: Cell<usize>,
+    steps: Cell<usize>,
+
+    // the results of `memo(f)` (see the module `memo`).
+    pub(crate) memo: RefCell<MemoCache>,
+
+    // the keys of the values passed on by `.name`, `.[i]` and `.[]`, and
+    // where the first uncaught error is raised, they are tracked only if
+    // `error_paths` is enabled (see the module `failure`).
+    pub(crate) error_paths: bool,
+    trail: RefCell<Vec<Value>>,
+    pub(crate) error_context: RefCell<Option<ErrorContext>>,
+}
+
+impl Evaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_provenance(provenance: Provenance) -> Self {
+        Self {
+            provenance: Some(provenance),
+            ..Self::default()
+        }
+    }
+
+    pub fn eval<'a>(
+        &self,
+        expression: &'a Expression,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        self.check_interrupted()?;
+
+        match expression {
+            Expression::Identity => output(input),
+            Expression::RecurseDefault => recurse_value(input, output),
+            Expression::Literal(value) => output(value.clone()),
+            Expression::Interpolation(parts) => self.eval_interpolation(
+                parts,
+                String::new(),
+                &input,
+                env,
+                &|v| Ok(v.to_text()),
+                output,
+            ),
+            Expression::Format {
+                name,
+                argument,
+                template,
+            } => self.eval_format(
+                name,
+                argument.as_deref(),
+                template.as_deref(),
+                input,
+                env,
+                output,
+            ),
+            Expression::Index(target, index) => {
+                if let Expression::Literal(index) = index.as_ref() {
+                    // fast path for `.name` and `.[0]`, the input does not
+                    // need to be cloned for evaluating the index.
+                    return self.eval(target, input, env, &mut |t| {
+                        let child = self.index_at(t, index, expression)?;
+                        self.step(|| index.clone(), || output(child))
+                    });
+                }
+
+                self.eval(index, input.clone(), env, &mut |i| {
+                    self.eval(target, input.clone(), env, &mut |t| {
+                        let child = self.index_at(t, &i, expression)?;
+                        self.step(|| i.clone(), || output(child))
+                    })
+                })
+            }
+            Expression::Slice(target, from, to) => {
+                self.eval_optional(from.as_deref(), &input, env, &mut |from| {
+                    self.eval_optional(to.as_deref(), &input, env, &mut |to| {
+                        self.eval(target, input.clone(), env, &mut |t| {
+                            output(slice_value(t, from.as_ref(), to.as_ref())?)
+                        })
+                    })
+                })
+            }
+            Expression::Iterate(target) => self.eval(target, input, env, &mut |t| match t {
+                Value::List(items) | Value::Tuple(items) => {
+                    for (idx, item) in items.into_iter().enumerate() {
+                        self.step(|| Value::from(idx), || output(item))?;
+                    }
+                    Ok(())
+                }
+                Value::Object(map) => {
+                    for (key, value) in map {
+                        self.step(|| Value::String(key), || output(value))?;
+                    }
+                    Ok(())
+                }
+                Value::ByteData(bytes) => {
+                    for byte in bytes {
+                        output(Value::Number(Number::U8(byte)))?;
+                    }
+                    Ok(())
+                }
+                _ => {
+                    let error = Error::Message(format!("Cannot iterate over {}.", t.type_name()));
+                    Err(self.fail(error, &t, None, expression))
+                }
+            }),
+            Expression::Try(body, handler) => {
+                self.eval_try(body, handler.as_deref(), input, env, output)
+            }
+            Expression::Pipe(left, right) => {
+                self.eval(left, input, env, &mut |v| self.eval(right, v, env, output))
+            }
+            Expression::Comma(left, right) => {
+                self.eval(left, input.clone(), env, output)?;
+                self.eval(right, input, env, output)
+            }
+            Expression::Negate(expression) => self.eval(expression, input, env, &mut |v| match v {
+                Value::Number(n) => output(Value::Number(negate(&n)?)),
+                _ => Err(Error::Message(format!("Cannot negate {}.", v.type_name()))),
+            }),
+            Expression::Binary(operator, left, right) => {
+                self.eval(right, input.clone(), env, &mut |r| {
+                    self.eval(left, input.clone(), env, &mut |l| {
+                        output(binary(*operator, l, &r)?)
+                    })
+                })
+            }
+            Expression::And(left, right) => self.eval(left, input.clone(), env, &mut |l| {
+                if !l.is_truthy() {
+                    return output(Value::Boolean(false));
+                }
+                self.eval(right, input.clone(), env, &mut |r| {
+                    output(Value::Boolean(r.is_truthy()))
+                })
+            }),
+            Expression::Or(left, right) => self.eval(left, input.clone(), env, &mut |l| {
+                if l.is_truthy() {
+                    return output(Value::Boolean(true));
+                }
+                self.eval(right, input.clone(), env, &mut |r| {
+                    output(Value::Boolean(r.is_truthy()))
+                })
+            }),
+            Expression::Alternative(left, right) => {
+                // the truthy values of the left side are passed to the
+                // receiver as soon as they are produced, the errors of the
+                // left side are suppressed, but the ones raised by the
+                // receiver are kept aside (as `try`).
+                let mut produced = false;
+                let mut output_error = None;
+                let result = self.eval(left, input.clone(), env, &mut |v| {
+                    if !v.is_truthy() {
+                        return Ok(());
+                    }
+                    produced = true;
+                    output(v).map_err(|e| {
+                        output_error = Some(e);
+                        Error::Message(String::new())
+                    })
+                });
+
+                if let Some(e) = output_error {
+                    return Err(e);
+                }
+                if matches!(&result, Err(e) if e.is_fatal()) {
+                    return result;
+                }
+
+                if result.is_err() {
+                    self.error_context.take();
+                }
+
+                if produced {
+                    Ok(())
+                } else {
+                    self.eval(right, input, env, output)
+                }
+            }
+            Expression::Assign(operator, left, right) => {
+                self.eval_assign(*operator, left, right, input, env, output)
+            }
+            Expression::List(None) => output(Value::List(Items::new())),
+            Expression::List(Some(expression)) => {
+                let items = self.collect(expression, input, env)?;
+                output(Value::List(items.into()))
+            }
+            Expression::Object(entries) => {
+                self.eval_object(entries, Map::new(), &input, env, output)
+            }
+            Expression::Variant {
+                type_name,
+                member_name,
+                payload,
+            } => {
+                let variant = |value| {
+                    Value::Variant(Arc::new(Variant {
+                        type_name: type_name.clone(),
+                        member_name: member_name.clone(),
+                        value,
+                    }))
+                };
+
+                match payload {
+                    VariantPayload::Empty => output(variant(VariantValue::Empty)),
+                    VariantPayload::Value(expression) => {
+                        self.eval(expression, input, env, &mut |v| {
+                            output(variant(VariantValue::Value(Box::new(v))))
+                        })
+                    }
+                    VariantPayload::Tuple(items) => {
+                        self.eval_items(items, vec![], &input, env, &mut |values| {
+                            output(variant(VariantValue::Tuple(values.into())))
+                        })
+                    }
+                    VariantPayload::Object(expression) => {
+                        self.eval(expression, input, env, &mut |v| match v {
+                            Value::Object(map) => output(variant(VariantValue::Object(map))),
+                            _ => unreachable!(),
+                        })
+                    }
+                }
+            }
+            Expression::If(branches, else_body) => {
+                self.eval_if(branches, else_body.as_deref(), input, env, output)
+            }
+            Expression::Reduce {
+                source,
+                pattern,
+                init,
+                update,
+            } => self.eval(init, input.clone(), env, &mut |init| {
+                let mut state = Some(init);
+                self.eval(source, input.clone(), env, &mut |item| {
+                    let item_env = bind_pattern(pattern, item, env)?;
+                    let current = state.take().unwrap_or_else(Value::null);
+
+                    // the last result of the update becomes the new state
+                    self.eval(update, current, &item_env, &mut |v| {
+                        state = Some(v);
+                        Ok(())
+                    })
+                })?;
+                output(state.unwrap_or_else(Value::null))
+            }),
+            Expression::Foreach {
+                source,
+                pattern,
+                init,
+                update,
+                extract,
+            } => self.eval(init, input.clone(), env, &mut |init| {
+                let mut state = init;
+                self.eval(source, input.clone(), env, &mut |item| {
+                    let item_env = bind_pattern(pattern, item, env)?;
+                    let current = state.clone();
+                    self.eval(update, current, &item_env, &mut |v| {
+                        state = v.clone();
+                        match extract {
+                            Some(extract) => self.eval(extract, v, &item_env, output),
+                            None => output(v),
+                        }
+                    })
+                })
+            }),
+            Expression::Binding {
+                source,
+                pattern,
+                body,
+            } => self.eval(source, input.clone(), env, &mut |v| {
+                let body_env = bind_pattern(pattern, v, env)?;
+                self.eval(body, input.clone(), &body_env, output)
+            }),
+            Expression::FunctionDefinition(definition, rest) => {
+                let rest_env = Environment::with_function(env, definition);
+                self.eval(rest, input, &rest_env, output)
+            }
+            Expression::Call(name, args) if self.error_paths => {
+                // the errors of the receiver are raised somewhere else.
+                let subject = input.clone();
+                let mut output_failed = false;
+                self.call(name, args, input, env, &mut |v| {
+                    output(v).inspect_err(|_| output_failed = true)
+                })
+                .map_err(|e| match output_failed {
+                    true => e,
+                    false => self.fail(e, &subject, None, expression),
+                })
+            }
+            Expression::Call(name, args) => self.call(name, args, input, env, output),
+            Expression::Variable(name) => match env.lookup_variable(name) {
+                Some(value) => output(value.clone()),
+                None if name == "ENV" => output(environment_variables()),
+                None if name == "__input__" => output(self.input_metadata()),
+                None => Err(Error::Message(format!(
+                    "Variable \"${}\" is not defined.",
+                    name
+                ))),
+            },
+        }
+    }
+
+    /// Evaluate an expression and collect all results.
+    pub fn collect<'a>(
+        &self,
+        expression: &'a Expression,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Vec<Value>, Error> {
+        let mut values = vec![];
+        self.eval(expression, input, env, &mut |v| {
+            values.push(v);
+            Ok(())
+        })?;
+        Ok(values)
+    }
+
+    /// Evaluate an expression and return the first result only,
+    /// the evaluation stops as soon as the first result is produced.
+    pub fn first<'a>(
+        &self,
+        expression: &'a Expression,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+    ) -> Result<Option<Value>, Error> {
+        let mut first = None;
+        self.catch_break(|label| {
+            self.eval(expression, input, env, &mut |v| {
+                first = Some(v);
+                Err(Error::Break(label))
+            })
+        })?;
+        Ok(first)
+    }
+
+    /// Run `body` with a new label, the receivers in `body` can return
+    /// `Error::Break(label)` to stop the generator early.
+    pub(crate) fn catch_break(
+        &self,
+        body: impl FnOnce(usize) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let label = self.next_label.get();
+        self.next_label.set(label + 1);
+
+        match body(label) {
+            Err(Error::Break(l)) if l == label => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Evaluate an expression in the path-tracking mode, each result is
+    /// passed to `output` as the path (relative to the input of the whole
+    /// path expression) and the value at that path.
+    ///
+    /// Only the path expressions, e.g. `.a`, `.[0]`, `.[]`, `..`, `select(f)`,
+    /// `if`, `//` and the pipe and comma of them, can be evaluated in this mode.
+    pub fn eval_path<'a>(
+        &self,
+        expression: &'a Expression,
+        path: Vec<Value>,
+        value: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut PathOutput,
+    ) -> Result<(), Error> {
+        self.check_interrupted()?;
+
+        match expression {
+            Expression::Identity => output(path, value),
+            Expression::RecurseDefault => recurse_paths(path, value, output),
+            Expression::Index(target, index) => self.eval(index, value.clone(), env, &mut |i| {
+                self.eval_path(target, path.clone(), value.clone(), env, &mut |p, v| {
+                    let child = index_value(v, &i)?;
+                    let mut child_path = p;
+                    child_path.push(i.clone());
+                    output(child_path, child)
+                })
+            }),
+            Expression::Slice(target, from, to) => {
+                self.eval_optional(from.as_deref(), &value, env, &mut |from| {
+                    self.eval_optional(to.as_deref(), &value, env, &mut |to| {
+                        self.eval_path(target, path.clone(), value.clone(), env, &mut |p, v| {
+                            let child = slice_value(v, from.as_ref(), to.as_ref())?;
+
+                            // the path key of a slice is `{start, end}`
+                            let mut bounds = Map::new();
+                            bounds.insert(
+                                "start".to_owned(),
+                                from.clone().unwrap_or_else(Value::null),
+                            );
+                            bounds.insert("end".to_owned(), to.clone().unwrap_or_else(Value::null));
+
+                            let mut child_path = p;
+                            child_path.push(Value::Object(bounds));
+                            output(child_path, child)
+                        })
+                    })
+                })
+            }
+            Expression::Iterate(target) => {
+                self.eval_path(target, path, value, env, &mut |p, v| match v {
+                    Value::List(items) | Value::Tuple(items) => {
+                        for (idx, item) in items.into_iter().enumerate() {
+                            let mut child_path = p.clone();
+                            child_path.push(Value::from(idx));
+                            output(child_path, item)?;
+                        }
+                        Ok(())
+                    }
+                    Value::Object(map) => {
+                        for (key, item) in map {
+                            let mut child_path = p.clone();
+                            child_path.push(Value::String(key));
+                            output(child_path, item)?;
+                        }
+                        Ok(())
+                    }
+                    _ => Err(Error::Message(format!(
+                        "Cannot iterate over {}.",
+                        v.type_name()
+                    ))),
+                })
+            }
+            Expression::Try(body, None) => {
+                let mut output_error = None;
+                let result = self.eval_path(body, path, value, env, &mut |p, v| {
+                    output(p, v).map_err(|e| {
+                        output_error = Some(e);
+                        Error::Message(String::new())
+                    })
+                });
+
+                match (output_error, result) {
+                    (Some(e), _) => Err(e),
+                    (None, Err(e)) if e.is_fatal() => Err(e),
+                    // errors of the body are suppressed
+                    (None, _) => {
+                        self.error_context.take();
+                        Ok(())
+                    }
+                }
+            }
+            Expression::Pipe(left, right) => self.eval_path(left, path, value, env, &mut |p, v| {
+                self.eval_path(right, p, v, env, output)
+            }),
+            Expression::Comma(left, right) => {
+                self.eval_path(left, path.clone(), value.clone(), env, output)?;
+                self.eval_path(right, path, value, env, output)
+            }
+            Expression::Alternative(left, right) => {
+                let mut results = vec![];
+                let result = self.eval_path(left, path.clone(), value.clone(), env, &mut |p, v| {
+                    if v.is_truthy() {
+                        results.push((p, v));
+                    }
+                    Ok(())
+                });
+
+                if matches!(&result, Err(e) if e.is_fatal()) {
+                    return result;
+                }
+                if result.is_err() {
+                    self.error_context.take();
+                }
+
+                if results.is_empty() {
+                    self.eval_path(right, path, value, env, output)
+                } else {
+                    for (p, v) in results {
+                        output(p, v)?;
+                    }
+                    Ok(())
+                }
+            }
+            Expression::If(branches, else_body) => {
+                self.eval_if_path(branches, else_body.as_deref(), path, value, env, output)
+            }
+            Expression::Binding {
+                source,
+                pattern,
+                body,
+            } => self.eval(source, value.clone(), env, &mut |v| {
+                let body_env = bind_pattern(pattern, v, env)?;
+                self.eval_path(body, path.clone(), value.clone(), &body_env, output)
+            }),
+            Expression::FunctionDefinition(definition, rest) => {
+                let rest_env = Environment::with_function(env, definition);
+                self.eval_path(rest, path, value, &rest_env, output)
+            }
+            Expression::Call(name, args) => {
+                match Environment::lookup_function(env, name, args.len()) {
+                    Some(Callable::Function(definition, definition_env)) => {
+                        let call_env = bind_arguments(definition, args, definition_env, env);
+                        self.enter_call(|| {
+                            self.eval_path(&definition.body, path, value, &call_env, output)
+                        })
+                    }
+                    Some(Callable::Closure(expression, closure_env)) => {
+                        self.eval_path(expression, path, value, &closure_env, output)
+                    }
+                    None => self.call_native_path(name, args, path, value, env, output),
+                }
+            }
+            _ => self.eval(expression, value, env, &mut |v| Err(invalid_path_error(&v))),
+        }
+    }
+
+    fn eval_if_path<'a>(
+        &self,
+        branches: &'a [(Expression, Expression)],
+        else_body: Option<&'a Expression>,
+        path: Vec<Value>,
+        value: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut PathOutput,
+    ) -> Result<(), Error> {
+        let ((condition, body), rest) = match branches.split_first() {
+            Some(first) => first,
+            None => {
+                return match else_body {
+                    Some(else_body) => self.eval_path(else_body, path, value, env, output),
+                    None => output(path, value),
+                };
+            }
+        };
+
+        self.eval(condition, value.clone(), env, &mut |c| {
+            if c.is_truthy() {
+                self.eval_path(body, path.clone(), value.clone(), env, output)
+            } else {
+                self.eval_if_path(rest, else_body, path.clone(), value.clone(), env, output)
+            }
+        })
+    }
+
+    fn call<'a>(
+        &self,
+        name: &'a str,
+        args: &'a [Expression],
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        match Environment::lookup_function(env, name, args.len()) {
+            Some(Callable::Function(definition, definition_env)) => {
+                let call_env = bind_arguments(definition, args, definition_env, env);
+                self.enter_call(|| self.eval(&definition.body, input, &call_env, output))
+            }
+            Some(Callable::Closure(expression, closure_env)) => {
+                self.eval(expression, input, &closure_env, output)
+            }
+            None => self.call_native(name, args, input, env, output),
+        }
+    }
+
+    // evaluate an optional expression, e.g. the ends of a slice,
+    // the absent expression produces a single `None`.
+    fn eval_optional<'a>(
+        &self,
+        expression: Option<&'a Expression>,
+        input: &Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut dyn FnMut(Option<Value>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        match expression {
+            Some(expression) => self.eval(expression, input.clone(), env, &mut |v| output(Some(v))),
+            None => output(None),
+        }
+    }
+
+    // the results of the interpolations are converted to text by `format`.
+    fn eval_interpolation<'a>(
+        &self,
+        parts: &'a [InterpolationPart],
+        prefix: String,
+        input: &Value,
+        env: &Rc<Environment<'a>>,
+        format: &dyn Fn(&Value) -> Result<String, Error>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        match parts.split_first() {
+            None => output(Value::String(prefix)),
+            Some((InterpolationPart::Literal(s), rest)) => {
+                let mut text = prefix;
+                text.push_str(s);
+                self.eval_interpolation(rest, text, input, env, format, output)
+            }
+            Some((InterpolationPart::Expression(expression), rest)) => {
+                self.eval(expression, input.clone(), env, &mut |v| {
+                    let mut text = prefix.clone();
+                    text.push_str(&format(&v)?);
+                    self.eval_interpolation(rest, text, input, env, format, output)
+                })
+            }
+        }
+    }
+
+    fn eval_format<'a>(
+        &self,
+        name: &str,
+        argument: Option<&'a Expression>,
+        template: Option<&'a [InterpolationPart]>,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        self.eval_optional(argument, &input, env, &mut |arg| {
+            let format = |v: &Value| apply_format(name, arg.as_ref(), v);
+            match template {
+                Some(parts) => {
+                    self.eval_interpolation(parts, String::new(), &input, env, &format, output)
+                }
+                None => output(Value::String(format(&input)?)),
+            }
+        })
+    }
+
+    fn eval_object<'a>(
+        &self,
+        entries: &'a [(Expression, Expression)],
+        map: Map,
+        input: &Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        let ((key_expression, value_expression), rest) = match entries.split_first() {
+            Some(first) => first,
+            None => return output(Value::Object(map)),
+        };
+
+        self.eval(key_expression, input.clone(), env, &mut |key| {
+            let key = match key {
+                Value::String(s) => s,
+                _ => {
+                    return Err(Error::Message(format!(
+                        "Object keys must be strings, found {}.",
+                        key.type_name()
+                    )));
+                }
+            };
+
+            self.eval(value_expression, input.clone(), env, &mut |value| {
+                let mut entries_map = map.clone();
+                entries_map.insert(key.clone(), value);
+                self.eval_object(rest, entries_map, input, env, output)
+            })
+        })
+    }
+
+    // the cartesian product of the results of the items, e.g. the
+    // items `(1, 2)` and `(3, 4)` produce `[1, 3]`, `[1, 4]`, `[2, 3]` and `[2, 4]`.
+    fn eval_items<'a>(
+        &self,
+        items: &'a [Expression],
+        values: Vec<Value>,
+        input: &Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut dyn FnMut(Vec<Value>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let Some((first, rest)) = items.split_first() else {
+            return output(values);
+        };
+
+        self.eval(first, input.clone(), env, &mut |v| {
+            let mut item_values = values.clone();
+            item_values.push(v);
+            self.eval_items(rest, item_values, input, env, output)
+        })
+    }
+
+    fn eval_if<'a>(
+        &self,
+        branches: &'a [(Expression, Expression)],
+        else_body: Option<&'a Expression>,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        let ((condition, body), rest) = match branches.split_first() {
+            Some(first) => first,
+            None => {
+                // `if ... then ... end` without `else` acts as `else . end`
+                return match else_body {
+                    Some(else_body) => self.eval(else_body, input, env, output),
+                    None => output(input),
+                };
+            }
+        };
+
+        self.eval(condition, input.clone(), env, &mut |c| {
+            if c.is_truthy() {
+                self.eval(body, input.clone(), env, output)
+            } else {
+                self.eval_if(rest, else_body, input.clone(), env, output)
+            }
+        })
+    }
+
+    fn eval_assign<'a>(
+        &self,
+        operator: AssignOperator,
+        left: &'a Expression,
+        right: &'a Expression,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        let mut paths = vec![];
+        self.eval_path(left, vec![], input.clone(), env, &mut |p, _| {
+            paths.push(p);
+            Ok(())
+        })?;
+
+        if operator == AssignOperator::Update {
+            // the first result of the update is used, and the value
+            // is deleted if there is no result.
+            let mut value = input;
+            let mut deleted_paths = vec![];
+            for path in paths {
+                let old = getpath(&value, &path)?;
+                match self.first(right, old, env)? {
+                    Some(new) => value = setpath(value, &path, new)?,
+                    None => deleted_paths.push(path),
+                }
+            }
+            return output(delpaths(value, deleted_paths)?);
+        }
+
+        // the right side is evaluated with the input of the whole expression,
+        // each result produces an output.
+        self.eval(right, input.clone(), env, &mut |rhs| {
+            let mut value = input.clone();
+            for path in &paths {
+                let new = match operator {
+                    AssignOperator::Arithmetic(op) => binary(op, getpath(&value, path)?, &rhs)?,
+                    AssignOperator::Alternative => {
+                        let old = getpath(&value, path)?;
+                        if old.is_truthy() {
+                            old
+                        } else {
+                            rhs.clone()
+                        }
+                    }
+                    _ => rhs.clone(),
+                };
+                value = setpath(value, path, new)?;
+            }
+            output(value)
+        })
+    }
+
+    fn check_interrupted(&self) -> Result<(), Error> {
+        if let Some(token) = &self.cancellation {
+            if token.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+        }
+
+        // reading the clock for each expression is too expensive
+        let steps = self.steps.get().wrapping_add(1);
+        self.steps.set(steps);
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if steps.is_multiple_of(DEADLINE_CHECK_INTERVAL) && Instant::now() >= deadline {
+                return Err(Error::LimitExceeded(format!(
+                    "The evaluation exceeds the time limit of {:?}.",
+                    timeout
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // run the body of a function call, the depth of the
+    // nested calls is checked against the limit.
+    fn enter_call(&self, body: impl FnOnce() -> Result<(), Error>) -> Result<(), Error> {
+        let depth = self.depth.get() + 1;
+        if let Some(max_depth) = self.limits.max_depth {
+            if depth > max_depth {
+                return Err(Error::LimitExceeded(format!(
+                    "The depth of the function calls exceeds the limit of {}.",
+                    max_depth
+                )));
+            }
+        }
+
+        self.depth.set(depth);
+        let result = body();
+        self.depth.set(depth - 1);
+        result
+    }
+
+    // index the value, the failure is recorded (see `fail`).
+    fn index_at(&self, target: Value, index: &Value, stage: &Expression) -> Result<Value, Error> {
+        if !self.error_paths {
+            return index_value(target, index);
+        }
+        index_value(target.clone(), index).map_err(|e| self.fail(e, &target, Some(index), stage))
+    }
+
+    // pass a value to the receiver with the key of the value on the trail.
+    fn step(
+        &self,
+        key: impl FnOnce() -> Value,
+        body: impl FnOnce() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        if !self.error_paths {
+            return body();
+        }
+        self.trail.borrow_mut().push(key());
+        let result = body();
+        self.trail.borrow_mut().pop();
+        result
+    }
+
+    // record where the error is raised, unless an inner
+    // expression has already recorded it.
+    fn fail(
+        &self,
+        error: Error,
+        subject: &Value,
+        key: Option<&Value>,
+        stage: &Expression,
+    ) -> Error {
+        if self.error_paths && matches!(error, Error::Message(_)) {
+            let mut context = self.error_context.borrow_mut();
+            if context.is_none() {
+                *context = Some(ErrorContext {
+                    message: error.to_string(),
+                    path: self.trail.borrow().clone(),
+                    key: key.cloned(),
+                    subject: subject.clone(),
+                    stage: stage.to_string(),
+                });
+            }
+        }
+        error
+    }
+
+    fn eval_try<'a>(
+        &self,
+        body: &'a Expression,
+        handler: Option<&'a Expression>,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        // errors raised by the receiver (i.e. the following stages of the pipeline)
+        // must not be caught by this `try`, so they are kept aside.
+        let mut output_error = None;
+
+        let result = self.eval(body, input, env, &mut |v| {
+            output(v).map_err(|e| {
+                output_error = Some(e);
+                Error::Message(String::new())
+            })
+        });
+
+        if let Some(e) = output_error {
+            return Err(e);
+        }
+
+        if matches!(&result, Err(e) if !e.is_fatal()) {
+            // the error is caught, so it is not located.
+            self.error_context.take();
+        }
+
+        match (result, handler) {
+            (Ok(()), _) => Ok(()),
+            (Err(e), _) if e.is_fatal() => Err(e),
+            (Err(_), None) => Ok(()),
+            (Err(e), Some(handler)) => self.eval(handler, e.to_value(), env, output),
+        }
+    }
+}
+
+/// The environment variables of the process as an object,
+/// the variables which are not valid Unicode are omitted.
+pub fn environment_variables() -> Value {
+    let mut map = Map::new();
+    for (key, value) in std::env::vars_os() {
+        if let (Ok(k), Ok(v)) = (key.into_string(), value.into_string()) {
+            map.insert(k, Value::String(v));
+        }
+    }
+    Value::Object(map)
+}
+
+// the arguments are bound as closures, they are
+// evaluated in the environment of the caller.
+fn bind_arguments<'a>(
+    definition: &'a FunctionDefinition,
+    args: &'a [Expression],
+    definition_env: Rc<Environment<'a>>,
+    caller_env: &Rc<Environment<'a>>,
+) -> Rc<Environment<'a>> {
+    let mut call_env = definition_env;
+    for (param, arg) in definition.params.iter().zip(args) {
+        call_env = Environment::push(
+            &call_env,
+            Binding::Closure {
+                name: param,
+                expression: arg,
+                environment: caller_env.clone(),
+            },
+        );
+    }
+    call_env
+}
+
+pub fn invalid_path_error(value: &Value) -> Error {
+    let mut text = value.to_string();
+    if text.chars().count() > 32 {
+        text = text.chars().take(32).collect::<String>() + "...";
+    }
+    Error::Message(format!("Invalid path expression with result {}.", text))
+}
+
+/// Output the paths of the value and all of its descendants, i.e. `path(..)`
+pub fn recurse_paths(path: Vec<Value>, value: Value, output: &mut PathOutput) -> Result<(), Error> {
+    let children: Vec<(Value, Value)> = match &value {
+        Value::List(items) | Value::Tuple(items) => items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| (Value::from(idx), item.clone()))
+            .collect(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, item)| (Value::String(key.clone()), item.clone()))
+            .collect(),
+        _ => vec![],
+    };
+
+    output(path.clone(), value)?;
+
+    for (key, child) in children {
+        let mut child_path = path.clone();
+        child_path.push(key);
+        recurse_paths(child_path, child, output)?;
+    }
+    Ok(())
+}
+
+fn bind_pattern<'a>(
+    pattern: &'a Pattern,
+    value: Value,
+    env: &Rc<Environment<'a>>,
+) -> Result<Rc<Environment<'a>>, Error> {
+    match pattern {
+        Pattern::Variable(name) => Ok(Environment::with_variable(env, name, value)),
+        Pattern::Elements(patterns) => {
+            let mut items = match value {
+                Value::List(items) | Value::Tuple(items) => items.into_vec(),
+                _ if value.is_null() => vec![],
+                _ => {
+                    return Err(Error::Message(format!(
+                        "Cannot destructure {} into elements.",
+                        value.type_name()
+                    )))
+                }
+            };
+
+            items.resize_with(patterns.len().max(items.len()), Value::null);
+            patterns
+                .iter()
+                .zip(items)
+                .try_fold(env.clone(), |env, (pattern, item)| {
+                    bind_pattern(pattern, item, &env)
+                })
+        }
+    }
+}
+
+/// Output the value and all of its descendants, i.e. `..`
+pub fn recurse_value(value: Value, output: &mut Output) -> Result<(), Error> {
+    let children: Vec<Value> = match &value {
+        Value::List(items) | Value::Tuple(items) => items.to_vec(),
+        Value::Object(map) => map.values().cloned().collect(),
+        _ => vec![],
+    };
+
+    output(value)?;
+
+    for child in children {
+        recurse_value(child, output)?;
+    }
+    Ok(())
+}
+
+// convert a number to a list index, the negative index counts from the end.
+pub fn to_index(n: &Number, length: usize) -> Option<usize> {
+    let i = n.as_integer().unwrap_or_else(|| n.as_f64().floor() as i128);
+    let i = if i < 0 { i + length as i128 } else { i };
+    if i >= 0 && (i as usize) < length {
+        Some(i as usize)
+    } else {
+        None
+    }
+}
+
+pub fn index_value(target: Value, index: &Value) -> Result<Value, Error> {
+    if target.is_null() {
+        return Ok(Value::null());
+    }
+
+    match (target, index) {
+        // the entries and the items may be shared with the other
+        // clones, so the value is copied instead of being taken out.
+        (Value::Object(map), Value::String(key)) => {
+            Ok(map.get(key).cloned().unwrap_or_else(Value::null))
+        }
+        (Value::List(items) | Value::Tuple(items), Value::Number(n)) => {
+            match to_index(n, items.len()) {
+                Some(i) => Ok(items[i].clone()),
+                None => Ok(Value::null()),
+            }
+        }
+        (Value::ByteData(bytes), Value::Number(n)) => match to_index(n, bytes.len()) {
+            Some(i) => Ok(Value::Number(Number::U8(bytes[i]))),
+            None => Ok(Value::null()),
+        },
+        (target, index) => Err(Error::Message(format!(
+            "Cannot index {} with {}.",
+            target.type_name(),
+            index.type_name()
+        ))),
+    }
+}
+
+// convert the ends of a slice to a range, the ends are clamped.
+pub fn to_range(
+    from: Option<&Value>,
+    to: Option<&Value>,
+    length: usize,
+) -> Result<(usize, usize), Error> {
+    let clamp = |v: Option<&Value>, default: usize| -> Result<usize, Error> {
+        match v {
+            None => Ok(default),
+            Some(v) if v.is_null() => Ok(default),
+            Some(Value::Number(n)) => {
+                let i = n.as_integer().unwrap_or_else(|| n.as_f64().floor() as i128);
+                let i = if i < 0 { i + length as i128 } else { i };
+                Ok(i.clamp(0, length as i128) as usize)
+            }
+            Some(v) => Err(Error::Message(format!(
+                "The slice index must be a number, found {}.",
+                v.type_name()
+            ))),
+        }
+    };
+
+    let start = clamp(from, 0)?;
+    let end = clamp(to, length)?;
+    Ok((start, end.max(start)))
+}
+
+pub fn slice_value(
+    target: Value,
+    from: Option<&Value>,
+    to: Option<&Value>,
+) -> Result<Value, Error> {
+    match target {
+        Value::List(items) => {
+            let (start, end) = to_range(from, to, items.len())?;
+            Ok(Value::List(items[start..end].to_vec().into()))
+        }
+        Value::Tuple(items) => {
+            let (start, end) = to_range(from, to, items.len())?;
+            Ok(Value::Tuple(items[start..end].to_vec().into()))
+        }
+        Value::String(s) => {
+            let chars: Vec<char> = s.chars().collect();
+            let (start, end) = to_range(from, to, chars.len())?;
+            Ok(Value::String(chars[start..end].iter().collect()))
+        }
+        Value::ByteData(bytes) => {
+            let (start, end) = to_range(from, to, bytes.len())?;
+            Ok(Value::ByteData(bytes[start..end].to_vec()))
+        }
+        _ if target.is_null() => Ok(Value::null()),
+        _ => Err(Error::Message(format!(
+            "Cannot slice {}.",
+            target.type_name()
+        ))),
+    }
+}
+
+fn negate(n: &Number) -> Result<Number, Error> {
+    let result = match n {
+        Number::F32(v) => Number::F32(-v),
+        Number::F64(v) => Number::F64(-v),
+        _ => Number::from_i128_like(-n.as_i128().unwrap(), Some(n)),
+    };
+    Ok(result)
+}
+
+// The type of the result of integer arithmetic:
+//
+// - the same type if both operands have the same type,
+// - the type of the other operand if one of them is `i32`
+//   (the default type of number literals), e.g. `.count + 1`.
+// - otherwise `i64`.
+//
+// The result falls back to a wider type when it does not fit in,
+// see `arithmetic()`.
+fn integer_template<'n>(a: &'n Number, b: &'n Number) -> Option<&'n Number> {
+    if std::mem::discriminant(a) == std::mem::discriminant(b) {
+        Some(a)
+    } else if matches!(a, Number::I32(_)) {
+        Some(b)
+    } else if matches!(b, Number::I32(_)) {
+        Some(a)
+    } else {
+        None
+    }
+}
+
+/// The arithmetic of the numbers, the integers never wrap around or
+/// fail on overflow, instead the result which does not fit in its type
+/// widens to `i64`, then `u64`, and then `f64` (which may lose the
+/// precision), e.g. `255_u8 + 1_u8` is `256_i64`, `u64::MAX + 1` is
+/// `1.8446744073709552e19`, and the inexact division is `f64` as well.
+pub fn arithmetic(operator: BinaryOperator, a: &Number, b: &Number) -> Result<Number, Error> {
+    let division_by_zero = || Error::Message("Division by zero.".to_owned());
+
+    if a.is_float() || b.is_float() {
+        let (x, y) = (a.as_f64(), b.as_f64());
+        let r = match operator {
+            BinaryOperator::Add => x + y,
+            BinaryOperator::Subtract => x - y,
+            BinaryOperator::Multiply => x * y,
+            BinaryOperator::Divide if y == 0.0 => return Err(division_by_zero()),
+            BinaryOperator::Divide => x / y,
+            BinaryOperator::Remainder if y == 0.0 => return Err(division_by_zero()),
+            BinaryOperator::Remainder => x % y,
+            _ => unreachable!(),
+        };
+
+        // `f32` is kept unless the other operand is `f64`
+        let is_f32 = matches!(a, Number::F32(_)) && !matches!(b, Number::F64(_))
+            || matches!(b, Number::F32(_)) && !matches!(a, Number::F64(_));
+        return Ok(if is_f32 {
+            Number::F32(r as f32)
+        } else {
+            Number::F64(r)
+        });
+    }
+
+    let (x, y) = (a.as_i128().unwrap(), b.as_i128().unwrap());
+    let r = match operator {
+        BinaryOperator::Add => x.checked_add(y),
+        BinaryOperator::Subtract => x.checked_sub(y),
+        BinaryOperator::Multiply => x.checked_mul(y),
+        BinaryOperator::Divide if y == 0 => return Err(division_by_zero()),
+        BinaryOperator::Divide if x % y != 0 => {
+            // inexact integer division produces a floating point
+            return Ok(Number::F64(x as f64 / y as f64));
+        }
+        BinaryOperator::Divide => Some(x / y),
+        BinaryOperator::Remainder if y == 0 => return Err(division_by_zero()),
+        BinaryOperator::Remainder => Some(x % y),
+        _ => unreachable!(),
+    };
+
+    match r {
+        Some(r) => Ok(Number::from_i128_like(r, integer_template(a, b))),
+        None => arithmetic(operator, &Number::F64(x as f64), &Number::F64(y as f64)),
+    }
+}
+
+fn add(left: Value, right: &Value) -> Result<Value, Error> {
+    // `null` is the identity of addition
+    if right.is_null() {
+        return Ok(left);
+    }
+    if left.is_null() {
+        return Ok(right.clone());
+    }
+
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => {
+            Ok(Value::Number(arithmetic(BinaryOperator::Add, &a, b)?))
+        }
+        (Value::String(mut a), Value::String(b)) => {
+            a.push_str(b);
+            Ok(Value::String(a))
+        }
+        (Value::List(mut a), Value::List(b)) => {
+            a.extend(b.iter().cloned());
+            Ok(Value::List(a))
+        }
+        (Value::Tuple(mut a), Value::Tuple(b)) => {
+            a.extend(b.iter().cloned());
+            Ok(Value::Tuple(a))
+        }
+        (Value::Object(mut a), Value::Object(b)) => {
+            for (key, value) in b.iter() {
+                a.insert(key.clone(), value.clone());
+            }
+            Ok(Value::Object(a))
+        }
+        (left, right) => Err(Error::Message(format!(
+            "Cannot add {} to {}.",
+            right.type_name(),
+            left.type_name()
+        ))),
+    }
+}
+
+/// Merge the objects recursively, i.e. `a * b`, the values of `b` replace the
+/// ones of `a` with the same keys, except that both of them are objects.
+pub fn deep_merge(mut left: Map, right: &Map) -> Map {
+    for (key, value) in right.iter() {
+        let merged = match (left.get(key), value) {
+            (Some(Value::Object(inner)), Value::Object(other)) => {
+                Value::Object(deep_merge(inner.clone(), other))
+            }
+            _ => value.clone(),
+        };
+        left.insert(key.clone(), merged);
+    }
+    left
+}
+
+pub fn binary(operator: BinaryOperator, left: Value, right: &Value) -> Result<Value, Error> {
+    let compare = |expected: &[Ordering]| Value::Boolean(expected.contains(&left.compare(right)));
+
+    let value = match operator {
+        BinaryOperator::Equal => Value::Boolean(left == *right),
+        BinaryOperator::NotEqual => Value::Boolean(left != *right),
+        BinaryOperator::Less => compare(&[Ordering::Less]),
+        BinaryOperator::LessOrEqual => compare(&[Ordering::Less, Ordering::Equal]),
+        BinaryOperator::Greater => compare(&[Ordering::Greater]),
+        BinaryOperator::GreaterOrEqual => compare(&[Ordering::Greater, Ordering::Equal]),
+        BinaryOperator::Add => return add(left, right),
+        _ => match (left, right) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(arithmetic(operator, &a, b)?),
+            (Value::List(mut a), Value::List(b)) if operator == BinaryOperator::Subtract => {
+                a.retain(|item| !b.contains(item));
+                Value::List(a)
+            }
+            (Value::Object(a), Value::Object(b)) if operator == BinaryOperator::Multiply => {
+                Value::Object(deep_merge(a, b))
+            }
+            (Value::String(s), Value::Number(n)) if operator == BinaryOperator::Multiply => {
+                // repeat the string, e.g. `"ab" * 3`
+                match n.as_integer() {
+                    Some(times) if times > 0 => {
+                        let length = usize::try_from(times)
+                            .ok()
+                            .and_then(|times| s.len().checked_mul(times));
+                        match length {
+                            Some(length) if length <= MAX_REPEATED_LENGTH => {
+                                Value::String(s.repeat(times as usize))
+                            }
+                            _ => {
+                                return Err(Error::Message(format!(
+                                    "Cannot repeat the string {} times, the result \
+                                    exceeds {} bytes.",
+                                    times, MAX_REPEATED_LENGTH
+                                )))
+                            }
+                        }
+                    }
+                    _ => Value::null(),
+                }
+            }
+            (Value::String(s), Value::String(separator)) if operator == BinaryOperator::Divide => {
+                Value::List(
+                    s.split(separator.as_str())
+                        .map(|part| Value::String(part.to_owned()))
+                        .collect(),
+                )
+            }
+            (left, right) => {
+                let verb = match operator {
+                    BinaryOperator::Subtract => "subtract",
+                    BinaryOperator::Multiply => "multiply",
+                    BinaryOperator::Divide => "divide",
+                    _ => "calculate the remainder of",
+                };
+                return Err(Error::Message(format!(
+                    "Cannot {} {} and {}.",
+                    verb,
+                    left.type_name(),
+                    right.type_name()
+                )));
+            }
+        },
+    };
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        error::Error,
+        input::{parse_input, ParseOptions},
+        limits::Limits,
+        query::Query,
+        value::Value,
+    };
+
+    // run the query with the input document (in ASON), the results are
+    // written in the compact form.
+    fn eval(query: &str, input: &str) -> Result<Vec<String>, Error> {
+        let input = parse_input(input, None, &ParseOptions::unlimited())?;
+        let results = Query::compile(query)?.run_value(input)?;
+        Ok(results.iter().map(|v| v.to_string()).collect())
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let doc = "{id: 7, total: 12.5, tags: [\"x\", \"y\"]}";
+        assert_eq!(
+            eval(r#""Order \(.id): \(.total) USD""#, doc).unwrap(),
+            [r#""Order 7: 12.5 USD""#]
+        );
+        assert_eq!(
+            eval(r#""tags: \(.tags), \(.missing)""#, doc).unwrap(),
+            [r#""tags: [\"x\",\"y\"], Option::None""#]
+        );
+        assert_eq!(eval(r#""\("a" + "b")!""#, doc).unwrap(), [r#""ab!""#]);
+
+        // the typed numbers have no suffix, and the chars no quotes
+        let doc = "{id: 123_u32, total: 9.5_f32, rate: 0.1_f32, grade: 'A', big: 18446744073709551615_u64}";
+        assert_eq!(
+            eval(
+                r#""Order \(.id): \(.total) USD, \(.rate), \(.grade), \(.big)""#,
+                doc
+            )
+            .unwrap(),
+            [r#""Order 123: 9.5 USD, 0.1, A, 18446744073709551615""#]
+        );
+        assert_eq!(
+            eval(r#""\([.id, .grade])""#, doc).unwrap(),
+            [r#""[123_u32,'A']""#]
+        );
+
+        // each combination of the results
+        assert_eq!(
+            eval(r#""\(1, 2)-\(3, 4)""#, "0").unwrap(),
+            [r#""1-3""#, r#""1-4""#, r#""2-3""#, r#""2-4""#]
+        );
+    }
+
+    #[test]
+    fn test_pipe_and_collections() {
+        let doc = r#"{items: [{price: 10, name: "a"}, {price: 20, name: "b"}]}"#;
+        assert_eq!(eval(".items[].price", doc).unwrap(), ["10", "20"]);
+        assert_eq!(
+            eval("[.items[] | select(.price > 15) | .name]", doc).unwrap(),
+            [r#"["b"]"#]
+        );
+        assert_eq!(
+            eval("{a: 1, b: (2, 3)}", "0").unwrap(),
+            ["{a:1,b:2}", "{a:1,b:3}"]
+        );
+        assert_eq!(eval("[1, 2, 3][1:]", "0").unwrap(), ["[2,3]"]);
+        assert_eq!(eval("[..]", "[[1]]").unwrap(), ["[[[1]],[1],1]"]);
+        assert_eq!(eval(".a // 5", "{}").unwrap(), ["5"]);
+    }
+
+    #[test]
+    fn test_control_flow() {
+        assert_eq!(
+            eval("reduce .[] as $x (0; . + $x)", "[1, 2, 3]").unwrap(),
+            ["6"]
+        );
+        assert_eq!(
+            eval("[foreach .[] as $x (0; . + $x)]", "[1, 2, 3]").unwrap(),
+            ["[1,3,6]"]
+        );
+        assert_eq!(
+            eval(
+                r#"if . > 2 then "big" elif . > 1 then "mid" else "small" end"#,
+                "2"
+            )
+            .unwrap(),
+            [r#""mid""#]
+        );
+        assert_eq!(eval(r#"try error("x") catch ."#, "0").unwrap(), [r#""x""#]);
+        assert_eq!(eval("[.[] | (1 / .)?]", "[1, 0, 2]").unwrap(), ["[1,0.5]"]);
+        assert_eq!(eval(". as $x | $x + 1", "1").unwrap(), ["2"]);
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(eval("def f(g): [g, g]; f(. + 1)", "1").unwrap(), ["[2,2]"]);
+        assert_eq!(
+            eval(
+                "def fac: if . <= 1 then 1 else . * (. - 1 | fac) end; fac",
+                "5"
+            )
+            .unwrap(),
+            ["120"]
+        );
+        assert_eq!(eval("def f($a; $b): $a + $b; f(1; 2)", "0").unwrap(), ["3"]);
+        assert_eq!(
+            eval("undefined_function", "0").unwrap_err().to_string(),
+            "Function \"undefined_function/0\" is not defined."
+        );
+    }
+
+    #[test]
+    fn test_number_arithmetic() {
+        assert_eq!(eval("1_u8 + 2", "0").unwrap(), ["3_u8"]);
+        assert_eq!(eval("7 / 2, 6 / 2", "0").unwrap(), ["3.5", "3"]);
+        assert_eq!(eval("1.5_f32 * 2", "0").unwrap(), ["3.0_f32"]);
+
+        // the overflowed result widens to `i64`, `u64` and then `f64`
+        assert_eq!(eval("1_u8 + 255_u8", "0").unwrap(), ["256_i64"]);
+        assert_eq!(eval("-128_i8 - 1_i8", "0").unwrap(), ["-129_i64"]);
+        assert_eq!(eval("2147483647 + 1", "0").unwrap(), ["2147483648_i64"]);
+        assert_eq!(
+            eval("9223372036854775807_i64 + 1_i64", "0").unwrap(),
+            ["9223372036854775808_u64"]
+        );
+        assert_eq!(
+            eval("18446744073709551615_u64 + 1_u64", "0").unwrap(),
+            ["1.8446744073709552e19"]
+        );
+        assert_eq!(
+            eval("18446744073709551615_u64 * 18446744073709551615_u64", "0").unwrap(),
+            ["3.402823669209385e38"]
+        );
+        assert_eq!(eval("0_u8 - 1_u8", "0").unwrap(), ["-1_i64"]);
+        assert_eq!(eval("-(-128_i8)", "0").unwrap(), ["128_i64"]);
+    }
+
+    #[test]
+    fn test_integer_limits() {
+        assert_eq!(
+            eval("18446744073709551615, 18446744073709551615_u64", "0").unwrap(),
+            ["18446744073709551615_u64", "18446744073709551615_u64"]
+        );
+        assert_eq!(
+            eval("-9223372036854775808, -9223372036854775808_i64", "0").unwrap(),
+            ["-9223372036854775808_i64", "-9223372036854775808_i64"]
+        );
+        assert_eq!(
+            eval("-128_i8, -32768_i16, -2147483648_i32", "0").unwrap(),
+            ["-128_i8", "-32768_i16", "-2147483648"]
+        );
+        assert_eq!(eval("[1, -128_i8] | .[1]", "0").unwrap(), ["-128_i8"]);
+
+        // the magnitude of the minimum is invalid without `-`
+        assert!(matches!(
+            eval("128_i8", "0"),
+            Err(Error::MessageWithLocation(message, _))
+                if message == "Number \"128\" is out of the range of \"i8\"."
+        ));
+        assert!(eval("-(9223372036854775808_i64)", "0").is_err());
+        assert!(eval("1 - 9223372036854775808_i64", "0").is_err());
+        assert!(eval("-129_i8", "0").is_err());
+        assert!(eval("18446744073709551616", "0").is_err());
+    }
+
+    #[test]
+    fn test_repeat_string() {
+        assert_eq!(eval(r#""ab" * 3"#, "0").unwrap(), [r#""ababab""#]);
+        assert_eq!(eval(r#""ab" * 0"#, "0").unwrap(), ["Option::None"]);
+        assert_eq!(
+            eval(r#""abc" * 1e18"#, "0").unwrap_err().to_string(),
+            "Cannot repeat the string 1000000000000000000 times, the result exceeds 1073741824 bytes."
+        );
+        assert_eq!(
+            eval(r#"try ("abc" * 1e18) catch "too long""#, "0").unwrap(),
+            [r#""too long""#]
+        );
+    }
+
+    #[test]
+    fn test_signed_zero_equality() {
+        assert_eq!(eval("0.0 == -0.0", "0").unwrap(), ["true"]);
+        assert_eq!(
+            eval("[0.0, -0.0, 0] | unique | length", "0").unwrap(),
+            ["1"]
+        );
+        assert_eq!(
+            eval("[-0.0, 0.0] | group_by(.) | length", "0").unwrap(),
+            ["1"]
+        );
+        assert_eq!(eval("{a: -0.0} == {a: 0.0}", "0").unwrap(), ["true"]);
+    }
+
+    // `input` has the side effect, so the cached results can be told apart
+    // from the evaluated ones.
+    fn eval_memo(query: &str, input: &str) -> String {
+        let input = parse_input(input, None, &ParseOptions::unlimited()).unwrap();
+        let inputs = vec![Value::from(10), Value::from(20), Value::from(30)];
+        let results = Query::compile(query)
+            .unwrap()
+            .with_inputs(inputs)
+            .run_value(input)
+            .unwrap();
+        results[0].to_string()
+    }
+
+    #[test]
+    fn test_memo() {
+        assert_eq!(eval_memo("[.[] | memo(input)]", "[1, 2, 1]"), "[10,20,10]");
+
+        // the types of the numbers are significant
+        assert_eq!(
+            eval_memo("[.[] | memo(input)]", "[1, 1_u8, 1]"),
+            "[10,20,10]"
+        );
+
+        // the environment of `f` is rebuilt by each call, but `memo(input)`
+        // does not depend on it.
+        assert_eq!(
+            eval_memo("def f(g): memo(input); [.[] | f(.)]", "[1, 2, 1]"),
+            "[10,20,10]"
+        );
+        assert_eq!(
+            eval_memo("def f: memo(input); def h: f; [.[] | h]", "[1, 1]"),
+            "[10,10]"
+        );
+
+        // the filter arguments and the variables are captured
+        assert_eq!(
+            eval_memo("def f(g): memo(g | input); [.[] | f(.)]", "[1, 1]"),
+            "[10,20]"
+        );
+        assert_eq!(
+            eval_memo("[.[] as $x | 0 | memo($x, input)]", "[1, 1]"),
+            "[1,10,1,20]"
+        );
+
+        // the recursive functions
+        assert_eq!(
+            eval(
+                "def fib: if . < 2 then . else (. - 1 | memo(fib)) + (. - 2 | memo(fib)) end; fib",
+                "30"
+            )
+            .unwrap(),
+            ["832040"]
+        );
+    }
+
+    #[test]
+    fn test_assignment() {
+        let doc = r#"{meta: {version: "1.0"}, items: [{price: 10}, {price: 20}]}"#;
+        assert_eq!(
+            eval(r#".meta.version = "2.0" | .meta"#, doc).unwrap(),
+            [r#"{version:"2.0"}"#]
+        );
+        assert_eq!(
+            eval(".items[].price |= . * 2 | [.items[].price]", doc).unwrap(),
+            ["[20,40]"]
+        );
+        assert_eq!(
+            eval(".items[].price += 1 | [.items[].price]", doc).unwrap(),
+            ["[11,21]"]
+        );
+        assert_eq!(
+            eval(".items[0].price -= 3 | .items[0]", doc).unwrap(),
+            ["{price:7}"]
+        );
+
+        // the right side is evaluated with the input of the whole expression
+        assert_eq!(eval(".a = .b", "{b: 3}").unwrap(), ["{b:3,a:3}"]);
+        assert_eq!(eval(".a = (1, 2) | .a", "{}").unwrap(), ["1", "2"]);
+        assert_eq!(eval(".a //= 5 | .a", "{}").unwrap(), ["5"]);
+        assert_eq!(eval(".x.y = 1", "Option::None").unwrap(), ["{x:{y:1}}"]);
+
+        // the paths which are updated to `empty` are deleted
+        assert_eq!(eval(".[] |= empty", "[1, 2, 3]").unwrap(), ["[]"]);
+        assert_eq!(
+            eval(r#"(.. | select(type == "number")) |= . + 1"#, "[1, [2]]").unwrap(),
+            ["[2,[3]]"]
+        );
+        assert_eq!(
+            eval("1 = 2", "0").unwrap_err().to_string(),
+            "Invalid path expression with result 1."
+        );
+    }
+
+    #[test]
+    fn test_variant_construction() {
+        assert_eq!(
+            eval("Option::Some(.id)", "{id: 5}").unwrap(),
+            ["Option::Some(5)"]
+        );
+        assert_eq!(
+            eval("Shape::Circle{radius: .r}", "{r: 2}").unwrap(),
+            ["Shape::Circle{radius:2}"]
+        );
+        assert_eq!(
+            eval("[Point::P(.x, .y[])]", "{x: 1, y: [2, 3]}").unwrap(),
+            ["[Point::P(1,2),Point::P(1,3)]"]
+        );
+        assert_eq!(
+            eval("Color::Red | variant_name", "0").unwrap(),
+            [r#""Color::Red""#]
+        );
+        assert_eq!(eval("Option::None == null", "0").unwrap(), ["true"]);
+        assert_eq!(eval("Option::Some(.a).unwrap()", "{a: 7}").unwrap(), ["7"]);
+        assert_eq!(
+            eval("[.[] | Option::Some(.)] | map(unwrap)", "[1, 2]").unwrap(),
+            ["[1,2]"]
+        );
+
+        assert!(eval("Option::", "0").is_err());
+    }
+
+    #[test]
+    fn test_tuple_elements() {
+        let doc = r#"("a", (1, 2), 3)"#;
+        assert_eq!(eval(".0, .1.1, .[-1]", doc).unwrap(), [r#""a""#, "2", "3"]);
+        assert_eq!(eval(".[1:]", doc).unwrap(), ["((1,2),3)"]);
+        assert_eq!(eval(".1.0 = 9 | .1", doc).unwrap(), ["(9,2)"]);
+        assert_eq!(
+            eval("[.0, .1.5]", "[1.5, [0]]").unwrap(),
+            ["[1.5,Option::None]"]
+        );
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let doc = r#"("a", (1, 2), 3)"#;
+        assert_eq!(
+            eval(". as [$a, ($b, $c)] | [$a, $b, $c]", doc).unwrap(),
+            [r#"["a",1,2]"#]
+        );
+        assert_eq!(
+            eval(". as [$a, $b, $c, $d] | $d", doc).unwrap(),
+            ["Option::None"]
+        );
+        assert_eq!(
+            eval(
+                "[.[] as [$k, $v] | {($k): $v}] | add",
+                r#"[["a", 1], ["b", 2]]"#
+            )
+            .unwrap(),
+            ["{a:1,b:2}"]
+        );
+        assert_eq!(
+            eval(
+                "reduce .[] as [$x, $y] (0; . + $x * $y)",
+                "[[1, 2], [3, 4]]"
+            )
+            .unwrap(),
+            ["14"]
+        );
+        assert_eq!(
+            eval("try (. as [$a] | $a) catch .", "1").unwrap(),
+            [r#""Cannot destructure number into elements.""#]
+        );
+    }
+
+    #[test]
+    fn test_update_shared_values() {
+        // the variable keeps the value before the update
+        assert_eq!(
+            eval(
+                ". as $orig | .a.b[1] |= . + 1 | [$orig, .]",
+                "{a: {b: [1, 2]}, c: [3]}"
+            )
+            .unwrap(),
+            ["[{a:{b:[1,2]},c:[3]},{a:{b:[1,3]},c:[3]}]"]
+        );
+        assert_eq!(
+            eval("[.a.n, (.a.n += 1 | .a.n), .a.n]", "{a: {n: 0}}").unwrap(),
+            ["[0,1,0]"]
+        );
+    }
+
+    #[test]
+    fn test_resource_limits() {
+        let run = |query: &str, limits: Limits| match Query::compile(query)
+            .unwrap()
+            .with_limits(limits)
+            .run_value(Value::null())
+        {
+            Ok(results) => results.iter().map(|v| v.to_string()).collect(),
+            Err(e) => vec![format!("{} (fatal: {})", e, e.is_fatal())],
+        };
+        let depth = Limits {
+            max_depth: Some(50),
+            ..Limits::default()
+        };
+        let output = Limits {
+            max_output: Some(3),
+            ..Limits::default()
+        };
+
+        // the limits cannot be caught by `try`
+        assert_eq!(
+            run("def f: f + 1; try f catch 0", depth),
+            ["The depth of the function calls exceeds the limit of 50. (fatal: true)"]
+        );
+        assert_eq!(
+            run("def f(n): if n == 0 then 0 else f(n - 1) end; f(5)", depth),
+            ["0"]
+        );
+        assert_eq!(
+            run("range(10)", output),
+            ["The number of results exceeds the limit of 3. (fatal: true)"]
+        );
+        assert_eq!(run("range(3)", output), ["0", "1", "2"]);
+        assert_eq!(
+            run(
+                "reduce range(100000000) as $i (0; . + $i)",
+                Limits {
+                    timeout: Some(Duration::from_millis(50)),
+                    ..Limits::default()
+                }
+            ),
+            ["The evaluation exceeds the time limit of 50ms. (fatal: true)"]
+        );
+    }
+
+    #[test]
+    fn test_query_variables() {
+        let query = Query::compile("[$name, $n + 1]")
+            .unwrap()
+            .with_variables(vec![
+                ("name".to_owned(), Value::String("a".to_owned())),
+                ("n".to_owned(), Value::from(1)),
+            ]);
+        let results: Vec<String> = query
+            .run_value(Value::null())
+            .unwrap()
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(results, ["[\"a\",2]"]);
+
+        // the variables of the query shadow the predefined ones
+        let query = Query::compile(". as $x | $x")
+            .unwrap()
+            .with_variables(vec![("x".to_owned(), Value::from(1))]);
+        let results = query.run_value(Value::from(2)).unwrap();
+        assert_eq!(results, [Value::from(2)]);
+    }
+
+    #[test]
+    fn test_stream_alternative() {
+        assert_eq!(eval("[(null, 1, false, 2) // 3]", "{}").unwrap(), ["[1,2]"]);
+        assert_eq!(eval("[(null, false) // 3]", "{}").unwrap(), ["[3]"]);
+        assert_eq!(eval("[error(\"x\") // 3]", "{}").unwrap(), ["[3]"]);
+
+        // the left side stops as soon as the receiver has enough results
+        assert_eq!(
+            eval("[limit(2; range(1; 1e18) // 0)]", "{}").unwrap(),
+            ["[1,2]"]
+        );
+
+        // the errors raised by the receiver are not suppressed
+        assert_eq!(
+            eval("try ((1, 2) // 3 | error) catch .", "{}").unwrap(),
+            ["1"]
+        );
+    }
+
+    #[test]
+    fn test_deep_merge() {
+        assert_eq!(
+            eval("{a: {b: 1, c: 2}, d: 1} * {a: {b: 3}, d: {e: 1}}", "{}").unwrap(),
+            ["{a:{b:3,c:2},d:{e:1}}"]
+        );
+        assert_eq!(eval("{a: 1} * {a: {b: 1}}", "{}").unwrap(), ["{a:{b:1}}"]);
+        assert_eq!(
+            eval("[{a: {x: 1}}, {a: {y: 2}}, {b: 3}] | deepmerge", "{}").unwrap(),
+            ["{a:{x:1,y:2},b:3}"]
+        );
+        assert_eq!(eval("[] | deepmerge", "{}").unwrap(), ["{}"]);
+    }
+}
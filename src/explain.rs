@@ -0,0 +1,473 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The plan of a query without running it, i.e. `aq explain QUERY`.
+//
+// The plan consists of the imports, the stages of the top-level pipeline,
+// the syntax tree, and the functions and variables the query uses, e.g.
+//
+// ```
+// Stages:
+//   1. .users[]
+//   2. select(.age > 30)
+//
+// Tree:
+// pipe |
+// ├─ iterate .[]
+// │  └─ index
+// ...
+// ```
+//
+// The calls are classified as the functions defined in the query, the
+// functions of the prelude, the native builtins or the module functions,
+// and the variables as bound (by `as`, `reduce` etc.) or free (e.g. the
+// ones of `--arg`).
+
+use std::collections::BTreeSet;
+
+use crate::{
+    ast::{AssignOperator, BinaryOperator, Expression, InterpolationPart, Pattern, VariantPayload},
+    error::Error,
+    parser::{parse_definitions_from_str, parse_query_from_str},
+    pipeline::split_pipeline,
+    query::PRELUDE,
+};
+
+/// The text of the plan of the query.
+pub fn explain_query(source: &str) -> Result<String, Error> {
+    let (imports, expression) = parse_query_from_str(source)?;
+    let prelude: BTreeSet<(String, usize)> = parse_definitions_from_str(PRELUDE)?
+        .into_iter()
+        .map(|d| (d.name, d.params.len()))
+        .collect();
+
+    let mut text = String::new();
+    if !imports.is_empty() {
+        text.push_str("Imports:\n");
+        for import in &imports {
+            text.push_str(&format!("  \"{}\" as {}\n", import.path, import.alias));
+        }
+        text.push('\n');
+    }
+
+    text.push_str("Stages:\n");
+    for (idx, stage) in split_pipeline(source)?.iter().enumerate() {
+        text.push_str(&format!("  {}. {}\n", idx + 1, stage));
+    }
+
+    let mut planner = Planner {
+        prelude,
+        functions: vec![],
+        variables: vec![],
+        calls: BTreeSet::new(),
+        free_variables: BTreeSet::new(),
+        bound_variables: BTreeSet::new(),
+        lines: vec![],
+    };
+    planner.visit(&expression, "", "", true);
+
+    text.push_str("\nTree:\n");
+    for line in &planner.lines {
+        text.push_str(line);
+        text.push('\n');
+    }
+
+    let sections = [
+        ("Functions", planner.calls.into_iter().collect::<Vec<_>>()),
+        (
+            "Bound variables",
+            planner.bound_variables.into_iter().collect(),
+        ),
+        (
+            "Free variables",
+            planner.free_variables.into_iter().collect(),
+        ),
+    ];
+    for (title, items) in sections {
+        if !items.is_empty() {
+            text.push_str(&format!("\n{}:\n", title));
+            for item in items {
+                text.push_str(&format!("  {}\n", item));
+            }
+        }
+    }
+    Ok(text)
+}
+
+struct Planner {
+    prelude: BTreeSet<(String, usize)>,
+
+    // the functions in scope, i.e. the names, the arities and whether
+    // they are the parameters, and the variables in scope.
+    functions: Vec<(String, usize, bool)>,
+    variables: Vec<String>,
+
+    calls: BTreeSet<String>,
+    free_variables: BTreeSet<String>,
+    bound_variables: BTreeSet<String>,
+    lines: Vec<String>,
+}
+
+impl Planner {
+    // add the line of the node, and visit the children, the `prefix` is
+    // the indentation of the node, and the `role` is the name of the
+    // child of its parent, e.g. `then:` of `if`.
+    fn visit(&mut self, expression: &Expression, prefix: &str, role: &str, last: bool) {
+        let (label, children) = self.node(expression);
+        let is_root = self.lines.is_empty();
+        let branch = match (is_root, last) {
+            (true, _) => "",
+            (false, true) => "└─ ",
+            (false, false) => "├─ ",
+        };
+        let role = if role.is_empty() {
+            String::new()
+        } else {
+            format!("{}: ", role)
+        };
+        self.lines
+            .push(format!("{}{}{}{}", prefix, branch, role, label));
+
+        let child_prefix = match (is_root, last) {
+            (true, _) => String::new(),
+            (false, true) => format!("{}   ", prefix),
+            (false, false) => format!("{}│  ", prefix),
+        };
+
+        // the scopes of the definitions and bindings
+        let (functions, variables) = (self.functions.len(), self.variables.len());
+        let last_node = children
+            .iter()
+            .rposition(|c| matches!(c, Child::Node(..)))
+            .unwrap_or(0);
+        for (idx, child) in children.into_iter().enumerate() {
+            match child {
+                Child::Node(role, expression) => {
+                    self.visit(expression, &child_prefix, role, idx == last_node)
+                }
+                Child::Function(name, arity) => self.functions.push((name, arity, false)),
+                Child::Parameter(name) => self.functions.push((name, 0, true)),
+                Child::EndParameters(count) => {
+                    self.functions.truncate(self.functions.len() - count)
+                }
+                Child::Variables(names) => {
+                    for name in names {
+                        self.bound_variables.insert(format!("${}", name));
+                        self.variables.push(name);
+                    }
+                }
+            }
+        }
+        self.functions.truncate(functions);
+        self.variables.truncate(variables);
+    }
+
+    // the label and the children of the node, the children also
+    // include the scope changes before visiting the following children.
+    fn node<'a>(&mut self, expression: &'a Expression) -> (String, Vec<Child<'a>>) {
+        use Child::Node;
+
+        match expression {
+            Expression::Identity => (".".to_owned(), vec![]),
+            Expression::RecurseDefault => ("..".to_owned(), vec![]),
+            Expression::Literal(v) => (format!("literal {}", v), vec![]),
+            Expression::Interpolation(parts) => (
+                "string interpolation".to_owned(),
+                interpolation_children(parts),
+            ),
+            Expression::Format {
+                name,
+                argument,
+                template,
+            } => {
+                let mut children: Vec<Child> = argument
+                    .iter()
+                    .map(|a| Node("argument", a.as_ref()))
+                    .collect();
+                if let Some(parts) = template {
+                    children.extend(interpolation_children(parts));
+                }
+                (format!("format @{}", name), children)
+            }
+            Expression::Index(target, key) => (
+                "index".to_owned(),
+                vec![Node("target", target), Node("key", key)],
+            ),
+            Expression::Slice(target, from, to) => {
+                let mut children = vec![Node("target", target.as_ref())];
+                children.extend(from.iter().map(|e| Node("from", e.as_ref())));
+                children.extend(to.iter().map(|e| Node("to", e.as_ref())));
+                ("slice".to_owned(), children)
+            }
+            Expression::Iterate(target) => ("iterate .[]".to_owned(), vec![Node("", target)]),
+            Expression::Try(body, handler) => {
+                let mut children = vec![Node("body", body.as_ref())];
+                children.extend(handler.iter().map(|e| Node("catch", e.as_ref())));
+                ("try".to_owned(), children)
+            }
+            Expression::Pipe(left, right) => {
+                ("pipe |".to_owned(), vec![Node("", left), Node("", right)])
+            }
+            Expression::Comma(left, right) => {
+                ("comma ,".to_owned(), vec![Node("", left), Node("", right)])
+            }
+            Expression::Negate(inner) => ("negate -".to_owned(), vec![Node("", inner)]),
+            Expression::Binary(operator, left, right) => (
+                format!("binary {}", binary_symbol(*operator)),
+                vec![Node("", left), Node("", right)],
+            ),
+            Expression::And(left, right) => {
+                ("and".to_owned(), vec![Node("", left), Node("", right)])
+            }
+            Expression::Or(left, right) => ("or".to_owned(), vec![Node("", left), Node("", right)]),
+            Expression::Alternative(left, right) => (
+                "alternative //".to_owned(),
+                vec![Node("", left), Node("", right)],
+            ),
+            Expression::Assign(operator, path, value) => {
+                let symbol = match operator {
+                    AssignOperator::Set => "=".to_owned(),
+                    AssignOperator::Update => "|=".to_owned(),
+                    AssignOperator::Arithmetic(op) => format!("{}=", binary_symbol(*op)),
+                    AssignOperator::Alternative => "//=".to_owned(),
+                };
+                (
+                    format!("assign {}", symbol),
+                    vec![Node("path", path), Node("value", value)],
+                )
+            }
+            Expression::List(inner) => (
+                "list [...]".to_owned(),
+                inner.iter().map(|e| Node("", e.as_ref())).collect(),
+            ),
+            Expression::Object(entries) => (
+                "object {...}".to_owned(),
+                entries
+                    .iter()
+                    .flat_map(|(k, v)| [Node("key", k), Node("value", v)])
+                    .collect(),
+            ),
+            Expression::Variant {
+                type_name,
+                member_name,
+                payload,
+            } => {
+                let children = match payload {
+                    VariantPayload::Empty => vec![],
+                    VariantPayload::Value(e) | VariantPayload::Object(e) => {
+                        vec![Node("", e.as_ref())]
+                    }
+                    VariantPayload::Tuple(items) => items.iter().map(|e| Node("", e)).collect(),
+                };
+                (format!("variant {}::{}", type_name, member_name), children)
+            }
+            Expression::If(branches, otherwise) => {
+                let mut children = vec![];
+                for (idx, (condition, body)) in branches.iter().enumerate() {
+                    children.push(Node(if idx == 0 { "if" } else { "elif" }, condition));
+                    children.push(Node("then", body));
+                }
+                children.extend(otherwise.iter().map(|e| Node("else", e.as_ref())));
+                ("if".to_owned(), children)
+            }
+            Expression::Reduce {
+                source,
+                pattern,
+                init,
+                update,
+            } => (
+                format!("reduce as {}", pattern_text(pattern)),
+                vec![
+                    Node("source", source),
+                    Node("init", init),
+                    Child::Variables(pattern_names(pattern)),
+                    Node("update", update),
+                ],
+            ),
+            Expression::Foreach {
+                source,
+                pattern,
+                init,
+                update,
+                extract,
+            } => {
+                let mut children = vec![
+                    Node("source", source.as_ref()),
+                    Node("init", init.as_ref()),
+                    Child::Variables(pattern_names(pattern)),
+                    Node("update", update.as_ref()),
+                ];
+                children.extend(extract.iter().map(|e| Node("extract", e.as_ref())));
+                (format!("foreach as {}", pattern_text(pattern)), children)
+            }
+            Expression::Binding {
+                source,
+                pattern,
+                body,
+            } => (
+                format!("binding as {}", pattern_text(pattern)),
+                vec![
+                    Node("source", source),
+                    Child::Variables(pattern_names(pattern)),
+                    Node("body", body),
+                ],
+            ),
+            Expression::FunctionDefinition(definition, rest) => {
+                let signature = if definition.params.is_empty() {
+                    definition.name.clone()
+                } else {
+                    format!("{}({})", definition.name, definition.params.join("; "))
+                };
+                let arity = definition.params.len();
+
+                // the function is visible in its body (recursion) and the rest,
+                // the parameters are only visible in the body.
+                let mut children = vec![Child::Function(definition.name.clone(), arity)];
+                children.extend(
+                    definition
+                        .params
+                        .iter()
+                        .map(|p| Child::Parameter(p.clone())),
+                );
+                children.push(Node("body", &definition.body));
+                children.push(Child::EndParameters(arity));
+                children.push(Node("rest", rest));
+                (format!("def {}", signature), children)
+            }
+            Expression::Call(name, args) => {
+                let arity = args.len();
+                let scoped = self
+                    .functions
+                    .iter()
+                    .rev()
+                    .find(|(n, a, _)| n == name && *a == arity);
+                let kind = if let Some((_, _, parameter)) = scoped {
+                    if *parameter {
+                        "parameter"
+                    } else {
+                        "defined in the query"
+                    }
+                } else if name.contains("::") {
+                    "module"
+                } else if self.prelude.contains(&(name.clone(), arity)) {
+                    "prelude"
+                } else {
+                    "builtin"
+                };
+                self.calls.insert(format!("{}/{} ({})", name, arity, kind));
+                (
+                    format!("call {}/{} ({})", name, arity, kind),
+                    args.iter().map(|a| Node("", a)).collect(),
+                )
+            }
+            Expression::Variable(name) => {
+                if !self.variables.contains(name) {
+                    self.free_variables.insert(format!("${}", name));
+                }
+                (format!("variable ${}", name), vec![])
+            }
+        }
+    }
+}
+
+enum Child<'a> {
+    Node(&'static str, &'a Expression),
+
+    // the function or the parameter which is in scope for the following children.
+    Function(String, usize),
+    Parameter(String),
+    EndParameters(usize),
+    Variables(Vec<String>),
+}
+
+fn interpolation_children(parts: &[InterpolationPart]) -> Vec<Child<'_>> {
+    parts
+        .iter()
+        .filter_map(|p| match p {
+            InterpolationPart::Expression(e) => Some(Child::Node("", e)),
+            InterpolationPart::Literal(_) => None,
+        })
+        .collect()
+}
+
+fn binary_symbol(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Remainder => "%",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessOrEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterOrEqual => ">=",
+    }
+}
+
+fn pattern_text(pattern: &Pattern) -> String {
+    match pattern {
+        Pattern::Variable(name) => format!("${}", name),
+        Pattern::Elements(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(pattern_text)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    }
+}
+
+fn pattern_names(pattern: &Pattern) -> Vec<String> {
+    match pattern {
+        Pattern::Variable(name) => vec![name.clone()],
+        Pattern::Elements(items) => items.iter().flat_map(pattern_names).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::explain_query;
+
+    #[test]
+    fn test_explain_query() {
+        let text =
+            explain_query("def f(x): x + 1; .items[] as $i | select($i > 1) | f($i) | $n").unwrap();
+        assert!(text.contains("call select/1 (prelude)"));
+        assert!(text.contains("call f/1 (defined in the query)"));
+        assert!(text.contains("call x/0 (parameter)"));
+        assert!(text.contains("binary +"));
+        assert!(text.contains("\nBound variables:\n  $i\n"));
+        assert!(text.contains("\nFree variables:\n  $n\n"));
+
+        assert_eq!(
+            explain_query(".users[] | .name").unwrap(),
+            "Stages:
+  1. .users[]
+  2. .name
+
+Tree:
+pipe |
+├─ iterate .[]
+│  └─ index
+│     ├─ target: .
+│     └─ key: literal \"users\"
+└─ index
+   ├─ target: .
+   └─ key: literal \"name\"
+"
+        );
+    }
+
+    #[test]
+    fn test_invalid_query() {
+        assert!(explain_query(".a |").is_err());
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The location of the runtime errors, i.e. the path of the failing value
+// in the input document and the stage of the query, e.g.
+//
+// ```
+// $ aq '.users[] | .name' users.ason
+// Cannot index number with "name" (at the path .users[17].name of the input,
+// in the query stage ".name").
+// ```
+//
+// (see `Query::with_error_paths()`). The evaluator keeps the keys of the
+// values which are passed on by `.name`, `.[i]` and `.[]` (the trail), and
+// the trail is checked against the input document when the error is
+// reported, the path is omitted if the failing value is computed,
+// e.g. `{n: .name} | .n.x`.
+
+use crate::{
+    error::Error,
+    path::{getpath, path_to_string},
+    value::Value,
+};
+
+/// Where an error of the evaluation is raised.
+pub(crate) struct ErrorContext {
+    pub message: String,
+
+    // the trail to the failing value, and the key which
+    // can not be applied to the value (if any).
+    pub path: Vec<Value>,
+    pub key: Option<Value>,
+    pub subject: Value,
+
+    // the unparsed expression which raises the error.
+    pub stage: String,
+}
+
+impl ErrorContext {
+    /// Add the location to the error, the error is returned as is
+    /// if it is not the one the context is recorded for.
+    pub fn locate(self, error: Error, root: &Value) -> Error {
+        let message = match &error {
+            Error::Message(message) if *message == self.message => message,
+            _ => return error,
+        };
+
+        let message = message.trim_end_matches('.');
+        let stage = self.stage.replace('\n', " ");
+        match getpath(root, &self.path) {
+            Ok(value) if value == self.subject => {
+                let mut path = self.path;
+                path.extend(self.key);
+                Error::Message(format!(
+                    "{} (at the path {} of the input, in the query stage \"{}\").",
+                    message,
+                    path_to_string(&path),
+                    stage
+                ))
+            }
+            _ => Error::Message(format!("{} (in the query stage \"{}\").", message, stage)),
+        }
+    }
+}
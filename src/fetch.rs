@@ -0,0 +1,131 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The HTTP(S) URLs as the inputs, e.g. `aq '.status' https://example.com/health.ason`.
+//
+// The body of the response is the input, the requests are sent with the
+// headers of `--header`, and a response with a status other than 2xx
+// is an error.
+
+use std::{io::Read, time::Duration};
+
+use crate::error::Error;
+
+// the time of establishing the connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct HttpOptions {
+    headers: Vec<(String, String)>,
+
+    // the time of the whole request, including reading the body.
+    timeout: Duration,
+}
+
+impl HttpOptions {
+    /// The headers are `NAME: VALUE`, e.g. `Authorization: Bearer TOKEN`.
+    pub fn new(headers: &[String], timeout_seconds: u64) -> Result<Self, Error> {
+        let headers = headers
+            .iter()
+            .map(|h| match h.split_once(':') {
+                Some((name, value)) if !name.trim().is_empty() => {
+                    Ok((name.trim().to_owned(), value.trim().to_owned()))
+                }
+                _ => Err(Error::Message(format!(
+                    "The header \"{}\" expects the form \"NAME: VALUE\".",
+                    h
+                ))),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Self {
+            headers,
+            timeout: Duration::from_secs(timeout_seconds),
+        })
+    }
+}
+
+/// Whether the input argument is an HTTP(S) URL instead of a file name.
+pub fn is_url(input: &str) -> bool {
+    let lower = input.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// The reader of the body of the response.
+pub fn open_url(url: &str, options: &HttpOptions) -> Result<Box<dyn Read + Send>, Error> {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout(options.timeout)
+        .build();
+    let mut request = agent.get(url);
+    for (name, value) in &options.headers {
+        request = request.set(name, value);
+    }
+
+    match request.call() {
+        Ok(response) => Ok(response.into_reader()),
+        Err(ureq::Error::Status(code, response)) => Err(Error::Message(format!(
+            "Fail to fetch \"{}\": {} {}",
+            url,
+            code,
+            response.status_text()
+        ))),
+        Err(e) => Err(Error::Message(format!("Fail to fetch \"{}\": {}", url, e))),
+    }
+}
+
+/// The body of the response.
+pub fn fetch_url(url: &str, options: &HttpOptions) -> Result<Vec<u8>, Error> {
+    let mut body = vec![];
+    open_url(url, options)?
+        .read_to_end(&mut body)
+        .map_err(|e| Error::Message(format!("Fail to fetch \"{}\": {}", url, e)))?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::{is_url, HttpOptions};
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://example.com/health.ason"));
+        assert!(is_url("HTTP://example.com"));
+        assert!(!is_url("data.ason"));
+        assert!(!is_url("ftp://example.com/a.ason"));
+    }
+
+    #[test]
+    fn test_http_options() {
+        let options = HttpOptions::new(
+            &[
+                "Authorization: Bearer a:b".to_owned(),
+                " Accept :text/plain".to_owned(),
+            ],
+            5,
+        )
+        .unwrap();
+        assert_eq!(
+            options.headers,
+            [
+                ("Authorization".to_owned(), "Bearer a:b".to_owned()),
+                ("Accept".to_owned(), "text/plain".to_owned())
+            ]
+        );
+        assert_eq!(options.timeout, Duration::from_secs(5));
+
+        assert!(HttpOptions::new(&["novalue".to_owned()], 5).is_err());
+        match HttpOptions::new(&[": x".to_owned()], 5) {
+            Ok(_) => panic!("the header lacks the name"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "The header \": x\" expects the form \"NAME: VALUE\"."
+            ),
+        }
+    }
+}
@@ -0,0 +1,360 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The C interface, for embedding the query engine in the programs of
+// C, C++, Go, etc., the declarations are in `include/ason_query.h`.
+//
+// The convention of the errors: each function which can fail returns an
+// `AqStatus`, `AQ_OK` (i.e. 0) on success, and on failure the message
+// of the error is written to `*error` (if `error` is not NULL), which
+// is freed by `aq_free_string`. The output arguments are written only
+// on success.
+//
+// The strings are NUL-terminated UTF-8, and the panics of the engine
+// are caught and reported as `AQ_INTERNAL_ERROR`.
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::{
+    convert::{parse_documents, write_text, Format},
+    input::ParseOptions,
+    query::Query,
+};
+
+pub type AqStatus = i32;
+
+pub const AQ_OK: AqStatus = 0;
+
+/// A NULL pointer, a string which is not valid UTF-8, or an unknown format.
+pub const AQ_INVALID_ARGUMENT: AqStatus = 1;
+
+/// The query cannot be compiled.
+pub const AQ_QUERY_ERROR: AqStatus = 2;
+
+/// The document cannot be parsed.
+pub const AQ_DOCUMENT_ERROR: AqStatus = 3;
+
+/// The evaluation fails, or a result cannot be written in the format.
+pub const AQ_EVALUATION_ERROR: AqStatus = 4;
+
+pub const AQ_INTERNAL_ERROR: AqStatus = 5;
+
+/// A compiled query, freed by `aq_free_query`.
+pub struct AqQuery {
+    query: Query,
+}
+
+/// The results of running a query, freed by `aq_free_result`.
+pub struct AqResult {
+    values: Vec<CString>,
+}
+
+struct Failure(AqStatus, String);
+
+// run the function, the failure (or the panic) is reported to `error`.
+unsafe fn guard(error: *mut *mut c_char, f: impl FnOnce() -> Result<(), Failure>) -> AqStatus {
+    let outcome = panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|_| {
+        Err(Failure(
+            AQ_INTERNAL_ERROR,
+            "The query engine panicked.".to_owned(),
+        ))
+    });
+    match outcome {
+        Ok(()) => AQ_OK,
+        Err(Failure(status, message)) => {
+            if !error.is_null() {
+                // the messages do not contain NUL
+                *error = CString::new(message).unwrap_or_default().into_raw();
+            }
+            status
+        }
+    }
+}
+
+unsafe fn read_str<'a>(text: *const c_char, name: &str) -> Result<&'a str, Failure> {
+    if text.is_null() {
+        return Err(Failure(
+            AQ_INVALID_ARGUMENT,
+            format!("The argument \"{}\" is NULL.", name),
+        ));
+    }
+    CStr::from_ptr(text).to_str().map_err(|_| {
+        Failure(
+            AQ_INVALID_ARGUMENT,
+            format!("The argument \"{}\" is not valid UTF-8.", name),
+        )
+    })
+}
+
+/// Compile the query, the compiled query is written to `*query`.
+///
+/// # Safety
+///
+/// `source` must be a NUL-terminated string, `query` must be valid
+/// for writing, and `error` must be NULL or valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn aq_compile(
+    source: *const c_char,
+    query: *mut *mut AqQuery,
+    error: *mut *mut c_char,
+) -> AqStatus {
+    guard(error, || {
+        let source = read_str(source, "source")?;
+        if query.is_null() {
+            return Err(Failure(
+                AQ_INVALID_ARGUMENT,
+                "The argument \"query\" is NULL.".to_owned(),
+            ));
+        }
+        let compiled =
+            Query::compile(source).map_err(|e| Failure(AQ_QUERY_ERROR, e.to_string()))?;
+        *query = Box::into_raw(Box::new(AqQuery { query: compiled }));
+        Ok(())
+    })
+}
+
+/// Run the query on the document, the results are written to `*result`.
+///
+/// `format` is the format of the document, e.g. "json", or NULL for ASON,
+/// and the results are written in the same format (JSON for JSON5 and JSONC).
+///
+/// # Safety
+///
+/// `query` must be returned by `aq_compile`, `document` and `format`
+/// (if it is not NULL) must be NUL-terminated strings, `result` must be
+/// valid for writing, and `error` must be NULL or valid for writing.
+#[no_mangle]
+pub unsafe extern "C" fn aq_run_on_string(
+    query: *const AqQuery,
+    document: *const c_char,
+    format: *const c_char,
+    result: *mut *mut AqResult,
+    error: *mut *mut c_char,
+) -> AqStatus {
+    guard(error, || {
+        let Some(query) = query.as_ref() else {
+            return Err(Failure(
+                AQ_INVALID_ARGUMENT,
+                "The argument \"query\" is NULL.".to_owned(),
+            ));
+        };
+        let document = read_str(document, "document")?;
+        let format = if format.is_null() {
+            Format::Ason
+        } else {
+            Format::from_name(read_str(format, "format")?)
+                .map_err(|e| Failure(AQ_INVALID_ARGUMENT, e.to_string()))?
+        };
+        if result.is_null() {
+            return Err(Failure(
+                AQ_INVALID_ARGUMENT,
+                "The argument \"result\" is NULL.".to_owned(),
+            ));
+        }
+
+        let documents = parse_documents(document, format, &ParseOptions::unlimited())
+            .map_err(|e| Failure(AQ_DOCUMENT_ERROR, e.to_string()))?;
+        let evaluation_error = |message: String| Failure(AQ_EVALUATION_ERROR, message);
+        let mut values = vec![];
+        for document in documents {
+            for value in query
+                .query
+                .run_value(document)
+                .map_err(|e| evaluation_error(e.to_string()))?
+            {
+                let text = write_text(&value, format.result_format())
+                    .map_err(|e| evaluation_error(e.to_string()))?;
+                values.push(CString::new(text).map_err(|_| {
+                    evaluation_error("The result contains the NUL character.".to_owned())
+                })?);
+            }
+        }
+        *result = Box::into_raw(Box::new(AqResult { values }));
+        Ok(())
+    })
+}
+
+/// The number of the results.
+///
+/// # Safety
+///
+/// `result` must be returned by `aq_run_on_string`.
+#[no_mangle]
+pub unsafe extern "C" fn aq_result_count(result: *const AqResult) -> usize {
+    result.as_ref().map_or(0, |r| r.values.len())
+}
+
+/// The text of the result at the index, or NULL if the index is out of
+/// range, the text is owned by the result.
+///
+/// # Safety
+///
+/// `result` must be returned by `aq_run_on_string`.
+#[no_mangle]
+pub unsafe extern "C" fn aq_result_get(result: *const AqResult, index: usize) -> *const c_char {
+    result
+        .as_ref()
+        .and_then(|r| r.values.get(index))
+        .map_or(ptr::null(), |v| v.as_ptr())
+}
+
+/// # Safety
+///
+/// `result` must be NULL or returned by `aq_run_on_string`,
+/// and it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn aq_free_result(result: *mut AqResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// # Safety
+///
+/// `query` must be NULL or returned by `aq_compile`,
+/// and it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn aq_free_query(query: *mut AqQuery) {
+    if !query.is_null() {
+        drop(Box::from_raw(query));
+    }
+}
+
+/// Free the message of an error.
+///
+/// # Safety
+///
+/// `text` must be NULL or a message written by the functions of this
+/// interface, and it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn aq_free_string(text: *mut c_char) {
+    if !text.is_null() {
+        drop(CString::from_raw(text));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{CStr, CString},
+        ptr,
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_compile_and_run() {
+        unsafe {
+            let mut query = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            let source = CString::new(".server.port").unwrap();
+            assert_eq!(aq_compile(source.as_ptr(), &mut query, &mut error), AQ_OK);
+            assert!(error.is_null());
+
+            let mut result = ptr::null_mut();
+            let document = CString::new(r#"{"server": {"port": 8080}}"#).unwrap();
+            let json = CString::new("json").unwrap();
+            assert_eq!(
+                aq_run_on_string(
+                    query,
+                    document.as_ptr(),
+                    json.as_ptr(),
+                    &mut result,
+                    &mut error
+                ),
+                AQ_OK
+            );
+            assert_eq!(aq_result_count(result), 1);
+            assert_eq!(
+                CStr::from_ptr(aq_result_get(result, 0)).to_str().unwrap(),
+                "8080"
+            );
+            assert!(aq_result_get(result, 1).is_null());
+
+            aq_free_result(result);
+            aq_free_query(query);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        unsafe {
+            let mut query = ptr::null_mut();
+            let mut error = ptr::null_mut();
+            let source = CString::new(".a |").unwrap();
+            assert_eq!(
+                aq_compile(source.as_ptr(), &mut query, &mut error),
+                AQ_QUERY_ERROR
+            );
+            assert!(query.is_null());
+            assert!(!CStr::from_ptr(error).to_str().unwrap().is_empty());
+            aq_free_string(error);
+            error = ptr::null_mut();
+
+            let source = CString::new(".a").unwrap();
+            assert_eq!(aq_compile(source.as_ptr(), &mut query, &mut error), AQ_OK);
+
+            let mut result = ptr::null_mut();
+            let document = CString::new("{").unwrap();
+            let json = CString::new("json").unwrap();
+            assert_eq!(
+                aq_run_on_string(
+                    query,
+                    document.as_ptr(),
+                    json.as_ptr(),
+                    &mut result,
+                    &mut error
+                ),
+                AQ_DOCUMENT_ERROR
+            );
+            assert!(result.is_null());
+            assert!(!error.is_null());
+            aq_free_string(error);
+
+            // the error is not written if `error` is NULL
+            assert_eq!(
+                aq_run_on_string(
+                    query,
+                    ptr::null(),
+                    ptr::null(),
+                    &mut result,
+                    ptr::null_mut()
+                ),
+                AQ_INVALID_ARGUMENT
+            );
+            aq_free_query(query);
+
+            let source = CString::new("error(\"x\")").unwrap();
+            assert_eq!(
+                aq_compile(source.as_ptr(), &mut query, ptr::null_mut()),
+                AQ_OK
+            );
+            let document = CString::new("{}").unwrap();
+            assert_eq!(
+                aq_run_on_string(
+                    query,
+                    document.as_ptr(),
+                    json.as_ptr(),
+                    &mut result,
+                    ptr::null_mut()
+                ),
+                AQ_EVALUATION_ERROR
+            );
+            aq_free_query(query);
+
+            // freeing NULL is a no-op
+            aq_free_result(ptr::null_mut());
+            aq_free_query(ptr::null_mut());
+            aq_free_string(ptr::null_mut());
+        }
+    }
+}
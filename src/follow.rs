@@ -0,0 +1,141 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Following the growing input files, i.e. `aq --follow QUERY app.log`.
+//
+// The files are polled for the appended data (like `tail -f`), the data
+// is split into the records by the separator (LF, or RS with `--seq`),
+// and the last partial record is kept until its separator is appended.
+//
+// A file which becomes shorter than the read position is treated as
+// truncated (or replaced by the log rotation), and it is read again
+// from the beginning.
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+use crate::error::Error;
+
+// the interval of checking the files for the appended data.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub struct FollowedFile {
+    path: String,
+    file: File,
+    offset: u64,
+    separator: u8,
+
+    // the bytes after the last separator.
+    pending: Vec<u8>,
+}
+
+impl FollowedFile {
+    pub fn open(path: &str, separator: u8) -> Result<Self, Error> {
+        Ok(Self {
+            path: path.to_owned(),
+            file: open_file(path)?,
+            offset: 0,
+            separator,
+            pending: vec![],
+        })
+    }
+
+    /// The complete records appended since the last reading.
+    pub fn read_records(&mut self) -> Result<Vec<String>, Error> {
+        let length = std::fs::metadata(&self.path)
+            .map(|m| m.len())
+            .map_err(|e| follow_error(&self.path, e))?;
+        if length < self.offset {
+            self.file = open_file(&self.path)?;
+            self.offset = 0;
+            self.pending.clear();
+        }
+
+        let mut data = vec![];
+        self.file
+            .seek(SeekFrom::Start(self.offset))
+            .and_then(|_| self.file.read_to_end(&mut data))
+            .map_err(|e| follow_error(&self.path, e))?;
+        self.offset += data.len() as u64;
+        self.pending.extend(data);
+
+        let end = match self.pending.iter().rposition(|b| *b == self.separator) {
+            Some(pos) => pos + 1,
+            None => return Ok(vec![]),
+        };
+        let complete: Vec<u8> = self.pending.drain(..end).collect();
+        let text = String::from_utf8(complete).map_err(|_| {
+            Error::Message(format!(
+                "The appended data of the file \"{}\" is not valid UTF-8.",
+                self.path
+            ))
+        })?;
+
+        Ok(text
+            .split(self.separator as char)
+            .filter(|r| !r.trim().is_empty())
+            .map(|r| r.to_owned())
+            .collect())
+    }
+}
+
+fn open_file(path: &str) -> Result<File, Error> {
+    File::open(path).map_err(|e| follow_error(path, e))
+}
+
+fn follow_error(path: &str, e: std::io::Error) -> Error {
+    Error::Message(format!("Fail to follow the file \"{}\": {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use pretty_assertions::assert_eq;
+
+    use super::FollowedFile;
+
+    #[test]
+    fn test_read_appended_records() {
+        let path = std::env::temp_dir().join(format!("aq-follow-{}.log", std::process::id()));
+        std::fs::write(&path, "{a: 1}\n{a:").unwrap();
+
+        // the incomplete record waits for the rest
+        let mut followed = FollowedFile::open(path.to_str().unwrap(), b'\n').unwrap();
+        assert_eq!(followed.read_records().unwrap(), ["{a: 1}"]);
+        assert!(followed.read_records().unwrap().is_empty());
+
+        // the blank records are skipped
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b" 2}\n\n{a: 3}\n").unwrap();
+        assert_eq!(followed.read_records().unwrap(), ["{a: 2}", "{a: 3}"]);
+
+        // the truncated file is read from the start
+        std::fs::write(&path, "{b: 1}\n").unwrap();
+        assert_eq!(followed.read_records().unwrap(), ["{b: 1}"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_record_separator() {
+        let path = std::env::temp_dir().join(format!("aq-follow-rs-{}.log", std::process::id()));
+        std::fs::write(&path, "\x1e{a: 1}\n\x1e{a: 2}\n").unwrap();
+
+        // the record is complete when the next one starts
+        let mut followed = FollowedFile::open(path.to_str().unwrap(), 0x1e).unwrap();
+        assert_eq!(followed.read_records().unwrap(), ["{a: 1}\n"]);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(FollowedFile::open(path.to_str().unwrap(), b'\n').is_err());
+    }
+}
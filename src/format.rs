@@ -0,0 +1,323 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The format strings, i.e. `@name` and `@name "template"`.
+
+use crate::{
+    error::Error,
+    json::to_json_string,
+    printer::{print_value, PrintOptions},
+    value::Value,
+};
+
+/// Format the value as text with the named format, the argument
+/// is the value of `x` in `@name(x)`.
+pub fn apply_format(name: &str, argument: Option<&Value>, value: &Value) -> Result<String, Error> {
+    match name {
+        "text" => Ok(value.to_text()),
+        "ason" => {
+            let options = match argument {
+                None => PrintOptions::compact(),
+                Some(arg) => PrintOptions::pretty(&indent_from_value(arg)?),
+            };
+            Ok(print_value(value, &options))
+        }
+        "json" => Ok(to_json_string(value)),
+        "csv" => join_row("csv", value, ",", |field| match string_of(field) {
+            Some(s) => Ok(format!("\"{}\"", s.replace('"', "\"\""))),
+            None => scalar_text("csv", field),
+        }),
+        "tsv" => join_row("tsv", value, "\t", |field| match string_of(field) {
+            Some(s) => Ok(s
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r")),
+            None => scalar_text("tsv", field),
+        }),
+        "sh" => {
+            let quote = |field: &Value| match string_of(field) {
+                Some(s) => Ok(format!("'{}'", s.replace('\'', "'\\''"))),
+                None => scalar_text("sh", field),
+            };
+            match value {
+                Value::List(_) | Value::Tuple(_) => join_row("sh", value, " ", quote),
+                _ => quote(value),
+            }
+        }
+        "uri" => Ok(encode_uri(&value.to_text())),
+        "base64" => Ok(encode_base64(&bytes_of(value))),
+        "base64d" => {
+            let bytes = decode_base64(&value.to_text())?;
+            String::from_utf8(bytes).map_err(|_| {
+                Error::Message(
+                    "The decoded data is not valid UTF-8 text, use \"frombase64\" \
+                    to decode it as byte data."
+                        .to_owned(),
+                )
+            })
+        }
+        _ => Err(Error::Message(format!(
+            "Format \"@{}\" is not defined.",
+            name
+        ))),
+    }
+}
+
+// join the fields of a row (i.e. a list or tuple) with the separator.
+fn join_row(
+    name: &str,
+    row: &Value,
+    separator: &str,
+    format_field: impl Fn(&Value) -> Result<String, Error>,
+) -> Result<String, Error> {
+    match row {
+        Value::List(items) | Value::Tuple(items) => Ok(items
+            .iter()
+            .map(format_field)
+            .collect::<Result<Vec<_>, _>>()?
+            .join(separator)),
+        _ => Err(Error::Message(format!(
+            "Format \"@{}\" expects list or tuple, found {}.",
+            name,
+            row.type_name()
+        ))),
+    }
+}
+
+// the content of strings and chars, they are quoted or escaped by the formats.
+fn string_of(field: &Value) -> Option<String> {
+    match field {
+        Value::String(s) => Some(s.clone()),
+        Value::Char(c) => Some(c.to_string()),
+        _ => None,
+    }
+}
+
+// the text of the other fields, the `null` is empty,
+// and the numbers have no type suffixes.
+fn scalar_text(name: &str, field: &Value) -> Result<String, Error> {
+    match field {
+        _ if field.is_null() => Ok(String::new()),
+        Value::Number(n) => Ok(n.plain_text()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::DateTime(d) => Ok(d.to_rfc3339()),
+        _ => Err(Error::Message(format!(
+            "Cannot format {} with \"@{}\".",
+            field.type_name(),
+            name
+        ))),
+    }
+}
+
+// the percent-encoding, all characters except the unreserved ones
+// (i.e. `A-Z a-z 0-9 - _ . ~`) are encoded.
+fn encode_uri(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", b));
+        }
+    }
+    encoded
+}
+
+// the indentation is either the number of spaces or the text itself, e.g. "\t".
+fn indent_from_value(value: &Value) -> Result<String, Error> {
+    match value {
+        Value::Number(n) => match n.as_integer() {
+            Some(i) if (0..=16).contains(&i) => Ok(" ".repeat(i as usize)),
+            _ => Err(Error::Message(format!(
+                "The indentation must be an integer between 0 and 16, found {}.",
+                n
+            ))),
+        },
+        Value::String(s) => Ok(s.clone()),
+        _ => Err(Error::Message(format!(
+            "The indentation must be a number or a string, found {}.",
+            value.type_name()
+        ))),
+    }
+}
+
+// the bytes of byte data, or the UTF-8 bytes of the text of other values.
+fn bytes_of(value: &Value) -> Vec<u8> {
+    match value {
+        Value::ByteData(bytes) => bytes.clone(),
+        _ => value.to_text().into_bytes(),
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The standard base64 with padding.
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (idx, b)| n | (*b as u32) << (16 - idx * 8));
+
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                text.push(BASE64_ALPHABET[(n >> (18 - idx * 6) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// The padding is optional, and the whitespaces are ignored.
+pub fn decode_base64(text: &str) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+    let mut n = 0u32;
+    let mut bits = 0;
+
+    for c in text.chars().filter(|c| !c.is_whitespace()) {
+        if c == '=' {
+            break;
+        }
+
+        let value = match BASE64_ALPHABET.iter().position(|a| *a as char == c) {
+            Some(v) => v as u32,
+            None => {
+                return Err(Error::Message(format!("Invalid base64 character '{}'.", c)));
+            }
+        };
+
+        n = n << 6 | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((n >> bits & 0xff) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+/// The lowercase hex digits without separators, e.g. "0a1b".
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The whitespaces between the bytes are ignored, e.g. "11 13 17".
+pub fn decode_hex(text: &str) -> Result<Vec<u8>, Error> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+    if !digits.len().is_multiple_of(2) {
+        return Err(Error::Message(format!(
+            "The hex text \"{}\" has an odd number of digits.",
+            text
+        )));
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let s: String = pair.iter().collect();
+            u8::from_str_radix(&s, 16)
+                .map_err(|_| Error::Message(format!("Invalid hex byte \"{}\".", s)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        query::Query,
+        value::Value,
+    };
+
+    use super::apply_format;
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    // run the query, the result is the formatted text or the error message.
+    fn format(query: &str, input: &str) -> String {
+        let result = Query::compile(query).and_then(|q| q.run_value(value(input)));
+        match result.map(|mut results| results.remove(0)) {
+            Ok(Value::String(text)) => text,
+            Ok(other) => panic!("not a string: {}", other),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_ason_format() {
+        let doc = r#"{a: 1, b: [1, "x"], c: {}}"#;
+        assert_eq!(
+            apply_format("ason", None, &value(doc)).unwrap(),
+            r#"{a:1,b:[1,"x"],c:{}}"#
+        );
+        assert_eq!(
+            format("@ason(2)", doc),
+            "{\n  a: 1\n  b: [\n    1\n    \"x\"\n  ]\n  c: {}\n}"
+        );
+        assert_eq!(
+            format(r#"@ason("\t") "value:\n\(.b)""#, doc),
+            "value:\n[\n\t1\n\t\"x\"\n]"
+        );
+        assert_eq!(format(r#""v=\(.b | @ason)""#, doc), r#"v=[1,"x"]"#);
+        assert_eq!(format(r#"@text "\(.a)-\(.b[1])""#, doc), "1-x");
+
+        assert_eq!(
+            format("@ason(17)", doc),
+            "The indentation must be an integer between 0 and 16, found 17."
+        );
+        assert_eq!(
+            format("@ason(true)", doc),
+            "The indentation must be a number or a string, found boolean."
+        );
+    }
+
+    #[test]
+    fn test_output_formats() {
+        assert_eq!(
+            format(r#"[1, "a,\"b\"", null, true, 2.5] | @csv"#, "0"),
+            r#"1,"a,""b""",,true,2.5"#
+        );
+        assert_eq!(format(r#"["a\tb", 3] | @tsv"#, "0"), "a\\tb\t3");
+        assert_eq!(format(r#"["it's", 1] | @sh"#, "0"), r#"'it'\''s' 1"#);
+        assert_eq!(format(r#""a b&c/é" | @uri"#, "0"), "a%20b%26c%2F%C3%A9");
+        assert_eq!(
+            format(
+                r#"{a: [1, 2.0, "x\n"], b: Option::None, c: Option::Some(3), d: Color::Red} | @json"#,
+                "0"
+            ),
+            r#"{"a":[1,2.0,"x\n"],"b":null,"c":3,"d":"Color::Red"}"#
+        );
+        assert_eq!(
+            format(r#"[("x" | tochar), (17 | to_u8)] | @json"#, "0"),
+            r#"["x",17]"#
+        );
+        assert_eq!(format("@base64", r#""hello""#), "aGVsbG8=");
+
+        // the format applies to the interpolations of the template
+        assert_eq!(
+            format(r#"@csv "row: \([.a, .b])""#, r#"{a: 1, b: "x"}"#),
+            r#"row: 1,"x""#
+        );
+
+        assert_eq!(
+            format("[[1]] | @csv", "0"),
+            r#"Cannot format list with "@csv"."#
+        );
+        assert_eq!(
+            format("1 | @tsv", "0"),
+            r#"Format "@tsv" expects list or tuple, found number."#
+        );
+    }
+}
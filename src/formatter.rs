@@ -0,0 +1,66 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Reformatting the ASON documents in the canonical style, i.e. `aq fmt <file(s)>`.
+//
+// The style is controlled by the printer options, e.g. the indentation,
+// the line width, the trailing commas and the style of the integers.
+//
+// Since the printer does not keep the comments, a document is not
+// formatted if anything except the radix of the numbers would be lost,
+// the losses are found by the round-trip check.
+
+use crate::{
+    error::Error,
+    input::{parse_input, ParseOptions},
+    printer::{print_value, PrintOptions},
+    roundtrip::{check_text, Loss},
+};
+
+/// The formatted text of the ASON document.
+pub fn format_document(source: &str, options: &PrintOptions) -> Result<String, Error> {
+    let losses = check_text(source).map_err(Error::Message)?;
+    if let Some(loss) = losses.iter().find(|l| !matches!(l, Loss::Radix(..))) {
+        return Err(Error::Message(format!(
+            "The document can not be formatted without loss: {}.",
+            loss
+        )));
+    }
+
+    let value = parse_input(source, None, &ParseOptions::unlimited())?;
+    Ok(format!("{}\n", print_value(&value, options)))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::printer::{NumberStyle, PrintOptions};
+
+    use super::format_document;
+
+    #[test]
+    fn test_format_document() {
+        let options = PrintOptions::pretty("  ").with_number_style(NumberStyle::Grouped);
+        assert_eq!(
+            format_document("{a: [1, 2], n: 1234567}", &options).unwrap(),
+            "{\n  a: [\n    1\n    2\n  ]\n  n: 1_234_567\n}\n"
+        );
+
+        // the radix of the numbers is not kept
+        assert_eq!(
+            format_document("0xff", &PrintOptions::pretty("  ")).unwrap(),
+            "255\n"
+        );
+
+        assert_eq!(
+            format_document("// the comment\n{a: 1}", &options)
+                .unwrap_err()
+                .to_string(),
+            "The document can not be formatted without loss: comment dropped at line 1."
+        );
+    }
+}
@@ -0,0 +1,118 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Searching the string values of documents, i.e. `aq grep <pattern> <file(s)>`.
+//
+// Each match is printed as the path and the value, e.g.
+//
+// `.users[2].email: "alice@example.com"`
+//
+// and the paths can be used in the queries directly.
+
+use crate::{
+    path::path_to_string,
+    printer::{print_value, PrintOptions},
+    regex::Regex,
+    value::Value,
+    visit::{walk, Visit, Visitor},
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GrepMatch {
+    /// The path of the matched string, or the path of the
+    /// entry when the key is matched.
+    pub path: Vec<Value>,
+    pub value: Value,
+}
+
+impl std::fmt::Display for GrepMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {}",
+            path_to_string(&self.path),
+            print_value(&self.value, &PrintOptions::compact())
+        )
+    }
+}
+
+/// Find the string values which match the regular expression, the object
+/// keys are also searched if `keys` is `true`. The matches are in the
+/// document order.
+pub fn grep_value(value: &Value, regex: &Regex, keys: bool) -> Vec<GrepMatch> {
+    let mut searcher = Searcher {
+        regex,
+        keys,
+        matches: vec![],
+    };
+    walk(value, &mut searcher);
+    searcher.matches
+}
+
+struct Searcher<'a> {
+    regex: &'a Regex,
+    keys: bool,
+    matches: Vec<GrepMatch>,
+}
+
+impl Visitor for Searcher<'_> {
+    fn enter(&mut self, path: &[Value], value: &Value) -> Visit {
+        let key_matched = self.keys
+            && matches!(path.last(), Some(Value::String(key)) if self.regex.is_match(key));
+        let value_matched = matches!(value, Value::String(s) if self.regex.is_match(s));
+
+        if key_matched || value_matched {
+            self.matches.push(GrepMatch {
+                path: path.to_vec(),
+                value: value.clone(),
+            });
+        }
+        Visit::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        regex::Regex,
+    };
+
+    use super::grep_value;
+
+    fn grep(pattern: &str, keys: bool) -> Vec<String> {
+        let document = parse_input(
+            r#"{users: [{name: "Alice", email: "alice@example.com"},
+                        {name: "Bob", email: "bob@test.org"}], example: 1}"#,
+            None,
+            &ParseOptions::unlimited(),
+        )
+        .unwrap();
+        let regex = Regex::new(pattern, false).unwrap();
+        grep_value(&document, &regex, keys)
+            .iter()
+            .map(|m| m.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_grep_value() {
+        assert_eq!(
+            grep("example", false),
+            [r#".users[0].email: "alice@example.com""#]
+        );
+        assert_eq!(grep("^B", false), [r#".users[1].name: "Bob""#]);
+        assert_eq!(grep("nothing", true), Vec::<String>::new());
+
+        // the value of the matched key is reported
+        assert_eq!(
+            grep("example", true),
+            [r#".users[0].email: "alice@example.com""#, ".example: 1"]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Rewriting the input files in place, i.e. `aq -i QUERY FILE`.
+//
+// The new content is written to a temporary file in the same directory,
+// flushed to the disk, and then renamed to the file, so the file is
+// either the old content or the new one, even if `aq` is interrupted.
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// Replace the content of the file atomically, the original file
+/// is kept as `FILE` + `backup_suffix` if the suffix is not empty.
+pub fn write_in_place(path: &Path, content: &[u8], backup_suffix: &str) -> io::Result<()> {
+    let temp_path = temp_path_of(path);
+    let outcome = write_synced(&temp_path, path, content).and_then(|_| {
+        if !backup_suffix.is_empty() {
+            let mut backup = path.as_os_str().to_owned();
+            backup.push(backup_suffix);
+            fs::copy(path, backup)?;
+        }
+        fs::rename(&temp_path, path)
+    });
+
+    if outcome.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    outcome
+}
+
+// the temporary file is hidden and unique to the process.
+fn temp_path_of(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.aq-{}.tmp", name, std::process::id()))
+}
+
+// write the content and flush it to the disk, the permissions
+// are copied from the original file.
+fn write_synced(temp_path: &Path, original: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = File::create(temp_path)?;
+    file.write_all(content)?;
+    file.set_permissions(fs::metadata(original)?.permissions())?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use pretty_assertions::assert_eq;
+
+    use super::{temp_path_of, write_in_place};
+
+    #[test]
+    fn test_write_in_place() {
+        let dir = std::env::temp_dir().join(format!("aq-in-place-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.ason");
+        fs::write(&path, "{n: 1}").unwrap();
+
+        write_in_place(&path, b"{n: 2}", "").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{n: 2}");
+
+        write_in_place(&path, b"{n: 3}", ".bak").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{n: 3}");
+        assert_eq!(
+            fs::read_to_string(dir.join("a.ason.bak")).unwrap(),
+            "{n: 2}"
+        );
+
+        // the temporary file is removed if the file does not exist
+        let missing = dir.join("missing.ason");
+        assert!(write_in_place(&missing, b"{}", "").is_err());
+        assert!(!temp_path_of(&missing).exists());
+        assert!(!missing.exists());
+
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["a.ason", "a.ason.bak"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,354 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The incremental parse of the input, i.e. `aq --incremental`.
+//
+// The input is read through a buffer and split into the documents
+// without parsing them, each document is parsed (and evaluated) when
+// it is complete, and it is dropped before the next one is read, so
+// the memory is bounded by the size of the largest document instead of
+// the size of the input. A document ends at the first line break after
+// its value, e.g. the concatenated documents
+//
+// ```
+// {id: 1, name: "foo"}
+// {
+//     id: 2
+//     name: "bar"
+// }
+// ```
+//
+// With `--items`, the items of a top-level list are split (at the commas
+// and the line breaks between them) and parsed one by one instead, e.g.
+// for a huge list of the records. The brackets inside the strings and
+// the comments are skipped.
+
+use std::io::{BufRead, ErrorKind};
+
+use crate::{
+    error::Error,
+    input::{parse_input, ParseOptions},
+    json::JsonDialect,
+    scan::{Class, Scanner},
+    value::Value,
+};
+
+/// The documents (or the items of the top-level lists) of the input,
+/// an error is produced if a document is malformed or it can not be read.
+pub struct IncrementalDocuments<R> {
+    reader: R,
+    dialect: Option<JsonDialect>,
+    items: bool,
+    splitter: Splitter,
+
+    // the text of the current document (or item).
+    buf: Vec<u8>,
+
+    // the line of the next byte, and the first line of the current document.
+    line: usize,
+    start_line: usize,
+    finished: bool,
+}
+
+struct Splitter {
+    scanner: Scanner,
+    depth: usize,
+
+    // whether the current document (or item) has any value,
+    // i.e. not only the whitespaces and comments.
+    has_value: bool,
+
+    // the top-level list whose items are split.
+    in_list: bool,
+
+    // the previous byte is a `/` which may start a comment.
+    slash: bool,
+}
+
+// what to do with the byte.
+#[derive(Debug, PartialEq)]
+enum Action {
+    Keep,
+
+    // the byte is not a part of the document, e.g. the brackets
+    // of the top-level list and the commas between the items.
+    Skip,
+
+    // the document (or item) ends before the byte.
+    Split,
+}
+
+pub fn incremental_documents<R: BufRead>(
+    reader: R,
+    dialect: Option<JsonDialect>,
+    items: bool,
+) -> IncrementalDocuments<R> {
+    IncrementalDocuments {
+        reader,
+        dialect,
+        items,
+        splitter: Splitter {
+            scanner: Scanner::new(),
+            depth: 0,
+            has_value: false,
+            in_list: false,
+            slash: false,
+        },
+        buf: vec![],
+        line: 1,
+        start_line: 1,
+        finished: false,
+    }
+}
+
+impl<R: BufRead> IncrementalDocuments<R> {
+    fn next_document(&mut self) -> Result<Option<Value>, Error> {
+        loop {
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "Fail to read the input document: {}",
+                        e
+                    )))
+                }
+            };
+
+            if buf.is_empty() {
+                return self.finish();
+            }
+
+            let mut consumed = 0;
+            let mut complete = false;
+            for &byte in buf {
+                let action = self.splitter.scan(byte, self.items);
+                if action == Action::Split {
+                    // the byte is scanned again for the next document.
+                    complete = true;
+                    break;
+                }
+
+                consumed += 1;
+                if byte == b'\n' {
+                    self.line += 1;
+                }
+                if action == Action::Keep {
+                    if self.buf.is_empty() && byte.is_ascii_whitespace() {
+                        // the whitespaces before the document
+                        self.start_line = self.line;
+                    } else {
+                        self.buf.push(byte);
+                    }
+                }
+            }
+            self.reader.consume(consumed);
+
+            if complete {
+                let text = std::mem::take(&mut self.buf);
+                let start_line = self.start_line;
+                return self.parse(text, start_line).map(Some);
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Result<Option<Value>, Error> {
+        let splitter = &self.splitter;
+        if splitter.depth > 0 || splitter.scanner.is_unterminated() {
+            return Err(Error::Message(format!(
+                "Unexpected end of the document (at line {}).",
+                self.line
+            )));
+        }
+
+        self.finished = true;
+        if !splitter.has_value {
+            return Ok(None);
+        }
+        let text = std::mem::take(&mut self.buf);
+        self.parse(text, self.start_line).map(Some)
+    }
+
+    fn parse(&mut self, text: Vec<u8>, line: usize) -> Result<Value, Error> {
+        self.splitter.has_value = false;
+        let text = String::from_utf8(text).map_err(|_| {
+            Error::Message(format!("The document at line {} is not valid UTF-8.", line))
+        })?;
+        parse_input(&text, self.dialect, &ParseOptions::unlimited())
+            .map_err(|e| Error::Message(format!("The document at line {}: {}", line, e)))
+    }
+}
+
+impl<R: BufRead> Iterator for IncrementalDocuments<R> {
+    type Item = Result<Value, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let document = self.next_document();
+        if document.is_err() {
+            self.finished = true;
+        }
+        document.transpose()
+    }
+}
+
+impl Splitter {
+    fn scan(&mut self, byte: u8, items: bool) -> Action {
+        let slash = std::mem::replace(&mut self.slash, false);
+        match self.scanner.scan(byte) {
+            Class::Code => {
+                if slash {
+                    // the `/` is a part of the value, e.g. a malformed
+                    // document, the parser reports the error.
+                    self.has_value = true;
+                }
+                self.scan_code(byte, items)
+            }
+            Class::Slash => {
+                self.slash = true;
+                Action::Keep
+            }
+            Class::String => {
+                self.has_value = true;
+                Action::Keep
+            }
+            Class::CommentStart | Class::Comment => Action::Keep,
+        }
+    }
+
+    fn scan_code(&mut self, byte: u8, items: bool) -> Action {
+        // the depth of the documents, or the items of the top-level list.
+        let base = self.in_list as usize;
+
+        match byte {
+            b'\n' if self.depth == base && self.has_value => Action::Split,
+            b',' if self.in_list && self.depth == 1 => {
+                if self.has_value {
+                    Action::Split
+                } else {
+                    Action::Skip
+                }
+            }
+            b'[' if items && self.depth == 0 && !self.has_value => {
+                self.in_list = true;
+                self.depth = 1;
+                Action::Skip
+            }
+            b']' if self.in_list && self.depth == 1 => {
+                if self.has_value {
+                    // the last item, the bracket is scanned again after it.
+                    return Action::Split;
+                }
+                self.in_list = false;
+                self.depth = 0;
+                Action::Skip
+            }
+            b'[' | b'{' | b'(' => {
+                self.depth += 1;
+                self.has_value = true;
+                Action::Keep
+            }
+            b']' | b'}' | b')' => {
+                self.depth = self.depth.saturating_sub(1);
+                self.has_value = true;
+                Action::Keep
+            }
+            _ if byte.is_ascii_whitespace() => Action::Keep,
+            _ => {
+                self.has_value = true;
+                Action::Keep
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        json::JsonDialect,
+        value::Value,
+    };
+
+    use super::incremental_documents;
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    fn documents(text: &str, dialect: Option<JsonDialect>, items: bool) -> Vec<Value> {
+        incremental_documents(text.as_bytes(), dialect, items)
+            .map(|d| d.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_split_documents() {
+        let text = "// head\n{a: 1, s: \"x\\\"}\\n[\"}\n\n{\n  b: [1,\n 2] /* } */\n}\n3\n";
+        assert_eq!(
+            documents(text, None, false),
+            [
+                value("{a: 1, s: \"x\\\"}\\n[\"}"),
+                value("{b: [1, 2]}"),
+                Value::from(3)
+            ]
+        );
+
+        // the block comments are nested
+        assert_eq!(
+            documents("[1, /* /* */\n] */ 2]\n3", None, false),
+            [value("[1, 2]"), Value::from(3)]
+        );
+    }
+
+    #[test]
+    fn test_split_items() {
+        assert_eq!(
+            documents("[1, 2,\n 3\n {a: \"]\"}\n]\n[4]\n5", None, true),
+            [
+                Value::from(1),
+                Value::from(2),
+                Value::from(3),
+                value("{a: \"]\"}"),
+                Value::from(4),
+                Value::from(5)
+            ]
+        );
+        assert_eq!(
+            documents(
+                "[\n  {\"a\": [1, 2]},\n  {\"a\": 3}\n]",
+                Some(JsonDialect::Json),
+                true
+            ),
+            [value("{a: [1, 2]}"), value("{a: 3}")]
+        );
+        assert_eq!(documents("[]\n", None, true), []);
+    }
+
+    #[test]
+    fn test_malformed_documents() {
+        let results: Vec<_> = incremental_documents("{a: 1\n".as_bytes(), None, false).collect();
+        assert_eq!(results.len(), 1);
+        assert!(results[0]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .starts_with("Unexpected end"));
+
+        let results: Vec<_> =
+            incremental_documents("1\n{a: }\n2".as_bytes(), None, false).collect();
+        assert_eq!(results.len(), 2);
+        assert!(results[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("line 2"));
+    }
+}
@@ -0,0 +1,333 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Parsing the input documents with the resource limits.
+//
+// The limits protect the embedders which handle untrusted input,
+// the size and the nesting depth are checked before the document is
+// parsed (so a deeply nested document cannot overflow the stack of
+// the parser), and the length of strings is checked after that.
+//
+// A byte range of the input can also be read alone, e.g. a document
+// at a known offset of a huge file, i.e. `aq --offset N --length N`.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+use ason::ast::parser::parse_from_str;
+
+use crate::{
+    error::Error,
+    json::{parse_json_from_str, JsonDialect},
+    scan::{Class, Scanner},
+    value::{Map, Value, VariantValue},
+};
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ParseOptions {
+    /// The maximum nesting depth of lists, tuples, objects and variants.
+    pub max_depth: Option<usize>,
+
+    /// The maximum length of strings and object keys, in bytes.
+    pub max_string_length: Option<usize>,
+
+    /// The maximum size of the document text, in bytes.
+    pub max_document_size: Option<usize>,
+}
+
+impl ParseOptions {
+    /// No limits, it is suitable for the trusted input.
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// The conservative limits for the untrusted input.
+    pub fn untrusted() -> Self {
+        Self {
+            max_depth: Some(128),
+            max_string_length: Some(1024 * 1024),
+            max_document_size: Some(64 * 1024 * 1024),
+        }
+    }
+}
+
+/// The byte range of the input text.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct ByteRange {
+    pub offset: u64,
+
+    /// `None` for the rest of the input.
+    pub length: Option<u64>,
+}
+
+/// Read the byte range of a file, the bytes before the
+/// offset are skipped without reading.
+pub fn read_file_range(file: &mut std::fs::File, range: ByteRange) -> io::Result<String> {
+    file.seek(SeekFrom::Start(range.offset))?;
+    read_range(file, ByteRange { offset: 0, ..range })
+}
+
+/// Read the byte range of a stream, e.g. the STDIN,
+/// the bytes before the offset are discarded.
+pub fn read_range(mut reader: impl Read, range: ByteRange) -> io::Result<String> {
+    io::copy(&mut reader.by_ref().take(range.offset), &mut io::sink())?;
+
+    let mut buf = vec![];
+    match range.length {
+        Some(length) => reader.take(length).read_to_end(&mut buf)?,
+        None => reader.read_to_end(&mut buf)?,
+    };
+
+    String::from_utf8(buf).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "the byte range is not valid UTF-8 (at byte offset {}), \
+                 it may start or end inside a character",
+                range.offset + e.utf8_error().valid_up_to() as u64
+            ),
+        )
+    })
+}
+
+/// Parse an input document, the text is ASON if the dialect is `None`.
+pub fn parse_input(
+    text: &str,
+    dialect: Option<JsonDialect>,
+    options: &ParseOptions,
+) -> Result<Value, Error> {
+    if let Some(max) = options.max_document_size {
+        if text.len() > max {
+            return Err(Error::Message(format!(
+                "The document size {} bytes exceeds the limit of {} bytes.",
+                text.len(),
+                max
+            )));
+        }
+    }
+
+    if let Some(max) = options.max_depth {
+        check_depth(text, max)?;
+    }
+
+    let value = match dialect {
+        Some(d) => parse_json_from_str(text, d)?,
+        None => match parse_from_str(text) {
+            Ok(n) => Value::from(n),
+            Err(e) => return Err(Error::Message(e.with_source(text).to_string())),
+        },
+    };
+
+    if let Some(max) = options.max_string_length {
+        check_string_length(&value, max)?;
+    }
+
+    Ok(value)
+}
+
+// scan the brackets outside the strings and comments.
+fn check_depth(text: &str, max: usize) -> Result<(), Error> {
+    let mut scanner = Scanner::new();
+    let mut depth = 0;
+    let mut line = 1;
+
+    for byte in text.bytes() {
+        if byte == b'\n' {
+            line += 1;
+        }
+        if scanner.scan(byte) != Class::Code {
+            continue;
+        }
+        match byte {
+            b'[' | b'{' | b'(' => {
+                depth += 1;
+                if depth > max {
+                    return Err(Error::Message(format!(
+                        "The nesting depth exceeds the limit of {} (at line {}).",
+                        max, line
+                    )));
+                }
+            }
+            b']' | b'}' | b')' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn check_string_length(value: &Value, max: usize) -> Result<(), Error> {
+    let check_items = |items: &[Value]| {
+        items
+            .iter()
+            .try_for_each(|item| check_string_length(item, max))
+    };
+
+    let check_map = |map: &Map| {
+        map.iter().try_for_each(|(key, item)| {
+            check_length(key, max)?;
+            check_string_length(item, max)
+        })
+    };
+
+    match value {
+        Value::String(s) => check_length(s, max),
+        Value::List(items) | Value::Tuple(items) => check_items(items),
+        Value::Object(map) => check_map(map),
+        Value::Variant(v) => match &v.value {
+            VariantValue::Empty => Ok(()),
+            VariantValue::Value(inner) => check_string_length(inner, max),
+            VariantValue::Tuple(items) => check_items(items),
+            VariantValue::Object(map) => check_map(map),
+        },
+        _ => Ok(()),
+    }
+}
+
+fn check_length(s: &str, max: usize) -> Result<(), Error> {
+    if s.len() > max {
+        Err(Error::Message(format!(
+            "The string length {} bytes exceeds the limit of {} bytes.",
+            s.len(),
+            max
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::json::JsonDialect;
+
+    use super::{parse_input, read_file_range, read_range, ByteRange, ParseOptions};
+
+    fn error_of(text: &str, options: &ParseOptions) -> String {
+        parse_input(text, None, options).unwrap_err().to_string()
+    }
+
+    fn error_of_json(text: &str, dialect: JsonDialect, options: &ParseOptions) -> String {
+        parse_input(text, Some(dialect), options)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse_untrusted_input() {
+        let options = ParseOptions::untrusted();
+
+        let text = "[".repeat(128) + &"]".repeat(128);
+        assert!(parse_input(&text, None, &options).is_ok());
+
+        let text = format!("{{a: 1,\n b: {}{}}}", "[".repeat(129), "]".repeat(129));
+        assert_eq!(
+            error_of(&text, &options),
+            "The nesting depth exceeds the limit of 128 (at line 2)."
+        );
+
+        // the brackets in the strings and the comments are not counted
+        let text = format!("[\"{}\" // {}\n]", "[".repeat(200), "{".repeat(200));
+        assert!(parse_input(&text, None, &options).is_ok());
+
+        // the block comments are nested
+        let text = format!("[/* /* */ {} */\n1]", "[".repeat(200));
+        assert!(parse_input(&text, None, &options).is_ok());
+
+        let text = format!("/* /* */ */ {}{}", "[".repeat(129), "]".repeat(129));
+        assert_eq!(
+            error_of(&text, &options),
+            "The nesting depth exceeds the limit of 128 (at line 1)."
+        );
+
+        let text = format!("{{s: \"{}\"}}", "a".repeat(1024 * 1024 + 1));
+        assert_eq!(
+            error_of(&text, &options),
+            "The string length 1048577 bytes exceeds the limit of 1048576 bytes."
+        );
+    }
+
+    #[test]
+    fn test_parse_with_limits() {
+        let options = ParseOptions {
+            max_document_size: Some(8),
+            ..ParseOptions::unlimited()
+        };
+        assert_eq!(
+            error_of("[1, 2, 3, 4]", &options),
+            "The document size 12 bytes exceeds the limit of 8 bytes."
+        );
+
+        let options = ParseOptions {
+            max_string_length: Some(2),
+            ..ParseOptions::unlimited()
+        };
+        assert_eq!(
+            error_of(r#"{abc: 1}"#, &options),
+            "The string length 3 bytes exceeds the limit of 2 bytes."
+        );
+        assert!(parse_input(r#"{ab: Option::Some(["xy"])}"#, None, &options).is_ok());
+    }
+
+    #[test]
+    fn test_parse_json_with_limits() {
+        let options = ParseOptions {
+            max_depth: Some(2),
+            ..ParseOptions::unlimited()
+        };
+        assert!(parse_input(r#"[[1], {a: "[[["}]"#, Some(JsonDialect::Json5), &options).is_ok());
+        assert!(error_of_json("[[[1]]]", JsonDialect::Json5, &options).contains("nesting depth"));
+
+        let options = ParseOptions {
+            max_document_size: Some(3),
+            ..ParseOptions::untrusted()
+        };
+        assert_eq!(
+            error_of_json("[1, 2]", JsonDialect::Jsonc, &options),
+            "The document size 6 bytes exceeds the limit of 3 bytes."
+        );
+    }
+
+    #[test]
+    fn test_read_range() {
+        let range = |offset, length| {
+            read_range("[1][22][333]".as_bytes(), ByteRange { offset, length }).unwrap()
+        };
+        assert_eq!(range(3, Some(4)), "[22]");
+        assert_eq!(range(7, None), "[333]");
+        assert_eq!(range(7, Some(100)), "[333]");
+        assert_eq!(range(20, None), "");
+
+        // the range ends inside the character "é"
+        assert_eq!(
+            read_range(
+                "aé".as_bytes(),
+                ByteRange {
+                    offset: 0,
+                    length: Some(2)
+                }
+            )
+            .unwrap_err()
+            .to_string(),
+            "the byte range is not valid UTF-8 (at byte offset 1), it may start or end inside a character"
+        );
+
+        let path = std::env::temp_dir().join(format!("aq-read-range-{}", std::process::id()));
+        std::fs::write(&path, "abcdef").unwrap();
+        let mut file = std::fs::File::open(&path).unwrap();
+        assert_eq!(
+            read_file_range(
+                &mut file,
+                ByteRange {
+                    offset: 2,
+                    length: Some(3)
+                }
+            )
+            .unwrap(),
+            "cde"
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+}
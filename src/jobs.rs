@@ -0,0 +1,185 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Evaluating the input files in parallel, i.e. `aq --jobs N`.
+//
+// N worker threads take the sources one by one, each worker owns its
+// state (e.g. a copy of the compiled query, which can not be shared
+// between the threads), and the outcomes are passed to the output in
+// the original order of the sources, e.g. the results of
+// `aq --jobs 4 '.name' a.ason b.ason c.ason` are always printed in
+// the order of the files, however long each file takes.
+
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Mutex,
+    },
+    thread,
+};
+
+use crate::error::Error;
+
+/// The number of the workers when `--jobs 0` is specified,
+/// i.e. the available parallelism of the machine.
+pub fn default_jobs() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Process the sources by `jobs` workers, the state of each worker is
+/// created by `init` in its thread. The outcomes are passed to `output`
+/// in the order of the sources, and the first error (of `init`, `work`
+/// or `output`) stops the workers and is returned.
+pub fn parallel<S, W, T>(
+    sources: Vec<S>,
+    jobs: usize,
+    init: impl Fn() -> Result<W, Error> + Sync,
+    work: impl Fn(&mut W, S) -> Result<T, Error> + Sync,
+    mut output: impl FnMut(T) -> Result<(), Error>,
+) -> Result<(), Error>
+where
+    S: Send,
+    T: Send,
+{
+    let count = sources.len();
+    let queue = Mutex::new(sources.into_iter().enumerate());
+    let stopped = AtomicBool::new(false);
+    let failure = Mutex::new(None);
+    let (sender, receiver) = mpsc::channel::<(usize, Result<T, Error>)>();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, count.max(1)) {
+            let sender = sender.clone();
+            let (queue, stopped, failure) = (&queue, &stopped, &failure);
+            let (init, work) = (&init, &work);
+            scope.spawn(move || {
+                let mut state = match init() {
+                    Ok(state) => state,
+                    Err(e) => {
+                        // the error is returned after the other
+                        // workers stop and the channel is closed.
+                        failure.lock().unwrap().get_or_insert(e);
+                        stopped.store(true, Ordering::Relaxed);
+                        return;
+                    }
+                };
+                while !stopped.load(Ordering::Relaxed) {
+                    let Some((index, source)) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    let outcome = work(&mut state, source);
+                    if sender.send((index, outcome)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // the outcomes which arrive ahead of their turn are kept until
+        // the previous ones are passed to the output.
+        let mut pending: BTreeMap<usize, Result<T, Error>> = BTreeMap::new();
+        let mut next = 0;
+        let result = loop {
+            if let Some(outcome) = pending.remove(&next) {
+                next += 1;
+                if let Err(e) = outcome.and_then(&mut output) {
+                    break Err(e);
+                }
+                continue;
+            }
+            if next == count {
+                break Ok(());
+            }
+            match receiver.recv() {
+                Ok((index, outcome)) => {
+                    pending.insert(index, outcome);
+                }
+                Err(_) => {
+                    break Err(failure.lock().unwrap().take().unwrap_or_else(|| {
+                        Error::Message(
+                            "The worker thread which evaluates the input panicked.".to_owned(),
+                        )
+                    }))
+                }
+            }
+        };
+
+        stopped.store(true, Ordering::Relaxed);
+        drop(receiver);
+        result
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{error::Error, query::Query, value::Value};
+
+    use super::parallel;
+
+    #[test]
+    fn test_keep_order() {
+        let program = Query::compile(". * 10").unwrap().to_program();
+        let mut outputs = vec![];
+        let result = parallel(
+            (0..50).collect(),
+            4,
+            || Query::from_program(&program),
+            |query: &mut Query, i: i32| {
+                if i % 7 == 0 {
+                    thread::sleep(Duration::from_millis(5));
+                }
+                query.run_value(Value::from(i))
+            },
+            |results| {
+                outputs.extend(results);
+                Ok(())
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            outputs,
+            (0..50).map(|i| Value::from(i * 10)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_stop_at_error() {
+        let mut outputs = vec![];
+        let result = parallel(
+            vec![1, 2, 0, 4],
+            3,
+            || Ok(()),
+            |_, i: i32| {
+                if i == 0 {
+                    Err(Error::Message("zero".to_owned()))
+                } else {
+                    Ok(i)
+                }
+            },
+            |i| {
+                outputs.push(i);
+                Ok(())
+            },
+        );
+        assert_eq!(result, Err(Error::Message("zero".to_owned())));
+        assert_eq!(outputs, [1, 2]);
+
+        let result = parallel(
+            vec![1, 2],
+            2,
+            || Err::<(), _>(Error::Message("init".to_owned())),
+            |_, i: i32| Ok(i),
+            |_| Ok(()),
+        );
+        assert_eq!(result, Err(Error::Message("init".to_owned())));
+    }
+}
@@ -0,0 +1,891 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Reading the "almost JSON" documents, i.e. JSON with comments (JSONC)
+// and JSON5, into the ASON data model.
+//
+// - `null` is converted to `Option::None`.
+// - integers are converted to `i32`, or `i64`/`u64` if they do not fit,
+//   other numbers are converted to `f64`.
+//
+// And writing the values as (strict) JSON, the types which JSON lacks
+// are converted, see `to_json_string`.
+//
+// The conversion of `to_json_string` loses the types, the reversible
+// one is `to_tagged_json` (i.e. `aq --to json` and `tojson`), which writes the values
+// that JSON lacks as the tagged objects, and the strict JSON documents
+// (i.e. `aq --from json` and `fromjson`) are read with the tagged objects
+// decoded:
+//
+// - the numbers other than `i32` and `f64` (and the `i64`/`u64` which
+//   do not fit in `i32`), e.g. `{"$u8": 17}`, `{"$f32": 1.5}`, and the
+//   `NaN` and infinities, e.g. `{"$f64": "NaN"}`, `{"$f64": "-Infinity"}`,
+// - chars, e.g. `{"$char": "a"}`,
+// - datetimes, e.g. `{"$datetime": "2024-01-01T00:00:00+00:00"}`,
+// - byte data in base64, e.g. `{"$bytes": "AQID"}`,
+// - tuples, e.g. `{"$tuple": [1, "a"]}`,
+// - variants (except `Option::None` which is `null`), e.g.
+//   `{"$variant": "Color::Red"}`, `{"$variant": "Option::Some", "value": 1}`,
+//   `{"$variant": "Shape::Rect", "tuple": [1, 2]}` and
+//   `{"$variant": "Shape::Circle", "object": {"r": 1}}`,
+// - the objects which would be read as the tagged objects, e.g.
+//   `{"$object": {"$char": "a"}}`.
+//
+// the other objects (e.g. `{"$ref": "#/a"}` of JSON Schema) are kept.
+
+use std::{iter::Peekable, str::Chars, sync::Arc};
+
+use crate::{
+    error::Error,
+    format::{decode_base64, encode_base64},
+    value::{Map, Number, Value, Variant, VariantValue},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum JsonDialect {
+    /// The strict JSON, the tagged objects are decoded.
+    Json,
+
+    /// JSON with comments and trailing commas.
+    Jsonc,
+
+    /// JSONC plus the unquoted keys, single-quoted strings,
+    /// hexadecimal numbers, `Infinity`, `NaN` etc.
+    Json5,
+}
+
+pub fn parse_json_from_str(text: &str, dialect: JsonDialect) -> Result<Value, Error> {
+    let mut parser = JsonParser {
+        chars: text.chars().peekable(),
+        dialect,
+        line: 1,
+        column: 1,
+    };
+
+    let value = parser.parse_value()?;
+    parser.skip_whitespace()?;
+    match parser.chars.peek().copied() {
+        None if dialect == JsonDialect::Json => decode_tagged(value),
+        None => Ok(value),
+        Some(c) => Err(parser.error(&format!("Unexpected char '{}' after the document.", c))),
+    }
+}
+
+struct JsonParser<'a> {
+    chars: Peekable<Chars<'a>>,
+    dialect: JsonDialect,
+    line: usize,
+    column: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::Message(format!(
+            "{} (at line {}, column {})",
+            message, self.line, self.column
+        ))
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect_char(&mut self) -> Result<char, Error> {
+        self.next_char()
+            .ok_or_else(|| self.error("Unexpected end of the document."))
+    }
+
+    fn is_json5(&self) -> bool {
+        self.dialect == JsonDialect::Json5
+    }
+
+    // skip the whitespaces and the comments
+    fn skip_whitespace(&mut self) -> Result<(), Error> {
+        loop {
+            match self.chars.peek() {
+                Some(c) if c.is_whitespace() => {
+                    self.next_char();
+                }
+                Some('/') if self.dialect == JsonDialect::Json => {
+                    return Err(self.error("The comments are not allowed in JSON."));
+                }
+                Some('/') => {
+                    self.next_char();
+                    match self.expect_char()? {
+                        '/' => {
+                            while let Some(c) = self.next_char() {
+                                if c == '\n' {
+                                    break;
+                                }
+                            }
+                        }
+                        '*' => {
+                            let mut last = ' ';
+                            loop {
+                                let c = self
+                                    .next_char()
+                                    .ok_or_else(|| self.error("Unterminated block comment."))?;
+                                if last == '*' && c == '/' {
+                                    break;
+                                }
+                                last = c;
+                            }
+                        }
+                        _ => return Err(self.error("Invalid comment.")),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        self.skip_whitespace()?;
+        match self.chars.peek().copied() {
+            Some('{') => self.parse_object(),
+            Some('[') => self.parse_array(),
+            Some('"') => Ok(Value::String(self.parse_string()?)),
+            Some('\'') if self.is_json5() => Ok(Value::String(self.parse_string()?)),
+            Some(c) if c == '-' || c == '+' || c == '.' || c.is_ascii_digit() => {
+                self.parse_number()
+            }
+            Some(c) if c.is_alphabetic() => {
+                let word = self.parse_word();
+                match word.as_str() {
+                    "null" => Ok(Value::null()),
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    "Infinity" if self.is_json5() => Ok(Value::Number(Number::F64(f64::INFINITY))),
+                    "NaN" if self.is_json5() => Ok(Value::Number(Number::F64(f64::NAN))),
+                    _ => Err(self.error(&format!("Unexpected identifier \"{}\".", word))),
+                }
+            }
+            Some(c) => Err(self.error(&format!("Unexpected char '{}'.", c))),
+            None => Err(self.error("Unexpected end of the document.")),
+        }
+    }
+
+    fn parse_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_alphanumeric() || c == '_' || c == '$' {
+                word.push(c);
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+        word
+    }
+
+    // consume the separator `,` and return true if there is another item,
+    // the trailing comma is allowed except in the strict JSON.
+    fn more_items(&mut self, close: char) -> Result<bool, Error> {
+        self.skip_whitespace()?;
+        match self.expect_char()? {
+            ',' => {
+                self.skip_whitespace()?;
+                if self.chars.peek() == Some(&close) && self.dialect == JsonDialect::Json {
+                    return Err(self.error("The trailing commas are not allowed in JSON."));
+                }
+                if self.chars.peek() == Some(&close) {
+                    self.next_char();
+                    Ok(false)
+                } else {
+                    Ok(true)
+                }
+            }
+            c if c == close => Ok(false),
+            c => Err(self.error(&format!("Expect ',' or '{}', found '{}'.", close, c))),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, Error> {
+        self.next_char(); // consume '['
+        let mut items = vec![];
+
+        self.skip_whitespace()?;
+        if self.chars.peek() == Some(&']') {
+            self.next_char();
+            return Ok(Value::List(items.into()));
+        }
+
+        loop {
+            items.push(self.parse_value()?);
+            if !self.more_items(']')? {
+                break;
+            }
+        }
+        Ok(Value::List(items.into()))
+    }
+
+    fn parse_object(&mut self) -> Result<Value, Error> {
+        self.next_char(); // consume '{'
+        let mut map = Map::new();
+
+        self.skip_whitespace()?;
+        if self.chars.peek() == Some(&'}') {
+            self.next_char();
+            return Ok(Value::Object(map));
+        }
+
+        loop {
+            self.skip_whitespace()?;
+            let key = match self.chars.peek().copied() {
+                Some('"') => self.parse_string()?,
+                Some('\'') if self.is_json5() => self.parse_string()?,
+                Some(c) if self.is_json5() && (c.is_alphabetic() || c == '_' || c == '$') => {
+                    self.parse_word()
+                }
+                _ => return Err(self.error("Expect an object key.")),
+            };
+
+            self.skip_whitespace()?;
+            if self.expect_char()? != ':' {
+                return Err(self.error("Expect ':' after the object key."));
+            }
+
+            let value = self.parse_value()?;
+            map.insert(key, value);
+
+            if !self.more_items('}')? {
+                break;
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn parse_string(&mut self) -> Result<String, Error> {
+        let quote = self.expect_char()?;
+        let mut s = String::new();
+
+        loop {
+            match self.expect_char()? {
+                c if c == quote => break,
+                '\\' => match self.expect_char()? {
+                    '"' => s.push('"'),
+                    '\'' => s.push('\''),
+                    '\\' => s.push('\\'),
+                    '/' => s.push('/'),
+                    'b' => s.push('\u{8}'),
+                    'f' => s.push('\u{c}'),
+                    'n' => s.push('\n'),
+                    'r' => s.push('\r'),
+                    't' => s.push('\t'),
+                    'u' => s.push(self.parse_unicode_escape()?),
+                    '0' if self.is_json5() => s.push('\0'),
+                    // line continuation
+                    '\n' if self.is_json5() => {}
+                    c => return Err(self.error(&format!("Unsupported escape char '{}'.", c))),
+                },
+                '\n' => return Err(self.error("Unterminated string.")),
+                c => s.push(c),
+            }
+        }
+
+        Ok(s)
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, Error> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let c = self.expect_char()?;
+            let digit = c
+                .to_digit(16)
+                .ok_or_else(|| self.error("Invalid unicode escape."))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn parse_unicode_escape(&mut self) -> Result<char, Error> {
+        let high = self.parse_hex4()?;
+
+        // surrogate pair
+        let code = if (0xD800..0xDC00).contains(&high) {
+            if self.expect_char()? != '\\' || self.expect_char()? != 'u' {
+                return Err(self.error("Expect the low surrogate."));
+            }
+            let low = self.parse_hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error("Invalid low surrogate."));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode code point."))
+    }
+
+    fn parse_number(&mut self) -> Result<Value, Error> {
+        let mut text = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.') {
+                text.push(c);
+                self.next_char();
+            } else {
+                break;
+            }
+        }
+
+        let (negative, body) = match text.as_bytes().first() {
+            Some(b'-') => (true, &text[1..]),
+            Some(b'+') if self.is_json5() => (false, &text[1..]),
+            _ => (false, text.as_str()),
+        };
+
+        if self.is_json5() {
+            if body == "Infinity" {
+                let f = if negative {
+                    f64::NEG_INFINITY
+                } else {
+                    f64::INFINITY
+                };
+                return Ok(Value::Number(Number::F64(f)));
+            }
+            if body == "NaN" {
+                return Ok(Value::Number(Number::F64(f64::NAN)));
+            }
+            if let Some(hex) = body.strip_prefix("0x").or_else(|| body.strip_prefix("0X")) {
+                let n = i128::from_str_radix(hex, 16)
+                    .map_err(|_| self.error(&format!("Invalid number \"{}\".", text)))?;
+                return Ok(Value::Number(integer_number(if negative { -n } else { n })));
+            }
+        } else if body.starts_with('.') || body.ends_with('.') {
+            return Err(self.error(&format!("Invalid number \"{}\".", text)));
+        }
+
+        if body.bytes().all(|b| b.is_ascii_digit()) {
+            if let Ok(n) = body.parse::<i128>() {
+                return Ok(Value::Number(integer_number(if negative { -n } else { n })));
+            }
+        }
+
+        match body.parse::<f64>() {
+            Ok(f) if !body.is_empty() && !body.starts_with(['i', 'I', 'n', 'N']) => {
+                Ok(Value::Number(Number::F64(if negative { -f } else { f })))
+            }
+            _ => Err(self.error(&format!("Invalid number \"{}\".", text))),
+        }
+    }
+}
+
+pub fn integer_number(n: i128) -> Number {
+    if let Ok(i) = i32::try_from(n) {
+        Number::I32(i)
+    } else if let Ok(i) = i64::try_from(n) {
+        Number::I64(i)
+    } else if let Ok(u) = u64::try_from(n) {
+        Number::U64(u)
+    } else {
+        Number::F64(n as f64)
+    }
+}
+
+/// Write the value as compact JSON text.
+///
+/// - `Option::None` is written as `null`, and `Option::Some(v)` as `v`.
+/// - the type suffixes of numbers are dropped, `NaN` and `Inf` are
+///   written as `null` (the same as `JSON.stringify`).
+/// - chars and datetimes are written as strings, byte data and tuples
+///   are written as arrays.
+/// - other variants are written as `"Type::Member"`, or as
+///   `{"Type::Member": value}` when they have values.
+pub fn to_json_string(value: &Value) -> String {
+    let mut text = String::new();
+    write_json(value, &mut text);
+    text
+}
+
+fn write_json(value: &Value, text: &mut String) {
+    match value {
+        _ if value.is_null() => text.push_str("null"),
+        Value::Number(n) if n.is_float() && !n.as_f64().is_finite() => text.push_str("null"),
+        Value::Number(n) => text.push_str(&n.plain_text()),
+        Value::Boolean(b) => text.push_str(if *b { "true" } else { "false" }),
+        Value::Char(c) => write_json_string(&c.to_string(), text),
+        Value::String(s) => write_json_string(s, text),
+        Value::DateTime(d) => write_json_string(&d.to_rfc3339(), text),
+        Value::ByteData(bytes) => {
+            let items: Vec<Value> = bytes
+                .iter()
+                .map(|b| Value::Number(Number::U8(*b)))
+                .collect();
+            write_json_array(&items, text);
+        }
+        Value::List(items) | Value::Tuple(items) => write_json_array(items, text),
+        Value::Object(map) => write_json_object(map.iter().map(|(k, v)| (k.as_str(), v)), text),
+        Value::Variant(v) => {
+            let name = format!("{}::{}", v.type_name, v.member_name);
+            match &v.value {
+                VariantValue::Empty => write_json_string(&name, text),
+                VariantValue::Value(inner) if v.type_name == "Option" => write_json(inner, text),
+                VariantValue::Value(inner) => {
+                    write_json_object(std::iter::once((name.as_str(), inner.as_ref())), text)
+                }
+                VariantValue::Tuple(items) => {
+                    let items = Value::Tuple(items.clone());
+                    write_json_object(std::iter::once((name.as_str(), &items)), text)
+                }
+                VariantValue::Object(map) => {
+                    let map = Value::Object(map.clone());
+                    write_json_object(std::iter::once((name.as_str(), &map)), text)
+                }
+            }
+        }
+    }
+}
+
+fn write_json_array(items: &[Value], text: &mut String) {
+    text.push('[');
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            text.push(',');
+        }
+        write_json(item, text);
+    }
+    text.push(']');
+}
+
+fn write_json_object<'a>(entries: impl Iterator<Item = (&'a str, &'a Value)>, text: &mut String) {
+    text.push('{');
+    for (idx, (key, value)) in entries.enumerate() {
+        if idx > 0 {
+            text.push(',');
+        }
+        write_json_string(key, text);
+        text.push(':');
+        write_json(value, text);
+    }
+    text.push('}');
+}
+
+fn write_json_string(s: &str, text: &mut String) {
+    text.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            '\u{8}' => text.push_str("\\b"),
+            '\u{c}' => text.push_str("\\f"),
+            _ if (c as u32) < 0x20 || c == '\u{7f}' => {
+                text.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            _ => text.push(c),
+        }
+    }
+    text.push('"');
+}
+
+/// Replace the non-ASCII characters of the JSON text with the `\uXXXX`
+/// escapes (the surrogate pairs for the supplementary characters),
+/// they only appear in the strings.
+pub fn escape_non_ascii_json(text: &str) -> String {
+    let mut buf = String::with_capacity(text.len());
+    let mut units = [0u16; 2];
+    for c in text.chars() {
+        if c.is_ascii() {
+            buf.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut units) {
+                buf.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    buf
+}
+
+const NUMBER_TAGS: [&str; 10] = [
+    "$i8", "$u8", "$i16", "$u16", "$i32", "$u32", "$i64", "$u64", "$f32", "$f64",
+];
+
+/// Write the value as JSON with the reversible encoding of the types
+/// which JSON lacks (see the top of this file), the text is compact
+/// if the indentation is `None`.
+pub fn to_tagged_json(value: &Value, indent: Option<&str>) -> String {
+    let mut text = String::new();
+    write_tagged(&encode_tagged(value), indent, 0, &mut text);
+    text
+}
+
+// convert the value to the JSON data model, i.e. the values of
+// the types which `parse_json_from_str` produces.
+fn encode_tagged(value: &Value) -> Value {
+    let tagged = |tag: &str, value: Value| {
+        let mut map = Map::new();
+        map.insert(tag.to_owned(), value);
+        Value::Object(map)
+    };
+
+    match value {
+        _ if value.is_null() => Value::null(),
+        Value::Number(n) => {
+            let natural = match n {
+                Number::F64(f) => f.is_finite(),
+                Number::I32(_) => true,
+                Number::I64(i) => i32::try_from(*i).is_err(),
+                Number::U64(u) => i64::try_from(*u).is_err(),
+                _ => false,
+            };
+            if natural {
+                Value::Number(*n)
+            } else if n.is_float() && !n.as_f64().is_finite() {
+                let f = n.as_f64();
+                let name = if f.is_nan() {
+                    "NaN"
+                } else if f > 0.0 {
+                    "Infinity"
+                } else {
+                    "-Infinity"
+                };
+                tagged(
+                    &format!("${}", n.type_suffix()),
+                    Value::String(name.to_owned()),
+                )
+            } else {
+                tagged(&format!("${}", n.type_suffix()), Value::Number(*n))
+            }
+        }
+        Value::Boolean(_) | Value::String(_) => value.clone(),
+        Value::Char(c) => tagged("$char", Value::String(c.to_string())),
+        Value::DateTime(d) => tagged("$datetime", Value::String(d.to_rfc3339())),
+        Value::ByteData(bytes) => tagged("$bytes", Value::String(encode_base64(bytes))),
+        Value::List(items) => Value::List(items.iter().map(encode_tagged).collect()),
+        Value::Tuple(items) => tagged(
+            "$tuple",
+            Value::List(items.iter().map(encode_tagged).collect()),
+        ),
+        Value::Object(map) => {
+            let encoded = Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), encode_tagged(v)))
+                    .collect(),
+            );
+            if tag_of(map).is_some() {
+                tagged("$object", encoded)
+            } else {
+                encoded
+            }
+        }
+        Value::Variant(v) => {
+            let mut map = Map::new();
+            map.insert(
+                "$variant".to_owned(),
+                Value::String(format!("{}::{}", v.type_name, v.member_name)),
+            );
+            match &v.value {
+                VariantValue::Empty => {}
+                VariantValue::Value(inner) => {
+                    map.insert("value".to_owned(), encode_tagged(inner));
+                }
+                VariantValue::Tuple(items) => {
+                    map.insert(
+                        "tuple".to_owned(),
+                        Value::List(items.iter().map(encode_tagged).collect()),
+                    );
+                }
+                VariantValue::Object(fields) => {
+                    map.insert(
+                        "object".to_owned(),
+                        encode_tagged(&Value::Object(fields.clone())),
+                    );
+                }
+            }
+            Value::Object(map)
+        }
+    }
+}
+
+// the tag of the object if it is a tagged object, i.e. it has a tag key
+// with the value of the expected type (and the payload of a variant).
+fn tag_of(map: &Map) -> Option<&str> {
+    let (key, value) = map.iter().next()?;
+    let matched = match (key.as_str(), value, map.len()) {
+        (tag, Value::Number(_) | Value::String(_), 1) if NUMBER_TAGS.contains(&tag) => true,
+        ("$char" | "$datetime" | "$bytes", Value::String(_), 1) => true,
+        ("$tuple", Value::List(_), 1) => true,
+        ("$object", Value::Object(_), 1) => true,
+        ("$variant", Value::String(name), 1) => name.contains("::"),
+        ("$variant", Value::String(name), 2) => {
+            name.contains("::")
+                && matches!(
+                    map.iter().nth(1).map(|(k, v)| (k.as_str(), v)),
+                    Some(("value", _) | ("tuple", Value::List(_)) | ("object", Value::Object(_)))
+                )
+        }
+        _ => false,
+    };
+    matched.then_some(key.as_str())
+}
+
+/// Decode the tagged objects of the JSON value, see the top of this file.
+pub fn decode_tagged(value: Value) -> Result<Value, Error> {
+    match value {
+        Value::List(items) => Ok(Value::List(
+            items
+                .into_vec()
+                .into_iter()
+                .map(decode_tagged)
+                .collect::<Result<Vec<_>, _>>()?
+                .into(),
+        )),
+        Value::Object(map) => match tag_of(&map).map(str::to_owned) {
+            None => decode_map(map).map(Value::Object),
+            Some(tag) => decode_tagged_object(&tag, map),
+        },
+        _ => Ok(value),
+    }
+}
+
+fn decode_map(map: Map) -> Result<Map, Error> {
+    map.into_iter()
+        .map(|(k, v)| decode_tagged(v).map(|v| (k, v)))
+        .collect()
+}
+
+fn decode_tagged_object(tag: &str, mut map: Map) -> Result<Value, Error> {
+    let invalid =
+        |message: &str| Error::Message(format!("Invalid \"{}\" of JSON: {}", tag, message));
+    let payload = map.remove(tag).unwrap();
+
+    match (tag, payload) {
+        ("$tuple", Value::List(items)) => Ok(Value::Tuple(
+            items
+                .into_vec()
+                .into_iter()
+                .map(decode_tagged)
+                .collect::<Result<Vec<_>, _>>()?
+                .into(),
+        )),
+        ("$object", Value::Object(inner)) => decode_map(inner).map(Value::Object),
+        ("$char", Value::String(s)) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Value::Char(c)),
+                _ => Err(invalid("expect a single-character string.")),
+            }
+        }
+        ("$datetime", Value::String(s)) => chrono::DateTime::parse_from_rfc3339(&s)
+            .map(Value::DateTime)
+            .map_err(|e| invalid(&e.to_string())),
+        ("$bytes", Value::String(s)) => decode_base64(&s).map(Value::ByteData),
+        ("$variant", Value::String(name)) => {
+            let (type_name, member_name) = name.split_once("::").unwrap();
+            let value = match map.into_iter().next() {
+                None => VariantValue::Empty,
+                Some((key, payload)) => match (key.as_str(), decode_tagged(payload)?) {
+                    ("tuple", Value::List(items)) => VariantValue::Tuple(items),
+                    ("object", Value::Object(fields)) => VariantValue::Object(fields),
+                    ("tuple" | "object", _) => unreachable!("the payloads are checked by `tag_of`"),
+                    (_, inner) => VariantValue::Value(Box::new(inner)),
+                },
+            };
+            Ok(Value::Variant(Arc::new(Variant {
+                type_name: type_name.to_owned(),
+                member_name: member_name.to_owned(),
+                value,
+            })))
+        }
+        (_, Value::String(s)) => {
+            let f = match s.as_str() {
+                "NaN" => f64::NAN,
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                _ => return Err(invalid("expect a number, \"NaN\" or \"Infinity\".")),
+            };
+            match tag {
+                "$f32" => Ok(Value::Number(Number::F32(f as f32))),
+                "$f64" => Ok(Value::Number(Number::F64(f))),
+                _ => Err(invalid("expect an integer.")),
+            }
+        }
+        (_, Value::Number(n)) => {
+            let cast = |n: &Number| -> Option<Number> {
+                let number = match tag {
+                    "$f32" => Number::F32(n.as_f64() as f32),
+                    "$f64" => Number::F64(n.as_f64()),
+                    _ => {
+                        let i = n.as_i128()?;
+                        match tag {
+                            "$i8" => Number::I8(i.try_into().ok()?),
+                            "$u8" => Number::U8(i.try_into().ok()?),
+                            "$i16" => Number::I16(i.try_into().ok()?),
+                            "$u16" => Number::U16(i.try_into().ok()?),
+                            "$i32" => Number::I32(i.try_into().ok()?),
+                            "$u32" => Number::U32(i.try_into().ok()?),
+                            "$i64" => Number::I64(i.try_into().ok()?),
+                            _ => Number::U64(i.try_into().ok()?),
+                        }
+                    }
+                };
+                Some(number)
+            };
+            cast(&n)
+                .map(Value::Number)
+                .ok_or_else(|| invalid(&format!("{} is out of the range.", n.plain_text())))
+        }
+        _ => unreachable!("the tags are checked by `tag_of`"),
+    }
+}
+
+// the floats are written with the fraction (e.g. `1.0`), so
+// they are not read as integers.
+fn write_tagged(value: &Value, indent: Option<&str>, level: usize, text: &mut String) {
+    let newline = |text: &mut String, level: usize| {
+        if let Some(indent) = indent {
+            text.push('\n');
+            text.push_str(&indent.repeat(level));
+        }
+    };
+
+    match value {
+        Value::Number(Number::F64(f)) => text.push_str(&format!("{:?}", f)),
+        Value::List(items) if !items.is_empty() => {
+            text.push('[');
+            for (idx, item) in items.iter().enumerate() {
+                if idx > 0 {
+                    text.push(',');
+                }
+                newline(text, level + 1);
+                write_tagged(item, indent, level + 1, text);
+            }
+            newline(text, level);
+            text.push(']');
+        }
+        Value::Object(map) if !map.is_empty() => {
+            text.push('{');
+            for (idx, (key, item)) in map.iter().enumerate() {
+                if idx > 0 {
+                    text.push(',');
+                }
+                newline(text, level + 1);
+                write_json_string(key, text);
+                text.push(':');
+                if indent.is_some() {
+                    text.push(' ');
+                }
+                write_tagged(item, indent, level + 1, text);
+            }
+            newline(text, level);
+            text.push('}');
+        }
+        _ => write_json(value, text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::{Map, Number, Value},
+    };
+
+    use super::{escape_non_ascii_json, parse_json_from_str, to_tagged_json, JsonDialect};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    fn parse(text: &str, dialect: JsonDialect) -> String {
+        parse_json_from_str(text, dialect).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_parse_jsonc() {
+        let text =
+            "// config\n{\"a\": [1, 2,], /* b */ \"b\": null, \"c\": 1.5e1, \"d\": 3000000000,}";
+        assert_eq!(
+            parse(text, JsonDialect::Jsonc),
+            "{a:[1,2],b:Option::None,c:15.0,d:3000000000_i64}"
+        );
+
+        // the keys must be quoted
+        assert!(parse_json_from_str("{a: 1}", JsonDialect::Jsonc).is_err());
+    }
+
+    #[test]
+    fn test_parse_json5() {
+        let text = r#"{a: 'x\'y', $b: 0x1F, c: +.5, d: -Infinity, e: "😀"}"#;
+        assert_eq!(
+            parse(text, JsonDialect::Json5),
+            r#"{a:"x'y","$b":31,c:0.5,d:-Inf,e:"😀"}"#
+        );
+        assert!(parse_json_from_str("[1] x", JsonDialect::Json5)
+            .unwrap_err()
+            .to_string()
+            .contains("line 1, column 5"));
+    }
+
+    #[test]
+    fn test_tagged_json() {
+        let document = value(
+            "[1_u8, 2.0, 'c', Option::Some(3), Option::None, Color::Red, 3_000_000_000_i64, 5_i64, h\"01 02\", (1, [\"x\"])]",
+        );
+        let text = to_tagged_json(&document, None);
+        assert_eq!(
+            text,
+            "[{\"$u8\":1},2.0,{\"$char\":\"c\"},{\"$variant\":\"Option::Some\",\"value\":3},null,{\"$variant\":\"Color::Red\"},3000000000,{\"$i64\":5},{\"$bytes\":\"AQI=\"},{\"$tuple\":[1,[\"x\"]]}]"
+        );
+        assert_eq!(
+            parse_json_from_str(&text, JsonDialect::Json).unwrap(),
+            document
+        );
+
+        // the objects which look like the tagged ones are escaped
+        let mut map = Map::new();
+        map.insert("$char".to_owned(), Value::String("y".to_owned()));
+        let object = Value::Object(map);
+        let text = to_tagged_json(&object, None);
+        assert_eq!(text, "{\"$object\":{\"$char\":\"y\"}}");
+        assert_eq!(
+            parse_json_from_str(&text, JsonDialect::Json).unwrap(),
+            object
+        );
+
+        assert_eq!(
+            to_tagged_json(&Value::Number(Number::F64(f64::NEG_INFINITY)), None),
+            "{\"$f64\":\"-Infinity\"}"
+        );
+        assert_eq!(
+            parse(
+                r#"{"s": {"$variant": "Shape::Rect", "object": {"w": {"$u16": 3}}}, "r": {"$ref": "x"}}"#,
+                JsonDialect::Json
+            ),
+            "{s:Shape::Rect{w:3_u16},r:{\"$ref\":\"x\"}}"
+        );
+        assert_eq!(
+            to_tagged_json(&value("{a: [1, {}], b: []}"), Some("  ")),
+            "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": []\n}"
+        );
+
+        // the strict JSON
+        assert!(parse_json_from_str("[1,]", JsonDialect::Json).is_err());
+        assert!(parse_json_from_str("// c\n1", JsonDialect::Json).is_err());
+        assert!(parse_json_from_str(r#"{"$u8": 300}"#, JsonDialect::Json).is_err());
+    }
+
+    #[test]
+    fn test_escape_non_ascii_json() {
+        // the supplementary characters are the surrogate pairs
+        assert_eq!(
+            escape_non_ascii_json(r#"{"clé":"café 😀","k":["ü"]}"#),
+            r#"{"cl\u00e9":"caf\u00e9 \ud83d\ude00","k":["\u00fc"]}"#
+        );
+    }
+}
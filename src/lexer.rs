@@ -0,0 +1,618 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use crate::{
+    error::{Error, Location},
+    value::Number,
+};
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Token {
+    Dot,              // `.`
+    DotDot,           // `..`
+    Field(String),    // `.name`
+    Element(usize),   // `.0`, the element of a tuple (or a list)
+    Variable(String), // `$name`
+    Format(String),   // `@name`
+
+    // including keywords, e.g. `if`, `def`, `reduce`, `and`.
+    Identifier(String),
+
+    Number(Number),
+    String(Vec<StringPart>),
+
+    // the minimum of a signed type without the minus sign, e.g. `128_i8`,
+    // which is only valid after `-`, the number is the minimum itself.
+    NegativeMinimum(Number),
+
+    Pipe,       // `|`
+    Comma,      // `,`
+    Colon,      // `:`
+    ColonColon, // `::`
+    Semicolon,  // `;`
+    Question,   // `?`
+
+    Plus,    // `+`
+    Minus,   // `-`
+    Star,    // `*`
+    Slash,   // `/`
+    Percent, // `%`
+
+    Equal,          // `==`
+    NotEqual,       // `!=`
+    Less,           // `<`
+    LessOrEqual,    // `<=`
+    Greater,        // `>`
+    GreaterOrEqual, // `>=`
+
+    Alternative, // `//`
+
+    Assign,            // `=`
+    UpdateAssign,      // `|=`
+    AddAssign,         // `+=`
+    SubtractAssign,    // `-=`
+    MultiplyAssign,    // `*=`
+    DivideAssign,      // `/=`
+    RemainderAssign,   // `%=`
+    AlternativeAssign, // `//=`
+
+    LeftParen,    // `(`
+    RightParen,   // `)`
+    LeftBracket,  // `[`
+    RightBracket, // `]`
+    LeftBrace,    // `{`
+    RightBrace,   // `}`
+}
+
+/// A string literal is made up of literal text and interpolations,
+/// e.g. `"Order \(.id): \(.total) USD"`.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Literal(String),
+    Interpolation(Vec<TokenWithLocation>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TokenWithLocation {
+    pub token: Token,
+    pub location: Location,
+}
+
+pub fn lex_from_str(source: &str) -> Result<Vec<TokenWithLocation>, Error> {
+    let mut lexer = Lexer {
+        chars: source.chars().collect(),
+        position: 0,
+    };
+    lexer.lex(false)
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    position: usize,
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_ascii_alphabetic() || c == '_'
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl Lexer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.position + offset).copied()
+    }
+
+    fn peek_equals(&self, offset: usize, expected: char) -> bool {
+        self.peek(offset) == Some(expected)
+    }
+
+    fn error(&self, msg: &str, index: usize) -> Error {
+        Error::MessageWithLocation(msg.to_owned(), Location::new(index, 1))
+    }
+
+    fn skip_whitespaces_and_comments(&mut self) {
+        while let Some(c) = self.peek(0) {
+            if c.is_whitespace() {
+                self.position += 1;
+            } else if c == '#' {
+                // line comment
+                while let Some(c) = self.peek(0) {
+                    if c == '\n' {
+                        break;
+                    }
+                    self.position += 1;
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    // Tokenize until the end of the source, or until the closing
+    // parenthesis of a string interpolation when `in_interpolation` is true.
+    fn lex(&mut self, in_interpolation: bool) -> Result<Vec<TokenWithLocation>, Error> {
+        let mut tokens = vec![];
+        let mut depth = 0;
+
+        loop {
+            self.skip_whitespaces_and_comments();
+
+            let start = self.position;
+            let c = match self.peek(0) {
+                Some(c) => c,
+                None if in_interpolation => {
+                    return Err(Error::UnexpectedEndOfQuery(
+                        "Incomplete string interpolation.".to_owned(),
+                    ));
+                }
+                None => break,
+            };
+
+            let token = match c {
+                '.' => {
+                    if self.peek_equals(1, '.') {
+                        self.position += 2;
+                        Token::DotDot
+                    } else if matches!(self.peek(1), Some(c) if is_identifier_start(c)) {
+                        self.position += 1;
+                        Token::Field(self.lex_identifier())
+                    } else if matches!(self.peek(1), Some(c) if c.is_ascii_digit()) {
+                        // only the digits are taken, so that `.0.1` is
+                        // two elements rather than a number.
+                        self.position += 1;
+                        let mut buf = String::new();
+                        while let Some(c) = self.peek(0).filter(|c| c.is_ascii_digit()) {
+                            buf.push(c);
+                            self.position += 1;
+                        }
+                        Token::Element(buf.parse().map_err(|_| {
+                            self.error(&format!("Element index \"{}\" is too large.", buf), start)
+                        })?)
+                    } else {
+                        self.position += 1;
+                        Token::Dot
+                    }
+                }
+                '$' => {
+                    self.position += 1;
+                    if !matches!(self.peek(0), Some(c) if is_identifier_start(c)) {
+                        return Err(self.error("Expect a variable name after \"$\".", start));
+                    }
+                    Token::Variable(self.lex_identifier())
+                }
+                '@' => {
+                    self.position += 1;
+                    if !matches!(self.peek(0), Some(c) if is_identifier_start(c)) {
+                        return Err(self.error("Expect a format name after \"@\".", start));
+                    }
+                    Token::Format(self.lex_identifier())
+                }
+                '"' => Token::String(self.lex_string()?),
+                '0'..='9' => self.lex_number()?,
+                _ if is_identifier_start(c) => Token::Identifier(self.lex_identifier()),
+                _ => {
+                    let (token, length) = match (c, self.peek(1)) {
+                        ('=', Some('=')) => (Token::Equal, 2),
+                        ('!', Some('=')) => (Token::NotEqual, 2),
+                        ('<', Some('=')) => (Token::LessOrEqual, 2),
+                        ('>', Some('=')) => (Token::GreaterOrEqual, 2),
+                        ('/', Some('/')) if self.peek(2) == Some('=') => {
+                            (Token::AlternativeAssign, 3)
+                        }
+                        ('/', Some('/')) => (Token::Alternative, 2),
+                        ('|', Some('=')) => (Token::UpdateAssign, 2),
+                        ('+', Some('=')) => (Token::AddAssign, 2),
+                        ('-', Some('=')) => (Token::SubtractAssign, 2),
+                        ('*', Some('=')) => (Token::MultiplyAssign, 2),
+                        ('/', Some('=')) => (Token::DivideAssign, 2),
+                        ('%', Some('=')) => (Token::RemainderAssign, 2),
+                        ('=', _) => (Token::Assign, 1),
+                        ('<', _) => (Token::Less, 1),
+                        ('>', _) => (Token::Greater, 1),
+                        ('|', _) => (Token::Pipe, 1),
+                        (',', _) => (Token::Comma, 1),
+                        (':', Some(':')) => (Token::ColonColon, 2),
+                        (':', _) => (Token::Colon, 1),
+                        (';', _) => (Token::Semicolon, 1),
+                        ('?', _) => (Token::Question, 1),
+                        ('+', _) => (Token::Plus, 1),
+                        ('-', _) => (Token::Minus, 1),
+                        ('*', _) => (Token::Star, 1),
+                        ('/', _) => (Token::Slash, 1),
+                        ('%', _) => (Token::Percent, 1),
+                        ('(', _) => {
+                            depth += 1;
+                            (Token::LeftParen, 1)
+                        }
+                        (')', _) => {
+                            if in_interpolation && depth == 0 {
+                                // the end of the interpolation
+                                self.position += 1;
+                                return Ok(tokens);
+                            }
+                            depth -= 1;
+                            (Token::RightParen, 1)
+                        }
+                        ('[', _) => (Token::LeftBracket, 1),
+                        (']', _) => (Token::RightBracket, 1),
+                        ('{', _) => (Token::LeftBrace, 1),
+                        ('}', _) => (Token::RightBrace, 1),
+                        _ => {
+                            return Err(self.error(&format!("Unexpected char '{}'.", c), start));
+                        }
+                    };
+                    self.position += length;
+                    token
+                }
+            };
+
+            tokens.push(TokenWithLocation {
+                token,
+                location: Location::new(start, self.position - start),
+            });
+        }
+
+        Ok(tokens)
+    }
+
+    fn lex_identifier(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(c) = self.peek(0) {
+            if !is_identifier_char(c) {
+                break;
+            }
+            name.push(c);
+            self.position += 1;
+        }
+        name
+    }
+
+    fn lex_digits(&mut self, buf: &mut String) {
+        while let Some(c) = self.peek(0) {
+            if c.is_ascii_digit() {
+                buf.push(c);
+            } else if c == '_' && matches!(self.peek(1), Some(d) if d.is_ascii_digit()) {
+                // digit separator, e.g. `1_000_000`
+            } else {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+
+    fn lex_number(&mut self) -> Result<Token, Error> {
+        let start = self.position;
+        let mut buf = String::new();
+        let mut is_float = false;
+
+        self.lex_digits(&mut buf);
+
+        if self.peek_equals(0, '.') && matches!(self.peek(1), Some(c) if c.is_ascii_digit()) {
+            is_float = true;
+            buf.push('.');
+            self.position += 1;
+            self.lex_digits(&mut buf);
+        }
+
+        if matches!(self.peek(0), Some('e' | 'E')) {
+            let sign = matches!(self.peek(1), Some('+' | '-'));
+            let digit_offset = if sign { 2 } else { 1 };
+            if matches!(self.peek(digit_offset), Some(c) if c.is_ascii_digit()) {
+                is_float = true;
+                buf.push('e');
+                if sign {
+                    buf.push(self.peek(1).unwrap());
+                }
+                self.position += digit_offset;
+                self.lex_digits(&mut buf);
+            }
+        }
+
+        // the type suffix, e.g. `11_i8`, `3.14_f32`
+        let mut suffix = String::new();
+        if self.peek_equals(0, '_') && matches!(self.peek(1), Some('i' | 'u' | 'f')) {
+            self.position += 1;
+            suffix = self.lex_identifier();
+        }
+
+        let number_error = |msg: String| {
+            Error::MessageWithLocation(msg, Location::new(start, self.position - start))
+        };
+
+        let number = match suffix.as_str() {
+            "" if is_float => Number::F64(buf.parse().unwrap()),
+            // integers out of the range of `i32` are promoted to
+            // `i64`, and then `u64`, e.g. `18446744073709551615`.
+            "" => match (buf.parse::<i32>(), buf.parse::<i64>(), buf.parse::<u64>()) {
+                (Ok(v), _, _) => Number::I32(v),
+                (_, Ok(v), _) => Number::I64(v),
+                (_, _, Ok(v)) => Number::U64(v),
+                _ => {
+                    return Err(number_error(format!(
+                        "Integer number \"{}\" is out of range.",
+                        buf
+                    )));
+                }
+            },
+            "f32" => Number::F32(buf.parse().unwrap()),
+            "f64" => Number::F64(buf.parse().unwrap()),
+            _ if is_float => {
+                return Err(number_error(format!(
+                    "Floating point number \"{}\" can not have the type \"{}\".",
+                    buf, suffix
+                )));
+            }
+            _ => {
+                let out_of_range = || {
+                    number_error(format!(
+                        "Number \"{}\" is out of the range of \"{}\".",
+                        buf, suffix
+                    ))
+                };
+                let number = match suffix.as_str() {
+                    "i8" => buf.parse().ok().map(Number::I8),
+                    "u8" => buf.parse().ok().map(Number::U8),
+                    "i16" => buf.parse().ok().map(Number::I16),
+                    "u16" => buf.parse().ok().map(Number::U16),
+                    "i32" => buf.parse().ok().map(Number::I32),
+                    "u32" => buf.parse().ok().map(Number::U32),
+                    "i64" => buf.parse().ok().map(Number::I64),
+                    "u64" => buf.parse().ok().map(Number::U64),
+                    _ => {
+                        return Err(number_error(format!("Unknown number type \"{}\".", suffix)));
+                    }
+                };
+                if let Some(n) = number {
+                    return Ok(Token::Number(n));
+                }
+
+                // the magnitude of the minimum of a signed type, e.g.
+                // `128_i8`, is valid after `-`, i.e. `-128_i8`.
+                let minimum = match suffix.as_str() {
+                    "i8" => Number::I8(i8::MIN),
+                    "i16" => Number::I16(i16::MIN),
+                    "i32" => Number::I32(i32::MIN),
+                    "i64" => Number::I64(i64::MIN),
+                    _ => return Err(out_of_range()),
+                };
+                return match buf.parse::<i128>() {
+                    Ok(v) if Some(-v) == minimum.as_i128() => Ok(Token::NegativeMinimum(minimum)),
+                    _ => Err(out_of_range()),
+                };
+            }
+        };
+
+        Ok(Token::Number(number))
+    }
+
+    fn lex_string(&mut self) -> Result<Vec<StringPart>, Error> {
+        self.position += 1; // consume the opening quote
+
+        let mut parts = vec![];
+        let mut buf = String::new();
+
+        loop {
+            let c = match self.peek(0) {
+                Some(c) => c,
+                None => {
+                    return Err(Error::UnexpectedEndOfQuery(
+                        "Incomplete string literal.".to_owned(),
+                    ));
+                }
+            };
+
+            self.position += 1;
+
+            match c {
+                '"' => break,
+                '\\' => {
+                    let escape_start = self.position - 1;
+                    let e = self.peek(0).ok_or_else(|| {
+                        Error::UnexpectedEndOfQuery("Incomplete escape sequence.".to_owned())
+                    })?;
+                    self.position += 1;
+
+                    match e {
+                        '(' => {
+                            // string interpolation
+                            if !buf.is_empty() {
+                                parts.push(StringPart::Literal(std::mem::take(&mut buf)));
+                            }
+                            let tokens = self.lex(true)?;
+                            parts.push(StringPart::Interpolation(tokens));
+                        }
+                        '\\' => buf.push('\\'),
+                        '"' => buf.push('"'),
+                        '\'' => buf.push('\''),
+                        '/' => buf.push('/'),
+                        'n' => buf.push('\n'),
+                        'r' => buf.push('\r'),
+                        't' => buf.push('\t'),
+                        '0' => buf.push('\0'),
+                        'u' => buf.push(self.lex_unicode_escape(escape_start)?),
+                        _ => {
+                            return Err(self.error(
+                                &format!("Unsupported escape char '{}'.", e),
+                                escape_start,
+                            ));
+                        }
+                    }
+                }
+                _ => buf.push(c),
+            }
+        }
+
+        if !buf.is_empty() || parts.is_empty() {
+            parts.push(StringPart::Literal(buf));
+        }
+
+        Ok(parts)
+    }
+
+    // `\u{6CB3}`
+    fn lex_unicode_escape(&mut self, escape_start: usize) -> Result<char, Error> {
+        if !self.peek_equals(0, '{') {
+            return Err(self.error("Expect \"{\" after \"\\u\".", escape_start));
+        }
+        self.position += 1;
+
+        let mut hex = String::new();
+        loop {
+            match self.peek(0) {
+                Some('}') => {
+                    self.position += 1;
+                    break;
+                }
+                Some(c) if c.is_ascii_hexdigit() && hex.len() < 6 => {
+                    hex.push(c);
+                    self.position += 1;
+                }
+                _ => {
+                    return Err(self.error("Invalid unicode escape sequence.", escape_start));
+                }
+            }
+        }
+
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error("Invalid unicode code point.", escape_start))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{error::Error, value::Number};
+
+    use super::{lex_from_str, StringPart, Token};
+
+    fn tokens(source: &str) -> Vec<Token> {
+        lex_from_str(source)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.token)
+            .collect()
+    }
+
+    // the tokens of the interpolations are flattened
+    fn string_parts(source: &str) -> Vec<(String, Vec<Token>)> {
+        let tokens = tokens(source);
+        let [Token::String(parts)] = tokens.as_slice() else {
+            panic!("expect a string literal: {}", source);
+        };
+        parts
+            .iter()
+            .map(|p| match p {
+                StringPart::Literal(s) => (s.clone(), vec![]),
+                StringPart::Interpolation(ts) => {
+                    (String::new(), ts.iter().map(|t| t.token.clone()).collect())
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_lex_tokens() {
+        assert_eq!(
+            tokens(".orders[0] | .id // $x # comment"),
+            vec![
+                Token::Field("orders".to_owned()),
+                Token::LeftBracket,
+                Token::Number(Number::I32(0)),
+                Token::RightBracket,
+                Token::Pipe,
+                Token::Field("id".to_owned()),
+                Token::Alternative,
+                Token::Variable("x".to_owned()),
+            ]
+        );
+        assert_eq!(
+            tokens(". |= .. + 1"),
+            vec![
+                Token::Dot,
+                Token::UpdateAssign,
+                Token::DotDot,
+                Token::Plus,
+                Token::Number(Number::I32(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lex_integer_limits() {
+        assert_eq!(
+            tokens("18446744073709551615 9223372036854775808"),
+            vec![
+                Token::Number(Number::U64(u64::MAX)),
+                Token::Number(Number::U64(9223372036854775808)),
+            ]
+        );
+        assert_eq!(
+            tokens("-9223372036854775808_i64 127_i8 128_i8"),
+            vec![
+                Token::Minus,
+                Token::NegativeMinimum(Number::I64(i64::MIN)),
+                Token::Number(Number::I8(127)),
+                Token::NegativeMinimum(Number::I8(i8::MIN)),
+            ]
+        );
+        assert!(matches!(
+            lex_from_str("129_i8"),
+            Err(Error::MessageWithLocation(message, _))
+                if message == "Number \"129\" is out of the range of \"i8\"."
+        ));
+        assert!(lex_from_str("256_u8").is_err());
+    }
+
+    #[test]
+    fn test_lex_string() {
+        assert_eq!(
+            string_parts(r#""a\tb\"\u{4e2d}""#),
+            vec![("a\tb\"中".to_owned(), vec![])]
+        );
+        assert_eq!(
+            string_parts(r#""Order \(.id): \(.total) USD""#),
+            vec![
+                ("Order ".to_owned(), vec![]),
+                (String::new(), vec![Token::Field("id".to_owned())]),
+                (": ".to_owned(), vec![]),
+                (String::new(), vec![Token::Field("total".to_owned())]),
+                (" USD".to_owned(), vec![]),
+            ]
+        );
+
+        // the parentheses and the strings inside the interpolation
+        let parts = string_parts(r#""\(("x" | length) + 1)!""#);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].1.first(), Some(&Token::LeftParen));
+        assert_eq!(parts[0].1.last(), Some(&Token::Number(Number::I32(1))));
+        assert_eq!(parts[1].0, "!");
+    }
+
+    #[test]
+    fn test_lex_incomplete_string() {
+        assert_eq!(
+            lex_from_str(r#""abc"#),
+            Err(Error::UnexpectedEndOfQuery(
+                "Incomplete string literal.".to_owned()
+            ))
+        );
+        assert_eq!(
+            lex_from_str(r#""a\(.b"#),
+            Err(Error::UnexpectedEndOfQuery(
+                "Incomplete string interpolation.".to_owned()
+            ))
+        );
+        assert!(matches!(
+            lex_from_str(r#""\q""#),
+            Err(Error::MessageWithLocation(..))
+        ));
+    }
+}
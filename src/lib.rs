@@ -0,0 +1,73 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The query engine of `aq`, for the programs which embed the queries
+// instead of running the command line tool, e.g.
+//
+// ```
+// let query = Query::compile(".orders[] | select(.total > 100) | .id")?;
+// let ids: Vec<AsonNode> = query.run(&document)?;
+// ```
+//
+// the queries can also be built programmatically by `Query::builder()`,
+// and run on the `Value`s directly by `Query::run_value()`, or on the
+// Rust types by `Query::run_typed()` with the feature `serde`.
+
+pub mod ast;
+pub mod builder;
+pub mod builtins;
+pub mod cancel;
+pub mod cast;
+pub mod cbor;
+pub mod compress;
+pub mod convert;
+pub mod cursor;
+pub mod diagnostic;
+pub mod diff;
+pub mod digest;
+pub mod emitter;
+pub mod error;
+pub mod evaluator;
+pub mod failure;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod format;
+pub mod input;
+pub mod json;
+pub mod lexer;
+pub mod limits;
+pub mod memo;
+pub mod module;
+pub mod msgpack;
+pub mod parser;
+pub mod path;
+pub mod pragma;
+pub mod printer;
+pub mod program;
+pub mod provenance;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod query;
+pub mod regex;
+pub mod scan;
+pub mod schema;
+#[cfg(feature = "serde")]
+pub mod serde_value;
+pub mod tabular;
+pub mod theme;
+pub mod toml;
+pub mod trace;
+pub mod unparse;
+pub mod value;
+pub mod visit;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod xml;
+pub mod yaml;
+
+pub use error::Error;
+pub use query::Query;
+pub use value::Value;
@@ -0,0 +1,25 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use std::time::Duration;
+
+/// The resource limits of the evaluation, e.g. for running untrusted
+/// queries, no limit is applied by default.
+///
+/// The evaluation that exceeds any limit fails with
+/// `Error::LimitExceeded`, which cannot be caught by `try ... catch`.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Limits {
+    /// The maximum depth of the nested function calls,
+    /// e.g. a runaway recursive function.
+    pub max_depth: Option<usize>,
+
+    /// The maximum number of the results of the query.
+    pub max_output: Option<usize>,
+
+    /// The maximum duration of the evaluation.
+    pub timeout: Option<Duration>,
+}
@@ -0,0 +1,544 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Checking the query files without running them, i.e. `aq lint FILE...`.
+//
+// The file is parsed as a query (or a module, which only has the
+// definitions), and the following problems are reported:
+//
+// - the calls of the undefined functions, and the calls with a wrong
+//   number of arguments, e.g. `sort_by(.a; .b)`,
+// - the undefined variables,
+// - the unreachable stages, e.g. `empty | .name`, the alternative of
+//   a truthy literal, and the branches of `if` after a literal condition,
+// - the calls of the deprecated builtins (see `aq migrate-query`).
+//
+// The imported modules are loaded for resolving the calls `alias::name`.
+// The expressions have no positions, so a problem is located at the
+// next occurrence of the name in the tokens, i.e. in the order of the
+// source.
+
+use crate::{
+    ast::{Expression, FunctionDefinition, InterpolationPart, Pattern, VariantPayload},
+    builtins::{is_native_function, NATIVE_FUNCTIONS},
+    error::Error,
+    lexer::{lex_from_str, StringPart, Token, TokenWithLocation},
+    migrate::replacement_of,
+    module::{Module, ModuleLoader},
+    parser::{parse_definitions_from_str, parse_module_from_str, parse_query_from_str},
+    query::PRELUDE,
+};
+
+/// The variables which are always defined.
+pub const PREDEFINED_VARIABLES: [&str; 3] = ["ENV", "ARGS", "__input__"];
+
+/// A problem of the query file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct LintWarning {
+    /// The line number, starts from 1.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// Check the query (or module) source, the names in `variables` are
+/// the variables defined when the query runs, e.g. by `--arg`.
+pub fn lint_query(
+    source: &str,
+    loader: &ModuleLoader,
+    variables: &[String],
+) -> Result<Vec<LintWarning>, Error> {
+    let (imports, expression) = match parse_query_from_str(source) {
+        Ok((imports, expression)) => (imports, expression),
+        Err(e) => match parse_module_from_str(source) {
+            Ok((imports, definitions)) => (imports, definitions_expression(definitions)),
+            Err(_) => return Err(e),
+        },
+    };
+
+    let mut tokens = vec![];
+    flatten_tokens(lex_from_str(source)?, &mut tokens);
+
+    let mut linter = Linter {
+        source,
+        tokens,
+        cursor: 0,
+        line: None,
+        prelude: parse_definitions_from_str(PRELUDE)?,
+        modules: vec![],
+        functions: vec![],
+        variables: variables.to_vec(),
+        warnings: vec![],
+    };
+
+    match loader.load(&imports) {
+        Ok(modules) => linter.modules = modules,
+        Err(e) => linter.warn(None, format!("fail to load the modules: {}", e)),
+    }
+
+    linter.check(&expression);
+    Ok(linter.warnings)
+}
+
+// a module is checked as its definitions followed by `.`.
+fn definitions_expression(definitions: Vec<FunctionDefinition>) -> Expression {
+    definitions
+        .into_iter()
+        .rev()
+        .fold(Expression::Identity, |rest, definition| {
+            Expression::FunctionDefinition(definition, Box::new(rest))
+        })
+}
+
+// the tokens of the string interpolations are included.
+fn flatten_tokens(tokens: Vec<TokenWithLocation>, flattened: &mut Vec<TokenWithLocation>) {
+    for token in tokens {
+        if let Token::String(parts) = &token.token {
+            for part in parts {
+                if let StringPart::Interpolation(inner) = part {
+                    flatten_tokens(inner.clone(), flattened);
+                }
+            }
+        }
+        flattened.push(token);
+    }
+}
+
+struct Linter<'a> {
+    source: &'a str,
+    tokens: Vec<TokenWithLocation>,
+
+    // the index of the token after the last located one, and its line.
+    cursor: usize,
+    line: Option<usize>,
+
+    prelude: Vec<FunctionDefinition>,
+    modules: Vec<Module>,
+
+    // the functions (including the parameters of the enclosing
+    // functions) and the variables in the scope.
+    functions: Vec<(String, usize)>,
+    variables: Vec<String>,
+
+    warnings: Vec<LintWarning>,
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, line: Option<usize>, message: String) {
+        self.warnings.push(LintWarning { line, message });
+    }
+
+    // the line of the next token (from the cursor) which matches,
+    // or the first one if there is none after the cursor.
+    fn locate(&mut self, matches: impl Fn(&Token) -> bool) -> Option<usize> {
+        let idx = (self.cursor..self.tokens.len())
+            .find(|i| matches(&self.tokens[*i].token))
+            .or_else(|| (0..self.cursor).find(|i| matches(&self.tokens[*i].token)))?;
+        let index = self.tokens[idx].location.index;
+        self.cursor = idx + 1;
+        self.line = Some(
+            1 + self
+                .source
+                .chars()
+                .take(index)
+                .filter(|c| *c == '\n')
+                .count(),
+        );
+        self.line
+    }
+
+    fn locate_identifier(&mut self, name: &str) -> Option<usize> {
+        self.locate(|t| matches!(t, Token::Identifier(n) if n == name))
+    }
+
+    fn check(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Identity | Expression::RecurseDefault | Expression::Literal(_) => {}
+            Expression::Interpolation(parts) => self.check_parts(parts),
+            Expression::Format {
+                argument, template, ..
+            } => {
+                if let Some(argument) = argument {
+                    self.check(argument);
+                }
+                if let Some(template) = template {
+                    self.check_parts(template);
+                }
+            }
+            Expression::Index(target, index) => {
+                self.check(target);
+                self.check(index);
+            }
+            Expression::Slice(target, from, to) => {
+                self.check(target);
+                for end in [from, to].into_iter().flatten() {
+                    self.check(end);
+                }
+            }
+            Expression::Iterate(target) | Expression::Negate(target) => self.check(target),
+            Expression::Try(body, handler) => {
+                self.check(body);
+                if let Some(handler) = handler {
+                    self.check(handler);
+                }
+            }
+            Expression::Pipe(left, right) => {
+                self.check(left);
+                if self.produces_nothing(left) {
+                    self.warn(
+                        self.line,
+                        format!(
+                            "the stage \"{}\" is unreachable, \"{}\" produces no results",
+                            right, left
+                        ),
+                    );
+                }
+                self.check(right);
+            }
+            Expression::Alternative(left, right) => {
+                self.check(left);
+                if matches!(left.as_ref(), Expression::Literal(v) if v.is_truthy()) {
+                    self.warn(
+                        self.line,
+                        format!(
+                            "the alternative \"{}\" is unreachable, \"{}\" is always truthy",
+                            right, left
+                        ),
+                    );
+                }
+                self.check(right);
+            }
+            Expression::Comma(left, right)
+            | Expression::Binary(_, left, right)
+            | Expression::And(left, right)
+            | Expression::Or(left, right)
+            | Expression::Assign(_, left, right) => {
+                self.check(left);
+                self.check(right);
+            }
+            Expression::List(items) => {
+                if let Some(items) = items {
+                    self.check(items);
+                }
+            }
+            Expression::Object(entries) => {
+                for (key, value) in entries {
+                    self.check(key);
+                    self.check(value);
+                }
+            }
+            Expression::Variant { payload, .. } => match payload {
+                VariantPayload::Empty => {}
+                VariantPayload::Value(value) | VariantPayload::Object(value) => self.check(value),
+                VariantPayload::Tuple(items) => items.iter().for_each(|item| self.check(item)),
+            },
+            Expression::If(branches, else_body) => self.check_if(branches, else_body.as_deref()),
+            Expression::Reduce {
+                source,
+                pattern,
+                init,
+                update,
+            } => {
+                self.check(source);
+                self.check(init);
+                self.with_pattern(pattern, |linter| linter.check(update));
+            }
+            Expression::Foreach {
+                source,
+                pattern,
+                init,
+                update,
+                extract,
+            } => {
+                self.check(source);
+                self.check(init);
+                self.with_pattern(pattern, |linter| {
+                    linter.check(update);
+                    if let Some(extract) = extract {
+                        linter.check(extract);
+                    }
+                });
+            }
+            Expression::Binding {
+                source,
+                pattern,
+                body,
+            } => {
+                self.check(source);
+                self.with_pattern(pattern, |linter| linter.check(body));
+            }
+            Expression::FunctionDefinition(definition, rest) => {
+                // the function can be called recursively by its body.
+                let scope = self.functions.len();
+                self.functions
+                    .push((definition.name.clone(), definition.params.len()));
+                let scope_of_body = self.functions.len();
+                self.functions
+                    .extend(definition.params.iter().map(|p| (p.clone(), 0)));
+                self.check(&definition.body);
+                self.functions.truncate(scope_of_body);
+                self.check(rest);
+                self.functions.truncate(scope);
+            }
+            Expression::Call(name, args) => {
+                let first = name.split("::").next().unwrap_or(name);
+                let line = self.locate_identifier(first);
+                self.check_call(name, args.len(), line);
+                for arg in args {
+                    self.check(arg);
+                }
+            }
+            Expression::Variable(name) => {
+                let line = self.locate(|t| matches!(t, Token::Variable(n) if n == name));
+                if !self.variables.contains(name) && !PREDEFINED_VARIABLES.contains(&name.as_str())
+                {
+                    self.warn(line, format!("the variable \"${}\" is not defined", name));
+                }
+            }
+        }
+    }
+
+    fn check_parts(&mut self, parts: &[InterpolationPart]) {
+        for part in parts {
+            if let InterpolationPart::Expression(expression) = part {
+                self.check(expression);
+            }
+        }
+    }
+
+    fn check_if(&mut self, branches: &[(Expression, Expression)], else_body: Option<&Expression>) {
+        let line = self.locate_identifier("if");
+        let mut always = None;
+        for (condition, body) in branches {
+            self.check(condition);
+            if let Some(taken) = &always {
+                self.warn(
+                    line,
+                    format!(
+                        "the branch \"{}\" is unreachable, the condition \"{}\" is always true",
+                        body, taken
+                    ),
+                );
+            } else if let Expression::Literal(value) = condition {
+                if value.is_truthy() {
+                    always = Some(condition.to_string());
+                } else {
+                    self.warn(
+                        line,
+                        format!(
+                            "the branch \"{}\" is unreachable, the condition \"{}\" is always false",
+                            body, condition
+                        ),
+                    );
+                }
+            }
+            self.check(body);
+        }
+
+        if let Some(else_body) = else_body {
+            if let Some(taken) = &always {
+                self.warn(
+                    line,
+                    format!(
+                        "the branch \"else {}\" is unreachable, the condition \"{}\" is always true",
+                        else_body, taken
+                    ),
+                );
+            }
+            self.check(else_body);
+        }
+    }
+
+    fn with_pattern(&mut self, pattern: &Pattern, body: impl FnOnce(&mut Self)) {
+        let scope = self.variables.len();
+        bind_names(pattern, &mut self.variables);
+        body(self);
+        self.variables.truncate(scope);
+    }
+
+    fn is_user_defined(&self, name: &str, arity: usize) -> bool {
+        self.functions.iter().any(|(n, a)| n == name && *a == arity)
+    }
+
+    // the builtins `empty`, `error` and `halt` (unless they are redefined), and
+    // the pipelines and comma expressions made up of them.
+    fn produces_nothing(&self, expression: &Expression) -> bool {
+        match expression {
+            Expression::Call(name, args) if !self.is_user_defined(name, args.len()) => {
+                matches!(
+                    (name.as_str(), args.len()),
+                    ("empty" | "halt", 0) | ("error" | "halt_error", 0 | 1)
+                )
+            }
+            Expression::Pipe(left, right) => {
+                self.produces_nothing(left) || self.produces_nothing(right)
+            }
+            Expression::Comma(left, right) => {
+                self.produces_nothing(left) && self.produces_nothing(right)
+            }
+            _ => false,
+        }
+    }
+
+    fn check_call(&mut self, name: &str, arity: usize, line: Option<usize>) {
+        if self.is_user_defined(name, arity) {
+            return;
+        }
+
+        if arity == 0 {
+            if let Some(replacement) = replacement_of(name) {
+                self.warn(
+                    line,
+                    format!(
+                        "the builtin \"{}\" is deprecated, use \"{}\" instead",
+                        name, replacement
+                    ),
+                );
+                return;
+            }
+        }
+
+        // the arities of the functions with the name.
+        let mut arities: Vec<usize> = match name.split_once("::") {
+            Some((alias, member)) => match find_module(&self.modules, alias) {
+                Some(module) => module_arities(module, member),
+                None => {
+                    self.warn(line, format!("the module \"{}\" is not imported", alias));
+                    return;
+                }
+            },
+            None => self
+                .functions
+                .iter()
+                .map(|(n, a)| (n.as_str(), *a))
+                .chain(
+                    self.prelude
+                        .iter()
+                        .map(|d| (d.name.as_str(), d.params.len())),
+                )
+                .chain(NATIVE_FUNCTIONS.iter().copied())
+                .filter(|(n, _)| *n == name)
+                .map(|(_, a)| a)
+                .collect(),
+        };
+
+        if arities.contains(&arity) || is_native_function(name, arity) {
+            return;
+        }
+
+        arities.sort_unstable();
+        arities.dedup();
+        let message = if arities.is_empty() {
+            format!("the function \"{}/{}\" is not defined", name, arity)
+        } else {
+            let expected: Vec<String> = arities.iter().map(usize::to_string).collect();
+            format!(
+                "the function \"{}\" takes {} argument(s), but it is called with {}",
+                name,
+                expected.join(" or "),
+                arity
+            )
+        };
+        self.warn(line, message);
+    }
+}
+
+fn bind_names(pattern: &Pattern, names: &mut Vec<String>) {
+    match pattern {
+        Pattern::Variable(name) => names.push(name.clone()),
+        Pattern::Elements(patterns) => patterns.iter().for_each(|p| bind_names(p, names)),
+    }
+}
+
+fn find_module<'m>(modules: &'m [Module], alias: &str) -> Option<&'m Module> {
+    modules.iter().find(|m| m.alias == alias)
+}
+
+// the arities of the function `member` of the module, the member may
+// be a function of a nested module, e.g. `b::f` of the module `a`.
+fn module_arities(module: &Module, member: &str) -> Vec<usize> {
+    match member.split_once("::") {
+        Some((alias, rest)) => find_module(&module.imports, alias)
+            .map(|m| module_arities(m, rest))
+            .unwrap_or_default(),
+        None => module
+            .definitions
+            .iter()
+            .filter(|d| d.name == member)
+            .map(|d| d.params.len())
+            .collect(),
+    }
+}
+
+impl std::fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::module::ModuleLoader;
+
+    use super::lint_query;
+
+    fn lint(source: &str, variables: &[&str]) -> Vec<String> {
+        let variables: Vec<String> = variables.iter().map(|v| v.to_string()).collect();
+        lint_query(source, &ModuleLoader::new(vec![]), &variables)
+            .unwrap()
+            .iter()
+            .map(|w| w.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_undefined_names() {
+        assert_eq!(
+            lint(
+                "def f(g): [g];\n.a | foo\n| sort_by(.a; .b)\n| $nope\n| f(.) | $x | $ENV",
+                &["x"]
+            ),
+            [
+                "line 2: the function \"foo/0\" is not defined",
+                "line 3: the function \"sort_by\" takes 1 argument(s), but it is called with 2",
+                "line 4: the variable \"$nope\" is not defined"
+            ]
+        );
+
+        // the cast functions and the modules without a query
+        assert_eq!(lint(".a | to_u8", &[]), Vec::<String>::new());
+        assert_eq!(lint("def inc: . + 1;", &[]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_unreachable_stages() {
+        assert_eq!(
+            lint(
+                "empty | .name,\n(1 // .x),\n(if true then 1 else 2 end)",
+                &[]
+            ),
+            [
+                "line 1: the stage \".name, 1 // .x, if true then 1 else 2 end\" is unreachable, \
+                \"empty\" produces no results",
+                "line 1: the alternative \".x\" is unreachable, \"1\" is always truthy",
+                "line 3: the branch \"else 2\" is unreachable, the condition \"true\" is always true"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deprecated_builtins() {
+        assert_eq!(
+            lint(".a | leaf_paths", &[]),
+            ["line 1: the builtin \"leaf_paths\" is deprecated, use \"paths(scalars)\" instead"]
+        );
+        assert!(lint_query(".a |", &ModuleLoader::new(vec![]), &[]).is_err());
+    }
+}
@@ -0,0 +1,1055 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The language server of the query files (`*.aql`), i.e. `aq lsp`, the
+// editor starts it and talks the Language Server Protocol through the
+// STDIN and STDOUT, e.g. Neovim:
+//
+// ```lua
+// vim.lsp.start({
+//     name = "aq",
+//     cmd = { "aq", "lsp" },
+//     init_options = { data = "samples/users.ason" },
+// })
+// ```
+//
+// The server provides:
+//
+// - the diagnostics, i.e. the syntax errors and the warnings of `aq lint`,
+//   they are updated when the file is opened, changed and saved,
+// - the completion of the builtins, the functions defined in the file,
+//   the variables, and (after `.`) the keys of the objects of the
+//   sample data,
+// - the hover documents of the functions.
+//
+// The options (`initializationOptions`, or the section `aq` of the
+// settings of `workspace/didChangeConfiguration`) are:
+//
+// - `data`: the sample data file, the keys are completed by evaluating
+//   the query before the cursor with the data, e.g. `.users[].` completes
+//   the keys of the users, and all keys of the data are completed if the
+//   query is incomplete, e.g. `select(.`,
+// - `variables`: the variables which are defined when the query runs,
+// - `library_path`: the directories to search for the imported modules.
+
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{
+    builtins::NATIVE_FUNCTIONS,
+    cast::CAST_TYPES,
+    convert::{read_document, Format},
+    error::Error,
+    json::{parse_json_from_str, to_json_string, JsonDialect},
+    lexer::{lex_from_str, Token},
+    limits::Limits,
+    lint::{lint_query, PREDEFINED_VARIABLES},
+    module::ModuleLoader,
+    query::{Query, PRELUDE},
+    value::{Map, Value},
+};
+
+// the kinds of the completion items and the severities of
+// the diagnostics of the protocol.
+const KIND_FUNCTION: i64 = 3;
+const KIND_FIELD: i64 = 5;
+const KIND_VARIABLE: i64 = 6;
+const SEVERITY_ERROR: i64 = 1;
+const SEVERITY_WARNING: i64 = 2;
+
+// the error code of the unknown requests.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// The documents of the native functions, for the hover and completion.
+const NATIVE_FUNCTION_DOCS: &[(&str, &str)] = &[
+    ("empty", "Output nothing."),
+    ("not", "Whether the input is `false` or `null`."),
+    ("type", "The type name of the input, e.g. `\"object\"`."),
+    ("error", "Raise an error with the input, or with the message."),
+    ("halt", "Stop the query, and exit with the status 0."),
+    ("halt_error", "Stop the query, write the input to STDERR, and exit with the status (5 by default)."),
+    ("input", "Read the next input document."),
+    ("inputs", "Output the rest of the input documents."),
+    ("input_filename", "The file name of the current input document."),
+    ("input_line_number", "The line number of the current input document."),
+    ("env", "The object of the environment variables."),
+    ("debug", "Write the input to STDERR as `[\"DEBUG:\", input]`, and output the input."),
+    ("stderr", "Write the input to STDERR without the line feed, and output the input."),
+    ("tostring", "Convert the input to a string, the strings are kept as is."),
+    ("tonumber", "Parse the string as a number."),
+    ("toason", "Convert the input to the ASON text."),
+    ("fromason", "Parse the ASON text."),
+    ("is_variant", "Whether the input is the variant of the name, e.g. `is_variant(\"Option::Some\")`."),
+    ("variant_name", "The full name of the variant, e.g. `\"Option::Some\"`."),
+    ("unwrap", "The value of `Option::Some` or `Result::Ok`, and an error otherwise."),
+    ("tohex", "Encode the byte data as the hexadecimal string."),
+    ("fromhex", "Decode the hexadecimal string to the byte data."),
+    ("frombase64", "Decode the Base64 string to the byte data."),
+    ("tobytes", "Convert the string or the list of numbers to the byte data."),
+    ("tolist", "Convert the byte data to the list of numbers."),
+    ("paths_with_key", "The paths of the entries with the key, or the ones match the regular expression with the flags."),
+    ("length", "The length of the string, list, tuple, object or byte data, or the absolute value of the number."),
+    ("keys", "The sorted keys of the object, or the indices of the list."),
+    ("keys_unsorted", "The keys of the object in the order of the entries."),
+    ("has", "Whether the object has the key, or the list has the index."),
+    ("add", "Add the items of the list together."),
+    ("recurse", "Output the input and all of its descendants."),
+    ("reverse", "Reverse the list or the string."),
+    ("sort", "Sort the list."),
+    ("sort_by", "Sort the list by the results of `f`."),
+    ("group_by", "Group the items of the list by the results of `f`."),
+    ("unique", "Sort the list and remove the duplicated items."),
+    ("min", "The minimum item of the list."),
+    ("max", "The maximum item of the list."),
+    ("min_by", "The item of the list with the minimum result of `f`."),
+    ("max_by", "The item of the list with the maximum result of `f`."),
+    ("join", "Join the strings of the list with the separator."),
+    ("split", "Split the string by the separator."),
+    ("ascii_downcase", "Convert the ASCII letters of the string to the lowercase."),
+    ("ascii_upcase", "Convert the ASCII letters of the string to the uppercase."),
+    ("tochar", "Convert the number (code point) or the single-character string to a char."),
+    ("tocodepoint", "The code point of the char."),
+    ("explode", "The list of the code points of the string."),
+    ("implode", "The string of the list of the code points."),
+    ("format_date", "Format the datetime (or the Unix timestamp) with the format, and the locale, e.g. `format_date(\"%Y-%m-%d\")`."),
+    ("ltrimstr", "Remove the prefix from the string."),
+    ("rtrimstr", "Remove the suffix from the string."),
+    ("tojson", "Convert the input to the JSON text."),
+    ("fromjson", "Parse the JSON text."),
+    ("toxml", "Convert the input to the XML text."),
+    ("fromxml", "Parse the XML text."),
+    ("pseudonym", "The salted pseudonym of the string, e.g. `\"anon_86a81c3023136e5e\"`."),
+    ("contains", "Whether the input contains the value, the strings are matched by the substrings."),
+    ("indices", "The indices of the value in the list or the string."),
+    ("startswith", "Whether the string starts with the prefix."),
+    ("endswith", "Whether the string ends with the suffix."),
+    ("flatten", "Flatten the nested lists, to the depth if specified."),
+    ("transpose", "Transpose the list of lists."),
+    ("zip", "Pair the items of the list with the items of the other list."),
+    ("range", "Output the numbers from 0 to `upto`, or from `from` to `upto` by `by`."),
+    ("limit", "Output the first `n` results of `f`."),
+    ("last", "The last result of `f`."),
+    ("nth", "The `n`-th result of `f`."),
+    ("memo", "Evaluate `f` once for each input, the results are reused."),
+    ("path", "The paths of the values selected by the path expression."),
+    ("provenance", "The file, line and path where the values selected by the path expression come from."),
+    ("getpath", "The value at the path."),
+    ("setpath", "Set the value at the path."),
+    ("delpaths", "Delete the values at the paths."),
+    ("validates", "Whether the input matches the schema, see `aq validate`."),
+];
+
+/// A function defined in the source (the file or the prelude).
+#[derive(Debug, PartialEq, Clone)]
+struct Definition {
+    name: String,
+
+    // e.g. `def map(f)`
+    signature: String,
+
+    // the comment lines above the definition.
+    doc: String,
+}
+
+pub struct LanguageServer {
+    // the text of the open documents by the URIs.
+    documents: HashMap<String, String>,
+
+    library_path: Vec<PathBuf>,
+    variables: Vec<String>,
+
+    // the sample data and all keys of its objects.
+    data: Option<Value>,
+    data_keys: Vec<String>,
+
+    prelude: Vec<Definition>,
+    shutdown: bool,
+
+    /// The exit status once the `exit` notification is received.
+    pub exit: Option<i32>,
+}
+
+impl LanguageServer {
+    pub fn new(library_path: Vec<PathBuf>) -> Self {
+        Self {
+            documents: HashMap::new(),
+            library_path,
+            variables: vec![],
+            data: None,
+            data_keys: vec![],
+            prelude: scan_definitions(PRELUDE),
+            shutdown: false,
+            exit: None,
+        }
+    }
+
+    /// Load the sample data for the completion of the keys.
+    pub fn load_data(&mut self, file: &str) -> Result<(), Error> {
+        let format = Format::from_path(Path::new(file)).unwrap_or(Format::Ason);
+        let data = std::fs::read(file)
+            .map_err(|e| {
+                Error::Message(format!(
+                    "Fail to read the sample data file: \"{}\".\n{}",
+                    file, e
+                ))
+            })
+            .and_then(|bytes| read_document(&bytes, format))?;
+
+        self.data_keys = vec![];
+        collect_keys(&data, &mut self.data_keys);
+        self.data = Some(data);
+        Ok(())
+    }
+
+    /// Handle a message of the client, the responses and the
+    /// notifications to send are returned.
+    pub fn handle(&mut self, message: &Value) -> Vec<Value> {
+        let params = field(message, "params")
+            .cloned()
+            .unwrap_or_else(Value::null);
+        match (string_field(message, "method"), field(message, "id")) {
+            (Some(method), Some(id)) => {
+                let mut map = Map::new();
+                map.insert("jsonrpc".to_owned(), Value::from("2.0"));
+                map.insert("id".to_owned(), id.clone());
+                match self.request(method, &params) {
+                    Ok(result) => {
+                        map.insert("result".to_owned(), result);
+                    }
+                    Err((code, message)) => {
+                        map.insert(
+                            "error".to_owned(),
+                            object(vec![
+                                ("code", Value::from(code)),
+                                ("message", Value::from(message)),
+                            ]),
+                        );
+                    }
+                }
+                vec![Value::Object(map)]
+            }
+            (Some(method), None) => self.notification(method, &params),
+            // the responses of the client are ignored
+            _ => vec![],
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        match method {
+            "initialize" => {
+                if let Some(options) = field(params, "initializationOptions") {
+                    self.configure(options);
+                }
+                Ok(object(vec![
+                    (
+                        "capabilities",
+                        object(vec![
+                            // the whole text is sent on each change
+                            ("textDocumentSync", Value::from(1)),
+                            (
+                                "completionProvider",
+                                object(vec![(
+                                    "triggerCharacters",
+                                    Value::List(vec![Value::from("."), Value::from("$")].into()),
+                                )]),
+                            ),
+                            ("hoverProvider", Value::from(true)),
+                        ]),
+                    ),
+                    (
+                        "serverInfo",
+                        object(vec![
+                            ("name", Value::from("aq")),
+                            ("version", Value::from(env!("CARGO_PKG_VERSION"))),
+                        ]),
+                    ),
+                ]))
+            }
+            "shutdown" => {
+                self.shutdown = true;
+                Ok(Value::null())
+            }
+            "textDocument/completion" => Ok(self
+                .locate(params)
+                .map(|(source, offset)| self.completion(source, offset))
+                .unwrap_or_else(|| Value::List(vec![].into()))),
+            "textDocument/hover" => Ok(self
+                .locate(params)
+                .and_then(|(source, offset)| self.hover(source, offset))
+                .unwrap_or_else(Value::null)),
+            _ => Err((
+                METHOD_NOT_FOUND,
+                format!("The method \"{}\" is not supported.", method),
+            )),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = field(params, "textDocument")
+            .and_then(|d| string_field(d, "uri"))
+            .map(str::to_owned);
+
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = field(params, "textDocument").and_then(|d| string_field(d, "text"));
+                self.documents
+                    .insert(uri.clone(), text.unwrap_or_default().to_owned());
+                vec![self.diagnostics(&uri)]
+            }
+            ("textDocument/didChange", Some(uri)) => {
+                let text = match field(params, "contentChanges") {
+                    Some(Value::List(changes)) => {
+                        changes.last().and_then(|c| string_field(c, "text"))
+                    }
+                    _ => None,
+                };
+                if let Some(text) = text {
+                    self.documents.insert(uri.clone(), text.to_owned());
+                }
+                vec![self.diagnostics(&uri)]
+            }
+            ("textDocument/didSave", Some(uri)) => {
+                if let Some(text) = string_field(params, "text") {
+                    self.documents.insert(uri.clone(), text.to_owned());
+                }
+                vec![self.diagnostics(&uri)]
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+                vec![publish_diagnostics(&uri, vec![])]
+            }
+            ("workspace/didChangeConfiguration", _) => {
+                let settings = field(params, "settings");
+                if let Some(options) = settings.map(|s| field(s, "aq").unwrap_or(s)) {
+                    self.configure(options);
+                }
+                // the variables and the modules may be changed
+                let mut uris: Vec<_> = self.documents.keys().cloned().collect();
+                uris.sort();
+                uris.iter().map(|uri| self.diagnostics(uri)).collect()
+            }
+            ("exit", _) => {
+                self.exit = Some(if self.shutdown { 0 } else { 1 });
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn configure(&mut self, options: &Value) {
+        if let Some(Value::List(variables)) = field(options, "variables") {
+            self.variables = variables
+                .iter()
+                .filter_map(|v| match v {
+                    Value::String(s) => Some(s.trim_start_matches('$').to_owned()),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Some(Value::List(paths)) = field(options, "library_path") {
+            self.library_path = paths
+                .iter()
+                .filter_map(|v| match v {
+                    Value::String(s) => Some(PathBuf::from(s)),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Some(file) = string_field(options, "data") {
+            // the messages of the server are read by the client, so
+            // the errors are written to the log (i.e. STDERR) instead.
+            if let Err(e) = self.load_data(file) {
+                eprintln!("{}", e);
+            }
+        }
+    }
+
+    // the source of the document and the character index
+    // of the position of the request.
+    fn locate(&self, params: &Value) -> Option<(&str, usize)> {
+        let uri = field(params, "textDocument").and_then(|d| string_field(d, "uri"))?;
+        let source = self.documents.get(uri)?;
+        let position = field(params, "position")?;
+        let line = number_field(position, "line")?;
+        let character = number_field(position, "character")?;
+        Some((source, index_of(source, line, character)))
+    }
+
+    fn diagnostics(&self, uri: &str) -> Value {
+        let Some(source) = self.documents.get(uri) else {
+            return publish_diagnostics(uri, vec![]);
+        };
+
+        // the modules are resolved against the directory of the file.
+        let base_dir = uri_to_path(uri)
+            .and_then(|p| p.parent().map(Path::to_path_buf))
+            .unwrap_or_default();
+        let loader = ModuleLoader::new(self.library_path.clone()).with_base_dir(base_dir);
+
+        let diagnostics = match lint_query(source, &loader, &self.variables) {
+            Ok(warnings) => warnings
+                .iter()
+                .map(|warning| {
+                    let line = warning.line.map_or(0, |n| n - 1);
+                    let text = source.lines().nth(line).unwrap_or("");
+                    let start = text.len() - text.trim_start().len();
+                    diagnostic(
+                        range(
+                            (line, utf16_len(&text[..start])),
+                            (line, utf16_len(text.trim_end())),
+                        ),
+                        SEVERITY_WARNING,
+                        &warning.message,
+                    )
+                })
+                .collect(),
+            Err(e) => {
+                let (message, start, end) = match &e {
+                    Error::MessageWithLocation(message, location) => (
+                        message.clone(),
+                        location.index,
+                        location.index + location.length.max(1),
+                    ),
+                    Error::UnexpectedEndOfQuery(_) => {
+                        let end = source.trim_end().chars().count();
+                        (e.to_string(), end, end)
+                    }
+                    _ => (e.to_string(), 0, 0),
+                };
+                vec![diagnostic(
+                    range(position_of(source, start), position_of(source, end)),
+                    SEVERITY_ERROR,
+                    &message,
+                )]
+            }
+        };
+        publish_diagnostics(uri, diagnostics)
+    }
+
+    fn completion(&self, source: &str, offset: usize) -> Value {
+        let before: String = source.chars().take(offset).collect();
+        let prefix = before.trim_end_matches(is_name_char);
+
+        let items = if let Some(stage) = prefix.strip_suffix('.') {
+            self.key_items(stage)
+        } else if let Some(head) = prefix.strip_suffix('$') {
+            // the incomplete variable under the cursor can not be lexed
+            let rest: String = source.chars().skip(offset).collect();
+            self.variable_items(&format!("{}{}", head, rest))
+        } else {
+            self.function_items(source)
+        };
+        Value::List(items.into())
+    }
+
+    fn function_items(&self, source: &str) -> Vec<Value> {
+        let mut items = vec![];
+        let mut seen = vec![];
+        let mut add = |name: &str, detail: String, doc: String| {
+            if !seen.iter().any(|s| s == name) {
+                seen.push(name.to_owned());
+                items.push(completion_item(name, KIND_FUNCTION, &detail, &doc));
+            }
+        };
+
+        for definition in scan_definitions(source).iter().chain(&self.prelude) {
+            add(
+                &definition.name,
+                definition.signature.clone(),
+                definition.doc.clone(),
+            );
+        }
+        for (name, _) in NATIVE_FUNCTIONS {
+            add(name, native_signature(name), native_doc(name).to_owned());
+        }
+        for target in CAST_TYPES {
+            let name = format!("to_{}", target);
+            let doc = format!(
+                "Convert the number to {}, the optional mode is \"checked\" (default), \"saturating\" or \"wrapping\".",
+                target
+            );
+            add(&name, format!("{}/0, {}/1", name, name), doc);
+        }
+        items
+    }
+
+    fn variable_items(&self, source: &str) -> Vec<Value> {
+        let mut names: Vec<String> = PREDEFINED_VARIABLES.iter().map(|s| s.to_string()).collect();
+        names.extend(self.variables.iter().cloned());
+        for token in lex_from_str(source).unwrap_or_default() {
+            if let Token::Variable(name) = token.token {
+                names.push(name);
+            }
+        }
+
+        let mut items = vec![];
+        for (idx, name) in names.iter().enumerate() {
+            if !names[..idx].contains(name) {
+                items.push(completion_item(name, KIND_VARIABLE, "variable", ""));
+            }
+        }
+        items
+    }
+
+    fn key_items(&self, stage: &str) -> Vec<Value> {
+        let Some(data) = &self.data else {
+            return vec![];
+        };
+        let keys = keys_of_stage(stage, data).unwrap_or_else(|| self.data_keys.clone());
+        keys.iter()
+            .map(|key| completion_item(key, KIND_FIELD, "key of the sample data", ""))
+            .collect()
+    }
+
+    fn hover(&self, source: &str, offset: usize) -> Option<Value> {
+        let chars: Vec<char> = source.chars().collect();
+        let start = chars[..offset.min(chars.len())]
+            .iter()
+            .rposition(|c| !is_name_char(*c))
+            .map_or(0, |pos| pos + 1);
+        let end = chars[start..]
+            .iter()
+            .position(|c| !is_name_char(*c))
+            .map_or(chars.len(), |pos| start + pos);
+
+        // the keys and the variables are not functions
+        if start == end || matches!(start.checked_sub(1).map(|i| chars[i]), Some('.' | '$')) {
+            return None;
+        }
+        let name: String = chars[start..end].iter().collect();
+
+        let definitions: Vec<Definition> = scan_definitions(source)
+            .into_iter()
+            .chain(self.prelude.iter().cloned())
+            .filter(|d| d.name == name)
+            .collect();
+        let text = if !definitions.is_empty() {
+            definitions
+                .iter()
+                .map(|d| match d.doc.is_empty() {
+                    true => format!("```\n{}\n```", d.signature),
+                    false => format!("```\n{}\n```\n{}", d.signature, d.doc),
+                })
+                .collect::<Vec<_>>()
+                .join("\n\n")
+        } else if NATIVE_FUNCTIONS.iter().any(|(n, _)| *n == name) {
+            format!(
+                "```\n{}\n```\n{}",
+                native_signature(&name),
+                native_doc(&name)
+            )
+        } else {
+            return None;
+        };
+
+        Some(object(vec![(
+            "contents",
+            object(vec![
+                ("kind", Value::from("markdown")),
+                ("value", Value::from(text)),
+            ]),
+        )]))
+    }
+}
+
+/// Run the server with the STDIN and STDOUT until the client exits,
+/// the exit status is returned.
+pub fn serve(mut server: LanguageServer) -> Result<i32, Error> {
+    let mut reader = io::stdin().lock();
+    let mut writer = io::stdout().lock();
+    while let Some(message) = read_message(&mut reader)? {
+        for reply in server.handle(&message) {
+            write_message(&mut writer, &reply)?;
+        }
+        if let Some(status) = server.exit {
+            return Ok(status);
+        }
+    }
+
+    // the client is gone without the `exit`
+    Ok(1)
+}
+
+/// Read a message, i.e. the headers and the JSON content,
+/// `None` is returned at the end of the input.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>, Error> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).map_err(transport_error)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>().map_err(|_| {
+                    Error::Message(format!("Invalid header of the message: \"{}\".", line))
+                })?);
+            }
+        }
+    }
+
+    let mut content = vec![0; length.unwrap_or(0)];
+    reader.read_exact(&mut content).map_err(transport_error)?;
+    let text = String::from_utf8(content)
+        .map_err(|_| Error::Message("The message is not valid UTF-8 text.".to_owned()))?;
+    parse_json_from_str(&text, JsonDialect::Jsonc).map(Some)
+}
+
+/// Write a message with the header `Content-Length`.
+pub fn write_message(writer: &mut impl Write, message: &Value) -> Result<(), Error> {
+    let content = to_json_string(message);
+    write!(
+        writer,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )
+    .and_then(|_| writer.flush())
+    .map_err(transport_error)
+}
+
+fn transport_error(e: io::Error) -> Error {
+    Error::Message(format!(
+        "Fail to transfer the message of the language server.\n{}",
+        e
+    ))
+}
+
+// the functions defined by `def` in the source, the source is lexed
+// only, so the definitions are found even if the query is incomplete.
+fn scan_definitions(source: &str) -> Vec<Definition> {
+    let Ok(tokens) = lex_from_str(source) else {
+        return vec![];
+    };
+    let chars: Vec<char> = source.chars().collect();
+    let lines: Vec<&str> = source.lines().collect();
+
+    let mut definitions = vec![];
+    for (idx, token) in tokens.iter().enumerate() {
+        if !matches!(&token.token, Token::Identifier(keyword) if keyword == "def") {
+            continue;
+        }
+        let Some(Token::Identifier(name)) = tokens.get(idx + 1).map(|t| &t.token) else {
+            continue;
+        };
+        let Some(colon) = tokens[idx..].iter().position(|t| t.token == Token::Colon) else {
+            continue;
+        };
+        let signature: String = chars[token.location.index..tokens[idx + colon].location.index]
+            .iter()
+            .collect();
+        let line = chars[..token.location.index]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count();
+        let comments: Vec<&str> = lines[..line]
+            .iter()
+            .rev()
+            .map(|l| l.trim())
+            .take_while(|l| l.starts_with('#'))
+            .map(|l| l.trim_start_matches('#').trim())
+            .collect();
+
+        definitions.push(Definition {
+            name: name.clone(),
+            signature: signature.split_whitespace().collect::<Vec<_>>().join(" "),
+            doc: comments.into_iter().rev().collect::<Vec<_>>().join("\n"),
+        });
+    }
+    definitions
+}
+
+fn native_signature(name: &str) -> String {
+    NATIVE_FUNCTIONS
+        .iter()
+        .filter(|(n, _)| *n == name)
+        .map(|(n, arity)| format!("{}/{}", n, arity))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn native_doc(name: &str) -> &'static str {
+    NATIVE_FUNCTION_DOCS
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map_or("", |(_, doc)| doc)
+}
+
+// the keys of the objects output by the stage (i.e. the query before
+// the cursor) with the sample data, `None` if the stage is incomplete
+// or outputs no object.
+fn keys_of_stage(stage: &str, data: &Value) -> Option<Vec<String>> {
+    let stage = stage.trim_end();
+    let candidates = if stage.is_empty() {
+        vec![".".to_owned()]
+    } else {
+        // e.g. `.users[]` and `.users[] | `
+        vec![stage.to_owned(), format!("{} .", stage)]
+    };
+
+    // the completion should not hang the editor.
+    let limits = Limits {
+        max_depth: Some(256),
+        max_output: Some(100),
+        timeout: Some(Duration::from_millis(200)),
+    };
+
+    for candidate in candidates {
+        let Ok(query) = Query::compile(&candidate) else {
+            continue;
+        };
+        let Ok(values) = query.with_limits(limits).run_value(data.clone()) else {
+            continue;
+        };
+
+        let mut keys: Vec<String> = vec![];
+        for value in &values {
+            if let Value::Object(map) = value {
+                for (key, _) in map.iter() {
+                    if !keys.contains(key) {
+                        keys.push(key.clone());
+                    }
+                }
+            }
+        }
+        return (!keys.is_empty()).then_some(keys);
+    }
+    None
+}
+
+fn collect_keys(value: &Value, keys: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter() {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+                collect_keys(value, keys);
+            }
+        }
+        Value::List(items) | Value::Tuple(items) => {
+            for item in items.iter() {
+                collect_keys(item, keys);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+    match value {
+        Value::Object(map) => map.get(key).filter(|v| !v.is_null()),
+        _ => None,
+    }
+}
+
+fn string_field<'a>(value: &'a Value, key: &str) -> Option<&'a str> {
+    match field(value, key) {
+        Some(Value::String(s)) => Some(s),
+        _ => None,
+    }
+}
+
+fn number_field(value: &Value, key: &str) -> Option<usize> {
+    match field(value, key) {
+        Some(Value::Number(n)) if n.as_f64() >= 0.0 => Some(n.as_f64() as usize),
+        _ => None,
+    }
+}
+
+fn object(entries: Vec<(&str, Value)>) -> Value {
+    Value::Object(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.to_owned(), value))
+            .collect(),
+    )
+}
+
+fn completion_item(label: &str, kind: i64, detail: &str, doc: &str) -> Value {
+    let mut entries = vec![
+        ("label", Value::from(label)),
+        ("kind", Value::from(kind)),
+        ("detail", Value::from(detail)),
+    ];
+    if !doc.is_empty() {
+        entries.push(("documentation", Value::from(doc)));
+    }
+    object(entries)
+}
+
+fn diagnostic(range: Value, severity: i64, message: &str) -> Value {
+    object(vec![
+        ("range", range),
+        ("severity", Value::from(severity)),
+        ("source", Value::from("aq")),
+        ("message", Value::from(message)),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Value>) -> Value {
+    object(vec![
+        ("jsonrpc", Value::from("2.0")),
+        ("method", Value::from("textDocument/publishDiagnostics")),
+        (
+            "params",
+            object(vec![
+                ("uri", Value::from(uri)),
+                ("diagnostics", Value::List(diagnostics.into())),
+            ]),
+        ),
+    ])
+}
+
+fn range(start: (usize, usize), end: (usize, usize)) -> Value {
+    let position = |(line, character): (usize, usize)| {
+        object(vec![
+            ("line", Value::from(line)),
+            ("character", Value::from(character)),
+        ])
+    };
+    object(vec![("start", position(start)), ("end", position(end))])
+}
+
+// the positions of the protocol are the lines (0-based) and
+// the UTF-16 offsets within the lines.
+fn position_of(source: &str, index: usize) -> (usize, usize) {
+    let mut line = 0;
+    let mut character = 0;
+    for c in source.chars().take(index) {
+        if c == '\n' {
+            line += 1;
+            character = 0;
+        } else {
+            character += c.len_utf16();
+        }
+    }
+    (line, character)
+}
+
+fn index_of(source: &str, line: usize, character: usize) -> usize {
+    let (mut current_line, mut current_character) = (0, 0);
+    for (index, c) in source.chars().enumerate() {
+        if current_line == line && (current_character >= character || c == '\n') {
+            return index;
+        }
+        if c == '\n' {
+            current_line += 1;
+        } else if current_line == line {
+            current_character += c.len_utf16();
+        }
+    }
+    source.chars().count()
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.chars().map(char::len_utf16).sum()
+}
+
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+
+    // the percent-encoded bytes, e.g. `%20`
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut idx = 0;
+    while idx < bytes.len() {
+        let hex = bytes
+            .get(idx + 1..idx + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[idx], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                idx += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                idx += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        json::{parse_json_from_str, to_json_string, JsonDialect},
+        value::Value,
+    };
+
+    use super::{read_message, write_message, LanguageServer, METHOD_NOT_FOUND};
+
+    fn json(text: &str) -> Value {
+        parse_json_from_str(text, JsonDialect::Json).unwrap()
+    }
+
+    // the value at the path, e.g. `result.0.label`.
+    fn at(value: &Value, path: &str) -> Value {
+        let mut current = value.clone();
+        for key in path.split('.') {
+            current = match (&current, key.parse::<usize>()) {
+                (Value::List(items), Ok(idx)) => items[idx].clone(),
+                (Value::Object(map), _) => map.get(key).cloned().unwrap(),
+                _ => panic!("no {} in {}", key, to_json_string(value)),
+            };
+        }
+        current
+    }
+
+    fn open(server: &mut LanguageServer, text: &str) -> Value {
+        let message = format!(
+            r#"{{"jsonrpc": "2.0", "method": "textDocument/didOpen",
+                "params": {{"textDocument": {{"uri": "file:///tmp/q.aql", "text": {}}}}}}}"#,
+            to_json_string(&Value::from(text))
+        );
+        server.handle(&json(&message)).remove(0)
+    }
+
+    fn request(server: &mut LanguageServer, method: &str, line: usize, character: usize) -> Value {
+        let message = format!(
+            r#"{{"jsonrpc": "2.0", "id": 1, "method": "{}",
+                "params": {{"textDocument": {{"uri": "file:///tmp/q.aql"}},
+                "position": {{"line": {}, "character": {}}}}}}}"#,
+            method, line, character
+        );
+        at(&server.handle(&json(&message))[0], "result")
+    }
+
+    fn labels(items: &Value) -> Vec<String> {
+        match items {
+            Value::List(items) => items
+                .iter()
+                .map(|item| at(item, "label").to_string().trim_matches('"').to_owned())
+                .collect(),
+            _ => panic!("the items should be a list"),
+        }
+    }
+
+    #[test]
+    fn test_messages() {
+        let mut buf = vec![];
+        let message = json(r#"{"jsonrpc": "2.0", "id": 1, "method": "shutdown"}"#);
+        write_message(&mut buf, &message).unwrap();
+        assert!(buf.starts_with(b"Content-Length: "));
+
+        let mut reader = &buf[..];
+        assert_eq!(read_message(&mut reader).unwrap(), Some(message));
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_lifecycle() {
+        let mut server = LanguageServer::new(vec![]);
+        let reply = server.handle(&json(
+            r#"{"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}"#,
+        ));
+        assert_eq!(
+            at(&reply[0], "result.capabilities.hoverProvider"),
+            Value::from(true)
+        );
+
+        let reply = server.handle(&json(r#"{"jsonrpc": "2.0", "id": 2, "method": "nope"}"#));
+        assert_eq!(at(&reply[0], "error.code"), Value::from(METHOD_NOT_FOUND));
+
+        server.handle(&json(
+            r#"{"jsonrpc": "2.0", "id": 3, "method": "shutdown"}"#,
+        ));
+        assert_eq!(server.exit, None);
+        server.handle(&json(r#"{"jsonrpc": "2.0", "method": "exit"}"#));
+        assert_eq!(server.exit, Some(0));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut server = LanguageServer::new(vec![]);
+        let notification = open(&mut server, ".a | (.b");
+        assert_eq!(
+            at(&notification, "method"),
+            Value::from("textDocument/publishDiagnostics")
+        );
+        assert_eq!(
+            at(&notification, "params.diagnostics.0.severity"),
+            Value::from(1)
+        );
+
+        let notification = open(&mut server, ".a\n  | foo");
+        let diagnostic = at(&notification, "params.diagnostics.0");
+        assert_eq!(at(&diagnostic, "severity"), Value::from(2));
+        assert_eq!(
+            at(&diagnostic, "message"),
+            Value::from("the function \"foo/0\" is not defined")
+        );
+        assert_eq!(at(&diagnostic, "range.start.line"), Value::from(1));
+        assert_eq!(at(&diagnostic, "range.start.character"), Value::from(2));
+
+        let notification = open(&mut server, ".a | length");
+        assert_eq!(at(&notification, "params.diagnostics"), json("[]"));
+    }
+
+    #[test]
+    fn test_completion_and_hover() {
+        let mut server = LanguageServer::new(vec![]);
+        open(&mut server, "# add one\ndef inc: . + 1;\n.a as $x | inc");
+
+        let functions = labels(&request(&mut server, "textDocument/completion", 2, 13));
+        assert_eq!(functions[0], "inc");
+        assert!(functions.contains(&"map".to_owned()));
+        assert!(functions.contains(&"length".to_owned()));
+        assert!(functions.contains(&"to_u8".to_owned()));
+
+        let hover = request(&mut server, "textDocument/hover", 2, 12);
+        assert_eq!(
+            at(&hover, "contents.value"),
+            Value::from("```\ndef inc\n```\nadd one")
+        );
+        assert!(request(&mut server, "textDocument/hover", 2, 1).is_null());
+
+        open(&mut server, ".a as $x | $");
+        let variables = labels(&request(&mut server, "textDocument/completion", 0, 12));
+        assert_eq!(variables, ["ENV", "ARGS", "__input__", "x"]);
+    }
+
+    #[test]
+    fn test_complete_keys() {
+        let dir = std::env::temp_dir().join(format!("aq-lsp-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data = dir.join("users.ason");
+        std::fs::write(&data, "{users: [{name: \"foo\", age: 42}], total: 1}").unwrap();
+
+        let mut server = LanguageServer::new(vec![]);
+        server.load_data(data.to_str().unwrap()).unwrap();
+        open(&mut server, ".users[].");
+        assert_eq!(
+            labels(&request(&mut server, "textDocument/completion", 0, 9)),
+            ["name", "age"]
+        );
+
+        // all keys of the data for the incomplete query
+        open(&mut server, "select(.");
+        let keys = labels(&request(&mut server, "textDocument/completion", 0, 8));
+        assert!(keys.contains(&"users".to_owned()) && keys.contains(&"name".to_owned()));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,16 +4,119 @@
 // the Mozilla Public License version 2.0 and additional exceptions,
 // more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
 
+mod browse;
+mod cache;
+mod check;
+mod codegen;
+mod config;
+mod distinct;
+mod doctor;
+mod embed;
+mod explain;
+mod fetch;
+mod follow;
+mod formatter;
+mod grep;
+mod in_place;
+mod incremental;
+mod jobs;
+mod lint;
+mod lsp;
+mod mapped;
+mod merge;
+mod migrate;
+mod pipeline;
+mod prefetch;
+mod preserve;
+mod progress;
+mod query_set;
+mod query_test;
+mod recover;
+mod roundtrip;
+mod rust_types;
+mod shell;
+mod split;
+mod stream;
+mod throttle;
+mod watch;
+mod window;
+
+// the modules of the library, i.e. `crate::value` etc. are available to the
+// modules of the command line tool.
+use ason_query::{
+    ast, builtins, cast, cbor, compress, convert, diff, error, evaluator, input, json, lexer,
+    limits, module, msgpack, parser, path, pragma, printer, provenance, query, regex, scan, schema,
+    tabular, theme, toml, value, visit, xml, yaml,
+};
 use std::{
-    io::{IsTerminal, Read},
+    cell::RefCell,
+    fmt::Display,
+    io::{BufRead, IsTerminal, Read, Write},
+    iter,
+    path::{Path, PathBuf},
     process,
+    rc::Rc,
+    time::{Duration, Instant},
 };
 
-use ason::ast::{
-    parser::parse_from_str,
-    printer::{print_to_string, print_to_writer},
-};
-use clap::Parser;
+use ason::ast::{printer::print_to_string, AsonNode};
+use browse::browse;
+use cache::{cache_dir, cache_max_size, module_hashes, CachedOutput, CompileCache, ResultCache};
+use cbor::{decode_cbor, encode_cbor};
+use check::{check_document, check_report};
+use chrono::{DateTime, FixedOffset, Utc};
+use clap::{Parser, Subcommand};
+use compress::{compress, decompress, decompress_reader, detect, Compression};
+use config::load_config;
+use convert::{parse_documents, read_document, write_document, Format};
+use diff::{diff_values, patch_document};
+use distinct::DistinctSet;
+use error::Error;
+use evaluator::InputStream;
+use explain::explain_query;
+use fetch::{fetch_url, is_url, open_url, HttpOptions};
+use follow::{FollowedFile, POLL_INTERVAL};
+use formatter::format_document;
+use in_place::write_in_place;
+use incremental::incremental_documents;
+use input::{parse_input, read_file_range, read_range, ByteRange, ParseOptions};
+use jobs::{default_jobs, parallel};
+use json::{escape_non_ascii_json, to_tagged_json, JsonDialect};
+use limits::Limits;
+use mapped::{map_input, InputData};
+use merge::merge_sorted;
+use module::ModuleLoader;
+use msgpack::{decode_msgpack, encode_msgpack};
+use pipeline::explain_empty;
+use prefetch::prefetch;
+use preserve::preserve_edits;
+use printer::{print_value, NumberStyle, PrintOptions};
+use progress::{render_progress_bar, Progress, ProgressTracker};
+use provenance::DocumentSource;
+use query::Query;
+use query_set::compile_query_set;
+use recover::parse_tolerant;
+use schema::{validation_report, Schema};
+use shell::export_shell;
+use split::{compile_template, split_target, write_split_file};
+use stream::stream_events;
+use tabular::{export_table, parse_table, CellStyle, TableFormat, TableOptions};
+use throttle::{parse_rate_limit, parse_sample_rate, RateLimiter, Sampler};
+use toml::to_toml;
+use value::{escape_non_ascii, Map, Value};
+use watch::FileWatcher;
+use window::{parse_window_width, WindowAggregator};
+use xml::to_xml;
+use yaml::to_yaml;
+
+// the exit status when the evaluation exceeds a limit, e.g. `--timeout`.
+const EXIT_LIMIT_EXCEEDED: i32 = 3;
+
+// the exit status of `--exit-status` when the query produces no output.
+const EXIT_NO_OUTPUT: i32 = 4;
+
+// the prefix of the records of `--seq` (RFC 7464).
+const RECORD_SEPARATOR: char = '\x1e';
 
 /// ASON Query is a powerful tool for querying, manipulating and generating ASON data.
 ///
@@ -31,8 +134,11 @@ use clap::Parser;
 #[derive(Parser, Debug)]
 #[command(name = "aq")]
 #[command(version, about)]
-// #[command()]
+#[command(args_conflicts_with_subcommands = true)]
 struct AqArgs {
+    #[command(subcommand)]
+    command: Option<AqCommand>,
+
     /*
     To specify the flags for an argument, you can use #[arg(short = 'n')] and/or #[arg(long = "name")]
     attributes on a field. When no value is given (e.g. #[arg(short)]),
@@ -43,11 +149,501 @@ struct AqArgs {
     #[arg(short, long, value_name = "OUTPUT_FILE")]
     output: Option<String>,
 
+    /// Write each result to its own file, the path is the template with
+    /// the expressions evaluated with the result, e.g. `'out/{.id}.ason'`
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with_all = ["output", "in_place", "split_output", "export_shell", "to_csv", "table", "seq", "stream", "prefetch", "lines", "watch", "follow", "window", "first_match"]
+    )]
+    output_template: Option<String>,
+
+    /// Write each result (an object `{filename, content}`) to its own file,
+    /// the string content is written as it is with `--raw-output`
+    #[arg(
+        long,
+        conflicts_with_all = ["output", "in_place", "export_shell", "to_csv", "table", "seq", "stream", "prefetch", "lines", "watch", "follow", "window", "first_match"]
+    )]
+    split_output: bool,
+
+    /// Rewrite each input file with the result of the query instead of
+    /// writing to the STDOUT, the original file is kept as FILE + SUFFIX
+    /// if the suffix is specified, e.g. `--in-place=.bak`
+    #[arg(
+        short,
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        conflicts_with_all = ["output", "window", "slurp", "merge_sorted_by", "null_input"]
+    )]
+    in_place: Option<String>,
+
+    /// With `--in-place`, keep the comments and the formatting of the
+    /// ASON files, only the changed values are rewritten
+    #[arg(long, requires = "in_place")]
+    preserve: bool,
+
     /* the query expression will be omitted if the query file is specified */
-    /// Specify the query file
+    /// Specify the query file, or `-` to read the query from the STDIN,
+    /// the input is read from the input files in that case,
+    /// e.g. `generate-query | aq -q - data.ason`
     #[arg(short, long, value_name = "QUERY_FILE")]
     query: Option<String>,
 
+    /// Define the variable `$NAME` as the string, this option
+    /// can be specified multiple times
+    #[arg(long, num_args = 2, value_names = ["NAME", "VALUE"])]
+    arg: Vec<String>,
+
+    /// Define the variable `$NAME` as the ASON value,
+    /// e.g. `--argason limit 10`
+    #[arg(long, num_args = 2, value_names = ["NAME", "ASON"])]
+    argason: Vec<String>,
+
+    /// Define the variable `$NAME` as the content of the file (a string)
+    #[arg(long, num_args = 2, value_names = ["NAME", "FILE"])]
+    rawfile: Vec<String>,
+
+    /// Define the variable `$NAME` as the list of the documents of the file,
+    /// e.g. `--slurpfile users users.ason`, the format is detected by
+    /// the file extension (ASON by default)
+    #[arg(long, num_args = 2, value_names = ["NAME", "FILE"])]
+    slurpfile: Vec<String>,
+
+    /// Define the variable `$NAME` as the document of the file, the format
+    /// is detected by the file extension (ASON by default)
+    #[arg(long, num_args = 2, value_names = ["NAME", "FILE"])]
+    argfile: Vec<String>,
+
+    /// Evaluate the named queries of the file (an ASON object of the names
+    /// and query texts) over the input in one pass, and output the object
+    /// of the names and results
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["query", "explain_empty"])]
+    query_set: Option<String>,
+
+    /// Take the remaining positional arguments as the strings of
+    /// `$ARGS.positional` instead of the input files
+    #[arg(long, conflicts_with = "asonargs")]
+    args: bool,
+
+    /// Take the remaining positional arguments as the ASON values
+    /// of `$ARGS.positional` instead of the input files
+    #[arg(long)]
+    asonargs: bool,
+
+    /// Add a directory to search for the modules imported by
+    /// `import "path" as alias;`, this option can be specified multiple times
+    #[arg(short = 'L', long, value_name = "DIR")]
+    library_path: Vec<PathBuf>,
+
+    /// Use `null` as the input instead of reading the STDIN, the input
+    /// files are still available for the `input` and `inputs` builtins
+    #[arg(short, long)]
+    null_input: bool,
+
+    /// Recover from the malformed entries of the input list or object,
+    /// the malformed entries are reported and skipped (the list elements
+    /// are replaced by `ParseError::Malformed{line, message, text}`)
+    #[arg(long, conflicts_with = "raw_input")]
+    tolerant: bool,
+
+    /// Read all the input documents into a single list and run the query
+    /// once with it, otherwise the query runs with each document in turn
+    #[arg(short, long, conflicts_with_all = ["null_input", "merge_sorted_by"])]
+    slurp: bool,
+
+    /// Read the input documents into an object of the file names, e.g.
+    /// `{"a.ason": {...}, "b.ason": {...}}` (a list for the file which has
+    /// multiple documents), and run the query once with it
+    #[arg(
+        long,
+        conflicts_with_all = ["slurp", "null_input", "merge_sorted_by", "stream", "prefetch", "watch", "follow", "lines", "window", "first_match", "jobs", "in_place", "incremental"]
+    )]
+    group_by_file: bool,
+
+    /// Read the input from the byte offset, e.g. a document at a known
+    /// position of a huge file, the bytes before it are not parsed
+    #[arg(long, value_name = "BYTES", conflicts_with = "window")]
+    offset: Option<u64>,
+
+    /// Read only the number of bytes of the input
+    #[arg(long, value_name = "BYTES", conflicts_with = "window")]
+    length: Option<u64>,
+
+    /// Map the input files into the memory and parse them without copying,
+    /// it is automatic for the files of 64 MiB or larger
+    #[arg(long, conflicts_with_all = ["no_mmap", "offset", "length"])]
+    mmap: bool,
+
+    /// Always read the input files into the memory instead of mapping them
+    #[arg(long)]
+    no_mmap: bool,
+
+    /// The header of the requests of the URL inputs, e.g.
+    /// `aq --header 'Authorization: Bearer TOKEN' '.status' https://example.com/health.ason`
+    #[arg(long, value_name = "NAME: VALUE")]
+    header: Vec<String>,
+
+    /// The time limit of fetching each URL input
+    #[arg(long, value_name = "SECONDS", default_value_t = 30)]
+    http_timeout: u64,
+
+    /// Parse the input as a stream of the `[path, leaf]` and `[path]` events
+    /// instead of the whole documents, the query runs with each event (or
+    /// reads them by `inputs` with `-n`), e.g. `aq -n --stream 'fromstream(inputs)'`
+    #[arg(
+        long,
+        conflicts_with_all = ["raw_input", "slurp", "window", "merge_sorted_by", "in_place", "tolerant", "offset", "length"]
+    )]
+    stream: bool,
+
+    /// Read the input incrementally and run the query with each document as
+    /// soon as it is complete, so the memory is bounded by the largest document
+    /// instead of the whole input, the documents are separated by line breaks
+    #[arg(
+        long,
+        conflicts_with_all = ["stream", "prefetch", "watch", "follow", "raw_input", "slurp", "window", "merge_sorted_by", "in_place", "tolerant", "offset", "length", "cache"]
+    )]
+    incremental: bool,
+
+    /// With `--incremental`, the items of the top-level list of each document
+    /// are the inputs instead of the document, e.g. for a huge list of records
+    #[arg(long, requires = "incremental")]
+    items: bool,
+
+    /// Read and parse the next N input files in the background while
+    /// the current one is being evaluated, the query runs with each file
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["stream", "raw_input", "slurp", "window", "merge_sorted_by", "in_place", "null_input", "offset", "length"]
+    )]
+    prefetch: Option<usize>,
+
+    /// Read, parse and evaluate the input files by N threads (0 for the
+    /// number of the CPUs), the results are printed in the order of the files
+    #[arg(
+        long,
+        value_name = "N",
+        conflicts_with_all = ["stream", "prefetch", "watch", "follow", "incremental", "raw_input", "slurp", "window", "merge_sorted_by", "in_place", "null_input", "first_match", "offset", "length"]
+    )]
+    jobs: Option<usize>,
+
+    /// Evaluate the query again whenever any input file changes,
+    /// the fresh results are printed until aq is interrupted
+    #[arg(
+        long,
+        conflicts_with_all = ["stream", "prefetch", "raw_input", "slurp", "window", "merge_sorted_by", "in_place", "null_input", "offset", "length", "cache"]
+    )]
+    watch: bool,
+
+    /// Keep the input files open and evaluate the query with each document
+    /// appended to them (like `tail -f`), the documents are line-delimited,
+    /// or RS-delimited with `--seq`, e.g. `aq --follow 'select(.level == "error")' app.log`
+    #[arg(
+        long,
+        conflicts_with_all = ["stream", "prefetch", "watch", "lines", "slurp", "window", "merge_sorted_by", "in_place", "null_input", "tolerant", "offset", "length", "cache"]
+    )]
+    follow: bool,
+
+    /// Read each line of the input as a string instead of parsing it
+    #[arg(short = 'R', long, conflicts_with_all = ["from_json5", "from_jsonc"])]
+    raw_input: bool,
+
+    /// The format of the input: ason (the default), json, jsonc, json5, ndjson,
+    /// yaml, toml, xml, csv, tsv, msgpack or cbor, the tagged objects of JSON are decoded,
+    /// e.g. `{"$u8": 17}`, and each line of NDJSON, each document of a YAML file
+    /// (separated by `---`), each row of a CSV file and each value of a MessagePack
+    /// stream (or a CBOR sequence) are an input, the elements of XML are converted to
+    /// objects with the attributes `"@name"` and the text `"#text"`
+    #[arg(long, value_name = "FORMAT", conflicts_with_all = ["from_json5", "from_jsonc"])]
+    from: Option<String>,
+
+    /// Read the rows of `--from csv` (or tsv) as lists, otherwise the
+    /// first row is the header, and the other rows are objects keyed by it
+    #[arg(long, requires = "from")]
+    no_header: bool,
+
+    /// Write each document as a record, i.e. it is prefixed with the record
+    /// separator (RS, 0x1E), and read the input as the records (RFC 7464),
+    /// the malformed records (e.g. the truncated ones) are reported and skipped
+    #[arg(
+        long,
+        conflicts_with_all = ["stream", "prefetch", "watch", "window", "in_place", "tolerant", "to_csv", "table", "export_shell", "wrap_tuple"]
+    )]
+    seq: bool,
+
+    /// Read the input as JSON5, i.e. JSON with comments, trailing commas,
+    /// unquoted keys and single-quoted strings
+    #[arg(long, conflicts_with = "from_jsonc")]
+    from_json5: bool,
+
+    /// Read the input as JSON with comments and trailing commas
+    #[arg(long)]
+    from_jsonc: bool,
+
+    /// Check the result with the expression, aq exits with status 1
+    /// if the expression produces no value or any falsy value,
+    /// this option can be specified multiple times
+    #[arg(long, value_name = "EXPR")]
+    assert: Vec<String>,
+
+    /// Print the entries of the result object as shell variable
+    /// assignments, e.g. `name='foo'`
+    #[arg(long)]
+    export_shell: bool,
+
+    /// Track where the values come from, it enables the
+    /// `provenance(f)` builtin
+    #[arg(long)]
+    provenance: bool,
+
+    /// Print each result on a single line with minimal whitespace
+    #[arg(short, long, conflicts_with_all = ["to_msgpack", "to_cbor"])]
+    compact_output: bool,
+
+    /// Write the string results without quotes and escaping, each
+    /// result is printed on its own line
+    #[arg(short, long, conflicts_with_all = ["to_msgpack", "to_cbor"])]
+    raw_output: bool,
+
+    /// Write the byte data results as the raw bytes instead of the hex
+    /// literals, e.g. `aq --binary-output -o cert.der '.tls.certificate' config.ason`,
+    /// the results are concatenated, and they must all be byte data
+    #[arg(
+        long,
+        conflicts_with_all = ["to", "to_msgpack", "to_cbor", "to_csv", "table", "export_shell", "seq", "ascii_output", "wrap_tuple", "lines", "stream", "prefetch", "watch", "follow", "window", "first_match", "in_place"]
+    )]
+    binary_output: bool,
+
+    /// The number of spaces of the indentation, the default is 4,
+    /// and 0 is the same as `--compact-output`
+    #[arg(long, value_name = "N", conflicts_with_all = ["compact_output", "tab"])]
+    indent: Option<usize>,
+
+    /// Indent with a tab instead of spaces
+    #[arg(long, conflicts_with = "compact_output")]
+    tab: bool,
+
+    /// Write the entries of the objects in the order of the keys,
+    /// e.g. for the stable diffs of the generated files
+    #[arg(short = 'S', long, overrides_with = "preserve_order")]
+    sort_keys: bool,
+
+    /// Write the entries of the objects in the order of the input, this is
+    /// the default, the entries added by the query follow the existing ones,
+    /// and the order is kept by all formats except TOML (the tables follow
+    /// the plain values), the last one of this and `--sort-keys` takes effect
+    #[arg(long, overrides_with = "sort_keys")]
+    preserve_order: bool,
+
+    /// Escape the non-ASCII characters of the strings, e.g. `"caf\u{e9}"`,
+    /// so the output is 7-bit clean, only ASON and JSON are supported
+    #[arg(short, long, conflicts_with = "multiline_strings")]
+    ascii_output: bool,
+
+    /// Keep the lists, tuples and objects which fit in the width in
+    /// one line, e.g. `--width 100`, instead of one item per line
+    #[arg(long, value_name = "COLUMNS", conflicts_with = "compact_output")]
+    width: Option<usize>,
+
+    /// Write the strings which contain line breaks as the raw strings,
+    /// e.g. `r"..."`, instead of the `\n` escapes
+    #[arg(long, conflicts_with = "compact_output")]
+    multiline_strings: bool,
+
+    /// The color theme of the output, i.e. "monokai" (the default),
+    /// "solarized", "light", or a theme defined in the config file
+    #[arg(long, value_name = "NAME", conflicts_with_all = ["to_msgpack", "to_cbor"])]
+    theme: Option<String>,
+
+    /// Color the output even if the STDOUT is not a terminal
+    #[arg(short = 'C', long, conflicts_with = "monochrome_output")]
+    color_output: bool,
+
+    /// Do not color the output, the output is colored by default when
+    /// the STDOUT is a terminal and `NO_COLOR` is not set
+    #[arg(short = 'M', long)]
+    monochrome_output: bool,
+
+    /// Wrap multiple results in a tuple, i.e. a single document,
+    /// instead of writing them as the separate documents
+    #[arg(long)]
+    wrap_tuple: bool,
+
+    /// The separator between the documents of multiple results
+    #[arg(
+        long,
+        value_name = "TEXT",
+        default_value = "\n",
+        conflicts_with = "wrap_tuple"
+    )]
+    separator: String,
+
+    /// Write the rows of the result as CSV, i.e. the objects (or lists)
+    /// of the result list
+    #[arg(long, conflicts_with_all = ["table", "to_msgpack", "to_cbor", "export_shell", "raw_output", "compact_output"])]
+    to_csv: bool,
+
+    /// Write the rows of the result as a table with aligned columns
+    #[arg(long, conflicts_with_all = ["to_msgpack", "to_cbor", "export_shell", "raw_output", "compact_output"])]
+    table: bool,
+
+    /// How the null cells are written in CSV and table,
+    /// i.e. "empty" (the default), "null" or "ason"
+    #[arg(long, value_name = "STYLE")]
+    null_as: Option<String>,
+
+    /// How the keys missing from a row are written, the default is "empty"
+    #[arg(long, value_name = "STYLE")]
+    missing_as: Option<String>,
+
+    /// How the nested lists, tuples and objects are written,
+    /// the default is "ason"
+    #[arg(long, value_name = "STYLE")]
+    nested_as: Option<String>,
+
+    /// How the variants are written, the default is "ason"
+    #[arg(long, value_name = "STYLE")]
+    variant_as: Option<String>,
+
+    /// How the byte data are written, the default is "ason"
+    #[arg(long, value_name = "STYLE")]
+    bytes_as: Option<String>,
+
+    /// The format of the output: ason (the default), json, ndjson, msgpack, cbor, yaml,
+    /// toml, xml or csv, each result of NDJSON is a line,
+    /// the types which JSON lacks are written as the tagged objects,
+    /// e.g. `{"$u8": 17}`, so the output can be read back by `--from json`
+    #[arg(long, value_name = "FORMAT", conflicts_with_all = ["to_msgpack", "to_cbor", "export_shell", "to_csv", "table"])]
+    to: Option<String>,
+
+    /// Write the result as MessagePack
+    #[arg(long, conflicts_with = "to_cbor")]
+    to_msgpack: bool,
+
+    /// Write the result as CBOR
+    #[arg(long)]
+    to_cbor: bool,
+
+    /// Allow writing the binary output (e.g. `--to-msgpack`) to the terminal
+    #[arg(long)]
+    allow_binary_tty: bool,
+
+    /// Compress the output with gzip or zstd, it is detected by the extension
+    /// of the output file by default, e.g. `-o result.ason.gz`
+    #[arg(
+        long,
+        value_name = "COMPRESSION",
+        conflicts_with_all = ["lines", "stream", "prefetch", "watch", "follow", "window", "first_match", "in_place", "split_output", "output_template"]
+    )]
+    compress_output: Option<String>,
+
+    /// Reuse the output of the identical invocation, i.e. the same
+    /// arguments, query, variables, modules, environment variables and
+    /// input documents
+    #[arg(long, conflicts_with_all = ["in_place", "stream", "prefetch", "window"])]
+    cache: bool,
+
+    /// Store the compiled query in the directory, and reuse it in the
+    /// later invocations of the same query, i.e. the query text and the
+    /// modules are not parsed again unless they are changed
+    #[arg(long, value_name = "DIR")]
+    compile_cache: Option<PathBuf>,
+
+    /// Show a progress bar of parsing the input documents on STDERR
+    #[arg(long)]
+    progress: bool,
+
+    /// Exit with 1 if the last result is `null` or `false`,
+    /// 4 if there is no result, and 0 otherwise
+    #[arg(short, long)]
+    exit_status: bool,
+
+    /// Report the first pipeline stage which outputs nothing on STDERR
+    /// when the query produces no output
+    #[arg(long)]
+    explain_empty: bool,
+
+    /// Write each stage of the pipeline and the values it produces
+    /// (truncated) to STDERR during the evaluation
+    #[arg(long, conflicts_with = "jobs")]
+    trace: bool,
+
+    /// Drop the results which are equal to an earlier result, the seen
+    /// results are moved to a temporary directory when there are many
+    #[arg(long)]
+    distinct: bool,
+
+    /// Merge the input documents (lists of elements) which are sorted by
+    /// the key expression, the query runs with each element in the sorted
+    /// order as soon as it is merged, only the next element of each file is
+    /// held in the memory
+    #[arg(
+        long,
+        value_name = "EXPR",
+        conflicts_with_all = ["stream", "prefetch", "jobs", "watch", "follow", "incremental", "raw_input", "tolerant", "offset", "length"]
+    )]
+    merge_sorted_by: Option<String>,
+
+    /// Read each line of the input as a document, the query is evaluated
+    /// with each line as soon as it is read, e.g. for `tail -f app.log | aq --lines`
+    #[arg(
+        long,
+        conflicts_with_all = ["stream", "prefetch", "watch", "window", "slurp", "merge_sorted_by", "in_place", "null_input", "tolerant", "offset", "length", "cache"]
+    )]
+    lines: bool,
+
+    /// With `--lines`, read each block of lines separated by the line `---`
+    /// as a document instead, e.g. for the multi-line documents of a log stream
+    #[arg(long, requires = "lines")]
+    blocks: bool,
+
+    /// Write each result of `--lines` with the probability, e.g. `0.01`
+    #[arg(long, value_name = "RATE", requires = "lines")]
+    sample_rate: Option<String>,
+
+    /// Write at most the number of the results of `--lines` in a second
+    /// (or minute, hour), e.g. `100/s`, `30/m`, the rest are dropped
+    #[arg(long, value_name = "N/UNIT", requires = "lines")]
+    rate_limit: Option<String>,
+
+    /// Write the first result and exit with 0 immediately, the rest of the
+    /// input is not read, or exit with 4 if there is no result, e.g.
+    /// `if aq --first-match '.items[] | select(.id == 7)' big.ason > /dev/null`
+    #[arg(
+        long,
+        conflicts_with_all = ["watch", "window", "slurp", "merge_sorted_by", "in_place", "null_input", "tolerant", "offset", "length", "exit_status", "cache"]
+    )]
+    first_match: bool,
+
+    /// Aggregate the line-delimited input in the time windows of the
+    /// width (e.g. `30s`, `5m` or `1h`), the query is evaluated with the
+    /// rollup of each window (`{start, end, count, items}`) when it closes
+    #[arg(long, value_name = "WIDTH")]
+    window: Option<String>,
+
+    /// The time of each value for `--window`, either a datetime or a
+    /// number of seconds since the Unix epoch, it is the value itself by default
+    #[arg(long, value_name = "EXPR")]
+    by: Option<String>,
+
+    /// Abort the evaluation when the function calls are nested
+    /// deeper than the number, e.g. a runaway recursive function
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// Abort the evaluation when the query produces more results
+    /// than the number
+    #[arg(long, value_name = "COUNT")]
+    max_output: Option<usize>,
+
+    /// Abort the evaluation when it takes longer than the seconds
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<f64>,
+
     /*
     a default value can be specified:
     https://docs.rs/clap/latest/clap/_derive/_tutorial/chapter_2/index.html#defaults
@@ -59,15 +655,395 @@ struct AqArgs {
     input_files: Vec<String>,
 }
 
+#[derive(Subcommand, Debug)]
+enum AqCommand {
+    /// Parse a file, reprint it and parse it again, then report the
+    /// information lost in the round trip, e.g. the changed values,
+    /// comments, radix of numbers and key order.
+    ///
+    /// The exit status is 0 if nothing is lost, 1 if any value is changed,
+    /// and 2 if only the formatting information is lost.
+    Roundtrip {
+        /// The ASON file to check
+        file: String,
+    },
+
+    /// Reformat the ASON files in the canonical style, e.g. for a pre-commit
+    /// hook `aq fmt --check --width 100 config/*.ason`, the files which contain
+    /// comments are not formatted, since the comments would be dropped.
+    ///
+    /// The exit status is 1 if any file is not formatted with `--check`,
+    /// or any file cannot be formatted, and 0 otherwise.
+    Fmt {
+        /// The number of spaces of the indentation
+        #[arg(long, value_name = "N", default_value_t = 4)]
+        indent: usize,
+
+        /// Keep the lists, tuples and objects which fit in the width in one line
+        #[arg(long, value_name = "COLUMNS")]
+        width: Option<usize>,
+
+        /// Write a comma after each item which is in its own line
+        #[arg(long)]
+        trailing_commas: bool,
+
+        /// The style of the integers: plain, grouped (e.g. `1_000_000`) or hex
+        #[arg(long, value_name = "STYLE", default_value = "plain")]
+        number_style: String,
+
+        /// Print the names of the files which are not formatted instead of
+        /// formatting them
+        #[arg(long, conflicts_with = "write")]
+        check: bool,
+
+        /// Write the formatted text to the files instead of the STDOUT
+        #[arg(short, long)]
+        write: bool,
+
+        /// The ASON file(s), the STDIN is read if no file is specified
+        files: Vec<String>,
+    },
+
+    /// Generate the type definitions from the documents,
+    /// e.g. `aq codegen --lang rust data.ason`
+    Codegen {
+        /// The language of the generated code, only "rust" is supported
+        #[arg(long, default_value = "rust")]
+        lang: String,
+
+        /// The name of the type of the documents
+        #[arg(long, default_value = "Root")]
+        name: String,
+
+        /// The ASON file(s) to infer the types from
+        files: Vec<String>,
+    },
+
+    /// Convert a file, or all files of a directory tree, between the formats,
+    /// e.g. `aq convert --from json5 --to ason src/ -o dest/`.
+    ///
+    /// The relative structure of the directory tree is kept, and the
+    /// extensions of the files are replaced by the target format.
+    Convert {
+        /// The format of the source files: ason, json, json5, jsonc, ndjson, yaml, toml,
+        /// xml, csv or tsv,
+        /// it is detected by the file extension by default
+        #[arg(long)]
+        from: Option<String>,
+
+        /// The format of the converted files: ason, json, ndjson, msgpack, cbor, yaml,
+        /// toml, xml or csv
+        #[arg(long)]
+        to: String,
+
+        /// The destination file or directory
+        #[arg(short, long, value_name = "DESTINATION")]
+        output: String,
+
+        /// The source file or directory
+        source: String,
+    },
+
+    /// Check the environment and the input files for the common issues,
+    /// e.g. the encoding, BOM, mixed line endings, enormous lines,
+    /// duplicate keys and deep nesting.
+    ///
+    /// The exit status is 1 if any file cannot be processed, and 0 otherwise.
+    Doctor {
+        /// The file(s) to check
+        files: Vec<String>,
+    },
+
+    /// Check the syntax of the documents without printing them, all the
+    /// files are checked, and the malformed entries are reported one by one,
+    /// e.g. `aq check --report ason data/*.ason`.
+    ///
+    /// The exit status is 0 if all documents are valid, 1 if any document
+    /// is invalid, and 2 if any file cannot be read.
+    Check {
+        /// The format of the files, it is detected by the file extension by default
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Write the summary of all files to the STDOUT, in ason or json
+        #[arg(long, value_name = "FORMAT")]
+        report: Option<String>,
+
+        /// The file(s) to check
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    /// Validate the documents against a schema written in ASON, e.g.
+    /// `aq validate --schema schema.ason config.ason`, each violation
+    /// is reported with the path of the value.
+    ///
+    /// The exit status is 0 if all documents are valid, 1 if any document
+    /// is invalid, and 2 if any file can not be read or parsed.
+    Validate {
+        /// The schema file
+        #[arg(long, value_name = "SCHEMA_FILE")]
+        schema: String,
+
+        /// Write the summary of all files to the STDOUT, in ason or json
+        #[arg(long, value_name = "FORMAT")]
+        report: Option<String>,
+
+        /// The file(s) to validate
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    /// Compare two documents structurally, the added, removed and changed
+    /// paths are listed with the old and new values, e.g.
+    /// `aq diff old.ason new.ason`.
+    ///
+    /// The exit status is 0 if the documents are the same, 1 if they
+    /// are different, and 2 if any file can not be read or parsed.
+    Diff {
+        /// The format of the files, it is detected by the file extension by default
+        #[arg(long)]
+        from: Option<String>,
+
+        /// The output format, "text" or "patch" (the patch document
+        /// which can be applied by `aq patch`)
+        #[arg(long, default_value = "text")]
+        format: String,
+
+        /// The old document
+        old: String,
+
+        /// The new document
+        new: String,
+    },
+
+    /// Apply a patch document (the output of `aq diff --format patch`) to a
+    /// document, e.g. `aq patch config.ason changes.ason -o config.new.ason`,
+    /// the patched document is written in the format of the document.
+    Patch {
+        /// The format of the document, it is detected by the file extension by default
+        #[arg(long)]
+        from: Option<String>,
+
+        /// Write the patched document to the file instead of the STDOUT
+        #[arg(short, long, value_name = "OUTPUT_FILE")]
+        output: Option<String>,
+
+        /// The document
+        file: String,
+
+        /// The patch document
+        patch: String,
+    },
+
+    /// Upgrade the query files to the current language version, the
+    /// deprecated builtins are replaced and the `#aq-version` pragma
+    /// is updated, e.g. `aq migrate-query lib/*.aql`.
+    ///
+    /// With `--check`, the files are not changed, and the exit status
+    /// is 1 if any file needs to be migrated.
+    MigrateQuery {
+        /// Report the changes without rewriting the files
+        #[arg(long)]
+        check: bool,
+
+        /// The query file(s) to migrate
+        files: Vec<String>,
+    },
+
+    /// Check the query files without running them, the calls of the
+    /// undefined functions (or with a wrong number of arguments), the
+    /// undefined variables, the unreachable stages and the deprecated
+    /// builtins are reported, e.g. `aq lint lib/*.aql`.
+    ///
+    /// The exit status is 1 if there is any warning.
+    Lint {
+        /// A variable which is defined when the query runs (e.g. by `--arg`),
+        /// this option can be specified multiple times
+        #[arg(long = "variable", value_name = "NAME")]
+        variables: Vec<String>,
+
+        /// Add a directory to search for the imported modules,
+        /// this option can be specified multiple times
+        #[arg(short = 'L', long, value_name = "DIR")]
+        library_path: Vec<PathBuf>,
+
+        /// The query file(s) to check
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    /// Run the test cases of the queries, each case is made up of the query,
+    /// the input document and the expected results, and the results which
+    /// differ from the expected ones are reported, e.g. `aq test tests.aql`.
+    ///
+    /// The exit status is 1 if any case fails.
+    Test {
+        /// Add a directory to search for the imported modules,
+        /// this option can be specified multiple times
+        #[arg(short = 'L', long, value_name = "DIR")]
+        library_path: Vec<PathBuf>,
+
+        /// The file(s) of the test cases
+        #[arg(required = true)]
+        files: Vec<String>,
+    },
+
+    /// Run the language server of the query files with the STDIN and STDOUT,
+    /// for the diagnostics, completion and hover documents in the editors,
+    /// the options can also be specified by the `initializationOptions`.
+    Lsp {
+        /// The sample data file for completing the keys of the objects
+        #[arg(long, value_name = "FILE")]
+        data: Option<String>,
+
+        /// Add a directory to search for the imported modules,
+        /// this option can be specified multiple times
+        #[arg(short = 'L', long, value_name = "DIR")]
+        library_path: Vec<PathBuf>,
+    },
+
+    /// Replace the placeholders of a document by the content of the files,
+    /// e.g. `{file: "cert.pem"}` by the text and `{file: "logo.png", as: "bytes"}`
+    /// by the byte data, the relative paths are resolved against the
+    /// directory of the document.
+    Embed {
+        /// Write the bundled document to the file instead of the STDOUT
+        #[arg(short, long, value_name = "OUTPUT_FILE")]
+        output: Option<String>,
+
+        /// The ASON document
+        file: String,
+    },
+
+    /// Write the large strings and byte data of a document to the individual
+    /// files, and replace them by the placeholders, i.e. the inverse of
+    /// `aq embed`, e.g. `aq extract --dir blobs bundle.ason -o main.ason`.
+    Extract {
+        /// The minimum size (in bytes) of the extracted values
+        #[arg(long, value_name = "BYTES", default_value_t = 4096)]
+        min_size: usize,
+
+        /// The directory of the extracted files, it is relative
+        /// to the directory of the document
+        #[arg(long, default_value = "extracted")]
+        dir: String,
+
+        /// Write the document to the file instead of the STDOUT
+        #[arg(short, long, value_name = "OUTPUT_FILE")]
+        output: Option<String>,
+
+        /// The ASON document
+        file: String,
+    },
+
+    /// Search the string values of the documents with a regular expression,
+    /// and print the paths and values of the matches,
+    /// e.g. `aq grep '@example\.com$' users.ason`.
+    ///
+    /// The exit status is 0 if any value matches, and 1 otherwise.
+    Grep {
+        /// Match the letters case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+
+        /// Search the keys of objects too
+        #[arg(long)]
+        keys: bool,
+
+        /// The regular expression
+        pattern: String,
+
+        /// The file(s) to search, the STDIN is read if no file is specified
+        files: Vec<String>,
+    },
+
+    /// Browse a document in the terminal, i.e. a tree view which can be
+    /// expanded and collapsed, with the incremental search and the query bar,
+    /// the path expression of the selected node is shown in the status line
+    Browse {
+        /// The format of the file, it is detected by the file extension by default
+        #[arg(long)]
+        from: Option<String>,
+
+        /// The file to browse
+        file: String,
+    },
+
+    /// Print the plan of a query without running it, i.e. the stages of the
+    /// pipeline, the syntax tree, and the functions and variables it uses,
+    /// e.g. `aq explain '.users[] | select(.age > 30) | .name'`.
+    Explain {
+        /// Read the query from the file instead of the argument
+        #[arg(short = 'q', long, value_name = "QUERY_FILE", conflicts_with = "query")]
+        query_file: Option<String>,
+
+        /// The query expression
+        #[arg(required_unless_present = "query_file")]
+        query: Option<String>,
+    },
+
+    /// Check the documents against the type definitions
+    Schema {
+        #[command(subcommand)]
+        command: SchemaCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SchemaCommand {
+    /// Check the ASON documents against the structs and enums defined
+    /// in a Rust source file, e.g. `aq schema check --types src/types.rs config.ason`.
+    ///
+    /// The exit status is 0 if all documents match the type, and 1 otherwise.
+    Check {
+        /// The Rust source file which defines the types
+        #[arg(long, value_name = "RUST_FILE")]
+        types: String,
+
+        /// The type of the documents, the first type defined
+        /// in the Rust source file is used by default
+        #[arg(long = "type", value_name = "TYPE_NAME")]
+        type_name: Option<String>,
+
+        /// The ASON file(s) to check
+        files: Vec<String>,
+    },
+}
+
 fn main() {
     // Usage:
     //   aq [options] <query expression> <input file(s)>
     //   aq [options] -o <output file> <query expression> <input file(s)>
     //   aq [options] -o <output file> -q <query file> <input file(s)>
+    //   aq roundtrip <file>
+    //   aq codegen --lang rust [--name <type name>] <file(s)>
+    //   aq schema check --types <rust file> [--type <type name>] <file(s)>
+    //   aq validate --schema <schema file> [--report <format>] <file(s)>
+    //   aq diff [--format text|patch] <old file> <new file>
+    //   aq patch [-o <output file>] <file> <patch file>
+    //   aq test [-L <dir>] <test file(s)>
+    //   aq lsp [--data <sample data file>] [-L <dir>]
+    //   aq doctor <file(s)>
+    //   aq grep [-i] [--keys] <pattern> <file(s)>
+    //   aq convert [--from <format>] --to <format> <file or dir> -o <file or dir>
     //
     // Command options:
     //   -o, --output=FILE      specify the output file
     //   -q, --query=FILE       specify the query file
+    //   --from-json5           read the input as JSON5
+    //   --from-jsonc           read the input as JSON with comments
+    //   --assert=EXPR          check the result with the expression
+    //   --export-shell         print the result object as shell variables
+    //   --provenance           track where the values come from
+    //   --to-msgpack           write the result as MessagePack
+    //   --to-cbor              write the result as CBOR
+    //   --binary-output        write the byte data results as the raw bytes
+    //   --allow-binary-tty     allow writing the binary output to the terminal
+    //   --progress             show the progress bar on STDERR
+    //   --explain-empty        report the stage which outputs nothing
+    //   --trace                print the values flowing through the stages
+    //   --group-by-file        run the query with the documents by the file names
 
     // Run with Cargo
     // --------------
@@ -118,99 +1094,3168 @@ fn main() {
     // println!("{}", title);
     // process::exit(1);
 
-    let aq_args = AqArgs::parse();
+    let mut aq_args = AqArgs::parse();
+
+    if let Some(command) = aq_args.command.take() {
+        match command {
+            AqCommand::Roundtrip { file } => run_roundtrip(&file),
+            AqCommand::Fmt {
+                indent,
+                width,
+                trailing_commas,
+                number_style,
+                check,
+                write,
+                files,
+            } => {
+                let options = NumberStyle::from_name(&number_style).map(|style| {
+                    let options = match indent {
+                        0 => PrintOptions::compact(),
+                        n => PrintOptions::pretty(&" ".repeat(n)),
+                    }
+                    .with_number_style(style);
+                    let options = match width {
+                        Some(w) => options.with_width(w),
+                        None => options,
+                    };
+                    match trailing_commas {
+                        true => options.with_trailing_commas(),
+                        false => options,
+                    }
+                });
+                match options {
+                    Ok(options) => run_fmt(&options, check, write, &files),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+            }
+            AqCommand::Codegen { lang, name, files } => run_codegen(&lang, &name, &files),
+            AqCommand::Doctor { files } => run_doctor(&files),
+            AqCommand::Check {
+                from,
+                report,
+                files,
+            } => run_check(from.as_deref(), report.as_deref(), &files),
+            AqCommand::Validate {
+                schema,
+                report,
+                files,
+            } => run_validate(&schema, report.as_deref(), &files),
+            AqCommand::Diff {
+                from,
+                format,
+                old,
+                new,
+            } => run_diff(from.as_deref(), &format, &old, &new),
+            AqCommand::Patch {
+                from,
+                output,
+                file,
+                patch,
+            } => run_patch(from.as_deref(), &file, &patch, output.as_deref()),
+            AqCommand::MigrateQuery { check, files } => run_migrate_query(check, &files),
+            AqCommand::Lint {
+                variables,
+                library_path,
+                files,
+            } => run_lint(&variables, library_path, &files),
+            AqCommand::Test {
+                library_path,
+                files,
+            } => run_test(library_path, &files),
+            AqCommand::Lsp { data, library_path } => run_lsp(data.as_deref(), library_path),
+            AqCommand::Embed { output, file } => run_embed(&file, output.as_deref()),
+            AqCommand::Extract {
+                min_size,
+                dir,
+                output,
+                file,
+            } => run_extract(&file, min_size, &dir, output.as_deref()),
+            AqCommand::Grep {
+                ignore_case,
+                keys,
+                pattern,
+                files,
+            } => run_grep(&pattern, ignore_case, keys, &files),
+            AqCommand::Convert {
+                from,
+                to,
+                output,
+                source,
+            } => run_convert(from.as_deref(), &to, &source, &output),
+            AqCommand::Browse { from, file } => run_browse(&file, from.as_deref()),
+            AqCommand::Explain { query_file, query } => {
+                run_explain(query.as_deref(), query_file.as_deref())
+            }
+            AqCommand::Schema {
+                command:
+                    SchemaCommand::Check {
+                        types,
+                        type_name,
+                        files,
+                    },
+            } => run_schema_check(&types, type_name.as_deref(), &files),
+        }
+    }
+
+    // the errors of the query mode are reported here, with the exit status.
+    match run_query(aq_args) {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(e) => {
+            eprintln!("{}", e);
+            let code = match e {
+                Error::LimitExceeded(_) => EXIT_LIMIT_EXCEEDED,
+                _ => 1,
+            };
+            process::exit(code);
+        }
+    }
+}
 
+// run the query with the input files (or the STDIN), and write the output,
+// returns the exit status.
+fn run_query(mut aq_args: AqArgs) -> Result<i32, Error> {
     // Note:
     //
     // - The STDIN will be omitted if INPUT_FILES is specified.
-    // - The QUERY_EXPRESSION will be omitted if QUERY_FILE is specified.
+    // - The QUERY_EXPRESSION will be omitted if QUERY_FILE (or the query set) is specified.
     // - The STDOUT will be omitted if OUTPUT_FILE is specified.
 
-    let mut texts = vec![];
+    let mut input_files = std::mem::take(&mut aq_args.input_files);
 
-    if !aq_args.input_files.is_empty() {
-        // if let Some(f) = aq_args.inputs {
+    // the flags override each other, so at most one of them is set.
+    aq_args.sort_keys &= !aq_args.preserve_order;
 
-        println!("{:?}", aq_args.input_files);
+    let query_text = if let Some(f) = aq_args.query.as_ref().or(aq_args.query_set.as_ref()) {
+        // the first positional argument is an input file
+        // when the query file is specified.
+        if let Some(e) = &aq_args.query_expression {
+            input_files.insert(0, e.clone());
+        }
 
-        for f in aq_args.input_files {
-            // text from input file
-            match std::fs::read_to_string(&f) {
-                Ok(s) => {
-                    texts.push(s);
-                }
-                Err(e) => {
-                    eprintln!("Fail to read the specified input file: \"{}\".", &f);
-                    eprintln!("{}", e);
-                    process::exit(1);
-                }
+        if f == "-" {
+            if input_files.is_empty() && !aq_args.null_input {
+                return Err(Error::Message(
+                    "The input files are required when the query is read from the STDIN."
+                        .to_owned(),
+                ));
             }
+
+            let mut text = String::new();
+            std::io::stdin()
+                .lock()
+                .read_to_string(&mut text)
+                .map_err(|e| failure("Fail to read the query from the STDIN.", e))?;
+            text
+        } else {
+            std::fs::read_to_string(f).map_err(|e| {
+                failure(
+                    format!("Fail to read the specified query file: \"{}\".", f),
+                    e,
+                )
+            })?
         }
     } else {
-        // text from STDIN
-        let mut i = std::io::stdin().lock();
-        if i.is_terminal() && aq_args.query_expression.is_none() {
-            eprintln!("Usage: aq [OPTIONS] [QUERY_EXPRESSION]");
-            eprintln!();
-            eprintln!("For more information, try '--help'.");
-            process::exit(1);
-        }
+        // the identity query `.` is used when the query expression is omitted.
+        aq_args
+            .query_expression
+            .clone()
+            .unwrap_or_else(|| ".".to_owned())
+    };
 
-        let mut buf = String::new();
-        match i.read_to_string(&mut buf) {
-            Ok(_) => {
-                texts.push(buf);
-            }
-            Err(e) => {
-                eprintln!("Fail to read the input text from STDIN.");
-                eprintln!("{}", e);
-                process::exit(1);
+    // the modules are resolved against the directory of the query file first.
+    let base_dir = match aq_args
+        .query
+        .as_deref()
+        .or(aq_args.query_set.as_deref())
+        .filter(|f| *f != "-")
+        .map(Path::new)
+    {
+        Some(f) => f.parent().map(Path::to_path_buf).unwrap_or_default(),
+        None => PathBuf::from("."),
+    };
+
+    let loader = ModuleLoader::new(aq_args.library_path.clone()).with_base_dir(base_dir.clone());
+
+    let compile_cache = aq_args
+        .compile_cache
+        .as_ref()
+        .map(|dir| compile_cache(dir, &aq_args, &query_text, &base_dir));
+    let cached = compile_cache.as_ref().and_then(|c| c.load());
+
+    let query = match cached {
+        Some(q) => q,
+        None => {
+            let compiled = if aq_args.query_set.is_some() {
+                parse_input(&query_text, None, &ParseOptions::unlimited())
+                    .and_then(|definition| compile_query_set(&definition, &loader))
+            } else {
+                Query::compile_with_loader(&query_text, &loader)
+            };
+            let query = compiled.map_err(|e| {
+                // the errors of the query set are located in the document.
+                if aq_args.query_set.is_some() {
+                    failure("Fail to parse the query expression.", e)
+                } else {
+                    failure(
+                        "Fail to parse the query expression.",
+                        e.with_source(&query_text),
+                    )
+                }
+            })?;
+            if let Some(cache) = &compile_cache {
+                if let Err(e) = cache.store(&query) {
+                    eprintln!("Fail to store the compiled query in the cache: {}", e);
+                }
             }
+            query
         }
     };
 
-    let mut nodes = vec![];
+    let variables = query_variables(&aq_args, &mut input_files)?;
 
-    for text in texts {
-        match parse_from_str(&text) {
-            Ok(n) => {
-                nodes.push(n);
-            }
-            Err(e) => {
-                eprintln!("{}", e.with_source(&text));
-                process::exit(1);
-            }
+    let query = query.with_variables(variables);
+    let query = if aq_args.trace {
+        query.with_trace()
+    } else {
+        query
+    };
+
+    // the limits apply to every mode, including the streaming ones.
+    let query = query.with_limits(Limits {
+        max_depth: aq_args.max_depth,
+        max_output: aq_args.max_output,
+        timeout: aq_args.timeout.map(Duration::from_secs_f64),
+    });
+
+    let mut assertions = vec![];
+    for text in &aq_args.assert {
+        let assertion = Query::compile(text).map_err(|e| {
+            failure(
+                format!("Fail to parse the assertion: \"{}\".", text),
+                e.with_source(text),
+            )
+        })?;
+        assertions.push((text, assertion));
+    }
+
+    let merge_sorted_by = match &aq_args.merge_sorted_by {
+        Some(text) => Some(Query::compile(text).map_err(|e| {
+            failure(
+                format!("Fail to parse the merge key: \"{}\".", text),
+                e.with_source(text),
+            )
+        })?),
+        None => None,
+    };
+
+    let (input_format, output_format) = io_formats(&aq_args)?;
+    check_streaming_options(&aq_args, output_format)?;
+    let dialect = input_format.json_dialect();
+
+    // the results of the streaming modes are written line by line.
+    let line_format = LineFormat {
+        raw: aq_args.raw_output,
+        json: matches!(output_format, Format::Json | Format::Ndjson),
+        sort_keys: aq_args.sort_keys,
+        ascii: aq_args.ascii_output,
+        seq: aq_args.seq,
+        options: layout_options(&aq_args),
+    };
+
+    let compression = output_compression(&aq_args)?;
+    let http = HttpOptions::new(&aq_args.header, aq_args.http_timeout)?;
+
+    // the modes which read the files repeatedly (or write
+    // them), or seek in them, do not support the URLs.
+    if let Some(url) = input_files.iter().find(|f| is_url(f)) {
+        let modes = [
+            ("--in-place", aq_args.in_place.is_some()),
+            ("--prefetch", aq_args.prefetch.is_some()),
+            ("--jobs", aq_args.jobs.is_some()),
+            ("--watch", aq_args.watch),
+            ("--follow", aq_args.follow),
+            ("--stream", aq_args.stream),
+            ("--first-match", aq_args.first_match),
+        ];
+        if let Some((flag, _)) = modes.iter().find(|(_, enabled)| *enabled) {
+            return Err(Error::Message(format!(
+                "The URL input \"{}\" is not supported by \"{}\".",
+                url, flag
+            )));
         }
     }
 
-    let root = if nodes.len() == 1 {
-        nodes.remove(0)
+    if let Some(width) = &aq_args.window {
+        run_window(
+            width,
+            &query,
+            &input_files,
+            dialect,
+            &aq_args,
+            &line_format,
+            &http,
+        );
+    }
+
+    if aq_args.lines {
+        run_lines(&query, &input_files, dialect, &aq_args, &line_format, &http);
+    }
+
+    if let Some(suffix) = &aq_args.in_place {
+        run_in_place(
+            &query,
+            &input_files,
+            dialect,
+            &layout_options(&aq_args),
+            aq_args.sort_keys,
+            aq_args.preserve,
+            suffix,
+        );
+    }
+
+    if let Some(depth) = aq_args.prefetch {
+        run_prefetch(query, &input_files, dialect, depth, &aq_args, &line_format);
+    }
+
+    if let Some(jobs) = aq_args.jobs {
+        run_jobs(&query, &input_files, dialect, jobs, &aq_args, &line_format);
+    }
+
+    if aq_args.watch {
+        run_watch(&query, &input_files, dialect, &line_format);
+    }
+
+    if aq_args.follow {
+        run_follow(&query, &input_files, dialect, &aq_args, &line_format);
+    }
+
+    if aq_args.stream {
+        run_stream(query, &input_files, dialect, &aq_args, &line_format);
+    }
+
+    if aq_args.incremental {
+        run_incremental(query, &input_files, &http, dialect, &aq_args, &line_format);
+    }
+
+    if let Some(key) = merge_sorted_by {
+        run_merge_sorted(
+            query,
+            key,
+            &input_files,
+            &http,
+            dialect,
+            &aq_args,
+            &line_format,
+        );
+    }
+
+    if aq_args.first_match {
+        run_first_match(&query, &input_files, input_format, &aq_args, &line_format);
+    }
+
+    // the content of each input, the binary formats are not text.
+    let mut texts: Vec<InputData> = vec![];
+
+    // the names of the input files, `None` for the STDIN.
+    let sources: Vec<Option<String>> = if input_files.is_empty() {
+        vec![None]
     } else {
-        ason::ast::AsonNode::Tuple(nodes)
+        input_files.iter().cloned().map(Some).collect()
     };
 
-    if let Some(f) = aq_args.output {
-        match std::fs::write(&f, print_to_string(&root)) {
-            Err(e) => {
-                eprintln!("Fail to write to the output file: \"{}\".", f);
-                eprintln!("{}", e);
-                process::exit(1);
-            }
-            Ok(_) => {
-                // ok
+    // the whole input is read unless a byte range is specified.
+    let range = (aq_args.offset.is_some() || aq_args.length.is_some()).then(|| ByteRange {
+        offset: aq_args.offset.unwrap_or(0),
+        length: aq_args.length,
+    });
+
+    if !input_files.is_empty() {
+        for f in input_files {
+            // the body of the URL
+            if is_url(&f) {
+                let body = fetch_url(&f, &http).and_then(|body| {
+                    read_input(body.as_slice(), range)
+                        .map_err(|e| Error::Message(format!("Fail to fetch \"{}\": {}", f, e)))
+                })?;
+                texts.push(body.into());
+                continue;
             }
+
+            // text from input file, the bytes before the offset of
+            // a file which is not compressed are skipped without reading.
+            let text = std::fs::File::open(&f).and_then(|file| {
+                // the large files are mapped instead of being read.
+                if range.is_none() && !aq_args.no_mmap {
+                    if let Some(map) = map_input(&file, aq_args.mmap)? {
+                        return Ok(InputData::Mapped(map));
+                    }
+                }
+
+                let mut reader = std::io::BufReader::new(file);
+                match range {
+                    Some(range) if detect(&mut reader)?.is_none() => {
+                        read_file_range(&mut reader.into_inner(), range).map(String::into_bytes)
+                    }
+                    _ => read_input(reader, range),
+                }
+                .map(InputData::from)
+            });
+            let text = text.map_err(|e| {
+                failure(
+                    format!("Fail to read the specified input file: \"{}\".", &f),
+                    e,
+                )
+            })?;
+            texts.push(text);
         }
+    } else if aq_args.null_input {
+        // no input document, the input files (if any) can
+        // still be consumed with `input` and `inputs`.
     } else {
-        let mut w = std::io::stdout().lock();
-        match print_to_writer(&mut w, &root) {
-            Err(e) => {
-                eprintln!("Fail to write to the STDOUT.");
-                eprintln!("{}", e);
-                process::exit(1);
+        // text from STDIN
+        let i = std::io::stdin().lock();
+        if i.is_terminal() && aq_args.query.is_none() && aq_args.query_expression.is_none() {
+            return Err(Error::Message(
+                "Usage: aq [OPTIONS] [QUERY_EXPRESSION]\n\nFor more information, try '--help'."
+                    .to_owned(),
+            ));
+        }
+
+        let text = read_input(i, range)
+            .map_err(|e| failure("Fail to read the input text from STDIN.", e))?;
+        texts.push(text.into());
+    };
+
+    // the stored output is written without evaluating the query.
+    let cache = if aq_args.cache {
+        result_cache(&query, &query_text, &texts, &sources)
+    } else {
+        None
+    };
+    if let Some(output) = cache.as_ref().and_then(|c| c.load()) {
+        let data = if output.binary {
+            OutputData::Binary(output.data)
+        } else {
+            OutputData::Text(String::from_utf8_lossy(&output.data).into_owned())
+        };
+        write_output(
+            aq_args.output.as_deref(),
+            &compressed(data, compression),
+            aq_args.allow_binary_tty,
+        );
+        return Ok(output.exit_code);
+    }
+
+    let mut values = vec![];
+
+    let bytes_total = texts.iter().map(|t| t.len() as u64).sum();
+    let mut progress_bar = ProgressBar::new(aq_args.progress);
+    let mut tracker = ProgressTracker::new(Some(bytes_total), Some(texts.len()), |p| {
+        progress_bar.draw(p)
+    });
+
+    // the source of each document, for the `input_filename` and
+    // `input_line_number` builtins and the provenance.
+    let mut document_sources = vec![];
+
+    for (data, filename) in texts.iter().zip(&sources) {
+        let (size, modified) = file_metadata(filename.as_deref(), data.len());
+        if aq_args.raw_input {
+            let text = input_text(data)?;
+
+            // each line is a string document
+            for (idx, line) in text.lines().enumerate() {
+                values.push(Value::String(line.to_owned()));
+                document_sources.push(DocumentSource {
+                    filename: filename.clone(),
+                    line: idx + 1,
+                    size,
+                    modified,
+                });
+            }
+            tracker.advance(text.len() as u64, 1);
+            continue;
+        }
+
+        let parsed = if aq_args.tolerant {
+            input_text(data)
+                .and_then(|text| parse_tolerant(text, dialect, &ParseOptions::unlimited()))
+                .map(|(v, malformed)| {
+                    for entry in malformed {
+                        eprintln!(
+                            "{}:{}: skipped a malformed entry: {}",
+                            filename.as_deref().unwrap_or("<stdin>"),
+                            entry.line,
+                            entry.message
+                        );
+                    }
+                    vec![v]
+                })
+        } else {
+            parse_input_documents(data, input_format, &aq_args)
+        };
+
+        match parsed {
+            Ok(documents) => {
+                for v in documents {
+                    values.push(v);
+                    document_sources.push(DocumentSource {
+                        filename: filename.clone(),
+                        line: 1,
+                        size,
+                        modified,
+                    });
+                }
+                tracker.advance(data.len() as u64, 1);
             }
-            Ok(_) => {
-                //
+            Err(e) => {
+                let message = match input_format {
+                    Format::Ason => return Err(e),
+                    Format::Yaml => "Fail to parse the input YAML text.",
+                    Format::Toml => "Fail to parse the input TOML text.",
+                    Format::Xml => "Fail to parse the input XML text.",
+                    Format::Csv | Format::Tsv => "Fail to parse the input table.",
+                    Format::MsgPack => "Fail to decode the input MessagePack data.",
+                    Format::Cbor => "Fail to decode the input CBOR data.",
+                    _ => "Fail to parse the input JSON text.",
+                };
+                return Err(failure(message, e));
             }
         }
     }
+
+    if aq_args.progress {
+        // move to the next line
+        eprintln!();
+    }
+
+    // the documents can also be consumed one by one
+    // with the `input` and `inputs` builtins.
+    let query = query
+        .with_inputs(values.clone())
+        .with_input_sources(document_sources.clone());
+    let inputs = if aq_args.explain_empty {
+        values.clone()
+    } else {
+        vec![]
+    };
+
+    // the input files of the roots (if each root is a document), the
+    // file of the failing root is reported with the evaluation error.
+    let root_files: Vec<_> = if aq_args.null_input || aq_args.slurp || aq_args.group_by_file {
+        vec![]
+    } else {
+        document_sources
+            .iter()
+            .map(|s| s.filename.clone())
+            .collect()
+    };
+
+    // the query runs with each root in turn.
+    let roots = if aq_args.null_input {
+        vec![Value::null()]
+    } else if aq_args.slurp {
+        vec![Value::List(values.into())]
+    } else if aq_args.group_by_file {
+        vec![group_by_file(values, &document_sources)]
+    } else {
+        values
+    };
+
+    let query = if aq_args.provenance {
+        query.with_provenance(document_sources.into_iter().map(|s| s.filename).collect())
+    } else {
+        query
+    };
+
+    let query = query.with_error_paths();
+
+    let explain_root = roots.first().filter(|_| aq_args.explain_empty).cloned();
+
+    // the duplicated results are dropped as soon as they are produced.
+    let mut distinct = aq_args.distinct.then(DistinctSet::new);
+    let mut results = vec![];
+    let mut collect = |value: Value| {
+        if let Some(set) = &mut distinct {
+            if !set.insert(&value)? {
+                return Ok(());
+            }
+        }
+        results.push(if aq_args.sort_keys {
+            value.sort_keys()
+        } else {
+            value
+        });
+        Ok(())
+    };
+    let outcome = if root_files.is_empty() {
+        // the documents are combined, e.g. by `--slurp`.
+        roots
+            .into_iter()
+            .try_for_each(|root| query.run_with(root, &mut collect).map_err(|e| (0, e)))
+    } else {
+        // each document is the input of a run in turn, except the
+        // ones consumed by the `input` and `inputs` builtins.
+        iter::from_fn(|| query.next_input()).try_for_each(|(idx, root)| {
+            query
+                .run_document(idx, root, &mut collect)
+                .map_err(|e| (idx, e))
+        })
+    };
+
+    // the results before `halt` (or `halt_error`) are written
+    // as usual, and the process exits with the status.
+    let mut halted = None;
+    match outcome {
+        Err((_, Error::Halted(status, message))) => {
+            write_halt_message(message.as_ref());
+            halted = Some(status);
+        }
+        Err((idx, e)) => {
+            return Err(match (e, root_files.get(idx)) {
+                (Error::Message(message), Some(Some(filename))) => failure(
+                    message,
+                    format!("The input document is from the file \"{}\".", filename),
+                ),
+                (e, _) => e,
+            });
+        }
+        Ok(()) => {}
+    }
+
+    if let Some(root) = explain_root.filter(|_| results.is_empty() && halted.is_none()) {
+        explain_empty_result(&query_text, &root, &inputs, &loader);
+    }
+
+    let colored = if output_format != Format::Ason || aq_args.export_shell {
+        false
+    } else if aq_args.color_output || aq_args.theme.is_some() {
+        true
+    } else if aq_args.monochrome_output
+        || std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+    {
+        false
+    } else {
+        aq_args.output.is_none() && std::io::stdout().is_terminal()
+    };
+
+    let theme = colored
+        .then(|| {
+            load_config().and_then(|config| {
+                let name = aq_args
+                    .theme
+                    .as_deref()
+                    .or(config.default_theme.as_deref())
+                    .unwrap_or("monokai");
+                config.theme(name)
+            })
+        })
+        .transpose()?;
+
+    let mut print_options = layout_options(&aq_args);
+    if let Some(theme) = theme {
+        print_options = print_options.with_theme(theme);
+    }
+
+    // in the compact and raw output (and NDJSON), each result is a line,
+    // except YAML, TOML and XML, which have no compact form.
+    let line_text =
+        ((aq_args.compact_output || aq_args.raw_output || output_format == Format::Ndjson)
+            && !matches!(
+                output_format,
+                Format::Yaml | Format::Toml | Format::Xml | Format::Csv
+            ))
+        .then(|| {
+            let wrapped;
+            let values = if aq_args.wrap_tuple && results.len() != 1 {
+                wrapped = [Value::Tuple(results.clone().into())];
+                &wrapped[..]
+            } else {
+                &results[..]
+            };
+            values
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) if aq_args.raw_output => format!("{}\n", s),
+                    _ if matches!(output_format, Format::Json | Format::Ndjson) => {
+                        format!("{}\n", to_tagged_json(v, None))
+                    }
+                    _ => result_line(v, aq_args.raw_output, &print_options),
+                })
+                .collect::<String>()
+        });
+
+    let exit_code = match (halted, results.last()) {
+        (Some(status), _) => status,
+        _ if !aq_args.exit_status => 0,
+        (_, Some(value)) if value.is_truthy() => 0,
+        (_, Some(_)) => 1,
+        (_, None) => EXIT_NO_OUTPUT,
+    };
+
+    // multiple results are written as the separate documents unless
+    // `--wrap-tuple` is specified, the assertions and the shell export
+    // check the tuple of them.
+    let separate = results.len() != 1 && !aq_args.wrap_tuple;
+    let documents = separate.then(|| results.clone());
+    let result = if results.len() == 1 {
+        results.remove(0)
+    } else {
+        Value::Tuple(results.into())
+    };
+
+    // the output is not written if any assertion fails.
+    for (text, assertion) in assertions {
+        let values = assertion
+            .run_value(result.clone())
+            .map_err(|e| failure(format!("Fail to evaluate the assertion: \"{}\".", text), e))?;
+
+        if values.is_empty() || !values.iter().all(|v| v.is_truthy()) {
+            return Err(Error::Message(format!("Assertion failed: \"{}\".", text)));
+        }
+    }
+
+    if aq_args.output_template.is_some() || aq_args.split_output {
+        let documents = documents.unwrap_or_else(|| vec![result]);
+        write_split_files(&aq_args, documents, output_format, &loader)?;
+        return Ok(exit_code);
+    }
+
+    if aq_args.export_shell {
+        let text = export_shell(&result)?;
+        finish_output(
+            &aq_args,
+            OutputData::Text(text),
+            exit_code,
+            cache.as_ref(),
+            compression,
+        );
+        return Ok(exit_code);
+    }
+
+    if aq_args.to_csv || aq_args.table || output_format == Format::Csv {
+        let format = if aq_args.to_csv || output_format == Format::Csv {
+            TableFormat::Csv
+        } else {
+            TableFormat::Table
+        };
+        let text =
+            table_options(&aq_args).and_then(|options| export_table(&result, format, &options))?;
+        finish_output(
+            &aq_args,
+            OutputData::Text(text),
+            exit_code,
+            cache.as_ref(),
+            compression,
+        );
+        return Ok(exit_code);
+    }
+
+    // the binary documents are concatenated, i.e. a MessagePack
+    // stream or a CBOR sequence.
+    let documents = documents.unwrap_or_else(|| vec![result]);
+    let ascii = aq_args.ascii_output.then_some(output_format);
+    let data = if aq_args.binary_output {
+        OutputData::Binary(byte_data(documents)?)
+    } else if aq_args.seq {
+        // each document is a record, i.e. RS, the text and LF
+        let text = documents
+            .iter()
+            .map(|v| {
+                let text = match v {
+                    Value::String(s) if aq_args.raw_output => s.clone(),
+                    _ if matches!(output_format, Format::Json | Format::Ndjson) => {
+                        let indent = print_options.indent.as_deref();
+                        to_tagged_json(v, indent.filter(|_| output_format == Format::Json))
+                    }
+                    _ => print_result(v, &print_options),
+                };
+                format!("{}{}\n", RECORD_SEPARATOR, text.trim_end_matches('\n'))
+            })
+            .collect();
+        OutputData::Text(text)
+    } else if let Some(text) = line_text {
+        OutputData::Text(text)
+    } else if output_format == Format::MsgPack {
+        OutputData::Binary(documents.iter().flat_map(encode_msgpack).collect())
+    } else if output_format == Format::Cbor {
+        OutputData::Binary(documents.iter().flat_map(encode_cbor).collect())
+    } else if output_format == Format::Json {
+        let texts: Vec<String> = documents
+            .iter()
+            .map(|v| to_tagged_json(v, print_options.indent.as_deref()))
+            .collect();
+        OutputData::Text(format!("{}\n", texts.join(&aq_args.separator)))
+    } else if output_format == Format::Yaml {
+        // the documents of YAML are separated by `---`
+        let texts: Vec<String> = documents.iter().map(to_yaml).collect();
+        OutputData::Text(texts.join("---\n"))
+    } else if matches!(output_format, Format::Toml | Format::Xml) {
+        let convert = if output_format == Format::Toml {
+            to_toml
+        } else {
+            to_xml
+        };
+        let texts = documents
+            .iter()
+            .map(convert)
+            .collect::<Result<Vec<_>, Error>>()?;
+        OutputData::Text(texts.join(&aq_args.separator))
+    } else {
+        let texts: Vec<String> = documents
+            .iter()
+            .map(|v| print_result(v, &print_options))
+            .collect();
+        OutputData::Text(texts.join(&aq_args.separator))
+    };
+    let data = match (data, ascii) {
+        (OutputData::Text(text), Some(format)) => OutputData::Text(ascii_text(&text, format)),
+        (data, _) => data,
+    };
+    finish_output(&aq_args, data, exit_code, cache.as_ref(), compression);
+    Ok(exit_code)
+}
+
+// the variables from the command line, the later ones shadow the earlier
+// ones with the same name, and `$ARGS` (the first one) collects them and
+// the positional arguments (which are taken from the input files).
+fn query_variables(
+    aq_args: &AqArgs,
+    input_files: &mut Vec<String>,
+) -> Result<Vec<(String, Value)>, Error> {
+    let mut variables = vec![];
+    for pair in aq_args.arg.chunks(2) {
+        variables.push((
+            variable_name(&pair[0], "--arg"),
+            Value::String(pair[1].clone()),
+        ));
+    }
+    for pair in aq_args.argason.chunks(2) {
+        let name = variable_name(&pair[0], "--argason");
+        let value = parse_input(&pair[1], None, &ParseOptions::unlimited()).map_err(|e| {
+            failure(
+                format!("Fail to parse the value of the variable \"${}\".", name),
+                e,
+            )
+        })?;
+        variables.push((name, value));
+    }
+    let files = [
+        ("--rawfile", &aq_args.rawfile),
+        ("--slurpfile", &aq_args.slurpfile),
+        ("--argfile", &aq_args.argfile),
+    ];
+    for (flag, pairs) in files {
+        for pair in pairs.chunks(2) {
+            let name = variable_name(&pair[0], flag);
+            let value = file_variable(flag, &pair[1]).map_err(|e| {
+                failure(
+                    format!(
+                        "Fail to load the variable \"${}\" from the file: \"{}\".",
+                        name, pair[1]
+                    ),
+                    e,
+                )
+            })?;
+            variables.push((name, value));
+        }
+    }
+
+    // `$ARGS` collects the positional arguments and the named variables.
+    let mut positional = vec![];
+    if aq_args.args || aq_args.asonargs {
+        for word in std::mem::take(input_files) {
+            if aq_args.args {
+                positional.push(Value::String(word));
+                continue;
+            }
+            let value = parse_input(&word, None, &ParseOptions::unlimited()).map_err(|e| {
+                failure(
+                    format!("Fail to parse the positional argument \"{}\".", word),
+                    e,
+                )
+            })?;
+            positional.push(value);
+        }
+    }
+    let mut named = Map::new();
+    for (name, value) in &variables {
+        named.insert(name.clone(), value.clone());
+    }
+    let mut args = Map::new();
+    args.insert("positional".to_owned(), Value::List(positional.into()));
+    args.insert("named".to_owned(), Value::Object(named));
+    variables.insert(0, ("ARGS".to_owned(), Value::Object(args)));
+    Ok(variables)
+}
+
+// the key of the compile cache includes the paths which decide the
+// modules to load, and the current directory since they are relative.
+fn compile_cache(dir: &Path, aq_args: &AqArgs, query_text: &str, base_dir: &Path) -> CompileCache {
+    let kind = if aq_args.query_set.is_some() {
+        "query-set"
+    } else {
+        "query"
+    };
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    let current_dir = current_dir.to_string_lossy();
+    let base_dir = base_dir.to_string_lossy();
+    let library_paths: Vec<_> = aq_args
+        .library_path
+        .iter()
+        .map(|p| p.to_string_lossy())
+        .collect();
+
+    let mut parts: Vec<&[u8]> = vec![
+        env!("CARGO_PKG_VERSION").as_bytes(),
+        kind.as_bytes(),
+        query_text.as_bytes(),
+        current_dir.as_bytes(),
+        base_dir.as_bytes(),
+    ];
+    parts.extend(library_paths.iter().map(|p| p.as_bytes()));
+    CompileCache::new(dir.to_path_buf(), &parts)
+}
+
+// the key of the cache includes whether the STDOUT is a terminal
+// and `NO_COLOR`, since they decide whether the output is colored,
+// and everything else the query can read (see the module `cache`),
+// `None` if any imported module cannot be read.
+fn result_cache(
+    query: &Query,
+    query_text: &str,
+    texts: &[InputData],
+    sources: &[Option<String>],
+) -> Option<ResultCache> {
+    let arguments: Vec<String> = std::env::args_os()
+        .skip(1)
+        .map(|a| a.to_string_lossy().into_owned())
+        .collect();
+    let terminal = std::io::stdout().is_terminal().to_string();
+    let no_color = std::env::var("NO_COLOR").unwrap_or_default();
+
+    // the values of the variables include the content of the files.
+    let variables: Vec<String> = query
+        .variables()
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect();
+    let modules: Vec<String> = module_hashes(query)
+        .ok()?
+        .into_iter()
+        .map(|(path, hash)| format!("{}:{:032x}", path, hash))
+        .collect();
+    let mut environment: Vec<String> = std::env::vars_os()
+        .map(|(k, v)| format!("{}={}", k.to_string_lossy(), v.to_string_lossy()))
+        .collect();
+    environment.sort();
+    // the modified time is read by `$__input__`.
+    let modified: Vec<String> = sources
+        .iter()
+        .map(|f| format!("{:?}", file_metadata(f.as_deref(), 0).1))
+        .collect();
+
+    let mut parts: Vec<&[u8]> = vec![
+        env!("CARGO_PKG_VERSION").as_bytes(),
+        terminal.as_bytes(),
+        no_color.as_bytes(),
+        query_text.as_bytes(),
+    ];
+    parts.extend(arguments.iter().map(|a| a.as_bytes()));
+    parts.extend(texts.iter().map(|t| &t[..]));
+    for group in [&variables, &modules, &environment, &modified] {
+        // an empty part separates the groups, none of the items is empty.
+        parts.push(b"");
+        parts.extend(group.iter().map(|p| p.as_bytes()));
+    }
+    Some(ResultCache::new(cache_dir()?, &parts).with_max_size(cache_max_size()))
+}
+
+// write the output, the output is stored if the cache is enabled, a
+// failure of storing it is reported but does not change the exit code.
+fn finish_output(
+    aq_args: &AqArgs,
+    data: OutputData,
+    exit_code: i32,
+    cache: Option<&ResultCache>,
+    compression: Option<Compression>,
+) {
+    if let Some(cache) = cache {
+        let output = match &data {
+            OutputData::Text(text) => CachedOutput {
+                exit_code,
+                binary: false,
+                data: text.as_bytes().to_vec(),
+            },
+            OutputData::Binary(bytes) => CachedOutput {
+                exit_code,
+                binary: true,
+                data: bytes.clone(),
+            },
+        };
+        if let Err(e) = cache.store(&output) {
+            eprintln!("Fail to store the output in the cache: {}", e);
+        }
+    }
+
+    write_output(
+        aq_args.output.as_deref(),
+        &compressed(data, compression),
+        aq_args.allow_binary_tty,
+    );
+}
+
+// the error with the message of the failed action, e.g.
+// "Fail to read the specified input file: ..." and the reason.
+fn failure(message: impl Display, reason: impl Display) -> Error {
+    Error::Message(format!("{}\n{}", message, reason))
+}
+
+// the compression of the output, by `--compress-output`
+// or the extension of the output file.
+fn output_compression(aq_args: &AqArgs) -> Result<Option<Compression>, Error> {
+    match (&aq_args.compress_output, &aq_args.output) {
+        (Some(name), _) => Compression::from_name(name).map(Some),
+        (None, Some(f)) => Ok(Compression::from_path(Path::new(f))),
+        (None, None) => Ok(None),
+    }
+}
+
+fn compressed(data: OutputData, compression: Option<Compression>) -> OutputData {
+    let Some(compression) = compression else {
+        return data;
+    };
+    let bytes = match &data {
+        OutputData::Text(text) => text.as_bytes(),
+        OutputData::Binary(bytes) => bytes.as_slice(),
+    };
+    match compress(bytes, compression) {
+        Ok(bytes) => OutputData::Binary(bytes),
+        Err(e) => {
+            eprintln!("Fail to compress the output.");
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// read the whole input, the compressed input is decompressed, and the
+// byte range (if specified) is the range of the decompressed data.
+fn read_input(reader: impl BufRead, range: Option<ByteRange>) -> std::io::Result<Vec<u8>> {
+    let mut reader = decompress_reader(reader)?;
+    match range {
+        Some(range) => read_range(reader, range).map(String::into_bytes),
+        None => {
+            let mut buf = vec![];
+            reader.read_to_end(&mut buf).map(|_| buf)
+        }
+    }
+}
+
+// read the whole input file, the same as the usual mode, i.e. the
+// compressed file is decompressed.
+fn read_input_file(f: &str) -> Result<Vec<u8>, Error> {
+    std::fs::File::open(f)
+        .and_then(|file| read_input(std::io::BufReader::new(file), None))
+        .map_err(|e| {
+            Error::Message(format!(
+                "Fail to read the specified input file: \"{}\".\n{}",
+                f, e
+            ))
+        })
+}
+
+// draws the progress on the same line of the STDERR, at most
+// 10 times per second, except the last one.
+struct ProgressBar {
+    enabled: bool,
+    last_drawn: Option<Instant>,
+}
+
+impl ProgressBar {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            last_drawn: None,
+        }
+    }
+
+    fn draw(&mut self, progress: &Progress) {
+        let finished = progress.documents_total == Some(progress.documents_done);
+        let due = self
+            .last_drawn
+            .is_none_or(|t| t.elapsed() >= Duration::from_millis(100));
+
+        if self.enabled && (finished || due) {
+            // clear the rest of the line with the ANSI escape code
+            eprint!("\r{}\x1b[K", render_progress_bar(progress, 30));
+            self.last_drawn = Some(Instant::now());
+        }
+    }
+}
+
+// the layout of the ASON output, the indentation `--indent 0` is
+// the same as the compact layout.
+fn layout_options(aq_args: &AqArgs) -> PrintOptions {
+    let mut options = match aq_args.indent {
+        _ if aq_args.compact_output => PrintOptions::compact(),
+        _ if aq_args.tab => PrintOptions::pretty("\t"),
+        Some(0) => PrintOptions::compact(),
+        Some(n) => PrintOptions::pretty(&" ".repeat(n)),
+        None => PrintOptions::pretty("    "),
+    };
+    if let Some(width) = aq_args.width {
+        options = options.with_width(width);
+    }
+    if aq_args.multiline_strings {
+        options = options.with_multiline_strings();
+    }
+    options
+}
+
+// escape the non-ASCII characters of the ASON or JSON text.
+fn ascii_text(text: &str, format: Format) -> String {
+    if matches!(format, Format::Json | Format::Ndjson) {
+        escape_non_ascii_json(text)
+    } else {
+        escape_non_ascii(text)
+    }
+}
+
+// the formats of the input and the output, the flags `--from-json5`,
+// `--to-msgpack` etc. are the same as the formats.
+fn io_formats(aq_args: &AqArgs) -> Result<(Format, Format), Error> {
+    let from = match &aq_args.from {
+        Some(name) => Format::from_name(name)?,
+        None if aq_args.from_json5 => Format::Json5,
+        None if aq_args.from_jsonc => Format::Jsonc,
+        None => Format::Ason,
+    };
+
+    // the documents of the other formats are not line-delimited,
+    // and the streaming parser only supports ASON and JSON.
+    if !matches!(
+        from,
+        Format::Ason | Format::Json | Format::Json5 | Format::Jsonc | Format::Ndjson
+    ) {
+        let modes = [
+            ("--window", aq_args.window.is_some()),
+            ("--lines", aq_args.lines),
+            ("--in-place", aq_args.in_place.is_some()),
+            ("--prefetch", aq_args.prefetch.is_some()),
+            ("--jobs", aq_args.jobs.is_some()),
+            ("--watch", aq_args.watch),
+            ("--follow", aq_args.follow),
+            ("--stream", aq_args.stream),
+            ("--incremental", aq_args.incremental),
+            ("--merge-sorted-by", aq_args.merge_sorted_by.is_some()),
+            ("--tolerant", aq_args.tolerant),
+        ];
+        if let Some((flag, _)) = modes.iter().find(|(_, enabled)| *enabled) {
+            return Err(Error::Message(format!(
+                "The input format \"{}\" is not supported by \"{}\".",
+                from.name(),
+                flag
+            )));
+        }
+    }
+
+    let to = match &aq_args.to {
+        Some(name) => Format::from_name(name)?,
+        None if aq_args.to_msgpack => Format::MsgPack,
+        None if aq_args.to_cbor => Format::Cbor,
+        None => Format::Ason,
+    };
+    if matches!(to, Format::Json5 | Format::Jsonc | Format::Tsv) {
+        return Err(Error::Message(format!(
+            "Writing the format \"{}\" is not supported.",
+            to.name()
+        )));
+    }
+    let text_modes = [
+        ("--ascii-output", aq_args.ascii_output),
+        ("--seq", aq_args.seq),
+    ];
+    if let Some((flag, _)) = text_modes.iter().find(|(_, enabled)| *enabled) {
+        if !matches!(to, Format::Ason | Format::Json | Format::Ndjson) {
+            return Err(Error::Message(format!(
+                "The output format \"{}\" is not supported by \"{}\".",
+                to.name(),
+                flag
+            )));
+        }
+    }
+    Ok((from, to))
+}
+
+// the flag of the mode which writes each result as soon as it is
+// produced, in the order of the dispatch in `run_query`.
+fn streaming_mode(aq_args: &AqArgs) -> Option<&'static str> {
+    [
+        ("--window", aq_args.window.is_some()),
+        ("--lines", aq_args.lines),
+        ("--in-place", aq_args.in_place.is_some()),
+        ("--prefetch", aq_args.prefetch.is_some()),
+        ("--jobs", aq_args.jobs.is_some()),
+        ("--watch", aq_args.watch),
+        ("--follow", aq_args.follow),
+        ("--stream", aq_args.stream),
+        ("--incremental", aq_args.incremental),
+        ("--merge-sorted-by", aq_args.merge_sorted_by.is_some()),
+        ("--first-match", aq_args.first_match),
+    ]
+    .into_iter()
+    .find_map(|(flag, enabled)| enabled.then_some(flag))
+}
+
+// the streaming modes write the results line by line, so the options
+// which need all of the results (or another output) are rejected, as
+// well as the ones a mode cannot apply, e.g. `--in-place` writes the
+// input files only, and `--watch` never exits.
+fn check_streaming_options(aq_args: &AqArgs, output_format: Format) -> Result<(), Error> {
+    let Some(mode) = streaming_mode(aq_args) else {
+        return Ok(());
+    };
+    let in_place = aq_args.in_place.is_some();
+
+    if !matches!(output_format, Format::Ason | Format::Json | Format::Ndjson)
+        || (in_place && output_format != Format::Ason)
+    {
+        return Err(Error::Message(format!(
+            "The output format \"{}\" is not supported by \"{}\".",
+            output_format.name(),
+            mode
+        )));
+    }
+
+    let options = [
+        ("--assert", !aq_args.assert.is_empty()),
+        ("--cache", aq_args.cache),
+        ("--compress-output", aq_args.compress_output.is_some()),
+        ("--to-csv", aq_args.to_csv),
+        ("--table", aq_args.table),
+        ("--export-shell", aq_args.export_shell),
+        ("--binary-output", aq_args.binary_output),
+        ("--output-template", aq_args.output_template.is_some()),
+        ("--split-output", aq_args.split_output),
+        ("--wrap-tuple", aq_args.wrap_tuple),
+        ("--provenance", aq_args.provenance),
+        ("--explain-empty", aq_args.explain_empty),
+        ("--group-by-file", aq_args.group_by_file),
+        (
+            "--output",
+            aq_args.output.is_some() && (in_place || aq_args.watch),
+        ),
+        (
+            "--distinct",
+            aq_args.distinct && (in_place || aq_args.watch || aq_args.jobs.is_some()),
+        ),
+        (
+            "--exit-status",
+            aq_args.exit_status && (in_place || aq_args.watch || aq_args.follow),
+        ),
+    ];
+    if let Some((flag, _)) = options.iter().find(|(_, enabled)| *enabled) {
+        return Err(Error::Message(format!(
+            "The option \"{}\" is not supported by \"{}\".",
+            flag, mode
+        )));
+    }
+    Ok(())
+}
+
+// write each result to its own file, the files are not colored.
+fn write_split_files(
+    aq_args: &AqArgs,
+    documents: Vec<Value>,
+    output_format: Format,
+    loader: &ModuleLoader,
+) -> Result<(), Error> {
+    let template = match &aq_args.output_template {
+        Some(t) => Some(compile_template(t, loader)?),
+        None => None,
+    };
+    let print_options = layout_options(aq_args);
+
+    for document in documents {
+        let (name, content) = split_target(document, template.as_ref())?;
+        let data = match &content {
+            Value::ByteData(b) if aq_args.binary_output => b.clone(),
+            Value::String(s) if aq_args.raw_output => s.clone().into_bytes(),
+            _ if output_format == Format::Ason => {
+                print_result(&content, &print_options).into_bytes()
+            }
+            _ if output_format == Format::Json => {
+                let text = to_tagged_json(&content, print_options.indent.as_deref());
+                format!("{}\n", text).into_bytes()
+            }
+            _ => write_document(&content, output_format)?,
+        };
+        let data = match aq_args.ascii_output {
+            true => ascii_text(&String::from_utf8_lossy(&data), output_format).into_bytes(),
+            false => data,
+        };
+        write_split_file(&name, &data)?;
+    }
+    Ok(())
+}
+
+// the concatenated bytes of the results of `--binary-output`.
+fn byte_data(documents: Vec<Value>) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![];
+    for document in documents {
+        match document {
+            Value::ByteData(b) => bytes.extend(b),
+            _ => {
+                return Err(Error::Message(format!(
+                    "The results of \"--binary-output\" expect byte data, found {}.",
+                    document.type_name()
+                )))
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+// the value of the variable of `--rawfile`, `--slurpfile` or `--argfile`.
+fn file_variable(flag: &str, path: &str) -> Result<Value, Error> {
+    let data = std::fs::read(path)
+        .and_then(decompress)
+        .map_err(|e| Error::Message(format!("Fail to read the file: {}", e)))?;
+    let format = Format::from_path(Path::new(path)).unwrap_or(Format::Ason);
+    match flag {
+        "--rawfile" => input_text(&data).map(|s| Value::String(s.to_owned())),
+        "--argfile" => read_document(&data, format),
+        _ => {
+            let documents = match format {
+                Format::MsgPack => decode_msgpack(&data)?,
+                Format::Cbor => decode_cbor(&data)?,
+                _ => parse_documents(input_text(&data)?, format, &ParseOptions::unlimited())?,
+            };
+            Ok(Value::List(documents.into()))
+        }
+    }
+}
+
+// the rows of CSV and TSV are lists instead of objects with `--no-header`.
+fn parse_input_documents(
+    data: &[u8],
+    input_format: Format,
+    aq_args: &AqArgs,
+) -> Result<Vec<Value>, Error> {
+    match input_format {
+        Format::MsgPack => decode_msgpack(data),
+        Format::Cbor => decode_cbor(data),
+        _ if aq_args.seq => Ok(parse_records(input_text(data)?, input_format)),
+        Format::Csv if aq_args.no_header => parse_table(input_text(data)?, ',', false),
+        Format::Tsv if aq_args.no_header => parse_table(input_text(data)?, '\t', false),
+        _ => parse_documents(input_text(data)?, input_format, &ParseOptions::unlimited()),
+    }
+}
+
+// the documents of the records separated by RS, the malformed records
+// are skipped, e.g. the last one written by an interrupted producer.
+fn parse_records(text: &str, input_format: Format) -> Vec<Value> {
+    let mut documents = vec![];
+    let records = text
+        .split(RECORD_SEPARATOR)
+        .filter(|r| !r.trim().is_empty());
+    for (index, record) in records.enumerate() {
+        match parse_documents(record, input_format, &ParseOptions::unlimited()) {
+            Ok(values) => documents.extend(values),
+            Err(e) => {
+                eprintln!("Skip the malformed record {}.", index + 1);
+                eprintln!("{}", e);
+            }
+        }
+    }
+    documents
+}
+
+fn input_text(data: &[u8]) -> Result<&str, Error> {
+    std::str::from_utf8(data)
+        .map_err(|e| Error::Message(format!("The input is not valid UTF-8 text: {}.", e)))
+}
+
+// the cell styles of `--to-csv` and `--table`.
+fn table_options(aq_args: &AqArgs) -> Result<TableOptions, Error> {
+    let mut options = TableOptions::default();
+    let styles = [
+        (&aq_args.null_as, &mut options.null),
+        (&aq_args.missing_as, &mut options.missing),
+        (&aq_args.nested_as, &mut options.nested),
+        (&aq_args.variant_as, &mut options.variant),
+        (&aq_args.bytes_as, &mut options.bytes),
+    ];
+    for (name, slot) in styles {
+        if let Some(name) = name {
+            *slot = CellStyle::from_name(name)?;
+        }
+    }
+    Ok(options)
+}
+
+// the ASON text of a result, the pretty layout is written by the
+// ASON printer unless any layout option is specified.
+fn print_result(value: &Value, options: &PrintOptions) -> String {
+    if *options == PrintOptions::pretty("    ") {
+        print_to_string(&AsonNode::from(value.clone()))
+    } else {
+        print_value(value, options)
+    }
+}
+
+// the text of a result in the line-oriented output, the strings
+// are written as they are in the raw output.
+fn result_line(value: &Value, raw: bool, options: &PrintOptions) -> String {
+    match value {
+        Value::String(s) if raw => format!("{}\n", s),
+        _ => format!("{}\n", print_result(value, options).trim_end()),
+    }
+}
+
+// the format of each result of the streaming modes, i.e. a line of
+// the ASON text or the tagged JSON, the strings are written as is with
+// `--raw-output`.
+struct LineFormat {
+    raw: bool,
+    json: bool,
+    sort_keys: bool,
+    ascii: bool,
+    seq: bool,
+    options: PrintOptions,
+}
+
+impl LineFormat {
+    fn line(&self, value: &Value) -> String {
+        let line = if self.sort_keys {
+            self.format(&value.clone().sort_keys())
+        } else {
+            self.format(value)
+        };
+        let line = match (self.ascii, self.json) {
+            (false, _) => line,
+            (true, true) => escape_non_ascii_json(&line),
+            (true, false) => escape_non_ascii(&line),
+        };
+        if self.seq {
+            format!("{}{}", RECORD_SEPARATOR, line)
+        } else {
+            line
+        }
+    }
+
+    fn format(&self, value: &Value) -> String {
+        match value {
+            Value::String(s) if self.raw => format!("{}\n", s),
+            _ if self.json => format!("{}\n", to_tagged_json(value, None)),
+            _ => result_line(value, false, &self.options),
+        }
+    }
+}
+
+/// The encoded result to be written.
+enum OutputData {
+    Text(String),
+
+    /// The data of the binary formats, it is written as raw bytes.
+    Binary(Vec<u8>),
+}
+
+// write the data to the output file, or to the STDOUT
+// if the output file is not specified.
+//
+// like curl, the binary data is not written to the terminal unless
+// `--allow-binary-tty` is specified, since it may mess up the terminal.
+fn write_output(output_file: Option<&str>, data: &OutputData, allow_binary_tty: bool) {
+    let bytes = match data {
+        OutputData::Text(text) => text.as_bytes(),
+        OutputData::Binary(bytes) => bytes.as_slice(),
+    };
+
+    if let Some(f) = output_file {
+        if let Err(e) = std::fs::write(f, bytes) {
+            eprintln!("Fail to write to the output file: \"{}\".", f);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    } else {
+        let mut w = std::io::stdout().lock();
+        if matches!(data, OutputData::Binary(_)) && w.is_terminal() && !allow_binary_tty {
+            eprintln!("Binary output can mess up your terminal.");
+            eprintln!("Use \"--allow-binary-tty\" to output it to the terminal anyway, or");
+            eprintln!("redirect the output to a file, or specify the output file by \"-o\".");
+            process::exit(1);
+        }
+
+        if let Err(e) = w.write_all(bytes) {
+            eprintln!("Fail to write to the STDOUT.");
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// the documents by the names of the input files, i.e. `--group-by-file`,
+// the documents of a file are in a list if there are more than one.
+fn group_by_file(values: Vec<Value>, sources: &[DocumentSource]) -> Value {
+    let mut groups: Vec<(String, Vec<Value>)> = vec![];
+    for (value, source) in values.into_iter().zip(sources) {
+        let name = source.filename.as_deref().unwrap_or("<stdin>");
+        match groups.iter_mut().find(|(n, _)| n == name) {
+            Some((_, documents)) => documents.push(value),
+            None => groups.push((name.to_owned(), vec![value])),
+        }
+    }
+
+    let map = groups
+        .into_iter()
+        .map(|(name, mut documents)| match documents.len() {
+            1 => (name, documents.remove(0)),
+            _ => (name, Value::List(documents.into())),
+        })
+        .collect();
+    Value::Object(map)
+}
+
+// the size and the last modification time of the input file, the
+// size of the data is used for the STDIN and the URLs.
+fn file_metadata(filename: Option<&str>, data_size: usize) -> (u64, Option<DateTime<FixedOffset>>) {
+    match filename
+        .filter(|f| !is_url(f))
+        .and_then(|f| std::fs::metadata(f).ok())
+    {
+        Some(metadata) => (
+            metadata.len(),
+            metadata
+                .modified()
+                .ok()
+                .map(|t| DateTime::<Utc>::from(t).fixed_offset()),
+        ),
+        None => (data_size as u64, None),
+    }
+}
+
+// `halt` and `halt_error` stop the query, the process exits with the
+// status after the message is written.
+fn exit_if_halted(e: &Error) {
+    if let Error::Halted(status, message) = e {
+        write_halt_message(message.as_ref());
+        process::exit(*status);
+    }
+}
+
+// the same as jq, the string message is written as is (without the
+// line feed), and the other values are written as the ASON text.
+fn write_halt_message(message: Option<&Value>) {
+    match message {
+        Some(Value::String(s)) => eprint!("{}", s),
+        Some(v) => eprintln!("{}", v),
+        None => {}
+    }
+}
+
+fn explain_empty_result(query_text: &str, root: &Value, inputs: &[Value], loader: &ModuleLoader) {
+    eprintln!("The query produces no output.");
+    match explain_empty(query_text, root, inputs, loader) {
+        Ok(Some(stage)) => eprintln!("{}", stage),
+        Ok(None) => eprintln!("Every stage of the pipeline outputs values."),
+        Err(e) => eprintln!("Fail to explain the empty output: {}", e),
+    }
+}
+
+// the name of the variable defined by the option, without the `$`.
+fn variable_name(name: &str, option: &str) -> String {
+    let name = name.strip_prefix('$').unwrap_or(name);
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+    if !valid {
+        eprintln!(
+            "Invalid variable name \"{}\" of the option {}.",
+            name, option
+        );
+        process::exit(1);
+    }
+    name.to_owned()
+}
+
+// run the query with each input file, and replace the file with the
+// results, the files which have been rewritten are kept if a later
+// one fails.
+fn run_in_place(
+    query: &Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    print_options: &PrintOptions,
+    sort_keys: bool,
+    preserve: bool,
+    backup_suffix: &str,
+) -> ! {
+    if input_files.is_empty() {
+        eprintln!("The input files are required by \"--in-place\".");
+        process::exit(1);
+    }
+    if preserve && dialect.is_some() {
+        eprintln!("The formatting can be preserved only for the ASON documents.");
+        process::exit(1);
+    }
+
+    for f in input_files {
+        // the compressed file is rewritten in the same compression.
+        let outcome = std::fs::read(f)
+            .and_then(|data| Ok((detect(&mut data.as_slice())?, decompress(data)?)))
+            .map_err(|e| {
+                Error::Message(format!(
+                    "Fail to read the specified input file: \"{}\".\n{}",
+                    f, e
+                ))
+            })
+            .and_then(|(compression, data)| {
+                input_text(&data).map(|text| (compression, text.to_owned()))
+            });
+        let (compression, text) = match outcome {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        let outcome = parse_input(&text, dialect, &ParseOptions::unlimited())
+            .and_then(|document| query.run_value(document));
+        let mut results = match outcome {
+            Ok(results) => results,
+            Err(e) => {
+                exit_if_halted(&e);
+                eprintln!("Fail to process the input file: \"{}\".", f);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        let result = if results.len() == 1 {
+            results.remove(0)
+        } else {
+            Value::Tuple(results.into())
+        };
+        let result = if sort_keys {
+            result.sort_keys()
+        } else {
+            result
+        };
+        let content = if preserve {
+            match preserve_edits(&text, &result, print_options) {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("Fail to process the input file: \"{}\".", f);
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        } else {
+            print_result(&result, print_options)
+        };
+        let content = match compression {
+            Some(compression) => compress(content.as_bytes(), compression),
+            None => Ok(content.into_bytes()),
+        };
+        if let Err(e) =
+            content.and_then(|content| write_in_place(Path::new(f), &content, backup_suffix))
+        {
+            eprintln!("Fail to rewrite the input file: \"{}\".", f);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    process::exit(0)
+}
+
+// run the query with the events of the streaming parse, and print
+// the results as soon as they are produced.
+fn run_stream(
+    query: Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+) -> ! {
+    let mut readers: Vec<Box<dyn BufRead>> = vec![];
+    if input_files.is_empty() {
+        match decompress_reader(std::io::stdin().lock()) {
+            Ok(reader) => readers.push(reader),
+            Err(e) => {
+                eprintln!("Fail to read the input text from STDIN.");
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        for f in input_files {
+            match std::fs::File::open(f)
+                .map(std::io::BufReader::new)
+                .and_then(decompress_reader)
+            {
+                Ok(reader) => readers.push(reader),
+                Err(e) => {
+                    eprintln!("Fail to read the specified input file: \"{}\".", f);
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+
+    let events = readers
+        .into_iter()
+        .flat_map(move |reader| stream_events(reader, dialect));
+    run_each(
+        query,
+        Rc::new(RefCell::new(events)),
+        aq_args.null_input,
+        ResultWriter::new(aq_args, line_format),
+    )
+}
+
+// run the query with each document (or each item of the top-level
+// lists) of the incremental parse.
+fn run_incremental(
+    query: Query,
+    input_files: &[String],
+    http: &HttpOptions,
+    dialect: Option<JsonDialect>,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+) -> ! {
+    let items = aq_args.items;
+    let documents = open_readers(input_files, http)
+        .into_iter()
+        .flat_map(move |reader| incremental_documents(reader, dialect, items));
+    run_each(
+        query,
+        Rc::new(RefCell::new(documents)),
+        aq_args.null_input,
+        ResultWriter::new(aq_args, line_format),
+    )
+}
+
+// run the query with each element of the sorted merge of the inputs, the
+// items of the top-level lists are read one by one.
+fn run_merge_sorted(
+    query: Query,
+    key: Query,
+    input_files: &[String],
+    http: &HttpOptions,
+    dialect: Option<JsonDialect>,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+) -> ! {
+    let sources = open_readers(input_files, http)
+        .into_iter()
+        .map(|reader| incremental_documents(reader, dialect, true))
+        .collect();
+    let elements = merge_sorted(sources, key)
+        .map(|element| element.map_err(|e| failure("Fail to merge the sorted inputs.", e)));
+    run_each(
+        query,
+        Rc::new(RefCell::new(elements)),
+        false,
+        ResultWriter::new(aq_args, line_format),
+    )
+}
+
+// run the query with the inputs which are loaded ahead by the threads.
+fn run_prefetch(
+    query: Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    depth: usize,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+) -> ! {
+    if input_files.is_empty() {
+        eprintln!("The input files are required by \"--prefetch\".");
+        process::exit(1);
+    }
+
+    let documents = prefetch(input_files.to_vec(), depth, move |f: String| {
+        let data = read_input_file(&f)?;
+        parse_input(input_text(&data)?, dialect, &ParseOptions::unlimited())
+    });
+    run_each(
+        query,
+        Rc::new(RefCell::new(documents)),
+        false,
+        ResultWriter::new(aq_args, line_format),
+    )
+}
+
+// run the query with each input file by the worker threads, each of
+// them loads its own copy of the query since the query (and the values
+// it produces) are bound to the thread.
+fn run_jobs(
+    query: &Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    jobs: usize,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+) -> ! {
+    if input_files.is_empty() {
+        eprintln!("The input files are required by \"--jobs\".");
+        process::exit(1);
+    }
+
+    let program = query.to_program();
+    let variables = query.variables().to_vec();
+    let limits = query.limits();
+    let mut writer = ResultWriter::new(aq_args, line_format);
+    let jobs = if jobs == 0 { default_jobs() } else { jobs };
+
+    let outcome = parallel(
+        input_files.to_vec(),
+        jobs,
+        || {
+            Ok(Query::from_program(&program)?
+                .with_variables(variables.clone())
+                .with_limits(limits)
+                .with_error_paths())
+        },
+        |query: &mut Query, f: String| {
+            let data = read_input_file(&f)?;
+            let value = parse_input(input_text(&data)?, dialect, &ParseOptions::unlimited())?;
+            let mut lines = String::new();
+            let results = query.run_value(value).map_err(|e| match e {
+                Error::Message(message) => Error::Message(format!(
+                    "{}\nThe input document is from the file \"{}\".",
+                    message, f
+                )),
+                _ => e,
+            })?;
+            for v in &results {
+                lines.push_str(&line_format.line(v));
+            }
+            Ok((lines, results.last().map(Value::is_truthy)))
+        },
+        |(lines, last): (String, Option<bool>)| writer.write_lines(&lines, last),
+    );
+
+    if let Err(e) = outcome {
+        exit_if_halted(&e);
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    writer.finish()
+}
+
+// evaluate the query with the documents of the input files, and again
+// after any of them changes, the errors are reported without exiting
+// since the file may be changed again, e.g. it is being edited.
+fn run_watch(
+    query: &Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    line_format: &LineFormat,
+) -> ! {
+    if input_files.is_empty() {
+        eprintln!("The input files are required by \"--watch\".");
+        process::exit(1);
+    }
+
+    let watcher = match FileWatcher::new(input_files) {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let terminal = std::io::stdout().is_terminal();
+    loop {
+        // the results of the last evaluation are cleared on the terminal.
+        let mut text = String::from(if terminal { "\x1b[2J\x1b[H" } else { "" });
+        for f in input_files {
+            let outcome = read_input_file(f)
+                .and_then(|data| {
+                    parse_input(input_text(&data)?, dialect, &ParseOptions::unlimited())
+                })
+                .and_then(|v| query.run_value(v));
+            match outcome {
+                Ok(values) => {
+                    for v in values {
+                        text.push_str(&line_format.line(&v));
+                    }
+                }
+                Err(e) => eprintln!("{}", e),
+            }
+        }
+
+        let mut w = std::io::stdout().lock();
+        if let Err(e) = w.write_all(text.as_bytes()).and_then(|_| w.flush()) {
+            eprintln!("Fail to write to the STDOUT.");
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        drop(w);
+
+        if let Err(e) = watcher.wait() {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+// evaluate the query with each document appended to the input files,
+// until aq is interrupted.
+fn run_follow(
+    query: &Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+) -> ! {
+    if input_files.is_empty() {
+        eprintln!("The input files are required by \"--follow\".");
+        process::exit(1);
+    }
+
+    let separator = if aq_args.seq {
+        RECORD_SEPARATOR as u8
+    } else {
+        b'\n'
+    };
+    let mut files = vec![];
+    for f in input_files {
+        match FollowedFile::open(f, separator) {
+            Ok(file) => files.push(file),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    let mut writer = ResultWriter::new(aq_args, line_format);
+    let mut print = |v: Value| writer.write(&v);
+
+    loop {
+        for file in &mut files {
+            let records = match file.read_records() {
+                Ok(r) => r,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            // an invalid document is reported, and the following
+            // documents are still evaluated.
+            for text in records {
+                let value = if aq_args.raw_input {
+                    Ok(Value::String(text))
+                } else {
+                    parse_input(&text, dialect, &ParseOptions::unlimited())
+                };
+                if let Err(e) = value.and_then(|v| query.run_with(v, &mut print)) {
+                    exit_if_halted(&e);
+                    eprintln!("{}", e);
+                }
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// run the query with each input of the stream (or once with `null`),
+// and print the results as soon as they are produced.
+fn run_each(query: Query, stream: InputStream, null_input: bool, mut writer: ResultWriter) -> ! {
+    let query = query.with_input_stream(Rc::clone(&stream));
+
+    let mut print = |v: Value| writer.write(&v);
+    let mut run = |root: Value| {
+        if let Err(e) = query.run_with(root, &mut print) {
+            exit_if_halted(&e);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    if null_input {
+        run(Value::null());
+    } else {
+        loop {
+            // the input is taken before the run, since the query
+            // can also read the next inputs by `input`.
+            let next = stream.borrow_mut().next();
+            match next {
+                Some(Ok(value)) => run(value),
+                Some(Err(e)) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+                None => break,
+            }
+        }
+    }
+    writer.finish()
+}
+
+// the writer of the streaming modes, each result is written to the
+// output file (or the STDOUT) and flushed as soon as it is produced.
+struct ResultWriter<'a> {
+    output: Box<dyn Write>,
+    // the name of the output in the error messages
+    target: String,
+    line_format: &'a LineFormat,
+    distinct: Option<DistinctSet>,
+    first_match: bool,
+    exit_status: bool,
+    // whether the last result is truthy, `None` if there is no result yet.
+    last: Option<bool>,
+}
+
+impl<'a> ResultWriter<'a> {
+    fn new(aq_args: &AqArgs, line_format: &'a LineFormat) -> Self {
+        let (output, target): (Box<dyn Write>, String) = match &aq_args.output {
+            Some(f) => match std::fs::File::create(f) {
+                Ok(file) => (
+                    Box::new(std::io::BufWriter::new(file)),
+                    format!("the output file \"{}\"", f),
+                ),
+                Err(e) => {
+                    eprintln!("Fail to write to the output file: \"{}\".", f);
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            },
+            None => (Box::new(std::io::stdout()), "the STDOUT".to_owned()),
+        };
+
+        Self {
+            output,
+            target,
+            line_format,
+            distinct: aq_args.distinct.then(DistinctSet::new),
+            first_match: aq_args.first_match,
+            exit_status: aq_args.exit_status,
+            last: None,
+        }
+    }
+
+    // write the result, the rest of the evaluation and the input are
+    // abandoned after the first result with `--first-match`.
+    fn write(&mut self, value: &Value) -> Result<(), Error> {
+        if let Some(set) = &mut self.distinct {
+            if !set.insert(value)? {
+                return Ok(());
+            }
+        }
+
+        let line = self.line_format.line(value);
+        self.write_lines(&line, Some(value.is_truthy()))?;
+        if self.first_match {
+            self.finish();
+        }
+        Ok(())
+    }
+
+    // write the lines of the results which are formatted elsewhere,
+    // `last` is whether the last one of them is truthy.
+    fn write_lines(&mut self, lines: &str, last: Option<bool>) -> Result<(), Error> {
+        self.output
+            .write_all(lines.as_bytes())
+            .and_then(|_| self.output.flush())
+            .map_err(|e| Error::Message(format!("Fail to write to {}: {}", self.target, e)))?;
+        if last.is_some() {
+            self.last = last;
+        }
+        Ok(())
+    }
+
+    // exit after the results are written, the status is of the last
+    // result with `--exit-status` (the same as the usual mode).
+    fn finish(&self) -> ! {
+        process::exit(match self.last {
+            Some(true) if self.exit_status => 0,
+            Some(false) if self.exit_status => 1,
+            None if self.exit_status || self.first_match => EXIT_NO_OUTPUT,
+            _ => 0,
+        })
+    }
+}
+
+// run the query with the documents of the input files one by one,
+// the files after the first result are not read.
+fn run_first_match(
+    query: &Query,
+    input_files: &[String],
+    input_format: Format,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+) -> ! {
+    let mut writer = ResultWriter::new(aq_args, line_format);
+    let mut print = |v: Value| writer.write(&v);
+
+    let sources: Vec<Option<&String>> = if input_files.is_empty() {
+        vec![None]
+    } else {
+        input_files.iter().map(Some).collect()
+    };
+    for source in sources {
+        let data = match source {
+            Some(f) => read_input_file(f),
+            None => read_input(std::io::stdin().lock(), None)
+                .map_err(|e| Error::Message(format!("Fail to read the STDIN.\n{}", e))),
+        };
+
+        let documents = data.and_then(|data| {
+            if aq_args.raw_input {
+                input_text(&data).map(|t| t.lines().map(|l| Value::String(l.to_owned())).collect())
+            } else {
+                parse_input_documents(&data, input_format, aq_args)
+            }
+        });
+        let outcome = documents.and_then(|documents| {
+            documents
+                .into_iter()
+                .try_for_each(|v| query.run_with(v, &mut print))
+        });
+        if let Err(e) = outcome {
+            exit_if_halted(&e);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    writer.finish()
+}
+
+// read the values line by line, and print the results of each
+// window as soon as it closes, e.g. for `tail -f`.
+fn run_window(
+    width: &str,
+    query: &Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+    http: &HttpOptions,
+) -> ! {
+    let width = match parse_window_width(width) {
+        Ok(w) => w,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let by_text = aq_args.by.as_deref().unwrap_or(".");
+    let by = match Query::compile(by_text) {
+        Ok(q) => q,
+        Err(e) => {
+            eprintln!("Fail to parse the window time: \"{}\".", by_text);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let readers = open_readers(input_files, http);
+    let mut aggregator = WindowAggregator::new(width, &by);
+    let mut writer = ResultWriter::new(aq_args, line_format);
+    let mut emit = |rollup: Value| {
+        let outcome = query.run_with(rollup, &mut |v| writer.write(&v));
+        if let Err(e) = outcome {
+            exit_if_halted(&e);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    for reader in readers {
+        for line in reader.lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => {
+                    eprintln!("Fail to read the input text.");
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let value = if aq_args.raw_input {
+                Ok(Value::String(line))
+            } else {
+                parse_input(&line, dialect, &ParseOptions::unlimited())
+            };
+            match value.and_then(|value| aggregator.push(value)) {
+                Ok(Some(rollup)) => emit(rollup),
+                Ok(None) => {}
+                Err(e) => {
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+
+    if let Some(rollup) = aggregator.finish() {
+        emit(rollup);
+    }
+    if aggregator.dropped > 0 {
+        eprintln!(
+            "{} value(s) were dropped since their windows had been closed.",
+            aggregator.dropped
+        );
+    }
+    writer.finish()
+}
+
+// the readers of the input files, or the STDIN if no file is specified.
+fn open_readers(input_files: &[String], http: &HttpOptions) -> Vec<Box<dyn BufRead>> {
+    let mut readers: Vec<Box<dyn BufRead>> = vec![];
+    if input_files.is_empty() {
+        match decompress_reader(std::io::stdin().lock()) {
+            Ok(reader) => readers.push(reader),
+            Err(e) => {
+                eprintln!("Fail to read the input text from STDIN.");
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    } else {
+        for f in input_files {
+            if is_url(f) {
+                match open_url(f, http).and_then(|body| {
+                    decompress_reader(std::io::BufReader::new(body))
+                        .map_err(|e| Error::Message(format!("Fail to fetch \"{}\": {}", f, e)))
+                }) {
+                    Ok(body) => readers.push(body),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        process::exit(1);
+                    }
+                }
+                continue;
+            }
+            match std::fs::File::open(f)
+                .map(std::io::BufReader::new)
+                .and_then(decompress_reader)
+            {
+                Ok(reader) => readers.push(reader),
+                Err(e) => {
+                    eprintln!("Fail to read the specified input file: \"{}\".", f);
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+    readers
+}
+
+// evaluate the query with each line as soon as it is read, the results
+// are sampled and rate limited (if specified) before they are written.
+fn run_lines(
+    query: &Query,
+    input_files: &[String],
+    dialect: Option<JsonDialect>,
+    aq_args: &AqArgs,
+    line_format: &LineFormat,
+    http: &HttpOptions,
+) -> ! {
+    let mut sampler = match aq_args.sample_rate.as_deref().map(parse_sample_rate) {
+        None => None,
+        Some(Ok(rate)) => Some(Sampler::new(rate)),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let mut limiter = match aq_args.rate_limit.as_deref().map(parse_rate_limit) {
+        None => None,
+        Some(Ok(per_second)) => Some(RateLimiter::new(per_second)),
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut limited = 0usize;
+    let mut writer = ResultWriter::new(aq_args, line_format);
+    let mut print = |v: Value| {
+        if sampler.as_mut().is_some_and(|s| !s.keep()) {
+            return Ok(());
+        }
+        if limiter.as_mut().is_some_and(|l| !l.allow(Instant::now())) {
+            limited += 1;
+            return Ok(());
+        }
+        writer.write(&v)
+    };
+
+    let mut evaluate = |mut text: String| {
+        if aq_args.seq {
+            text.retain(|c| c != RECORD_SEPARATOR);
+        }
+        if text.trim().is_empty() {
+            return;
+        }
+        let value = if aq_args.raw_input {
+            Ok(Value::String(text))
+        } else {
+            parse_input(&text, dialect, &ParseOptions::unlimited())
+        };
+        if let Err(e) = value.and_then(|v| query.run_with(v, &mut print)) {
+            exit_if_halted(&e);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    for reader in open_readers(input_files, http) {
+        // the lines of the current block with `--blocks`
+        let mut block = String::new();
+        for line in reader.lines() {
+            let line = match line {
+                Ok(l) => l,
+                Err(e) => {
+                    eprintln!("Fail to read the input text.");
+                    eprintln!("{}", e);
+                    process::exit(1);
+                }
+            };
+
+            if !aq_args.blocks {
+                evaluate(line);
+            } else if line.trim_end() == "---" {
+                evaluate(std::mem::take(&mut block));
+            } else {
+                block.push_str(&line);
+                block.push('\n');
+            }
+        }
+        evaluate(block);
+    }
+
+    if limited > 0 {
+        eprintln!("{} result(s) were dropped by the rate limit.", limited);
+    }
+    writer.finish()
+}
+
+fn run_explain(query: Option<&str>, query_file: Option<&str>) -> ! {
+    let source = match (query, query_file) {
+        (Some(q), _) => q.to_owned(),
+        (None, Some(f)) => match std::fs::read_to_string(f) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Fail to read the specified query file: \"{}\".", f);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        },
+        (None, None) => unreachable!(),
+    };
+
+    match explain_query(&source) {
+        Ok(text) => {
+            print!("{}", text);
+            process::exit(0)
+        }
+        Err(e) => {
+            eprintln!("{}", e.with_source(&source));
+            process::exit(1);
+        }
+    }
+}
+
+fn run_browse(file: &str, from: Option<&str>) -> ! {
+    if !std::io::stdout().is_terminal() {
+        eprintln!("The browser requires the STDOUT to be a terminal.");
+        process::exit(1);
+    }
+
+    let format = match from.map(Format::from_name) {
+        Some(Ok(format)) => format,
+        Some(Err(e)) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+        None => Format::from_path(Path::new(file)).unwrap_or(Format::Ason),
+    };
+    let document = std::fs::read(file)
+        .and_then(decompress)
+        .map_err(|e| Error::Message(format!("Fail to read the file: \"{}\".\n{}", file, e)))
+        .and_then(|data| read_document(&data, format))
+        .and_then(browse);
+    if let Err(e) = document {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    process::exit(0)
+}
+
+fn run_fmt(options: &PrintOptions, check: bool, write: bool, files: &[String]) -> ! {
+    let sources: Vec<Option<&String>> = if files.is_empty() {
+        vec![None]
+    } else {
+        files.iter().map(Some).collect()
+    };
+
+    let mut failed = false;
+    for source in sources {
+        let name = source.map_or("<stdin>", |f| f.as_str());
+        let text = match source {
+            Some(f) => std::fs::read_to_string(f),
+            None => {
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text).map(|_| text)
+            }
+        };
+        let text = match text {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("Fail to read the input file: \"{}\".", name);
+                eprintln!("{}", e);
+                failed = true;
+                continue;
+            }
+        };
+
+        let formatted = match format_document(&text, options) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("{}: {}", name, e);
+                failed = true;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != text {
+                println!("{}", name);
+                failed = true;
+            }
+        } else if let (true, Some(f)) = (write, source) {
+            if formatted != text {
+                if let Err(e) = std::fs::write(f, formatted) {
+                    eprintln!("Fail to write to the file: \"{}\".", f);
+                    eprintln!("{}", e);
+                    failed = true;
+                }
+            }
+        } else {
+            print!("{}", formatted);
+        }
+    }
+    process::exit(if failed { 1 } else { 0 })
+}
+
+fn run_roundtrip(file: &str) -> ! {
+    let text = match std::fs::read_to_string(file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Fail to read the specified input file: \"{}\".", file);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let losses = match roundtrip::check_text(&text) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    for loss in &losses {
+        println!("{}", loss);
+    }
+
+    if losses.iter().any(|l| l.is_semantic()) {
+        process::exit(1);
+    } else if !losses.is_empty() {
+        process::exit(2);
+    } else {
+        println!("No information is lost in the round trip.");
+        process::exit(0);
+    }
+}
+
+fn run_codegen(lang: &str, name: &str, files: &[String]) -> ! {
+    if lang != "rust" {
+        eprintln!(
+            "Language \"{}\" is not supported, only \"rust\" is available.",
+            lang
+        );
+        process::exit(1);
+    }
+
+    let mut documents = vec![];
+    for file in files {
+        let text = match std::fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Fail to read the specified input file: \"{}\".", file);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        match parse_input(&text, None, &ParseOptions::unlimited()) {
+            Ok(v) => documents.push(v),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    print!("{}", codegen::generate_rust(&documents, name));
+    process::exit(0)
+}
+
+fn run_schema_check(types_file: &str, type_name: Option<&str>, files: &[String]) -> ! {
+    let source = match std::fs::read_to_string(types_file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Fail to read the specified types file: \"{}\".", types_file);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let types = match rust_types::TypeSet::parse(&source) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("Fail to parse the types file: \"{}\".", types_file);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut matched = true;
+    for file in files {
+        let text = match std::fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Fail to read the specified input file: \"{}\".", file);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        let value = match parse_input(&text, None, &ParseOptions::unlimited()) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        let mismatches = match types.check(type_name, &value) {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        for mismatch in &mismatches {
+            println!("{}: {}", file, mismatch);
+        }
+        matched &= mismatches.is_empty();
+    }
+
+    process::exit(if matched { 0 } else { 1 })
+}
+
+fn run_doctor(files: &[String]) -> ! {
+    println!("aq {}", env!("CARGO_PKG_VERSION"));
+
+    let mut clean = true;
+    let mut failed = false;
+
+    for finding in doctor::diagnose_environment() {
+        println!("environment: {}", finding);
+        clean = false;
+    }
+
+    for file in files {
+        let bytes = match std::fs::read(file) {
+            Ok(b) => b,
+            Err(e) => {
+                eprintln!("Fail to read the specified input file: \"{}\".", file);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        for finding in doctor::diagnose_file(&bytes) {
+            println!("{}: {}", file, finding);
+            clean = false;
+            failed |= finding.severity == doctor::Severity::Error;
+        }
+    }
+
+    if clean {
+        println!("No issues found.");
+    }
+    process::exit(if failed { 1 } else { 0 })
+}
+
+fn run_check(from: Option<&str>, report: Option<&str>, files: &[String]) -> ! {
+    let from = match from.map(Format::from_name).transpose() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let report_format = match report.map(Format::from_name).transpose() {
+        Ok(None) => None,
+        Ok(Some(format @ (Format::Ason | Format::Json))) => Some(format),
+        Ok(Some(format)) => {
+            eprintln!(
+                "The report format \"{}\" is not supported, the available ones are: ason and json.",
+                format.name()
+            );
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut unreadable = false;
+    let mut results = vec![];
+    for file in files {
+        let problems = match std::fs::read(file) {
+            Ok(data) => {
+                let format = from
+                    .or_else(|| Format::from_path(Path::new(file)))
+                    .unwrap_or(Format::Ason);
+                check_document(data, format)
+            }
+            Err(e) => {
+                unreadable = true;
+                vec![check::Problem {
+                    line: None,
+                    message: format!("Fail to read the file: {}", e),
+                }]
+            }
+        };
+        for problem in &problems {
+            eprintln!("{}: {}", file, problem);
+        }
+        results.push((file.clone(), problems));
+    }
+
+    if let Some(format) = report_format {
+        let summary = check_report(&results);
+        let text = match format {
+            Format::Json => format!("{}\n", to_tagged_json(&summary, Some("    "))),
+            _ => print_result(&summary, &PrintOptions::pretty("    ")),
+        };
+        print!("{}", text);
+    }
+
+    let invalid = results.iter().any(|(_, problems)| !problems.is_empty());
+    process::exit(match (unreadable, invalid) {
+        (true, _) => 2,
+        (false, true) => 1,
+        (false, false) => 0,
+    })
+}
+
+fn run_validate(schema_file: &str, report: Option<&str>, files: &[String]) -> ! {
+    let report_format = match report.map(Format::from_name).transpose() {
+        Ok(None) => None,
+        Ok(Some(format @ (Format::Ason | Format::Json))) => Some(format),
+        Ok(Some(format)) => {
+            eprintln!(
+                "The report format \"{}\" is not supported, the available ones are: ason and json.",
+                format.name()
+            );
+            process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let schema = std::fs::read_to_string(schema_file)
+        .map_err(|e| {
+            Error::Message(format!(
+                "Fail to read the specified schema file: \"{}\".\n{}",
+                schema_file, e
+            ))
+        })
+        .and_then(|text| parse_input(&text, None, &ParseOptions::unlimited()))
+        .and_then(|value| Schema::from_value(&value));
+    let schema = match schema {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut unreadable = false;
+    let mut results = vec![];
+    for file in files {
+        let value = std::fs::read_to_string(file)
+            .map_err(|e| Error::Message(format!("Fail to read the file: {}", e)))
+            .and_then(|text| parse_input(&text, None, &ParseOptions::unlimited()));
+        let violations = match value {
+            Ok(value) => schema.validate(&value),
+            Err(e) => {
+                unreadable = true;
+                eprintln!("{}: {}", file, e);
+                continue;
+            }
+        };
+
+        // the violations are listed by the report if it is written.
+        for violation in &violations {
+            if report_format.is_some() {
+                eprintln!("{}: {}", file, violation);
+            } else {
+                println!("{}: {}", file, violation);
+            }
+        }
+        results.push((file.clone(), violations));
+    }
+
+    if let Some(format) = report_format {
+        let summary = validation_report(&results);
+        let text = match format {
+            Format::Json => format!("{}\n", to_tagged_json(&summary, Some("    "))),
+            _ => print_result(&summary, &PrintOptions::pretty("    ")),
+        };
+        print!("{}", text);
+    }
+
+    let invalid = results.iter().any(|(_, violations)| !violations.is_empty());
+    process::exit(match (unreadable, invalid) {
+        (true, _) => 2,
+        (false, true) => 1,
+        (false, false) => 0,
+    })
+}
+
+fn run_diff(from: Option<&str>, output_format: &str, old_file: &str, new_file: &str) -> ! {
+    if !matches!(output_format, "text" | "patch") {
+        eprintln!(
+            "The diff format \"{}\" is not supported, the available ones are: text and patch.",
+            output_format
+        );
+        process::exit(2);
+    }
+    let from = match from.map(Format::from_name).transpose() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    let read = |file: &str| {
+        let format = from
+            .or_else(|| Format::from_path(Path::new(file)))
+            .unwrap_or(Format::Ason);
+        let document = std::fs::read(file)
+            .map_err(|e| Error::Message(format!("Fail to read the file: {}", e)))
+            .and_then(|data| read_document(&data, format));
+        match document {
+            Ok(value) => value,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                process::exit(2);
+            }
+        }
+    };
+    let old = read(old_file);
+    let new = read(new_file);
+
+    let changes = diff_values(&old, &new);
+    if output_format == "patch" {
+        print!(
+            "{}",
+            print_result(&patch_document(&changes), &PrintOptions::pretty("    "))
+        );
+    } else {
+        for change in &changes {
+            println!("{}", change);
+        }
+    }
+    process::exit(if changes.is_empty() { 0 } else { 1 })
+}
+
+fn run_patch(from: Option<&str>, file: &str, patch_file: &str, output_file: Option<&str>) -> ! {
+    let from = match from.map(Format::from_name).transpose() {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    let format = from
+        .or_else(|| Format::from_path(Path::new(file)))
+        .unwrap_or(Format::Ason);
+    let patch_format = Format::from_path(Path::new(patch_file)).unwrap_or(Format::Ason);
+
+    let read = |file: &str, format: Format| {
+        std::fs::read(file)
+            .map_err(|e| {
+                Error::Message(format!(
+                    "Fail to read the specified input file: \"{}\".\n{}",
+                    file, e
+                ))
+            })
+            .and_then(|data| read_document(&data, format))
+    };
+    let patched = read(file, format).and_then(|document| {
+        let patch = read(patch_file, patch_format)?;
+        diff::apply_patch(document, &patch)
+    });
+    let bytes = match patched.and_then(|value| write_document(&value, format)) {
+        Ok(b) => b,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let outcome = match output_file {
+        Some(f) => std::fs::write(f, &bytes),
+        None => std::io::stdout().write_all(&bytes),
+    };
+    if let Err(e) = outcome {
+        eprintln!("Fail to write the patched document: {}", e);
+        process::exit(1);
+    }
+    process::exit(0)
+}
+
+fn run_migrate_query(check: bool, files: &[String]) -> ! {
+    let mut outdated = false;
+    for file in files {
+        let source = match std::fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Fail to read the specified query file: \"{}\".", file);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        let (migrated, changes) = match migrate::migrate_query(&source) {
+            Ok(m) => m,
+            Err(e) => {
+                eprintln!("Fail to parse the query file: \"{}\".", file);
+                eprintln!("{}", e.with_source(&source));
+                process::exit(1);
+            }
+        };
+
+        for change in &changes {
+            println!("{}: {}", file, change);
+        }
+        if changes.is_empty() {
+            continue;
+        }
+
+        outdated = true;
+        if !check {
+            if let Err(e) = write_in_place(Path::new(file), migrated.as_bytes(), "") {
+                eprintln!("Fail to rewrite the query file: \"{}\".", file);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+    process::exit(if check && outdated { 1 } else { 0 })
+}
+
+fn run_lint(variables: &[String], library_path: Vec<PathBuf>, files: &[String]) -> ! {
+    let mut warned = false;
+    for file in files {
+        let source = match std::fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Fail to read the specified query file: \"{}\".", file);
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        };
+
+        // the modules are resolved against the directory of the file.
+        let base_dir = Path::new(file)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let loader = ModuleLoader::new(library_path.clone()).with_base_dir(base_dir);
+        match lint::lint_query(&source, &loader, variables) {
+            Ok(warnings) => {
+                for warning in &warnings {
+                    println!("{}: {}", file, warning);
+                }
+                warned |= !warnings.is_empty();
+            }
+            Err(e) => {
+                eprintln!("Fail to parse the query file: \"{}\".", file);
+                eprintln!("{}", e.with_source(&source));
+                warned = true;
+            }
+        }
+    }
+    process::exit(if warned { 1 } else { 0 })
+}
+
+fn run_test(library_path: Vec<PathBuf>, files: &[String]) -> ! {
+    let mut passed = 0;
+    let mut failed = 0;
+    for file in files {
+        let cases = match std::fs::read_to_string(file) {
+            Ok(source) => query_test::parse_test_cases(&source),
+            Err(e) => Err(Error::Message(format!(
+                "Fail to read the specified test file: \"{}\".\n{}",
+                file, e
+            ))),
+        };
+        let cases = match cases {
+            Ok(cases) => cases,
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(2);
+            }
+        };
+
+        // the modules are resolved against the directory of the file.
+        let base_dir = Path::new(file)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .to_path_buf();
+        let loader = ModuleLoader::new(library_path.clone()).with_base_dir(base_dir);
+        for case in &cases {
+            match query_test::run_test_case(case, &loader) {
+                Ok(()) => passed += 1,
+                Err(failure) => {
+                    failed += 1;
+                    print!("FAIL {}: {}", file, failure);
+                }
+            }
+        }
+    }
+
+    println!("{} passed, {} failed.", passed, failed);
+    process::exit(if failed > 0 { 1 } else { 0 })
+}
+
+fn run_lsp(data: Option<&str>, library_path: Vec<PathBuf>) -> ! {
+    let mut server = lsp::LanguageServer::new(library_path);
+    if let Some(file) = data {
+        if let Err(e) = server.load_data(file) {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    match lsp::serve(server) {
+        Ok(status) => process::exit(status),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn run_embed(file: &str, output_file: Option<&str>) -> ! {
+    let text = match std::fs::read_to_string(file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Fail to read the specified input file: \"{}\".", file);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let base_dir = Path::new(file).parent().unwrap_or(Path::new(""));
+    let value = match parse_input(&text, None, &ParseOptions::unlimited())
+        .and_then(|v| embed::embed_files(v, base_dir))
+    {
+        Ok((v, _)) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let text = print_result(&value, &PrintOptions::pretty("    "));
+    write_output(output_file, &OutputData::Text(text), false);
+    process::exit(0);
+}
+
+fn run_extract(file: &str, min_size: usize, dir: &str, output_file: Option<&str>) -> ! {
+    let text = match std::fs::read_to_string(file) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Fail to read the specified input file: \"{}\".", file);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let value = match parse_input(&text, None, &ParseOptions::unlimited()) {
+        Ok(v) => v,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    // the placeholders are resolved against the directory of
+    // the document by `aq embed`, so are the extracted files.
+    let (value, files) = embed::extract_files(value, min_size, dir);
+    let target_dir = Path::new(file).parent().unwrap_or(Path::new("")).join(dir);
+    if !files.is_empty() {
+        if let Err(e) = std::fs::create_dir_all(&target_dir) {
+            eprintln!(
+                "Fail to create the directory: \"{}\".",
+                target_dir.display()
+            );
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+    for extracted in &files {
+        let path = target_dir.join(&extracted.name);
+        if let Err(e) = std::fs::write(&path, &extracted.content) {
+            eprintln!("Fail to write the extracted file: \"{}\".", path.display());
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+
+    let text = print_result(&value, &PrintOptions::pretty("    "));
+    write_output(output_file, &OutputData::Text(text), false);
+    process::exit(0);
+}
+
+fn run_grep(pattern: &str, ignore_case: bool, keys: bool, files: &[String]) -> ! {
+    let regex = match regex::Regex::new(pattern, ignore_case) {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    };
+
+    // the documents and their names, the names are printed
+    // before the paths when there are multiple files.
+    let mut documents = vec![];
+    if files.is_empty() {
+        let mut text = String::new();
+        if let Err(e) = std::io::stdin().read_to_string(&mut text) {
+            eprintln!("Fail to read the input text from STDIN.");
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+        documents.push((None, text.into_bytes(), convert::Format::Ason));
+    } else {
+        for file in files {
+            match std::fs::read(file) {
+                Ok(bytes) => {
+                    let format = convert::Format::from_path(std::path::Path::new(file))
+                        .unwrap_or(convert::Format::Ason);
+                    documents.push((Some(file), bytes, format));
+                }
+                Err(e) => {
+                    eprintln!("Fail to read the specified input file: \"{}\".", file);
+                    eprintln!("{}", e);
+                    process::exit(2);
+                }
+            }
+        }
+    }
+
+    let mut matched = false;
+    for (name, bytes, format) in &documents {
+        let value = match convert::read_document(bytes, *format) {
+            Ok(v) => v,
+            Err(e) => {
+                eprintln!("{}: {}", name.map_or("STDIN", |n| n.as_str()), e);
+                process::exit(2);
+            }
+        };
+
+        for m in grep::grep_value(&value, &regex, keys) {
+            matched = true;
+            match name {
+                Some(name) if documents.len() > 1 => println!("{}:{}", name, m),
+                _ => println!("{}", m),
+            }
+        }
+    }
+
+    process::exit(if matched { 0 } else { 1 })
+}
+
+fn run_convert(from: Option<&str>, to: &str, source: &str, destination: &str) -> ! {
+    let parse_format = |name: &str| match convert::Format::from_name(name) {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let from = from.map(parse_format);
+    let to = parse_format(to);
+
+    let tasks = match convert::plan_conversion(
+        std::path::Path::new(source),
+        std::path::Path::new(destination),
+        from,
+        to,
+    ) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+
+    let mut failed = 0;
+    for task in &tasks {
+        match convert::run_task(task, to) {
+            Ok(_) => println!(
+                "{} -> {}",
+                task.source.display(),
+                task.destination.display()
+            ),
+            Err(e) => {
+                eprintln!("{}: {}", task.source.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "{} file(s) converted, {} failed.",
+        tasks.len() - failed,
+        failed
+    );
+    process::exit(if failed > 0 { 1 } else { 0 })
 }
@@ -0,0 +1,99 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The memory-mapped input files, i.e. `aq --mmap QUERY huge.ason`.
+//
+// The file is mapped into the memory and parsed from the mapped bytes,
+// instead of being copied to a buffer first, so the pages are loaded by
+// the operating system on demand and they are shared with the page cache.
+// The files of `MMAP_THRESHOLD` bytes or larger are mapped automatically
+// (unless `--no-mmap` is specified).
+//
+// The compressed files and the byte ranges (`--offset` and `--length`)
+// are read as usual. The file must not be truncated by the other
+// processes while it is being parsed.
+
+use std::{fs::File, io, ops::Deref};
+
+use memmap2::Mmap;
+
+use crate::compress::detect;
+
+/// The size from which the input files are mapped automatically.
+pub const MMAP_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// The content of an input, either read into a buffer or mapped.
+pub enum InputData {
+    Buffer(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for InputData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            InputData::Buffer(buf) => buf,
+            InputData::Mapped(map) => map,
+        }
+    }
+}
+
+impl From<Vec<u8>> for InputData {
+    fn from(buf: Vec<u8>) -> Self {
+        InputData::Buffer(buf)
+    }
+}
+
+/// Map the file if `forced` or the file is large enough, `None` if
+/// the file should be read instead, e.g. it is empty or compressed.
+pub fn map_input(file: &File, forced: bool) -> io::Result<Option<Mmap>> {
+    let size = file.metadata()?.len();
+    if size == 0 || (!forced && size < MMAP_THRESHOLD) {
+        return Ok(None);
+    }
+
+    // the mapping is only read, and the file is not expected to be
+    // modified while it is mapped (see the comment of the module).
+    let map = unsafe { Mmap::map(file)? };
+    if detect(&mut &map[..])?.is_some() {
+        return Ok(None);
+    }
+    Ok(Some(map))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use pretty_assertions::assert_eq;
+
+    use super::{map_input, InputData};
+
+    #[test]
+    fn test_map_input() {
+        let dir = std::env::temp_dir().join(format!("aq-mapped-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, content: &[u8]| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            File::open(path).unwrap()
+        };
+
+        let text = file("a.ason", b"{a: 1}");
+        let map = map_input(&text, true).unwrap().unwrap();
+        assert_eq!(&InputData::Mapped(map)[..], b"{a: 1}");
+
+        // the small, empty and compressed files are read instead
+        assert!(map_input(&text, false).unwrap().is_none());
+        assert!(map_input(&file("empty.ason", b""), true).unwrap().is_none());
+        let compressed = file("a.ason.gz", b"\x1f\x8b\x08\x00");
+        assert!(map_input(&compressed, true).unwrap().is_none());
+
+        assert_eq!(&InputData::from(b"{b: 2}".to_vec())[..], b"{b: 2}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The cache of the results of `memo(f)`, e.g.
+//
+// ```
+// def fib: if . < 2 then . else (. - 1 | memo(fib)) + (. - 2 | memo(fib)) end; 80 | fib
+// ```
+//
+// The entries are keyed by the expression `f`, the input and, if `f`
+// captures the variables or the filter arguments of its scope, the
+// environment of `f`. The inputs are compared structurally, but unlike
+// `==`, the types of the numbers and the order of the keys matter,
+// e.g. `1_u8` and `1` have their own entries, since `f` may tell
+// them apart (e.g. `tostring`).
+//
+// The cache holds `MEMO_CAPACITY` entries at most, the least
+// recently used entry is evicted when a new one is added.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::value::{Map, Number, Value, VariantValue};
+
+/// The maximum number of the entries of the cache.
+pub const MEMO_CAPACITY: usize = 4096;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub(crate) struct MemoKey {
+    // the address of the expression `f`
+    expression: usize,
+
+    // the id of the environment, `None` if `f` captures nothing
+    environment: Option<usize>,
+
+    input: MemoInput,
+}
+
+impl MemoKey {
+    pub(crate) fn new(expression: usize, environment: Option<usize>, input: Value) -> Self {
+        Self {
+            expression,
+            environment,
+            input: MemoInput(input),
+        }
+    }
+}
+
+// the input compared and hashed structurally, see the module.
+#[derive(Debug, Clone)]
+struct MemoInput(Value);
+
+impl PartialEq for MemoInput {
+    fn eq(&self, other: &Self) -> bool {
+        identical(&self.0, &other.0)
+    }
+}
+
+impl Eq for MemoInput {}
+
+impl Hash for MemoInput {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_value(&self.0, state);
+    }
+}
+
+fn identical(a: &Value, b: &Value) -> bool {
+    let same_items = |xs: &[Value], ys: &[Value]| {
+        xs.len() == ys.len() && xs.iter().zip(ys).all(|(x, y)| identical(x, y))
+    };
+    let same_maps = |xs: &Map, ys: &Map| {
+        xs.len() == ys.len()
+            && xs
+                .iter()
+                .zip(ys.iter())
+                .all(|((kx, x), (ky, y))| kx == ky && identical(x, y))
+    };
+
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => number_bits(x) == number_bits(y),
+        (Value::Boolean(x), Value::Boolean(y)) => x == y,
+        (Value::Char(x), Value::Char(y)) => x == y,
+        (Value::String(x), Value::String(y)) => x == y,
+        (Value::DateTime(x), Value::DateTime(y)) => x == y && x.offset() == y.offset(),
+        (Value::ByteData(x), Value::ByteData(y)) => x == y,
+        (Value::List(x), Value::List(y)) | (Value::Tuple(x), Value::Tuple(y)) => same_items(x, y),
+        (Value::Object(x), Value::Object(y)) => same_maps(x, y),
+        (Value::Variant(x), Value::Variant(y)) => {
+            x.type_name == y.type_name
+                && x.member_name == y.member_name
+                && match (&x.value, &y.value) {
+                    (VariantValue::Empty, VariantValue::Empty) => true,
+                    (VariantValue::Value(x), VariantValue::Value(y)) => identical(x, y),
+                    (VariantValue::Tuple(x), VariantValue::Tuple(y)) => same_items(x, y),
+                    (VariantValue::Object(x), VariantValue::Object(y)) => same_maps(x, y),
+                    _ => false,
+                }
+        }
+        _ => false,
+    }
+}
+
+// the type and the bits of the number, e.g. `0.0` and `-0.0` differ.
+fn number_bits(n: &Number) -> (&'static str, u128) {
+    let bits = match n {
+        Number::F32(f) => f.to_bits() as u128,
+        Number::F64(f) => f.to_bits() as u128,
+        _ => n.as_i128().unwrap_or_default() as u128,
+    };
+    (n.type_suffix(), bits)
+}
+
+fn hash_value<H: Hasher>(value: &Value, state: &mut H) {
+    std::mem::discriminant(value).hash(state);
+    match value {
+        Value::Number(n) => number_bits(n).hash(state),
+        Value::Boolean(b) => b.hash(state),
+        Value::Char(c) => c.hash(state),
+        Value::String(s) => s.hash(state),
+        Value::DateTime(d) => {
+            d.timestamp().hash(state);
+            d.timestamp_subsec_nanos().hash(state);
+        }
+        Value::ByteData(bytes) => bytes.hash(state),
+        Value::List(items) | Value::Tuple(items) => hash_items(items, state),
+        Value::Object(map) => hash_map(map, state),
+        Value::Variant(v) => {
+            v.type_name.hash(state);
+            v.member_name.hash(state);
+            match &v.value {
+                VariantValue::Empty => 0u8.hash(state),
+                VariantValue::Value(inner) => hash_value(inner, state),
+                VariantValue::Tuple(items) => hash_items(items, state),
+                VariantValue::Object(map) => hash_map(map, state),
+            }
+        }
+    }
+}
+
+fn hash_items<H: Hasher>(items: &[Value], state: &mut H) {
+    items.len().hash(state);
+    for item in items {
+        hash_value(item, state);
+    }
+}
+
+fn hash_map<H: Hasher>(map: &Map, state: &mut H) {
+    map.len().hash(state);
+    for (key, item) in map.iter() {
+        key.hash(state);
+        hash_value(item, state);
+    }
+}
+
+/// The least recently used cache of the results, see the module.
+#[derive(Debug)]
+pub(crate) struct MemoCache {
+    capacity: usize,
+
+    // the results and the last time they are used
+    entries: HashMap<MemoKey, (u64, Vec<Value>)>,
+
+    // the keys by the last time they are used, the first one
+    // is the least recently used.
+    recency: BTreeMap<u64, MemoKey>,
+    clock: u64,
+}
+
+impl MemoCache {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    pub(crate) fn get(&mut self, key: &MemoKey) -> Option<Vec<Value>> {
+        let now = self.tick();
+        let (used, results) = self.entries.get_mut(key)?;
+        let key = self.recency.remove(used).unwrap();
+        self.recency.insert(now, key);
+        *used = now;
+        Some(results.clone())
+    }
+
+    pub(crate) fn insert(&mut self, key: MemoKey, results: Vec<Value>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let now = self.tick();
+        if let Some((used, _)) = self.entries.get(&key) {
+            self.recency.remove(used);
+        } else if self.entries.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.recency.insert(now, key.clone());
+        self.entries.insert(key, (now, results));
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+impl Default for MemoCache {
+    fn default() -> Self {
+        Self::with_capacity(MEMO_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::value::{Number, Value};
+
+    use super::{MemoCache, MemoKey};
+
+    fn key(input: Value) -> MemoKey {
+        MemoKey::new(1, None, input)
+    }
+
+    #[test]
+    fn test_memo_key() {
+        assert_eq!(key(Value::from(1)), key(Value::from(1)));
+        assert_ne!(key(Value::from(1)), key(Value::Number(Number::U8(1))));
+        assert_ne!(key(Value::from(0.0)), key(Value::from(-0.0)));
+        assert_ne!(
+            key(Value::from(1)),
+            MemoKey::new(1, Some(7), Value::from(1))
+        );
+
+        let object = |pairs: &[(&str, i32)]| {
+            Value::Object(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), Value::from(*v)))
+                    .collect(),
+            )
+        };
+        assert_eq!(
+            key(object(&[("a", 1), ("b", 2)])),
+            key(object(&[("a", 1), ("b", 2)]))
+        );
+        assert_ne!(
+            key(object(&[("a", 1), ("b", 2)])),
+            key(object(&[("b", 2), ("a", 1)]))
+        );
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut cache = MemoCache::with_capacity(2);
+        cache.insert(key(Value::from(1)), vec![Value::from(10)]);
+        cache.insert(key(Value::from(2)), vec![Value::from(20)]);
+
+        // `1` is used, so `2` is evicted
+        assert_eq!(cache.get(&key(Value::from(1))), Some(vec![Value::from(10)]));
+        cache.insert(key(Value::from(3)), vec![Value::from(30)]);
+        assert_eq!(cache.get(&key(Value::from(2))), None);
+        assert_eq!(cache.get(&key(Value::from(1))), Some(vec![Value::from(10)]));
+        assert_eq!(cache.get(&key(Value::from(3))), Some(vec![Value::from(30)]));
+
+        // replacing an entry does not evict the others
+        cache.insert(key(Value::from(3)), vec![]);
+        assert_eq!(cache.get(&key(Value::from(3))), Some(vec![]));
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Merging the inputs which are sorted by the same key, i.e.
+// `aq --merge-sorted-by EXPR`, e.g. combining the rotated log files
+// which are sorted by the timestamp.
+//
+// It is a k-way merge, only the next element of each input is held
+// in a binary heap, so the elements are produced one by one with the
+// memory proportional to the number of the inputs (the inputs are read
+// lazily, e.g. the items of the incremental parse). The elements with
+// the equal keys are produced in the order of the inputs.
+
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+use crate::{error::Error, query::Query, value::Value};
+
+// the next element of an input, the heap is a max-heap,
+// so the order is reversed.
+struct Head {
+    key: Vec<Value>,
+    source: usize,
+    value: Value,
+}
+
+impl PartialEq for Head {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head {}
+
+impl PartialOrd for Head {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Head {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .key
+            .cmp(&self.key)
+            .then_with(|| other.source.cmp(&self.source))
+    }
+}
+
+/// The iterator of the merged elements, an error is produced if the
+/// key expression fails, or an input is not sorted by the key.
+pub struct SortedMerge<I> {
+    key: Query,
+    sources: Vec<I>,
+
+    // the number of the elements taken from each input.
+    taken: Vec<usize>,
+    heap: BinaryHeap<Head>,
+    started: bool,
+}
+
+/// Merge the sorted inputs, the key of an element is the list of
+/// the results of the `key` query, just like `sort_by(f)`.
+pub fn merge_sorted<I>(sources: Vec<I>, key: Query) -> SortedMerge<I>
+where
+    I: Iterator<Item = Result<Value, Error>>,
+{
+    SortedMerge {
+        key,
+        taken: vec![0; sources.len()],
+        sources,
+        heap: BinaryHeap::new(),
+        started: false,
+    }
+}
+
+impl<I> SortedMerge<I>
+where
+    I: Iterator<Item = Result<Value, Error>>,
+{
+    // take the next element of the input.
+    fn pull(&mut self, source: usize) -> Result<Option<Head>, Error> {
+        let Some(value) = self.sources[source].next().transpose()? else {
+            return Ok(None);
+        };
+
+        self.taken[source] += 1;
+        let key = self.key.run_value(value.clone())?;
+        Ok(Some(Head { key, source, value }))
+    }
+
+    fn next_element(&mut self) -> Result<Option<Value>, Error> {
+        if !self.started {
+            self.started = true;
+            for source in 0..self.sources.len() {
+                if let Some(head) = self.pull(source)? {
+                    self.heap.push(head);
+                }
+            }
+        }
+
+        let Some(head) = self.heap.pop() else {
+            return Ok(None);
+        };
+
+        if let Some(next) = self.pull(head.source)? {
+            if next.key < head.key {
+                return Err(Error::Message(format!(
+                    "The input {} is not sorted by the key, the element {} is out of order.",
+                    head.source + 1,
+                    self.taken[head.source] - 1
+                )));
+            }
+            self.heap.push(next);
+        }
+        Ok(Some(head.value))
+    }
+}
+
+impl<I> Iterator for SortedMerge<I>
+where
+    I: Iterator<Item = Result<Value, Error>>,
+{
+    type Item = Result<Value, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_element().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{error::Error, incremental::incremental_documents, query::Query};
+
+    use super::merge_sorted;
+
+    // the elements of the documents are the items of the incremental parse.
+    fn merge(documents: &[&'static str], key: &str) -> Result<Vec<String>, Error> {
+        let key = Query::compile(key).unwrap();
+        let sources = documents
+            .iter()
+            .map(|text| incremental_documents(text.as_bytes(), None, true))
+            .collect();
+        merge_sorted(sources, key)
+            .map(|r| r.map(|v| v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        assert_eq!(
+            merge(
+                &[
+                    "[{t: 1, s: \"a\"}, {t: 4, s: \"a\"}, {t: 4, s: \"a2\"}]",
+                    "[{t: 2, s: \"b\"}, {t: 4, s: \"b\"}]",
+                    "{t: 3, s: \"c\"}",
+                    "[]"
+                ],
+                ".t"
+            )
+            .unwrap(),
+            [
+                "{t:1,s:\"a\"}",
+                "{t:2,s:\"b\"}",
+                "{t:3,s:\"c\"}",
+                "{t:4,s:\"a\"}",
+                "{t:4,s:\"a2\"}",
+                "{t:4,s:\"b\"}"
+            ]
+        );
+
+        assert_eq!(merge(&[], ".").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_unsorted_input() {
+        assert_eq!(
+            merge(&["[1, 3]", "[2, 5, 4]"], ".")
+                .unwrap_err()
+                .to_string(),
+            "The input 2 is not sorted by the key, the element 2 is out of order."
+        );
+
+        // the errors of the inputs are produced when the elements are taken
+        assert!(merge(&["[1, 3]", "[2, {]"], ".")
+            .unwrap_err()
+            .to_string()
+            .contains("at line 1"));
+    }
+}
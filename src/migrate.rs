@@ -0,0 +1,183 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Upgrading the query files to the current language version,
+// i.e. `aq migrate-query FILE...`.
+//
+// The calls of the deprecated builtins are replaced by their modern
+// equivalents (the functions defined by the file itself are kept),
+// and the `#aq-version` pragma is updated to the current version.
+// The source is rewritten by the tokens, so the layout and comments
+// are kept.
+
+use crate::{
+    error::Error,
+    lexer::{lex_from_str, Token},
+    pragma::language_version,
+};
+
+struct Migration {
+    deprecated: &'static str,
+    replacement: &'static str,
+}
+
+// the deprecated builtins which take no arguments.
+const MIGRATIONS: [Migration; 2] = [
+    Migration {
+        deprecated: "recurse_down",
+        replacement: "recurse",
+    },
+    Migration {
+        deprecated: "leaf_paths",
+        replacement: "paths(scalars)",
+    },
+];
+
+/// Migrate the source, the migrated source and the descriptions
+/// of the changes are returned.
+pub fn migrate_query(source: &str) -> Result<(String, Vec<String>), Error> {
+    let tokens = lex_from_str(source)?;
+    let chars: Vec<char> = source.chars().collect();
+
+    // the functions defined by the file itself.
+    let defined: Vec<&str> = tokens
+        .windows(2)
+        .filter_map(|pair| match (&pair[0].token, &pair[1].token) {
+            (Token::Identifier(keyword), Token::Identifier(name)) if keyword == "def" => {
+                Some(name.as_str())
+            }
+            _ => None,
+        })
+        .collect();
+
+    // the replacements of the ranges of the chars.
+    let mut edits = vec![];
+    let mut changes = vec![];
+    for (idx, token) in tokens.iter().enumerate() {
+        let Token::Identifier(name) = &token.token else {
+            continue;
+        };
+        let Some(migration) = MIGRATIONS.iter().find(|m| m.deprecated == name) else {
+            continue;
+        };
+
+        // the calls with arguments and the module functions are not the builtins
+        let next = tokens.get(idx + 1).map(|t| &t.token);
+        let previous = idx.checked_sub(1).map(|i| &tokens[i].token);
+        if defined.contains(&name.as_str())
+            || matches!(next, Some(Token::LeftParen))
+            || matches!(previous, Some(Token::ColonColon))
+        {
+            continue;
+        }
+
+        let start = token.location.index;
+        let line = 1 + chars[..start].iter().filter(|c| **c == '\n').count();
+        edits.push((
+            start,
+            token.location.length,
+            migration.replacement.to_owned(),
+        ));
+        changes.push(format!(
+            "line {}: replace the deprecated \"{}\" with \"{}\"",
+            line, migration.deprecated, migration.replacement
+        ));
+    }
+
+    let (major, minor) = language_version();
+    let pragma = format!("#aq-version \"{}.{}\"", major, minor);
+    let mut offset = 0;
+    for (idx, line) in source.split_inclusive('\n').enumerate() {
+        let length = line.chars().count();
+        let content = line.trim_end_matches(['\r', '\n']);
+        let trimmed = content.trim_start();
+        if trimmed.starts_with("#aq-version") && trimmed != pragma {
+            let indent = content.chars().count() - trimmed.chars().count();
+            edits.push((offset + indent, trimmed.chars().count(), pragma.clone()));
+            changes.push(format!(
+                "line {}: update the version to {}.{}",
+                idx + 1,
+                major,
+                minor
+            ));
+        }
+        offset += length;
+    }
+
+    edits.sort_by_key(|(start, _, _)| *start);
+    let mut migrated = String::new();
+    let mut position = 0;
+    for (start, length, replacement) in edits {
+        migrated.extend(&chars[position..start]);
+        migrated.push_str(&replacement);
+        position = start + length;
+    }
+    migrated.extend(&chars[position..]);
+
+    Ok((migrated, changes))
+}
+
+/// The replacement of a deprecated builtin (which takes no arguments).
+pub fn replacement_of(name: &str) -> Option<&'static str> {
+    MIGRATIONS
+        .iter()
+        .find(|m| m.deprecated == name)
+        .map(|m| m.replacement)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::pragma::language_version;
+
+    use super::migrate_query;
+
+    #[test]
+    fn test_migrate_query() {
+        let (major, minor) = language_version();
+        let (migrated, changes) =
+            migrate_query("#aq-version \"0.0\"\n[leaf_paths] | recurse_down # leaf_paths\n")
+                .unwrap();
+        assert_eq!(
+            migrated,
+            format!(
+                "#aq-version \"{}.{}\"\n[paths(scalars)] | recurse # leaf_paths\n",
+                major, minor
+            )
+        );
+        assert_eq!(
+            changes,
+            [
+                "line 2: replace the deprecated \"leaf_paths\" with \"paths(scalars)\"".to_owned(),
+                "line 2: replace the deprecated \"recurse_down\" with \"recurse\"".to_owned(),
+                format!("line 1: update the version to {}.{}", major, minor)
+            ]
+        );
+
+        // the up-to-date source is unchanged
+        let source = format!("#aq-version \"{}.{}\"\n.", major, minor);
+        assert_eq!(migrate_query(&source).unwrap(), (source.clone(), vec![]));
+    }
+
+    #[test]
+    fn test_keep_user_functions() {
+        // the functions defined by the file, the module functions and
+        // the calls with arguments are not the deprecated builtins
+        let (migrated, changes) = migrate_query(
+            "def recurse_down: .; recurse_down, m::leaf_paths, leaf_paths(1), \"é\" | leaf_paths",
+        )
+        .unwrap();
+        assert_eq!(
+            migrated,
+            "def recurse_down: .; recurse_down, m::leaf_paths, leaf_paths(1), \"é\" | paths(scalars)"
+        );
+        assert_eq!(
+            changes,
+            ["line 1: replace the deprecated \"leaf_paths\" with \"paths(scalars)\""]
+        );
+    }
+}
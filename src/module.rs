@@ -0,0 +1,242 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Loading the query modules, i.e. `import "lib/strings" as str;`.
+//
+// A module is a query file (`*.aql`) which contains only imports and
+// definitions, e.g.
+//
+// `def trim: ltrimstr(" ") | rtrimstr(" ");`
+//
+// and the functions are called with the alias of the module, e.g.
+// `.name | str::trim`. The path of the module is resolved against the
+// directory of the importing file first, then the library paths (`-L`),
+// the extension `.aql` can be omitted.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    ast::{FunctionDefinition, Import},
+    error::Error,
+    parser::parse_module_from_str,
+};
+
+const MODULE_EXTENSION: &str = "aql";
+
+/// A loaded module and the modules it imports.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Module {
+    pub alias: String,
+    pub path: PathBuf,
+    pub imports: Vec<Module>,
+    pub definitions: Vec<FunctionDefinition>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ModuleLoader {
+    library_paths: Vec<PathBuf>,
+
+    // the directory of the query file, the modules imported
+    // by the query are resolved against it first.
+    base_dir: PathBuf,
+}
+
+impl Default for ModuleLoader {
+    fn default() -> Self {
+        Self {
+            library_paths: vec![],
+            base_dir: PathBuf::from("."),
+        }
+    }
+}
+
+impl ModuleLoader {
+    pub fn new(library_paths: Vec<PathBuf>) -> Self {
+        Self {
+            library_paths,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_base_dir(mut self, base_dir: PathBuf) -> Self {
+        self.base_dir = base_dir;
+        self
+    }
+
+    /// Load the modules imported by a query, and the modules they import.
+    pub fn load(&self, imports: &[Import]) -> Result<Vec<Module>, Error> {
+        let mut loading = vec![];
+        self.load_imports(imports, &self.base_dir, &mut loading)
+    }
+
+    // `loading` is the chain of the module files being loaded,
+    // which is used to detect the circular imports.
+    fn load_imports(
+        &self,
+        imports: &[Import],
+        base_dir: &Path,
+        loading: &mut Vec<PathBuf>,
+    ) -> Result<Vec<Module>, Error> {
+        imports
+            .iter()
+            .map(|import| self.load_module(import, base_dir, loading))
+            .collect()
+    }
+
+    fn load_module(
+        &self,
+        import: &Import,
+        base_dir: &Path,
+        loading: &mut Vec<PathBuf>,
+    ) -> Result<Module, Error> {
+        let path = self.resolve(&import.path, base_dir)?;
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if loading.contains(&canonical) {
+            return Err(Error::Message(format!(
+                "Circular import of the module \"{}\".",
+                import.path
+            )));
+        }
+
+        let source = std::fs::read_to_string(&path).map_err(|e| {
+            Error::Message(format!(
+                "Failed to read the module \"{}\": {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let (module_imports, definitions) = parse_module_from_str(&source).map_err(|e| {
+            Error::Message(format!(
+                "Failed to parse the module \"{}\": {}",
+                path.display(),
+                e
+            ))
+        })?;
+
+        let module_dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        loading.push(canonical);
+        let imports = self.load_imports(&module_imports, &module_dir, loading)?;
+        loading.pop();
+
+        Ok(Module {
+            alias: import.alias.clone(),
+            path,
+            imports,
+            definitions,
+        })
+    }
+
+    fn resolve(&self, module_path: &str, base_dir: &Path) -> Result<PathBuf, Error> {
+        let mut relative = PathBuf::from(module_path);
+        if relative.extension().is_none() {
+            relative.set_extension(MODULE_EXTENSION);
+        }
+
+        if relative.is_absolute() {
+            return if relative.is_file() {
+                Ok(relative)
+            } else {
+                Err(Error::Message(format!(
+                    "Cannot find the module \"{}\".",
+                    module_path
+                )))
+            };
+        }
+
+        let dirs: Vec<&Path> = std::iter::once(base_dir)
+            .chain(self.library_paths.iter().map(PathBuf::as_path))
+            .collect();
+
+        dirs.iter()
+            .map(|dir| dir.join(&relative))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                let searched: Vec<String> =
+                    dirs.iter().map(|dir| dir.display().to_string()).collect();
+                Error::Message(format!(
+                    "Cannot find the module \"{}\", searched in: {}.",
+                    module_path,
+                    searched.join(", ")
+                ))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{query::Query, value::Value};
+
+    use super::ModuleLoader;
+
+    // the modules in a temporary directory, `lib` is the library path
+    // and the directory itself is the base directory.
+    fn loader(test: &str, files: &[(&str, &str)]) -> ModuleLoader {
+        let dir = std::env::temp_dir().join(format!("aq-module-{}-{}", test, std::process::id()));
+        for (name, content) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        ModuleLoader::new(vec![dir.join("lib")]).with_base_dir(dir)
+    }
+
+    fn compile_error(query: &str, loader: &ModuleLoader) -> String {
+        match Query::compile_with_loader(query, loader) {
+            Ok(_) => panic!("the query is compiled: {}", query),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_import_modules() {
+        let loader = loader(
+            "import",
+            &[
+                (
+                    "lib/strings.aql",
+                    r#"import "util" as u; def shout: u::up + "!"; def wrap($s): $s + . + $s;"#,
+                ),
+                ("lib/util.aql", "def up: ascii_upcase;"),
+                ("local.aql", "def one: 1;"),
+            ],
+        );
+        let query = Query::compile_with_loader(
+            r#"import "strings" as str; import "local" as l; str::shout, str::wrap("*"), l::one"#,
+            &loader,
+        )
+        .unwrap();
+        assert_eq!(
+            query.run_value(Value::from("abc")).unwrap(),
+            [Value::from("ABC!"), Value::from("*abc*"), Value::from(1)]
+        );
+
+        // the functions imported by a module are not exported
+        let query =
+            Query::compile_with_loader(r#"import "strings" as str; str::up"#, &loader).unwrap();
+        assert!(query
+            .run_value(Value::from("a"))
+            .unwrap_err()
+            .to_string()
+            .contains("str::up/0"));
+    }
+
+    #[test]
+    fn test_import_errors() {
+        let loader = loader(
+            "import-errors",
+            &[
+                ("a.aql", r#"import "b" as b; def f: 1;"#),
+                ("b.aql", r#"import "a" as a; def g: 2;"#),
+            ],
+        );
+        assert!(compile_error(r#"import "a" as a; ."#, &loader)
+            .contains("Circular import of the module \"a\""));
+        assert!(compile_error(r#"import "nope" as n; ."#, &loader)
+            .contains("Cannot find the module \"nope\", searched in:"));
+    }
+}
@@ -0,0 +1,356 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Encoding values as MessagePack, and decoding them back.
+//
+// - `Option::None` is encoded as `nil`, and `Option::Some(v)` as `v`.
+// - the numbers keep their width, e.g. `1_u16` is encoded as `uint 16`,
+//   the `i32` numbers (the default type) use the shortest encoding.
+// - chars are encoded as `str`, byte data as `bin`, tuples as `array`.
+// - date times are encoded as the timestamp extension type (-1),
+//   the time zone offset is not kept.
+// - other variants are encoded as a `map` with a single entry,
+//   the key is the name of the variant (e.g. "Color::Red"), and the value is
+//   `nil`, the inner value, the `array` of the tuple or the `map` of the object.
+//
+// The decoding is the inverse, e.g. `uint 16` is decoded as `u16`, and the
+// single entry maps with the variant names are decoded as the variants.
+// The integer keys of maps are converted to strings.
+
+use chrono::DateTime;
+
+use crate::{
+    error::Error,
+    value::{decode_variant_entry, Map, Number, Value, VariantValue},
+};
+
+pub fn encode_msgpack(value: &Value) -> Vec<u8> {
+    let mut buf = vec![];
+    write_value(&mut buf, value);
+    buf
+}
+
+fn write_value(buf: &mut Vec<u8>, value: &Value) {
+    if let Some(inner) = value.as_some() {
+        return write_value(buf, inner);
+    }
+
+    match value {
+        _ if value.is_null() => buf.push(0xc0),
+        Value::Boolean(b) => buf.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => write_number(buf, n),
+        Value::Char(c) => write_str(buf, &c.to_string()),
+        Value::String(s) => write_str(buf, s),
+        Value::DateTime(d) => {
+            // timestamp 96
+            buf.extend([0xc7, 12, 0xff]);
+            buf.extend(d.timestamp_subsec_nanos().to_be_bytes());
+            buf.extend(d.timestamp().to_be_bytes());
+        }
+        Value::ByteData(bytes) => {
+            write_length(buf, bytes.len(), [0, 0xc4, 0xc5, 0xc6], None);
+            buf.extend(bytes);
+        }
+        Value::List(items) | Value::Tuple(items) => write_array(buf, items),
+        Value::Object(map) => write_map(buf, map),
+        Value::Variant(v) => {
+            // a single entry map
+            buf.push(0x81);
+            write_str(buf, &format!("{}::{}", v.type_name, v.member_name));
+            match &v.value {
+                VariantValue::Empty => buf.push(0xc0),
+                VariantValue::Value(inner) => write_value(buf, inner),
+                VariantValue::Tuple(items) => write_array(buf, items),
+                VariantValue::Object(map) => write_map(buf, map),
+            }
+        }
+    }
+}
+
+fn write_number(buf: &mut Vec<u8>, n: &Number) {
+    match *n {
+        Number::I8(v) => {
+            buf.push(0xd0);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::I16(v) => {
+            buf.push(0xd1);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::I32(v) if (-32..=127).contains(&v) => {
+            // positive and negative fixint
+            buf.push(v as i8 as u8);
+        }
+        Number::I32(v) => {
+            buf.push(0xd2);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::I64(v) => {
+            buf.push(0xd3);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::U8(v) => buf.extend([0xcc, v]),
+        Number::U16(v) => {
+            buf.push(0xcd);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::U32(v) => {
+            buf.push(0xce);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::U64(v) => {
+            buf.push(0xcf);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::F32(v) => {
+            buf.push(0xca);
+            buf.extend(v.to_be_bytes());
+        }
+        Number::F64(v) => {
+            buf.push(0xcb);
+            buf.extend(v.to_be_bytes());
+        }
+    }
+}
+
+// write the marker and the length, the markers are for the
+// 8-bit, 16-bit and 32-bit lengths respectively, preceded by the
+// marker of the "fix" family (which holds the length in the low bits)
+// and its maximum length.
+fn write_length(buf: &mut Vec<u8>, length: usize, markers: [u8; 4], fix_max: Option<usize>) {
+    match fix_max {
+        Some(max) if length <= max => buf.push(markers[0] | length as u8),
+        _ if length <= u8::MAX as usize && markers[1] != 0 => {
+            buf.extend([markers[1], length as u8])
+        }
+        _ if length <= u16::MAX as usize => {
+            buf.push(markers[2]);
+            buf.extend((length as u16).to_be_bytes());
+        }
+        _ => {
+            buf.push(markers[3]);
+            buf.extend((length as u32).to_be_bytes());
+        }
+    }
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    write_length(buf, s.len(), [0xa0, 0xd9, 0xda, 0xdb], Some(31));
+    buf.extend(s.as_bytes());
+}
+
+fn write_array(buf: &mut Vec<u8>, items: &[Value]) {
+    write_length(buf, items.len(), [0x90, 0, 0xdc, 0xdd], Some(15));
+    for item in items {
+        write_value(buf, item);
+    }
+}
+
+fn write_map(buf: &mut Vec<u8>, map: &Map) {
+    write_length(buf, map.len(), [0x80, 0, 0xde, 0xdf], Some(15));
+    for (key, item) in map.iter() {
+        write_str(buf, key);
+        write_value(buf, item);
+    }
+}
+
+/// Decode the values of the MessagePack stream, i.e. the concatenated values.
+pub fn decode_msgpack(bytes: &[u8]) -> Result<Vec<Value>, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut values = vec![];
+    while reader.pos < bytes.len() {
+        values.push(reader.read_value()?);
+    }
+    Ok(values)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::Message(format!("{} (at byte offset {}).", message, self.pos))
+    }
+
+    fn take(&mut self, length: usize) -> Result<&[u8], Error> {
+        if self.bytes.len() - self.pos < length {
+            return Err(self.error("Unexpected end of the MessagePack data"));
+        }
+        let data = &self.bytes[self.pos..self.pos + length];
+        self.pos += length;
+        Ok(data)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn read_length(&mut self, width: usize) -> Result<usize, Error> {
+        let length = match width {
+            1 => self.take_array::<1>()?[0] as usize,
+            2 => u16::from_be_bytes(self.take_array()?) as usize,
+            _ => u32::from_be_bytes(self.take_array()?) as usize,
+        };
+        Ok(length)
+    }
+
+    fn read_value(&mut self) -> Result<Value, Error> {
+        let marker = self.take_array::<1>()?[0];
+        let value = match marker {
+            0x00..=0x7f => Value::Number(Number::I32(marker as i32)),
+            0xe0..=0xff => Value::Number(Number::I32(marker as i8 as i32)),
+            0x80..=0x8f => self.read_map((marker & 0x0f) as usize)?,
+            0x90..=0x9f => self.read_array((marker & 0x0f) as usize)?,
+            0xa0..=0xbf => self.read_str((marker & 0x1f) as usize)?,
+            0xc0 => Value::null(),
+            0xc2 => Value::Boolean(false),
+            0xc3 => Value::Boolean(true),
+            0xc4..=0xc6 => {
+                let length = self.read_length(1 << (marker - 0xc4))?;
+                Value::ByteData(self.take(length)?.to_vec())
+            }
+            0xc7..=0xc9 => {
+                let length = self.read_length(1 << (marker - 0xc7))?;
+                self.read_ext(length)?
+            }
+            0xca => Value::Number(Number::F32(f32::from_be_bytes(self.take_array()?))),
+            0xcb => Value::Number(Number::F64(f64::from_be_bytes(self.take_array()?))),
+            0xcc => Value::Number(Number::U8(self.take_array::<1>()?[0])),
+            0xcd => Value::Number(Number::U16(u16::from_be_bytes(self.take_array()?))),
+            0xce => Value::Number(Number::U32(u32::from_be_bytes(self.take_array()?))),
+            0xcf => Value::Number(Number::U64(u64::from_be_bytes(self.take_array()?))),
+            0xd0 => Value::Number(Number::I8(self.take_array::<1>()?[0] as i8)),
+            0xd1 => Value::Number(Number::I16(i16::from_be_bytes(self.take_array()?))),
+            0xd2 => Value::Number(Number::I32(i32::from_be_bytes(self.take_array()?))),
+            0xd3 => Value::Number(Number::I64(i64::from_be_bytes(self.take_array()?))),
+            0xd4..=0xd8 => self.read_ext(1 << (marker - 0xd4))?,
+            0xd9..=0xdb => {
+                let length = self.read_length(1 << (marker - 0xd9))?;
+                self.read_str(length)?
+            }
+            0xdc | 0xdd => {
+                let length = self.read_length(2 << (marker - 0xdc))?;
+                self.read_array(length)?
+            }
+            0xde | 0xdf => {
+                let length = self.read_length(2 << (marker - 0xde))?;
+                self.read_map(length)?
+            }
+            0xc1 => return Err(self.error("Invalid MessagePack marker 0xc1")),
+        };
+        Ok(value)
+    }
+
+    fn read_str(&mut self, length: usize) -> Result<Value, Error> {
+        let data = self.take(length)?.to_vec();
+        String::from_utf8(data)
+            .map(Value::String)
+            .map_err(|_| self.error("Invalid UTF-8 string of MessagePack"))
+    }
+
+    fn read_array(&mut self, length: usize) -> Result<Value, Error> {
+        (0..length)
+            .map(|_| self.read_value())
+            .collect::<Result<Vec<_>, Error>>()
+            .map(|items| Value::List(items.into()))
+    }
+
+    fn read_map(&mut self, length: usize) -> Result<Value, Error> {
+        let mut map = Map::new();
+        for _ in 0..length {
+            let key = match self.read_value()? {
+                Value::String(s) => s,
+                Value::Number(n) if !n.is_float() => n.plain_text(),
+                other => {
+                    return Err(self.error(&format!(
+                        "The map keys of MessagePack must be strings or integers, found {}",
+                        other.type_name()
+                    )))
+                }
+            };
+            let item = self.read_value()?;
+            map.insert(key, item);
+        }
+        Ok(decode_variant_entry(map))
+    }
+
+    // only the timestamp extension type (-1) is supported.
+    fn read_ext(&mut self, length: usize) -> Result<Value, Error> {
+        let ext_type = self.take_array::<1>()?[0] as i8;
+        let data = self.take(length)?;
+        let (seconds, nanos) = match (ext_type, data.len()) {
+            (-1, 4) => (u32::from_be_bytes(data.try_into().unwrap()) as i64, 0),
+            (-1, 8) => {
+                let v = u64::from_be_bytes(data.try_into().unwrap());
+                ((v & 0x3_ffff_ffff) as i64, (v >> 34) as u32)
+            }
+            (-1, 12) => (
+                i64::from_be_bytes(data[4..].try_into().unwrap()),
+                u32::from_be_bytes(data[..4].try_into().unwrap()),
+            ),
+            _ => {
+                return Err(self.error(&format!(
+                    "Unsupported MessagePack extension type {}",
+                    ext_type
+                )))
+            }
+        };
+        DateTime::from_timestamp(seconds, nanos)
+            .map(|d| Value::DateTime(d.fixed_offset()))
+            .ok_or_else(|| self.error("Invalid MessagePack timestamp"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{decode_msgpack, encode_msgpack};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_encode_msgpack() {
+        assert_eq!(
+            encode_msgpack(&value("{a: [1, -1, 300, Option::None, true]}")),
+            vec![0x81, 0xa1, b'a', 0x95, 1, 0xff, 0xd2, 0, 0, 1, 44, 0xc0, 0xc3]
+        );
+        assert_eq!(
+            encode_msgpack(&value(r#"("x", 1.5)"#)),
+            vec![0x92, 0xa1, b'x', 0xcb, 0x3f, 0xf8, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn test_decode_msgpack() {
+        let document = value(
+            r#"{a: [1, -40, 300, 70000, 5_000_000_000_i64, 1.5, "s", true, Option::None], b: 7_u16, c: -3_i8, e: Color::Red, f: Shape::Rect{w: 1}, g: Pair::P(1, 2), h: Opt::V(3), k: 2.5_f32}"#,
+        );
+
+        // the documents are concatenated
+        let mut data = encode_msgpack(&document);
+        data.extend(encode_msgpack(&value(r#""x""#)));
+        let documents = decode_msgpack(&data).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(documents[0].to_string(), document.to_string());
+        assert_eq!(documents[1], value(r#""x""#));
+
+        let bytes = Value::ByteData(vec![1, 2, 3]);
+        assert_eq!(decode_msgpack(&encode_msgpack(&bytes)).unwrap(), [bytes]);
+
+        // the array lacks an item
+        assert!(decode_msgpack(&[0x92, 0x01]).is_err());
+    }
+}
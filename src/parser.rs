@@ -0,0 +1,1040 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use crate::{
+    ast::{
+        AssignOperator, BinaryOperator, Expression, FunctionDefinition, Import, InterpolationPart,
+        Pattern, VariantPayload,
+    },
+    error::Error,
+    lexer::{lex_from_str, StringPart, Token, TokenWithLocation},
+    pragma::check_pragmas,
+    value::Value,
+};
+
+const KEYWORDS: [&str; 14] = [
+    "def", "if", "then", "elif", "else", "end", "as", "reduce", "foreach", "try", "catch", "and",
+    "or", "import",
+];
+
+/// Parse a list of function definitions, e.g. `def f: body; def g: body;`,
+/// it is used to load the prelude.
+pub fn parse_definitions_from_str(source: &str) -> Result<Vec<FunctionDefinition>, Error> {
+    let tokens = lex_from_str(source)?;
+    let mut parser = Parser::new(&tokens);
+    let mut definitions = vec![];
+    while parser.peek_keyword("def") {
+        definitions.push(parser.parse_definition()?);
+    }
+    parser.expect_end()?;
+    Ok(definitions)
+}
+
+/// Parse a query with the leading imports, e.g.
+/// `import "lib/strings" as str; .name | str::trim`.
+pub fn parse_query_from_str(source: &str) -> Result<(Vec<Import>, Expression), Error> {
+    check_pragmas(source)?;
+    let tokens = lex_from_str(source)?;
+    let mut parser = Parser::new(&tokens);
+    let imports = parser.parse_imports()?;
+    let expression = parser.parse_pipe()?;
+    parser.expect_end()?;
+    Ok((imports, expression))
+}
+
+/// Parse a module file, i.e. the imports followed by
+/// a list of function definitions.
+pub fn parse_module_from_str(
+    source: &str,
+) -> Result<(Vec<Import>, Vec<FunctionDefinition>), Error> {
+    check_pragmas(source)?;
+    let tokens = lex_from_str(source)?;
+    let mut parser = Parser::new(&tokens);
+    let imports = parser.parse_imports()?;
+    let mut definitions = vec![];
+    while parser.peek_keyword("def") {
+        definitions.push(parser.parse_definition()?);
+    }
+    parser.expect_end()?;
+    Ok((imports, definitions))
+}
+
+struct Parser<'a> {
+    tokens: &'a [TokenWithLocation],
+    position: usize,
+
+    // the comma operator is not allowed in object values
+    // unless the value is enclosed in parentheses, e.g. `{a: (1, 2)}`.
+    allow_comma: bool,
+
+    // the aliases of the imported modules, `alias::name` is
+    // a call of the module function instead of a variant.
+    module_aliases: Vec<String>,
+}
+
+fn describe_token(token: &Token) -> String {
+    let s = match token {
+        Token::Dot => ".",
+        Token::DotDot => "..",
+        Token::Field(name) => return format!("field \".{}\"", name),
+        Token::Element(index) => return format!("element \".{}\"", index),
+        Token::Variable(name) => return format!("variable \"${}\"", name),
+        Token::Format(name) => return format!("format \"@{}\"", name),
+        Token::Identifier(name) => return format!("\"{}\"", name),
+        Token::Number(n) | Token::NegativeMinimum(n) => return format!("number \"{}\"", n),
+        Token::String(_) => return "string".to_owned(),
+        Token::Pipe => "|",
+        Token::Comma => ",",
+        Token::Colon => ":",
+        Token::ColonColon => "::",
+        Token::Semicolon => ";",
+        Token::Question => "?",
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Star => "*",
+        Token::Slash => "/",
+        Token::Percent => "%",
+        Token::Equal => "==",
+        Token::NotEqual => "!=",
+        Token::Less => "<",
+        Token::LessOrEqual => "<=",
+        Token::Greater => ">",
+        Token::GreaterOrEqual => ">=",
+        Token::Alternative => "//",
+        Token::Assign => "=",
+        Token::UpdateAssign => "|=",
+        Token::AddAssign => "+=",
+        Token::SubtractAssign => "-=",
+        Token::MultiplyAssign => "*=",
+        Token::DivideAssign => "/=",
+        Token::RemainderAssign => "%=",
+        Token::AlternativeAssign => "//=",
+        Token::LeftParen => "(",
+        Token::RightParen => ")",
+        Token::LeftBracket => "[",
+        Token::RightBracket => "]",
+        Token::LeftBrace => "{",
+        Token::RightBrace => "}",
+    };
+    format!("\"{}\"", s)
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [TokenWithLocation]) -> Self {
+        Self {
+            tokens,
+            position: 0,
+            allow_comma: true,
+            module_aliases: vec![],
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<&'a Token> {
+        self.tokens.get(self.position + offset).map(|t| &t.token)
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(0), Some(Token::Identifier(name)) if name == keyword)
+    }
+
+    fn next(&mut self) -> Result<&'a TokenWithLocation, Error> {
+        match self.tokens.get(self.position) {
+            Some(t) => {
+                self.position += 1;
+                Ok(t)
+            }
+            None => Err(Error::UnexpectedEndOfQuery(
+                "Expect more tokens.".to_owned(),
+            )),
+        }
+    }
+
+    fn consume(&mut self, expected: &Token) -> bool {
+        if self.peek(0) == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unexpected(&self, token: &TokenWithLocation, expected: &str) -> Error {
+        Error::MessageWithLocation(
+            format!(
+                "Unexpected {}, expect {}.",
+                describe_token(&token.token),
+                expected
+            ),
+            token.location,
+        )
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), Error> {
+        let token = self.tokens.get(self.position).ok_or_else(|| {
+            Error::UnexpectedEndOfQuery(format!("Expect {}.", describe_token(expected)))
+        })?;
+
+        if &token.token == expected {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected(token, &describe_token(expected)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        self.expect(&Token::Identifier(keyword.to_owned()))
+    }
+
+    fn expect_end(&self) -> Result<(), Error> {
+        match self.tokens.get(self.position) {
+            Some(token) => Err(Error::MessageWithLocation(
+                format!("Unexpected {}.", describe_token(&token.token)),
+                token.location,
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn expect_identifier(&mut self) -> Result<String, Error> {
+        let token = self.next()?;
+        match &token.token {
+            Token::Identifier(name) if !KEYWORDS.contains(&name.as_str()) => Ok(name.clone()),
+            _ => Err(self.unexpected(token, "a name")),
+        }
+    }
+
+    // parse with the comma operator enabled or disabled,
+    // the previous state is restored afterwards.
+    fn with_comma<T>(
+        &mut self,
+        allow: bool,
+        f: impl FnOnce(&mut Self) -> Result<T, Error>,
+    ) -> Result<T, Error> {
+        let saved = self.allow_comma;
+        self.allow_comma = allow;
+        let result = f(self);
+        self.allow_comma = saved;
+        result
+    }
+
+    fn parse_pipe(&mut self) -> Result<Expression, Error> {
+        if self.peek_keyword("def") {
+            let definition = self.parse_definition()?;
+            let rest = self.parse_pipe()?;
+            return Ok(Expression::FunctionDefinition(definition, Box::new(rest)));
+        }
+
+        let left = self.parse_comma()?;
+        if self.consume(&Token::Pipe) {
+            let right = self.parse_pipe()?;
+            Ok(Expression::Pipe(Box::new(left), Box::new(right)))
+        } else {
+            Ok(left)
+        }
+    }
+
+    fn parse_comma(&mut self) -> Result<Expression, Error> {
+        let mut left = self.parse_alternative()?;
+        while self.allow_comma && self.consume(&Token::Comma) {
+            let right = self.parse_alternative()?;
+            left = Expression::Comma(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_alternative(&mut self) -> Result<Expression, Error> {
+        let left = self.parse_assignment()?;
+        if self.consume(&Token::Alternative) {
+            // right associative
+            let right = self.parse_alternative()?;
+            Ok(Expression::Alternative(Box::new(left), Box::new(right)))
+        } else {
+            Ok(left)
+        }
+    }
+
+    fn parse_assignment(&mut self) -> Result<Expression, Error> {
+        let left = self.parse_or()?;
+
+        let operator = match self.peek(0) {
+            Some(Token::Assign) => AssignOperator::Set,
+            Some(Token::UpdateAssign) => AssignOperator::Update,
+            Some(Token::AddAssign) => AssignOperator::Arithmetic(BinaryOperator::Add),
+            Some(Token::SubtractAssign) => AssignOperator::Arithmetic(BinaryOperator::Subtract),
+            Some(Token::MultiplyAssign) => AssignOperator::Arithmetic(BinaryOperator::Multiply),
+            Some(Token::DivideAssign) => AssignOperator::Arithmetic(BinaryOperator::Divide),
+            Some(Token::RemainderAssign) => AssignOperator::Arithmetic(BinaryOperator::Remainder),
+            Some(Token::AlternativeAssign) => AssignOperator::Alternative,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+
+        // assignment operators are non-associative
+        let right = self.parse_or()?;
+        Ok(Expression::Assign(
+            operator,
+            Box::new(left),
+            Box::new(right),
+        ))
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, Error> {
+        let mut left = self.parse_and()?;
+        while self.consume_keyword("or") {
+            let right = self.parse_and()?;
+            left = Expression::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, Error> {
+        let mut left = self.parse_comparison()?;
+        while self.consume_keyword("and") {
+            let right = self.parse_comparison()?;
+            left = Expression::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expression, Error> {
+        let left = self.parse_additive()?;
+
+        let operator = match self.peek(0) {
+            Some(Token::Equal) => BinaryOperator::Equal,
+            Some(Token::NotEqual) => BinaryOperator::NotEqual,
+            Some(Token::Less) => BinaryOperator::Less,
+            Some(Token::LessOrEqual) => BinaryOperator::LessOrEqual,
+            Some(Token::Greater) => BinaryOperator::Greater,
+            Some(Token::GreaterOrEqual) => BinaryOperator::GreaterOrEqual,
+            _ => return Ok(left),
+        };
+        self.position += 1;
+
+        // comparison operators are non-associative
+        let right = self.parse_additive()?;
+        Ok(Expression::Binary(
+            operator,
+            Box::new(left),
+            Box::new(right),
+        ))
+    }
+
+    fn parse_additive(&mut self) -> Result<Expression, Error> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let operator = match self.peek(0) {
+                Some(Token::Plus) => BinaryOperator::Add,
+                Some(Token::Minus) => BinaryOperator::Subtract,
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.parse_multiplicative()?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expression, Error> {
+        let mut left = self.parse_unary()?;
+        loop {
+            let operator = match self.peek(0) {
+                Some(Token::Star) => BinaryOperator::Multiply,
+                Some(Token::Slash) => BinaryOperator::Divide,
+                Some(Token::Percent) => BinaryOperator::Remainder,
+                _ => break,
+            };
+            self.position += 1;
+            let right = self.parse_unary()?;
+            left = Expression::Binary(operator, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expression, Error> {
+        if self.consume(&Token::Minus) {
+            // the minimum of a signed type, e.g. `-128_i8`
+            if let Some(Token::NegativeMinimum(n)) = self.peek(0) {
+                self.position += 1;
+                return Ok(Expression::Literal(Value::Number(*n)));
+            }
+            let expression = self.parse_unary()?;
+            Ok(Expression::Negate(Box::new(expression)))
+        } else {
+            self.parse_postfix(true)
+        }
+    }
+
+    // whether the next token is a `(` which follows the previous
+    // token immediately, e.g. the method call style `.filter(. > 13)`.
+    fn is_adjacent_paren(&self) -> bool {
+        match (
+            self.tokens.get(self.position - 1),
+            self.tokens.get(self.position),
+        ) {
+            (Some(previous), Some(next)) => {
+                next.token == Token::LeftParen
+                    && previous.location.index + previous.location.length == next.location.index
+            }
+            _ => false,
+        }
+    }
+
+    // `.name` or the method call `.name(args)`
+    fn parse_field(&mut self, target: Expression, name: &str) -> Result<Expression, Error> {
+        if self.is_adjacent_paren() {
+            let args = self.parse_call_args()?;
+            let call = Expression::Call(name.to_owned(), args);
+            if target == Expression::Identity {
+                Ok(call)
+            } else {
+                Ok(Expression::Pipe(Box::new(target), Box::new(call)))
+            }
+        } else {
+            Ok(Expression::Index(
+                Box::new(target),
+                Box::new(Expression::Literal(Value::String(name.to_owned()))),
+            ))
+        }
+    }
+
+    fn parse_postfix(&mut self, allow_binding: bool) -> Result<Expression, Error> {
+        let mut term = self.parse_primary()?;
+
+        loop {
+            match self.peek(0) {
+                Some(Token::Field(name)) => {
+                    self.position += 1;
+                    term = self.parse_field(term, name)?;
+                }
+                Some(Token::Element(index)) => {
+                    self.position += 1;
+                    term = element(term, *index);
+                }
+                Some(Token::Dot) if matches!(self.peek(1), Some(Token::String(_))) => {
+                    // `.a."b"`
+                    self.position += 1;
+                    let key = self.parse_primary()?;
+                    term = Expression::Index(Box::new(term), Box::new(key));
+                }
+                Some(Token::Dot) if matches!(self.peek(1), Some(Token::LeftBracket)) => {
+                    // `.a.[0]`, the same as `.a[0]`
+                    self.position += 1;
+                }
+                Some(Token::LeftBracket) => {
+                    self.position += 1;
+                    term = self.parse_bracket_suffix(term)?;
+                }
+                Some(Token::Question) => {
+                    self.position += 1;
+                    term = Expression::Try(Box::new(term), None);
+                }
+                _ => break,
+            }
+        }
+
+        if allow_binding && self.consume_keyword("as") {
+            let pattern = self.parse_pattern()?;
+            self.expect(&Token::Pipe)?;
+            let body = self.parse_pipe()?;
+            term = Expression::Binding {
+                source: Box::new(term),
+                pattern,
+                body: Box::new(body),
+            };
+        }
+
+        Ok(term)
+    }
+
+    // `[]`, `[index]`, `[from:to]`, the `[` has been consumed.
+    fn parse_bracket_suffix(&mut self, target: Expression) -> Result<Expression, Error> {
+        if self.consume(&Token::RightBracket) {
+            return Ok(Expression::Iterate(Box::new(target)));
+        }
+
+        self.with_comma(true, |parser| {
+            let from = if parser.peek(0) == Some(&Token::Colon) {
+                None
+            } else {
+                Some(Box::new(parser.parse_pipe()?))
+            };
+
+            if parser.consume(&Token::Colon) {
+                let to = if parser.peek(0) == Some(&Token::RightBracket) {
+                    None
+                } else {
+                    Some(Box::new(parser.parse_pipe()?))
+                };
+                parser.expect(&Token::RightBracket)?;
+                Ok(Expression::Slice(Box::new(target), from, to))
+            } else {
+                parser.expect(&Token::RightBracket)?;
+                Ok(Expression::Index(Box::new(target), from.unwrap()))
+            }
+        })
+    }
+
+    fn parse_primary(&mut self) -> Result<Expression, Error> {
+        let token = self.next()?;
+
+        let expression = match &token.token {
+            Token::Dot => {
+                if let Some(Token::String(parts)) = self.peek(0) {
+                    // `."name"`
+                    self.position += 1;
+                    let key = self.parse_string(parts)?;
+                    Expression::Index(Box::new(Expression::Identity), Box::new(key))
+                } else {
+                    Expression::Identity
+                }
+            }
+            Token::DotDot => Expression::RecurseDefault,
+            Token::Field(name) => self.parse_field(Expression::Identity, name)?,
+            Token::Element(index) => element(Expression::Identity, *index),
+            Token::Variable(name) => Expression::Variable(name.clone()),
+            Token::Format(name) => self.parse_format(name)?,
+            Token::Number(n) => Expression::Literal(Value::Number(*n)),
+            Token::NegativeMinimum(n) => {
+                return Err(Error::MessageWithLocation(
+                    format!(
+                        "Number \"{}\" is out of the range of \"{}\".",
+                        n.plain_text().trim_start_matches('-'),
+                        n.type_suffix()
+                    ),
+                    token.location,
+                ));
+            }
+            Token::String(parts) => self.parse_string(parts)?,
+            Token::LeftParen => {
+                let expression = self.with_comma(true, |parser| parser.parse_pipe())?;
+                self.expect(&Token::RightParen)?;
+                expression
+            }
+            Token::LeftBracket => {
+                if self.consume(&Token::RightBracket) {
+                    Expression::List(None)
+                } else {
+                    let expression = self.with_comma(true, |parser| parser.parse_pipe())?;
+                    self.expect(&Token::RightBracket)?;
+                    Expression::List(Some(Box::new(expression)))
+                }
+            }
+            Token::LeftBrace => self.parse_object()?,
+            Token::Identifier(name) => match name.as_str() {
+                "true" => Expression::Literal(Value::Boolean(true)),
+                "false" => Expression::Literal(Value::Boolean(false)),
+                "null" => Expression::Literal(Value::null()),
+                "if" => self.parse_if()?,
+                "try" => self.parse_try()?,
+                "reduce" => self.parse_reduce()?,
+                "foreach" => self.parse_foreach()?,
+                _ if KEYWORDS.contains(&name.as_str()) => {
+                    return Err(self.unexpected(token, "an expression"));
+                }
+                _ if self.module_aliases.contains(name) && self.consume(&Token::ColonColon) => {
+                    let member = self.expect_identifier()?;
+                    let args = if self.peek(0) == Some(&Token::LeftParen) {
+                        self.parse_call_args()?
+                    } else {
+                        vec![]
+                    };
+                    Expression::Call(format!("{}::{}", name, member), args)
+                }
+                _ if self.consume(&Token::ColonColon) => self.parse_variant(name)?,
+                _ => {
+                    let args = if self.peek(0) == Some(&Token::LeftParen) {
+                        self.parse_call_args()?
+                    } else {
+                        vec![]
+                    };
+                    Expression::Call(name.clone(), args)
+                }
+            },
+            _ => return Err(self.unexpected(token, "an expression")),
+        };
+
+        Ok(expression)
+    }
+
+    // `Type::Member`, `Type::Member(value)`, `Type::Member(a, b)` and
+    // `Type::Member{key: value, ...}`, the `Type::` has been consumed.
+    fn parse_variant(&mut self, type_name: &str) -> Result<Expression, Error> {
+        let token = self.next()?;
+        let member_name = match &token.token {
+            Token::Identifier(name) => name.clone(),
+            _ => return Err(self.unexpected(token, "a variant member name")),
+        };
+
+        let payload = if self.consume(&Token::LeftParen) {
+            let mut items = vec![];
+            self.with_comma(false, |parser| loop {
+                items.push(parser.parse_pipe()?);
+                if !parser.consume(&Token::Comma) {
+                    return parser.expect(&Token::RightParen);
+                }
+            })?;
+
+            if items.len() == 1 {
+                VariantPayload::Value(Box::new(items.remove(0)))
+            } else {
+                VariantPayload::Tuple(items)
+            }
+        } else if self.consume(&Token::LeftBrace) {
+            VariantPayload::Object(Box::new(self.parse_object()?))
+        } else {
+            VariantPayload::Empty
+        };
+
+        Ok(Expression::Variant {
+            type_name: type_name.to_owned(),
+            member_name,
+            payload,
+        })
+    }
+
+    // `@name`, `@name(arg)`, `@name "template"` and `@name(arg) "template"`,
+    // the `@name` has been consumed.
+    fn parse_format(&mut self, name: &str) -> Result<Expression, Error> {
+        let argument = if self.consume(&Token::LeftParen) {
+            let expression = self.with_comma(true, |parser| parser.parse_pipe())?;
+            self.expect(&Token::RightParen)?;
+            Some(Box::new(expression))
+        } else {
+            None
+        };
+
+        let template = if let Some(Token::String(parts)) = self.peek(0) {
+            self.position += 1;
+            Some(self.parse_interpolation_parts(parts)?)
+        } else {
+            None
+        };
+
+        Ok(Expression::Format {
+            name: name.to_owned(),
+            argument,
+            template,
+        })
+    }
+
+    fn parse_string(&mut self, parts: &[StringPart]) -> Result<Expression, Error> {
+        if let [StringPart::Literal(s)] = parts {
+            return Ok(Expression::Literal(Value::String(s.clone())));
+        }
+
+        Ok(Expression::Interpolation(
+            self.parse_interpolation_parts(parts)?,
+        ))
+    }
+
+    fn parse_interpolation_parts(
+        &mut self,
+        parts: &[StringPart],
+    ) -> Result<Vec<InterpolationPart>, Error> {
+        let mut items = vec![];
+        for part in parts {
+            let item = match part {
+                StringPart::Literal(s) => InterpolationPart::Literal(s.clone()),
+                StringPart::Interpolation(tokens) => {
+                    // the segment is parsed with the aliases of the
+                    // imported modules, e.g. `"\(str::trim)"`.
+                    let mut parser = Parser {
+                        module_aliases: self.module_aliases.clone(),
+                        ..Parser::new(tokens)
+                    };
+                    let expression = parser.parse_pipe()?;
+                    parser.expect_end()?;
+                    InterpolationPart::Expression(expression)
+                }
+            };
+            items.push(item);
+        }
+        Ok(items)
+    }
+
+    // `(arg1; arg2; ...)`
+    fn parse_call_args(&mut self) -> Result<Vec<Expression>, Error> {
+        self.expect(&Token::LeftParen)?;
+        let mut args = vec![];
+
+        // `name()`, e.g. the method call style `.unwrap()`
+        if self.consume(&Token::RightParen) {
+            return Ok(args);
+        }
+
+        self.with_comma(true, |parser| loop {
+            args.push(parser.parse_pipe()?);
+            if !parser.consume(&Token::Semicolon) {
+                return parser.expect(&Token::RightParen);
+            }
+        })?;
+        Ok(args)
+    }
+
+    fn parse_object(&mut self) -> Result<Expression, Error> {
+        let mut entries = vec![];
+
+        loop {
+            if self.consume(&Token::RightBrace) {
+                break;
+            }
+
+            entries.push(self.parse_object_entry()?);
+
+            if !self.consume(&Token::Comma) {
+                self.expect(&Token::RightBrace)?;
+                break;
+            }
+        }
+
+        Ok(Expression::Object(entries))
+    }
+
+    fn parse_object_entry(&mut self) -> Result<(Expression, Expression), Error> {
+        let token = self.next()?;
+
+        let key = match &token.token {
+            Token::Variable(name) => {
+                // `{$name}` is the shorthand of `{name: $name}`
+                return Ok((
+                    Expression::Literal(Value::String(name.clone())),
+                    Expression::Variable(name.clone()),
+                ));
+            }
+            Token::Identifier(name) => Expression::Literal(Value::String(name.clone())),
+            Token::String(parts) => self.parse_string(parts)?,
+            Token::LeftParen => {
+                let key = self.with_comma(true, |parser| parser.parse_pipe())?;
+                self.expect(&Token::RightParen)?;
+                self.expect(&Token::Colon)?;
+                let value = self.parse_object_value()?;
+                return Ok((key, value));
+            }
+            _ => return Err(self.unexpected(token, "an object key")),
+        };
+
+        let value = if self.consume(&Token::Colon) {
+            self.parse_object_value()?
+        } else {
+            // `{name}` is the shorthand of `{name: .name}`
+            Expression::Index(Box::new(Expression::Identity), Box::new(key.clone()))
+        };
+
+        Ok((key, value))
+    }
+
+    fn parse_object_value(&mut self) -> Result<Expression, Error> {
+        self.with_comma(false, |parser| parser.parse_pipe())
+    }
+
+    fn parse_if(&mut self) -> Result<Expression, Error> {
+        self.with_comma(true, |parser| {
+            let mut branches = vec![];
+            let condition = parser.parse_pipe()?;
+            parser.expect_keyword("then")?;
+            let body = parser.parse_pipe()?;
+            branches.push((condition, body));
+
+            let mut else_body = None;
+            loop {
+                if parser.consume_keyword("elif") {
+                    let condition = parser.parse_pipe()?;
+                    parser.expect_keyword("then")?;
+                    let body = parser.parse_pipe()?;
+                    branches.push((condition, body));
+                } else if parser.consume_keyword("else") {
+                    else_body = Some(Box::new(parser.parse_pipe()?));
+                    parser.expect_keyword("end")?;
+                    break;
+                } else {
+                    parser.expect_keyword("end")?;
+                    break;
+                }
+            }
+
+            Ok(Expression::If(branches, else_body))
+        })
+    }
+
+    fn parse_try(&mut self) -> Result<Expression, Error> {
+        let body = self.parse_postfix(false)?;
+        let handler = if self.consume_keyword("catch") {
+            Some(Box::new(self.parse_postfix(false)?))
+        } else {
+            None
+        };
+        Ok(Expression::Try(Box::new(body), handler))
+    }
+
+    fn parse_reduce(&mut self) -> Result<Expression, Error> {
+        let source = self.parse_postfix(false)?;
+        self.expect_keyword("as")?;
+        let pattern = self.parse_pattern()?;
+
+        self.expect(&Token::LeftParen)?;
+        let (init, update) = self.with_comma(true, |parser| {
+            let init = parser.parse_pipe()?;
+            parser.expect(&Token::Semicolon)?;
+            let update = parser.parse_pipe()?;
+            Ok((init, update))
+        })?;
+        self.expect(&Token::RightParen)?;
+
+        Ok(Expression::Reduce {
+            source: Box::new(source),
+            pattern,
+            init: Box::new(init),
+            update: Box::new(update),
+        })
+    }
+
+    fn parse_foreach(&mut self) -> Result<Expression, Error> {
+        let source = self.parse_postfix(false)?;
+        self.expect_keyword("as")?;
+        let pattern = self.parse_pattern()?;
+
+        self.expect(&Token::LeftParen)?;
+        let (init, update, extract) = self.with_comma(true, |parser| {
+            let init = parser.parse_pipe()?;
+            parser.expect(&Token::Semicolon)?;
+            let update = parser.parse_pipe()?;
+            let extract = if parser.consume(&Token::Semicolon) {
+                Some(Box::new(parser.parse_pipe()?))
+            } else {
+                None
+            };
+            Ok((init, update, extract))
+        })?;
+        self.expect(&Token::RightParen)?;
+
+        Ok(Expression::Foreach {
+            source: Box::new(source),
+            pattern,
+            init: Box::new(init),
+            update: Box::new(update),
+            extract,
+        })
+    }
+
+    // `$name`, `[$a, $b]` and `($a, $b)`, the patterns can be nested.
+    fn parse_pattern(&mut self) -> Result<Pattern, Error> {
+        let token = self.next()?;
+        let close = match &token.token {
+            Token::Variable(name) => return Ok(Pattern::Variable(name.clone())),
+            Token::LeftBracket => Token::RightBracket,
+            Token::LeftParen => Token::RightParen,
+            _ => return Err(self.unexpected(token, "a variable or a destructuring pattern")),
+        };
+
+        let mut patterns = vec![];
+        loop {
+            patterns.push(self.parse_pattern()?);
+            if !self.consume(&Token::Comma) {
+                self.expect(&close)?;
+                return Ok(Pattern::Elements(patterns));
+            }
+        }
+    }
+
+    // `import "path" as alias;`, the imports must precede
+    // the definitions and the expression.
+    fn parse_imports(&mut self) -> Result<Vec<Import>, Error> {
+        let mut imports = vec![];
+        while self.consume_keyword("import") {
+            let token = self.next()?;
+            let path = match &token.token {
+                Token::String(parts) => match parts.as_slice() {
+                    [] => String::new(),
+                    [StringPart::Literal(s)] => s.clone(),
+                    _ => return Err(self.unexpected(token, "a module path without interpolation")),
+                },
+                _ => return Err(self.unexpected(token, "a module path")),
+            };
+
+            self.expect_keyword("as")?;
+            let token = self.next()?;
+            let alias = match &token.token {
+                Token::Identifier(alias) if !KEYWORDS.contains(&alias.as_str()) => alias.clone(),
+                _ => return Err(self.unexpected(token, "a module alias")),
+            };
+            self.expect(&Token::Semicolon)?;
+
+            if self.module_aliases.contains(&alias) {
+                return Err(Error::MessageWithLocation(
+                    format!("The module alias \"{}\" is already defined.", alias),
+                    token.location,
+                ));
+            }
+            self.module_aliases.push(alias.clone());
+            imports.push(Import { path, alias });
+        }
+        Ok(imports)
+    }
+
+    // `def name: body;` or `def name(f; $v): body;`
+    fn parse_definition(&mut self) -> Result<FunctionDefinition, Error> {
+        self.expect_keyword("def")?;
+        let name = self.expect_identifier()?;
+
+        let mut params = vec![];
+        let mut value_params = vec![];
+
+        if self.consume(&Token::LeftParen) {
+            loop {
+                let token = self.next()?;
+                match &token.token {
+                    Token::Identifier(param) if !KEYWORDS.contains(&param.as_str()) => {
+                        params.push(param.clone());
+                    }
+                    Token::Variable(param) => {
+                        params.push(param.clone());
+                        value_params.push(param.clone());
+                    }
+                    _ => return Err(self.unexpected(token, "a parameter name")),
+                }
+
+                if !self.consume(&Token::Semicolon) {
+                    self.expect(&Token::RightParen)?;
+                    break;
+                }
+            }
+        }
+
+        self.expect(&Token::Colon)?;
+        let mut body = self.with_comma(true, |parser| parser.parse_pipe())?;
+        self.expect(&Token::Semicolon)?;
+
+        // `def f($a): body` => `def f(a): a as $a | body`
+        for param in value_params.into_iter().rev() {
+            body = Expression::Binding {
+                source: Box::new(Expression::Call(param.clone(), vec![])),
+                pattern: Pattern::Variable(param),
+                body: Box::new(body),
+            };
+        }
+
+        Ok(FunctionDefinition {
+            name,
+            params,
+            body: Box::new(body),
+        })
+    }
+}
+
+// `.0`, i.e. `.[0]`
+fn element(target: Expression, index: usize) -> Expression {
+    Expression::Index(
+        Box::new(target),
+        Box::new(Expression::Literal(Value::from(index))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        ast::{BinaryOperator, Expression, InterpolationPart},
+        error::Error,
+    };
+
+    use super::parse_query_from_str;
+
+    fn parse(source: &str) -> Expression {
+        parse_query_from_str(source).unwrap().1
+    }
+
+    fn field(name: &str) -> Expression {
+        Expression::Index(
+            Box::new(Expression::Identity),
+            Box::new(Expression::Literal(name.into())),
+        )
+    }
+
+    #[test]
+    fn test_parse_pipe_and_precedence() {
+        assert_eq!(
+            parse(".a | .b, 1 + 2 * 3"),
+            Expression::Pipe(
+                Box::new(field("a")),
+                Box::new(Expression::Comma(
+                    Box::new(field("b")),
+                    Box::new(Expression::Binary(
+                        BinaryOperator::Add,
+                        Box::new(Expression::Literal(1.into())),
+                        Box::new(Expression::Binary(
+                            BinaryOperator::Multiply,
+                            Box::new(Expression::Literal(2.into())),
+                            Box::new(Expression::Literal(3.into())),
+                        ))
+                    ))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_interpolation() {
+        assert_eq!(
+            parse(r#""Order \(.id): \(.total) USD""#),
+            Expression::Interpolation(vec![
+                InterpolationPart::Literal("Order ".to_owned()),
+                InterpolationPart::Expression(field("id")),
+                InterpolationPart::Literal(": ".to_owned()),
+                InterpolationPart::Expression(field("total")),
+                InterpolationPart::Literal(" USD".to_owned()),
+            ])
+        );
+
+        // a string without interpolations is a literal
+        assert_eq!(parse(r#""abc""#), Expression::Literal("abc".into()));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        assert!(matches!(
+            parse_query_from_str(".a +"),
+            Err(Error::UnexpectedEndOfQuery(_))
+        ));
+        assert!(matches!(
+            parse_query_from_str(r#""\(.a | )""#),
+            Err(Error::MessageWithLocation(..) | Error::UnexpectedEndOfQuery(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_interpolation_with_module_call() {
+        let (imports, expression) =
+            parse_query_from_str(r#"import "lib/strings" as str; "name: \(.name | str::trim)""#)
+                .unwrap();
+        assert_eq!(imports.len(), 1);
+
+        let Expression::Interpolation(parts) = expression else {
+            panic!("expect an interpolation, found {:?}", expression);
+        };
+        assert_eq!(parts[0], InterpolationPart::Literal("name: ".to_owned()));
+        assert_eq!(
+            parts[1],
+            InterpolationPart::Expression(Expression::Pipe(
+                Box::new(Expression::Index(
+                    Box::new(Expression::Identity),
+                    Box::new(Expression::Literal("name".into()))
+                )),
+                Box::new(Expression::Call("str::trim".to_owned(), vec![]))
+            ))
+        );
+    }
+}
@@ -0,0 +1,349 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Paths as data.
+//
+// A path is a list of keys, e.g. `["users", 3, "email"]`, each key is
+// either a string (object key), a number (list or tuple index), or
+// an object `{start, end}` which stands for a slice.
+
+use crate::{
+    error::Error,
+    evaluator::{slice_value, to_index, to_range},
+    value::{escape_string, is_identifier, Items, Map, Number, Value},
+};
+
+fn slice_bounds(bounds: &Map) -> (Option<&Value>, Option<&Value>) {
+    (bounds.get("start"), bounds.get("end"))
+}
+
+fn invalid_key_error(action: &str, target: &Value, key: &Value) -> Error {
+    Error::Message(format!(
+        "Cannot {} {} with key {}.",
+        action,
+        target.type_name(),
+        key
+    ))
+}
+
+pub fn path_from_value(value: Value) -> Result<Vec<Value>, Error> {
+    match value {
+        Value::List(keys) => Ok(keys.into_vec()),
+        _ => Err(Error::Message(format!(
+            "A path must be a list, found {}.",
+            value.type_name()
+        ))),
+    }
+}
+
+/// Format the path as a path expression, e.g. `.users[3].email`.
+pub fn path_to_string(path: &[Value]) -> String {
+    if path.is_empty() {
+        return ".".to_owned();
+    }
+
+    // the path of an index starts with a dot as well, e.g. `.[0].name`.
+    let mut s = match path[0] {
+        Value::String(_) => String::new(),
+        _ => ".".to_owned(),
+    };
+    for key in path {
+        match key {
+            Value::String(k) if is_identifier(k) => {
+                s.push('.');
+                s.push_str(k);
+            }
+            Value::String(k) => {
+                s.push('.');
+                s.push_str(&escape_string(k, '"'));
+            }
+            Value::Object(bounds) => {
+                let (start, end) = slice_bounds(bounds);
+                let bound = |v: Option<&Value>| match v {
+                    Some(v) if !v.is_null() => v.to_string(),
+                    _ => String::new(),
+                };
+                s.push_str(&format!("[{}:{}]", bound(start), bound(end)));
+            }
+            _ => s.push_str(&format!("[{}]", key)),
+        }
+    }
+    s
+}
+
+/// Get the value at the path, `null` is returned if the path does not exist.
+pub fn getpath(value: &Value, path: &[Value]) -> Result<Value, Error> {
+    let mut current = value;
+
+    for (idx, key) in path.iter().enumerate() {
+        if current.is_null() {
+            return Ok(Value::null());
+        }
+
+        current = match (current, key) {
+            (Value::Object(map), Value::String(k)) => match map.get(k) {
+                Some(v) => v,
+                None => return Ok(Value::null()),
+            },
+            (Value::List(items) | Value::Tuple(items), Value::Number(n)) => {
+                match to_index(n, items.len()) {
+                    Some(i) => &items[i],
+                    None => return Ok(Value::null()),
+                }
+            }
+            (Value::List(_) | Value::Tuple(_) | Value::String(_), Value::Object(bounds)) => {
+                let (start, end) = slice_bounds(bounds);
+                let sliced = slice_value(current.clone(), start, end)?;
+                return getpath(&sliced, &path[idx + 1..]);
+            }
+            _ => return Err(invalid_key_error("index", current, key)),
+        };
+    }
+
+    Ok(current.clone())
+}
+
+/// Set the value at the path, the missing objects and lists
+/// along the path are created.
+pub fn setpath(target: Value, path: &[Value], new_value: Value) -> Result<Value, Error> {
+    let (key, rest) = match path.split_first() {
+        Some(first) => first,
+        None => return Ok(new_value),
+    };
+
+    match (target, key) {
+        (Value::Object(mut map), Value::String(k)) => {
+            match map.get_mut(k) {
+                Some(slot) => {
+                    let child = std::mem::replace(slot, Value::null());
+                    *slot = setpath(child, rest, new_value)?;
+                }
+                None => {
+                    map.insert(k.clone(), setpath(Value::null(), rest, new_value)?);
+                }
+            }
+            Ok(Value::Object(map))
+        }
+        (target, Value::String(k)) if target.is_null() => {
+            let mut map = Map::new();
+            map.insert(k.clone(), setpath(Value::null(), rest, new_value)?);
+            Ok(Value::Object(map))
+        }
+        (Value::List(mut items), Value::Number(n)) => {
+            set_item(&mut items, n, rest, new_value, true)?;
+            Ok(Value::List(items))
+        }
+        (Value::Tuple(mut items), Value::Number(n)) => {
+            // the length of a tuple is fixed
+            set_item(&mut items, n, rest, new_value, false)?;
+            Ok(Value::Tuple(items))
+        }
+        (target, Value::Number(n)) if target.is_null() => {
+            let mut items = vec![];
+            set_item(&mut items, n, rest, new_value, true)?;
+            Ok(Value::List(items.into()))
+        }
+        (target, Value::Object(bounds)) if target.is_null() || matches!(target, Value::List(_)) => {
+            let mut items = match target {
+                Value::List(items) => items,
+                _ => Items::new(),
+            };
+
+            let (start, end) = slice_bounds(bounds);
+            let (start, end) = to_range(start, end, items.len())?;
+            let current = Value::List(items[start..end].to_vec().into());
+            match setpath(current, rest, new_value)? {
+                Value::List(replacement) => {
+                    items.splice(start..end, replacement);
+                    Ok(Value::List(items))
+                }
+                other => Err(Error::Message(format!(
+                    "A slice can only be replaced with a list, found {}.",
+                    other.type_name()
+                ))),
+            }
+        }
+        (target, key) => Err(invalid_key_error("update", &target, key)),
+    }
+}
+
+fn set_item(
+    items: &mut Vec<Value>,
+    n: &Number,
+    rest: &[Value],
+    new_value: Value,
+    extendable: bool,
+) -> Result<(), Error> {
+    let i = n.as_integer().unwrap_or_else(|| n.as_f64().floor() as i128);
+    let i = if i < 0 { i + items.len() as i128 } else { i };
+
+    if i < 0 {
+        return Err(Error::Message("Index out of bounds.".to_owned()));
+    }
+
+    let i = i as usize;
+    if i >= items.len() {
+        if !extendable {
+            return Err(Error::Message(format!(
+                "Index {} is out of the range of the tuple.",
+                i
+            )));
+        }
+        items.resize(i + 1, Value::null());
+    }
+
+    let child = std::mem::replace(&mut items[i], Value::null());
+    items[i] = setpath(child, rest, new_value)?;
+    Ok(())
+}
+
+/// Delete the values at the paths.
+pub fn delpaths(target: Value, mut paths: Vec<Vec<Value>>) -> Result<Value, Error> {
+    // delete the latter paths first, so that the indices of
+    // the former paths keep valid.
+    paths.sort_by(|a, b| b.cmp(a));
+
+    let mut value = target;
+    for path in paths {
+        value = delpath(value, &path)?;
+    }
+    Ok(value)
+}
+
+fn delpath(target: Value, path: &[Value]) -> Result<Value, Error> {
+    let (key, rest) = match path.split_first() {
+        Some(first) => first,
+        None => return Ok(Value::null()),
+    };
+
+    if target.is_null() {
+        return Ok(target);
+    }
+
+    match (target, key) {
+        (Value::Object(mut map), Value::String(k)) => {
+            if rest.is_empty() {
+                map.remove(k);
+            } else if let Some(slot) = map.get_mut(k) {
+                let child = std::mem::replace(slot, Value::null());
+                *slot = delpath(child, rest)?;
+            }
+            Ok(Value::Object(map))
+        }
+        (Value::List(mut items), Value::Number(n)) => {
+            delete_item(&mut items, n, rest)?;
+            Ok(Value::List(items))
+        }
+        (Value::Tuple(mut items), Value::Number(n)) => {
+            delete_item(&mut items, n, rest)?;
+            Ok(Value::Tuple(items))
+        }
+        (Value::List(mut items), Value::Object(bounds)) => {
+            let (start, end) = slice_bounds(bounds);
+            let (start, end) = to_range(start, end, items.len())?;
+            if rest.is_empty() {
+                items.drain(start..end);
+            } else {
+                let current = Value::List(items[start..end].to_vec().into());
+                if let Value::List(replacement) = delpath(current, rest)? {
+                    items.splice(start..end, replacement);
+                }
+            }
+            Ok(Value::List(items))
+        }
+        (target, key) => Err(invalid_key_error("delete", &target, key)),
+    }
+}
+
+fn delete_item(items: &mut Vec<Value>, n: &Number, rest: &[Value]) -> Result<(), Error> {
+    if let Some(i) = to_index(n, items.len()) {
+        if rest.is_empty() {
+            items.remove(i);
+        } else {
+            let child = std::mem::replace(&mut items[i], Value::null());
+            items[i] = delpath(child, rest)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{delpaths, getpath, path_to_string, setpath};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_getpath() {
+        let doc = value(r#"{a: [1, {b: 2}], c: "x"}"#);
+        assert_eq!(
+            getpath(&doc, &["a".into(), 1.into(), "b".into()]).unwrap(),
+            Value::from(2)
+        );
+        assert_eq!(
+            getpath(&doc, &["x".into(), "y".into()]).unwrap(),
+            Value::null()
+        );
+        assert_eq!(
+            getpath(&doc, &["c".into(), 0.into()])
+                .unwrap_err()
+                .to_string(),
+            r#"Cannot index string with key 0."#
+        );
+    }
+
+    #[test]
+    fn test_setpath() {
+        let doc = value(r#"{a: [1, {b: 2}]}"#);
+        assert_eq!(
+            setpath(doc, &["a".into(), 1.into(), "b".into()], 9.into())
+                .unwrap()
+                .to_string(),
+            "{a:[1,{b:9}]}"
+        );
+
+        // the missing containers are created, the lists are padded with `null`
+        assert_eq!(
+            setpath(Value::null(), &["p".into(), 2.into()], 1.into())
+                .unwrap()
+                .to_string(),
+            "{p:[Option::None,Option::None,1]}"
+        );
+    }
+
+    #[test]
+    fn test_delpaths() {
+        let doc = value(r#"{a: [1, {b: 2}, 3], c: "x"}"#);
+        let paths = vec![
+            vec!["a".into(), 0.into()],
+            vec!["a".into(), 2.into()],
+            vec!["c".into()],
+        ];
+        assert_eq!(delpaths(doc, paths).unwrap().to_string(), "{a:[{b:2}]}");
+    }
+
+    #[test]
+    fn test_path_to_string() {
+        let path = |text: &str| match value(text) {
+            Value::List(keys) => path_to_string(&keys),
+            _ => unreachable!(),
+        };
+        assert_eq!(path("[]"), ".");
+        assert_eq!(path("[\"users\", 3, \"email\"]"), ".users[3].email");
+        assert_eq!(path("[0, \"name\"]"), ".[0].name");
+        assert_eq!(path("[{start: 1}]"), ".[1:]");
+        assert_eq!(path("[\"a b\", 1]"), ".\"a b\"[1]");
+    }
+}
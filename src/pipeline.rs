@@ -0,0 +1,183 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The stages of the query pipeline, and the diagnosis of the queries
+// which produce no output, i.e. `aq --explain-empty`.
+//
+// The query is split at the pipes `|`, and the prefixes of the pipeline
+// are evaluated one by one to find the first stage which outputs nothing, e.g.
+//
+// `.users[] | select(.age > 30) | .name`
+//
+// reports the stage `select(.age > 30)` if no user is older than 30,
+// along with the first value the stage receives.
+
+use crate::{
+    error::Error,
+    lexer::{lex_from_str, Token},
+    module::ModuleLoader,
+    parser::parse_query_from_str,
+    query::Query,
+    value::Value,
+};
+
+/// Split the query source into the stages of the top-level pipeline.
+///
+/// The pipes inside the parentheses, brackets, `if ... end`,
+/// definitions and bindings (e.g. `. as $x | ...`) do not split the
+/// pipeline, i.e. each prefix of the stages is a valid query.
+pub fn split_pipeline(source: &str) -> Result<Vec<String>, Error> {
+    let chars: Vec<char> = source.chars().collect();
+    let tokens = lex_from_str(source)?;
+
+    let mut stages = vec![];
+    let mut start = 0;
+    for token in tokens.iter().filter(|t| t.token == Token::Pipe) {
+        let end = token.location.index;
+        let prefix: String = chars[..end].iter().collect();
+
+        // the pipe which is not top-level leaves the prefix incomplete
+        if parse_query_from_str(&prefix).is_ok() {
+            stages.push(
+                chars[start..end]
+                    .iter()
+                    .collect::<String>()
+                    .trim()
+                    .to_owned(),
+            );
+            start = end + token.location.length;
+        }
+    }
+
+    stages.push(chars[start..].iter().collect::<String>().trim().to_owned());
+    Ok(stages)
+}
+
+/// The stage at which the stream of values becomes empty.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EmptyStage {
+    /// The number of the stage, starts from 1.
+    pub number: usize,
+
+    /// The source text of the stage.
+    pub source: String,
+
+    /// The number of values the stage receives.
+    pub received: usize,
+
+    /// The first value the stage receives.
+    pub sample: Value,
+}
+
+impl std::fmt::Display for EmptyStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "The stream becomes empty at stage {}: `{}`",
+            self.number, self.source
+        )?;
+        write!(
+            f,
+            "It receives {} value(s), the first one is:\n{}",
+            self.received, self.sample
+        )
+    }
+}
+
+/// Find the first stage which outputs nothing, `None` is returned if
+/// the whole query produces output. The `inputs` are the documents
+/// consumed by the `input` and `inputs` builtins, and the imported
+/// modules are loaded by the `loader`.
+pub fn explain_empty(
+    source: &str,
+    root: &Value,
+    inputs: &[Value],
+    loader: &ModuleLoader,
+) -> Result<Option<EmptyStage>, Error> {
+    let stages = split_pipeline(source)?;
+
+    // the values received by the current stage, the first stage
+    // receives the root value.
+    let mut received = vec![root.clone()];
+
+    for (idx, stage) in stages.iter().enumerate() {
+        let prefix = stages[..=idx].join(" | ");
+        let query = Query::compile_with_loader(&prefix, loader)?.with_inputs(inputs.to_vec());
+        let results = query.run_value(root.clone())?;
+
+        if results.is_empty() {
+            return Ok(Some(EmptyStage {
+                number: idx + 1,
+                source: stage.clone(),
+                received: received.len(),
+                sample: received.swap_remove(0),
+            }));
+        }
+        received = results;
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{explain_empty, split_pipeline};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_split_pipeline() {
+        // the pipes within the definitions, the brackets, the bindings
+        // and the interpolations are not split
+        assert_eq!(
+            split_pipeline(r#"def f: .a | .b; f | [.[] | .x] | . as $v | $v | "\(1 | .)""#)
+                .unwrap(),
+            [
+                "def f: .a | .b; f",
+                "[.[] | .x]",
+                ". as $v | $v",
+                r#""\(1 | .)""#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_explain_empty() {
+        let root = value(r#"{users: [{name: "a", age: 20}, {name: "b", age: 25}]}"#);
+        let stage = explain_empty(
+            ".users[] | select(.age > 30) | .name",
+            &root,
+            &[],
+            &Default::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(stage.number, 2);
+        assert_eq!(stage.source, "select(.age > 30)");
+        assert_eq!(stage.received, 2);
+        assert_eq!(stage.sample.to_string(), r#"{name:"a",age:20}"#);
+
+        assert!(
+            explain_empty(".users[0].name", &root, &[], &Default::default())
+                .unwrap()
+                .is_none()
+        );
+
+        let stage = explain_empty(".orders[]? | .id", &root, &[], &Default::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!((stage.number, stage.received), (1, 1));
+    }
+}
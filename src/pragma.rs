@@ -0,0 +1,135 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The pragmas of the query files and modules, e.g.
+//
+// `#aq-version "0.3"`
+//
+// they are the comment lines starting with `#aq-`, so they are ignored
+// by the lexer and checked before the source is parsed. The version
+// pragma requires a compatible language version:
+//
+// - before 1.0, the major and minor versions must be the same,
+// - otherwise, the major version must be the same, and the minor
+//   version of the language must not be less than the required one.
+
+use crate::error::Error;
+
+/// The version of the query language, i.e. the major and
+/// minor version of `aq`.
+pub fn language_version() -> (u64, u64) {
+    let mut parts = env!("CARGO_PKG_VERSION").split('.');
+    let mut next = || parts.next().and_then(|p| p.parse().ok()).unwrap_or(0);
+    (next(), next())
+}
+
+/// Check the pragmas of the source.
+pub fn check_pragmas(source: &str) -> Result<(), Error> {
+    for (idx, line) in source.lines().enumerate() {
+        let Some(pragma) = line.trim_start().strip_prefix("#aq-") else {
+            continue;
+        };
+
+        let (name, argument) = pragma
+            .split_once(char::is_whitespace)
+            .unwrap_or((pragma, ""));
+        match name {
+            "version" => check_version(argument.trim(), idx + 1)?,
+            _ => {
+                return Err(Error::Message(format!(
+                    "Unknown pragma \"#aq-{}\" at line {}.",
+                    name,
+                    idx + 1
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_version(argument: &str, line: usize) -> Result<(), Error> {
+    let text = argument
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(argument);
+
+    let required = text
+        .split_once('.')
+        .and_then(|(major, minor)| Some((major.parse::<u64>().ok()?, minor.parse::<u64>().ok()?)));
+    let Some((major, minor)) = required else {
+        return Err(Error::Message(format!(
+            "Invalid version \"{}\" of the pragma at line {}, expect e.g. \"0.3\".",
+            text, line
+        )));
+    };
+
+    let (current_major, current_minor) = language_version();
+    let compatible = if major == 0 {
+        current_major == 0 && current_minor == minor
+    } else {
+        current_major == major && current_minor >= minor
+    };
+
+    if compatible {
+        Ok(())
+    } else {
+        Err(Error::Message(format!(
+            "The query requires the language version {}.{}, which is incompatible with the version {}.{} of aq.",
+            major, minor, current_major, current_minor
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{check_pragmas, language_version};
+
+    fn error_of(source: &str) -> String {
+        check_pragmas(source).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_version_pragma() {
+        let (major, minor) = language_version();
+        assert_eq!(
+            check_pragmas(&format!("#aq-version \"{}.{}\"\n1", major, minor)),
+            Ok(())
+        );
+        assert_eq!(
+            check_pragmas(&format!("  #aq-version {}.{}\n1", major, minor)),
+            Ok(())
+        );
+
+        assert_eq!(
+            error_of(&format!("#aq-version \"{}.{}\"\n1", major + 1, minor)),
+            format!(
+                "The query requires the language version {}.{}, which is incompatible with the version {}.{} of aq.",
+                major + 1,
+                minor,
+                major,
+                minor
+            )
+        );
+        assert_eq!(
+            error_of("1\n#aq-version 0.x"),
+            "Invalid version \"0.x\" of the pragma at line 2, expect e.g. \"0.3\"."
+        );
+    }
+
+    #[test]
+    fn test_other_pragmas() {
+        assert_eq!(
+            error_of("#aq-frozen\n1"),
+            "Unknown pragma \"#aq-frozen\" at line 1."
+        );
+
+        // the ordinary comments are not pragmas
+        assert_eq!(check_pragmas("# aq-version 9.9\n1"), Ok(()));
+        assert_eq!(check_pragmas("1 # #aq-version 9.9"), Ok(()));
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Loading the inputs ahead of the evaluation, i.e. `aq --prefetch N`.
+//
+// The next inputs are read and parsed by the worker threads while the
+// current one is being evaluated, at most N inputs are loaded at the
+// same time. The inputs are produced in their original order, and the
+// threads which are still running are joined when the iterator is dropped.
+
+use std::{collections::VecDeque, sync::Arc, thread::JoinHandle};
+
+use crate::error::Error;
+
+pub struct Prefetch<S, T> {
+    sources: std::vec::IntoIter<S>,
+    load: Arc<dyn Fn(S) -> Result<T, Error> + Send + Sync>,
+    pending: VecDeque<JoinHandle<Result<T, Error>>>,
+    depth: usize,
+}
+
+/// Load the sources with at most `depth` of them loading at the same time.
+pub fn prefetch<S, T>(
+    sources: Vec<S>,
+    depth: usize,
+    load: impl Fn(S) -> Result<T, Error> + Send + Sync + 'static,
+) -> Prefetch<S, T>
+where
+    S: Send + 'static,
+    T: Send + 'static,
+{
+    Prefetch {
+        sources: sources.into_iter(),
+        load: Arc::new(load),
+        pending: VecDeque::new(),
+        depth: depth.max(1),
+    }
+}
+
+impl<S, T> Iterator for Prefetch<S, T>
+where
+    S: Send + 'static,
+    T: Send + 'static,
+{
+    type Item = Result<T, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.len() < self.depth {
+            let Some(source) = self.sources.next() else {
+                break;
+            };
+            let load = Arc::clone(&self.load);
+            self.pending
+                .push_back(std::thread::spawn(move || load(source)));
+        }
+
+        let handle = self.pending.pop_front()?;
+        Some(handle.join().unwrap_or_else(|_| {
+            Err(Error::Message(
+                "The thread which loads the input panicked.".to_owned(),
+            ))
+        }))
+    }
+}
+
+impl<S, T> Drop for Prefetch<S, T> {
+    fn drop(&mut self) {
+        for handle in self.pending.drain(..) {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use pretty_assertions::assert_eq;
+
+    use crate::error::Error;
+
+    use super::prefetch;
+
+    #[test]
+    fn test_prefetch_order() {
+        // the later inputs are loaded faster
+        let items: Vec<Result<u64, String>> = prefetch((0..20u64).collect(), 4, |i| {
+            std::thread::sleep(Duration::from_millis(20 - i));
+            if i == 7 {
+                Err(Error::Message("seven".to_owned()))
+            } else {
+                Ok(i * 2)
+            }
+        })
+        .map(|r| r.map_err(|e| e.to_string()))
+        .collect();
+
+        let expected: Vec<Result<u64, String>> = (0..20u64)
+            .map(|i| {
+                if i == 7 {
+                    Err("seven".to_owned())
+                } else {
+                    Ok(i * 2)
+                }
+            })
+            .collect();
+        assert_eq!(items, expected);
+    }
+
+    #[test]
+    fn test_prefetch_depth() {
+        let loading = Arc::new(AtomicUsize::new(0));
+        let max_loading = Arc::new(AtomicUsize::new(0));
+        let (current, max) = (Arc::clone(&loading), Arc::clone(&max_loading));
+        let count = prefetch((0..10).collect(), 3, move |i: i32| {
+            let now = current.fetch_add(1, Ordering::SeqCst) + 1;
+            max.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+            current.fetch_sub(1, Ordering::SeqCst);
+            Ok(i)
+        })
+        .count();
+
+        assert_eq!(count, 10);
+        assert!(max_loading.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn test_prefetch_panic() {
+        let items: Vec<String> = prefetch(vec![1], 2, |_: i32| -> Result<i32, Error> {
+            panic!("load")
+        })
+        .map(|r| r.unwrap_err().to_string())
+        .collect();
+        assert_eq!(items, ["The thread which loads the input panicked."]);
+    }
+}
@@ -0,0 +1,649 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Rewriting the ASON files in place without losing the comments and
+// the formatting, i.e. `aq -i --preserve QUERY FILE`.
+//
+// The source text is scanned for the spans of the values (a lossless
+// view of the text besides the parsed document), the result of the query
+// is compared with the original document, and only the spans of the
+// changed values are replaced, e.g. for `.server.port = 8080`:
+//
+// ```diff
+//   // the settings of the server
+//   server: {
+//       host: "localhost"  // the host name
+// -     port: 80
+// +     port: 8080
+//   }
+// ```
+//
+// The added entries are inserted before the closing bracket, the removed
+// entries are deleted with their lines, and a container is reprinted
+// as a whole if its entries are reordered or its type is changed.
+//
+// The rewritten text is parsed again, and it must be the result of
+// the query, otherwise the rewriting is refused.
+
+use std::ops::Range;
+
+use crate::{
+    error::Error,
+    input::{parse_input, ParseOptions},
+    printer::{print_value, PrintOptions},
+    value::{escape_string, is_identifier, Map, Value},
+};
+
+/// The text of the source with the changes from the original document to the updated one.
+pub fn preserve_edits(
+    source: &str,
+    updated: &Value,
+    options: &PrintOptions,
+) -> Result<String, Error> {
+    let original = parse_input(source, None, &ParseOptions::unlimited())?;
+    let root = Scanner::new(source).document().ok_or_else(|| {
+        Error::Message("The formatting of the document can not be preserved.".to_owned())
+    })?;
+
+    let mut options = options.clone();
+    options.indent = Some(indent_unit(source).unwrap_or_else(|| "    ".to_owned()));
+    options.multiline_strings = false;
+    let mut editor = Editor {
+        source,
+        options,
+        edits: vec![],
+    };
+    editor.diff(&root, &original, updated);
+
+    let mut text = source.to_owned();
+    editor
+        .edits
+        .sort_by_key(|(range, _)| std::cmp::Reverse(range.start));
+    for (range, replacement) in editor.edits {
+        text.replace_range(range, &replacement);
+    }
+
+    match parse_input(&text, None, &ParseOptions::unlimited()) {
+        Ok(v) if same(&v, updated) => Ok(text),
+        _ => Err(Error::Message(
+            "The changes can not be applied while preserving the formatting \
+            of the document."
+                .to_owned(),
+        )),
+    }
+}
+
+// the identical values, e.g. `1_u8` and `1` are equal in queries, but not identical.
+fn same(a: &Value, b: &Value) -> bool {
+    a == b && a.to_string() == b.to_string()
+}
+
+// the indentation of the first indented line.
+fn indent_unit(source: &str) -> Option<String> {
+    source.lines().find_map(|line| {
+        let content = line.trim_start_matches([' ', '\t']);
+        let indent = &line[..line.len() - content.len()];
+        (!indent.is_empty() && !content.is_empty()).then(|| indent.to_owned())
+    })
+}
+
+#[derive(Debug)]
+struct Node {
+    span: Range<usize>,
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Leaf,
+    List(Vec<Entry>),
+    Object(Vec<Entry>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// The key of the object entry.
+    key: Option<String>,
+
+    /// The start of the entry, i.e. the key (if any).
+    start: usize,
+    node: Node,
+
+    /// The position of the comma after the entry.
+    comma: Option<usize>,
+}
+
+struct Scanner<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn new(source: &'a str) -> Self {
+        Self {
+            text: source.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn document(&mut self) -> Option<Node> {
+        self.skip_trivia();
+        let node = self.value()?;
+        self.skip_trivia();
+        (self.pos == self.text.len()).then_some(node)
+    }
+
+    // the whitespaces and the comments, the block comments can be nested.
+    fn skip_trivia(&mut self) {
+        while let Some(c) = self.peek() {
+            match (c, self.text.get(self.pos + 1)) {
+                (b' ' | b'\t' | b'\r' | b'\n', _) => self.pos += 1,
+                (b'/', Some(b'/')) => {
+                    while self.peek().is_some_and(|c| c != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                (b'/', Some(b'*')) => {
+                    let mut depth = 0;
+                    while self.pos < self.text.len() {
+                        match (self.text[self.pos], self.text.get(self.pos + 1)) {
+                            (b'/', Some(b'*')) => {
+                                depth += 1;
+                                self.pos += 1;
+                            }
+                            (b'*', Some(b'/')) => {
+                                depth -= 1;
+                                self.pos += 1;
+                            }
+                            _ => {}
+                        }
+                        self.pos += 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn value(&mut self) -> Option<Node> {
+        let start = self.pos;
+        let kind = match self.peek()? {
+            b'[' => self.entries(b']', false).map(Kind::List)?,
+            b'{' => self.entries(b'}', true).map(Kind::Object)?,
+            b'(' => {
+                self.group()?;
+                Kind::Leaf
+            }
+            b'"' | b'\'' => {
+                self.quoted(false)?;
+                Kind::Leaf
+            }
+            _ => {
+                self.word()?;
+                Kind::Leaf
+            }
+        };
+        Some(Node {
+            span: start..self.pos,
+            kind,
+        })
+    }
+
+    fn entries(&mut self, close: u8, keyed: bool) -> Option<Vec<Entry>> {
+        self.pos += 1;
+        let mut entries = vec![];
+        loop {
+            self.skip_trivia();
+            if self.peek()? == close {
+                self.pos += 1;
+                return Some(entries);
+            }
+
+            let start = self.pos;
+            let key = if keyed {
+                let key = self.key()?;
+                self.skip_trivia();
+                if self.peek()? != b':' {
+                    return None;
+                }
+                self.pos += 1;
+                self.skip_trivia();
+                Some(key)
+            } else {
+                None
+            };
+            let node = self.value()?;
+            self.skip_trivia();
+            let comma = (self.peek()? == b',').then(|| {
+                self.pos += 1;
+                self.pos - 1
+            });
+            entries.push(Entry {
+                key,
+                start,
+                node,
+                comma,
+            });
+        }
+    }
+
+    fn key(&mut self) -> Option<String> {
+        let start = self.pos;
+        if self.peek()? == b'"' {
+            self.quoted(false)?;
+            let text = std::str::from_utf8(&self.text[start..self.pos]).ok()?;
+            return match parse_input(text, None, &ParseOptions::unlimited()) {
+                Ok(Value::String(key)) => Some(key),
+                _ => None,
+            };
+        }
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == b'_')
+        {
+            self.pos += 1;
+        }
+        (self.pos > start)
+            .then(|| String::from_utf8_lossy(&self.text[start..self.pos]).into_owned())
+    }
+
+    // the string, char, or the raw string (`raw`) from the opening quote.
+    fn quoted(&mut self, raw: bool) -> Option<()> {
+        let quote = self.peek()?;
+        self.pos += 1;
+        while let Some(c) = self.peek() {
+            self.pos += 1;
+            match c {
+                b'\\' if !raw => self.pos += 1,
+                _ if c == quote => return Some(()),
+                _ => {}
+            }
+        }
+        None
+    }
+
+    // the numbers, keywords, the prefixed strings (e.g. `h"..."`),
+    // and the variants with their payloads.
+    fn word(&mut self) -> Option<()> {
+        match (self.peek()?, self.text.get(self.pos + 1)) {
+            (b'r', Some(b'"')) => {
+                self.pos += 1;
+                return self.quoted(true);
+            }
+            (b'r', Some(b'#')) => {
+                // the closing `"#` after the opening `r#"`
+                let end = self.text[self.pos..]
+                    .windows(2)
+                    .skip(3)
+                    .position(|w| w == b"\"#")?;
+                self.pos += end + 5;
+                return Some(());
+            }
+            (b'h' | b'd', Some(b'"')) => {
+                self.pos += 1;
+                return self.quoted(false);
+            }
+            _ => {}
+        }
+
+        let start = self.pos;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || b"_.+-:".contains(&c))
+        {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return None;
+        }
+        match self.peek() {
+            Some(b'(') => self.group(),
+            Some(b'{') => self.entries(b'}', true).map(|_| ()),
+            _ => Some(()),
+        }
+    }
+
+    // the balanced brackets, e.g. a tuple.
+    fn group(&mut self) -> Option<()> {
+        let close = match self.peek()? {
+            b'(' => b')',
+            b'[' => b']',
+            _ => b'}',
+        };
+        self.pos += 1;
+        loop {
+            self.skip_trivia();
+            match self.peek()? {
+                c if c == close => {
+                    self.pos += 1;
+                    return Some(());
+                }
+                b'(' | b'[' | b'{' => self.group()?,
+                b'"' | b'\'' => self.quoted(false)?,
+                b',' | b':' => self.pos += 1,
+                _ => self.word()?,
+            }
+        }
+    }
+}
+
+struct Editor<'a> {
+    source: &'a str,
+    options: PrintOptions,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl Editor<'_> {
+    fn diff(&mut self, node: &Node, old: &Value, new: &Value) {
+        if same(old, new) {
+            return;
+        }
+        let patched = match (&node.kind, old, new) {
+            (Kind::Object(entries), Value::Object(old), Value::Object(new)) => {
+                self.diff_object(node, entries, old, new)
+            }
+            (Kind::List(entries), Value::List(old), Value::List(new)) => {
+                self.diff_list(node, entries, old, new)
+            }
+            _ => false,
+        };
+        if !patched {
+            let text = self.print(new, node.span.start);
+            self.edits.push((node.span.clone(), text));
+        }
+    }
+
+    // returns false if the object should be reprinted as a whole.
+    fn diff_object(&mut self, node: &Node, entries: &[Entry], old: &Map, new: &Map) -> bool {
+        let keys: Vec<&String> = entries.iter().filter_map(|e| e.key.as_ref()).collect();
+        if keys.len() != old.len() {
+            return false;
+        }
+
+        // the values can be replaced in place, while the entries
+        // are added or removed only in the multi-line objects.
+        let values_only = keys.len() == new.len() && keys.iter().all(|k| new.contains_key(k));
+        if !values_only && !self.multiline(node) {
+            return false;
+        }
+
+        // the kept entries must be in the same order.
+        let kept: Vec<&String> = keys
+            .iter()
+            .copied()
+            .filter(|k| new.contains_key(k))
+            .collect();
+        let ordered: Vec<&String> = new.keys().filter(|k| old.contains_key(k)).collect();
+        if kept != ordered {
+            return false;
+        }
+
+        for entry in entries {
+            let key = entry.key.as_ref().unwrap();
+            match new.get(key) {
+                Some(value) => self.diff(&entry.node, old.get(key).unwrap(), value),
+                None => self.remove(entry),
+            }
+        }
+
+        let added: Vec<String> = new
+            .iter()
+            .filter(|(k, _)| !old.contains_key(k))
+            .map(|(k, v)| {
+                let key = if is_identifier(k) {
+                    k.clone()
+                } else {
+                    escape_string(k, '"')
+                };
+                format!("{}: ", key) + &self.print_at_entry(v, node, entries)
+            })
+            .collect();
+        let last = entries
+            .iter()
+            .rev()
+            .find(|e| new.contains_key(e.key.as_ref().unwrap()));
+        self.append(node, entries, last, added);
+        true
+    }
+
+    // returns false if the list should be reprinted as a whole.
+    fn diff_list(&mut self, node: &Node, entries: &[Entry], old: &[Value], new: &[Value]) -> bool {
+        if entries.len() != old.len() {
+            return false;
+        }
+
+        if old.len() == new.len() {
+            for ((entry, a), b) in entries.iter().zip(old).zip(new) {
+                self.diff(&entry.node, a, b);
+            }
+            return true;
+        }
+
+        if !self.multiline(node) {
+            return false;
+        }
+
+        // the elements are appended, or some of them are removed.
+        if new.len() > old.len() && old.iter().zip(new).all(|(a, b)| same(a, b)) {
+            let added = new[old.len()..]
+                .iter()
+                .map(|v| self.print_at_entry(v, node, entries))
+                .collect();
+            self.append(node, entries, entries.last(), added);
+            return true;
+        }
+
+        let mut kept = new.iter().peekable();
+        let mut removed = vec![];
+        for (entry, value) in entries.iter().zip(old) {
+            match kept.peek() {
+                Some(v) if same(v, value) => {
+                    kept.next();
+                }
+                _ => removed.push(entry),
+            }
+        }
+        if kept.next().is_some() {
+            return false;
+        }
+        for entry in removed {
+            self.remove(entry);
+        }
+        true
+    }
+
+    fn multiline(&self, node: &Node) -> bool {
+        self.source[node.span.clone()].contains('\n')
+    }
+
+    // the start of the line of the position if there are only whitespaces before it.
+    fn line_start(&self, pos: usize) -> Option<usize> {
+        let start = self.source[..pos].rfind('\n').map_or(0, |i| i + 1);
+        self.source[start..pos]
+            .chars()
+            .all(|c| c == ' ' || c == '\t')
+            .then_some(start)
+    }
+
+    fn line_indent(&self, pos: usize) -> &str {
+        let start = self.source[..pos].rfind('\n').map_or(0, |i| i + 1);
+        let line = &self.source[start..];
+        &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+    }
+
+    // remove the entry, and its line (including the comment
+    // after it) if the entry is in its own line.
+    fn remove(&mut self, entry: &Entry) {
+        let end = entry.comma.map_or(entry.node.span.end, |c| c + 1);
+        let range = match self.line_start(entry.start) {
+            Some(start) => {
+                let rest = &self.source[end..];
+                let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+                let tail = rest[..line_end].trim();
+                if tail.is_empty() || tail.starts_with("//") {
+                    start..end + line_end
+                } else {
+                    entry.start..end
+                }
+            }
+            None => entry.start..end,
+        };
+        self.edits.push((range, String::new()));
+    }
+
+    // insert the entries in their own lines before the closing bracket,
+    // the `last` is the last entry which is kept.
+    fn append(&mut self, node: &Node, entries: &[Entry], last: Option<&Entry>, added: Vec<String>) {
+        if added.is_empty() {
+            return;
+        }
+        let close = node.span.end - 1;
+        let indent = self.entry_indent(node, entries);
+
+        // the entries are separated by commas if the existing ones are.
+        let commas = entries.iter().any(|e| e.comma.is_some());
+        if let Some(last) = last.filter(|e| commas && e.comma.is_none()) {
+            self.edits
+                .push((last.node.span.end..last.node.span.end, ",".to_owned()));
+        }
+        // and there is a trailing comma if the original last entry has one.
+        let trailing = entries.last().is_some_and(|e| e.comma.is_some());
+
+        let mut text = String::new();
+        let count = added.len();
+        for (idx, entry) in added.into_iter().enumerate() {
+            let comma = commas && (idx + 1 < count || trailing);
+            text.push_str(&format!(
+                "{}{}{}\n",
+                indent,
+                entry,
+                if comma { "," } else { "" }
+            ));
+        }
+        match self.line_start(close) {
+            Some(start) => self.edits.push((start..start, text)),
+            None => self.edits.push((close..close, format!("\n{}", text))),
+        }
+    }
+
+    fn entry_indent(&self, node: &Node, entries: &[Entry]) -> String {
+        match entries.first() {
+            Some(e) if self.line_start(e.start).is_some() => self.line_indent(e.start).to_owned(),
+            _ => format!(
+                "{}{}",
+                self.line_indent(node.span.start),
+                self.options.indent.as_deref().unwrap_or_default()
+            ),
+        }
+    }
+
+    fn print_at_entry(&self, value: &Value, node: &Node, entries: &[Entry]) -> String {
+        self.print_indented(value, &self.entry_indent(node, entries))
+    }
+
+    // print the value at the position, i.e. the following lines
+    // are indented as the line of the position.
+    fn print(&self, value: &Value, pos: usize) -> String {
+        self.print_indented(value, self.line_indent(pos))
+    }
+
+    fn print_indented(&self, value: &Value, indent: &str) -> String {
+        print_value(value, &self.options).replace('\n', &format!("\n{}", indent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        error::Error,
+        input::{parse_input, ParseOptions},
+        printer::PrintOptions,
+        query::Query,
+    };
+
+    use super::preserve_edits;
+
+    const SETTINGS: &str = "// the settings
+{
+  server: {
+    host: \"localhost\"  // the host
+    port: 80
+  }
+  // the users
+  users: [
+    \"alice\"
+    \"bob\"
+  ]
+  debug: false // off
+}
+";
+
+    fn edit(source: &str, query: &str) -> Result<String, Error> {
+        let document = parse_input(source, None, &ParseOptions::unlimited()).unwrap();
+        let edited = Query::compile(query)
+            .unwrap()
+            .run_value(document)
+            .unwrap()
+            .remove(0);
+        preserve_edits(source, &edited, &PrintOptions::pretty("    "))
+    }
+
+    #[test]
+    fn test_preserve_edits() {
+        assert_eq!(
+            edit(SETTINGS, ".server.port = 8080").unwrap(),
+            SETTINGS.replace("port: 80", "port: 8080")
+        );
+        assert_eq!(
+            edit(SETTINGS, "del(.debug)").unwrap(),
+            SETTINGS.replace("  debug: false // off\n", "")
+        );
+        assert_eq!(
+            edit(SETTINGS, ".users += [\"carol\"]").unwrap(),
+            SETTINGS.replace("    \"bob\"\n", "    \"bob\"\n    \"carol\"\n")
+        );
+        assert_eq!(
+            edit(SETTINGS, ".users -= [\"alice\"]").unwrap(),
+            SETTINGS.replace("    \"alice\"\n", "")
+        );
+
+        // the new entry follows the indentation of its siblings
+        assert_eq!(
+            edit(SETTINGS, ".server.tls = {cert: \"a.pem\"}").unwrap(),
+            SETTINGS.replace(
+                "    port: 80\n",
+                "    port: 80\n    tls: {\n      cert: \"a.pem\"\n    }\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_separators() {
+        let commas = "{\n    a: 1,\n    b: 2\n}";
+        assert_eq!(
+            edit(commas, ".c = 3").unwrap(),
+            "{\n    a: 1,\n    b: 2,\n    c: 3\n}"
+        );
+        assert_eq!(
+            edit(commas, "del(.b) | .c = 3").unwrap(),
+            "{\n    a: 1,\n    c: 3\n}"
+        );
+        assert_eq!(edit("{a: 1, b: 2}", ".a = 5").unwrap(), "{a: 5, b: 2}");
+        assert_eq!(
+            edit("{\n  a: r\"x\", b: [1]\n}", ".b[0] = 2").unwrap(),
+            "{\n  a: r\"x\", b: [2]\n}"
+        );
+    }
+}
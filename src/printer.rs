@@ -0,0 +1,525 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Printing values as ASON text with the layout controls.
+//
+// The compact layout is the same as the `Display` of `Value`, e.g. `{a:1,b:[1,2]}`,
+// the pretty layout puts each item of the lists, tuples and objects in its own line:
+//
+// ```
+// {
+//     a: 1
+//     b: [
+//         1
+//         2
+//     ]
+// }
+// ```
+//
+// and with a line width, the lists, tuples and objects which fit in
+// the rest of the line are kept in one line, e.g. `b: [1, 2]`.
+//
+// The strings which contain line breaks can be written as the raw
+// strings in the pretty layout, e.g. `r"first line
+// second line"`, instead of the `\n` escapes.
+//
+// The items in their own lines can be followed by the trailing commas,
+// and the integers can be written with the digit separators (e.g. `1_000_000`)
+// or in hexadecimal (e.g. `0xff`), these are the styles of `aq fmt`.
+
+use crate::{
+    error::Error,
+    theme::{Style, Theme},
+    value::{escape_string, is_identifier, Map, Number, Value, VariantValue},
+};
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PrintOptions {
+    /// The indentation of the nested items, the compact layout
+    /// is used if it is `None`.
+    pub indent: Option<String>,
+
+    /// The colors of the tokens, the text is not colored if it is `None`.
+    pub theme: Option<Theme>,
+
+    /// The maximum width of the lines in the pretty layout, each item is
+    /// put in its own line if it is `None`.
+    pub width: Option<usize>,
+
+    /// Write the multi-line strings as the raw strings in the pretty layout.
+    pub multiline_strings: bool,
+
+    /// Write a comma after each item which is in its own line.
+    pub trailing_commas: bool,
+
+    pub number_style: NumberStyle,
+}
+
+/// The style of the integer literals, the floating-point
+/// numbers are always written in decimal.
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum NumberStyle {
+    #[default]
+    Plain,
+
+    /// The digits are grouped by `_` if there are more than 4 digits, e.g. `12_345`.
+    Grouped,
+
+    /// The non-negative integers are written in hexadecimal, e.g. `0xff_u8`.
+    Hex,
+}
+
+impl NumberStyle {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "plain" => Ok(NumberStyle::Plain),
+            "grouped" => Ok(NumberStyle::Grouped),
+            "hex" => Ok(NumberStyle::Hex),
+            _ => Err(Error::Message(format!(
+                "Number style \"{}\" is not supported, the available styles are: \
+                plain, grouped and hex.",
+                name
+            ))),
+        }
+    }
+}
+
+impl PrintOptions {
+    pub fn compact() -> Self {
+        Self {
+            indent: None,
+            theme: None,
+            width: None,
+            multiline_strings: false,
+            trailing_commas: false,
+            number_style: NumberStyle::Plain,
+        }
+    }
+
+    pub fn pretty(indent: &str) -> Self {
+        Self {
+            indent: Some(indent.to_owned()),
+            theme: None,
+            width: None,
+            multiline_strings: false,
+            trailing_commas: false,
+            number_style: NumberStyle::Plain,
+        }
+    }
+
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+
+    pub fn with_width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    pub fn with_multiline_strings(mut self) -> Self {
+        self.multiline_strings = true;
+        self
+    }
+
+    pub fn with_trailing_commas(mut self) -> Self {
+        self.trailing_commas = true;
+        self
+    }
+
+    pub fn with_number_style(mut self, style: NumberStyle) -> Self {
+        self.number_style = style;
+        self
+    }
+}
+
+pub fn print_value(value: &Value, options: &PrintOptions) -> String {
+    print_value_at(value, options, 0)
+}
+
+/// Print the value which is nested in the level of indentation,
+/// e.g. an item written by the `AsonEmitter`.
+pub fn print_value_at(value: &Value, options: &PrintOptions, level: usize) -> String {
+    if options.indent.is_none()
+        && options.theme.is_none()
+        && options.number_style == NumberStyle::Plain
+    {
+        return value.to_string();
+    }
+
+    let plain = Theme::default();
+    let mut printer = Printer {
+        indent: options.indent.as_deref(),
+        inline: false,
+        width: options.width,
+        multiline_strings: options.multiline_strings && options.indent.is_some(),
+        trailing_commas: options.trailing_commas,
+        number_style: options.number_style,
+        theme: options.theme.as_ref().unwrap_or(&plain),
+        buf: String::new(),
+    };
+    printer.print(value, level);
+    printer.buf
+}
+
+struct Printer<'a> {
+    indent: Option<&'a str>,
+
+    // the items are separated by `, ` in one line, it is
+    // the layout of the short items in the pretty layout.
+    inline: bool,
+    width: Option<usize>,
+    multiline_strings: bool,
+    trailing_commas: bool,
+    number_style: NumberStyle,
+    theme: &'a Theme,
+    buf: String,
+}
+
+impl Printer<'_> {
+    fn paint(&mut self, style: &Style, text: &str) {
+        self.buf.push_str(&style.paint(text));
+    }
+
+    fn punctuation(&mut self, text: &str) {
+        let theme = self.theme;
+        self.paint(&theme.punctuation, text);
+    }
+
+    // print the container in one line if it fits in the rest of the line.
+    fn print_inline(&mut self, print: impl Fn(&mut Printer)) -> bool {
+        let Some(width) = self.width.filter(|_| self.indent.is_some()) else {
+            return false;
+        };
+
+        let plain = Theme::default();
+        let mut measure = Printer {
+            indent: None,
+            inline: true,
+            width: None,
+            multiline_strings: self.multiline_strings,
+            trailing_commas: false,
+            number_style: self.number_style,
+            theme: &plain,
+            buf: String::new(),
+        };
+        print(&mut measure);
+
+        // the raw multi-line strings can not be in one line.
+        let column = visible_width(self.buf.rsplit('\n').next().unwrap_or_default());
+        if measure.buf.contains('\n') || column + measure.buf.chars().count() > width {
+            return false;
+        }
+
+        let mut printer = Printer {
+            indent: None,
+            inline: true,
+            width: None,
+            multiline_strings: self.multiline_strings,
+            trailing_commas: false,
+            number_style: self.number_style,
+            theme: self.theme,
+            buf: String::new(),
+        };
+        print(&mut printer);
+        self.buf.push_str(&printer.buf);
+        true
+    }
+
+    // the line break in the pretty layout, or the
+    // separator in the compact layout.
+    fn separate(&mut self, level: usize, first: bool) {
+        match self.indent {
+            None if self.inline && !first => {
+                self.punctuation(",");
+                self.buf.push(' ');
+            }
+            Some(indent) => {
+                self.buf.push('\n');
+                for _ in 0..level {
+                    self.buf.push_str(indent);
+                }
+            }
+            None if !first => self.punctuation(","),
+            None => {}
+        }
+    }
+
+    fn trailing_comma(&mut self) {
+        if self.trailing_commas && self.indent.is_some() {
+            self.punctuation(",");
+        }
+    }
+
+    fn print(&mut self, value: &Value, level: usize) {
+        let theme = self.theme;
+        match value {
+            Value::List(items) => self.print_items(items, "[", "]", level),
+            Value::Tuple(items) => self.print_items(items, "(", ")", level),
+            Value::Object(map) => self.print_map(map, level),
+            Value::Variant(_) if value.is_null() => self.paint(&theme.null, &value.to_string()),
+            Value::Variant(v) => {
+                self.paint(
+                    &theme.variant,
+                    &format!("{}::{}", v.type_name, v.member_name),
+                );
+                match &v.value {
+                    VariantValue::Empty => {}
+                    VariantValue::Value(inner) => {
+                        self.punctuation("(");
+                        self.print(inner, level);
+                        self.punctuation(")");
+                    }
+                    VariantValue::Tuple(items) => self.print_items(items, "(", ")", level),
+                    VariantValue::Object(map) => self.print_map(map, level),
+                }
+            }
+            Value::Number(n) => self.paint(&theme.number, &number_text(n, self.number_style)),
+            Value::Boolean(_) => self.paint(&theme.boolean, &value.to_string()),
+            Value::Char(_) => self.paint(&theme.char, &value.to_string()),
+            Value::String(text) if self.multiline_strings && text.contains('\n') => {
+                let text = raw_string(text).unwrap_or_else(|| value.to_string());
+                self.paint(&theme.string, &text)
+            }
+            Value::String(_) => self.paint(&theme.string, &value.to_string()),
+            Value::DateTime(_) => self.paint(&theme.datetime, &value.to_string()),
+            Value::ByteData(_) => self.paint(&theme.bytes, &value.to_string()),
+        }
+    }
+
+    fn print_items(&mut self, items: &[Value], open: &str, close: &str, level: usize) {
+        if !items.is_empty() && self.print_inline(|p| p.print_items(items, open, close, level)) {
+            return;
+        }
+
+        self.punctuation(open);
+        if !items.is_empty() {
+            for (idx, item) in items.iter().enumerate() {
+                self.separate(level + 1, idx == 0);
+                self.print(item, level + 1);
+                self.trailing_comma();
+            }
+            if self.indent.is_some() {
+                self.separate(level, false);
+            }
+        }
+        self.punctuation(close);
+    }
+
+    fn print_map(&mut self, map: &Map, level: usize) {
+        if !map.is_empty() && self.print_inline(|p| p.print_map(map, level)) {
+            return;
+        }
+
+        let theme = self.theme;
+        self.punctuation("{");
+        if !map.is_empty() {
+            for (idx, (key, item)) in map.iter().enumerate() {
+                self.separate(level + 1, idx == 0);
+                if is_identifier(key) {
+                    self.paint(&theme.key, key);
+                } else {
+                    self.paint(&theme.key, &escape_string(key, '"'));
+                }
+                self.punctuation(":");
+                if self.indent.is_some() || self.inline {
+                    self.buf.push(' ');
+                }
+                self.print(item, level + 1);
+                self.trailing_comma();
+            }
+            if self.indent.is_some() {
+                self.separate(level, false);
+            }
+        }
+        self.punctuation("}");
+    }
+}
+
+fn number_text(number: &Number, style: NumberStyle) -> String {
+    let text = number.to_string();
+    let Some(integer) = number.as_i128() else {
+        return text;
+    };
+    match style {
+        NumberStyle::Plain => text,
+        NumberStyle::Grouped => {
+            let (digits, suffix) = text.split_at(text.find('_').unwrap_or(text.len()));
+            let (sign, digits) = digits.split_at(usize::from(digits.starts_with('-')));
+            if digits.len() <= 4 {
+                return text;
+            }
+            let mut grouped = String::from(sign);
+            for (idx, c) in digits.chars().enumerate() {
+                if idx > 0 && (digits.len() - idx) % 3 == 0 {
+                    grouped.push('_');
+                }
+                grouped.push(c);
+            }
+            grouped.push_str(suffix);
+            grouped
+        }
+        NumberStyle::Hex if integer >= 0 => match number {
+            Number::I32(_) => format!("0x{:x}", integer),
+            _ => format!("0x{:x}_{}", integer, number.type_suffix()),
+        },
+        NumberStyle::Hex => text,
+    }
+}
+
+// the raw string `r"..."`, or `r#"..."#` if the text contains `"`, the text
+// which contains the control characters (except the line break and tab)
+// or `"#` can not be written as a raw string.
+fn raw_string(text: &str) -> Option<String> {
+    if text
+        .chars()
+        .any(|c| c.is_control() && c != '\n' && c != '\t')
+    {
+        None
+    } else if !text.contains('"') {
+        Some(format!("r\"{}\"", text))
+    } else if !text.contains("\"#") {
+        Some(format!("r#\"{}\"#", text))
+    } else {
+        None
+    }
+}
+
+// the width of the text without the ANSI escape codes of the theme.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match c {
+            '\x1b' => in_escape = true,
+            'm' if in_escape => in_escape = false,
+            _ if in_escape => {}
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        theme::Theme,
+        value::Value,
+    };
+
+    use super::{print_value, NumberStyle, PrintOptions};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_width_layout() {
+        let v = value(
+            "{a: [1, 2, 3], long: [\"aaaaaaaaaa\", \"bbbbbbbbbb\", \"cccccccccc\"], o: {x: 1}, e: []}",
+        );
+        assert_eq!(
+            print_value(&v, &PrintOptions::pretty("    ").with_width(30)),
+            "{
+    a: [1, 2, 3]
+    long: [
+        \"aaaaaaaaaa\"
+        \"bbbbbbbbbb\"
+        \"cccccccccc\"
+    ]
+    o: {x: 1}
+    e: []
+}"
+        );
+        assert_eq!(
+            print_value(&value("[1, 2]"), &PrintOptions::pretty("  ").with_width(80)),
+            "[1, 2]"
+        );
+
+        // the escape codes of the theme do not count
+        let theme = Theme::from_value(&value("{number: \"red\"}")).unwrap();
+        assert_eq!(
+            print_value(
+                &value("[1, 2]"),
+                &PrintOptions::pretty("  ").with_width(6).with_theme(theme)
+            ),
+            "[\x1b[31m1\x1b[0m, \x1b[31m2\x1b[0m]"
+        );
+
+        // the width does not apply to the compact layout
+        assert_eq!(
+            print_value(&v, &PrintOptions::compact().with_width(10)),
+            v.to_string()
+        );
+    }
+
+    #[test]
+    fn test_multiline_strings() {
+        let options = PrintOptions::pretty("  ")
+            .with_multiline_strings()
+            .with_width(80);
+        let print = |s: &str| {
+            let v = Value::List(vec![Value::String(s.to_owned()), Value::from(1)].into());
+            print_value(&v, &options)
+        };
+
+        // the multi-line strings are not kept in one line
+        assert_eq!(print("a\nb"), "[\n  r\"a\nb\"\n  1\n]");
+        assert_eq!(print("a\n\"b\""), "[\n  r#\"a\n\"b\"\"#\n  1\n]");
+
+        // the carriage return can not be written literally
+        assert_eq!(print("a\r\nb"), "[\"a\\r\\nb\", 1]");
+        assert_eq!(print("ab"), "[\"ab\", 1]");
+
+        // the compact layout always escapes the line breaks
+        assert_eq!(
+            print_value(
+                &Value::String("a\nb".to_owned()),
+                &PrintOptions::compact().with_multiline_strings()
+            ),
+            "\"a\\nb\""
+        );
+    }
+
+    #[test]
+    fn test_number_styles() {
+        let v = value("{a: [1, 2], n: 1234567, m: -12345, s: 255_u8, f: 12345.5}");
+        assert_eq!(
+            print_value(
+                &v,
+                &PrintOptions::pretty("  ")
+                    .with_trailing_commas()
+                    .with_number_style(NumberStyle::Grouped)
+            ),
+            "{\n  a: [\n    1,\n    2,\n  ],\n  n: 1_234_567,\n  m: -12_345,\n  s: 255_u8,\n  f: 12345.5,\n}"
+        );
+
+        // no trailing commas in one line, and the negative numbers are decimal
+        assert_eq!(
+            print_value(
+                &v,
+                &PrintOptions::pretty("  ")
+                    .with_width(80)
+                    .with_trailing_commas()
+                    .with_number_style(NumberStyle::Hex)
+            ),
+            "{a: [0x1, 0x2], n: 0x12d687, m: -12345, s: 0xff_u8, f: 12345.5}"
+        );
+        assert_eq!(
+            print_value(
+                &v,
+                &PrintOptions::compact().with_number_style(NumberStyle::Grouped)
+            ),
+            "{a:[1,2],n:1_234_567,m:-12_345,s:255_u8,f:12345.5}"
+        );
+
+        assert_eq!(NumberStyle::from_name("hex").unwrap(), NumberStyle::Hex);
+        assert!(NumberStyle::from_name("octal").is_err());
+    }
+}
@@ -0,0 +1,566 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The compiled programs, i.e. the syntax trees of a query, the modules it
+// imports and the prelude, which are saved (e.g. by `--compile-cache`) and
+// loaded without parsing the query text again.
+//
+// The program is a MessagePack document, each node of the syntax tree is
+// a list with the name of the node first, e.g. `.a | f` is
+//
+// `["Pipe", ["Index", ["Identity"], ["Literal", "\"a\""]], ["Call", "f", []]]`
+//
+// the literals are kept in the ASON text, since the MessagePack
+// does not keep some types of the values, e.g. chars and tuples.
+
+use std::path::PathBuf;
+
+use crate::{
+    ast::{
+        AssignOperator, BinaryOperator, Expression, FunctionDefinition, InterpolationPart, Pattern,
+        VariantPayload,
+    },
+    error::Error,
+    input::{parse_input, ParseOptions},
+    module::Module,
+    msgpack::{decode_msgpack, encode_msgpack},
+    printer::{print_value, PrintOptions},
+    value::{Items, Value},
+};
+
+// the version of the structure of the programs.
+const PROGRAM_VERSION: i32 = 1;
+
+/// The parts of a compiled query.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    pub prelude: Vec<FunctionDefinition>,
+    pub modules: Vec<Module>,
+    pub expression: Expression,
+}
+
+pub fn encode_program(program: &Program) -> Vec<u8> {
+    let value = node(
+        "Program",
+        vec![
+            Value::from(PROGRAM_VERSION),
+            list(program.prelude.iter().map(encode_definition)),
+            list(program.modules.iter().map(encode_module)),
+            encode_expression(&program.expression),
+        ],
+    );
+    encode_msgpack(&value)
+}
+
+pub fn decode_program(bytes: &[u8]) -> Result<Program, Error> {
+    let values = decode_msgpack(bytes).map_err(|_| damaged())?;
+    let [value] = values.as_slice() else {
+        return Err(damaged());
+    };
+    let [version, prelude, modules, expression] = fields(value, "Program")? else {
+        return Err(damaged());
+    };
+    if !matches!(version, Value::Number(n) if n.as_i128() == Some(PROGRAM_VERSION as i128)) {
+        return Err(Error::Message(
+            "The compiled program is made by another version of aq.".to_owned(),
+        ));
+    }
+
+    Ok(Program {
+        prelude: items(prelude)?
+            .iter()
+            .map(decode_definition)
+            .collect::<Result<_, _>>()?,
+        modules: items(modules)?
+            .iter()
+            .map(decode_module)
+            .collect::<Result<_, _>>()?,
+        expression: decode_expression(expression)?,
+    })
+}
+
+fn damaged() -> Error {
+    Error::Message("The compiled program is damaged.".to_owned())
+}
+
+fn node(name: &str, mut fields: Vec<Value>) -> Value {
+    fields.insert(0, Value::String(name.to_owned()));
+    Value::List(Items::from(fields))
+}
+
+fn list(values: impl Iterator<Item = Value>) -> Value {
+    Value::List(values.collect())
+}
+
+fn string(s: &str) -> Value {
+    Value::String(s.to_owned())
+}
+
+fn optional(expression: &Option<Box<Expression>>) -> Value {
+    expression
+        .as_deref()
+        .map_or_else(Value::null, encode_expression)
+}
+
+fn encode_definition(definition: &FunctionDefinition) -> Value {
+    list(
+        [
+            string(&definition.name),
+            list(definition.params.iter().map(|p| string(p))),
+            encode_expression(&definition.body),
+        ]
+        .into_iter(),
+    )
+}
+
+fn encode_module(module: &Module) -> Value {
+    list(
+        [
+            string(&module.alias),
+            string(&module.path.to_string_lossy()),
+            list(module.imports.iter().map(encode_module)),
+            list(module.definitions.iter().map(encode_definition)),
+        ]
+        .into_iter(),
+    )
+}
+
+fn encode_pattern(pattern: &Pattern) -> Value {
+    match pattern {
+        Pattern::Variable(name) => string(name),
+        Pattern::Elements(patterns) => list(patterns.iter().map(encode_pattern)),
+    }
+}
+
+fn encode_parts(parts: &[InterpolationPart]) -> Value {
+    list(parts.iter().map(|part| match part {
+        InterpolationPart::Literal(s) => string(s),
+        InterpolationPart::Expression(e) => encode_expression(e),
+    }))
+}
+
+fn binary_operator_name(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "Add",
+        BinaryOperator::Subtract => "Subtract",
+        BinaryOperator::Multiply => "Multiply",
+        BinaryOperator::Divide => "Divide",
+        BinaryOperator::Remainder => "Remainder",
+        BinaryOperator::Equal => "Equal",
+        BinaryOperator::NotEqual => "NotEqual",
+        BinaryOperator::Less => "Less",
+        BinaryOperator::LessOrEqual => "LessOrEqual",
+        BinaryOperator::Greater => "Greater",
+        BinaryOperator::GreaterOrEqual => "GreaterOrEqual",
+    }
+}
+
+fn assign_operator_name(operator: AssignOperator) -> &'static str {
+    match operator {
+        AssignOperator::Set => "Set",
+        AssignOperator::Update => "Update",
+        AssignOperator::Arithmetic(op) => binary_operator_name(op),
+        AssignOperator::Alternative => "Alternative",
+    }
+}
+
+fn encode_expression(expression: &Expression) -> Value {
+    let e = |expression: &Expression| encode_expression(expression);
+    match expression {
+        Expression::Identity => node("Identity", vec![]),
+        Expression::RecurseDefault => node("RecurseDefault", vec![]),
+        Expression::Literal(value) => node(
+            "Literal",
+            vec![Value::String(print_value(value, &PrintOptions::compact()))],
+        ),
+        Expression::Interpolation(parts) => node("Interpolation", vec![encode_parts(parts)]),
+        Expression::Format {
+            name,
+            argument,
+            template,
+        } => node(
+            "Format",
+            vec![
+                string(name),
+                optional(argument),
+                template.as_deref().map_or_else(Value::null, encode_parts),
+            ],
+        ),
+        Expression::Index(target, index) => node("Index", vec![e(target), e(index)]),
+        Expression::Slice(target, from, to) => {
+            node("Slice", vec![e(target), optional(from), optional(to)])
+        }
+        Expression::Iterate(target) => node("Iterate", vec![e(target)]),
+        Expression::Try(body, handler) => node("Try", vec![e(body), optional(handler)]),
+        Expression::Pipe(left, right) => node("Pipe", vec![e(left), e(right)]),
+        Expression::Comma(left, right) => node("Comma", vec![e(left), e(right)]),
+        Expression::Negate(operand) => node("Negate", vec![e(operand)]),
+        Expression::Binary(operator, left, right) => node(
+            "Binary",
+            vec![string(binary_operator_name(*operator)), e(left), e(right)],
+        ),
+        Expression::And(left, right) => node("And", vec![e(left), e(right)]),
+        Expression::Or(left, right) => node("Or", vec![e(left), e(right)]),
+        Expression::Alternative(left, right) => node("Alternative", vec![e(left), e(right)]),
+        Expression::Assign(operator, path, value) => node(
+            "Assign",
+            vec![string(assign_operator_name(*operator)), e(path), e(value)],
+        ),
+        Expression::List(inner) => node("List", vec![optional(inner)]),
+        Expression::Object(entries) => node(
+            "Object",
+            vec![list(
+                entries
+                    .iter()
+                    .map(|(key, value)| list([e(key), e(value)].into_iter())),
+            )],
+        ),
+        Expression::Variant {
+            type_name,
+            member_name,
+            payload,
+        } => {
+            let payload = match payload {
+                VariantPayload::Empty => Value::null(),
+                VariantPayload::Value(value) => node("Value", vec![e(value)]),
+                VariantPayload::Tuple(items) => node("Tuple", vec![list(items.iter().map(e))]),
+                VariantPayload::Object(object) => node("Object", vec![e(object)]),
+            };
+            node(
+                "Variant",
+                vec![string(type_name), string(member_name), payload],
+            )
+        }
+        Expression::If(branches, otherwise) => node(
+            "If",
+            vec![
+                list(
+                    branches
+                        .iter()
+                        .map(|(cond, body)| list([e(cond), e(body)].into_iter())),
+                ),
+                optional(otherwise),
+            ],
+        ),
+        Expression::Reduce {
+            source,
+            pattern,
+            init,
+            update,
+        } => node(
+            "Reduce",
+            vec![e(source), encode_pattern(pattern), e(init), e(update)],
+        ),
+        Expression::Foreach {
+            source,
+            pattern,
+            init,
+            update,
+            extract,
+        } => node(
+            "Foreach",
+            vec![
+                e(source),
+                encode_pattern(pattern),
+                e(init),
+                e(update),
+                optional(extract),
+            ],
+        ),
+        Expression::Binding {
+            source,
+            pattern,
+            body,
+        } => node("Binding", vec![e(source), encode_pattern(pattern), e(body)]),
+        Expression::FunctionDefinition(definition, rest) => node(
+            "FunctionDefinition",
+            vec![encode_definition(definition), e(rest)],
+        ),
+        Expression::Call(name, args) => node("Call", vec![string(name), list(args.iter().map(e))]),
+        Expression::Variable(name) => node("Variable", vec![string(name)]),
+    }
+}
+
+fn items(value: &Value) -> Result<&[Value], Error> {
+    match value {
+        Value::List(items) => Ok(items),
+        _ => Err(damaged()),
+    }
+}
+
+fn text(value: &Value) -> Result<&str, Error> {
+    match value {
+        Value::String(s) => Ok(s),
+        _ => Err(damaged()),
+    }
+}
+
+// the fields of the node with the name.
+fn fields<'a>(value: &'a Value, name: &str) -> Result<&'a [Value], Error> {
+    match items(value)? {
+        [Value::String(n), fields @ ..] if n == name => Ok(fields),
+        _ => Err(damaged()),
+    }
+}
+
+fn decode_boxed(value: &Value) -> Result<Box<Expression>, Error> {
+    decode_expression(value).map(Box::new)
+}
+
+fn decode_optional(value: &Value) -> Result<Option<Box<Expression>>, Error> {
+    match value {
+        _ if value.is_null() => Ok(None),
+        _ => decode_boxed(value).map(Some),
+    }
+}
+
+fn decode_definition(value: &Value) -> Result<FunctionDefinition, Error> {
+    let [name, params, body] = items(value)? else {
+        return Err(damaged());
+    };
+    Ok(FunctionDefinition {
+        name: text(name)?.to_owned(),
+        params: items(params)?
+            .iter()
+            .map(|p| text(p).map(str::to_owned))
+            .collect::<Result<_, _>>()?,
+        body: decode_boxed(body)?,
+    })
+}
+
+fn decode_module(value: &Value) -> Result<Module, Error> {
+    let [alias, path, imports, definitions] = items(value)? else {
+        return Err(damaged());
+    };
+    Ok(Module {
+        alias: text(alias)?.to_owned(),
+        path: PathBuf::from(text(path)?),
+        imports: items(imports)?
+            .iter()
+            .map(decode_module)
+            .collect::<Result<_, _>>()?,
+        definitions: items(definitions)?
+            .iter()
+            .map(decode_definition)
+            .collect::<Result<_, _>>()?,
+    })
+}
+
+fn decode_pattern(value: &Value) -> Result<Pattern, Error> {
+    match value {
+        Value::String(name) => Ok(Pattern::Variable(name.clone())),
+        Value::List(patterns) => patterns
+            .iter()
+            .map(decode_pattern)
+            .collect::<Result<_, _>>()
+            .map(Pattern::Elements),
+        _ => Err(damaged()),
+    }
+}
+
+fn decode_parts(value: &Value) -> Result<Vec<InterpolationPart>, Error> {
+    items(value)?
+        .iter()
+        .map(|part| match part {
+            Value::String(s) => Ok(InterpolationPart::Literal(s.clone())),
+            _ => decode_expression(part).map(InterpolationPart::Expression),
+        })
+        .collect()
+}
+
+fn decode_binary_operator(name: &str) -> Result<BinaryOperator, Error> {
+    Ok(match name {
+        "Add" => BinaryOperator::Add,
+        "Subtract" => BinaryOperator::Subtract,
+        "Multiply" => BinaryOperator::Multiply,
+        "Divide" => BinaryOperator::Divide,
+        "Remainder" => BinaryOperator::Remainder,
+        "Equal" => BinaryOperator::Equal,
+        "NotEqual" => BinaryOperator::NotEqual,
+        "Less" => BinaryOperator::Less,
+        "LessOrEqual" => BinaryOperator::LessOrEqual,
+        "Greater" => BinaryOperator::Greater,
+        "GreaterOrEqual" => BinaryOperator::GreaterOrEqual,
+        _ => return Err(damaged()),
+    })
+}
+
+fn decode_assign_operator(name: &str) -> Result<AssignOperator, Error> {
+    Ok(match name {
+        "Set" => AssignOperator::Set,
+        "Update" => AssignOperator::Update,
+        "Alternative" => AssignOperator::Alternative,
+        _ => AssignOperator::Arithmetic(decode_binary_operator(name)?),
+    })
+}
+
+fn decode_expression(value: &Value) -> Result<Expression, Error> {
+    let b = decode_boxed;
+    let expression = match items(value)? {
+        [Value::String(name), fields @ ..] => match (name.as_str(), fields) {
+            ("Identity", []) => Expression::Identity,
+            ("RecurseDefault", []) => Expression::RecurseDefault,
+            ("Literal", [literal]) => Expression::Literal(
+                parse_input(text(literal)?, None, &ParseOptions::unlimited())
+                    .map_err(|_| damaged())?,
+            ),
+            ("Interpolation", [parts]) => Expression::Interpolation(decode_parts(parts)?),
+            ("Format", [name, argument, template]) => Expression::Format {
+                name: text(name)?.to_owned(),
+                argument: decode_optional(argument)?,
+                template: match template {
+                    _ if template.is_null() => None,
+                    _ => Some(decode_parts(template)?),
+                },
+            },
+            ("Index", [target, index]) => Expression::Index(b(target)?, b(index)?),
+            ("Slice", [target, from, to]) => {
+                Expression::Slice(b(target)?, decode_optional(from)?, decode_optional(to)?)
+            }
+            ("Iterate", [target]) => Expression::Iterate(b(target)?),
+            ("Try", [body, handler]) => Expression::Try(b(body)?, decode_optional(handler)?),
+            ("Pipe", [left, right]) => Expression::Pipe(b(left)?, b(right)?),
+            ("Comma", [left, right]) => Expression::Comma(b(left)?, b(right)?),
+            ("Negate", [operand]) => Expression::Negate(b(operand)?),
+            ("Binary", [operator, left, right]) => Expression::Binary(
+                decode_binary_operator(text(operator)?)?,
+                b(left)?,
+                b(right)?,
+            ),
+            ("And", [left, right]) => Expression::And(b(left)?, b(right)?),
+            ("Or", [left, right]) => Expression::Or(b(left)?, b(right)?),
+            ("Alternative", [left, right]) => Expression::Alternative(b(left)?, b(right)?),
+            ("Assign", [operator, path, value]) => Expression::Assign(
+                decode_assign_operator(text(operator)?)?,
+                b(path)?,
+                b(value)?,
+            ),
+            ("List", [inner]) => Expression::List(decode_optional(inner)?),
+            ("Object", [entries]) => Expression::Object(
+                items(entries)?
+                    .iter()
+                    .map(|entry| match items(entry)? {
+                        [key, value] => Ok((decode_expression(key)?, decode_expression(value)?)),
+                        _ => Err(damaged()),
+                    })
+                    .collect::<Result<_, Error>>()?,
+            ),
+            ("Variant", [type_name, member_name, payload]) => Expression::Variant {
+                type_name: text(type_name)?.to_owned(),
+                member_name: text(member_name)?.to_owned(),
+                payload: if payload.is_null() {
+                    VariantPayload::Empty
+                } else {
+                    match items(payload)? {
+                        [Value::String(kind), inner] => match kind.as_str() {
+                            "Value" => VariantPayload::Value(b(inner)?),
+                            "Tuple" => VariantPayload::Tuple(
+                                items(inner)?
+                                    .iter()
+                                    .map(decode_expression)
+                                    .collect::<Result<_, _>>()?,
+                            ),
+                            "Object" => VariantPayload::Object(b(inner)?),
+                            _ => return Err(damaged()),
+                        },
+                        _ => return Err(damaged()),
+                    }
+                },
+            },
+            ("If", [branches, otherwise]) => Expression::If(
+                items(branches)?
+                    .iter()
+                    .map(|branch| match items(branch)? {
+                        [cond, body] => Ok((decode_expression(cond)?, decode_expression(body)?)),
+                        _ => Err(damaged()),
+                    })
+                    .collect::<Result<_, Error>>()?,
+                decode_optional(otherwise)?,
+            ),
+            ("Reduce", [source, pattern, init, update]) => Expression::Reduce {
+                source: b(source)?,
+                pattern: decode_pattern(pattern)?,
+                init: b(init)?,
+                update: b(update)?,
+            },
+            ("Foreach", [source, pattern, init, update, extract]) => Expression::Foreach {
+                source: b(source)?,
+                pattern: decode_pattern(pattern)?,
+                init: b(init)?,
+                update: b(update)?,
+                extract: decode_optional(extract)?,
+            },
+            ("Binding", [source, pattern, body]) => Expression::Binding {
+                source: b(source)?,
+                pattern: decode_pattern(pattern)?,
+                body: b(body)?,
+            },
+            ("FunctionDefinition", [definition, rest]) => {
+                Expression::FunctionDefinition(decode_definition(definition)?, b(rest)?)
+            }
+            ("Call", [name, args]) => Expression::Call(
+                text(name)?.to_owned(),
+                items(args)?
+                    .iter()
+                    .map(decode_expression)
+                    .collect::<Result<_, _>>()?,
+            ),
+            ("Variable", [name]) => Expression::Variable(text(name)?.to_owned()),
+            _ => return Err(damaged()),
+        },
+        _ => return Err(damaged()),
+    };
+    Ok(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        query::Query,
+        value::Value,
+    };
+
+    use super::{decode_program, encode_program};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_program_round_trip() {
+        let source = r#"def f($a; g): [g, $a] | .[1:];
+            reduce .xs[] as [$x, $y] (0; . + $x) as $s
+            | {
+                s: $s, t: "v\(.n)", o: Option::Some(.n), p: Point::P(1, 2), q: T::R{a: 1}, e: E::A,
+                i: (if .n > 1 then "a" elif .n == 0 then "b" else "c" end), f: f(1; .n),
+                r: [foreach .xs[] as $x (0; . + 1; -.)], z: (.zz // 3), k: try error("x") catch .,
+                fm: @base64 "a\(.n)", sl: .xs[1:], m: (.n |= . * 2 | .n += 1), str: "te\"x\nt", fl: 1.5
+            }"#;
+        let query = Query::compile(source).unwrap();
+        let bytes = query.to_program();
+        let loaded = Query::from_program(&bytes).unwrap();
+        assert_eq!(loaded.to_program(), bytes);
+
+        let input = value("{xs: [[1, 2], [3, 4]], n: 2}");
+        assert_eq!(
+            loaded.run_value(input.clone()).unwrap(),
+            query.run_value(input).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_damaged_program() {
+        let bytes = Query::compile(".a | keys").unwrap().to_program();
+        let program = decode_program(&bytes).unwrap();
+        assert_eq!(encode_program(&program), bytes);
+
+        assert!(decode_program(&bytes[..bytes.len() - 3]).is_err());
+        assert!(decode_program(b"").is_err());
+    }
+}
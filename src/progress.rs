@@ -0,0 +1,190 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Reporting the progress of processing the input documents.
+//
+// The tracker counts the bytes and documents processed, and passes
+// a snapshot to the callback after each step, e.g.
+//
+// ```
+// let mut tracker = ProgressTracker::new(Some(total_bytes), Some(files.len()), |p| {
+//     eprintln!("{} of {} documents", p.documents_done, files.len());
+// });
+// tracker.advance(text.len() as u64, 1);
+// ```
+
+use std::time::{Duration, Instant};
+
+/// A snapshot of the progress.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Progress {
+    pub bytes_done: u64,
+
+    /// `None` if the total size is unknown, e.g. reading from the STDIN.
+    pub bytes_total: Option<u64>,
+
+    pub documents_done: usize,
+    pub documents_total: Option<usize>,
+
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// The fraction of the work done, between 0.0 and 1.0, it is
+    /// estimated by the bytes, or the documents if the size is unknown.
+    pub fn fraction(&self) -> Option<f64> {
+        match (self.bytes_total, self.documents_total) {
+            (Some(total), _) if total > 0 => Some(self.bytes_done as f64 / total as f64),
+            (_, Some(total)) if total > 0 => Some(self.documents_done as f64 / total as f64),
+            _ => None,
+        }
+    }
+
+    /// The estimated remaining time, assuming the rate so far is kept.
+    pub fn eta(&self) -> Option<Duration> {
+        let fraction = self.fraction()?;
+        if fraction <= 0.0 {
+            return None;
+        }
+
+        let remaining = self.elapsed.as_secs_f64() * (1.0 - fraction.min(1.0)) / fraction;
+        Some(Duration::from_secs_f64(remaining))
+    }
+}
+
+pub struct ProgressTracker<F: FnMut(&Progress)> {
+    progress: Progress,
+    started: Instant,
+    callback: F,
+}
+
+impl<F: FnMut(&Progress)> ProgressTracker<F> {
+    pub fn new(bytes_total: Option<u64>, documents_total: Option<usize>, callback: F) -> Self {
+        Self {
+            progress: Progress {
+                bytes_done: 0,
+                bytes_total,
+                documents_done: 0,
+                documents_total,
+                elapsed: Duration::ZERO,
+            },
+            started: Instant::now(),
+            callback,
+        }
+    }
+
+    /// Record the bytes and documents processed since the last step.
+    pub fn advance(&mut self, bytes: u64, documents: usize) {
+        self.progress.bytes_done += bytes;
+        self.progress.documents_done += documents;
+        self.progress.elapsed = self.started.elapsed();
+        (self.callback)(&self.progress);
+    }
+}
+
+/// Render the progress as a single line for the terminal, e.g.
+///
+/// `[#########...........]  45% 12.0 MiB/26.7 MiB 3/7 documents ETA 00:01:05`
+pub fn render_progress_bar(progress: &Progress, width: usize) -> String {
+    let mut line = String::new();
+
+    if let Some(fraction) = progress.fraction() {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let filled = (fraction * width as f64).round() as usize;
+        line.push('[');
+        line.push_str(&"#".repeat(filled));
+        line.push_str(&".".repeat(width - filled));
+        line.push_str(&format!("] {:3.0}% ", fraction * 100.0));
+    }
+
+    line.push_str(&format_bytes(progress.bytes_done));
+    if let Some(total) = progress.bytes_total {
+        line.push('/');
+        line.push_str(&format_bytes(total));
+    }
+
+    line.push_str(&format!(" {}", progress.documents_done));
+    if let Some(total) = progress.documents_total {
+        line.push_str(&format!("/{}", total));
+    }
+    line.push_str(" documents");
+
+    if let Some(eta) = progress.eta() {
+        let secs = eta.as_secs();
+        line.push_str(&format!(
+            " ETA {:02}:{:02}:{:02}",
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60
+        ));
+    }
+
+    line
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use pretty_assertions::assert_eq;
+
+    use super::{render_progress_bar, Progress, ProgressTracker};
+
+    #[test]
+    fn test_tracker() {
+        let mut seen = vec![];
+        let mut tracker = ProgressTracker::new(Some(4096), Some(2), |p: &Progress| {
+            seen.push((p.bytes_done, p.documents_done))
+        });
+        tracker.advance(1024, 1);
+        tracker.advance(3072, 1);
+        assert_eq!(seen, [(1024, 1), (4096, 2)]);
+    }
+
+    #[test]
+    fn test_render_progress_bar() {
+        let progress = Progress {
+            bytes_done: 1024,
+            bytes_total: Some(4096),
+            documents_done: 1,
+            documents_total: Some(2),
+            elapsed: Duration::from_secs(10),
+        };
+        assert_eq!(progress.fraction(), Some(0.25));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(30)));
+        assert_eq!(
+            render_progress_bar(&progress, 8),
+            "[##......]  25% 1.0 KiB/4.0 KiB 1/2 documents ETA 00:00:30"
+        );
+
+        // the totals are unknown, e.g. reading from the STDIN
+        let progress = Progress {
+            bytes_done: 10,
+            bytes_total: None,
+            documents_done: 1,
+            documents_total: None,
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!(progress.eta(), None);
+        assert_eq!(render_progress_bar(&progress, 8), "10 B 1 documents");
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Tracking where the values come from.
+//
+// The `provenance(f)` builtin evaluates `f` as a path expression against
+// the input documents, and outputs an object for each value, e.g.
+//
+// `{value: 8080, file: "b.ason", path: ".server.port"}`
+//
+// When there are multiple input documents they are joined in a tuple,
+// the first component of the path selects the document, and it is
+// replaced by the name of the file.
+//
+// And the sources of the input documents, which are reported by the
+// `input_filename` and `input_line_number` builtins, and the variable
+// `$__input__`, e.g.
+//
+// `{filename: "b.ason", index: 1, line: 1, size: 312, mtime: d"2024-10-01T08:00:00Z"}`
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{
+    path::path_to_string,
+    value::{Map, Value},
+};
+
+/// Where an input document comes from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DocumentSource {
+    /// The name of the input file, `None` for the STDIN.
+    pub filename: Option<String>,
+
+    /// The line where the document starts, starts from 1.
+    pub line: usize,
+
+    /// The size of the input file (or the text of the STDIN) in bytes.
+    pub size: u64,
+
+    /// The last modification time of the input file, `None` for
+    /// the STDIN and the URLs.
+    pub modified: Option<DateTime<FixedOffset>>,
+}
+
+impl DocumentSource {
+    /// The metadata of the document of the index, i.e. `$__input__`.
+    pub fn metadata(&self, index: usize) -> Value {
+        let mut map = Map::new();
+        map.insert(
+            "filename".to_owned(),
+            self.filename
+                .clone()
+                .map_or_else(Value::null, Value::String),
+        );
+        map.insert("index".to_owned(), Value::from(index));
+        map.insert("line".to_owned(), Value::from(self.line));
+        map.insert("size".to_owned(), Value::from(self.size as usize));
+        map.insert(
+            "mtime".to_owned(),
+            self.modified.map_or_else(Value::null, Value::DateTime),
+        );
+        Value::Object(map)
+    }
+}
+
+pub struct Provenance {
+    /// The names of the input files, `None` for the STDIN.
+    pub sources: Vec<Option<String>>,
+
+    /// The input of the whole query.
+    pub root: Value,
+}
+
+impl Provenance {
+    pub fn describe(&self, mut path: Vec<Value>, value: Value) -> Value {
+        let source = if self.sources.len() == 1 {
+            self.sources.first().cloned().flatten()
+        } else {
+            match path.first() {
+                Some(Value::Number(n)) => {
+                    let source = n
+                        .as_integer()
+                        .and_then(|i| usize::try_from(i).ok())
+                        .and_then(|i| self.sources.get(i))
+                        .cloned()
+                        .flatten();
+                    path.remove(0);
+                    source
+                }
+                _ => None,
+            }
+        };
+
+        let mut map = Map::new();
+        map.insert("value".to_owned(), value);
+        map.insert(
+            "file".to_owned(),
+            source.map_or_else(Value::null, Value::String),
+        );
+        map.insert("path".to_owned(), Value::String(path_to_string(&path)));
+        Value::Object(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        query::Query,
+        value::Value,
+    };
+
+    use super::{DocumentSource, Provenance};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_describe() {
+        let provenance = Provenance {
+            sources: vec![Some("a.ason".to_owned()), None],
+            root: Value::null(),
+        };
+        assert_eq!(
+            provenance
+                .describe(vec![Value::from(0), Value::from("port")], Value::from(80))
+                .to_string(),
+            r#"{value:80,file:"a.ason",path:".port"}"#
+        );
+        assert_eq!(
+            provenance
+                .describe(vec![Value::from(1)], Value::from(1))
+                .to_string(),
+            r#"{value:1,file:Option::None,path:"."}"#
+        );
+    }
+
+    #[test]
+    fn test_provenance_builtin() {
+        let documents = Value::Tuple(vec![value("{port: 80}"), value(r#"{"a b": [1, 2]}"#)].into());
+        let query = Query::compile(r#".[1]."a b"[1] = 9 | provenance(.[0].port, .[1][])"#)
+            .unwrap()
+            .with_provenance(vec![Some("a.ason".to_owned()), None]);
+        let results: Vec<String> = query
+            .run_value(documents)
+            .unwrap()
+            .iter()
+            .map(|v| v.to_string())
+            .collect();
+        assert_eq!(
+            results,
+            [
+                r#"{value:80,file:"a.ason",path:".port"}"#,
+                r#"{value:[1,2],file:Option::None,path:".\"a b\""}"#,
+            ]
+        );
+
+        // the tracking is opt-in
+        let error = Query::compile("provenance(.)")
+            .unwrap()
+            .run_value(Value::null())
+            .unwrap_err();
+        assert!(error.to_string().contains("--provenance"));
+    }
+
+    #[test]
+    fn test_metadata() {
+        let source = DocumentSource {
+            filename: None,
+            line: 3,
+            size: 42,
+            modified: None,
+        };
+        assert_eq!(
+            source.metadata(2).to_string(),
+            "{filename:Option::None,index:2,line:3,size:42,mtime:Option::None}"
+        );
+    }
+}
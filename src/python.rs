@@ -0,0 +1,393 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The Python bindings (by `PyO3`), the module `pyason_query` is built
+// by `maturin build --release` (see `pyproject.toml`), e.g.
+//
+// ```python
+// import pyason_query as aq
+//
+// query = aq.compile(".users[] | select(.age > 30) | .name")
+// query.run({"users": [{"name": "foo", "age": 42}]})  # ['foo']
+// aq.run(".a + 1", {"a": 1})                          # [2]
+// ```
+//
+// The data are the native Python values:
+//
+// - `None` is `null` (i.e. `Option::None`), and `Option::Some(v)` is `v`
+// - `bool`, `int`, `float`, `str`, `bytes`, `list`, `tuple` and `dict`
+// - `datetime.datetime` (with the timezone) is the ASON datetime
+// - the chars are the strings of one char
+// - the other variants are the instances of `Variant`, e.g.
+//   `Variant("Color", "Red")`, the value is `None` for the empty variants,
+//   a tuple for the tuple variants, and a dict for the object variants
+
+use std::sync::Arc;
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyTypeError},
+    prelude::*,
+    types::{PyBool, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple},
+};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::{
+    error::Error,
+    query::Query,
+    value::{Items, Map, Number, Value, Variant, VariantValue},
+};
+
+create_exception!(
+    pyason_query,
+    QueryError,
+    PyException,
+    "The query cannot be compiled, or fails to run."
+);
+
+fn query_error(error: Error) -> PyErr {
+    QueryError::new_err(error.to_string())
+}
+
+/// A compiled query, which can run on many values.
+#[pyclass(name = "Query", module = "pyason_query", unsendable)]
+pub struct CompiledQuery {
+    query: Query,
+}
+
+#[pymethods]
+impl CompiledQuery {
+    /// Run the query on the value, and return the list of the results.
+    fn run(&self, py: Python<'_>, data: &Bound<'_, PyAny>) -> PyResult<Vec<PyObject>> {
+        let results = self
+            .query
+            .run_value(from_python(data)?)
+            .map_err(query_error)?;
+        results.iter().map(|v| to_python(py, v)).collect()
+    }
+}
+
+/// An ASON variant other than `Option`, e.g. `Color::Red`.
+#[pyclass(name = "Variant", module = "pyason_query")]
+pub struct PyVariant {
+    #[pyo3(get)]
+    type_name: String,
+    #[pyo3(get)]
+    member_name: String,
+    value: Option<PyObject>,
+}
+
+#[pymethods]
+impl PyVariant {
+    #[new]
+    #[pyo3(signature = (type_name, member_name, value = None))]
+    fn new(type_name: String, member_name: String, value: Option<PyObject>) -> Self {
+        Self {
+            type_name,
+            member_name,
+            value,
+        }
+    }
+
+    #[getter]
+    fn value(&self, py: Python<'_>) -> Option<PyObject> {
+        self.value.as_ref().map(|v| v.clone_ref(py))
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> PyResult<String> {
+        match &self.value {
+            None => Ok(format!(
+                "Variant({:?}, {:?})",
+                self.type_name, self.member_name
+            )),
+            Some(value) => Ok(format!(
+                "Variant({:?}, {:?}, {})",
+                self.type_name,
+                self.member_name,
+                value.bind(py).repr()?
+            )),
+        }
+    }
+}
+
+/// Compile the query, the syntax errors are raised as `QueryError`.
+#[pyfunction]
+fn compile(source: &str) -> PyResult<CompiledQuery> {
+    Ok(CompiledQuery {
+        query: Query::compile(source).map_err(query_error)?,
+    })
+}
+
+/// Compile the query and run it on the value, see `Query.run()`.
+#[pyfunction]
+fn run(py: Python<'_>, source: &str, data: &Bound<'_, PyAny>) -> PyResult<Vec<PyObject>> {
+    compile(source)?.run(py, data)
+}
+
+#[pymodule]
+fn pyason_query(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<CompiledQuery>()?;
+    module.add_class::<PyVariant>()?;
+    module.add("QueryError", module.py().get_type_bound::<QueryError>())?;
+    module.add_function(wrap_pyfunction!(compile, module)?)?;
+    module.add_function(wrap_pyfunction!(run, module)?)?;
+    Ok(())
+}
+
+fn to_python(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    if let Some(inner) = value.as_some() {
+        return to_python(py, inner);
+    }
+
+    let object = match value {
+        Value::Number(n) => match n.as_i128() {
+            Some(i) => i.into_py(py),
+            None => n.as_f64().into_py(py),
+        },
+        Value::Boolean(b) => b.into_py(py),
+        Value::Char(c) => c.to_string().into_py(py),
+        Value::String(s) => s.as_str().into_py(py),
+        Value::DateTime(d) => d.into_py(py),
+        Value::ByteData(bytes) => PyBytes::new_bound(py, bytes).into_py(py),
+        _ if value.is_null() => py.None(),
+        Value::Variant(variant) => {
+            let payload = match &variant.value {
+                VariantValue::Empty => None,
+                VariantValue::Value(inner) => Some(to_python(py, inner)?),
+                VariantValue::Tuple(items) => Some(to_tuple(py, items)?),
+                VariantValue::Object(map) => Some(to_dict(py, map)?),
+            };
+            let variant = PyVariant::new(
+                variant.type_name.clone(),
+                variant.member_name.clone(),
+                payload,
+            );
+            Py::new(py, variant)?.into_py(py)
+        }
+        Value::List(items) => {
+            let items = items
+                .iter()
+                .map(|v| to_python(py, v))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new_bound(py, items).into_py(py)
+        }
+        Value::Tuple(items) => to_tuple(py, items)?,
+        Value::Object(map) => to_dict(py, map)?,
+    };
+    Ok(object)
+}
+
+fn to_tuple(py: Python<'_>, items: &[Value]) -> PyResult<PyObject> {
+    let items = items
+        .iter()
+        .map(|v| to_python(py, v))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(PyTuple::new_bound(py, items).into_py(py))
+}
+
+fn to_dict(py: Python<'_>, map: &Map) -> PyResult<PyObject> {
+    let dict = PyDict::new_bound(py);
+    for (key, value) in map.iter() {
+        dict.set_item(key, to_python(py, value)?)?;
+    }
+    Ok(dict.into_py(py))
+}
+
+fn from_python(object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    // `bool` is a subclass of `int`, so it is checked first.
+    let value = if object.is_none() {
+        Value::null()
+    } else if let Ok(b) = object.downcast::<PyBool>() {
+        Value::Boolean(b.is_true())
+    } else if object.is_instance_of::<PyInt>() {
+        let number = if let Ok(i) = object.extract::<i32>() {
+            Number::I32(i)
+        } else if let Ok(i) = object.extract::<i64>() {
+            Number::I64(i)
+        } else if let Ok(i) = object.extract::<u64>() {
+            Number::U64(i)
+        } else {
+            return Err(PyTypeError::new_err(format!(
+                "The integer {} is out of the range of the ASON numbers.",
+                object
+            )));
+        };
+        Value::Number(number)
+    } else if let Ok(f) = object.downcast::<PyFloat>() {
+        Value::Number(Number::F64(f.value()))
+    } else if let Ok(s) = object.downcast::<PyString>() {
+        Value::String(s.to_str()?.to_owned())
+    } else if let Ok(bytes) = object.downcast::<PyBytes>() {
+        Value::ByteData(bytes.as_bytes().to_vec())
+    } else if let Ok(list) = object.downcast::<PyList>() {
+        Value::List(from_items(list.iter())?)
+    } else if let Ok(tuple) = object.downcast::<PyTuple>() {
+        Value::Tuple(from_items(tuple.iter())?)
+    } else if let Ok(dict) = object.downcast::<PyDict>() {
+        Value::Object(from_dict(dict)?)
+    } else if let Ok(variant) = object.downcast::<PyVariant>() {
+        let variant = variant.borrow();
+        let value = match &variant.value {
+            None => VariantValue::Empty,
+            Some(inner) => {
+                let inner = inner.bind(object.py());
+                if let Ok(tuple) = inner.downcast::<PyTuple>() {
+                    VariantValue::Tuple(from_items(tuple.iter())?)
+                } else if let Ok(dict) = inner.downcast::<PyDict>() {
+                    VariantValue::Object(from_dict(dict)?)
+                } else {
+                    VariantValue::Value(Box::new(from_python(inner)?))
+                }
+            }
+        };
+        Value::Variant(Arc::new(Variant {
+            type_name: variant.type_name.clone(),
+            member_name: variant.member_name.clone(),
+            value,
+        }))
+    } else if let Ok(datetime) = object.extract::<DateTime<FixedOffset>>() {
+        Value::DateTime(datetime)
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "The Python type {} cannot be converted to ASON, \
+            or the datetime has no timezone.",
+            object.get_type().name()?
+        )));
+    };
+    Ok(value)
+}
+
+fn from_items<'py>(items: impl Iterator<Item = Bound<'py, PyAny>>) -> PyResult<Items> {
+    let items = items
+        .map(|item| from_python(&item))
+        .collect::<PyResult<Vec<_>>>()?;
+    Ok(Items::from(items))
+}
+
+fn from_dict(dict: &Bound<'_, PyDict>) -> PyResult<Map> {
+    let mut map = Map::new();
+    for (key, value) in dict.iter() {
+        let Ok(key) = key.downcast::<PyString>() else {
+            return Err(PyTypeError::new_err(format!(
+                "The key of the dict expects str, found {}.",
+                key.get_type().name()?
+            )));
+        };
+        map.insert(key.to_str()?.to_owned(), from_python(&value)?);
+    }
+    Ok(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::DateTime;
+    use pretty_assertions::assert_eq;
+    use pyo3::{exceptions::PyTypeError, prelude::*};
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{compile, from_python, run, to_python, QueryError};
+
+    fn with_python(f: impl FnOnce(Python<'_>)) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(f)
+    }
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_run() {
+        with_python(|py| {
+            let data = py
+                .eval_bound(
+                    "{'users': [{'name': 'foo', 'age': 42}, {'name': 'bar', 'age': 7}]}",
+                    None,
+                    None,
+                )
+                .unwrap();
+            let query = compile(".users[] | select(.age > 30) | .name").unwrap();
+            let names: Vec<String> = query
+                .run(py, &data)
+                .unwrap()
+                .iter()
+                .map(|name| name.extract(py).unwrap())
+                .collect();
+            assert_eq!(names, ["foo"]);
+
+            let data = py.eval_bound("{'a': 1}", None, None).unwrap();
+            let results = run(py, ".a + 1", &data).unwrap();
+            assert_eq!(results[0].extract::<i64>(py).unwrap(), 2);
+        });
+    }
+
+    #[test]
+    fn test_errors() {
+        with_python(|py| {
+            match compile(".a |") {
+                Ok(_) => panic!("the query should not compile"),
+                Err(e) => assert!(e.is_instance_of::<QueryError>(py)),
+            }
+
+            let data = py.eval_bound("{'a': 1}", None, None).unwrap();
+            let error = run(py, ".a[0]", &data).unwrap_err();
+            assert!(error.is_instance_of::<QueryError>(py));
+
+            let data = py.eval_bound("{1: 2}", None, None).unwrap();
+            let error = run(py, ".", &data).unwrap_err();
+            assert!(error.is_instance_of::<PyTypeError>(py));
+            assert_eq!(
+                error.value_bound(py).to_string(),
+                "The key of the dict expects str, found int."
+            );
+
+            let data = py.eval_bound("object()", None, None).unwrap();
+            assert!(run(py, ".", &data).is_err());
+        });
+    }
+
+    #[test]
+    fn test_convert_values() {
+        with_python(|py| {
+            let round_trip = |value: &Value| {
+                let object = to_python(py, value).unwrap();
+                from_python(object.bind(py)).unwrap()
+            };
+
+            let input = value(
+                r#"{
+                    a: [1, 2.5, true, "s", (1, "t")],
+                    b: [Color::Red, Point::P(1, 2), Shape::Rect{w: 3}, Box::B(4)],
+                    c: Option::None
+                }"#,
+            );
+            assert_eq!(round_trip(&input), input);
+
+            // the types of the numbers, the chars and the options are not kept
+            assert_eq!(
+                round_trip(&value("[3_u8, 'c', Option::Some(5)]")),
+                value("[3, \"c\", 5]")
+            );
+
+            let bytes = Value::ByteData(vec![1, 2, 3]);
+            assert_eq!(round_trip(&bytes), bytes);
+            let datetime =
+                Value::DateTime(DateTime::parse_from_rfc3339("2024-03-16T16:30:50+08:00").unwrap());
+            assert_eq!(round_trip(&datetime), datetime);
+
+            let variant = to_python(py, &value("Color::Red")).unwrap();
+            assert_eq!(
+                variant.bind(py).repr().unwrap().to_string(),
+                "Variant(\"Color\", \"Red\")"
+            );
+        });
+    }
+}
@@ -0,0 +1,451 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use std::{cell::RefCell, collections::VecDeque, path::PathBuf, rc::Rc, time::Instant};
+
+use ason::ast::AsonNode;
+
+use crate::{
+    ast::{Expression, FunctionDefinition, Import},
+    builder::QueryBuilder,
+    cancel::CancellationToken,
+    error::Error,
+    evaluator::{Environment, Evaluator, InputStream},
+    limits::Limits,
+    module::{Module, ModuleLoader},
+    parser::{parse_definitions_from_str, parse_query_from_str},
+    program::{decode_program, encode_program, Program},
+    provenance::{DocumentSource, Provenance},
+    value::Value,
+};
+
+/// The functions which are available to all queries, e.g. `map(f)`.
+pub const PRELUDE: &str = include_str!("prelude.aql");
+
+/// A compiled query expression.
+pub struct Query {
+    prelude: Vec<FunctionDefinition>,
+    modules: Vec<Module>,
+    expression: Expression,
+    sources: Option<Vec<Option<String>>>,
+    inputs: Rc<RefCell<VecDeque<Value>>>,
+
+    // the number of the documents of `with_inputs`
+    input_count: usize,
+    input_stream: Option<InputStream>,
+    input_sources: Vec<DocumentSource>,
+    variables: Vec<(String, Value)>,
+    limits: Limits,
+    error_paths: bool,
+    trace: bool,
+}
+
+impl Query {
+    pub fn compile(source: &str) -> Result<Self, Error> {
+        Self::compile_with_loader(source, &ModuleLoader::default())
+    }
+
+    /// Compile the query, the imported modules are
+    /// resolved and loaded by the `loader`.
+    pub fn compile_with_loader(source: &str, loader: &ModuleLoader) -> Result<Self, Error> {
+        let (imports, expression) = parse_query_from_str(source)?;
+        Self::from_expression(expression)?.with_imports(&imports, loader)
+    }
+
+    /// Load the modules imported by the query.
+    pub fn with_imports(
+        mut self,
+        imports: &[Import],
+        loader: &ModuleLoader,
+    ) -> Result<Self, Error> {
+        self.modules = loader.load(imports)?;
+        Ok(self)
+    }
+
+    /// Build a query from the syntax tree, e.g. the one
+    /// constructed by the `QueryBuilder`.
+    pub fn from_expression(expression: Expression) -> Result<Self, Error> {
+        let prelude = parse_definitions_from_str(PRELUDE)?;
+        Ok(Self::from_parts(prelude, vec![], expression))
+    }
+
+    /// Load the query from a compiled program, see `Query::to_program()`.
+    pub fn from_program(bytes: &[u8]) -> Result<Self, Error> {
+        let program = decode_program(bytes)?;
+        Ok(Self::from_parts(
+            program.prelude,
+            program.modules,
+            program.expression,
+        ))
+    }
+
+    /// The compiled program of the query, i.e. the syntax trees of the
+    /// query, the modules and the prelude, which is loaded later by
+    /// `Query::from_program()` without parsing the query and the modules.
+    pub fn to_program(&self) -> Vec<u8> {
+        encode_program(&Program {
+            prelude: self.prelude.clone(),
+            modules: self.modules.clone(),
+            expression: self.expression.clone(),
+        })
+    }
+
+    /// The paths of the imported module files, including
+    /// the ones imported by the modules.
+    pub fn module_paths(&self) -> Vec<PathBuf> {
+        fn collect(modules: &[Module], paths: &mut Vec<PathBuf>) {
+            for module in modules {
+                if !paths.contains(&module.path) {
+                    paths.push(module.path.clone());
+                }
+                collect(&module.imports, paths);
+            }
+        }
+
+        let mut paths = vec![];
+        collect(&self.modules, &mut paths);
+        paths
+    }
+
+    fn from_parts(
+        prelude: Vec<FunctionDefinition>,
+        modules: Vec<Module>,
+        expression: Expression,
+    ) -> Self {
+        Self {
+            prelude,
+            modules,
+            expression,
+            sources: None,
+            inputs: Rc::default(),
+            input_count: 0,
+            input_stream: None,
+            input_sources: vec![],
+            variables: vec![],
+            limits: Limits::default(),
+            error_paths: false,
+            trace: false,
+        }
+    }
+
+    pub fn builder() -> QueryBuilder {
+        QueryBuilder::new()
+    }
+
+    /// Track the provenance of the values for the `provenance` builtin,
+    /// `sources` are the names of the input files (`None` for the STDIN)
+    /// in the order of the input documents.
+    pub fn with_provenance(mut self, sources: Vec<Option<String>>) -> Self {
+        self.sources = Some(sources);
+        self
+    }
+
+    /// The documents consumed by the `input` and `inputs` builtins,
+    /// they are shared by all runs of the query.
+    pub fn with_inputs(mut self, inputs: Vec<Value>) -> Self {
+        self.input_count += inputs.len();
+        self.inputs.borrow_mut().extend(inputs);
+        self
+    }
+
+    /// Take the next document of `with_inputs` and its index, the
+    /// documents consumed by the `input` and `inputs` builtins are
+    /// skipped, e.g. when each document is the input of a run in turn.
+    pub fn next_input(&self) -> Option<(usize, Value)> {
+        let mut inputs = self.inputs.borrow_mut();
+        let value = inputs.pop_front()?;
+        Some((self.input_count - inputs.len() - 1, value))
+    }
+
+    /// The documents produced on demand for the `input` and `inputs`
+    /// builtins after the documents of `with_inputs`, e.g. the events
+    /// of the streaming parse.
+    pub fn with_input_stream(mut self, stream: InputStream) -> Self {
+        self.input_stream = Some(stream);
+        self
+    }
+
+    /// The sources of the input documents, in the same order as the
+    /// inputs, for the `input_filename` and `input_line_number` builtins.
+    pub fn with_input_sources(mut self, sources: Vec<DocumentSource>) -> Self {
+        self.input_sources = sources;
+        self
+    }
+
+    /// Define the variables, e.g. `("name", value)` is `$name` in the query.
+    pub fn with_variables(mut self, variables: Vec<(String, Value)>) -> Self {
+        self.variables = variables;
+        self
+    }
+
+    /// The variables defined by `with_variables()`.
+    pub fn variables(&self) -> &[(String, Value)] {
+        &self.variables
+    }
+
+    /// Limit the resources used by each run of the query.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The limits defined by `with_limits()`.
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    /// Add the path of the failing value in the input document and the
+    /// stage of the query to the evaluation errors, e.g.
+    /// `Cannot index number with "name" (at the path .users[17].name ...)`,
+    /// the keys of the values are tracked during the evaluation.
+    pub fn with_error_paths(mut self) -> Self {
+        self.error_paths = true;
+        self
+    }
+
+    /// Write the values produced by each stage of the top-level
+    /// pipeline to STDERR (see the module `trace`).
+    pub fn with_trace(mut self) -> Self {
+        self.trace = true;
+        self
+    }
+
+    /// Run the query, each result is passed to `output` as soon as
+    /// it is produced.
+    pub fn run_with(
+        &self,
+        input: Value,
+        output: &mut dyn FnMut(Value) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.run_cancellable(input, None, None, output)
+    }
+
+    /// Run the query with the input document of the index (see
+    /// `with_input_sources()`), i.e. the document which is reported by
+    /// `input_filename` and `$__input__`.
+    pub fn run_document(
+        &self,
+        index: usize,
+        input: Value,
+        output: &mut dyn FnMut(Value) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.run_cancellable(input, Some(index), None, output)
+    }
+
+    /// Run the query which can be aborted by the token, the
+    /// evaluation fails with `Error::Cancelled` once the token is cancelled.
+    pub fn run_with_cancellation(
+        &self,
+        input: Value,
+        cancellation: &CancellationToken,
+        output: &mut dyn FnMut(Value) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.run_cancellable(input, None, Some(cancellation.clone()), output)
+    }
+
+    fn run_cancellable(
+        &self,
+        input: Value,
+        document: Option<usize>,
+        cancellation: Option<CancellationToken>,
+        output: &mut dyn FnMut(Value) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut env = Environment::root();
+        for definition in &self.prelude {
+            env = Environment::with_function(&env, definition);
+        }
+
+        let prelude_env = env.clone();
+        for module in &self.modules {
+            env = bind_module(&prelude_env, &env, module);
+        }
+
+        for (name, value) in &self.variables {
+            env = Environment::with_variable(&env, name, value.clone());
+        }
+
+        let mut evaluator = match &self.sources {
+            Some(sources) => Evaluator::with_provenance(Provenance {
+                sources: sources.clone(),
+                root: input.clone(),
+            }),
+            None => Evaluator::new(),
+        };
+        evaluator.inputs = Rc::clone(&self.inputs);
+        evaluator.input_stream = self.input_stream.clone();
+        evaluator.input_sources = self.input_sources.clone();
+        if document.is_some() {
+            evaluator.current_input.set(document);
+        } else if self.input_sources.len() == 1 {
+            // the only document is the input of the query
+            evaluator.current_input.set(Some(0));
+        }
+        evaluator.cancellation = cancellation;
+        evaluator.limits = self.limits;
+        evaluator.deadline = self.limits.timeout.map(|t| Instant::now() + t);
+        evaluator.error_paths = self.error_paths;
+
+        let root = self.error_paths.then(|| input.clone());
+        let mut count = 0;
+        let mut limited_output = |v| {
+            count += 1;
+            match self.limits.max_output {
+                Some(max_output) if count > max_output => Err(Error::LimitExceeded(format!(
+                    "The number of results exceeds the limit of {}.",
+                    max_output
+                ))),
+                _ => output(v),
+            }
+        };
+        let result = if self.trace {
+            evaluator.eval_traced(&self.expression, input, &env, &mut limited_output)
+        } else {
+            evaluator.eval(&self.expression, input, &env, &mut limited_output)
+        };
+
+        match (result, root, evaluator.error_context.take()) {
+            (Err(e), Some(root), Some(context)) => Err(context.locate(e, &root)),
+            (result, _, _) => result,
+        }
+    }
+
+    /// Run the query on an ASON document and collect all results.
+    pub fn run(&self, input: &AsonNode) -> Result<Vec<AsonNode>, Error> {
+        let results = self.run_value(Value::from(input.clone()))?;
+        Ok(results.into_iter().map(AsonNode::from).collect())
+    }
+
+    /// Run the query on a Rust value and convert the results to the Rust type,
+    /// e.g. `let names: Vec<String> = query.run_typed(&config)?`.
+    #[cfg(feature = "serde")]
+    pub fn run_typed<I, T>(&self, input: &I) -> Result<Vec<T>, Error>
+    where
+        I: serde::Serialize + ?Sized,
+        T: serde::de::DeserializeOwned,
+    {
+        let results = self.run_value(crate::serde_value::to_value(input)?)?;
+        results
+            .into_iter()
+            .map(crate::serde_value::from_value)
+            .collect()
+    }
+
+    /// Run the query and collect all results.
+    pub fn run_value(&self, input: Value) -> Result<Vec<Value>, Error> {
+        let mut results = vec![];
+        self.run_with(input, &mut |v| {
+            results.push(v);
+            Ok(())
+        })?;
+        Ok(results)
+    }
+}
+
+// the functions of a module can call the prelude, the
+// functions defined before them and the modules it imports.
+fn bind_module<'a>(
+    prelude_env: &Rc<Environment<'a>>,
+    env: &Rc<Environment<'a>>,
+    module: &'a Module,
+) -> Rc<Environment<'a>> {
+    let mut module_env = prelude_env.clone();
+    for import in &module.imports {
+        module_env = bind_module(prelude_env, &module_env, import);
+    }
+    for definition in &module.definitions {
+        module_env = Environment::with_function(&module_env, definition);
+    }
+    Environment::with_module(env, &module.alias, module_env)
+}
+
+#[cfg(test)]
+mod tests {
+    use ason::ast::parser::parse_from_str;
+    use pretty_assertions::assert_eq;
+
+    use crate::value::Value;
+
+    use super::Query;
+
+    #[test]
+    fn test_run() {
+        let document = parse_from_str(
+            "{orders: [{id: 1, total: 50}, {id: 2, total: 150}, {id: 3, total: 120}]}",
+        )
+        .unwrap();
+        let query = Query::compile(".orders[] | select(.total > 100) | .id").unwrap();
+        let ids: Vec<Value> = query
+            .run(&document)
+            .unwrap()
+            .into_iter()
+            .map(Value::from)
+            .collect();
+        assert_eq!(ids, [Value::from(2), Value::from(3)]);
+
+        // the compiled query can run again
+        assert_eq!(query.run(&document).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Query::compile(".a |").is_err());
+
+        let document = parse_from_str("[1]").unwrap();
+        assert_eq!(
+            Query::compile(".a")
+                .unwrap()
+                .run(&document)
+                .unwrap_err()
+                .to_string(),
+            "Cannot index list with string."
+        );
+    }
+
+    #[test]
+    fn test_error_paths() {
+        let error = |query: &str| {
+            let document = Value::from(parse_from_str("{users: [{name: \"a\"}, 5]}").unwrap());
+            Query::compile(query)
+                .unwrap()
+                .with_error_paths()
+                .run_value(document)
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(
+            error(".users[] | .name"),
+            "Cannot index number with string (at the path .users[1].name of the input, \
+            in the query stage \".name\")."
+        );
+        assert_eq!(
+            error(".users[] | keys"),
+            "number has no keys (at the path .users[1] of the input, in the query stage \"keys\")."
+        );
+        assert_eq!(
+            error(".users[] | (.name // .x)"),
+            "Cannot index number with string (at the path .users[1].x of the input, \
+            in the query stage \".x\")."
+        );
+
+        // the path is omitted if the failing value is computed
+        assert_eq!(
+            error("{n: .users[1]} | .n.x"),
+            "Cannot index number with string (in the query stage \".n.x\")."
+        );
+
+        // the errors are not located without `with_error_paths()`
+        let document = Value::from(parse_from_str("[1]").unwrap());
+        assert_eq!(
+            Query::compile(".[] | .a")
+                .unwrap()
+                .run_value(document)
+                .unwrap_err()
+                .to_string(),
+            "Cannot index number with string."
+        );
+    }
+}
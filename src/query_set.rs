@@ -0,0 +1,150 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// A set of named queries evaluated over the same input, i.e.
+// `aq --query-set FILE`, so the input is read and parsed only once
+// for many metrics. The file is an ASON object of the names and
+// the query texts, e.g.
+//
+// ```
+// {
+//     total: "length"
+//     errors: "map(select(.level == \"error\")) | length"
+// }
+// ```
+//
+// the result is an object of the names and the results of the queries,
+// the single result of a query is the value, otherwise (no result or
+// multiple results) it is the list of the results.
+
+use crate::{
+    ast::{BinaryOperator, Expression, Import},
+    error::Error,
+    module::ModuleLoader,
+    parser::parse_query_from_str,
+    query::Query,
+    value::Value,
+};
+
+/// Compile the query set, the modules imported by the
+/// queries are loaded by the `loader`.
+pub fn compile_query_set(definition: &Value, loader: &ModuleLoader) -> Result<Query, Error> {
+    let Value::Object(map) = definition else {
+        return Err(Error::Message(format!(
+            "The query set expects object, found {}.",
+            definition.type_name()
+        )));
+    };
+
+    let mut imports: Vec<Import> = vec![];
+    let mut entries = vec![];
+    for (name, source) in map.iter() {
+        let Value::String(source) = source else {
+            return Err(Error::Message(format!(
+                "The query \"{}\" of the set expects string, found {}.",
+                name,
+                source.type_name()
+            )));
+        };
+
+        let (query_imports, expression) = parse_query_from_str(source)
+            .map_err(|e| Error::Message(format!("Invalid query \"{}\" of the set: {}", name, e)))?;
+
+        for import in query_imports {
+            match imports.iter().find(|i| i.alias == import.alias) {
+                Some(existing) if existing.path == import.path => {}
+                Some(_) => {
+                    return Err(Error::Message(format!(
+                    "The module alias \"{}\" is imported with different paths in the query set.",
+                    import.alias
+                )))
+                }
+                None => imports.push(import),
+            }
+        }
+
+        entries.push((
+            Expression::Literal(Value::String(name.clone())),
+            collect_results(expression),
+        ));
+    }
+
+    Query::from_expression(Expression::Object(entries))?.with_imports(&imports, loader)
+}
+
+// `[expr] | if length == 1 then .[0] else . end`
+fn collect_results(expression: Expression) -> Expression {
+    let is_single = Expression::Binary(
+        BinaryOperator::Equal,
+        Box::new(Expression::Call("length".to_owned(), vec![])),
+        Box::new(Expression::Literal(Value::from(1i64))),
+    );
+    let first = Expression::Index(
+        Box::new(Expression::Identity),
+        Box::new(Expression::Literal(Value::from(0i64))),
+    );
+
+    Expression::Pipe(
+        Box::new(Expression::List(Some(Box::new(expression)))),
+        Box::new(Expression::If(
+            vec![(is_single, first)],
+            Some(Box::new(Expression::Identity)),
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        module::ModuleLoader,
+        value::Value,
+    };
+
+    use super::compile_query_set;
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    fn error_of(definition: &str) -> String {
+        match compile_query_set(&value(definition), &ModuleLoader::default()) {
+            Ok(_) => panic!("the query set is compiled"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_query_set() {
+        let query = compile_query_set(
+            &value("{total: \"length\", each: \".[]\", none: \"empty\"}"),
+            &ModuleLoader::default(),
+        )
+        .unwrap();
+        let results = query.run_value(value("[1, 2]")).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].to_string(), "{total:2,each:[1,2],none:[]}");
+    }
+
+    #[test]
+    fn test_invalid_query_set() {
+        assert_eq!(
+            error_of("[\"length\"]"),
+            "The query set expects object, found list."
+        );
+        assert_eq!(
+            error_of("{total: 1}"),
+            "The query \"total\" of the set expects string, found number."
+        );
+        assert!(error_of("{bad: \"(\"}").starts_with("Invalid query \"bad\" of the set: "));
+        assert_eq!(
+            error_of("{a: \"import \\\"x\\\" as m; 1\", b: \"import \\\"y\\\" as m; 2\"}"),
+            "The module alias \"m\" is imported with different paths in the query set."
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The test cases of the queries, i.e. `aq test tests.aql`, e.g.
+//
+// ```
+// # the names of the adults
+// import "lib/users" as users; users::adults | .name
+// {users: [{name: "alice", age: 25}, {name: "bob", age: 16}]}
+// "alice"
+//
+// %%FAIL
+// .users[] | .name
+// {users: [1]}
+// Cannot index number
+// ```
+//
+// The cases are separated by the blank lines, the lines starting with `#`
+// are comments. A case is made up of the query (one line), the input
+// document (one line, in ASON) and the expected results (one per line,
+// none if the query outputs nothing).
+//
+// A case after the line `%%FAIL` expects the query to fail (either to
+// compile or to run), and the optional line after the input is a part
+// of the expected error message.
+//
+// The results are compared as `aq diff` does, e.g. `1_u8` and `1` are
+// different, and the differences of the results are reported.
+
+use std::fmt::Display;
+
+use crate::{
+    diff::diff_values,
+    error::Error,
+    input::{parse_input, ParseOptions},
+    module::ModuleLoader,
+    query::Query,
+    value::Value,
+};
+
+/// A test case of the query file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TestCase {
+    /// The line number of the query, starts from 1.
+    pub line: usize,
+    pub query: String,
+    pub input: String,
+
+    /// The expected results, or the part of the expected
+    /// error message if the case expects a failure.
+    pub expected: Vec<String>,
+    pub fails: bool,
+}
+
+/// Why a test case does not pass.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TestFailure {
+    pub case: TestCase,
+    pub reason: String,
+}
+
+impl Display for TestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "line {}: {}", self.case.line, self.case.query)?;
+        for line in self.reason.lines() {
+            writeln!(f, "    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Read the test cases of the file (see the module).
+pub fn parse_test_cases(source: &str) -> Result<Vec<TestCase>, Error> {
+    let mut cases = vec![];
+    let mut block: Vec<(usize, &str)> = vec![];
+
+    // a blank line after the last line closes the last case.
+    let lines = source.lines().chain([""]);
+    for (idx, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            if !block.is_empty() {
+                cases.push(test_case(&block)?);
+                block.clear();
+            }
+        } else if !line.trim_start().starts_with('#') {
+            block.push((idx + 1, line.trim_end()));
+        }
+    }
+    Ok(cases)
+}
+
+fn test_case(block: &[(usize, &str)]) -> Result<TestCase, Error> {
+    let (fails, lines) = match block.split_first() {
+        Some((first, rest)) if first.1.trim() == "%%FAIL" => (true, rest),
+        _ => (false, block),
+    };
+
+    match lines {
+        [(line, query), (_, input), expected @ ..] if !fails || expected.len() <= 1 => {
+            Ok(TestCase {
+                line: *line,
+                query: query.to_string(),
+                input: input.to_string(),
+                expected: expected.iter().map(|(_, e)| e.to_string()).collect(),
+                fails,
+            })
+        }
+        [(line, _), _, ..] => Err(Error::Message(format!(
+            "The failing test case at line {} has more than one error message.",
+            line
+        ))),
+        _ => Err(Error::Message(format!(
+            "The test case at line {} has no input document.",
+            block[0].0
+        ))),
+    }
+}
+
+/// Run the test case, the modules of the query are loaded by the `loader`.
+pub fn run_test_case(case: &TestCase, loader: &ModuleLoader) -> Result<(), TestFailure> {
+    let fail = |reason: String| TestFailure {
+        case: case.clone(),
+        reason,
+    };
+
+    let input = parse_input(&case.input, None, &ParseOptions::unlimited())
+        .map_err(|e| fail(format!("The input document is invalid: {}", e)))?;
+    let results =
+        Query::compile_with_loader(&case.query, loader).and_then(|query| query.run_value(input));
+
+    if case.fails {
+        return match results {
+            Ok(results) => Err(fail(format!(
+                "The query is expected to fail, but it outputs:\n{}",
+                list_values(&results)
+            ))),
+            Err(e) => match case.expected.first() {
+                Some(message) if !e.to_string().contains(message.as_str()) => Err(fail(format!(
+                    "The error message is expected to contain \"{}\", found:\n    {}",
+                    message, e
+                ))),
+                _ => Ok(()),
+            },
+        };
+    }
+
+    let results = results.map_err(|e| fail(format!("The query fails: {}", e)))?;
+    let expected = case
+        .expected
+        .iter()
+        .map(|text| parse_input(text, None, &ParseOptions::unlimited()))
+        .collect::<Result<Vec<Value>, Error>>()
+        .map_err(|e| fail(format!("The expected result is invalid: {}", e)))?;
+
+    if expected.len() != results.len() {
+        return Err(fail(format!(
+            "The query is expected to output {} result(s):\n{}\nfound {}:\n{}",
+            expected.len(),
+            list_values(&expected),
+            results.len(),
+            list_values(&results)
+        )));
+    }
+
+    let mut reasons = vec![];
+    for (idx, (expected, actual)) in expected.iter().zip(&results).enumerate() {
+        let changes = diff_values(expected, actual);
+        if !changes.is_empty() {
+            reasons.push(format!(
+                "The result {} is different from the expected:",
+                idx + 1
+            ));
+            reasons.extend(changes.iter().map(|change| format!("    {}", change)));
+        }
+    }
+    match reasons.is_empty() {
+        true => Ok(()),
+        false => Err(fail(reasons.join("\n"))),
+    }
+}
+
+fn list_values(values: &[Value]) -> String {
+    match values.is_empty() {
+        true => "    (nothing)".to_owned(),
+        false => values
+            .iter()
+            .map(|v| format!("    {}", v))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::module::ModuleLoader;
+
+    use super::{parse_test_cases, run_test_case, TestCase};
+
+    fn case(query: &str, input: &str, expected: &[&str], fails: bool) -> TestCase {
+        TestCase {
+            line: 1,
+            query: query.to_owned(),
+            input: input.to_owned(),
+            expected: expected.iter().map(|s| s.to_string()).collect(),
+            fails,
+        }
+    }
+
+    fn run(case: &TestCase) -> Result<(), String> {
+        run_test_case(case, &ModuleLoader::new(vec![])).map_err(|failure| failure.reason)
+    }
+
+    #[test]
+    fn test_parse_test_cases() {
+        let source = r#"# the names
+.[] | .name
+[{name: "foo"}, {name: "bar"}]
+"foo"
+"bar"
+
+  # nothing
+empty
+1
+
+%%FAIL
+.a
+[1]
+Cannot index
+"#;
+        assert_eq!(
+            parse_test_cases(source).unwrap(),
+            vec![
+                TestCase {
+                    line: 2,
+                    ..case(
+                        ".[] | .name",
+                        r#"[{name: "foo"}, {name: "bar"}]"#,
+                        &[r#""foo""#, r#""bar""#],
+                        false
+                    )
+                },
+                TestCase {
+                    line: 8,
+                    ..case("empty", "1", &[], false)
+                },
+                TestCase {
+                    line: 12,
+                    ..case(".a", "[1]", &["Cannot index"], true)
+                },
+            ]
+        );
+
+        assert!(matches!(
+            parse_test_cases("\n.a\n"),
+            Err(e) if e.to_string() == "The test case at line 2 has no input document."
+        ));
+        assert!(matches!(
+            parse_test_cases("%%FAIL\n.a\n[1]\nfoo\nbar"),
+            Err(e) if e.to_string() ==
+                "The failing test case at line 2 has more than one error message."
+        ));
+    }
+
+    #[test]
+    fn test_run_test_case() {
+        assert_eq!(run(&case(".a + 1", "{a: 1}", &["2"], false)), Ok(()));
+        assert_eq!(run(&case("empty", "1", &[], false)), Ok(()));
+        assert_eq!(run(&case(".a", "[1]", &["Cannot index"], true)), Ok(()));
+
+        let reason = run(&case(".a", "{a: 1_u8}", &["1"], false)).unwrap_err();
+        assert!(reason.starts_with("The result 1 is different from the expected:\n    "));
+
+        assert_eq!(
+            run(&case(".[]", "[1, 2]", &["1"], false)),
+            Err(
+                "The query is expected to output 1 result(s):\n    1\nfound 2:\n    1\n    2"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            run(&case(".[0]", "[1]", &[], true)),
+            Err("The query is expected to fail, but it outputs:\n    1".to_owned())
+        );
+        assert_eq!(
+            run(&case(".a", "[1]", &["foo"], true)),
+            Err("The error message is expected to contain \"foo\", found:\n    Cannot index list with string.".to_owned())
+        );
+        assert!(run(&case(".a", "{a: ", &[], false))
+            .unwrap_err()
+            .starts_with("The input document is invalid: "));
+    }
+}
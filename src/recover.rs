@@ -0,0 +1,304 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The tolerant parsing of the input documents, i.e. `aq --tolerant`.
+//
+// When a document fails to parse, and it is a list or an object, the
+// top-level entries are split (at the commas and line breaks which are
+// not nested) and parsed one by one, so a malformed entry does not make
+// the whole document unreadable.
+//
+// The malformed list elements are replaced by the error nodes, e.g.
+//
+// `ParseError::Malformed{line: 12, message: "...", text: "{id: 1, name: }"}`
+//
+// and the malformed object entries are skipped, since their keys
+// are unknown. All the malformed entries are reported.
+
+use std::sync::Arc;
+
+use crate::{
+    error::Error,
+    input::{parse_input, ParseOptions},
+    json::JsonDialect,
+    value::{Items, Map, Value, Variant, VariantValue},
+};
+
+/// A malformed entry of the document.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MalformedEntry {
+    /// The line number of the entry, starts from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+/// Parse the document, the malformed top-level entries are recovered.
+///
+/// The error of the whole document is returned if it can not be
+/// split into entries, i.e. it is neither a list nor an object.
+pub fn parse_tolerant(
+    text: &str,
+    dialect: Option<JsonDialect>,
+    options: &ParseOptions,
+) -> Result<(Value, Vec<MalformedEntry>), Error> {
+    let error = match parse_input(text, dialect, options) {
+        Ok(value) => return Ok((value, vec![])),
+        Err(e) => e,
+    };
+
+    let Some((open, entries)) = split_entries(text) else {
+        return Err(error);
+    };
+
+    let mut malformed = vec![];
+    let value = if open == '[' {
+        let mut items = vec![];
+        for entry in entries {
+            match parse_input(&entry.text, dialect, options) {
+                Ok(value) => items.push(value),
+                Err(e) => {
+                    items.push(error_node(&entry, &e));
+                    malformed.push(MalformedEntry {
+                        line: entry.line,
+                        message: e.to_string(),
+                    });
+                }
+            }
+        }
+        Value::List(Items::from(items))
+    } else {
+        let mut map = Map::new();
+        for entry in entries {
+            match parse_input(&format!("{{{}}}", entry.text), dialect, options) {
+                Ok(Value::Object(entry_map)) => {
+                    for (key, value) in entry_map {
+                        map.insert(key, value);
+                    }
+                }
+                Ok(_) => unreachable!("the braces produce an object"),
+                Err(e) => malformed.push(MalformedEntry {
+                    line: entry.line,
+                    message: e.to_string(),
+                }),
+            }
+        }
+        Value::Object(map)
+    };
+
+    Ok((value, malformed))
+}
+
+fn error_node(entry: &Entry, error: &Error) -> Value {
+    let mut map = Map::new();
+    map.insert("line".to_owned(), Value::from(entry.line));
+    map.insert("message".to_owned(), Value::String(error.to_string()));
+    map.insert("text".to_owned(), Value::String(entry.text.clone()));
+    Value::Variant(Arc::new(Variant {
+        type_name: "ParseError".to_owned(),
+        member_name: "Malformed".to_owned(),
+        value: VariantValue::Object(map),
+    }))
+}
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    line: usize,
+    text: String,
+}
+
+// split the top-level list or object into the texts of the entries,
+// the strings and comments are skipped when counting the brackets.
+fn split_entries(text: &str) -> Option<(char, Vec<Entry>)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut line = 1;
+    let mut pos = 0;
+
+    // the leading whitespaces and comments
+    loop {
+        match chars.get(pos)? {
+            '\n' => line += 1,
+            c if c.is_whitespace() => {}
+            '/' if chars.get(pos + 1) == Some(&'/') => {
+                pos = skip_line_comment(&chars, pos);
+                continue;
+            }
+            '/' if chars.get(pos + 1) == Some(&'*') => {
+                let end = skip_block_comment(&chars, pos);
+                line += count_lines(&chars[pos..end]);
+                pos = end;
+                continue;
+            }
+            _ => break,
+        }
+        pos += 1;
+    }
+
+    let open = chars[pos];
+    let close = match open {
+        '[' => ']',
+        '{' => '}',
+        _ => return None,
+    };
+    pos += 1;
+
+    let mut entries = vec![];
+    let mut current = String::new();
+    let mut current_line = line;
+    let mut depth = 0usize;
+
+    let mut flush = |current: &mut String, current_line: usize| {
+        let trimmed = current.trim();
+        if !trimmed.is_empty() {
+            entries.push(Entry {
+                line: current_line,
+                text: trimmed.to_owned(),
+            });
+        }
+        current.clear();
+    };
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        if current.trim().is_empty() && !c.is_whitespace() && c != ',' {
+            current_line = line;
+        }
+
+        match c {
+            '"' | '\'' => {
+                let end = skip_quoted(&chars, pos, c);
+                current.extend(&chars[pos..end]);
+                line += count_lines(&chars[pos..end]);
+                pos = end;
+                continue;
+            }
+            '/' if chars.get(pos + 1) == Some(&'/') => {
+                pos = skip_line_comment(&chars, pos);
+                continue;
+            }
+            '/' if chars.get(pos + 1) == Some(&'*') => {
+                let end = skip_block_comment(&chars, pos);
+                line += count_lines(&chars[pos..end]);
+                pos = end;
+                continue;
+            }
+            _ if c == close && depth == 0 => {
+                flush(&mut current, current_line);
+                return Some((open, entries));
+            }
+            ',' if depth == 0 => {
+                flush(&mut current, current_line);
+                pos += 1;
+                continue;
+            }
+            '\n' if depth == 0 => {
+                flush(&mut current, current_line);
+                line += 1;
+                pos += 1;
+                continue;
+            }
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth = depth.saturating_sub(1),
+            '\n' => line += 1,
+            _ => {}
+        }
+
+        current.push(c);
+        pos += 1;
+    }
+
+    // the closing bracket is missing
+    flush(&mut current, current_line);
+    Some((open, entries))
+}
+
+// the position after the closing quote.
+fn skip_quoted(chars: &[char], start: usize, quote: char) -> usize {
+    let mut pos = start + 1;
+    while pos < chars.len() {
+        match chars[pos] {
+            '\\' => pos += 2,
+            c if c == quote => return pos + 1,
+            _ => pos += 1,
+        }
+    }
+    chars.len()
+}
+
+// the position of the line break (or the end of the text).
+fn skip_line_comment(chars: &[char], start: usize) -> usize {
+    chars[start..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |offset| start + offset)
+}
+
+// the position after the `*/`.
+fn skip_block_comment(chars: &[char], start: usize) -> usize {
+    let mut pos = start + 2;
+    while pos + 1 < chars.len() {
+        if chars[pos] == '*' && chars[pos + 1] == '/' {
+            return pos + 2;
+        }
+        pos += 1;
+    }
+    chars.len()
+}
+
+fn count_lines(chars: &[char]) -> usize {
+    chars.iter().filter(|c| **c == '\n').count()
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{input::ParseOptions, json::JsonDialect};
+
+    use super::{parse_tolerant, MalformedEntry};
+
+    #[test]
+    fn test_recover_list() {
+        let text = "[\n  {\"a\": 1},\n  {\"a\": },\n  // c\n  {\"a\": \"x,]\"}\n]";
+        let (value, malformed) =
+            parse_tolerant(text, Some(JsonDialect::Json5), &ParseOptions::unlimited()).unwrap();
+        assert_eq!(
+            value.to_string(),
+            "[{a:1},ParseError::Malformed{line:3,message:\"Unexpected char '}'. (at line 1, column 7)\",text:\"{\\\"a\\\": }\"},{a:\"x,]\"}]"
+        );
+        assert_eq!(
+            malformed,
+            [MalformedEntry {
+                line: 3,
+                message: "Unexpected char '}'. (at line 1, column 7)".to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_recover_object() {
+        // the malformed entry is skipped
+        let text = "{\"a\": 1,\n \"b\": [1,\n 2],\n \"c\": oops\n}";
+        let (value, malformed) =
+            parse_tolerant(text, Some(JsonDialect::Json5), &ParseOptions::unlimited()).unwrap();
+        assert_eq!(value.to_string(), "{a:1,b:[1,2]}");
+        assert_eq!(malformed.len(), 1);
+        assert_eq!(malformed[0].line, 4);
+
+        // the well-formed document is parsed as usual
+        let (value, malformed) = parse_tolerant(
+            "[1, 2]",
+            Some(JsonDialect::Json5),
+            &ParseOptions::unlimited(),
+        )
+        .unwrap();
+        assert_eq!(value.to_string(), "[1,2]");
+        assert_eq!(malformed, []);
+
+        assert!(
+            parse_tolerant("oops", Some(JsonDialect::Json5), &ParseOptions::unlimited()).is_err()
+        );
+    }
+}
@@ -0,0 +1,657 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// A small regular expression engine, it is used by `aq grep` and the
+// `paths_with_key(re; flags)` builtin.
+//
+// The pattern is compiled to the instructions which are run by a Pike VM,
+// so the time is linear to the length of the text (for a pattern), and
+// neither the long text nor the pattern like `(a+)+b` exhausts the stack.
+//
+// The supported syntax:
+//
+// - literals, `.` (any char except the line feed), `^`, `$` and `\b`.
+// - classes, e.g. `[a-z_]`, `[^0-9]`, and `\d`, `\w`, `\s`, `\D`, `\W`, `\S`.
+// - groups `(...)` and `(?:...)` with alternatives `a|b`.
+// - quantifiers `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}`, and the lazy
+//   ones with the suffix `?`, e.g. `*?`.
+//
+// Groups do not capture, since only the matching (and the matched range)
+// is needed.
+
+use crate::error::Error;
+
+#[derive(Debug, PartialEq, Clone)]
+enum Node {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    WordBoundary,
+    Group(Vec<Vec<Node>>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Char(expected) => c == *expected,
+            ClassItem::Range(from, to) => (*from..=*to).contains(&c),
+            ClassItem::Digit(positive) => c.is_ascii_digit() == *positive,
+            ClassItem::Word(positive) => is_word_char(c) == *positive,
+            ClassItem::Space(positive) => c.is_whitespace() == *positive,
+        }
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// the size of the compiled program is limited, since the bounded
+// repetitions are expanded, e.g. `(a{1000}){1000}`.
+const MAX_PROGRAM_SIZE: usize = 100_000;
+
+// the nesting of the groups is limited, since they are parsed recursively.
+const MAX_GROUP_DEPTH: usize = 256;
+
+// The instructions of the compiled pattern, the targets are the
+// indices of the instructions.
+#[derive(Debug, PartialEq, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class {
+        items: Vec<ClassItem>,
+        negated: bool,
+    },
+    Start,
+    End,
+    WordBoundary,
+
+    // try the first target, then the second one
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Regex {
+    program: Vec<Inst>,
+    ignore_case: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Self, Error> {
+        let mut parser = RegexParser {
+            pattern,
+            chars: pattern.chars().collect(),
+            position: 0,
+            depth: 0,
+        };
+
+        let alternatives = parser.parse_alternatives()?;
+        if parser.position < parser.chars.len() {
+            return Err(parser.error("unmatched \")\""));
+        }
+
+        let mut program = vec![];
+        compile_group(&alternatives, &mut program);
+        program.push(Inst::Match);
+        if program.len() > MAX_PROGRAM_SIZE {
+            return Err(Error::Message(format!(
+                "Invalid regular expression \"{}\": the pattern is too large.",
+                pattern
+            )));
+        }
+
+        Ok(Self {
+            program,
+            ignore_case,
+        })
+    }
+
+    /// Whether the text contains a match, it stops at the first one
+    /// which is found, i.e. the range of the match is not settled.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.search(text, true).is_some()
+    }
+
+    /// The range (in chars) of the first (leftmost) match.
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.search(text, false)
+    }
+
+    fn search(&self, text: &str, earliest: bool) -> Option<(usize, usize)> {
+        let chars: Vec<char> = text.chars().collect();
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut found = None;
+
+        for pos in 0..=chars.len() {
+            // a new attempt starts at each position until a match is
+            // found, it has the lowest priority.
+            if found.is_none() {
+                self.add_thread(&mut current, 0, pos, pos, &chars);
+            }
+            if current.list.is_empty() {
+                if found.is_some() {
+                    break;
+                }
+                continue;
+            }
+
+            for &(pc, start) in &current.list {
+                let matched = match &self.program[pc] {
+                    Inst::Match if earliest => return Some((start, pos)),
+                    Inst::Match => {
+                        // the threads after it have lower priorities
+                        found = Some((start, pos));
+                        break;
+                    }
+                    Inst::Char(expected) => chars
+                        .get(pos)
+                        .is_some_and(|c| self.char_matches(*c, |c| c == *expected)),
+                    Inst::Any => chars.get(pos).is_some_and(|c| *c != '\n'),
+                    Inst::Class { items, negated } => chars.get(pos).is_some_and(|c| {
+                        self.char_matches(*c, |c| items.iter().any(|item| item.matches(c)))
+                            != *negated
+                    }),
+                    _ => false,
+                };
+                if matched {
+                    self.add_thread(&mut next, pc + 1, start, pos + 1, &chars);
+                }
+            }
+
+            std::mem::swap(&mut current, &mut next);
+            next.list.clear();
+        }
+
+        found
+    }
+
+    // add the thread and the threads which it reaches without consuming
+    // any char, in the order of their priorities.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        start: usize,
+        pos: usize,
+        chars: &[char],
+    ) {
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            // the lower priority thread of the same instruction is dropped,
+            // which also stops the empty loops, e.g. `(a*)*`.
+            if threads.marks[pc] == pos + 1 {
+                continue;
+            }
+            threads.marks[pc] = pos + 1;
+
+            match &self.program[pc] {
+                Inst::Split(first, second) => {
+                    stack.push(*second);
+                    stack.push(*first);
+                }
+                Inst::Jump(target) => stack.push(*target),
+                Inst::Start if pos == 0 => stack.push(pc + 1),
+                Inst::End if pos == chars.len() => stack.push(pc + 1),
+                Inst::WordBoundary => {
+                    let before = pos > 0 && is_word_char(chars[pos - 1]);
+                    let after = chars.get(pos).is_some_and(|c| is_word_char(*c));
+                    if before != after {
+                        stack.push(pc + 1);
+                    }
+                }
+                Inst::Start | Inst::End => {}
+                _ => threads.list.push((pc, start)),
+            }
+        }
+    }
+
+    fn char_matches(&self, c: char, test: impl Fn(char) -> bool) -> bool {
+        test(c)
+            || (self.ignore_case && (c.to_lowercase().any(&test) || c.to_uppercase().any(&test)))
+    }
+}
+
+// the threads of a step of the VM, i.e. the instructions and the
+// start positions of the matches.
+struct Threads {
+    list: Vec<(usize, usize)>,
+
+    // the (position + 1) which the instruction is added at
+    marks: Vec<usize>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Self {
+        Self {
+            list: Vec::with_capacity(size),
+            marks: vec![0; size],
+        }
+    }
+}
+
+fn compile_group(alternatives: &[Vec<Node>], program: &mut Vec<Inst>) {
+    let mut jumps = vec![];
+    for (idx, sequence) in alternatives.iter().enumerate() {
+        let is_last = idx + 1 == alternatives.len();
+        let split = program.len();
+        if !is_last {
+            program.push(Inst::Split(split + 1, 0));
+        }
+        compile_sequence(sequence, program);
+        if !is_last {
+            jumps.push(program.len());
+            program.push(Inst::Jump(0));
+            program[split] = Inst::Split(split + 1, program.len());
+        }
+    }
+
+    let end = program.len();
+    for idx in jumps {
+        program[idx] = Inst::Jump(end);
+    }
+}
+
+fn compile_sequence(nodes: &[Node], program: &mut Vec<Inst>) {
+    for node in nodes {
+        // stop expanding the huge repetitions, the size is checked later
+        if program.len() > MAX_PROGRAM_SIZE {
+            return;
+        }
+        compile_node(node, program);
+    }
+}
+
+fn compile_node(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class { items, negated } => program.push(Inst::Class {
+            items: items.clone(),
+            negated: *negated,
+        }),
+        Node::Start => program.push(Inst::Start),
+        Node::End => program.push(Inst::End),
+        Node::WordBoundary => program.push(Inst::WordBoundary),
+        Node::Group(alternatives) => compile_group(alternatives, program),
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                if program.len() > MAX_PROGRAM_SIZE {
+                    return;
+                }
+                compile_node(node, program);
+            }
+
+            match max {
+                None => {
+                    // L: split body, out; body; jump L
+                    let head = program.len();
+                    program.push(Inst::Jump(0));
+                    compile_node(node, program);
+                    program.push(Inst::Jump(head));
+                    let out = program.len();
+                    split_at(program, head, head + 1, out, *greedy);
+                }
+                Some(max) => {
+                    // each optional iteration skips the rest ones
+                    let mut splits = vec![];
+                    for _ in *min..*max {
+                        if program.len() > MAX_PROGRAM_SIZE {
+                            return;
+                        }
+                        splits.push(program.len());
+                        program.push(Inst::Jump(0));
+                        compile_node(node, program);
+                    }
+                    let out = program.len();
+                    for idx in splits {
+                        split_at(program, idx, idx + 1, out, *greedy);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn split_at(program: &mut [Inst], idx: usize, body: usize, out: usize, greedy: bool) {
+    program[idx] = match greedy {
+        true => Inst::Split(body, out),
+        false => Inst::Split(out, body),
+    };
+}
+
+struct RegexParser<'a> {
+    pattern: &'a str,
+    chars: Vec<char>,
+    position: usize,
+    depth: usize,
+}
+
+impl RegexParser<'_> {
+    fn error(&self, message: &str) -> Error {
+        Error::Message(format!(
+            "Invalid regular expression \"{}\": {} (at position {}).",
+            self.pattern, message, self.position
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.position += 1;
+        c
+    }
+
+    fn consume(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternatives(&mut self) -> Result<Vec<Vec<Node>>, Error> {
+        let mut alternatives = vec![self.parse_sequence()?];
+        while self.consume('|') {
+            alternatives.push(self.parse_sequence()?);
+        }
+        Ok(alternatives)
+    }
+
+    fn parse_sequence(&mut self) -> Result<Vec<Node>, Error> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifier(atom)?);
+        }
+        Ok(nodes)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, Error> {
+        let c = self.next_char().unwrap_or_default();
+        let node = match c {
+            '.' => Node::Any,
+            '^' => Node::Start,
+            '$' => Node::End,
+            '(' => {
+                if self.depth >= MAX_GROUP_DEPTH {
+                    return Err(self.error("too many nested groups"));
+                }
+                // the groups do not capture, so `(?:` is the same as `(`
+                if self.peek() == Some('?') {
+                    self.position += 1;
+                    if !self.consume(':') {
+                        return Err(self.error("only the group \"(?:...)\" is supported"));
+                    }
+                }
+
+                self.depth += 1;
+                let alternatives = self.parse_alternatives()?;
+                self.depth -= 1;
+                if !self.consume(')') {
+                    return Err(self.error("unclosed group"));
+                }
+                Node::Group(alternatives)
+            }
+            '[' => self.parse_class()?,
+            '\\' if self.consume('b') => Node::WordBoundary,
+            '\\' => match self.parse_escape()? {
+                ClassItem::Char(c) => Node::Char(c),
+                item => Node::Class {
+                    items: vec![item],
+                    negated: false,
+                },
+            },
+            '*' | '+' | '?' => {
+                self.position -= 1;
+                return Err(self.error("nothing to repeat"));
+            }
+            _ => Node::Char(c),
+        };
+        Ok(node)
+    }
+
+    fn parse_escape(&mut self) -> Result<ClassItem, Error> {
+        let item = match self.next_char() {
+            Some('d') => ClassItem::Digit(true),
+            Some('D') => ClassItem::Digit(false),
+            Some('w') => ClassItem::Word(true),
+            Some('W') => ClassItem::Word(false),
+            Some('s') => ClassItem::Space(true),
+            Some('S') => ClassItem::Space(false),
+            Some('n') => ClassItem::Char('\n'),
+            Some('r') => ClassItem::Char('\r'),
+            Some('t') => ClassItem::Char('\t'),
+            Some(c) if !c.is_alphanumeric() => ClassItem::Char(c),
+            Some(c) => return Err(self.error(&format!("unsupported escape \"\\{}\"", c))),
+            None => return Err(self.error("incomplete escape")),
+        };
+        Ok(item)
+    }
+
+    fn parse_class(&mut self) -> Result<Node, Error> {
+        let negated = self.consume('^');
+        let mut items = vec![];
+
+        loop {
+            let c = match self.next_char() {
+                // the `]` at the beginning is a literal
+                Some(']') if !items.is_empty() => break,
+                Some('\\') => {
+                    if self.peek() == Some('b') {
+                        self.position += 1;
+                        items.push(ClassItem::Char('\u{8}'));
+                        continue;
+                    }
+                    match self.parse_escape()? {
+                        ClassItem::Char(c) => c,
+                        item => {
+                            items.push(item);
+                            continue;
+                        }
+                    }
+                }
+                Some(c) => c,
+                None => return Err(self.error("unclosed class")),
+            };
+
+            let is_range = self.peek() == Some('-')
+                && self.chars.get(self.position + 1).is_some_and(|c| *c != ']');
+            if is_range {
+                self.position += 1;
+                let to = match self.next_char() {
+                    Some('\\') => match self.parse_escape()? {
+                        ClassItem::Char(c) => c,
+                        _ => return Err(self.error("invalid range")),
+                    },
+                    Some(c) => c,
+                    None => return Err(self.error("unclosed class")),
+                };
+                if to < c {
+                    return Err(self.error("invalid range"));
+                }
+                items.push(ClassItem::Range(c, to));
+            } else {
+                items.push(ClassItem::Char(c));
+            }
+        }
+
+        Ok(Node::Class { items, negated })
+    }
+
+    fn parse_quantifier(&mut self, atom: Node) -> Result<Node, Error> {
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.parse_bounds()? {
+                Some(bounds) => bounds,
+                // `{` which is not a quantifier is a literal, e.g. `a{b`
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+
+        if matches!(atom, Node::Start | Node::End | Node::WordBoundary) {
+            return Err(self.error("nothing to repeat"));
+        }
+        self.position += 1;
+
+        let greedy = !self.consume('?');
+        Ok(Node::Repeat {
+            node: Box::new(atom),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    // parse `{n}`, `{n,}` and `{n,m}`, the position stays at the `}`.
+    fn parse_bounds(&mut self) -> Result<Option<(usize, Option<usize>)>, Error> {
+        let close = match self.chars[self.position..].iter().position(|c| *c == '}') {
+            Some(offset) => self.position + offset,
+            None => return Ok(None),
+        };
+
+        let content: String = self.chars[self.position + 1..close].iter().collect();
+        let parse = |s: &str| s.trim().parse::<usize>().ok();
+        let bounds = match content.split_once(',') {
+            None => parse(&content).map(|n| (n, Some(n))),
+            Some((min, max)) if max.trim().is_empty() => parse(min).map(|n| (n, None)),
+            Some((min, max)) => parse(min).zip(parse(max)).map(|(a, b)| (a, Some(b))),
+        };
+
+        match bounds {
+            Some((min, Some(max))) if max < min => Err(self.error("invalid repetition bounds")),
+            Some(bounds) => {
+                self.position = close;
+                Ok(Some(bounds))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::Regex;
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        Regex::new(pattern, false).unwrap().find(text)
+    }
+
+    fn error_of(pattern: &str) -> String {
+        Regex::new(pattern, false).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_find() {
+        assert_eq!(find("b+", "abbbc"), Some((1, 4)));
+        assert_eq!(find("b+?", "abbbc"), Some((1, 2)));
+        assert_eq!(find("^a.c$", "abc"), Some((0, 3)));
+        assert_eq!(find("^a.c$", "abcd"), None);
+        assert_eq!(find("(foo|ba[rz])+!", "xbazfoo!"), Some((1, 8)));
+        assert_eq!(find(r"\d{2,3}", "a1234"), Some((1, 4)));
+        assert_eq!(find(r"[^a-c\s]", "ab d"), Some((3, 4)));
+        assert_eq!(find(r"\bcat\b", "concat cat"), Some((7, 10)));
+        assert_eq!(find("(a*)*b", "aaab"), Some((0, 4)));
+        assert_eq!(find(r"@example\.com$", "x@example.com"), Some((1, 13)));
+
+        // the brace which is not a repetition is literal
+        assert_eq!(find("a{b", "a{b"), Some((0, 3)));
+
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("(a|ab)c", "abc"), Some((0, 3)));
+        assert_eq!(find("a{2}?b", "aab"), Some((0, 3)));
+        assert_eq!(find("x*", "abc"), Some((0, 0)));
+        assert_eq!(find("a.{1,2}?c", "abbcc"), Some((0, 4)));
+    }
+
+    #[test]
+    fn test_long_text() {
+        // the matching does not recurse per char, nor backtrack
+        let text = format!("{}b", "a".repeat(100_000));
+        assert_eq!(find("a.*b", &text), Some((0, 100_001)));
+        assert_eq!(find("^(a+)+b", &text), Some((0, 100_001)));
+        assert_eq!(find("(a+)+c", &text), None);
+        assert_eq!(find("(a+)+b", &"a".repeat(24)), None);
+        assert_eq!(find("^(a|aa)*$", &text), None);
+    }
+
+    #[test]
+    fn test_is_match() {
+        let regex = Regex::new("(a+)+b|c", false).unwrap();
+        assert!(regex.is_match("xaab"));
+        assert!(regex.is_match(&format!("{}c", "a".repeat(100_000))));
+        assert!(!regex.is_match(&"a".repeat(100_000)));
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        assert!(Regex::new("HELLO", true).unwrap().is_match("say hello"));
+        assert!(Regex::new("[A-Z]+", true).unwrap().is_match("abc"));
+        assert!(!Regex::new("[A-Z]+", false).unwrap().is_match("abc"));
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert_eq!(
+            error_of("(ab"),
+            "Invalid regular expression \"(ab\": unclosed group (at position 3)."
+        );
+        assert_eq!(
+            error_of("*a"),
+            "Invalid regular expression \"*a\": nothing to repeat (at position 0)."
+        );
+        assert_eq!(
+            error_of("a)"),
+            "Invalid regular expression \"a)\": unmatched \")\" (at position 1)."
+        );
+        assert_eq!(
+            error_of("(a{1000}){1000}"),
+            "Invalid regular expression \"(a{1000}){1000}\": the pattern is too large."
+        );
+        assert!(error_of(&"(".repeat(100_000)).contains("too many nested groups"));
+    }
+}
@@ -0,0 +1,273 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The round-trip fidelity check, i.e. `aq roundtrip <file>`.
+//
+// The document is parsed, printed and parsed again, then the two
+// documents are compared. Since `aq` writes the output with the ASON
+// printer, whatever is lost in the round trip would also be lost when
+// the file is rewritten by `aq`.
+
+use ason::ast::{parser::parse_from_str, printer::print_to_string};
+
+use crate::{
+    path::path_to_string,
+    scan::{is_word_byte, Class, Scanner},
+    value::Value,
+};
+
+/// The information lost in the round trip.
+#[derive(Debug, PartialEq)]
+pub enum Loss {
+    /// The value at the path is changed.
+    Value {
+        path: Vec<Value>,
+        original: Value,
+        reprinted: Value,
+    },
+
+    /// The order of the keys of the object at the path is changed.
+    KeyOrder(Vec<Value>),
+
+    /// A comment at the line is dropped.
+    Comment(usize),
+
+    /// A number written in hexadecimal, octal or binary at the line
+    /// is reprinted in decimal.
+    Radix(usize, String),
+}
+
+impl Loss {
+    pub fn is_semantic(&self) -> bool {
+        matches!(self, Loss::Value { .. })
+    }
+}
+
+impl std::fmt::Display for Loss {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Loss::Value {
+                path,
+                original,
+                reprinted,
+            } => write!(
+                f,
+                "value changed at {}: {} => {}",
+                path_to_string(path),
+                original,
+                reprinted
+            ),
+            Loss::KeyOrder(path) => write!(f, "key order changed at {}", path_to_string(path)),
+            Loss::Comment(line) => write!(f, "comment dropped at line {}", line),
+            Loss::Radix(line, literal) => {
+                write!(f, "radix of number {} lost at line {}", literal, line)
+            }
+        }
+    }
+}
+
+/// Run the round trip on the source text, the semantic differences
+/// come first in the result.
+pub fn check_text(source: &str) -> Result<Vec<Loss>, String> {
+    let original = parse_from_str(source).map_err(|e| e.with_source(source))?;
+    let reprinted_text = print_to_string(&original);
+    let reprinted = parse_from_str(&reprinted_text).map_err(|e| {
+        format!(
+            "The reprinted document can not be parsed.\n{}",
+            e.with_source(&reprinted_text)
+        )
+    })?;
+
+    let mut losses = vec![];
+    compare_values(
+        &mut vec![],
+        &Value::from(original),
+        &Value::from(reprinted),
+        &mut losses,
+    );
+    scan_source(source, &mut losses);
+    Ok(losses)
+}
+
+fn compare_values(path: &mut Vec<Value>, a: &Value, b: &Value, losses: &mut Vec<Loss>) {
+    match (a, b) {
+        (Value::List(xs), Value::List(ys)) | (Value::Tuple(xs), Value::Tuple(ys))
+            if xs.len() == ys.len() =>
+        {
+            for (idx, (x, y)) in xs.iter().zip(ys).enumerate() {
+                path.push(Value::from(idx));
+                compare_values(path, x, y, losses);
+                path.pop();
+            }
+        }
+        (Value::Object(xs), Value::Object(ys)) if xs.len() == ys.len() => {
+            if xs.keys().ne(ys.keys()) {
+                if xs.keys().all(|k| ys.contains_key(k)) {
+                    losses.push(Loss::KeyOrder(path.clone()));
+                } else {
+                    losses.push(value_loss(path, a, b));
+                    return;
+                }
+            }
+
+            for (key, x) in xs.iter() {
+                if let Some(y) = ys.get(key) {
+                    path.push(Value::String(key.clone()));
+                    compare_values(path, x, y, losses);
+                    path.pop();
+                }
+            }
+        }
+        // the `NaN` is not equal to itself
+        (Value::Number(x), Value::Number(y))
+            if x.is_float() && x.as_f64().is_nan() && y.as_f64().is_nan() => {}
+        _ => {
+            // the numbers of different types, e.g. `1_u8` and `1`, are equal
+            // in queries, but they are not the same in the document.
+            if a != b || a.to_string() != b.to_string() {
+                losses.push(value_loss(path, a, b));
+            }
+        }
+    }
+}
+
+fn value_loss(path: &[Value], a: &Value, b: &Value) -> Loss {
+    Loss::Value {
+        path: path.to_vec(),
+        original: a.clone(),
+        reprinted: b.clone(),
+    }
+}
+
+// Find the comments and the numbers in hexadecimal, octal or binary
+// of the source text, the printer does not keep them.
+fn scan_source(source: &str, losses: &mut Vec<Loss>) {
+    let bytes = source.as_bytes();
+    let mut scanner = Scanner::new();
+    let mut line = 1;
+
+    // the start of the current word, and whether it is a number in radix.
+    let mut word: Option<(usize, bool)> = None;
+
+    for (idx, &byte) in bytes.iter().enumerate() {
+        let class = scanner.scan(byte);
+        if class == Class::CommentStart {
+            losses.push(Loss::Comment(line));
+        }
+
+        let in_word = class == Class::Code && is_word_byte(byte);
+        match word {
+            Some((start, radix)) if !in_word => {
+                if radix {
+                    losses.push(Loss::Radix(line, source[start..idx].to_owned()));
+                }
+                word = None;
+            }
+            None if in_word => {
+                // only a word starts a number, so the digits in the
+                // identifiers (e.g. `a0x1`) are not taken as numbers.
+                let radix = byte == b'0' && matches!(bytes.get(idx + 1), Some(b'x' | b'o' | b'b'));
+                word = Some((idx, radix));
+            }
+            _ => {}
+        }
+
+        if byte == b'\n' {
+            line += 1;
+        }
+    }
+
+    if let Some((start, true)) = word {
+        losses.push(Loss::Radix(line, source[start..].to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::{Number, Value},
+    };
+
+    use super::{check_text, compare_values, scan_source, Loss};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    fn compare(a: &str, b: &str) -> Vec<String> {
+        let mut losses = vec![];
+        compare_values(&mut vec![], &value(a), &value(b), &mut losses);
+        losses.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn test_compare_values() {
+        assert_eq!(compare("{a: [1, 2]}", "{a: [1, 2]}"), Vec::<String>::new());
+        assert_eq!(
+            compare("{a: [1, 2]}", "{a: [1, 3]}"),
+            ["value changed at .a[1]: 2 => 3"]
+        );
+        assert_eq!(
+            compare("{a: 1, b: 2}", "{b: 2, a: 1}"),
+            ["key order changed at ."]
+        );
+
+        // the numbers of different types
+        let mut losses = vec![];
+        compare_values(
+            &mut vec![],
+            &Value::Number(Number::U8(1)),
+            &Value::from(1),
+            &mut losses,
+        );
+        assert!(losses[0].is_semantic());
+    }
+
+    #[test]
+    fn test_scan_source() {
+        let source = "{\n    // the id\n    id: 0x1F\n    name: \"// 0x20\" /* the name */\n}";
+        let mut losses = vec![];
+        scan_source(source, &mut losses);
+        assert_eq!(
+            losses,
+            [
+                Loss::Comment(2),
+                Loss::Radix(3, "0x1F".to_owned()),
+                Loss::Comment(4)
+            ]
+        );
+
+        let source = "[/* a /* 0x1 */ */ 0b1, a0x1, r\"0o1\"]\n// b\n0o7";
+        let mut losses = vec![];
+        scan_source(source, &mut losses);
+        assert_eq!(
+            losses,
+            [
+                Loss::Comment(1),
+                Loss::Radix(1, "0b1".to_owned()),
+                Loss::Comment(2),
+                Loss::Radix(3, "0o7".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_text() {
+        let losses = check_text("{\n    id: 0x10 // the id\n}").unwrap();
+        assert!(losses.iter().all(|l| !l.is_semantic()));
+        assert_eq!(
+            losses.iter().map(|l| l.to_string()).collect::<Vec<_>>(),
+            [
+                "radix of number 0x10 lost at line 2",
+                "comment dropped at line 2"
+            ]
+        );
+        assert!(check_text("{id: ").is_err());
+    }
+}
@@ -0,0 +1,976 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Checking documents against the Rust type definitions,
+// i.e. `aq schema check --types <types.rs> <file(s)>`.
+//
+// Only the `struct` and `enum` items of the Rust source file are read,
+// the other items (e.g. `impl`, `fn` and `use`) are skipped. The layout
+// follows the serde data model of the `ason` crate:
+//
+// - structs with named fields are objects, newtype structs are the inner
+//   value, tuple structs are tuples.
+// - enum members are variants, e.g. `Color::Red`, `Shape::Circle(1.0)`
+//   and `Shape::Rect{width: 1.0, height: 2.0}`.
+// - `Option<T>` is `Option::None` or `Option::Some(T)`, the fields of
+//   this type can be omitted.
+// - `Vec<T>`, `[T; N]` and the sets are lists, `(A, B)` are tuples,
+//   and the maps with string keys are objects.
+//
+// The serde attributes `rename`, `default` and `skip` are supported.
+
+use std::fmt::Display;
+
+use crate::{
+    path::path_to_string,
+    value::{Map, Number, Value, VariantValue},
+};
+
+#[derive(Debug, PartialEq, Clone)]
+enum RustType {
+    Bool,
+    Char,
+    String,
+    Integer(&'static str),
+    Float,
+    DateTime,
+    Bytes,
+    List(Box<RustType>),
+    Array(Box<RustType>, usize),
+    Tuple(Vec<RustType>),
+    Map(Box<RustType>),
+    Option(Box<RustType>),
+    Named(String),
+
+    // a generic parameter, any value is accepted.
+    Any,
+}
+
+#[derive(Debug, PartialEq)]
+struct Field {
+    name: String,
+    field_type: RustType,
+    optional: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Shape {
+    Unit,
+    Tuple(Vec<RustType>),
+    Fields(Vec<Field>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Definition {
+    Struct(String, Shape),
+    Enum(String, Vec<(String, Shape)>),
+}
+
+impl Definition {
+    fn name(&self) -> &str {
+        match self {
+            Definition::Struct(name, _) | Definition::Enum(name, _) => name,
+        }
+    }
+}
+
+/// A value which does not match the type.
+#[derive(Debug, PartialEq)]
+pub struct Mismatch {
+    pub path: Vec<Value>,
+    pub message: String,
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", path_to_string(&self.path), self.message)
+    }
+}
+
+/// The type definitions read from a Rust source file.
+pub struct TypeSet {
+    definitions: Vec<Definition>,
+}
+
+impl TypeSet {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = ItemParser { tokens, index: 0 };
+        let definitions = parser.parse_items()?;
+        if definitions.is_empty() {
+            return Err("No struct or enum is defined in the types file.".to_owned());
+        }
+        Ok(Self { definitions })
+    }
+
+    /// Check the value against the type, the first type
+    /// in the file is used if the name is not specified.
+    pub fn check(&self, type_name: Option<&str>, value: &Value) -> Result<Vec<Mismatch>, String> {
+        let name = match type_name {
+            Some(name) => {
+                if self.find(name).is_none() {
+                    return Err(format!(
+                        "Type \"{}\" is not defined in the types file.",
+                        name
+                    ));
+                }
+                name.to_owned()
+            }
+            None => self.definitions[0].name().to_owned(),
+        };
+
+        let mut checker = Checker {
+            types: self,
+            path: vec![],
+            mismatches: vec![],
+        };
+        checker.check(&RustType::Named(name), value);
+        Ok(checker.mismatches)
+    }
+
+    fn find(&self, name: &str) -> Option<&Definition> {
+        self.definitions.iter().find(|d| d.name() == name)
+    }
+}
+
+struct Checker<'a> {
+    types: &'a TypeSet,
+    path: Vec<Value>,
+    mismatches: Vec<Mismatch>,
+}
+
+impl Checker<'_> {
+    fn mismatch(&mut self, message: String) {
+        self.mismatches.push(Mismatch {
+            path: self.path.clone(),
+            message,
+        });
+    }
+
+    fn expect(&mut self, expected: &str, value: &Value) {
+        self.mismatch(format!("expect {}, found {}", expected, value));
+    }
+
+    fn check(&mut self, rust_type: &RustType, value: &Value) {
+        match (rust_type, value) {
+            (RustType::Any, _) => {}
+            (RustType::Bool, Value::Boolean(_)) => {}
+            (RustType::Char, Value::Char(_)) => {}
+            (RustType::String, Value::String(_)) => {}
+            (RustType::DateTime, Value::DateTime(_)) => {}
+            (RustType::Float, Value::Number(n)) if n.is_float() => {}
+            (RustType::Integer(suffix), Value::Number(n)) => {
+                let fitted = n.as_i128().is_some_and(|i| {
+                    Number::from_i128_like(i, Some(&integer_template(suffix))).type_suffix()
+                        == *suffix
+                });
+                if !fitted {
+                    self.expect(&format!("an integer of type {}", suffix), value);
+                }
+            }
+            (RustType::Bytes, Value::ByteData(_)) => {}
+            (RustType::Bytes, Value::List(_)) => {
+                self.check(&RustType::List(Box::new(RustType::Integer("u8"))), value)
+            }
+            (RustType::List(item_type), Value::List(items)) => self.check_items(item_type, items),
+            (RustType::Array(item_type, length), Value::List(items)) => {
+                if items.len() != *length {
+                    self.mismatch(format!(
+                        "expect {} items, found {} items",
+                        length,
+                        items.len()
+                    ));
+                }
+                self.check_items(item_type, items);
+            }
+            (RustType::Tuple(types), Value::Tuple(items)) => self.check_tuple(types, items),
+            (RustType::Map(value_type), Value::Object(map)) => {
+                for (key, item) in map.iter() {
+                    self.path.push(Value::String(key.clone()));
+                    self.check(value_type, item);
+                    self.path.pop();
+                }
+            }
+            (RustType::Option(_), _) if value.is_null() => {}
+            (RustType::Option(inner), Value::Variant(v))
+                if v.type_name == "Option" && v.member_name == "Some" =>
+            {
+                if let VariantValue::Value(inner_value) = &v.value {
+                    self.check(inner, inner_value);
+                } else {
+                    self.expect("Option::Some(...)", value);
+                }
+            }
+            (RustType::Named(name), _) => self.check_named(name, value),
+            _ => self.expect(&describe(rust_type), value),
+        }
+    }
+
+    fn check_items(&mut self, item_type: &RustType, items: &[Value]) {
+        for (idx, item) in items.iter().enumerate() {
+            self.path.push(Value::from(idx));
+            self.check(item_type, item);
+            self.path.pop();
+        }
+    }
+
+    fn check_tuple(&mut self, types: &[RustType], items: &[Value]) {
+        if types.len() != items.len() {
+            self.mismatch(format!(
+                "expect a tuple of {} items, found {} items",
+                types.len(),
+                items.len()
+            ));
+            return;
+        }
+
+        for (idx, (t, item)) in types.iter().zip(items).enumerate() {
+            self.path.push(Value::from(idx));
+            self.check(t, item);
+            self.path.pop();
+        }
+    }
+
+    fn check_fields(&mut self, fields: &[Field], map: &Map) {
+        for field in fields {
+            match map.get(&field.name) {
+                Some(item) => {
+                    self.path.push(Value::String(field.name.clone()));
+                    self.check(&field.field_type, item);
+                    self.path.pop();
+                }
+                None if field.optional => {}
+                None => self.mismatch(format!("missing field \"{}\"", field.name)),
+            }
+        }
+
+        for key in map.keys() {
+            if !fields.iter().any(|f| f.name == *key) {
+                self.mismatch(format!("unknown field \"{}\"", key));
+            }
+        }
+    }
+
+    fn check_named(&mut self, name: &str, value: &Value) {
+        let types = self.types;
+        match types.find(name) {
+            None => self.mismatch(format!(
+                "type \"{}\" is not defined in the types file",
+                name
+            )),
+            Some(Definition::Struct(_, shape)) => match (shape, value) {
+                (Shape::Fields(fields), Value::Object(map)) => self.check_fields(fields, map),
+                (Shape::Tuple(types), _) if types.len() == 1 => self.check(&types[0], value),
+                (Shape::Tuple(types), Value::Tuple(items)) => self.check_tuple(types, items),
+                (Shape::Unit, Value::Tuple(items)) if items.is_empty() => {}
+                _ => self.expect(&format!("struct \"{}\"", name), value),
+            },
+            Some(Definition::Enum(_, members)) => {
+                let v = match value {
+                    Value::Variant(v) if v.type_name == name => v,
+                    _ => return self.expect(&format!("a member of enum \"{}\"", name), value),
+                };
+
+                let shape = match members.iter().find(|(m, _)| *m == v.member_name) {
+                    Some((_, shape)) => shape,
+                    None => {
+                        return self.mismatch(format!(
+                            "enum \"{}\" has no member \"{}\"",
+                            name, v.member_name
+                        ))
+                    }
+                };
+
+                match (shape, &v.value) {
+                    (Shape::Unit, VariantValue::Empty) => {}
+                    (Shape::Tuple(types), VariantValue::Value(inner)) if types.len() == 1 => {
+                        self.check(&types[0], inner)
+                    }
+                    (Shape::Tuple(types), VariantValue::Tuple(items)) => {
+                        self.check_tuple(types, items)
+                    }
+                    (Shape::Fields(fields), VariantValue::Object(map)) => {
+                        self.check_fields(fields, map)
+                    }
+                    _ => self.expect(
+                        &format!("the member \"{}::{}\"", name, v.member_name),
+                        value,
+                    ),
+                }
+            }
+        }
+    }
+}
+
+fn integer_template(suffix: &str) -> Number {
+    match suffix {
+        "i8" => Number::I8(0),
+        "u8" => Number::U8(0),
+        "i16" => Number::I16(0),
+        "u16" => Number::U16(0),
+        "i32" => Number::I32(0),
+        "u32" => Number::U32(0),
+        "i64" => Number::I64(0),
+        _ => Number::U64(0),
+    }
+}
+
+fn describe(rust_type: &RustType) -> String {
+    match rust_type {
+        RustType::Bool => "a boolean".to_owned(),
+        RustType::Char => "a char".to_owned(),
+        RustType::String => "a string".to_owned(),
+        RustType::Integer(suffix) => format!("an integer of type {}", suffix),
+        RustType::Float => "a floating-point number".to_owned(),
+        RustType::DateTime => "a date time".to_owned(),
+        RustType::Bytes => "byte data".to_owned(),
+        RustType::List(_) | RustType::Array(..) => "a list".to_owned(),
+        RustType::Tuple(types) => format!("a tuple of {} items", types.len()),
+        RustType::Map(_) => "an object".to_owned(),
+        RustType::Option(_) => "an Option".to_owned(),
+        RustType::Named(name) => format!("type \"{}\"", name),
+        RustType::Any => "any value".to_owned(),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Identifier(String),
+    Lifetime,
+    Literal(String),
+    Punct(char),
+}
+
+// split the Rust source into tokens, the comments are dropped.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            '\n' => {
+                line += 1;
+                i += 1;
+            }
+            _ if c.is_whitespace() => i += 1,
+            '/' if next == Some('/') => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+            }
+            '/' if next == Some('*') => {
+                // block comments can be nested
+                let mut depth = 0;
+                loop {
+                    match (chars.get(i), chars.get(i + 1)) {
+                        (None, _) => return Err(format!("Unclosed comment (at line {}).", line)),
+                        (Some('/'), Some('*')) => {
+                            depth += 1;
+                            i += 2;
+                        }
+                        (Some('*'), Some('/')) => {
+                            depth -= 1;
+                            i += 2;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        (Some(c), _) => {
+                            if *c == '\n' {
+                                line += 1;
+                            }
+                            i += 1;
+                        }
+                    }
+                }
+            }
+            '"' => {
+                let start_line = line;
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(format!("Unclosed string (at line {}).", start_line));
+                        }
+                        Some('"') => break,
+                        Some('\\') => {
+                            if let Some(c) = chars.get(i + 1) {
+                                text.push(*c);
+                            }
+                            i += 2;
+                        }
+                        Some(c) => {
+                            if *c == '\n' {
+                                line += 1;
+                            }
+                            text.push(*c);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                tokens.push((Token::Literal(text), start_line));
+            }
+            '\'' => {
+                // a lifetime, or a char literal
+                if chars.get(i + 2) == Some(&'\'') || next == Some('\\') {
+                    i += 1;
+                    while i < chars.len() && chars[i] != '\'' {
+                        i += if chars[i] == '\\' { 2 } else { 1 };
+                    }
+                    i += 1;
+                    tokens.push((Token::Literal(String::new()), line));
+                } else {
+                    i += 1;
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                        i += 1;
+                    }
+                    tokens.push((Token::Lifetime, line));
+                }
+            }
+            _ if c.is_alphanumeric() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                if c.is_ascii_digit() {
+                    tokens.push((Token::Literal(word), line));
+                } else {
+                    tokens.push((Token::Identifier(word), line));
+                }
+            }
+            _ => {
+                tokens.push((Token::Punct(c), line));
+                i += 1;
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+// the serde attributes of a field or an enum member.
+#[derive(Default)]
+struct Attributes {
+    rename: Option<String>,
+    optional: bool,
+    skip: bool,
+}
+
+struct ItemParser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+}
+
+impl ItemParser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(t, _)| t)
+    }
+
+    fn peek_is(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punct(c))
+    }
+
+    fn peek_is_word(&self, word: &str) -> bool {
+        matches!(self.peek(), Some(Token::Identifier(w)) if w == word)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.index).map(|(t, _)| t.clone());
+        self.index += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> String {
+        match self.tokens.get(self.index) {
+            Some((_, line)) => format!("{} (at line {}).", message, line),
+            None => format!("{} (at the end of the types file).", message),
+        }
+    }
+
+    fn consume(&mut self, c: char) -> Result<(), String> {
+        if self.peek_is(c) {
+            self.index += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expect \"{}\"", c)))
+        }
+    }
+
+    fn consume_if(&mut self, c: char) -> bool {
+        if self.peek_is(c) {
+            self.index += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(Token::Identifier(name)) => {
+                let name = name.clone();
+                self.index += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("Expect an identifier")),
+        }
+    }
+
+    // skip a balanced group, e.g. `{...}`, the current token
+    // is the opening bracket.
+    fn skip_group(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.next() {
+            match token {
+                Token::Punct('(' | '[' | '{') => depth += 1,
+                Token::Punct(')' | ']' | '}') => {
+                    depth -= 1;
+                    if depth <= 0 {
+                        return;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn parse_items(&mut self) -> Result<Vec<Definition>, String> {
+        let mut definitions = vec![];
+        while let Some(token) = self.peek().cloned() {
+            match token {
+                Token::Identifier(word) if word == "struct" || word == "enum" => {
+                    self.index += 1;
+                    if !matches!(self.peek(), Some(Token::Identifier(_))) {
+                        continue;
+                    }
+                    if word == "struct" {
+                        definitions.push(self.parse_struct()?);
+                    } else {
+                        definitions.push(self.parse_enum()?);
+                    }
+                }
+                Token::Identifier(word)
+                    if ["impl", "fn", "trait", "macro_rules"].contains(&word.as_str()) =>
+                {
+                    // skip to the end of the body
+                    while let Some(token) = self.peek() {
+                        match token {
+                            Token::Punct('{') => {
+                                self.skip_group();
+                                break;
+                            }
+                            Token::Punct(';') => {
+                                self.index += 1;
+                                break;
+                            }
+                            _ => self.index += 1,
+                        }
+                    }
+                }
+                Token::Punct('#') => {
+                    self.index += 1;
+                    self.consume_if('!');
+                    if self.peek_is('[') {
+                        self.skip_group();
+                    }
+                }
+                _ => self.index += 1,
+            }
+        }
+        Ok(definitions)
+    }
+
+    // the names of the type parameters, e.g. `<'a, T: Clone, const N: usize>`.
+    fn parse_generics(&mut self) -> Vec<String> {
+        let mut params = vec![];
+        if !self.consume_if('<') {
+            return params;
+        }
+
+        let mut depth = 1;
+        let mut expect_param = true;
+        while let Some(token) = self.next() {
+            match token {
+                Token::Punct('<') => depth += 1,
+                Token::Punct('>') => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                Token::Punct(',') if depth == 1 => expect_param = true,
+                Token::Identifier(name) if expect_param && name != "const" => {
+                    params.push(name);
+                    expect_param = false;
+                }
+                Token::Lifetime => expect_param = false,
+                _ => {}
+            }
+        }
+        params
+    }
+
+    // skip the `where` clause until the body.
+    fn skip_where(&mut self) {
+        if self.peek_is_word("where") {
+            while let Some(token) = self.peek() {
+                if matches!(token, Token::Punct('{' | ';')) {
+                    break;
+                }
+                self.index += 1;
+            }
+        }
+    }
+
+    fn parse_struct(&mut self) -> Result<Definition, String> {
+        let name = self.identifier()?;
+        let generics = self.parse_generics();
+        self.skip_where();
+
+        let shape = if self.peek_is('{') {
+            self.parse_fields(&generics)?
+        } else if self.peek_is('(') {
+            let shape = self.parse_tuple_fields(&generics)?;
+            self.skip_where();
+            self.consume(';')?;
+            shape
+        } else {
+            self.consume(';')?;
+            Shape::Unit
+        };
+        Ok(Definition::Struct(name, shape))
+    }
+
+    fn parse_enum(&mut self) -> Result<Definition, String> {
+        let name = self.identifier()?;
+        let generics = self.parse_generics();
+        self.skip_where();
+        self.consume('{')?;
+
+        let mut members = vec![];
+        while !self.consume_if('}') {
+            let attributes = self.parse_attributes()?;
+            let member_name = self.identifier()?;
+            let shape = if self.peek_is('{') {
+                self.parse_fields(&generics)?
+            } else if self.peek_is('(') {
+                self.parse_tuple_fields(&generics)?
+            } else {
+                Shape::Unit
+            };
+
+            if self.consume_if('=') {
+                // the discriminant
+                while !self.peek_is(',') && !self.peek_is('}') && self.peek().is_some() {
+                    self.index += 1;
+                }
+            }
+
+            if !attributes.skip {
+                members.push((attributes.rename.unwrap_or(member_name), shape));
+            }
+
+            if !self.consume_if(',') && !self.peek_is('}') {
+                return Err(self.error("Expect \",\" or \"}\""));
+            }
+        }
+        Ok(Definition::Enum(name, members))
+    }
+
+    fn parse_fields(&mut self, generics: &[String]) -> Result<Shape, String> {
+        self.consume('{')?;
+        let mut fields = vec![];
+        while !self.consume_if('}') {
+            let attributes = self.parse_attributes()?;
+            self.parse_visibility();
+            let name = self.identifier()?;
+            self.consume(':')?;
+            let field_type = self.parse_type(generics)?;
+
+            if !attributes.skip {
+                fields.push(Field {
+                    name: attributes.rename.unwrap_or(name),
+                    optional: attributes.optional || matches!(field_type, RustType::Option(_)),
+                    field_type,
+                });
+            }
+
+            if !self.consume_if(',') && !self.peek_is('}') {
+                return Err(self.error("Expect \",\" or \"}\""));
+            }
+        }
+        Ok(Shape::Fields(fields))
+    }
+
+    fn parse_tuple_fields(&mut self, generics: &[String]) -> Result<Shape, String> {
+        self.consume('(')?;
+        let mut types = vec![];
+        while !self.consume_if(')') {
+            self.parse_attributes()?;
+            self.parse_visibility();
+            types.push(self.parse_type(generics)?);
+            if !self.consume_if(',') && !self.peek_is(')') {
+                return Err(self.error("Expect \",\" or \")\""));
+            }
+        }
+        Ok(Shape::Tuple(types))
+    }
+
+    fn parse_visibility(&mut self) {
+        if self.peek_is_word("pub") {
+            self.index += 1;
+            if self.peek_is('(') {
+                self.skip_group();
+            }
+        }
+    }
+
+    fn parse_attributes(&mut self) -> Result<Attributes, String> {
+        let mut attributes = Attributes::default();
+        while self.consume_if('#') {
+            self.consume('[')?;
+            if !self.peek_is_word("serde") {
+                self.index -= 1;
+                self.skip_group();
+                continue;
+            }
+
+            self.index += 1;
+            self.consume('(')?;
+            let mut depth = 1;
+            while let Some(token) = self.next() {
+                match token {
+                    Token::Punct('(') => depth += 1,
+                    Token::Punct(')') => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    Token::Identifier(word) if depth == 1 => match word.as_str() {
+                        "rename" if self.consume_if('=') => {
+                            if let Some(Token::Literal(name)) = self.next() {
+                                attributes.rename = Some(name);
+                            }
+                        }
+                        "default" => attributes.optional = true,
+                        "skip" | "skip_deserializing" => attributes.skip = true,
+                        _ => {}
+                    },
+                    _ => {}
+                }
+            }
+            self.consume(']')?;
+        }
+        Ok(attributes)
+    }
+
+    fn parse_type(&mut self, generics: &[String]) -> Result<RustType, String> {
+        if self.consume_if('&') {
+            if self.peek() == Some(&Token::Lifetime) {
+                self.index += 1;
+            }
+            if self.peek_is_word("mut") {
+                self.index += 1;
+            }
+            return self.parse_type(generics);
+        }
+
+        if self.consume_if('(') {
+            let mut types = vec![];
+            while !self.consume_if(')') {
+                types.push(self.parse_type(generics)?);
+                if !self.consume_if(',') && !self.peek_is(')') {
+                    return Err(self.error("Expect \",\" or \")\""));
+                }
+            }
+            return Ok(RustType::Tuple(types));
+        }
+
+        if self.consume_if('[') {
+            let item_type = self.parse_type(generics)?;
+            let rust_type = if self.consume_if(';') {
+                let length = match self.next() {
+                    Some(Token::Literal(n)) => n
+                        .trim_end_matches("usize")
+                        .replace('_', "")
+                        .parse::<usize>()
+                        .map_err(|_| self.error("Expect the length of the array"))?,
+                    _ => return Err(self.error("Expect the length of the array")),
+                };
+                RustType::Array(Box::new(item_type), length)
+            } else {
+                RustType::List(Box::new(item_type))
+            };
+            self.consume(']')?;
+            return Ok(rust_type);
+        }
+
+        // the path, e.g. `std::collections::HashMap`, only the last
+        // segment is significant.
+        let mut name = self.identifier()?;
+        while self.peek_is(':') {
+            self.consume(':')?;
+            self.consume(':')?;
+            name = self.identifier()?;
+        }
+
+        let mut args = vec![];
+        if self.consume_if('<') {
+            while !self.consume_if('>') {
+                if self.peek() == Some(&Token::Lifetime) {
+                    self.index += 1;
+                } else {
+                    args.push(self.parse_type(generics)?);
+                }
+                if !self.consume_if(',') && !self.peek_is('>') {
+                    return Err(self.error("Expect \",\" or \">\""));
+                }
+            }
+        }
+
+        let arg = |i: usize| args.get(i).cloned().unwrap_or(RustType::Any);
+
+        let rust_type = match name.as_str() {
+            "bool" => RustType::Bool,
+            "char" => RustType::Char,
+            "String" | "str" | "PathBuf" | "Path" => RustType::String,
+            "i8" => RustType::Integer("i8"),
+            "u8" => RustType::Integer("u8"),
+            "i16" => RustType::Integer("i16"),
+            "u16" => RustType::Integer("u16"),
+            "i32" => RustType::Integer("i32"),
+            "u32" => RustType::Integer("u32"),
+            "i64" | "isize" => RustType::Integer("i64"),
+            "u64" | "usize" => RustType::Integer("u64"),
+            "f32" | "f64" => RustType::Float,
+            "DateTime" | "NaiveDateTime" => RustType::DateTime,
+            "ByteBuf" | "Bytes" => RustType::Bytes,
+            "Vec" if arg(0) == RustType::Integer("u8") => RustType::Bytes,
+            "Vec" | "VecDeque" | "LinkedList" | "HashSet" | "BTreeSet" | "IndexSet" => {
+                RustType::List(Box::new(arg(0)))
+            }
+            "HashMap" | "BTreeMap" | "IndexMap" => RustType::Map(Box::new(arg(1))),
+            "Option" => RustType::Option(Box::new(arg(0))),
+            "Box" | "Rc" | "Arc" | "Cell" | "RefCell" => arg(0),
+            "Cow" => args.last().cloned().unwrap_or(RustType::Any),
+            _ if generics.contains(&name) => RustType::Any,
+            _ => RustType::Named(name),
+        };
+        Ok(rust_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::TypeSet;
+
+    const SOURCE: &str = r#"
+        use std::collections::HashMap;
+
+        /// The configuration
+        #[derive(Serialize, Deserialize)]
+        pub struct Config<'a, T> {
+            pub name: String,
+            #[serde(rename = "port_number")]
+            port: u16,
+            tags: Vec<String>,
+            extra: HashMap<String, T>,
+            mode: Mode,
+            point: (f64, f64),
+            note: Option<&'a str>,
+            #[serde(default)]
+            retries: u8,
+            #[serde(skip)]
+            cache: Vec<u8>,
+        }
+
+        impl Config<'_, i32> {
+            fn new() -> Self {
+                struct Inner;
+                todo!()
+            }
+        }
+
+        enum Mode {
+            Fast,
+            Slow(u32),
+            Custom { level: i32 },
+            Pair(i32, char) = 3,
+        }
+    "#;
+
+    fn check(type_name: Option<&str>, text: &str) -> Vec<String> {
+        let types = TypeSet::parse(SOURCE).unwrap();
+        let value = parse_input(text, None, &ParseOptions::unlimited()).unwrap();
+        types
+            .check(type_name, &value)
+            .unwrap()
+            .iter()
+            .map(|m| m.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_check_struct() {
+        assert_eq!(
+            check(
+                None,
+                r#"{name: "a", port_number: 80_u16, tags: ["x"], extra: {k: 1},
+                    mode: Mode::Slow(3_u32), point: (1.0, 2.0)}"#
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            check(
+                None,
+                r#"{name: 1, port_number: 70000, tags: ["x", 2], extra: {}, bad: 1,
+                    mode: Mode::Custom{level: 1, x: 2}, point: (1.0, 2.0, 3.0)}"#
+            ),
+            [
+                ".name: expect a string, found 1",
+                ".port_number: expect an integer of type u16, found 70000",
+                ".tags[1]: expect a string, found 2",
+                ".mode: unknown field \"x\"",
+                ".point: expect a tuple of 2 items, found 3 items",
+                ".: unknown field \"bad\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_enum() {
+        assert_eq!(
+            check(
+                None,
+                r#"{name: "a", port_number: 1_u16, tags: [], extra: {},
+                    mode: Mode::Turbo, point: (1.0, 2.0)}"#
+            ),
+            [".mode: enum \"Mode\" has no member \"Turbo\""]
+        );
+        assert_eq!(
+            check(Some("Mode"), "Mode::Pair(1, 'c')"),
+            Vec::<String>::new()
+        );
+
+        let types = TypeSet::parse(SOURCE).unwrap();
+        assert!(types.check(Some("Nope"), &Value::null()).is_err());
+    }
+}
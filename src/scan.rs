@@ -0,0 +1,205 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The byte scanner of the ASON (and JSON) source text, which tells the
+// bytes of the strings and the comments from the others without parsing
+// the text, e.g. to count the nesting depth of the brackets before the
+// document is parsed, or to split the documents of a stream.
+//
+// The bytes are scanned one by one, so the text can be scanned in pieces
+// (e.g. through a read buffer). The block comments can be nested, e.g.
+// `/* a /* b */ c */`, and the strings prefixed with `r` are raw, i.e.
+// the backslashes in them are not escapes.
+
+/// The kind of a scanned byte.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Class {
+    /// A byte outside the strings and the comments.
+    Code,
+
+    /// A `/` outside the strings and the comments, which may start
+    /// a comment. The next byte is `CommentStart` if it does, otherwise
+    /// the `/` is a part of the code.
+    Slash,
+
+    /// The second byte of `//` or `/*` of a comment. For the nested
+    /// block comments, only the outermost one starts with it.
+    CommentStart,
+
+    /// A byte of a comment, including the end of a block comment.
+    Comment,
+
+    /// A byte of a string (or a char), including the quotes.
+    String,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum State {
+    Normal,
+    Slash,
+    String { quote: u8, raw: bool, escape: bool },
+    LineComment,
+
+    // the nesting depth, and the previous byte of the comment.
+    BlockComment { depth: usize, previous: u8 },
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Scanner {
+    state: State,
+
+    // the previous two bytes, for the prefix of the raw strings.
+    previous: [u8; 2],
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self {
+            state: State::Normal,
+            previous: [b'\n'; 2],
+        }
+    }
+
+    /// Whether the scanned text ends inside a string or a block comment.
+    pub fn is_unterminated(&self) -> bool {
+        matches!(
+            self.state,
+            State::String { .. } | State::BlockComment { .. }
+        )
+    }
+
+    pub fn scan(&mut self, byte: u8) -> Class {
+        let class = match self.state {
+            State::String { quote, raw, escape } => {
+                self.state = match byte {
+                    _ if escape => State::String {
+                        quote,
+                        raw,
+                        escape: false,
+                    },
+                    b'\\' if !raw => State::String {
+                        quote,
+                        raw,
+                        escape: true,
+                    },
+                    _ if byte == quote => State::Normal,
+                    _ => self.state,
+                };
+                Class::String
+            }
+            State::LineComment if byte == b'\n' => {
+                self.state = State::Normal;
+                Class::Code
+            }
+            State::LineComment => Class::Comment,
+            State::BlockComment { depth, previous } => {
+                self.state = match (previous, byte) {
+                    (b'/', b'*') => State::BlockComment {
+                        depth: depth + 1,
+                        previous: 0,
+                    },
+                    (b'*', b'/') if depth == 1 => State::Normal,
+                    (b'*', b'/') => State::BlockComment {
+                        depth: depth - 1,
+                        previous: 0,
+                    },
+                    _ => State::BlockComment {
+                        depth,
+                        previous: byte,
+                    },
+                };
+                Class::Comment
+            }
+            State::Slash if byte == b'/' => {
+                self.state = State::LineComment;
+                Class::CommentStart
+            }
+            State::Slash if byte == b'*' => {
+                self.state = State::BlockComment {
+                    depth: 1,
+                    previous: 0,
+                };
+                Class::CommentStart
+            }
+            State::Slash | State::Normal => self.scan_normal(byte),
+        };
+        self.previous = [self.previous[1], byte];
+        class
+    }
+
+    fn scan_normal(&mut self, byte: u8) -> Class {
+        match byte {
+            b'/' => {
+                self.state = State::Slash;
+                Class::Slash
+            }
+            b'"' | b'\'' => {
+                let [before, previous] = self.previous;
+                self.state = State::String {
+                    quote: byte,
+                    raw: byte == b'"' && previous == b'r' && !is_word_byte(before),
+                    escape: false,
+                };
+                Class::String
+            }
+            _ => {
+                self.state = State::Normal;
+                Class::Code
+            }
+        }
+    }
+}
+
+/// Whether the byte is a part of an identifier or a number,
+/// the bytes of the non-ASCII chars are taken as a part of them.
+pub fn is_word_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'.' || byte >= 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{Class, Scanner};
+
+    // the text of the bytes of the class.
+    fn text_of(source: &str, class: Class) -> String {
+        let mut scanner = Scanner::new();
+        let bytes: Vec<u8> = source
+            .bytes()
+            .filter(|byte| scanner.scan(*byte) == class)
+            .collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_scan_strings() {
+        let source = r#"[1, "a]\"b", r"c\", 'd', '\'', "中"]"#;
+        assert_eq!(text_of(source, Class::Code), "[1, , r, , , ]");
+        assert_eq!(text_of(source, Class::String), r#""a]\"b""c\"'d''\''"中""#);
+    }
+
+    #[test]
+    fn test_scan_comments() {
+        let source = "[1, // a ]\n2 /* b /* c ] */ d ] */, 3 / 4]";
+        assert_eq!(text_of(source, Class::Code), "[1, \n2 , 3  4]");
+        assert_eq!(text_of(source, Class::Slash), "///");
+        assert_eq!(text_of(source, Class::CommentStart), "/*");
+        assert_eq!(text_of(source, Class::Comment), " a ] b /* c ] */ d ] */");
+
+        let mut scanner = Scanner::new();
+        "/* a /* b */".bytes().for_each(|byte| {
+            scanner.scan(byte);
+        });
+        assert!(scanner.is_unterminated());
+    }
+}
@@ -0,0 +1,829 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Validating the documents against a schema written in ASON,
+// i.e. `aq validate --schema schema.ason <file(s)>` and the
+// builtin `validates(schema)`, e.g.
+//
+// ```
+// {
+//     type: "object"
+//     fields: {
+//         name: {type: "string", min_length: 1}
+//         age: {type: "u8", maximum: 150_u8}
+//         email: {type: "string", pattern: "^[^@]+@[^@]+$"}
+//         tags: {type: "list", items: "string", unique: true}
+//         shape: {
+//             type: "variant"
+//             name: "Shape"
+//             members: {
+//                 Circle: "f64"
+//                 Rect: {type: "tuple", items: ["f64", "f64"]}
+//                 Empty: "unit"
+//             }
+//         }
+//         joined: {type: "option", value: "datetime"}
+//     }
+//     required: ["name", "age"]
+//     additional: false
+// }
+// ```
+//
+// A schema is a type name, or an object with the type name (`type`) and
+// the constraints, the types are:
+//
+// - `any`, `boolean`, `char`, `string`, `datetime` and `bytes`,
+// - `number`, `integer`, `float` and the fixed-width numbers `i8` to `u64`,
+//   `f32` and `f64`, the integers must fit in the fixed-width type,
+// - `list` (with the schema of the `items`) and `tuple` (with the list of
+//   the schemas of the `items`),
+// - `object` with the schemas of the `fields`, the `required` keys, and
+//   `additional` (a boolean or the schema of the other values),
+// - `variant` with the type `name` and the schemas of the payloads of the
+//   `members` (`unit` for the members without payload),
+// - `option` with the schema of the `value` of `Option::Some`.
+//
+// The constraints are `enum` (the list of the allowed values), `minimum`,
+// `maximum`, `min_length`, `max_length` (the number of chars, items or
+// entries) and `pattern` (a regular expression of the strings). Instead
+// of a type, `{one_of: [schema, ...]}` accepts the values matching any
+// of the schemas.
+//
+// All violations are reported, each with the path of the value.
+
+use std::{cmp::Ordering, fmt::Display};
+
+use crate::{
+    cast::{cast_number, CastMode, CAST_TYPES},
+    error::Error,
+    path::path_to_string,
+    regex::Regex,
+    value::{Items, Map, Value, VariantValue},
+};
+
+#[derive(Debug, PartialEq, Clone)]
+enum Kind {
+    Any,
+    Boolean,
+    Char,
+    String,
+    DateTime,
+    Bytes,
+    Number,
+    Integer,
+    Float,
+    Fixed(&'static str),
+    List {
+        items: Option<Box<Rule>>,
+        unique: bool,
+    },
+    Tuple(Option<Vec<Rule>>),
+    Object {
+        fields: Vec<(String, Rule)>,
+        required: Vec<String>,
+        additional: Additional,
+    },
+    Variant {
+        name: Option<String>,
+        members: Option<Vec<(String, Rule)>>,
+    },
+    Option(Option<Box<Rule>>),
+
+    // the payload of the variant members without value.
+    Unit,
+
+    OneOf(Vec<Rule>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Additional {
+    Allowed,
+    Denied,
+    Schema(Box<Rule>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+struct Rule {
+    kind: Kind,
+    allowed: Option<Vec<Value>>,
+    minimum: Option<Value>,
+    maximum: Option<Value>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<(String, Regex)>,
+}
+
+/// A value which violates the schema.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Violation {
+    pub path: Vec<Value>,
+    pub message: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", path_to_string(&self.path), self.message)
+    }
+}
+
+/// A schema read from an ASON value.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Schema {
+    root: Rule,
+}
+
+impl Schema {
+    pub fn from_value(value: &Value) -> Result<Self, Error> {
+        let mut path = vec![];
+        let root = parse_rule(value, &mut path)?;
+        Ok(Self { root })
+    }
+
+    /// Check the value, all violations are returned (in the order of the paths).
+    pub fn validate(&self, value: &Value) -> Vec<Violation> {
+        let mut validator = Validator {
+            path: vec![],
+            violations: vec![],
+        };
+        validator.check(&self.root, value);
+        validator.violations
+    }
+}
+
+fn schema_error(path: &[Value], message: &str) -> Error {
+    Error::Message(format!(
+        "Invalid schema at {}: {}.",
+        path_to_string(path),
+        message
+    ))
+}
+
+fn parse_rule(value: &Value, path: &mut Vec<Value>) -> Result<Rule, Error> {
+    let map = match value {
+        Value::String(name) => {
+            return Ok(Rule::new(parse_kind(name, &Map::new(), path)?));
+        }
+        Value::Object(map) => map,
+        _ => {
+            return Err(schema_error(
+                path,
+                &format!("expect a type name or an object, found {}", value),
+            ))
+        }
+    };
+
+    let kind = match (map.get("type"), map.get("one_of")) {
+        (None, Some(Value::List(schemas))) => {
+            let mut rules = vec![];
+            path.push(Value::String("one_of".to_owned()));
+            for (idx, schema) in schemas.iter().enumerate() {
+                path.push(Value::from(idx));
+                rules.push(parse_rule(schema, path)?);
+                path.pop();
+            }
+            path.pop();
+            Kind::OneOf(rules)
+        }
+        (Some(Value::String(name)), None) => parse_kind(name, map, path)?,
+        (Some(_), Some(_)) => {
+            return Err(schema_error(
+                path,
+                "\"type\" and \"one_of\" can not be used together",
+            ))
+        }
+        _ => {
+            return Err(schema_error(
+                path,
+                "expect the type name \"type\" or the list of \"one_of\"",
+            ))
+        }
+    };
+
+    let mut rule = Rule::new(kind);
+    for (key, item) in map.iter() {
+        let constrained = match key.as_str() {
+            "enum" => match item {
+                Value::List(values) => {
+                    rule.allowed = Some(values.to_vec());
+                    true
+                }
+                _ => false,
+            },
+            "minimum" => {
+                rule.minimum = Some(item.clone());
+                true
+            }
+            "maximum" => {
+                rule.maximum = Some(item.clone());
+                true
+            }
+            "min_length" | "max_length" => {
+                let Some(length) = length_of(item) else {
+                    return Err(schema_error(
+                        path,
+                        &format!("\"{}\" must be a non-negative integer", key),
+                    ));
+                };
+                if key == "min_length" {
+                    rule.min_length = Some(length);
+                } else {
+                    rule.max_length = Some(length);
+                }
+                true
+            }
+            "pattern" => match item {
+                Value::String(pattern) => {
+                    let regex = Regex::new(pattern, false)
+                        .map_err(|e| schema_error(path, &e.to_string()))?;
+                    rule.pattern = Some((pattern.clone(), regex));
+                    true
+                }
+                _ => false,
+            },
+            "type" | "one_of" => true,
+            _ => is_kind_key(&rule.kind, key),
+        };
+        if !constrained {
+            return Err(schema_error(
+                path,
+                &format!("the key \"{}\" is unknown or invalid", key),
+            ));
+        }
+    }
+    Ok(rule)
+}
+
+fn parse_kind(name: &str, map: &Map, path: &mut Vec<Value>) -> Result<Kind, Error> {
+    let kind = match name {
+        "any" => Kind::Any,
+        "boolean" => Kind::Boolean,
+        "char" => Kind::Char,
+        "string" => Kind::String,
+        "datetime" => Kind::DateTime,
+        "bytes" => Kind::Bytes,
+        "number" => Kind::Number,
+        "integer" => Kind::Integer,
+        "float" => Kind::Float,
+        "unit" => Kind::Unit,
+        "list" => Kind::List {
+            items: parse_member(map, "items", path)?.map(Box::new),
+            unique: match map.get("unique") {
+                None => false,
+                Some(Value::Boolean(b)) => *b,
+                Some(_) => return Err(schema_error(path, "\"unique\" must be a boolean")),
+            },
+        },
+        "tuple" => Kind::Tuple(match map.get("items") {
+            None => None,
+            Some(Value::List(schemas)) => {
+                let mut rules = vec![];
+                path.push(Value::String("items".to_owned()));
+                for (idx, schema) in schemas.iter().enumerate() {
+                    path.push(Value::from(idx));
+                    rules.push(parse_rule(schema, path)?);
+                    path.pop();
+                }
+                path.pop();
+                Some(rules)
+            }
+            Some(_) => {
+                return Err(schema_error(
+                    path,
+                    "the \"items\" of a tuple must be a list",
+                ))
+            }
+        }),
+        "object" => Kind::Object {
+            fields: parse_entries(map, "fields", path)?.unwrap_or_default(),
+            required: match map.get("required") {
+                None => vec![],
+                Some(Value::List(keys)) => keys
+                    .iter()
+                    .map(|key| match key {
+                        Value::String(k) => Ok(k.clone()),
+                        _ => Err(schema_error(path, "the \"required\" keys must be strings")),
+                    })
+                    .collect::<Result<_, _>>()?,
+                Some(_) => return Err(schema_error(path, "\"required\" must be a list")),
+            },
+            additional: match map.get("additional") {
+                None | Some(Value::Boolean(true)) => Additional::Allowed,
+                Some(Value::Boolean(false)) => Additional::Denied,
+                Some(_) => {
+                    Additional::Schema(Box::new(parse_member(map, "additional", path)?.unwrap()))
+                }
+            },
+        },
+        "variant" => Kind::Variant {
+            name: match map.get("name") {
+                None => None,
+                Some(Value::String(n)) => Some(n.clone()),
+                Some(_) => return Err(schema_error(path, "the \"name\" must be a string")),
+            },
+            members: parse_entries(map, "members", path)?,
+        },
+        "option" => Kind::Option(parse_member(map, "value", path)?.map(Box::new)),
+        _ => match CAST_TYPES.iter().find(|t| **t == name) {
+            Some(suffix) => Kind::Fixed(suffix),
+            None => {
+                return Err(schema_error(
+                    path,
+                    &format!("the type \"{}\" is unknown", name),
+                ))
+            }
+        },
+    };
+    Ok(kind)
+}
+
+// the schema of the key of the schema object.
+fn parse_member(map: &Map, key: &str, path: &mut Vec<Value>) -> Result<Option<Rule>, Error> {
+    let Some(schema) = map.get(key) else {
+        return Ok(None);
+    };
+    path.push(Value::String(key.to_owned()));
+    let rule = parse_rule(schema, path)?;
+    path.pop();
+    Ok(Some(rule))
+}
+
+// the schemas of the entries of the schema object, e.g. the `fields`.
+fn parse_entries(
+    map: &Map,
+    key: &str,
+    path: &mut Vec<Value>,
+) -> Result<Option<Vec<(String, Rule)>>, Error> {
+    let entries = match map.get(key) {
+        None => return Ok(None),
+        Some(Value::Object(entries)) => entries,
+        Some(_) => {
+            return Err(schema_error(
+                path,
+                &format!("the \"{}\" must be an object", key),
+            ))
+        }
+    };
+
+    path.push(Value::String(key.to_owned()));
+    let mut rules = vec![];
+    for (name, schema) in entries.iter() {
+        path.push(Value::String(name.clone()));
+        rules.push((name.clone(), parse_rule(schema, path)?));
+        path.pop();
+    }
+    path.pop();
+    Ok(Some(rules))
+}
+
+// the keys which are specific to the type.
+fn is_kind_key(kind: &Kind, key: &str) -> bool {
+    matches!(
+        (kind, key),
+        (Kind::List { .. }, "items" | "unique")
+            | (Kind::Tuple(_), "items")
+            | (Kind::Object { .. }, "fields" | "required" | "additional")
+            | (Kind::Variant { .. }, "name" | "members")
+            | (Kind::Option(_), "value")
+    )
+}
+
+fn length_of(value: &Value) -> Option<usize> {
+    match value {
+        Value::Number(n) if !n.is_float() => n.as_i128().and_then(|i| usize::try_from(i).ok()),
+        _ => None,
+    }
+}
+
+impl Rule {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            allowed: None,
+            minimum: None,
+            maximum: None,
+            min_length: None,
+            max_length: None,
+            pattern: None,
+        }
+    }
+}
+
+struct Validator {
+    path: Vec<Value>,
+    violations: Vec<Violation>,
+}
+
+impl Validator {
+    fn violate(&mut self, message: String) {
+        self.violations.push(Violation {
+            path: self.path.clone(),
+            message,
+        });
+    }
+
+    fn at(&mut self, key: Value, rule: &Rule, value: &Value) {
+        self.path.push(key);
+        self.check(rule, value);
+        self.path.pop();
+    }
+
+    fn check(&mut self, rule: &Rule, value: &Value) {
+        // the constraints are checked only if the type matches.
+        if self.check_kind(&rule.kind, value) {
+            self.check_constraints(rule, value);
+        }
+    }
+
+    // returns `false` if the type does not match.
+    fn check_kind(&mut self, kind: &Kind, value: &Value) -> bool {
+        let matched = match (kind, value) {
+            (Kind::Any, _) => true,
+            (Kind::Boolean, Value::Boolean(_))
+            | (Kind::Char, Value::Char(_))
+            | (Kind::String, Value::String(_))
+            | (Kind::DateTime, Value::DateTime(_))
+            | (Kind::Bytes, Value::ByteData(_))
+            | (Kind::Number, Value::Number(_)) => true,
+            (Kind::Integer, Value::Number(n)) => !n.is_float(),
+            (Kind::Float, Value::Number(n)) => n.is_float(),
+            (Kind::Fixed(suffix), Value::Number(n)) => {
+                let float = suffix.starts_with('f');
+                n.is_float() == float && cast_number(n, suffix, CastMode::Checked).is_ok()
+            }
+            (Kind::List { items, unique }, Value::List(values)) => {
+                for (idx, item) in values.iter().enumerate() {
+                    self.path.push(Value::from(idx));
+                    if let Some(item_rule) = items {
+                        self.check(item_rule, item);
+                    }
+                    if *unique && values[..idx].contains(item) {
+                        self.violate(format!("duplicate item {}", item));
+                    }
+                    self.path.pop();
+                }
+                true
+            }
+            (Kind::Tuple(None), Value::Tuple(_)) => true,
+            (Kind::Tuple(Some(rules)), Value::Tuple(items)) => {
+                if rules.len() == items.len() {
+                    for (idx, (item_rule, item)) in rules.iter().zip(items.iter()).enumerate() {
+                        self.at(Value::from(idx), item_rule, item);
+                    }
+                } else {
+                    self.violate(format!(
+                        "expect a tuple of {} items, found {} items",
+                        rules.len(),
+                        items.len()
+                    ));
+                }
+                true
+            }
+            (
+                Kind::Object {
+                    fields,
+                    required,
+                    additional,
+                },
+                Value::Object(map),
+            ) => {
+                self.check_object(fields, required, additional, map);
+                true
+            }
+            (Kind::Option(_), _) if value.is_null() => true,
+            (Kind::Option(inner), _) if value.as_some().is_some() => {
+                if let (Some(inner_rule), Some(inner_value)) = (inner, value.as_some()) {
+                    self.check(inner_rule, inner_value);
+                }
+                true
+            }
+            (Kind::Variant { name, members }, Value::Variant(v))
+                if name.as_ref().is_none_or(|n| *n == v.type_name) =>
+            {
+                let Some(members) = members else {
+                    return true;
+                };
+                match members.iter().find(|(m, _)| *m == v.member_name) {
+                    Some((_, payload_rule)) => self.check_payload(payload_rule, &v.value),
+                    None => self.violate(format!(
+                        "the member \"{}\" is not allowed by the schema",
+                        v.member_name
+                    )),
+                }
+                true
+            }
+            (Kind::OneOf(rules), _) => {
+                let matched = rules.iter().any(|r| {
+                    let mut validator = Validator {
+                        path: vec![],
+                        violations: vec![],
+                    };
+                    validator.check(r, value);
+                    validator.violations.is_empty()
+                });
+                if !matched {
+                    self.violate(format!("expect one of the schemas, found {}", value));
+                }
+                return matched;
+            }
+            _ => false,
+        };
+
+        if !matched {
+            self.violate(format!("expect {}, found {}", describe(kind), value));
+        }
+        matched
+    }
+
+    fn check_object(
+        &mut self,
+        fields: &[(String, Rule)],
+        required: &[String],
+        additional: &Additional,
+        map: &Map,
+    ) {
+        for key in required {
+            if map.get(key).is_none() {
+                self.violate(format!("missing key \"{}\"", key));
+            }
+        }
+
+        for (key, item) in map.iter() {
+            match (fields.iter().find(|(name, _)| name == key), additional) {
+                (Some((_, field_rule)), _) => self.at(Value::String(key.clone()), field_rule, item),
+                (None, Additional::Allowed) => {}
+                (None, Additional::Denied) => self.violate(format!("unknown key \"{}\"", key)),
+                (None, Additional::Schema(rule)) => self.at(Value::String(key.clone()), rule, item),
+            }
+        }
+    }
+
+    fn check_payload(&mut self, rule: &Rule, payload: &VariantValue) {
+        // the tuple and object payloads are checked as the values, the
+        // items are shared, so they are not copied.
+        let value = match (&rule.kind, payload) {
+            (Kind::Unit, VariantValue::Empty) => return,
+            (_, VariantValue::Value(inner)) => inner.as_ref().clone(),
+            (_, VariantValue::Tuple(items)) => Value::Tuple(items.clone()),
+            (_, VariantValue::Object(map)) => Value::Object(map.clone()),
+            (_, VariantValue::Empty) => {
+                return self.violate(format!("expect {}, found no value", describe(&rule.kind)))
+            }
+        };
+        if matches!(rule.kind, Kind::Unit) {
+            return self.violate(format!("expect no value, found {}", value));
+        }
+        self.check(rule, &value);
+    }
+
+    fn check_constraints(&mut self, rule: &Rule, value: &Value) {
+        if let Some(allowed) = &rule.allowed {
+            if !allowed.contains(value) {
+                let texts: Vec<String> = allowed.iter().map(Value::to_string).collect();
+                self.violate(format!(
+                    "expect one of {}, found {}",
+                    texts.join(", "),
+                    value
+                ));
+            }
+        }
+
+        if let Some(minimum) = &rule.minimum {
+            if is_comparable(value, minimum) && value.compare(minimum) == Ordering::Less {
+                self.violate(format!("{} is less than the minimum {}", value, minimum));
+            }
+        }
+        if let Some(maximum) = &rule.maximum {
+            if is_comparable(value, maximum) && value.compare(maximum) == Ordering::Greater {
+                self.violate(format!("{} is greater than the maximum {}", value, maximum));
+            }
+        }
+
+        let length = match value {
+            Value::String(s) => Some(s.chars().count()),
+            Value::List(items) | Value::Tuple(items) => Some(items.len()),
+            Value::ByteData(bytes) => Some(bytes.len()),
+            Value::Object(map) => Some(map.len()),
+            _ => None,
+        };
+        if let Some(length) = length {
+            if rule.min_length.is_some_and(|min| length < min) {
+                self.violate(format!(
+                    "expect the length at least {}, found {}",
+                    rule.min_length.unwrap(),
+                    length
+                ));
+            }
+            if rule.max_length.is_some_and(|max| length > max) {
+                self.violate(format!(
+                    "expect the length at most {}, found {}",
+                    rule.max_length.unwrap(),
+                    length
+                ));
+            }
+        }
+
+        if let (Some((pattern, regex)), Value::String(s)) = (&rule.pattern, value) {
+            if !regex.is_match(s) {
+                self.violate(format!(
+                    "the string {} does not match the pattern \"{}\"",
+                    value, pattern
+                ));
+            }
+        }
+    }
+}
+
+// the minimum and maximum are applied to the values of the same type.
+fn is_comparable(value: &Value, bound: &Value) -> bool {
+    value.type_name() == bound.type_name()
+}
+
+fn describe(kind: &Kind) -> String {
+    match kind {
+        Kind::Any => "any value".to_owned(),
+        Kind::Boolean => "a boolean".to_owned(),
+        Kind::Char => "a char".to_owned(),
+        Kind::String => "a string".to_owned(),
+        Kind::DateTime => "a date time".to_owned(),
+        Kind::Bytes => "byte data".to_owned(),
+        Kind::Number => "a number".to_owned(),
+        Kind::Integer => "an integer".to_owned(),
+        Kind::Float => "a floating-point number".to_owned(),
+        Kind::Fixed(suffix) if suffix.starts_with('f') => format!("a number of type {}", suffix),
+        Kind::Fixed(suffix) => format!("an integer of type {}", suffix),
+        Kind::List { .. } => "a list".to_owned(),
+        Kind::Tuple(_) => "a tuple".to_owned(),
+        Kind::Object { .. } => "an object".to_owned(),
+        Kind::Variant {
+            name: Some(name), ..
+        } => format!("a member of \"{}\"", name),
+        Kind::Variant { name: None, .. } => "a variant".to_owned(),
+        Kind::Option(_) => "an Option".to_owned(),
+        Kind::Unit => "no value".to_owned(),
+        Kind::OneOf(_) => "one of the schemas".to_owned(),
+    }
+}
+
+/// The summary of the validation of the files, e.g.
+///
+/// ```text
+/// {
+///     files: 2
+///     valid: 1
+///     invalid: 1
+///     results: [
+///         {file: "a.ason", valid: true, violations: []}
+///         {file: "b.ason", valid: false, violations: [{path: ".age", message: "..."}]}
+///     ]
+/// }
+/// ```
+pub fn validation_report(results: &[(String, Vec<Violation>)]) -> Value {
+    let invalid = results.iter().filter(|(_, v)| !v.is_empty()).count();
+
+    let entries = results.iter().map(|(file, violations)| {
+        let valid = violations.is_empty();
+        let violations = violations.iter().map(|v| {
+            let mut map = Map::new();
+            map.insert("path".to_owned(), Value::String(path_to_string(&v.path)));
+            map.insert("message".to_owned(), Value::String(v.message.clone()));
+            Value::Object(map)
+        });
+
+        let mut map = Map::new();
+        map.insert("file".to_owned(), Value::String(file.clone()));
+        map.insert("valid".to_owned(), Value::Boolean(valid));
+        map.insert(
+            "violations".to_owned(),
+            Value::List(Items::from(violations.collect::<Vec<_>>())),
+        );
+        Value::Object(map)
+    });
+
+    let mut map = Map::new();
+    map.insert("files".to_owned(), Value::from(results.len()));
+    map.insert("valid".to_owned(), Value::from(results.len() - invalid));
+    map.insert("invalid".to_owned(), Value::from(invalid));
+    map.insert(
+        "results".to_owned(),
+        Value::List(Items::from(entries.collect::<Vec<_>>())),
+    );
+    Value::Object(map)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{validation_report, Schema};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    fn validate(schema: &str, document: &str) -> Vec<String> {
+        Schema::from_value(&value(schema))
+            .unwrap()
+            .validate(&value(document))
+            .iter()
+            .map(|v| v.to_string())
+            .collect()
+    }
+
+    const SCHEMA: &str = r#"{
+        type: "object"
+        fields: {
+            name: {type: "string", min_length: 1}
+            age: {type: "u8", maximum: 150_u8}
+            email: {type: "string", pattern: "^[^@]+@[^@]+$"}
+            tags: {type: "list", items: "string", unique: true}
+            shape: {
+                type: "variant"
+                name: "Shape"
+                members: {
+                    Circle: "f64"
+                    Rect: {type: "tuple", items: ["f64", "f64"]}
+                    Empty: "unit"
+                }
+            }
+            level: {one_of: ["u8", {type: "string", enum: ["high", "low"]}]}
+            joined: {type: "option", value: "datetime"}
+        }
+        required: ["name", "age"]
+        additional: false
+    }"#;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            validate(
+                SCHEMA,
+                "{name: \"foo\", age: 42, shape: Shape::Empty, level: \"low\", joined: Option::None}"
+            ),
+            Vec::<String>::new()
+        );
+        assert_eq!(
+            validate(
+                SCHEMA,
+                r#"{
+                    name: "", age: 300, email: "x", tags: ["a", "a", 1],
+                    shape: Shape::Rect(1.0), level: "mid", extra: 1
+                }"#
+            ),
+            [
+                ".name: expect the length at least 1, found 0",
+                ".age: expect an integer of type u8, found 300",
+                ".email: the string \"x\" does not match the pattern \"^[^@]+@[^@]+$\"",
+                ".tags[1]: duplicate item \"a\"",
+                ".tags[2]: expect a string, found 1",
+                ".shape: expect a tuple, found 1.0",
+                ".level: expect one of the schemas, found \"mid\"",
+                ".: unknown key \"extra\""
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let error = |schema: &str| Schema::from_value(&value(schema)).unwrap_err().to_string();
+        assert_eq!(
+            error("{type: \"nope\"}"),
+            "Invalid schema at .: the type \"nope\" is unknown."
+        );
+        assert_eq!(
+            error("{type: \"list\", items: {type: \"x\"}}"),
+            "Invalid schema at .items: the type \"x\" is unknown."
+        );
+    }
+
+    #[test]
+    fn test_validation_report() {
+        let schema = Schema::from_value(&value("\"u8\"")).unwrap();
+        let results = [
+            ("a.ason".to_owned(), schema.validate(&value("1"))),
+            ("b.ason".to_owned(), schema.validate(&value("-1"))),
+        ];
+        assert_eq!(
+            validation_report(&results),
+            value(
+                r#"{
+                    files: 2, valid: 1, invalid: 1
+                    results: [
+                        {file: "a.ason", valid: true, violations: []}
+                        {
+                            file: "b.ason", valid: false
+                            violations: [{path: ".", message: "expect an integer of type u8, found -1"}]
+                        }
+                    ]
+                }"#
+            )
+        );
+    }
+}
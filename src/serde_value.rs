@@ -0,0 +1,947 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Converting the Rust types (by `serde`) to and from the value model,
+// so the queries can run over the structs of the application directly, e.g.
+//
+// ```
+// let names: Vec<String> = Query::compile(".users[] | .name")?.run_typed(&config)?;
+// ```
+//
+// The types are mapped as the ASON serializer does:
+//
+// - the structs and the maps are objects, the keys of the maps
+//   must be strings, chars or numbers.
+// - the sequences are lists, the tuples and tuple structs are tuples.
+// - `None` and `Some(v)` are `Option::None` and `Option::Some(v)`.
+// - the enum variants are ASON variants, e.g. `Color::Red`.
+// - the unit value is an empty tuple, and the newtype structs are
+//   their inner values.
+
+use std::{fmt::Display, sync::Arc};
+
+use serde::{
+    de::{
+        self, DeserializeOwned, DeserializeSeed, EnumAccess, MapAccess, SeqAccess, VariantAccess,
+        Visitor,
+    },
+    ser::{self, Impossible, Serialize},
+};
+
+use crate::{
+    error::Error,
+    value::{Items, Map, Number, Value, Variant, VariantValue},
+};
+
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// Convert a Rust value to the value model.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, Error> {
+    value.serialize(ValueSerializer)
+}
+
+/// Convert a value to the Rust type.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, Error> {
+    T::deserialize(value)
+}
+
+fn variant(type_name: &str, member_name: &str, value: VariantValue) -> Value {
+    Value::Variant(Arc::new(Variant {
+        type_name: type_name.to_owned(),
+        member_name: member_name.to_owned(),
+        value,
+    }))
+}
+
+struct ValueSerializer;
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = MapSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(Value::Number(Number::I8(v)))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(Value::Number(Number::I16(v)))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(Value::Number(Number::I32(v)))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(Value::Number(Number::I64(v)))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(Value::Number(Number::U8(v)))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(Value::Number(Number::U16(v)))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(Value::Number(Number::U32(v)))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(Value::Number(Number::U64(v)))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(Value::Number(Number::F32(v)))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(Value::Number(Number::F64(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(Value::Char(v))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(Value::String(v.to_owned()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(Value::ByteData(v.to_vec()))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(variant("Option", "None", VariantValue::Empty))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        let value = value.serialize(ValueSerializer)?;
+        Ok(variant(
+            "Option",
+            "Some",
+            VariantValue::Value(Box::new(value)),
+        ))
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::Tuple(Items::new()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant_name: &'static str,
+    ) -> Result<Value, Error> {
+        Ok(variant(name, variant_name, VariantValue::Empty))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(ValueSerializer)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant_name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let value = value.serialize(ValueSerializer)?;
+        Ok(variant(
+            name,
+            variant_name,
+            VariantValue::Value(Box::new(value)),
+        ))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer::new(None, len, false))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer::new(None, Some(len), true))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant_name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer::new(
+            Some((name, variant_name)),
+            Some(len),
+            true,
+        ))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::new(None))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::new(None))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant_name: &'static str,
+        _len: usize,
+    ) -> Result<MapSerializer, Error> {
+        Ok(MapSerializer::new(Some((name, variant_name))))
+    }
+}
+
+struct SeqSerializer {
+    variant: Option<(&'static str, &'static str)>,
+    items: Vec<Value>,
+    tuple: bool,
+}
+
+impl SeqSerializer {
+    fn new(variant: Option<(&'static str, &'static str)>, len: Option<usize>, tuple: bool) -> Self {
+        Self {
+            variant,
+            items: Vec::with_capacity(len.unwrap_or_default()),
+            tuple,
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.items.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.variant {
+            Some((name, member)) => {
+                variant(name, member, VariantValue::Tuple(Items::from(self.items)))
+            }
+            None if self.tuple => Value::Tuple(Items::from(self.items)),
+            None => Value::List(Items::from(self.items)),
+        })
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    variant: Option<(&'static str, &'static str)>,
+    map: Map,
+
+    // the key of `serialize_key`, which is waiting for its value.
+    key: Option<String>,
+}
+
+impl MapSerializer {
+    fn new(variant: Option<(&'static str, &'static str)>) -> Self {
+        Self {
+            variant,
+            map: Map::new(),
+            key: None,
+        }
+    }
+
+    fn insert<T: Serialize + ?Sized>(&mut self, key: String, value: &T) -> Result<(), Error> {
+        self.map.insert(key, value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        Ok(match self.variant {
+            Some((name, member)) => variant(name, member, VariantValue::Object(self.map)),
+            None => Value::Object(self.map),
+        })
+    }
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self
+            .key
+            .take()
+            .ok_or_else(|| Error::Message("The value of the map has no key.".to_owned()))?;
+        self.insert(key, value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for MapSerializer {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key.to_owned(), value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+// the keys of the maps, i.e. the strings, chars and numbers.
+struct KeySerializer;
+
+fn key_error(type_name: &str) -> Error {
+    Error::Message(format!(
+        "The keys of the map expect string, char or number, found {}.",
+        type_name
+    ))
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Error> {
+        Err(key_error("boolean"))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_owned())
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> {
+        Err(key_error("byte data"))
+    }
+
+    fn serialize_none(self) -> Result<String, Error> {
+        Err(key_error("variant"))
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, Error> {
+        Err(key_error("variant"))
+    }
+
+    fn serialize_unit(self) -> Result<String, Error> {
+        Err(key_error("tuple"))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Err(key_error("tuple"))
+    }
+
+    // e.g. the enums without data as the keys: `HashMap<Color, u32>`.
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant_name: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant_name.to_owned())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(KeySerializer)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant_name: &'static str,
+        _value: &T,
+    ) -> Result<String, Error> {
+        Err(key_error("variant"))
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(key_error("list"))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(key_error("tuple"))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(key_error("tuple"))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant_name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(key_error("variant"))
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(key_error("object"))
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(key_error("object"))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant_name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(key_error("variant"))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Number(n) => match n {
+                Number::I8(v) => visitor.visit_i8(v),
+                Number::U8(v) => visitor.visit_u8(v),
+                Number::I16(v) => visitor.visit_i16(v),
+                Number::U16(v) => visitor.visit_u16(v),
+                Number::I32(v) => visitor.visit_i32(v),
+                Number::U32(v) => visitor.visit_u32(v),
+                Number::I64(v) => visitor.visit_i64(v),
+                Number::U64(v) => visitor.visit_u64(v),
+                Number::F32(v) => visitor.visit_f32(v),
+                Number::F64(v) => visitor.visit_f64(v),
+            },
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Char(c) => visitor.visit_char(c),
+            Value::String(s) => visitor.visit_string(s),
+            Value::DateTime(d) => visitor.visit_string(d.to_rfc3339()),
+            Value::ByteData(b) => visitor.visit_byte_buf(b),
+            Value::List(items) | Value::Tuple(items) => {
+                visitor.visit_seq(SeqDeserializer::new(items.into_iter().collect()))
+            }
+            Value::Object(map) => visitor.visit_map(MapDeserializer::new(map)),
+            Value::Variant(variant) => match Arc::unwrap_or_clone(variant) {
+                Variant {
+                    type_name,
+                    member_name,
+                    value: VariantValue::Empty,
+                } if type_name == "Option" && member_name == "None" => visitor.visit_none(),
+                Variant {
+                    type_name,
+                    member_name,
+                    value: VariantValue::Value(value),
+                } if type_name == "Option" && member_name == "Some" => visitor.visit_some(*value),
+                Variant {
+                    member_name, value, ..
+                } => visitor.visit_enum(EnumDeserializer { member_name, value }),
+            },
+        }
+    }
+
+    // a value other than `Option::None` and `Option::Some(v)`
+    // is the same as `Some(value)`.
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Variant(variant) if variant.type_name == "Option" => {
+                match Arc::unwrap_or_clone(variant) {
+                    Variant {
+                        member_name,
+                        value: VariantValue::Empty,
+                        ..
+                    } if member_name == "None" => visitor.visit_none(),
+                    Variant {
+                        member_name,
+                        value: VariantValue::Value(value),
+                        ..
+                    } if member_name == "Some" => visitor.visit_some(*value),
+                    variant => visitor.visit_some(Value::Variant(Arc::new(variant))),
+                }
+            }
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    // a unit variant can also be a string, e.g. `"Red"` for `Color::Red`.
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            Value::Variant(v) => {
+                let v = Arc::unwrap_or_clone(v);
+                visitor.visit_enum(EnumDeserializer {
+                    member_name: v.member_name,
+                    value: v.value,
+                })
+            }
+            Value::String(s) => visitor.visit_enum(EnumDeserializer {
+                member_name: s,
+                value: VariantValue::Empty,
+            }),
+            _ => Err(Error::Message(format!(
+                "Expect variant, found {}.",
+                self.type_name()
+            ))),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Tuple(items) if items.is_empty() => visitor.visit_unit(),
+            _ => Err(Error::Message(format!(
+                "Expect empty tuple, found {}.",
+                self.type_name()
+            ))),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+struct SeqDeserializer {
+    items: std::vec::IntoIter<Value>,
+}
+
+impl SeqDeserializer {
+    fn new(items: Vec<Value>) -> Self {
+        Self {
+            items: items.into_iter(),
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for SeqDeserializer {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.items.next().map(|v| seed.deserialize(v)).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct MapDeserializer {
+    entries: std::vec::IntoIter<(String, Value)>,
+
+    // the value of the last key returned by `next_key_seed`.
+    value: Option<Value>,
+}
+
+impl MapDeserializer {
+    fn new(map: Map) -> Self {
+        Self {
+            entries: map.into_iter().collect::<Vec<_>>().into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'de> MapAccess<'de> for MapDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Value::String(key)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self
+            .value
+            .take()
+            .ok_or_else(|| Error::Message("The key of the map has no value.".to_owned()))?;
+        seed.deserialize(value)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumDeserializer {
+    member_name: String,
+    value: VariantValue,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = VariantValue;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantValue), Error> {
+        let member = seed.deserialize(Value::String(self.member_name))?;
+        Ok((member, self.value))
+    }
+}
+
+fn variant_error(expected: &str, value: &VariantValue) -> Error {
+    let found = match value {
+        VariantValue::Empty => "empty variant",
+        VariantValue::Value(_) => "single value variant",
+        VariantValue::Tuple(_) => "tuple variant",
+        VariantValue::Object(_) => "object variant",
+    };
+    Error::Message(format!("Expect {}, found {}.", expected, found))
+}
+
+impl<'de> VariantAccess<'de> for VariantValue {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        match self {
+            VariantValue::Empty => Ok(()),
+            _ => Err(variant_error("empty variant", &self)),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        match self {
+            VariantValue::Value(value) => seed.deserialize(*value),
+            _ => Err(variant_error("single value variant", &self)),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            VariantValue::Tuple(items) => visitor.visit_seq(SeqDeserializer::new(items.into_vec())),
+            _ => Err(variant_error("tuple variant", &self)),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            VariantValue::Object(map) => visitor.visit_map(MapDeserializer::new(map)),
+            _ => Err(variant_error("object variant", &self)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use pretty_assertions::assert_eq;
+    use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        query::Query,
+        value::{Map, Value},
+    };
+
+    use super::{from_value, to_value};
+
+    struct User {
+        name: String,
+        age: u32,
+        tags: Vec<String>,
+        manager: Option<String>,
+    }
+
+    impl Serialize for User {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut state = serializer.serialize_struct("User", 4)?;
+            state.serialize_field("name", &self.name)?;
+            state.serialize_field("age", &self.age)?;
+            state.serialize_field("tags", &self.tags)?;
+            state.serialize_field("manager", &self.manager)?;
+            state.end()
+        }
+    }
+
+    struct Color;
+
+    impl Serialize for Color {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_unit_variant("Color", 0, "Red")
+        }
+    }
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    fn users() -> Vec<User> {
+        vec![
+            User {
+                name: "alice".to_owned(),
+                age: 30,
+                tags: vec!["admin".to_owned()],
+                manager: None,
+            },
+            User {
+                name: "bob".to_owned(),
+                age: 25,
+                tags: vec![],
+                manager: Some("alice".to_owned()),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_to_value() {
+        assert_eq!(
+            to_value(&users()).unwrap(),
+            value(
+                r#"[{name: "alice", age: 30_u32, tags: ["admin"], manager: Option::None}, {name: "bob", age: 25_u32, tags: [], manager: Option::Some("alice")}]"#
+            )
+        );
+        assert_eq!(to_value(&Color).unwrap(), value("Color::Red"));
+        assert_eq!(to_value(&(1, 'c')).unwrap(), value("(1, 'c')"));
+
+        // the keys of the maps are strings
+        let mut map = BTreeMap::new();
+        map.insert(1u64, true);
+        let mut expected = Map::new();
+        expected.insert("1".to_owned(), Value::Boolean(true));
+        assert_eq!(to_value(&map).unwrap(), Value::Object(expected));
+
+        let map: BTreeMap<Vec<i32>, i32> = [(vec![1], 2)].into_iter().collect();
+        assert!(to_value(&map).is_err());
+    }
+
+    #[test]
+    fn test_from_value() {
+        let mut map = Map::new();
+        map.insert("1".to_owned(), Value::Boolean(true));
+        let back: BTreeMap<String, bool> = from_value(Value::Object(map)).unwrap();
+        assert_eq!(back.get("1"), Some(&true));
+
+        let options: Vec<Option<u32>> =
+            from_value(value("[Option::Some(1), Option::None]")).unwrap();
+        assert_eq!(options, [Some(1), None]);
+        assert!(from_value::<Vec<u32>>(value("[1, -1]")).is_err());
+    }
+
+    #[test]
+    fn test_run_typed() {
+        let query = Query::compile(".[] | select(.age > 26) | .name").unwrap();
+        let names: Vec<String> = query.run_typed(&users()).unwrap();
+        assert_eq!(names, ["alice"]);
+
+        let query = Query::compile("map(.manager)").unwrap();
+        let managers: Vec<Vec<Option<String>>> = query.run_typed(&users()).unwrap();
+        assert_eq!(managers, [vec![None, Some("alice".to_owned())]]);
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Exporting the result object as shell variable assignments, e.g.
+//
+// `{name: "foo", port: 8080}` => `name='foo'` and `port='8080'`
+//
+// so that the output can be `eval`-ed by the shell:
+//
+// `$ eval "$(aq --export-shell '.server' config.ason)"`
+
+use crate::{error::Error, value::Value};
+
+pub fn export_shell(value: &Value) -> Result<String, Error> {
+    let map = match value {
+        Value::Object(map) => map,
+        _ => {
+            return Err(Error::Message(format!(
+                "Only an object can be exported as shell variables, found {}.",
+                value.type_name()
+            )))
+        }
+    };
+
+    let mut s = String::new();
+    for (key, item) in map.iter() {
+        // strings are exported without quotes, `null` is exported as
+        // the empty string, other values are exported as ASON text.
+        let text = match item {
+            Value::String(text) => text.clone(),
+            _ if item.is_null() => String::new(),
+            _ => item.to_string(),
+        };
+
+        s.push_str(&shell_variable_name(key));
+        s.push('=');
+        s.push_str(&shell_quote(&text));
+        s.push('\n');
+    }
+    Ok(s)
+}
+
+// the chars which are not allowed in the shell variable name
+// are replaced with `_`.
+fn shell_variable_name(key: &str) -> String {
+    let mut name: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if !name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Quote the text with single quotes, the single quote within the text
+/// is written as `'\''`.
+pub fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::input::{parse_input, ParseOptions};
+
+    use super::{export_shell, shell_quote};
+
+    fn export(text: &str) -> String {
+        let value = parse_input(text, None, &ParseOptions::unlimited()).unwrap();
+        export_shell(&value).unwrap_or_else(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_export_shell() {
+        assert_eq!(
+            export(r#"{name: "foo", port: 8080, debug: true}"#),
+            "name='foo'\nport='8080'\ndebug='true'\n"
+        );
+        assert_eq!(
+            export(r#"{tags: ["a", "b"], none: Option::None}"#),
+            "tags='[\"a\",\"b\"]'\nnone=''\n"
+        );
+
+        // the keys which are not valid shell variable names
+        assert_eq!(
+            export(r#"{"server-name": "x", "1st": "y"}"#),
+            "server_name='x'\n_1st='y'\n"
+        );
+
+        assert_eq!(
+            export("[1, 2]"),
+            "Only an object can be exported as shell variables, found list."
+        );
+    }
+
+    #[test]
+    fn test_shell_quote() {
+        assert_eq!(shell_quote("abc"), "'abc'");
+        assert_eq!(shell_quote("it's $HOME"), "'it'\\''s $HOME'");
+        assert_eq!(shell_quote(""), "''");
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Writing each result to its own file, i.e. `--output-template` and
+// `--split-output`.
+//
+// The template is a path with the expressions in braces, e.g.
+// `out/{.id}.ason`, the expressions are evaluated with each result,
+// it is the same as the string interpolation `"out/\(.id).ason"`.
+//
+// With `--split-output`, each result is an object `{filename, content}`,
+// and the content is written to the file.
+
+use std::{fs, path::Path};
+
+use crate::{error::Error, module::ModuleLoader, query::Query, value::Value};
+
+/// The query of the string interpolation of the output template.
+pub fn compile_template(template: &str, loader: &ModuleLoader) -> Result<Query, Error> {
+    let mut text = String::from("\"");
+    let mut depth = 0;
+    for c in template.chars() {
+        match c {
+            '{' if depth == 0 => {
+                text.push_str("\\(");
+                depth = 1;
+            }
+            '}' if depth == 1 => {
+                text.push(')');
+                depth = 0;
+            }
+            // the braces of the objects in the expressions
+            '{' => {
+                text.push(c);
+                depth += 1;
+            }
+            '}' if depth > 1 => {
+                text.push(c);
+                depth -= 1;
+            }
+            '"' | '\\' if depth == 0 => {
+                text.push('\\');
+                text.push(c);
+            }
+            _ => text.push(c),
+        }
+    }
+    if depth != 0 {
+        return Err(Error::Message(format!(
+            "Unclosed \"{{\" of the output template \"{}\".",
+            template
+        )));
+    }
+    text.push('"');
+    Query::compile_with_loader(&text, loader)
+}
+
+/// The file name and the content of the result.
+pub fn split_target(value: Value, template: Option<&Query>) -> Result<(String, Value), Error> {
+    if let Some(query) = template {
+        let mut names = query.run_value(value.clone())?;
+        return match (names.len(), names.pop()) {
+            (1, Some(Value::String(name))) => Ok((name, value)),
+            _ => Err(Error::Message(
+                "The output template expects to produce a single file name.".to_owned(),
+            )),
+        };
+    }
+
+    match value {
+        Value::Object(mut map) => match (map.remove("filename"), map.remove("content")) {
+            (Some(Value::String(name)), Some(content)) => Ok((name, content)),
+            _ => Err(Error::Message(
+                "The results of \"--split-output\" expect the objects \
+                `{filename: string, content: value}`."
+                    .to_owned(),
+            )),
+        },
+        _ => Err(Error::Message(format!(
+            "The results of \"--split-output\" expect objects, found {}.",
+            value.type_name()
+        ))),
+    }
+}
+
+/// Write the file, the parent directories are created if necessary.
+pub fn write_split_file(name: &str, content: &[u8]) -> Result<(), Error> {
+    let path = Path::new(name);
+    let outcome = match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(dir) => fs::create_dir_all(dir).and_then(|_| fs::write(path, content)),
+        None => fs::write(path, content),
+    };
+    outcome.map_err(|e| {
+        Error::Message(format!(
+            "Fail to write to the output file: \"{}\".\n{}",
+            name, e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        module::ModuleLoader,
+        value::Value,
+    };
+
+    use super::{compile_template, split_target, write_split_file};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_output_template() {
+        let loader = ModuleLoader::new(vec![]);
+
+        // the quotes are escaped, and the braces of the objects are kept
+        let template = compile_template("out/{.id}-{ {a: .n}.a }\".ason", &loader).unwrap();
+        let (name, content) = split_target(value("{id: 7, n: \"x\"}"), Some(&template)).unwrap();
+        assert_eq!(name, "out/7-x\".ason");
+        assert_eq!(content, value("{id: 7, n: \"x\"}"));
+
+        let template = compile_template("{.[]}", &loader).unwrap();
+        assert_eq!(
+            split_target(value("[\"a\", \"b\"]"), Some(&template))
+                .unwrap_err()
+                .to_string(),
+            "The output template expects to produce a single file name."
+        );
+
+        match compile_template("out/{.id", &loader) {
+            Ok(_) => panic!("the template is unclosed"),
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "Unclosed \"{\" of the output template \"out/{.id\"."
+            ),
+        }
+    }
+
+    #[test]
+    fn test_split_output() {
+        let (name, content) =
+            split_target(value("{filename: \"a.txt\", content: [1]}"), None).unwrap();
+        assert_eq!((name.as_str(), content), ("a.txt", value("[1]")));
+
+        assert_eq!(
+            split_target(value("[1]"), None).unwrap_err().to_string(),
+            "The results of \"--split-output\" expect objects, found list."
+        );
+        assert!(split_target(value("{filename: 1, content: 2}"), None).is_err());
+
+        // the parent directories are created
+        let dir = std::env::temp_dir().join(format!("aq-split-{}", std::process::id()));
+        let file = dir.join("a/b.txt");
+        write_split_file(file.to_str().unwrap(), b"hi").unwrap();
+        assert_eq!(std::fs::read(&file).unwrap(), b"hi");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,497 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The streaming parse of the input documents, i.e. `aq --stream`.
+//
+// The document is read char by char and turned into the events without
+// building the whole value tree, so the memory is bounded by the depth
+// of the document instead of its size. The events are the same as jq:
+//
+// - `[path, leaf]` for each leaf (a scalar, a variant, or an empty
+//   list, tuple or object), e.g. `[["a", 0], 1]`,
+// - `[path]` after the last item of a list, tuple or object, the path
+//   is the one of the last item, e.g. `[["a", 0]]`.
+//
+// e.g. `{a: [1, 2]}` is streamed as `[["a", 0], 1]`, `[["a", 1], 2]`,
+// `[["a", 1]]` and `[["a"]]`. The subtrees can be reassembled with
+// `fromstream(f)`, and the leading path elements can be removed
+// with `truncate_stream(f)`.
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, ErrorKind},
+};
+
+use crate::{
+    error::Error,
+    input::{parse_input, ParseOptions},
+    json::JsonDialect,
+    value::{Map, Value},
+};
+
+/// The events of the documents, an error is produced if the
+/// document is malformed or it can not be read.
+pub struct StreamEvents<R> {
+    chars: CharReader<R>,
+    dialect: Option<JsonDialect>,
+    frames: Vec<Frame>,
+    finished: bool,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Container {
+    List,
+    Tuple,
+    Object,
+}
+
+struct Frame {
+    container: Container,
+    length: usize,
+
+    // the key (or the index) of the current item.
+    current: Option<Value>,
+}
+
+pub fn stream_events<R: BufRead>(reader: R, dialect: Option<JsonDialect>) -> StreamEvents<R> {
+    StreamEvents {
+        chars: CharReader::new(reader),
+        dialect,
+        frames: vec![],
+        finished: false,
+    }
+}
+
+impl<R: BufRead> StreamEvents<R> {
+    fn next_event(&mut self) -> Result<Option<Value>, Error> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        loop {
+            self.skip_trivia(!self.frames.is_empty())?;
+            let Some(c) = self.chars.peek(0)? else {
+                if !self.frames.is_empty() {
+                    return Err(self.error("Unexpected end of the document"));
+                }
+                self.finished = true;
+                return Ok(None);
+            };
+
+            if let Some(frame) = self.frames.last() {
+                if c == close_of(frame.container) {
+                    self.chars.next()?;
+                    let frame = self.frames.pop().unwrap();
+                    let mut path = self.path();
+                    path.extend(frame.current);
+                    self.after_item();
+                    return Ok(Some(Value::List(vec![Value::List(path.into())].into())));
+                }
+            }
+
+            self.start_item()?;
+            self.skip_trivia(false)?;
+
+            let container = match self.chars.peek(0)? {
+                Some('[') => Some(Container::List),
+                Some('(') => Some(Container::Tuple),
+                Some('{') => Some(Container::Object),
+                _ => None,
+            };
+
+            let Some(container) = container else {
+                let text = self.read_leaf()?;
+                let value = parse_input(&text, self.dialect, &ParseOptions::unlimited())
+                    .map_err(|e| self.error(&e.to_string()))?;
+                return Ok(Some(self.leaf_event(value)));
+            };
+
+            self.chars.next()?;
+            self.skip_trivia(false)?;
+            if self.chars.peek(0)? == Some(close_of(container)) {
+                self.chars.next()?;
+                let value = match container {
+                    Container::List => Value::List(vec![].into()),
+                    Container::Tuple => Value::Tuple(vec![].into()),
+                    Container::Object => Value::Object(Map::new()),
+                };
+                return Ok(Some(self.leaf_event(value)));
+            }
+
+            self.frames.push(Frame {
+                container,
+                length: 0,
+                current: None,
+            });
+        }
+    }
+
+    fn path(&self) -> Vec<Value> {
+        self.frames
+            .iter()
+            .filter_map(|f| f.current.clone())
+            .collect()
+    }
+
+    fn leaf_event(&mut self, value: Value) -> Value {
+        let path = self.path();
+        self.after_item();
+        Value::List(vec![Value::List(path.into()), value].into())
+    }
+
+    // the key (or the index) of the next item of the container.
+    fn start_item(&mut self) -> Result<(), Error> {
+        let current = match self.frames.last().map(|f| f.container) {
+            None => return Ok(()),
+            Some(Container::Object) => {
+                let key = self.read_key()?;
+                self.skip_trivia(false)?;
+                if self.chars.next()? != Some(':') {
+                    return Err(self.error(&format!("Expect \":\" after the key \"{}\"", key)));
+                }
+                Value::String(key)
+            }
+            Some(_) => Value::from(self.frames.last().unwrap().length),
+        };
+
+        let frame = self.frames.last_mut().unwrap();
+        frame.length += 1;
+        frame.current = Some(current);
+        Ok(())
+    }
+
+    // the document is finished when the root item is finished.
+    fn after_item(&mut self) {
+        if self.frames.is_empty() {
+            self.finished = true;
+        }
+    }
+
+    fn read_key(&mut self) -> Result<String, Error> {
+        match self.chars.peek(0)? {
+            Some(quote @ ('"' | '\'')) => {
+                let mut text = String::new();
+                self.read_quoted(&mut text, quote, false)?;
+                match parse_input(&text, self.dialect, &ParseOptions::unlimited()) {
+                    Ok(Value::String(s)) => Ok(s),
+                    _ => Err(self.error(&format!("Invalid key {}", text))),
+                }
+            }
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.chars.peek(0)? {
+                    if !(c.is_alphanumeric() || c == '_' || c == '$') {
+                        break;
+                    }
+                    key.push(c);
+                    self.chars.next()?;
+                }
+                if key.is_empty() {
+                    return Err(self.error("Expect the key of the object"));
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    // the text of a scalar or a variant, the nested brackets
+    // (e.g. the payload of a variant) are included.
+    fn read_leaf(&mut self) -> Result<String, Error> {
+        let mut text = String::new();
+        let mut depth = 0usize;
+        while let Some(c) = self.chars.peek(0)? {
+            match c {
+                '"' | '\'' => {
+                    // the raw strings `r"..."` and `r#"..."#`
+                    let raw = text.ends_with('r') || text.ends_with("r#");
+                    self.read_quoted(&mut text, c, raw)?;
+                    continue;
+                }
+                '[' | '(' | '{' => depth += 1,
+                ']' | ')' | '}' if depth > 0 => depth -= 1,
+                ']' | ')' | '}' | ',' if depth == 0 => break,
+                '/' if depth == 0 && matches!(self.chars.peek(1)?, Some('/' | '*')) => break,
+                _ if depth == 0 && c.is_whitespace() => break,
+                _ => {}
+            }
+            text.push(c);
+            self.chars.next()?;
+        }
+
+        if text.is_empty() {
+            let found = self
+                .chars
+                .peek(0)?
+                .map_or("the end".to_owned(), |c| format!("'{}'", c));
+            return Err(self.error(&format!("Unexpected {}", found)));
+        }
+        Ok(text)
+    }
+
+    // append the quoted text (including the quotes) to the text.
+    fn read_quoted(&mut self, text: &mut String, quote: char, raw: bool) -> Result<(), Error> {
+        let hashed = raw && text.ends_with('#');
+        text.push(quote);
+        self.chars.next()?;
+        loop {
+            let Some(c) = self.chars.next()? else {
+                return Err(self.error("Unterminated string"));
+            };
+            text.push(c);
+            match c {
+                '\\' if !raw => {
+                    if let Some(escaped) = self.chars.next()? {
+                        text.push(escaped);
+                    }
+                }
+                _ if c == quote && !hashed => return Ok(()),
+                _ if c == quote && self.chars.peek(0)? == Some('#') => {
+                    text.push('#');
+                    self.chars.next()?;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // skip the whitespaces and comments, and the commas
+    // between the items if `commas` is true.
+    fn skip_trivia(&mut self, commas: bool) -> Result<(), Error> {
+        while let Some(c) = self.chars.peek(0)? {
+            match c {
+                ',' if commas => {}
+                '/' if self.chars.peek(1)? == Some('/') => {
+                    while !matches!(self.chars.next()?, Some('\n') | None) {}
+                    continue;
+                }
+                '/' if self.chars.peek(1)? == Some('*') => {
+                    self.chars.next()?;
+                    self.chars.next()?;
+                    loop {
+                        match self.chars.next()? {
+                            Some('*') if self.chars.peek(0)? == Some('/') => {
+                                self.chars.next()?;
+                                break;
+                            }
+                            Some(_) => {}
+                            None => return Err(self.error("Unterminated comment")),
+                        }
+                    }
+                    continue;
+                }
+                _ if c.is_whitespace() => {}
+                _ => break,
+            }
+            self.chars.next()?;
+        }
+        Ok(())
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error::Message(format!(
+            "{} (at line {} of the streamed document).",
+            message.trim_end_matches('.'),
+            self.chars.line
+        ))
+    }
+}
+
+impl<R: BufRead> Iterator for StreamEvents<R> {
+    type Item = Result<Value, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.next_event();
+        if event.is_err() {
+            self.finished = true;
+        }
+        event.transpose()
+    }
+}
+
+fn close_of(container: Container) -> char {
+    match container {
+        Container::List => ']',
+        Container::Tuple => ')',
+        Container::Object => '}',
+    }
+}
+
+// decode the UTF-8 chars of the reader, with the lookahead.
+struct CharReader<R> {
+    reader: R,
+    lookahead: VecDeque<char>,
+
+    // the line of the next char, starts from 1.
+    line: usize,
+}
+
+impl<R: BufRead> CharReader<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            lookahead: VecDeque::new(),
+            line: 1,
+        }
+    }
+
+    fn peek(&mut self, offset: usize) -> Result<Option<char>, Error> {
+        while self.lookahead.len() <= offset {
+            match self.decode()? {
+                Some(c) => self.lookahead.push_back(c),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(self.lookahead[offset]))
+    }
+
+    fn next(&mut self) -> Result<Option<char>, Error> {
+        let c = match self.lookahead.pop_front() {
+            Some(c) => Some(c),
+            None => self.decode()?,
+        };
+        if c == Some('\n') {
+            self.line += 1;
+        }
+        Ok(c)
+    }
+
+    fn decode(&mut self) -> Result<Option<char>, Error> {
+        let mut bytes = [0u8; 4];
+        if !self.read_byte(&mut bytes[0])? {
+            return Ok(None);
+        }
+
+        let length = match bytes[0] {
+            0x00..=0x7f => 1,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        };
+        for byte in bytes.iter_mut().take(length).skip(1) {
+            if !self.read_byte(byte)? {
+                break;
+            }
+        }
+
+        std::str::from_utf8(&bytes[..length])
+            .ok()
+            .and_then(|s| s.chars().next())
+            .map(Some)
+            .ok_or_else(|| {
+                Error::Message(format!(
+                    "The streamed document is not valid UTF-8 (at line {}).",
+                    self.line
+                ))
+            })
+    }
+
+    fn read_byte(&mut self, byte: &mut u8) -> Result<bool, Error> {
+        loop {
+            let buf = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    return Err(Error::Message(format!(
+                        "Fail to read the streamed document: {}",
+                        e
+                    )))
+                }
+            };
+            let Some(first) = buf.first() else {
+                return Ok(false);
+            };
+            *byte = *first;
+            self.reader.consume(1);
+            return Ok(true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::{evaluator::InputStream, json::JsonDialect, query::Query, value::Value};
+
+    use super::stream_events;
+
+    fn events(text: &str, dialect: Option<JsonDialect>) -> Vec<String> {
+        stream_events(text.as_bytes(), dialect)
+            .map(|e| e.unwrap().to_string())
+            .collect()
+    }
+
+    // run the query with the events of the text as the inputs.
+    fn run_stream(query: &str, text: &'static str) -> String {
+        let stream: InputStream = Rc::new(RefCell::new(stream_events(text.as_bytes(), None)));
+        let results = Query::compile(query)
+            .unwrap()
+            .with_input_stream(stream)
+            .run_value(Value::null())
+            .unwrap();
+        results[0].to_string()
+    }
+
+    #[test]
+    fn test_stream_events() {
+        assert_eq!(
+            events(
+                "{a: [1, 2], b: (3, \"s\"), c: Option::Some(1), d: {}}",
+                None
+            ),
+            [
+                "[[\"a\",0],1]",
+                "[[\"a\",1],2]",
+                "[[\"a\",1]]",
+                "[[\"b\",0],3]",
+                "[[\"b\",1],\"s\"]",
+                "[[\"b\",1]]",
+                "[[\"c\"],Option::Some(1)]",
+                "[[\"d\"],{}]",
+                "[[\"d\"]]"
+            ]
+        );
+        assert_eq!(events("3", None), ["[[],3]"]);
+        assert!(stream_events("[1, ".as_bytes(), None).any(|e| e.is_err()));
+    }
+
+    #[test]
+    fn test_stream_json5_events() {
+        assert_eq!(
+            events(
+                "// c\n{\"a\": [1, {\"b\": \"x,]\"}], 'c d': [], \"e\": /* x */ -2.5,}",
+                Some(JsonDialect::Json5)
+            ),
+            [
+                "[[\"a\",0],1]",
+                "[[\"a\",1,\"b\"],\"x,]\"]",
+                "[[\"a\",1,\"b\"]]",
+                "[[\"a\",1]]",
+                "[[\"c d\"],[]]",
+                "[[\"e\"],-2.5]",
+                "[[\"e\"]]"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reassemble_stream() {
+        let text = "{a: [1, {b: \"x\"}], c: []}";
+        assert_eq!(
+            run_stream("[fromstream(inputs)]", text),
+            "[{a:[1,{b:\"x\"}],c:[]}]"
+        );
+
+        // the leaf `[["c"], []]` is at the depth 1, so it is removed
+        assert_eq!(
+            run_stream("[fromstream(1 | truncate_stream(inputs))]", text),
+            "[[1,{b:\"x\"}]]"
+        );
+    }
+}
@@ -0,0 +1,495 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The tabular output, i.e. `aq --to-csv` and `aq --table`.
+//
+// The rows are the items of the result list (or the results of the
+// query), each row is either an object or a list (tuple). The header
+// of the object rows is the keys of all rows, in the order of their
+// first appearance. e.g. `[{id: 1, name: "foo"}, {id: 2, tags: ["a"]}]`
+//
+// ```
+// id,name,tags
+// 1,foo,
+// 2,,"[""a""]"
+// ```
+//
+// The heterogeneous rows would be exported ambiguously, so the cells
+// of the null, missing keys, nested lists/tuples/objects, variants and
+// byte data are rendered by the styles of the options, i.e. an empty
+// cell, the literal `null`, or the ASON text.
+//
+// The tabular input, i.e. `aq --from csv` and `aq --from tsv`, each row is
+// a document, it is an object keyed by the header row, or a list with
+// `--no-header`. The types of the cells are sniffed:
+//
+// - the empty cells are `Option::None`,
+// - `true` and `false` are booleans,
+// - integers are `i32` (or `i64`), other numbers are `f64`, but the
+//   integers with leading zeros (e.g. the zip codes `007`) are strings,
+// - the dates (e.g. `2024-03-16`) and the RFC 3339 datetimes are datetimes,
+// - others are strings.
+
+use chrono::DateTime;
+
+use crate::{
+    error::Error,
+    json::integer_number,
+    printer::{print_value, PrintOptions},
+    value::{Map, Number, Value},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TableFormat {
+    Csv,
+
+    /// The columns are aligned with spaces.
+    Table,
+}
+
+/// How a cell is rendered.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CellStyle {
+    Empty,
+
+    /// The literal text `null`.
+    Null,
+
+    /// The compact ASON text of the value.
+    Ason,
+}
+
+impl CellStyle {
+    pub fn from_name(name: &str) -> Result<Self, Error> {
+        match name {
+            "empty" => Ok(Self::Empty),
+            "null" => Ok(Self::Null),
+            "ason" => Ok(Self::Ason),
+            _ => Err(Error::Message(format!(
+                "Invalid cell style \"{}\", expect \"empty\", \"null\" or \"ason\".",
+                name
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TableOptions {
+    pub null: CellStyle,
+
+    /// The keys which are missing from an object row.
+    pub missing: CellStyle,
+
+    /// The lists, tuples and objects.
+    pub nested: CellStyle,
+    pub variant: CellStyle,
+    pub bytes: CellStyle,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        Self {
+            null: CellStyle::Empty,
+            missing: CellStyle::Empty,
+            nested: CellStyle::Ason,
+            variant: CellStyle::Ason,
+            bytes: CellStyle::Ason,
+        }
+    }
+}
+
+/// Export the rows, `value` is either a list (or tuple) of the rows,
+/// or a single object row.
+pub fn export_table(
+    value: &Value,
+    format: TableFormat,
+    options: &TableOptions,
+) -> Result<String, Error> {
+    let rows: Vec<&Value> = match value {
+        Value::List(items) | Value::Tuple(items) => items.iter().collect(),
+        Value::Object(_) => vec![value],
+        _ => {
+            return Err(Error::Message(format!(
+                "The tabular output expects list of rows, found {}.",
+                value.type_name()
+            )))
+        }
+    };
+
+    let mut lines: Vec<Vec<String>> = vec![];
+    if rows.iter().all(|r| matches!(r, Value::Object(_))) && !rows.is_empty() {
+        let mut keys: Vec<&String> = vec![];
+        for row in &rows {
+            if let Value::Object(map) = row {
+                for key in map.keys() {
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+        }
+
+        lines.push(keys.iter().map(|k| k.to_string()).collect());
+        for row in &rows {
+            let Value::Object(map) = row else {
+                unreachable!("all rows are objects")
+            };
+            lines.push(
+                keys.iter()
+                    .map(|key| match map.get(key.as_str()) {
+                        Some(field) => cell_text(field, options),
+                        None => style_text(options.missing, None),
+                    })
+                    .collect(),
+            );
+        }
+    } else {
+        for row in &rows {
+            let (Value::List(items) | Value::Tuple(items)) = row else {
+                return Err(Error::Message(format!(
+                    "The rows of the tabular output expect all objects or \
+                    all lists, found {}.",
+                    row.type_name()
+                )));
+            };
+            lines.push(items.iter().map(|f| cell_text(f, options)).collect());
+        }
+    }
+
+    Ok(match format {
+        TableFormat::Csv => lines
+            .iter()
+            .map(|cells| {
+                let quoted: Vec<String> = cells.iter().map(|c| quote_csv(c)).collect();
+                format!("{}\n", quoted.join(","))
+            })
+            .collect(),
+        TableFormat::Table => align_columns(&lines),
+    })
+}
+
+fn cell_text(field: &Value, options: &TableOptions) -> String {
+    match field {
+        _ if field.is_null() => style_text(options.null, Some(field)),
+        Value::String(s) => s.clone(),
+        Value::Char(c) => c.to_string(),
+        Value::Number(n) => n.plain_text(),
+        Value::Boolean(b) => b.to_string(),
+        Value::DateTime(d) => d.to_rfc3339(),
+        Value::ByteData(_) => style_text(options.bytes, Some(field)),
+        Value::Variant(_) => style_text(options.variant, Some(field)),
+        _ => style_text(options.nested, Some(field)),
+    }
+}
+
+// the text of the missing field is `null` in the ASON style.
+fn style_text(style: CellStyle, field: Option<&Value>) -> String {
+    match (style, field) {
+        (CellStyle::Empty, _) => String::new(),
+        (CellStyle::Ason, Some(value)) => print_value(value, &PrintOptions::compact()),
+        _ => "null".to_owned(),
+    }
+}
+
+// quote the field only if it contains the comma,
+// quote or line break, as RFC 4180.
+fn quote_csv(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_owned()
+    }
+}
+
+// the columns are separated by two spaces, the line breaks
+// of the cells are escaped to keep the rows aligned.
+fn align_columns(lines: &[Vec<String>]) -> String {
+    let lines: Vec<Vec<String>> = lines
+        .iter()
+        .map(|cells| {
+            cells
+                .iter()
+                .map(|c| c.replace('\r', "\\r").replace('\n', "\\n"))
+                .collect()
+        })
+        .collect();
+
+    let columns = lines.iter().map(|cells| cells.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|idx| {
+            lines
+                .iter()
+                .filter_map(|cells| cells.get(idx))
+                .map(|c| c.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let mut text = String::new();
+    for cells in &lines {
+        let mut line = String::new();
+        for (idx, cell) in cells.iter().enumerate() {
+            if idx > 0 {
+                line.push_str("  ");
+            }
+            line.push_str(cell);
+            line.push_str(&" ".repeat(widths[idx] - cell.chars().count()));
+        }
+        text.push_str(line.trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+/// Parse the rows of the CSV (or TSV) text, each row is a document.
+pub fn parse_table(text: &str, delimiter: char, header: bool) -> Result<Vec<Value>, Error> {
+    let mut records = parse_records(text, delimiter)?.into_iter();
+    if !header {
+        return Ok(records
+            .map(|(_, fields)| Value::List(fields.iter().map(|f| sniff_cell(f)).collect()))
+            .collect());
+    }
+
+    let Some((_, names)) = records.next() else {
+        return Ok(vec![]);
+    };
+    records
+        .map(|(line, fields)| {
+            if fields.len() > names.len() {
+                return Err(Error::Message(format!(
+                    "The row has {} fields, but the header has only {} fields (at line {}).",
+                    fields.len(),
+                    names.len(),
+                    line
+                )));
+            }
+            // the missing fields of the short rows are `Option::None`
+            let map: Map = names
+                .iter()
+                .enumerate()
+                .map(|(idx, name)| {
+                    let field = fields.get(idx).map_or("", String::as_str);
+                    (name.clone(), sniff_cell(field))
+                })
+                .collect();
+            Ok(Value::Object(map))
+        })
+        .collect()
+}
+
+// the records of RFC 4180 with the line numbers where they start,
+// the quoted fields can contain the delimiters, quotes (`""`) and
+// line breaks, and the blank lines are skipped.
+fn parse_records(text: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, Error> {
+    let mut records = vec![];
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+
+    while chars.peek().is_some() {
+        let start_line = line;
+        let mut fields = vec![];
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            match chars.next() {
+                Some('"') if field.is_empty() && !quoted => {
+                    quoted = true;
+                    loop {
+                        match chars.next() {
+                            Some('"') if chars.peek() == Some(&'"') => {
+                                chars.next();
+                                field.push('"');
+                            }
+                            Some('"') => break,
+                            Some(c) => {
+                                if c == '\n' {
+                                    line += 1;
+                                }
+                                field.push(c);
+                            }
+                            None => {
+                                return Err(Error::Message(format!(
+                                    "Unterminated quoted field (at line {}).",
+                                    start_line
+                                )))
+                            }
+                        }
+                    }
+                }
+                Some(c) if c == delimiter => {
+                    fields.push(std::mem::take(&mut field));
+                    quoted = false;
+                }
+                Some('\r') if chars.peek() == Some(&'\n') => {}
+                Some('\n') | None => {
+                    line += 1;
+                    break;
+                }
+                Some(c) => field.push(c),
+            }
+        }
+        fields.push(field);
+
+        if !(fields.len() == 1 && fields[0].is_empty() && !quoted) {
+            records.push((start_line, fields));
+        }
+    }
+    Ok(records)
+}
+
+fn sniff_cell(text: &str) -> Value {
+    match text {
+        "" => return Value::null(),
+        "true" => return Value::Boolean(true),
+        "false" => return Value::Boolean(false),
+        _ => {}
+    }
+
+    let digits = text.strip_prefix(['-', '+']).unwrap_or(text);
+    let leading_zero = digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.");
+    if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        if let (false, Ok(n)) = (leading_zero, text.parse::<i128>()) {
+            return Value::Number(integer_number(n));
+        }
+        return Value::String(text.to_owned());
+    }
+
+    let is_float = digits.starts_with(|c: char| c.is_ascii_digit())
+        && digits
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'-' | b'+'));
+    if is_float && !leading_zero {
+        if let Ok(f) = text.parse::<f64>() {
+            return Value::Number(Number::F64(f));
+        }
+    }
+
+    let bytes = text.as_bytes();
+    if bytes.len() >= 10 && bytes[4] == b'-' && bytes[7] == b'-' {
+        let datetime = if bytes.len() == 10 {
+            format!("{}T00:00:00Z", text)
+        } else {
+            text.replacen(' ', "T", 1)
+        };
+        if let Ok(d) = DateTime::parse_from_rfc3339(&datetime) {
+            return Value::DateTime(d);
+        }
+    }
+    Value::String(text.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{export_table, parse_table, CellStyle, TableFormat, TableOptions};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_export_csv() {
+        let rows = value("[{id: 1, name: \"a,b\"}, {id: 2, tags: [\"x\"], n: Option::None}]");
+        assert_eq!(
+            export_table(&rows, TableFormat::Csv, &TableOptions::default()).unwrap(),
+            "id,name,tags,n\n1,\"a,b\",,\n2,,\"[\"\"x\"\"]\",\n"
+        );
+
+        let options = TableOptions {
+            null: CellStyle::Null,
+            missing: CellStyle::Null,
+            nested: CellStyle::Empty,
+            ..TableOptions::default()
+        };
+        assert_eq!(
+            export_table(&rows, TableFormat::Csv, &options).unwrap(),
+            "id,name,tags,n\n1,\"a,b\",null,null\n2,null,,null\n"
+        );
+
+        // the list rows have no header
+        assert_eq!(
+            export_table(
+                &value("[[1, Option::Some(\"a\")], (2, h\"01\")]"),
+                TableFormat::Csv,
+                &TableOptions::default()
+            )
+            .unwrap(),
+            "1,\"Option::Some(\"\"a\"\")\"\n2,\"h\"\"01\"\"\"\n"
+        );
+    }
+
+    #[test]
+    fn test_export_table() {
+        let rows = value("[{id: 1, name: \"a,b\"}, {id: 2, tags: [\"x\"], n: Option::None}]");
+        assert_eq!(
+            export_table(&rows, TableFormat::Table, &TableOptions::default()).unwrap(),
+            "id  name  tags   n\n1   a,b\n2         [\"x\"]\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_rows() {
+        let error_of = |text: &str| {
+            export_table(&value(text), TableFormat::Csv, &TableOptions::default())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(
+            error_of("1"),
+            "The tabular output expects list of rows, found number."
+        );
+        assert_eq!(
+            error_of("[1, 2]"),
+            "The rows of the tabular output expect all objects or all lists, found number."
+        );
+        assert_eq!(
+            CellStyle::from_name("none").unwrap_err().to_string(),
+            "Invalid cell style \"none\", expect \"empty\", \"null\" or \"ason\"."
+        );
+    }
+
+    #[test]
+    fn test_parse_table() {
+        let text = "id,name,zip,score,ok\r\n1,\"Smith, J\",007,2.5,true\n\n2,\"say \"\"hi\"\"\nthere\",,-3,false\n3,x\n";
+        let rows: Vec<String> = parse_table(text, ',', true)
+            .unwrap()
+            .iter()
+            .map(|row| row.to_string())
+            .collect();
+        assert_eq!(
+            rows,
+            [
+                r#"{id:1,name:"Smith, J",zip:"007",score:2.5,ok:true}"#,
+                r#"{id:2,name:"say \"hi\"\nthere",zip:Option::None,score:-3,ok:false}"#,
+                r#"{id:3,name:"x",zip:Option::None,score:Option::None,ok:Option::None}"#
+            ]
+        );
+
+        // without the header
+        let rows: Vec<String> = parse_table("a\tb\n1\t0.5\n", '\t', false)
+            .unwrap()
+            .iter()
+            .map(|row| row.to_string())
+            .collect();
+        assert_eq!(rows, [r#"["a","b"]"#, "[1,0.5]"]);
+
+        assert_eq!(
+            parse_table("a\n1,2\n", ',', true).unwrap_err().to_string(),
+            "The row has 2 fields, but the header has only 1 fields (at line 2)."
+        );
+        assert_eq!(
+            parse_table("a\n\"x\n", ',', true).unwrap_err().to_string(),
+            "Unterminated quoted field (at line 2)."
+        );
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The color themes of the output, i.e. `aq --theme monokai`.
+//
+// A theme assigns a style to each type of the tokens, the style is
+// a list of words, e.g. `"bold blue"`, `"bright_green"`, or the raw
+// ANSI SGR parameters, e.g. `"38;5;208"`. The themes can be defined
+// in the config file, e.g.
+//
+// ```
+// {
+//     theme: "mine"
+//     themes: {
+//         mine: {
+//             key: "bold blue"
+//             string: "green"
+//             number: "38;5;208"
+//         }
+//     }
+// }
+// ```
+//
+// the tokens without style are not colored, and the `theme` is the
+// theme of the colored output when `--theme` is not specified.
+
+use crate::{error::Error, value::Value};
+
+/// The ANSI SGR parameters of a style, e.g. `1;34`,
+/// an empty style leaves the text uncolored.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Style(String);
+
+impl Style {
+    /// Parse the style words, e.g. `bold blue` or `38;5;208`.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut codes = vec![];
+        for word in text.split_whitespace() {
+            let code = match word {
+                "bold" => "1",
+                "dim" => "2",
+                "italic" => "3",
+                "underline" => "4",
+                _ if word.chars().all(|c| c.is_ascii_digit() || c == ';') => word,
+                _ => match color_code(word) {
+                    Some(code) => code,
+                    None => {
+                        return Err(Error::Message(format!(
+                            "Invalid style \"{}\" of the theme, expect e.g. \"bold blue\" or \"38;5;208\".",
+                            text
+                        )))
+                    }
+                },
+            };
+            codes.push(code);
+        }
+        Ok(Self(codes.join(";")))
+    }
+
+    pub fn paint(&self, text: &str) -> String {
+        if self.0.is_empty() {
+            text.to_owned()
+        } else {
+            format!("\x1b[{}m{}\x1b[0m", self.0, text)
+        }
+    }
+}
+
+fn color_code(name: &str) -> Option<&'static str> {
+    let code = match name {
+        "black" => "30",
+        "red" => "31",
+        "green" => "32",
+        "yellow" => "33",
+        "blue" => "34",
+        "magenta" => "35",
+        "cyan" => "36",
+        "white" => "37",
+        "gray" | "grey" | "bright_black" => "90",
+        "bright_red" => "91",
+        "bright_green" => "92",
+        "bright_yellow" => "93",
+        "bright_blue" => "94",
+        "bright_magenta" => "95",
+        "bright_cyan" => "96",
+        "bright_white" => "97",
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// The styles of the token types.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Theme {
+    pub key: Style,
+    pub string: Style,
+    pub char: Style,
+    pub number: Style,
+    pub boolean: Style,
+    pub null: Style,
+    pub variant: Style,
+    pub datetime: Style,
+    pub bytes: Style,
+
+    /// The brackets, commas and colons.
+    pub punctuation: Style,
+}
+
+pub const BUILTIN_THEMES: [&str; 3] = ["monokai", "solarized", "light"];
+
+impl Theme {
+    pub fn builtin(name: &str) -> Option<Self> {
+        // key, string, char, number, boolean, null, variant, datetime, bytes, punctuation
+        let codes = match name {
+            "monokai" => [
+                "38;5;81", "38;5;186", "38;5;186", "38;5;141", "38;5;141", "38;5;242", "38;5;197",
+                "38;5;208", "38;5;208", "38;5;250",
+            ],
+            "solarized" => [
+                "38;5;33", "38;5;37", "38;5;37", "38;5;136", "38;5;166", "38;5;245", "38;5;125",
+                "38;5;61", "38;5;61", "38;5;244",
+            ],
+            // the dark colors which are readable on a light background
+            "light" => [
+                "34", "32", "32", "35", "38;5;130", "90", "31", "36", "36", "",
+            ],
+            _ => return None,
+        };
+
+        let style = |idx: usize| Style(codes[idx].to_owned());
+        Some(Self {
+            key: style(0),
+            string: style(1),
+            char: style(2),
+            number: style(3),
+            boolean: style(4),
+            null: style(5),
+            variant: style(6),
+            datetime: style(7),
+            bytes: style(8),
+            punctuation: style(9),
+        })
+    }
+
+    /// Read the theme definition, i.e. an object of the
+    /// token types and their styles.
+    pub fn from_value(value: &Value) -> Result<Self, Error> {
+        let Value::Object(map) = value else {
+            return Err(Error::Message(format!(
+                "The theme definition expects object, found {}.",
+                value.type_name()
+            )));
+        };
+
+        let mut theme = Theme::default();
+        for (token, style) in map.iter() {
+            let Value::String(text) = style else {
+                return Err(Error::Message(format!(
+                    "The style of \"{}\" expects string, found {}.",
+                    token,
+                    style.type_name()
+                )));
+            };
+
+            let slot = match token.as_str() {
+                "key" => &mut theme.key,
+                "string" => &mut theme.string,
+                "char" => &mut theme.char,
+                "number" => &mut theme.number,
+                "boolean" => &mut theme.boolean,
+                "null" => &mut theme.null,
+                "variant" => &mut theme.variant,
+                "datetime" => &mut theme.datetime,
+                "bytes" => &mut theme.bytes,
+                "punctuation" => &mut theme.punctuation,
+                _ => {
+                    return Err(Error::Message(format!(
+                        "Unknown token type \"{}\" of the theme.",
+                        token
+                    )))
+                }
+            };
+            *slot = Style::parse(text)?;
+        }
+        Ok(theme)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        printer::{print_value, PrintOptions},
+        value::Value,
+    };
+
+    use super::{Style, Theme, BUILTIN_THEMES};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_style() {
+        assert_eq!(
+            Style::parse("bold blue").unwrap().paint("x"),
+            "\x1b[1;34mx\x1b[0m"
+        );
+        assert_eq!(
+            Style::parse("38;5;208").unwrap().paint("x"),
+            "\x1b[38;5;208mx\x1b[0m"
+        );
+        assert_eq!(Style::parse("").unwrap().paint("x"), "x");
+        assert_eq!(
+            Style::parse("bold pink").unwrap_err().to_string(),
+            "Invalid style \"bold pink\" of the theme, expect e.g. \"bold blue\" or \"38;5;208\"."
+        );
+    }
+
+    #[test]
+    fn test_theme() {
+        for name in BUILTIN_THEMES {
+            assert!(Theme::builtin(name).is_some());
+        }
+        assert_eq!(Theme::builtin("x"), None);
+
+        // the default theme colors nothing
+        let v = value("{a: [1, \"s\"], b: Option::None, d: Option::Some(true)}");
+        assert_eq!(
+            print_value(&v, &PrintOptions::compact().with_theme(Theme::default())),
+            v.to_string()
+        );
+        assert_eq!(
+            print_value(&v, &PrintOptions::pretty("  ").with_theme(Theme::default())),
+            print_value(&v, &PrintOptions::pretty("  "))
+        );
+
+        let theme = Theme::from_value(&value("{key: \"blue\", number: \"red\"}")).unwrap();
+        assert_eq!(
+            print_value(&value("{a: 1}"), &PrintOptions::compact().with_theme(theme)),
+            "{\x1b[34ma\x1b[0m:\x1b[31m1\x1b[0m}"
+        );
+
+        assert_eq!(
+            Theme::from_value(&value("{keys: \"red\"}"))
+                .unwrap_err()
+                .to_string(),
+            "Unknown token type \"keys\" of the theme."
+        );
+        assert_eq!(
+            Theme::from_value(&value("[1]")).unwrap_err().to_string(),
+            "The theme definition expects object, found list."
+        );
+    }
+}
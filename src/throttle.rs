@@ -0,0 +1,175 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The output controls of the line-delimited streaming, i.e.
+// `aq --lines --sample-rate 0.01` and `aq --lines --rate-limit 100/s`,
+// for tapping a high-volume stream without overwhelming the consumers.
+//
+// The results which are not sampled or exceed the rate limit are
+// dropped instead of delayed, so the reading of the input never waits
+// for the output.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::error::Error;
+
+/// Parse the sample rate, a number between 0 (exclusive) and 1 (inclusive).
+pub fn parse_sample_rate(text: &str) -> Result<f64, Error> {
+    match text.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok(rate),
+        _ => Err(Error::Message(format!(
+            "Invalid sample rate \"{}\", expect a number between 0 and 1, e.g. \"0.01\".",
+            text
+        ))),
+    }
+}
+
+/// Parse the rate limit, e.g. `100/s`, `30/m` and `5/h`,
+/// the result is the number of results per second.
+pub fn parse_rate_limit(text: &str) -> Result<f64, Error> {
+    let invalid = || {
+        Error::Message(format!(
+            "Invalid rate limit \"{}\", expect e.g. \"100/s\", \"30/m\" or \"5/h\".",
+            text
+        ))
+    };
+
+    let (count, unit) = text.split_once('/').ok_or_else(invalid)?;
+    let count: f64 = count.parse().map_err(|_| invalid())?;
+    let seconds = match unit {
+        "s" => 1.0,
+        "m" => 60.0,
+        "h" => 60.0 * 60.0,
+        _ => return Err(invalid()),
+    };
+
+    if !(count > 0.0 && count.is_finite()) {
+        return Err(invalid());
+    }
+    Ok(count / seconds)
+}
+
+/// Keep each result with the probability of the rate.
+pub struct Sampler {
+    rate: f64,
+    state: u64,
+}
+
+impl Sampler {
+    /// The seed is the current time, so the samples differ between runs.
+    pub fn new(rate: f64) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::with_seed(rate, nanos ^ std::process::id() as u64)
+    }
+
+    pub fn with_seed(rate: f64, seed: u64) -> Self {
+        Self { rate, state: seed }
+    }
+
+    pub fn keep(&mut self) -> bool {
+        // SplitMix64, the top 53 bits are the fraction in [0, 1).
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        ((z >> 11) as f64 / (1u64 << 53) as f64) < self.rate
+    }
+}
+
+/// The token bucket, the burst is at most one second of the results
+/// (and at least one result).
+pub struct RateLimiter {
+    per_second: f64,
+    tokens: f64,
+    last: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: f64) -> Self {
+        Self {
+            per_second,
+            tokens: Self::capacity_of(per_second),
+            last: None,
+        }
+    }
+
+    fn capacity_of(per_second: f64) -> f64 {
+        per_second.max(1.0)
+    }
+
+    pub fn allow(&mut self, now: Instant) -> bool {
+        if let Some(last) = self.last {
+            let elapsed = now.saturating_duration_since(last).as_secs_f64();
+            self.tokens =
+                (self.tokens + elapsed * self.per_second).min(Self::capacity_of(self.per_second));
+        }
+        self.last = Some(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use pretty_assertions::assert_eq;
+
+    use super::{parse_rate_limit, parse_sample_rate, RateLimiter, Sampler};
+
+    #[test]
+    fn test_parse_controls() {
+        assert_eq!(parse_sample_rate("0.01").unwrap(), 0.01);
+        assert_eq!(parse_sample_rate("1").unwrap(), 1.0);
+        assert!(parse_sample_rate("0").is_err());
+        assert!(parse_sample_rate("1.5").is_err());
+
+        assert_eq!(parse_rate_limit("100/s").unwrap(), 100.0);
+        assert_eq!(parse_rate_limit("30/m").unwrap(), 0.5);
+        assert_eq!(parse_rate_limit("36/h").unwrap(), 0.01);
+        assert_eq!(
+            parse_rate_limit("30").unwrap_err().to_string(),
+            "Invalid rate limit \"30\", expect e.g. \"100/s\", \"30/m\" or \"5/h\"."
+        );
+        assert!(parse_rate_limit("0/s").is_err());
+        assert!(parse_rate_limit("3/d").is_err());
+    }
+
+    #[test]
+    fn test_sampler() {
+        let mut sampler = Sampler::with_seed(0.1, 42);
+        let kept = (0..10000).filter(|_| sampler.keep()).count();
+        assert!((800..1200).contains(&kept), "{}", kept);
+
+        let mut sampler = Sampler::with_seed(1.0, 42);
+        assert!((0..100).all(|_| sampler.keep()));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(2.0);
+        let start = Instant::now();
+
+        // the burst is one second of the results
+        assert_eq!((0..5).filter(|_| limiter.allow(start)).count(), 2);
+        assert!(limiter.allow(start + Duration::from_millis(500)));
+        assert!(!limiter.allow(start + Duration::from_millis(600)));
+
+        // at least one result
+        let mut limiter = RateLimiter::new(0.5);
+        assert!(limiter.allow(start));
+        assert!(!limiter.allow(start + Duration::from_secs(1)));
+        assert!(limiter.allow(start + Duration::from_secs(2)));
+    }
+}
@@ -0,0 +1,804 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Reading and writing TOML (v1.0), i.e. `aq --from toml` and `aq --to toml`,
+// e.g. for querying and updating the `Cargo.toml` files.
+//
+// The values are mapped onto the ASON types:
+//
+// - integers are converted to `i32`, or `i64` if they do not fit,
+// - floats (including `inf` and `nan`) are converted to `f64`,
+// - the offset datetimes are converted to datetimes, the local datetimes
+//   and the local dates are treated as UTC, e.g. `1979-05-27` is
+//   `d"1979-05-27T00:00:00Z"`, and the local times are strings,
+// - tables, inline tables and arrays of tables are objects and lists.
+//
+// TOML has no null, so `Option::None` is omitted when writing an object,
+// and the other types which TOML lacks are converted in the same way as
+// JSON (see `to_json_string`).
+
+use chrono::DateTime;
+
+use crate::{
+    error::Error,
+    json::integer_number,
+    value::{Map, Number, Value, VariantValue},
+};
+
+pub fn parse_toml(text: &str) -> Result<Value, Error> {
+    let mut parser = TomlParser {
+        chars: text.chars().collect(),
+        pos: 0,
+        line: 1,
+    };
+    parser.parse_document().map(Value::Object)
+}
+
+struct TomlParser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Header {
+    Table,
+    ArrayOfTables,
+}
+
+impl TomlParser {
+    fn error(&self, message: &str) -> Error {
+        Error::Message(format!("{} (at line {})", message, self.line))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(idx, c)| self.peek_at(idx) == Some(c))
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), Error> {
+        match self.next_char() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("Expect '{}', found '{}'.", expected, c))),
+            None => Err(self.error(&format!("Expect '{}', found the end of text.", expected))),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    // the spaces, comments and line breaks, e.g. between the items of arrays.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') => {
+                    self.next_char();
+                }
+                Some('\r') if self.peek_at(1) == Some('\n') => {
+                    self.pos += 1;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // the rest of the line must be blank or a comment.
+    fn expect_line_end(&mut self) -> Result<(), Error> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.next_char();
+                Ok(())
+            }
+            Some(c) => Err(self.error(&format!("Unexpected '{}' after the value.", c))),
+        }
+    }
+
+    fn parse_document(&mut self) -> Result<Map, Error> {
+        let mut root = Map::new();
+        let mut current: Vec<String> = vec![];
+
+        loop {
+            self.skip_blank();
+            match self.peek() {
+                None => break,
+                Some('[') => {
+                    let kind = if self.peek_at(1) == Some('[') {
+                        self.pos += 2;
+                        Header::ArrayOfTables
+                    } else {
+                        self.pos += 1;
+                        Header::Table
+                    };
+                    self.skip_spaces();
+                    let path = self.parse_key()?;
+                    self.skip_spaces();
+                    self.expect(']')?;
+                    if kind == Header::ArrayOfTables {
+                        self.expect(']')?;
+                    }
+                    self.expect_line_end()?;
+
+                    self.open_table(&mut root, &path, kind)?;
+                    current = path;
+                }
+                Some(_) => {
+                    let (path, value) = self.parse_key_value()?;
+                    self.expect_line_end()?;
+                    let table = self.table_at(&mut root, &current)?;
+                    self.insert_dotted(table, &path, value)?;
+                }
+            }
+        }
+        Ok(root)
+    }
+
+    fn open_table(&self, root: &mut Map, path: &[String], kind: Header) -> Result<(), Error> {
+        let (last, parents) = path.split_last().expect("the key is not empty");
+        let parent = self.table_at(root, parents)?;
+        match (kind, parent.get_mut(last)) {
+            (Header::Table, None) => {
+                parent.insert(last.clone(), Value::Object(Map::new()));
+            }
+            // the super-tables can be defined after the sub-tables,
+            // e.g. `[a.b]` and then `[a]`.
+            (Header::Table, Some(Value::Object(_))) => {}
+            (Header::ArrayOfTables, None) => {
+                parent.insert(
+                    last.clone(),
+                    Value::List(vec![Value::Object(Map::new())].into()),
+                );
+            }
+            (Header::ArrayOfTables, Some(Value::List(items))) => {
+                items.push(Value::Object(Map::new()));
+            }
+            _ => {
+                return Err(self.error(&format!(
+                    "The key \"{}\" is already defined as a value.",
+                    path.join(".")
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    // the table of the path, the missing tables are created, and
+    // the array of tables refers to its last table.
+    fn table_at<'a>(&self, map: &'a mut Map, path: &[String]) -> Result<&'a mut Map, Error> {
+        let Some((first, rest)) = path.split_first() else {
+            return Ok(map);
+        };
+        if map.get(first).is_none() {
+            map.insert(first.clone(), Value::Object(Map::new()));
+        }
+        let table = match map.get_mut(first) {
+            Some(Value::Object(table)) => table,
+            Some(Value::List(items)) if matches!(items.last(), Some(Value::Object(_))) => {
+                let Some(Value::Object(table)) = items.last_mut() else {
+                    unreachable!("the last item is a table")
+                };
+                table
+            }
+            _ => return Err(self.error(&format!("The key \"{}\" is not a table.", first))),
+        };
+        self.table_at(table, rest)
+    }
+
+    fn insert_dotted(&self, table: &mut Map, path: &[String], value: Value) -> Result<(), Error> {
+        let (last, parents) = path.split_last().expect("the key is not empty");
+        let table = self.table_at(table, parents)?;
+        if table.contains_key(last) {
+            return Err(self.error(&format!(
+                "The key \"{}\" is defined more than once.",
+                path.join(".")
+            )));
+        }
+        table.insert(last.clone(), value);
+        Ok(())
+    }
+
+    fn parse_key_value(&mut self) -> Result<(Vec<String>, Value), Error> {
+        let path = self.parse_key()?;
+        self.skip_spaces();
+        self.expect('=')?;
+        self.skip_spaces();
+        let value = self.parse_value()?;
+        Ok((path, value))
+    }
+
+    // the dotted key, e.g. `a."b.c".d`
+    fn parse_key(&mut self) -> Result<Vec<String>, Error> {
+        let mut path = vec![];
+        loop {
+            self.skip_spaces();
+            let segment = match self.peek() {
+                Some('"') => self.parse_basic_string()?,
+                Some('\'') => self.parse_literal_string()?,
+                _ => {
+                    let start = self.pos;
+                    while self
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                    {
+                        self.pos += 1;
+                    }
+                    if start == self.pos {
+                        return Err(self.error("Expect a key."));
+                    }
+                    self.chars[start..self.pos].iter().collect()
+                }
+            };
+            path.push(segment);
+
+            self.skip_spaces();
+            if self.peek() == Some('.') {
+                self.pos += 1;
+            } else {
+                return Ok(path);
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, Error> {
+        match self.peek() {
+            Some('"') if self.starts_with("\"\"\"") => {
+                self.parse_multiline_string('"').map(Value::String)
+            }
+            Some('\'') if self.starts_with("'''") => {
+                self.parse_multiline_string('\'').map(Value::String)
+            }
+            Some('"') => self.parse_basic_string().map(Value::String),
+            Some('\'') => self.parse_literal_string().map(Value::String),
+            Some('[') => self.parse_array(),
+            Some('{') => self.parse_inline_table(),
+            Some(_) => {
+                let start = self.pos;
+                while self
+                    .peek()
+                    .is_some_and(|c| !matches!(c, ',' | ']' | '}' | '#' | '\n' | '\r'))
+                {
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                self.parse_scalar(text.trim_end())
+            }
+            None => Err(self.error("Expect a value.")),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Value, Error> {
+        self.pos += 1; // consume '['
+        let mut items = vec![];
+        loop {
+            self.skip_blank();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                break;
+            }
+            items.push(self.parse_value()?);
+            self.skip_blank();
+            match self.next_char() {
+                Some(',') => {}
+                Some(']') => break,
+                _ => return Err(self.error("Expect ',' or ']' of the array.")),
+            }
+        }
+        Ok(Value::List(items.into()))
+    }
+
+    fn parse_inline_table(&mut self) -> Result<Value, Error> {
+        self.pos += 1; // consume '{'
+        let mut table = Map::new();
+        self.skip_spaces();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Value::Object(table));
+        }
+        loop {
+            self.skip_spaces();
+            let (path, value) = self.parse_key_value()?;
+            self.insert_dotted(&mut table, &path, value)?;
+            self.skip_spaces();
+            match self.next_char() {
+                Some(',') => {}
+                Some('}') => break,
+                _ => return Err(self.error("Expect ',' or '}' of the inline table.")),
+            }
+        }
+        Ok(Value::Object(table))
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.next_char() {
+                Some('\'') => return Ok(s),
+                Some('\n') | None => return Err(self.error("Unterminated string.")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.next_char() {
+                Some('"') => return Ok(s),
+                Some('\\') => s.push(self.parse_escape()?),
+                Some('\n') | None => return Err(self.error("Unterminated string.")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    fn parse_multiline_string(&mut self, quote: char) -> Result<String, Error> {
+        self.pos += 3;
+        // the line break after the opening quotes is trimmed
+        if self.peek() == Some('\r') && self.peek_at(1) == Some('\n') {
+            self.pos += 1;
+        }
+        if self.peek() == Some('\n') {
+            self.next_char();
+        }
+
+        let closing: String = std::iter::repeat_n(quote, 3).collect();
+        let mut s = String::new();
+        loop {
+            if self.starts_with(&closing) {
+                self.pos += 3;
+                // up to two quotes can be adjacent to the closing quotes
+                for _ in 0..2 {
+                    if self.peek() == Some(quote) {
+                        s.push(quote);
+                        self.pos += 1;
+                    }
+                }
+                return Ok(s);
+            }
+            match self.next_char() {
+                Some('\\') if quote == '"' => {
+                    // the line ending backslash trims the whitespaces
+                    let rest = self.pos;
+                    self.skip_spaces();
+                    if matches!(self.peek(), Some('\n' | '\r')) {
+                        self.skip_blank_lines();
+                    } else {
+                        self.pos = rest;
+                        s.push(self.parse_escape()?);
+                    }
+                }
+                Some(c) => s.push(c),
+                None => return Err(self.error("Unterminated multi-line string.")),
+            }
+        }
+    }
+
+    fn skip_blank_lines(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.next_char();
+        }
+    }
+
+    fn parse_escape(&mut self) -> Result<char, Error> {
+        let c = self
+            .next_char()
+            .ok_or_else(|| self.error("Unterminated string."))?;
+        let hex_digits = match c {
+            'u' => 4,
+            'U' => 8,
+            _ => {
+                return match c {
+                    'b' => Ok('\u{8}'),
+                    't' => Ok('\t'),
+                    'n' => Ok('\n'),
+                    'f' => Ok('\u{c}'),
+                    'r' => Ok('\r'),
+                    'e' => Ok('\u{1b}'),
+                    '"' => Ok('"'),
+                    '\\' => Ok('\\'),
+                    _ => Err(self.error(&format!("Invalid escape \"\\{}\".", c))),
+                }
+            }
+        };
+        let digits: String = self.chars.iter().skip(self.pos).take(hex_digits).collect();
+        self.pos += hex_digits;
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.error(&format!("Invalid escape \"\\{}{}\".", c, digits)))
+    }
+
+    // the booleans, numbers and datetimes.
+    fn parse_scalar(&self, text: &str) -> Result<Value, Error> {
+        match text {
+            "true" => return Ok(Value::Boolean(true)),
+            "false" => return Ok(Value::Boolean(false)),
+            "inf" | "+inf" => return Ok(Value::Number(Number::F64(f64::INFINITY))),
+            "-inf" => return Ok(Value::Number(Number::F64(f64::NEG_INFINITY))),
+            "nan" | "+nan" | "-nan" => return Ok(Value::Number(Number::F64(f64::NAN))),
+            _ => {}
+        }
+
+        if let Some(value) = parse_datetime(text) {
+            return Ok(value);
+        }
+
+        let invalid = || self.error(&format!("Invalid value \"{}\".", text));
+        let digits = text.replace('_', "");
+        if text.starts_with('_') || text.ends_with('_') || text.contains("__") {
+            return Err(invalid());
+        }
+
+        let radix_body = [("0x", 16), ("0o", 8), ("0b", 2)]
+            .iter()
+            .find_map(|(prefix, radix)| digits.strip_prefix(prefix).map(|d| (d, *radix)));
+        if let Some((body, radix)) = radix_body {
+            return i64::from_str_radix(body, radix)
+                .map(|n| Value::Number(integer_number(n as i128)))
+                .map_err(|_| invalid());
+        }
+
+        let unsigned = digits.trim_start_matches(['+', '-']);
+        let is_number = unsigned.starts_with(|c: char| c.is_ascii_digit())
+            && unsigned
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+        if !is_number {
+            return Err(invalid());
+        }
+        if unsigned.chars().all(|c| c.is_ascii_digit()) {
+            // the leading zeros are not allowed
+            if unsigned.len() > 1 && unsigned.starts_with('0') {
+                return Err(invalid());
+            }
+            return digits
+                .parse::<i64>()
+                .map(|n| Value::Number(integer_number(n as i128)))
+                .map_err(|_| invalid());
+        }
+        digits
+            .parse::<f64>()
+            .map(|f| Value::Number(Number::F64(f)))
+            .map_err(|_| invalid())
+    }
+}
+
+// the offset datetimes, the local datetimes and dates (as UTC),
+// and the local times (as strings).
+fn parse_datetime(text: &str) -> Option<Value> {
+    let bytes = text.as_bytes();
+    let is_date = bytes.len() >= 10
+        && bytes[4] == b'-'
+        && bytes[7] == b'-'
+        && text[..4].bytes().all(|b| b.is_ascii_digit());
+    let is_time = bytes.len() >= 8 && bytes[2] == b':' && bytes[5] == b':';
+
+    if is_time && !is_date {
+        return Some(Value::String(text.to_owned()));
+    }
+    if !is_date {
+        return None;
+    }
+
+    let mut normalized = text.to_ascii_uppercase().replacen(' ', "T", 1);
+    if normalized.len() == 10 {
+        normalized.push_str("T00:00:00");
+    }
+    let time = normalized.get(11..).unwrap_or_default();
+    let has_offset = time.ends_with('Z') || time.contains(['+', '-']);
+    if !has_offset {
+        normalized.push('Z');
+    }
+    DateTime::parse_from_rfc3339(&normalized)
+        .ok()
+        .map(Value::DateTime)
+}
+
+/// Write the object as a TOML document.
+pub fn to_toml(value: &Value) -> Result<String, Error> {
+    let Value::Object(map) = value else {
+        return Err(Error::Message(format!(
+            "The TOML document must be an object, found {}.",
+            value.type_name()
+        )));
+    };
+    let mut text = String::new();
+    write_table(map, &mut vec![], &mut text)?;
+    Ok(text)
+}
+
+// the key/values first, and then the sub-tables and the arrays of tables.
+fn write_table(map: &Map, path: &mut Vec<String>, text: &mut String) -> Result<(), Error> {
+    let mut tables = vec![];
+    for (key, item) in map.iter() {
+        let item = convert_variant(item);
+        match &item {
+            _ if item.is_null() => {}
+            Value::Object(_) => tables.push((key, item)),
+            Value::List(items) if is_array_of_tables(items) => tables.push((key, item)),
+            _ => {
+                text.push_str(&format!("{} = {}\n", key_text(key), inline_value(&item)?));
+            }
+        }
+    }
+
+    for (key, item) in tables {
+        path.push(key.clone());
+        let header = path
+            .iter()
+            .map(|k| key_text(k))
+            .collect::<Vec<_>>()
+            .join(".");
+        match item {
+            Value::Object(table) => {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(&format!("[{}]\n", header));
+                write_table(&table, path, text)?;
+            }
+            Value::List(items) => {
+                for table in items.iter() {
+                    let Value::Object(table) = convert_variant(table) else {
+                        unreachable!("the items of the array of tables are objects")
+                    };
+                    if !text.is_empty() {
+                        text.push('\n');
+                    }
+                    text.push_str(&format!("[[{}]]\n", header));
+                    write_table(&table, path, text)?;
+                }
+            }
+            _ => unreachable!("only the tables are deferred"),
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn is_array_of_tables(items: &[Value]) -> bool {
+    !items.is_empty()
+        && items
+            .iter()
+            .all(|item| matches!(convert_variant(item), Value::Object(_)))
+}
+
+// `Option::Some(v)` is `v`, and other variants which have values
+// are the objects `{"Type::Member": value}`.
+fn convert_variant(value: &Value) -> Value {
+    let Value::Variant(v) = value else {
+        return value.clone();
+    };
+    if v.type_name == "Option" {
+        return match &v.value {
+            VariantValue::Value(inner) => convert_variant(inner),
+            _ => Value::null(),
+        };
+    }
+
+    let payload = match &v.value {
+        VariantValue::Empty => return Value::String(format!("{}::{}", v.type_name, v.member_name)),
+        VariantValue::Value(inner) => inner.as_ref().clone(),
+        VariantValue::Tuple(items) => Value::List(items.clone()),
+        VariantValue::Object(map) => Value::Object(map.clone()),
+    };
+    let mut map = Map::new();
+    map.insert(format!("{}::{}", v.type_name, v.member_name), payload);
+    Value::Object(map)
+}
+
+fn inline_value(value: &Value) -> Result<String, Error> {
+    let value = convert_variant(value);
+    let text = match &value {
+        _ if value.is_null() => {
+            return Err(Error::Message(
+                "TOML has no null, the \"Option::None\" in arrays can not be written.".to_owned(),
+            ))
+        }
+        Value::Number(n) => match n.as_i128() {
+            Some(i) if i64::try_from(i).is_ok() => i.to_string(),
+            Some(i) => {
+                return Err(Error::Message(format!(
+                    "The integer {} exceeds the range of TOML integers.",
+                    i
+                )))
+            }
+            None => {
+                let f = n.as_f64();
+                if f.is_nan() {
+                    "nan".to_owned()
+                } else if f.is_infinite() {
+                    if f > 0.0 { "inf" } else { "-inf" }.to_owned()
+                } else {
+                    format!("{:?}", f)
+                }
+            }
+        },
+        Value::Boolean(b) => b.to_string(),
+        Value::Char(c) => string_text(&c.to_string()),
+        Value::String(s) => string_text(s),
+        Value::DateTime(d) => d.to_rfc3339(),
+        Value::ByteData(bytes) => format!(
+            "[{}]",
+            bytes
+                .iter()
+                .map(|b| b.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Value::List(items) | Value::Tuple(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(inline_value)
+                .collect::<Result<Vec<_>, Error>>()?
+                .join(", ")
+        ),
+        Value::Object(map) => {
+            let mut entries = vec![];
+            for (key, item) in map.iter() {
+                if !convert_variant(item).is_null() {
+                    entries.push(format!("{} = {}", key_text(key), inline_value(item)?));
+                }
+            }
+            if entries.is_empty() {
+                "{}".to_owned()
+            } else {
+                format!("{{ {} }}", entries.join(", "))
+            }
+        }
+        Value::Variant(_) => unreachable!("the variants are converted"),
+    };
+    Ok(text)
+}
+
+// the bare keys are written without quotes.
+fn key_text(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_owned()
+    } else {
+        string_text(key)
+    }
+}
+
+fn string_text(s: &str) -> String {
+    let mut text = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            _ if c.is_control() => text.push_str(&format!("\\u{:04X}", c as u32)),
+            _ => text.push(c),
+        }
+    }
+    text.push('"');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::Value,
+    };
+
+    use super::{parse_toml, to_toml};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let text = r#"
+# the manifest
+[package]
+name = "aq"   # the name
+authors = [
+    "a",  # first
+    'b\c',
+]
+edition = 2021
+"quoted key" = 0x1F
+big = 9_000_000_000
+ratio = 1.5e3
+off = -inf
+
+[dependencies]
+ason = { path = "../ason", features = ["x"] }
+chrono.version = "0.4"
+
+[[bin]]
+name = "aq"
+test = false
+
+[[bin]]
+name = "aq2"
+
+[a.b]
+c = 1
+
+[a]
+f = 07:32:00
+text = """
+line one \
+  still one
+line "two\""""
+"#;
+        let document = parse_toml(text).unwrap();
+        assert_eq!(
+            document.to_string(),
+            r#"{package:{name:"aq",authors:["a","b\\c"],edition:2021,"quoted key":31,big:9000000000_i64,ratio:1500.0,off:-Inf},dependencies:{ason:{path:"../ason",features:["x"]},chrono:{version:"0.4"}},bin:[{name:"aq",test:false},{name:"aq2"}],a:{b:{c:1},f:"07:32:00",text:"line one still one\nline \"two\""}}"#
+        );
+
+        // the written document reads back
+        let written = to_toml(&document).unwrap();
+        assert_eq!(parse_toml(&written).unwrap(), document, "{}", written);
+
+        assert!(parse_toml("a = 1\na = 2").is_err());
+        assert!(parse_toml("a = 01").is_err());
+        assert!(parse_toml("a = 1 b").is_err());
+    }
+
+    #[test]
+    fn test_to_toml() {
+        assert_eq!(
+            to_toml(&value(
+                r#"{a: 1, t: {x: [1, 2], n: Option::None}, l: [{k: "v"}], e: []}"#
+            ))
+            .unwrap(),
+            "a = 1\ne = []\n\n[t]\nx = [1, 2]\n\n[[l]]\nk = \"v\"\n"
+        );
+        assert!(to_toml(&value("[1]")).is_err());
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The trace of the values flowing through the stages of the top-level
+// pipeline, i.e. `aq --trace`, e.g.
+//
+// ```
+// $ aq --trace '.users[] | select(.age > 30) | .name' users.ason
+// [trace] stage 1 ".users[]": {name:"alice",age:25}
+// [trace] stage 2 "select(.age > 30)": nothing for {name:"alice",age:25}
+// [trace] stage 1 ".users[]": {name:"bob",age:42}
+// [trace] stage 2 "select(.age > 30)": {name:"bob",age:42}
+// [trace] stage 3 ".name": "bob"
+// ```
+//
+// the lines are written to STDERR as soon as the values are produced,
+// so the results and the trace are interleaved as they are evaluated.
+// The long values are truncated, and the pipes inside the
+// parentheses, function bodies, etc., are not traced.
+
+use std::rc::Rc;
+
+use crate::{
+    ast::Expression,
+    error::Error,
+    evaluator::{Environment, Evaluator, Output},
+    value::Value,
+};
+
+// the maximum number of characters of a traced value.
+const MAX_VALUE_WIDTH: usize = 80;
+
+impl Evaluator {
+    /// Evaluate the expression and trace each stage of its pipeline.
+    pub(crate) fn eval_traced<'a>(
+        &self,
+        expression: &'a Expression,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        // the definitions before the pipeline, e.g. `def f: ...; .a | f`
+        if let Expression::FunctionDefinition(definition, rest) = expression {
+            let rest_env = Environment::with_function(env, definition);
+            return self.eval_traced(rest, input, &rest_env, output);
+        }
+
+        let mut stages = vec![];
+        flatten_pipeline(expression, &mut stages);
+        let sources: Vec<String> = stages
+            .iter()
+            .map(|stage| stage.to_string().replace('\n', " "))
+            .collect();
+        self.eval_stages(&stages, &sources, 0, input, env, output)
+    }
+
+    fn eval_stages<'a>(
+        &self,
+        stages: &[&'a Expression],
+        sources: &[String],
+        index: usize,
+        input: Value,
+        env: &Rc<Environment<'a>>,
+        output: &mut Output,
+    ) -> Result<(), Error> {
+        let Some(stage) = stages.get(index) else {
+            return output(input);
+        };
+
+        let received = input.clone();
+        let mut count = 0;
+        self.eval(stage, input, env, &mut |v| {
+            count += 1;
+            eprintln!(
+                "[trace] stage {} \"{}\": {}",
+                index + 1,
+                sources[index],
+                truncate_value(&v)
+            );
+            self.eval_stages(stages, sources, index + 1, v, env, output)
+        })?;
+
+        if count == 0 {
+            eprintln!(
+                "[trace] stage {} \"{}\": nothing for {}",
+                index + 1,
+                sources[index],
+                truncate_value(&received)
+            );
+        }
+        Ok(())
+    }
+}
+
+fn flatten_pipeline<'a>(expression: &'a Expression, stages: &mut Vec<&'a Expression>) {
+    match expression {
+        Expression::Pipe(left, right) => {
+            flatten_pipeline(left, stages);
+            flatten_pipeline(right, stages);
+        }
+        _ => stages.push(expression),
+    }
+}
+
+fn truncate_value(value: &Value) -> String {
+    let text = value.to_string().replace('\n', " ");
+    if text.chars().count() > MAX_VALUE_WIDTH {
+        text.chars().take(MAX_VALUE_WIDTH).collect::<String>() + "..."
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{parser::parse_query_from_str, value::Value};
+
+    use super::{flatten_pipeline, truncate_value};
+
+    #[test]
+    fn test_flatten_pipeline() {
+        let (_, expression) = parse_query_from_str(".a | (.b | .c), 1 | map(. | f) | .d").unwrap();
+        let mut stages = vec![];
+        flatten_pipeline(&expression, &mut stages);
+        let sources: Vec<String> = stages.iter().map(|s| s.to_string()).collect();
+        assert_eq!(sources, [".a", "(.b | .c), 1", "map(. | f)", ".d"]);
+    }
+
+    #[test]
+    fn test_truncate_value() {
+        assert_eq!(truncate_value(&Value::from("a\nb")), "\"a\\nb\"");
+
+        let long = Value::String("x".repeat(100));
+        let text = truncate_value(&long);
+        assert_eq!(text.chars().count(), 83);
+        assert!(text.ends_with("xxx..."));
+    }
+}
@@ -0,0 +1,530 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Rendering the syntax trees back to the query text, e.g. the queries
+// built by the `builder` are printed for the logs, or passed to the
+// `aq` command line, e.g.
+//
+// ```
+// let expression = Path::field("users").each().pipe(Builtin::Keys);
+// assert_eq!(expression.to_string(), ".users[] | keys");
+// ```
+//
+// The parentheses are added only where the precedence requires them, and
+// the text parses back to the same syntax tree, except that the negative
+// numbers are parsed as the negation of the numbers, and the literals
+// which have no syntax in the query (e.g. lists, chars and datetimes)
+// are written as `("ASON text" | fromason)`.
+
+use std::fmt::{self, Display, Write};
+
+use crate::{
+    ast::{
+        AssignOperator, BinaryOperator, Expression, FunctionDefinition, InterpolationPart, Pattern,
+        VariantPayload,
+    },
+    value::{escape_string, is_identifier, Value},
+};
+
+// the precedences, from the lowest to the highest.
+const PIPE: u8 = 0;
+const COMMA: u8 = 1;
+const ALTERNATIVE: u8 = 2;
+const ASSIGN: u8 = 3;
+const OR: u8 = 4;
+const AND: u8 = 5;
+const COMPARISON: u8 = 6;
+const ADDITIVE: u8 = 7;
+const MULTIPLICATIVE: u8 = 8;
+const UNARY: u8 = 9;
+const POSTFIX: u8 = 10;
+
+/// The query text of the syntax tree.
+pub fn print_expression(expression: &Expression) -> String {
+    expression.to_string()
+}
+
+impl Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_expression(f, self, PIPE)
+    }
+}
+
+fn precedence(expression: &Expression) -> u8 {
+    match expression {
+        Expression::Pipe(..) | Expression::Binding { .. } | Expression::FunctionDefinition(..) => {
+            PIPE
+        }
+        Expression::Comma(..) => COMMA,
+        Expression::Alternative(..) => ALTERNATIVE,
+        Expression::Assign(..) => ASSIGN,
+        Expression::Or(..) => OR,
+        Expression::And(..) => AND,
+        Expression::Binary(operator, ..) => binary_precedence(*operator),
+        Expression::Negate(_) | Expression::Try(_, Some(_)) => UNARY,
+        Expression::Literal(value) => literal_precedence(value),
+        _ => POSTFIX,
+    }
+}
+
+fn binary_precedence(operator: BinaryOperator) -> u8 {
+    match operator {
+        BinaryOperator::Add | BinaryOperator::Subtract => ADDITIVE,
+        BinaryOperator::Multiply | BinaryOperator::Divide | BinaryOperator::Remainder => {
+            MULTIPLICATIVE
+        }
+        _ => COMPARISON,
+    }
+}
+
+fn binary_symbol(operator: BinaryOperator) -> &'static str {
+    match operator {
+        BinaryOperator::Add => "+",
+        BinaryOperator::Subtract => "-",
+        BinaryOperator::Multiply => "*",
+        BinaryOperator::Divide => "/",
+        BinaryOperator::Remainder => "%",
+        BinaryOperator::Equal => "==",
+        BinaryOperator::NotEqual => "!=",
+        BinaryOperator::Less => "<",
+        BinaryOperator::LessOrEqual => "<=",
+        BinaryOperator::Greater => ">",
+        BinaryOperator::GreaterOrEqual => ">=",
+    }
+}
+
+fn assign_symbol(operator: AssignOperator) -> &'static str {
+    match operator {
+        AssignOperator::Set => "=",
+        AssignOperator::Update => "|=",
+        AssignOperator::Arithmetic(BinaryOperator::Add) => "+=",
+        AssignOperator::Arithmetic(BinaryOperator::Subtract) => "-=",
+        AssignOperator::Arithmetic(BinaryOperator::Multiply) => "*=",
+        AssignOperator::Arithmetic(BinaryOperator::Divide) => "/=",
+        AssignOperator::Arithmetic(BinaryOperator::Remainder) => "%=",
+        // the comparison operators have no assignment form,
+        // they are never produced by the parser.
+        AssignOperator::Arithmetic(_) => "=",
+        AssignOperator::Alternative => "//=",
+    }
+}
+
+// the numbers, strings, booleans and `null` have the literal syntax.
+fn has_literal_syntax(value: &Value) -> bool {
+    match value {
+        Value::Number(n) => n.as_i128().is_some() || n.as_f64().is_finite(),
+        Value::String(_) | Value::Boolean(_) => true,
+        _ => value.is_null(),
+    }
+}
+
+fn literal_precedence(value: &Value) -> u8 {
+    match value {
+        _ if !has_literal_syntax(value) => PIPE,
+        Value::Number(n) if n.to_string().starts_with('-') => UNARY,
+        _ => POSTFIX,
+    }
+}
+
+// write the expression, it is enclosed in parentheses if its precedence
+// is lower than the precedence required by the context.
+fn write_expression(
+    f: &mut fmt::Formatter<'_>,
+    expression: &Expression,
+    context: u8,
+) -> fmt::Result {
+    if precedence(expression) < context {
+        f.write_char('(')?;
+        write_bare(f, expression)?;
+        f.write_char(')')
+    } else {
+        write_bare(f, expression)
+    }
+}
+
+fn write_bare(f: &mut fmt::Formatter<'_>, expression: &Expression) -> fmt::Result {
+    match expression {
+        Expression::Identity => f.write_char('.'),
+        Expression::RecurseDefault => f.write_str(".."),
+        Expression::Literal(value) => write_literal(f, value),
+        Expression::Interpolation(parts) => write_parts(f, parts),
+        Expression::Format {
+            name,
+            argument,
+            template,
+        } => {
+            write!(f, "@{}", name)?;
+            if let Some(argument) = argument {
+                f.write_char('(')?;
+                write_expression(f, argument, PIPE)?;
+                f.write_char(')')?;
+            }
+            if let Some(template) = template {
+                f.write_char(' ')?;
+                write_parts(f, template)?;
+            }
+            Ok(())
+        }
+        Expression::Index(target, key) => match key.as_ref() {
+            Expression::Literal(Value::String(name)) if is_identifier(name) => {
+                write_target(f, target, false)?;
+                write!(f, ".{}", name)
+            }
+            _ => {
+                write_target(f, target, true)?;
+                f.write_char('[')?;
+                write_expression(f, key, PIPE)?;
+                f.write_char(']')
+            }
+        },
+        Expression::Slice(target, from, to) => {
+            write_target(f, target, true)?;
+            f.write_char('[')?;
+            if let Some(from) = from {
+                write_expression(f, from, PIPE)?;
+            }
+            f.write_char(':')?;
+            if let Some(to) = to {
+                write_expression(f, to, PIPE)?;
+            }
+            f.write_char(']')
+        }
+        Expression::Iterate(target) => {
+            write_target(f, target, true)?;
+            f.write_str("[]")
+        }
+        Expression::Try(body, None) => {
+            write_expression(f, body, POSTFIX)?;
+            f.write_char('?')
+        }
+        Expression::Try(body, Some(handler)) => {
+            f.write_str("try ")?;
+            write_expression(f, body, POSTFIX)?;
+            f.write_str(" catch ")?;
+            write_expression(f, handler, POSTFIX)
+        }
+        Expression::Pipe(left, right) => {
+            write_expression(f, left, COMMA)?;
+            f.write_str(" | ")?;
+            write_expression(f, right, PIPE)
+        }
+        Expression::Comma(left, right) => {
+            write_expression(f, left, COMMA)?;
+            f.write_str(", ")?;
+            write_expression(f, right, ALTERNATIVE)
+        }
+        Expression::Negate(operand) => {
+            f.write_char('-')?;
+            write_expression(f, operand, POSTFIX)
+        }
+        Expression::Binary(operator, left, right) => {
+            let level = binary_precedence(*operator);
+            // the comparisons are non-associative, and the others are
+            // left associative.
+            let left_level = if level == COMPARISON {
+                level + 1
+            } else {
+                level
+            };
+            write_expression(f, left, left_level)?;
+            write!(f, " {} ", binary_symbol(*operator))?;
+            write_expression(f, right, level + 1)
+        }
+        Expression::And(left, right) => {
+            write_expression(f, left, AND)?;
+            f.write_str(" and ")?;
+            write_expression(f, right, COMPARISON)
+        }
+        Expression::Or(left, right) => {
+            write_expression(f, left, OR)?;
+            f.write_str(" or ")?;
+            write_expression(f, right, AND)
+        }
+        Expression::Alternative(left, right) => {
+            write_expression(f, left, ASSIGN)?;
+            f.write_str(" // ")?;
+            write_expression(f, right, ALTERNATIVE)
+        }
+        Expression::Assign(operator, path, value) => {
+            write_expression(f, path, OR)?;
+            write!(f, " {} ", assign_symbol(*operator))?;
+            write_expression(f, value, OR)
+        }
+        Expression::List(None) => f.write_str("[]"),
+        Expression::List(Some(inner)) => {
+            f.write_char('[')?;
+            write_expression(f, inner, PIPE)?;
+            f.write_char(']')
+        }
+        Expression::Object(entries) => write_object(f, entries),
+        Expression::Variant {
+            type_name,
+            member_name,
+            payload,
+        } => {
+            write!(f, "{}::{}", type_name, member_name)?;
+            match payload {
+                VariantPayload::Empty => Ok(()),
+                VariantPayload::Value(value) => {
+                    f.write_char('(')?;
+                    write_expression(f, value, ALTERNATIVE)?;
+                    f.write_char(')')
+                }
+                VariantPayload::Tuple(items) => {
+                    f.write_char('(')?;
+                    for (i, item) in items.iter().enumerate() {
+                        if i > 0 {
+                            f.write_str(", ")?;
+                        }
+                        write_expression(f, item, ALTERNATIVE)?;
+                    }
+                    f.write_char(')')
+                }
+                VariantPayload::Object(object) => match object.as_ref() {
+                    Expression::Object(entries) => write_object(f, entries),
+                    // the payload is always an object when parsed
+                    other => {
+                        f.write_char('(')?;
+                        write_expression(f, other, ALTERNATIVE)?;
+                        f.write_char(')')
+                    }
+                },
+            }
+        }
+        Expression::If(branches, otherwise) => {
+            for (i, (condition, body)) in branches.iter().enumerate() {
+                f.write_str(if i == 0 { "if " } else { " elif " })?;
+                write_expression(f, condition, PIPE)?;
+                f.write_str(" then ")?;
+                write_expression(f, body, PIPE)?;
+            }
+            if let Some(otherwise) = otherwise {
+                f.write_str(" else ")?;
+                write_expression(f, otherwise, PIPE)?;
+            }
+            f.write_str(" end")
+        }
+        Expression::Reduce {
+            source,
+            pattern,
+            init,
+            update,
+        } => {
+            f.write_str("reduce ")?;
+            write_expression(f, source, POSTFIX)?;
+            f.write_str(" as ")?;
+            write_pattern(f, pattern)?;
+            f.write_str(" (")?;
+            write_expression(f, init, PIPE)?;
+            f.write_str("; ")?;
+            write_expression(f, update, PIPE)?;
+            f.write_char(')')
+        }
+        Expression::Foreach {
+            source,
+            pattern,
+            init,
+            update,
+            extract,
+        } => {
+            f.write_str("foreach ")?;
+            write_expression(f, source, POSTFIX)?;
+            f.write_str(" as ")?;
+            write_pattern(f, pattern)?;
+            f.write_str(" (")?;
+            write_expression(f, init, PIPE)?;
+            f.write_str("; ")?;
+            write_expression(f, update, PIPE)?;
+            if let Some(extract) = extract {
+                f.write_str("; ")?;
+                write_expression(f, extract, PIPE)?;
+            }
+            f.write_char(')')
+        }
+        Expression::Binding {
+            source,
+            pattern,
+            body,
+        } => {
+            write_expression(f, source, POSTFIX)?;
+            f.write_str(" as ")?;
+            write_pattern(f, pattern)?;
+            f.write_str(" | ")?;
+            write_expression(f, body, PIPE)
+        }
+        Expression::FunctionDefinition(definition, rest) => {
+            write_definition(f, definition)?;
+            f.write_char(' ')?;
+            write_expression(f, rest, PIPE)
+        }
+        Expression::Call(name, args) => {
+            f.write_str(name)?;
+            if !args.is_empty() {
+                f.write_char('(')?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    write_expression(f, arg, PIPE)?;
+                }
+                f.write_char(')')?;
+            }
+            Ok(())
+        }
+        Expression::Variable(name) => write!(f, "${}", name),
+    }
+}
+
+// the target of the postfix operators, the identity is omitted
+// before the fields, e.g. `.name` rather than `..name`.
+fn write_target(f: &mut fmt::Formatter<'_>, target: &Expression, bracket: bool) -> fmt::Result {
+    match target {
+        Expression::Identity if bracket => f.write_char('.'),
+        Expression::Identity => Ok(()),
+        _ => write_expression(f, target, POSTFIX),
+    }
+}
+
+fn write_literal(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    match value {
+        _ if !has_literal_syntax(value) => {
+            write!(f, "{} | fromason", escape_string(&value.to_string(), '"'))
+        }
+        _ if value.is_null() => f.write_str("null"),
+        _ => write!(f, "{}", value),
+    }
+}
+
+fn write_parts(f: &mut fmt::Formatter<'_>, parts: &[InterpolationPart]) -> fmt::Result {
+    f.write_char('"')?;
+    for part in parts {
+        match part {
+            InterpolationPart::Literal(s) => {
+                let escaped = escape_string(s, '"');
+                f.write_str(&escaped[1..escaped.len() - 1])?;
+            }
+            InterpolationPart::Expression(expression) => {
+                f.write_str("\\(")?;
+                write_expression(f, expression, PIPE)?;
+                f.write_char(')')?;
+            }
+        }
+    }
+    f.write_char('"')
+}
+
+fn write_object(f: &mut fmt::Formatter<'_>, entries: &[(Expression, Expression)]) -> fmt::Result {
+    f.write_char('{')?;
+    for (i, (key, value)) in entries.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        match key {
+            Expression::Literal(Value::String(name)) if is_identifier(name) => f.write_str(name)?,
+            Expression::Literal(Value::String(_)) | Expression::Interpolation(_) => {
+                write_bare(f, key)?
+            }
+            _ => {
+                f.write_char('(')?;
+                write_expression(f, key, PIPE)?;
+                f.write_char(')')?;
+            }
+        }
+        f.write_str(": ")?;
+        // the comma operator is not allowed in the object values
+        write_expression(f, value, ALTERNATIVE)?;
+    }
+    f.write_char('}')
+}
+
+fn write_pattern(f: &mut fmt::Formatter<'_>, pattern: &Pattern) -> fmt::Result {
+    match pattern {
+        Pattern::Variable(name) => write!(f, "${}", name),
+        Pattern::Elements(patterns) => {
+            f.write_char('[')?;
+            for (i, pattern) in patterns.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write_pattern(f, pattern)?;
+            }
+            f.write_char(']')
+        }
+    }
+}
+
+// the value parameters have been expanded by the parser, so all
+// parameters are written as the filter parameters.
+fn write_definition(f: &mut fmt::Formatter<'_>, definition: &FunctionDefinition) -> fmt::Result {
+    write!(f, "def {}", definition.name)?;
+    if !definition.params.is_empty() {
+        write!(f, "({})", definition.params.join("; "))?;
+    }
+    f.write_str(": ")?;
+    write_expression(f, &definition.body, PIPE)?;
+    f.write_char(';')
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        builder::{lit, ops, Builtin, Path},
+        parser::parse_query_from_str,
+        value::Value,
+    };
+
+    #[test]
+    fn test_print_round_trip() {
+        let cases = [
+            ".a.b[0] | keys",
+            ".[\"a b\"][1:][:2][] ?",
+            "1, 2 | 3 // 4 // 5",
+            "(1, 2) // 3",
+            "(. | f), 2",
+            "(1 + 2) * 3 - 4 / (5 % 6)",
+            "1 - (2 - 3)",
+            "(1 == 2) == 3",
+            ".a = 1 | .b |= . + 1 | .c //= 2 | .d *= 3",
+            "not or (a and b) and c",
+            "{a: 1, \"b c\": (1, 2), (.k): .v | f, \"x\\(.n)\": 3}",
+            "[.[] | . as [$a, [$b]] | $a + $b]",
+            "(. as $x | $x), 1",
+            "reduce .[] as $x (0; . + $x) | foreach (.[], 1) as $y (0; . + 1; -.)",
+            "if . then 1 elif 2 then 3 else 4 end.a",
+            "try error(\"x\\n\\\"\") catch . | .a?",
+            "def f(g): [g]; def h: 1; f(h; .) | Option::Some(.a) | P::Q(1, 2) | T::R{a: 1} | E::A",
+            "@base64 \"a\\(.n)b\" | @ason(4) | @text",
+            "-(.a + 1) | -.b | ..",
+            "1.5, 3_u8, 1e300, \"s\\u{1}\"",
+            "$__loc__ | $x.a | $x[0]",
+        ];
+        for case in cases {
+            let (_, expression) = parse_query_from_str(case).unwrap();
+            let text = expression.to_string();
+            let (_, again) = parse_query_from_str(&text).unwrap();
+            assert_eq!(expression, again, "{} => {}", case, text);
+        }
+    }
+
+    #[test]
+    fn test_print_built_expressions() {
+        assert_eq!(
+            Path::field("a b")
+                .key("c")
+                .each()
+                .pipe(Builtin::Length)
+                .to_string(),
+            ".[\"a b\"].c[] | length"
+        );
+
+        // the literals without the query syntax
+        let list = lit(Value::List(vec![Value::from(1)].into()));
+        assert_eq!(list.to_string(), "\"[1]\" | fromason");
+        assert_eq!(ops::sub(lit(1), lit(-1)).to_string(), "1 - -1");
+    }
+}
@@ -0,0 +1,1217 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    fmt::Display,
+    ops::{Deref, DerefMut, Range},
+    sync::{Arc, OnceLock},
+};
+
+use ason::ast::AsonNode;
+use chrono::{DateTime, FixedOffset};
+
+/// The value model the query engine works on.
+///
+/// It mirrors `ason::ast::AsonNode`, but owns its own types so that the
+/// evaluator can compare, order and hash values freely.
+///
+/// The items of lists and tuples (and of the tuple variants) and the
+/// entries of objects are shared between the clones of a value, and
+/// copied on the first modification (i.e. copy-on-write), so cloning
+/// a large document takes O(1), and
+/// updating `.a.b` of it copies only the containers along the path.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(Number),
+    Boolean(bool),
+    Char(char),
+    String(String),
+    DateTime(DateTime<FixedOffset>),
+    Variant(Arc<Variant>),
+    ByteData(Vec<u8>),
+    List(Items),
+    Tuple(Items),
+    Object(Map),
+}
+
+// the values are kept small (the variants are boxed, since they are rare
+// and large), the lists and objects of a large document hold many of them.
+const _: () = assert!(std::mem::size_of::<Value>() <= 32);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Number {
+    I8(i8),
+    U8(u8),
+    I16(i16),
+    U16(u16),
+    I32(i32),
+    U32(u32),
+    I64(i64),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub type_name: String,
+    pub member_name: String,
+    pub value: VariantValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariantValue {
+    Empty,
+    Value(Box<Value>),
+    Tuple(Items),
+    Object(Map),
+}
+
+/// The items of a list or tuple, they are shared between the clones,
+/// and the modifications (through `DerefMut`) copy the items first if
+/// they are still shared.
+#[derive(Clone, Default, PartialEq)]
+pub struct Items(Arc<Vec<Value>>);
+
+impl Items {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the items out, they are copied only if they are shared.
+    pub fn into_vec(self) -> Vec<Value> {
+        Arc::unwrap_or_clone(self.0)
+    }
+}
+
+impl Deref for Items {
+    type Target = Vec<Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Items {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        Arc::make_mut(&mut self.0)
+    }
+}
+
+impl std::fmt::Debug for Items {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<Vec<Value>> for Items {
+    fn from(items: Vec<Value>) -> Self {
+        Self(Arc::new(items))
+    }
+}
+
+impl FromIterator<Value> for Items {
+    fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
+        Self(Arc::new(iter.into_iter().collect()))
+    }
+}
+
+impl IntoIterator for Items {
+    type Item = Value;
+    type IntoIter = SharedIntoIter<Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SharedIntoIter::new(self.0)
+    }
+}
+
+/// The iterator which takes the items (or the entries) out of a shared
+/// vector, the items are moved if the vector is not shared, otherwise
+/// each item is copied when it is produced, so e.g. `first(.[])` copies
+/// only one item of a large list instead of the whole list.
+pub struct SharedIntoIter<T>(SharedItems<T>);
+
+enum SharedItems<T> {
+    Owned(std::vec::IntoIter<T>),
+    Shared(Arc<Vec<T>>, Range<usize>),
+}
+
+impl<T: Clone> SharedIntoIter<T> {
+    fn new(items: Arc<Vec<T>>) -> Self {
+        match Arc::try_unwrap(items) {
+            Ok(items) => Self(SharedItems::Owned(items.into_iter())),
+            Err(items) => {
+                let range = 0..items.len();
+                Self(SharedItems::Shared(items, range))
+            }
+        }
+    }
+}
+
+impl<T: Clone> Iterator for SharedIntoIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match &mut self.0 {
+            SharedItems::Owned(iter) => iter.next(),
+            SharedItems::Shared(items, range) => range.next().map(|i| items[i].clone()),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match &self.0 {
+            SharedItems::Owned(iter) => iter.size_hint(),
+            SharedItems::Shared(_, range) => range.size_hint(),
+        }
+    }
+
+    // skip the items without copying them
+    fn nth(&mut self, n: usize) -> Option<T> {
+        match &mut self.0 {
+            SharedItems::Owned(iter) => iter.nth(n),
+            SharedItems::Shared(items, range) => range.nth(n).map(|i| items[i].clone()),
+        }
+    }
+}
+
+impl<T: Clone> DoubleEndedIterator for SharedIntoIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        match &mut self.0 {
+            SharedItems::Owned(iter) => iter.next_back(),
+            SharedItems::Shared(items, range) => range.next_back().map(|i| items[i].clone()),
+        }
+    }
+
+    fn nth_back(&mut self, n: usize) -> Option<T> {
+        match &mut self.0 {
+            SharedItems::Owned(iter) => iter.nth_back(n),
+            SharedItems::Shared(items, range) => range.nth_back(n).map(|i| items[i].clone()),
+        }
+    }
+}
+
+impl<T: Clone> ExactSizeIterator for SharedIntoIter<T> {}
+
+impl<'a> IntoIterator for &'a Items {
+    type Item = &'a Value;
+    type IntoIter = std::slice::Iter<'a, Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// An object, the entries keep the order they were inserted in.
+///
+/// The small objects are scanned linearly, and the objects with
+/// `INDEX_THRESHOLD` or more entries keep a hash index from the keys to
+/// the positions of the entries, so that:
+///
+/// - `get`, `get_mut`, `contains_key` and `insert` take O(1) (on average)
+///   for the large objects, and O(n) for the small ones.
+/// - `remove` takes O(n), since the following entries are shifted.
+/// - iterating takes O(n), in the insertion order.
+/// - cloning takes O(1), the entries (and the index) are shared
+///   until one of the clones is modified.
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Arc<Vec<(String, Value)>>,
+
+    // the positions of the entries by the keys, it is built when
+    // the object becomes large.
+    index: Option<Arc<HashMap<String, usize>>>,
+}
+
+/// The number of entries from which an object keeps a hash index.
+pub const INDEX_THRESHOLD: usize = 16;
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        match &self.index {
+            Some(index) => index.get(key).copied(),
+            None => self.entries.iter().position(|(k, _)| k == key),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.position(key).map(|pos| &self.entries[pos].1)
+    }
+
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Value> {
+        let pos = self.position(key)?;
+        Some(&mut Arc::make_mut(&mut self.entries)[pos].1)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    /// Insert an entry, an existing entry with the same key
+    /// is replaced in place (i.e. the position is kept).
+    pub fn insert(&mut self, key: String, value: Value) -> Option<Value> {
+        if let Some(v) = self.get_mut(&key) {
+            return Some(std::mem::replace(v, value));
+        }
+
+        let pos = self.entries.len();
+        Arc::make_mut(&mut self.entries).push((key, value));
+
+        match &mut self.index {
+            Some(index) => {
+                Arc::make_mut(index).insert(self.entries[pos].0.clone(), pos);
+            }
+            None if self.entries.len() >= INDEX_THRESHOLD => self.rebuild_index(),
+            None => {}
+        }
+        None
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        let pos = self.position(key)?;
+        let (_, value) = Arc::make_mut(&mut self.entries).remove(pos);
+
+        if let Some(index) = &mut self.index {
+            let index = Arc::make_mut(index);
+            index.remove(key);
+            for p in index.values_mut() {
+                if *p > pos {
+                    *p -= 1;
+                }
+            }
+        }
+        Some(value)
+    }
+
+    fn rebuild_index(&mut self) {
+        let index = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(pos, (k, _))| (k.clone(), pos))
+            .collect();
+        self.index = Some(Arc::new(index));
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, (String, Value)> {
+        self.entries.iter()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    /// Entries sorted by key, used for order-insensitive comparison.
+    fn sorted_entries(&self) -> Vec<&(String, Value)> {
+        let mut entries: Vec<&(String, Value)> = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        entries
+    }
+}
+
+impl FromIterator<(String, Value)> for Map {
+    fn from_iter<T: IntoIterator<Item = (String, Value)>>(iter: T) -> Self {
+        let mut map = Map::new();
+        for (key, value) in iter {
+            map.insert(key, value);
+        }
+        map
+    }
+}
+
+impl IntoIterator for Map {
+    type Item = (String, Value);
+    type IntoIter = SharedIntoIter<(String, Value)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SharedIntoIter::new(self.entries)
+    }
+}
+
+// objects are compared by their entries regardless of the order,
+// e.g. `{a: 1, b: 2} == {b: 2, a: 1}`.
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(k, v)| other.get(k).is_some_and(|o| o == v))
+    }
+}
+
+impl Number {
+    pub fn is_float(&self) -> bool {
+        matches!(self, Number::F32(_) | Number::F64(_))
+    }
+
+    /// Returns the integer value, or `None` if the number is a floating point.
+    pub fn as_i128(&self) -> Option<i128> {
+        let i = match self {
+            Number::I8(v) => *v as i128,
+            Number::U8(v) => *v as i128,
+            Number::I16(v) => *v as i128,
+            Number::U16(v) => *v as i128,
+            Number::I32(v) => *v as i128,
+            Number::U32(v) => *v as i128,
+            Number::I64(v) => *v as i128,
+            Number::U64(v) => *v as i128,
+            Number::F32(_) | Number::F64(_) => return None,
+        };
+        Some(i)
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Number::F32(v) => *v as f64,
+            Number::F64(v) => *v,
+            _ => self.as_i128().unwrap() as f64,
+        }
+    }
+
+    /// Returns the integer value if the number is an integer,
+    /// or a floating point with no fractional part.
+    pub fn as_integer(&self) -> Option<i128> {
+        match self.as_i128() {
+            Some(i) => Some(i),
+            None => {
+                let f = self.as_f64();
+                if f.is_finite() && f.fract() == 0.0 {
+                    Some(f as i128)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// The number without the type suffix, e.g. "17" of `17_u8`,
+    /// the floating points keep the decimal point, e.g. "2.0".
+    pub fn plain_text(&self) -> String {
+        match (self.as_i128(), self) {
+            (Some(i), _) => i.to_string(),
+            // the `f32` is written in its own precision, e.g. "0.1"
+            // rather than "0.10000000149011612".
+            (None, Number::F32(f)) => format!("{:?}", f),
+            (None, _) => format!("{:?}", self.as_f64()),
+        }
+    }
+
+    /// The type name in ASON number literal suffix style, e.g. "i32".
+    pub fn type_suffix(&self) -> &'static str {
+        match self {
+            Number::I8(_) => "i8",
+            Number::U8(_) => "u8",
+            Number::I16(_) => "i16",
+            Number::U16(_) => "u16",
+            Number::I32(_) => "i32",
+            Number::U32(_) => "u32",
+            Number::I64(_) => "i64",
+            Number::U64(_) => "u64",
+            Number::F32(_) => "f32",
+            Number::F64(_) => "f64",
+        }
+    }
+
+    /// Build an integer number that takes the type of `template` if
+    /// the value fits in, otherwise falls back to `i64`, `u64` and `f64`
+    /// in turn.
+    pub fn from_i128_like(value: i128, template: Option<&Number>) -> Number {
+        if let Some(template) = template {
+            let fitted = match template {
+                Number::I8(_) => i8::try_from(value).ok().map(Number::I8),
+                Number::U8(_) => u8::try_from(value).ok().map(Number::U8),
+                Number::I16(_) => i16::try_from(value).ok().map(Number::I16),
+                Number::U16(_) => u16::try_from(value).ok().map(Number::U16),
+                Number::I32(_) => i32::try_from(value).ok().map(Number::I32),
+                Number::U32(_) => u32::try_from(value).ok().map(Number::U32),
+                Number::I64(_) => i64::try_from(value).ok().map(Number::I64),
+                Number::U64(_) => u64::try_from(value).ok().map(Number::U64),
+                Number::F32(_) | Number::F64(_) => None,
+            };
+
+            if let Some(n) = fitted {
+                return n;
+            }
+        }
+
+        if let Ok(v) = i64::try_from(value) {
+            Number::I64(v)
+        } else if let Ok(v) = u64::try_from(value) {
+            Number::U64(v)
+        } else {
+            Number::F64(value as f64)
+        }
+    }
+
+    pub fn compare(&self, other: &Number) -> Ordering {
+        match (self.as_i128(), other.as_i128()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => {
+                // `0.0 == -0.0`, and the `NaN`s are ordered by `total_cmp`
+                let (a, b) = (self.as_f64(), other.as_f64());
+                a.partial_cmp(&b).unwrap_or_else(|| a.total_cmp(&b))
+            }
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare(other) == Ordering::Equal
+    }
+}
+
+impl Value {
+    /// The query language `null`, which is represented by
+    /// the ASON variant `Option::None`.
+    /// The nulls share one variant, so creating them allocates nothing.
+    pub fn null() -> Value {
+        static NULL: OnceLock<Arc<Variant>> = OnceLock::new();
+        let null = NULL.get_or_init(|| {
+            Arc::new(Variant {
+                type_name: "Option".to_owned(),
+                member_name: "None".to_owned(),
+                value: VariantValue::Empty,
+            })
+        });
+        Value::Variant(Arc::clone(null))
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Variant(v) if matches!(v.value, VariantValue::Empty)
+            && v.type_name == "Option" && v.member_name == "None")
+    }
+
+    /// The value of `Option::Some(v)`, `None` for the other values.
+    pub fn as_some(&self) -> Option<&Value> {
+        match self {
+            Value::Variant(v) if v.type_name == "Option" && v.member_name == "Some" => {
+                match &v.value {
+                    VariantValue::Value(inner) => Some(inner),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Only `false` and `null` are falsy, all other values are truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Boolean(false)) && !self.is_null()
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Boolean(_) => "boolean",
+            Value::Char(_) => "char",
+            Value::String(_) => "string",
+            Value::DateTime(_) => "datetime",
+            Value::Variant(_) if self.is_null() => "null",
+            Value::Variant(_) => "variant",
+            Value::ByteData(_) => "bytedata",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Object(_) => "object",
+        }
+    }
+
+    /// The text used when a value is embedded into a string,
+    /// strings and chars are inserted as they are, numbers are
+    /// written without the type suffix, e.g. "9.5" of `9.5_f32`,
+    /// other values are formatted as compact ASON text.
+    pub fn to_text(&self) -> String {
+        match self {
+            Value::String(s) => s.clone(),
+            Value::Char(c) => c.to_string(),
+            Value::Number(n) => n.plain_text(),
+            _ => self.to_string(),
+        }
+    }
+
+    fn type_order(&self) -> u8 {
+        match self {
+            Value::Variant(_) if self.is_null() => 0,
+            Value::Boolean(_) => 1,
+            Value::Number(_) => 2,
+            Value::Char(_) => 3,
+            Value::String(_) => 4,
+            Value::DateTime(_) => 5,
+            Value::ByteData(_) => 6,
+            Value::List(_) => 7,
+            Value::Tuple(_) => 8,
+            Value::Object(_) => 9,
+            Value::Variant(_) => 10,
+        }
+    }
+
+    /// The total order used by `sort`, `min`, `max` and the comparison operators:
+    ///
+    /// null < boolean < number < char < string < datetime < bytedata < list < tuple < object < variant
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.compare(b),
+            (Value::Boolean(a), Value::Boolean(b)) => a.cmp(b),
+            (Value::Char(a), Value::Char(b)) => a.cmp(b),
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::DateTime(a), Value::DateTime(b)) => a.cmp(b),
+            (Value::ByteData(a), Value::ByteData(b)) => a.cmp(b),
+            (Value::List(a), Value::List(b)) | (Value::Tuple(a), Value::Tuple(b)) => {
+                compare_slices(a, b)
+            }
+            (Value::Object(a), Value::Object(b)) => compare_maps(a, b),
+            (Value::Variant(a), Value::Variant(b)) => a
+                .type_name
+                .cmp(&b.type_name)
+                .then_with(|| a.member_name.cmp(&b.member_name))
+                .then_with(|| compare_variant_values(&a.value, &b.value)),
+            _ => self.type_order().cmp(&other.type_order()),
+        }
+    }
+
+    /// Sort the entries of the objects by the keys recursively,
+    /// e.g. for `--sort-keys`.
+    pub fn sort_keys(self) -> Value {
+        let sort_map = |map: Map| {
+            let mut entries: Vec<(String, Value)> =
+                map.into_iter().map(|(k, v)| (k, v.sort_keys())).collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            entries.into_iter().collect::<Map>()
+        };
+
+        match self {
+            Value::List(items) => Value::List(items.into_iter().map(Value::sort_keys).collect()),
+            Value::Tuple(items) => Value::Tuple(items.into_iter().map(Value::sort_keys).collect()),
+            Value::Object(map) => Value::Object(sort_map(map)),
+            Value::Variant(variant) => {
+                let Variant {
+                    type_name,
+                    member_name,
+                    value,
+                } = Arc::unwrap_or_clone(variant);
+                let value = match value {
+                    VariantValue::Empty => VariantValue::Empty,
+                    VariantValue::Value(v) => VariantValue::Value(Box::new(v.sort_keys())),
+                    VariantValue::Tuple(items) => {
+                        VariantValue::Tuple(items.into_iter().map(Value::sort_keys).collect())
+                    }
+                    VariantValue::Object(map) => VariantValue::Object(sort_map(map)),
+                };
+                Value::Variant(Arc::new(Variant {
+                    type_name,
+                    member_name,
+                    value,
+                }))
+            }
+            other => other,
+        }
+    }
+}
+
+fn compare_slices(a: &[Value], b: &[Value]) -> Ordering {
+    for (x, y) in a.iter().zip(b.iter()) {
+        let ordering = x.compare(y);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+fn compare_maps(a: &Map, b: &Map) -> Ordering {
+    let left = a.sorted_entries();
+    let right = b.sorted_entries();
+
+    // compare the keys first, and then the values
+    let keys_ordering = left
+        .iter()
+        .map(|(k, _)| k)
+        .cmp(right.iter().map(|(k, _)| k));
+    if keys_ordering != Ordering::Equal {
+        return keys_ordering;
+    }
+
+    for ((_, x), (_, y)) in left.iter().zip(right.iter()) {
+        let ordering = x.compare(y);
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+fn compare_variant_values(a: &VariantValue, b: &VariantValue) -> Ordering {
+    let order = |v: &VariantValue| match v {
+        VariantValue::Empty => 0,
+        VariantValue::Value(_) => 1,
+        VariantValue::Tuple(_) => 2,
+        VariantValue::Object(_) => 3,
+    };
+
+    match (a, b) {
+        (VariantValue::Empty, VariantValue::Empty) => Ordering::Equal,
+        (VariantValue::Value(x), VariantValue::Value(y)) => x.compare(y),
+        (VariantValue::Tuple(x), VariantValue::Tuple(y)) => compare_slices(x, y),
+        (VariantValue::Object(x), VariantValue::Object(y)) => compare_maps(x, y),
+        _ => order(a).cmp(&order(b)),
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.compare(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Value {}
+
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Value {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.compare(other)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Boolean(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_owned())
+    }
+}
+
+impl From<Number> for Value {
+    fn from(value: Number) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Number(Number::I32(value))
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Number(Number::I64(value))
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(Number::F64(value))
+    }
+}
+
+// the default integer type of ASON is `i32`, larger indices
+// and lengths fall back to `i64`.
+impl From<usize> for Value {
+    fn from(value: usize) -> Self {
+        Value::Number(Number::from_i128_like(value as i128, Some(&Number::I32(0))))
+    }
+}
+
+impl From<AsonNode> for Value {
+    fn from(node: AsonNode) -> Self {
+        match node {
+            AsonNode::Number(n) => Value::Number(n.into()),
+            AsonNode::Boolean(b) => Value::Boolean(b),
+            AsonNode::Char(c) => Value::Char(c),
+            AsonNode::String(s) => Value::String(s),
+            AsonNode::DateTime(d) => Value::DateTime(d),
+            AsonNode::Variant(v) => Value::Variant(Arc::new(Variant {
+                type_name: v.type_name,
+                member_name: v.member_name,
+                value: match v.value {
+                    ason::ast::VariantValue::Empty => VariantValue::Empty,
+                    ason::ast::VariantValue::Value(v) => VariantValue::Value(Box::new((*v).into())),
+                    ason::ast::VariantValue::Tuple(items) => {
+                        VariantValue::Tuple(items.into_iter().map(Value::from).collect())
+                    }
+                    ason::ast::VariantValue::Object(pairs) => {
+                        VariantValue::Object(map_from_pairs(pairs))
+                    }
+                },
+            })),
+            AsonNode::ByteData(b) => Value::ByteData(b),
+            AsonNode::List(items) => Value::List(items.into_iter().map(Value::from).collect()),
+            AsonNode::Tuple(items) => Value::Tuple(items.into_iter().map(Value::from).collect()),
+            AsonNode::Object(pairs) => Value::Object(map_from_pairs(pairs)),
+        }
+    }
+}
+
+fn map_from_pairs(pairs: Vec<ason::ast::KeyValuePair>) -> Map {
+    pairs
+        .into_iter()
+        .map(|pair| (pair.key, Value::from(*pair.value)))
+        .collect()
+}
+
+fn map_to_pairs(map: Map) -> Vec<ason::ast::KeyValuePair> {
+    map.into_iter()
+        .map(|(key, value)| ason::ast::KeyValuePair {
+            key,
+            value: Box::new(value.into()),
+        })
+        .collect()
+}
+
+impl From<Value> for AsonNode {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Number(n) => AsonNode::Number(n.into()),
+            Value::Boolean(b) => AsonNode::Boolean(b),
+            Value::Char(c) => AsonNode::Char(c),
+            Value::String(s) => AsonNode::String(s),
+            Value::DateTime(d) => AsonNode::DateTime(d),
+            Value::Variant(v) => {
+                let v = Arc::unwrap_or_clone(v);
+                AsonNode::Variant(ason::ast::Variant {
+                    type_name: v.type_name,
+                    member_name: v.member_name,
+                    value: match v.value {
+                        VariantValue::Empty => ason::ast::VariantValue::Empty,
+                        VariantValue::Value(v) => {
+                            ason::ast::VariantValue::Value(Box::new((*v).into()))
+                        }
+                        VariantValue::Tuple(items) => ason::ast::VariantValue::Tuple(
+                            items.into_iter().map(AsonNode::from).collect(),
+                        ),
+                        VariantValue::Object(map) => {
+                            ason::ast::VariantValue::Object(map_to_pairs(map))
+                        }
+                    },
+                })
+            }
+            Value::ByteData(b) => AsonNode::ByteData(b),
+            Value::List(items) => AsonNode::List(items.into_iter().map(AsonNode::from).collect()),
+            Value::Tuple(items) => AsonNode::Tuple(items.into_iter().map(AsonNode::from).collect()),
+            Value::Object(map) => AsonNode::Object(map_to_pairs(map)),
+        }
+    }
+}
+
+impl From<ason::ast::Number> for Number {
+    fn from(n: ason::ast::Number) -> Self {
+        match n {
+            ason::ast::Number::I8(v) => Number::I8(v),
+            ason::ast::Number::U8(v) => Number::U8(v),
+            ason::ast::Number::I16(v) => Number::I16(v),
+            ason::ast::Number::U16(v) => Number::U16(v),
+            ason::ast::Number::I32(v) => Number::I32(v),
+            ason::ast::Number::U32(v) => Number::U32(v),
+            ason::ast::Number::I64(v) => Number::I64(v),
+            ason::ast::Number::U64(v) => Number::U64(v),
+            ason::ast::Number::F32(v) => Number::F32(v),
+            ason::ast::Number::F64(v) => Number::F64(v),
+        }
+    }
+}
+
+impl From<Number> for ason::ast::Number {
+    fn from(n: Number) -> Self {
+        match n {
+            Number::I8(v) => ason::ast::Number::I8(v),
+            Number::U8(v) => ason::ast::Number::U8(v),
+            Number::I16(v) => ason::ast::Number::I16(v),
+            Number::U16(v) => ason::ast::Number::U16(v),
+            Number::I32(v) => ason::ast::Number::I32(v),
+            Number::U32(v) => ason::ast::Number::U32(v),
+            Number::I64(v) => ason::ast::Number::I64(v),
+            Number::U64(v) => ason::ast::Number::U64(v),
+            Number::F32(v) => ason::ast::Number::F32(v),
+            Number::F64(v) => ason::ast::Number::F64(v),
+        }
+    }
+}
+
+impl Display for Number {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            // `i32` and `f64` are the default types of ASON number literals,
+            // so the type suffix is omitted.
+            Number::I32(v) => write!(f, "{}", v),
+            Number::F64(v) => write_float(f, *v, ""),
+            Number::F32(v) => write_float(f, *v as f64, "_f32"),
+            _ => write!(f, "{}_{}", self.as_i128().unwrap(), self.type_suffix()),
+        }
+    }
+}
+
+fn write_float(f: &mut std::fmt::Formatter<'_>, v: f64, suffix: &str) -> std::fmt::Result {
+    if v.is_nan() {
+        write!(f, "NaN{}", suffix)
+    } else if v.is_infinite() {
+        write!(f, "{}Inf{}", if v < 0.0 { "-" } else { "" }, suffix)
+    } else {
+        // the `Debug` format always keeps the decimal point (e.g. "1.0"),
+        // and switches to the exponent notation for very large or small values.
+        write!(f, "{:?}{}", v, suffix)
+    }
+}
+
+/// Whether the key can be written without quotes.
+pub fn is_identifier(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+/// The inverse of encoding a variant as a single entry map in the formats
+/// which lack variants (e.g. MessagePack and CBOR), i.e. `{"Color::Red": nil}`
+/// is `Color::Red`, the arrays are the tuples, and the maps are the objects.
+/// Other maps are kept as the objects.
+pub fn decode_variant_entry(map: Map) -> Value {
+    let is_variant = map.len() == 1
+        && map.keys().all(|key| {
+            key.split_once("::")
+                .is_some_and(|(t, m)| is_identifier(t) && is_identifier(m))
+        });
+    if !is_variant {
+        return Value::Object(map);
+    }
+
+    let (key, item) = map.into_iter().next().unwrap();
+    let (type_name, member_name) = key.split_once("::").unwrap();
+    let value = match item {
+        _ if item.is_null() => VariantValue::Empty,
+        Value::List(items) => VariantValue::Tuple(items),
+        Value::Object(map) => VariantValue::Object(map),
+        other => VariantValue::Value(Box::new(other)),
+    };
+    Value::Variant(Arc::new(Variant {
+        type_name: type_name.to_owned(),
+        member_name: member_name.to_owned(),
+        value,
+    }))
+}
+
+pub fn escape_string(s: &str, quote: char) -> String {
+    let mut buf = String::with_capacity(s.len() + 2);
+    buf.push(quote);
+    for c in s.chars() {
+        match c {
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            '\0' => buf.push_str("\\0"),
+            _ if c == quote => {
+                buf.push('\\');
+                buf.push(c);
+            }
+            _ if c.is_control() => buf.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            _ => buf.push(c),
+        }
+    }
+    buf.push(quote);
+    buf
+}
+
+/// Replace the non-ASCII characters of the ASON text with the escapes,
+/// e.g. `"café"` to `"caf\u{e9}"`. They only appear in the literals of
+/// the strings, chars and quoted keys since the identifiers are ASCII.
+pub fn escape_non_ascii(text: &str) -> String {
+    let mut buf = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii() {
+            buf.push(c);
+        } else {
+            buf.push_str(&format!("\\u{{{:x}}}", c as u32));
+        }
+    }
+    buf
+}
+
+fn write_key(f: &mut std::fmt::Formatter<'_>, key: &str) -> std::fmt::Result {
+    if is_identifier(key) {
+        write!(f, "{}", key)
+    } else {
+        write!(f, "{}", escape_string(key, '"'))
+    }
+}
+
+fn write_items(f: &mut std::fmt::Formatter<'_>, items: &[Value]) -> std::fmt::Result {
+    for (idx, item) in items.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ",")?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
+
+fn write_map(f: &mut std::fmt::Formatter<'_>, map: &Map) -> std::fmt::Result {
+    write!(f, "{{")?;
+    for (idx, (key, value)) in map.iter().enumerate() {
+        if idx > 0 {
+            write!(f, ",")?;
+        }
+        write_key(f, key)?;
+        write!(f, ":{}", value)?;
+    }
+    write!(f, "}}")
+}
+
+// Display a value as compact (single line) ASON text.
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Char(c) => write!(f, "{}", escape_string(&c.to_string(), '\'')),
+            Value::String(s) => write!(f, "{}", escape_string(s, '"')),
+            Value::DateTime(d) => write!(f, "d\"{}\"", d.to_rfc3339()),
+            Value::Variant(v) => {
+                write!(f, "{}::{}", v.type_name, v.member_name)?;
+                match &v.value {
+                    VariantValue::Empty => Ok(()),
+                    VariantValue::Value(v) => write!(f, "({})", v),
+                    VariantValue::Tuple(items) => {
+                        write!(f, "(")?;
+                        write_items(f, items)?;
+                        write!(f, ")")
+                    }
+                    VariantValue::Object(map) => write_map(f, map),
+                }
+            }
+            Value::ByteData(bytes) => {
+                let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                write!(f, "h\"{}\"", hex.join(" "))
+            }
+            Value::List(items) => {
+                write!(f, "[")?;
+                write_items(f, items)?;
+                write!(f, "]")
+            }
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                write_items(f, items)?;
+                write!(f, ")")
+            }
+            Value::Object(map) => write_map(f, map),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Ordering, sync::Arc};
+
+    use pretty_assertions::assert_eq;
+
+    use crate::input::{parse_input, ParseOptions};
+
+    use super::{escape_non_ascii, Items, Map, Value, Variant, VariantValue, INDEX_THRESHOLD};
+
+    #[test]
+    fn test_to_text() {
+        let value = |text: &str| parse_input(text, None, &ParseOptions::unlimited()).unwrap();
+        assert_eq!(value("17_u8").to_text(), "17");
+        assert_eq!(value("-3_i64").to_text(), "-3");
+        assert_eq!(value("0.1_f32").to_text(), "0.1");
+        assert_eq!(value("2.0").to_text(), "2.0");
+        assert_eq!(value("'x'").to_text(), "x");
+        assert_eq!(value("\"a b\"").to_text(), "a b");
+        assert_eq!(value("[1_u8, 'x']").to_text(), "[1_u8,'x']");
+    }
+
+    #[test]
+    fn test_shared_into_iter() {
+        let items: Items = (0..5).map(Value::from).collect();
+
+        // the items are still shared with `items`
+        let mut iter = items.clone().into_iter();
+        assert_eq!(iter.len(), 5);
+        assert_eq!(iter.nth(3), Some(Value::from(3)));
+        assert_eq!(iter.len(), 1);
+        assert_eq!(iter.nth_back(0), Some(Value::from(4)));
+        assert_eq!(iter.nth(1), None);
+
+        // the items are moved
+        let mut iter = items.into_iter();
+        assert_eq!(iter.nth(1), Some(Value::from(1)));
+        assert_eq!(iter.next_back(), Some(Value::from(4)));
+        assert_eq!(
+            iter.collect::<Vec<_>>(),
+            vec![Value::from(2), Value::from(3)]
+        );
+    }
+
+    #[test]
+    fn test_compare_signed_zero() {
+        let zero = Value::from(0.0);
+        let negative_zero = Value::from(-0.0);
+        assert_eq!(zero, negative_zero);
+        assert_eq!(zero.compare(&negative_zero), Ordering::Equal);
+        assert_eq!(Value::from(-0.0).compare(&Value::from(0)), Ordering::Equal);
+        assert_eq!(Value::from(-0.5).compare(&zero), Ordering::Less);
+
+        // the `NaN` is still ordered (after the other numbers)
+        let nan = Value::from(f64::NAN);
+        assert_eq!(nan.compare(&Value::from(f64::INFINITY)), Ordering::Greater);
+        assert_eq!(nan.compare(&nan), Ordering::Equal);
+    }
+
+    #[test]
+    fn test_indexed_map() {
+        let mut map: Map = (0..1000)
+            .map(|i| (format!("k{}", i), Value::from(i)))
+            .collect();
+        assert_eq!(map.get("k999"), Some(&Value::from(999)));
+
+        // the order of the keys is kept by the updates
+        assert_eq!(
+            map.insert("k5".to_owned(), Value::from(-1)),
+            Some(Value::from(5))
+        );
+        assert_eq!(map.keys().nth(5).unwrap(), "k5");
+        assert_eq!(map.remove("k10"), Some(Value::from(10)));
+        assert!(!map.contains_key("k10"));
+        assert_eq!(map.get("k11"), Some(&Value::from(11)));
+        assert_eq!(map.keys().nth(10).unwrap(), "k11");
+
+        map.insert("new".to_owned(), Value::from(1));
+        assert_eq!(map.len(), 1000);
+        assert_eq!(map.get("new"), Some(&Value::from(1)));
+
+        // the small maps have no index
+        let mut map: Map = (0..INDEX_THRESHOLD - 1)
+            .map(|i| (format!("k{}", i), Value::from(i)))
+            .collect();
+        map.insert("last".to_owned(), Value::from(0));
+        assert_eq!(map.get("last"), Some(&Value::from(0)));
+        assert_eq!(map.remove("k0"), Some(Value::from(0)));
+        assert_eq!(map.get("last"), Some(&Value::from(0)));
+    }
+
+    #[test]
+    fn test_copy_on_write() {
+        let items: Items = (1..3).map(Value::from).collect();
+        let original = Value::List(items);
+        let mut copy = original.clone();
+        if let Value::List(items) = &mut copy {
+            items.push(Value::from(3));
+        }
+        assert_eq!(original.to_string(), "[1,2]");
+        assert_eq!(copy.to_string(), "[1,2,3]");
+
+        let mut map = Map::new();
+        map.insert("a".to_owned(), Value::from(1));
+        let original = Value::Object(map);
+        let mut copy = original.clone();
+        if let Value::Object(map) = &mut copy {
+            map.insert("b".to_owned(), Value::from(2));
+        }
+        assert_eq!(original.to_string(), "{a:1}");
+        assert_eq!(copy.to_string(), "{a:1,b:2}");
+
+        // the items of the tuple variants are shared as well
+        let variant = |value: &Value| match value {
+            Value::Variant(v) => match &v.value {
+                VariantValue::Tuple(items) => items.as_ptr(),
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        };
+        let original = Value::Variant(Arc::new(Variant {
+            type_name: "Point".to_owned(),
+            member_name: "P".to_owned(),
+            value: VariantValue::Tuple((1..3).map(Value::from).collect()),
+        }));
+        let mut copy = original.clone();
+        assert_eq!(variant(&copy), variant(&original));
+        if let Value::Variant(v) = &mut copy {
+            if let VariantValue::Tuple(items) = &mut Arc::make_mut(v).value {
+                items[0] = Value::from(0);
+            }
+        }
+        assert_ne!(variant(&copy), variant(&original));
+        assert_eq!(original.to_string(), "Point::P(1,2)");
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let value = parse_input(
+            "[{b: 1, a: {d: 2, c: 3}}, Shape::Rect{y: 1, x: 2}, Opt::V({z: 1, y: 2}), ([{b: 1, a: 2}],)]",
+            None,
+            &ParseOptions::unlimited(),
+        )
+        .unwrap();
+        assert_eq!(
+            value.sort_keys().to_string(),
+            "[{a:{c:3,d:2},b:1},Shape::Rect{x:2,y:1},Opt::V({y:2,z:1}),([{a:2,b:1}])]"
+        );
+    }
+
+    #[test]
+    fn test_escape_non_ascii() {
+        assert_eq!(
+            escape_non_ascii(r#"{"clé":"café 😀",k:['ü']}"#),
+            r#"{"cl\u{e9}":"caf\u{e9} \u{1f600}",k:['\u{fc}']}"#
+        );
+    }
+
+    #[test]
+    fn test_option_values() {
+        let value = |text: &str| parse_input(text, None, &ParseOptions::unlimited()).unwrap();
+
+        assert!(Value::null().is_null());
+        assert!(value("Option::None").is_null());
+
+        // the nulls share one variant
+        match (Value::null(), Value::null()) {
+            (Value::Variant(a), Value::Variant(b)) => assert!(Arc::ptr_eq(&a, &b)),
+            _ => unreachable!(),
+        }
+        assert!(!value("Other::None").is_null());
+        assert_eq!(Value::null().as_some(), None);
+
+        let some = value("Option::Some([1, 2])");
+        assert!(!some.is_null());
+        assert_eq!(some.as_some(), Some(&value("[1, 2]")));
+        assert_eq!(value("Other::Some(1)").as_some(), None);
+        assert_eq!(Value::from(1).as_some(), None);
+    }
+}
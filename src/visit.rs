@@ -0,0 +1,140 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Traversing values with a visitor.
+//
+// The values are visited in depth-first order, the children of lists,
+// tuples and objects are visited between the `enter` and `leave` of
+// their parent. The paths are the same as the `path(f)` builtin, i.e.
+// the keys of objects and the indices of lists and tuples.
+//
+// e.g. collecting the paths of all strings:
+//
+// ```
+// struct Strings(Vec<Vec<Value>>);
+//
+// impl Visitor for Strings {
+//     fn enter(&mut self, path: &[Value], value: &Value) -> Visit {
+//         if let Value::String(_) = value {
+//             self.0.push(path.to_vec());
+//         }
+//         Visit::Continue
+//     }
+// }
+// ```
+
+use crate::value::Value;
+
+/// What to do after entering a value.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Visit {
+    Continue,
+
+    /// Do not visit the children of the value, the `leave` is still called.
+    SkipChildren,
+
+    /// Stop the whole traversal, the `leave` is not called.
+    Stop,
+}
+
+pub trait Visitor {
+    /// Called before the children of the value are visited.
+    fn enter(&mut self, _path: &[Value], _value: &Value) -> Visit {
+        Visit::Continue
+    }
+
+    /// Called after the children of the value are visited.
+    fn leave(&mut self, _path: &[Value], _value: &Value) {}
+}
+
+/// Visit the value and all its descendants,
+/// returns `false` if the traversal is stopped by the visitor.
+pub fn walk(value: &Value, visitor: &mut impl Visitor) -> bool {
+    let mut path = vec![];
+    walk_value(value, &mut path, visitor)
+}
+
+fn walk_value(value: &Value, path: &mut Vec<Value>, visitor: &mut impl Visitor) -> bool {
+    match visitor.enter(path, value) {
+        Visit::Stop => return false,
+        Visit::SkipChildren => {}
+        Visit::Continue => match value {
+            Value::List(items) | Value::Tuple(items) => {
+                for (idx, item) in items.iter().enumerate() {
+                    path.push(Value::from(idx));
+                    let completed = walk_value(item, path, visitor);
+                    path.pop();
+                    if !completed {
+                        return false;
+                    }
+                }
+            }
+            Value::Object(map) => {
+                for (key, item) in map.iter() {
+                    path.push(Value::String(key.clone()));
+                    let completed = walk_value(item, path, visitor);
+                    path.pop();
+                    if !completed {
+                        return false;
+                    }
+                }
+            }
+            _ => {}
+        },
+    }
+
+    visitor.leave(path, value);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        path::path_to_string,
+        value::Value,
+    };
+
+    use super::{walk, Visit, Visitor};
+
+    // records the paths of entering (`+`) and leaving (`-`) the values,
+    // the object with the key `skip` is not descended, and the string
+    // "stop" stops the walk.
+    struct Trace(Vec<String>);
+
+    impl Visitor for Trace {
+        fn enter(&mut self, path: &[Value], value: &Value) -> Visit {
+            self.0.push(format!("+{}", path_to_string(path)));
+            match value {
+                Value::Object(map) if map.contains_key("skip") => Visit::SkipChildren,
+                Value::String(s) if s == "stop" => Visit::Stop,
+                _ => Visit::Continue,
+            }
+        }
+
+        fn leave(&mut self, path: &[Value], _value: &Value) {
+            self.0.push(format!("-{}", path_to_string(path)));
+        }
+    }
+
+    fn trace(text: &str) -> (bool, String) {
+        let value = parse_input(text, None, &ParseOptions::unlimited()).unwrap();
+        let mut trace = Trace(vec![]);
+        let completed = walk(&value, &mut trace);
+        (completed, trace.0.join(" "))
+    }
+
+    #[test]
+    fn test_walk() {
+        assert_eq!(
+            trace("{a: [1], b: {skip: 1}}"),
+            (true, "+. +.a +.a[0] -.a[0] -.a +.b -.b -.".to_owned())
+        );
+        assert_eq!(trace(r#"["stop", 2]"#), (false, "+. +.[0]".to_owned()));
+    }
+}
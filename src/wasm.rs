@@ -0,0 +1,126 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The JavaScript bindings (by `wasm-bindgen`), e.g. for an ASON playground
+// in the browser, or for the scripts of Node.js:
+//
+// ```js
+// import { compile, run } from "ason-query";
+//
+// const query = compile(".users[] | .name");
+// query.run('{users: [{name: "foo"}, {name: "bar"}]}');  // ['"foo"', '"bar"']
+// run(".a + 1", '{"a": 1}', "json");                       // ['2']
+// ```
+//
+// The documents and the results are text, in ASON by default, or in
+// one of the other text formats, e.g. "json", "yaml" and "toml".
+//
+// Nothing is read from the filesystem or the process, i.e. the queries
+// cannot import the modules, `$ENV` is an empty object, and the
+// `Limits::timeout` is not available since there is no clock.
+
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    convert::{parse_documents, write_text, Format},
+    input::ParseOptions,
+    query::Query,
+};
+
+/// A compiled query, which can run on many documents.
+#[wasm_bindgen(js_name = Query)]
+pub struct CompiledQuery {
+    query: Query,
+}
+
+#[wasm_bindgen(js_class = Query)]
+impl CompiledQuery {
+    /// Run the query on the document, `from` is the format of the document
+    /// ("ason" by default), and `to` is the format of the results (the
+    /// same as the document by default).
+    pub fn run(
+        &self,
+        document: &str,
+        from: Option<String>,
+        to: Option<String>,
+    ) -> Result<Vec<String>, JsError> {
+        let from = match from {
+            Some(name) => Format::from_name(&name)?,
+            None => Format::Ason,
+        };
+        let to = match to {
+            Some(name) => Format::from_name(&name)?,
+            None => from.result_format(),
+        };
+
+        let mut results = vec![];
+        for document in parse_documents(document, from, &ParseOptions::unlimited())? {
+            for value in self.query.run_value(document)? {
+                results.push(write_text(&value, to)?);
+            }
+        }
+        Ok(results)
+    }
+}
+
+/// Compile the query, the syntax errors are thrown.
+#[wasm_bindgen]
+pub fn compile(source: &str) -> Result<CompiledQuery, JsError> {
+    Ok(CompiledQuery {
+        query: Query::compile(source)?,
+    })
+}
+
+/// Compile the query and run it on the document, see `Query.run()`.
+#[wasm_bindgen]
+pub fn run(
+    source: &str,
+    document: &str,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<String>, JsError> {
+    compile(source)?.run(document, from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{compile, run};
+
+    // the errors are not tested, since `JsError` is only available
+    // on the `wasm32` target.
+    #[test]
+    fn test_run() {
+        let query = compile(".users[] | .name").unwrap();
+        assert_eq!(
+            query
+                .run("{users: [{name: \"foo\"}, {name: \"bar\"}]}", None, None)
+                .unwrap(),
+            ["\"foo\"", "\"bar\""]
+        );
+        assert_eq!(
+            query
+                .run(
+                    "users:\n  - name: foo\n",
+                    Some("yaml".to_owned()),
+                    Some("ason".to_owned())
+                )
+                .unwrap(),
+            ["\"foo\""]
+        );
+
+        // the results are in the format of the document by default
+        assert_eq!(
+            run(".a + 1", "{\"a\": 1}", Some("json".to_owned()), None).unwrap(),
+            ["2"]
+        );
+        assert_eq!(
+            run("{b: .a}", "{\"a\": 1}", Some("json".to_owned()), None).unwrap(),
+            ["{\n    \"b\": 1\n}"]
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Watching the input files, i.e. `aq --watch QUERY FILE`.
+//
+// The directories of the files are watched instead of the files
+// themselves, since many editors save a file by writing a new file
+// and renaming it, which ends the watching of the old file.
+
+use std::{
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use notify::{recommended_watcher, Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::error::Error;
+
+// the changes within this interval are treated as one change,
+// e.g. an editor may truncate a file and then write it.
+const DEBOUNCE_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct FileWatcher {
+    // the watching is stopped when the watcher is dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    files: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    pub fn new(files: &[String]) -> Result<Self, Error> {
+        let (sender, events) = channel();
+        let mut watcher = recommended_watcher(sender).map_err(watch_error)?;
+
+        let mut paths = vec![];
+        for file in files {
+            let path = std::fs::canonicalize(file).map_err(|e| {
+                Error::Message(format!("Fail to watch the file \"{}\": {}", file, e))
+            })?;
+            let dir = path.parent().map(PathBuf::from).unwrap_or_default();
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+            paths.push(path);
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            events,
+            files: paths,
+        })
+    }
+
+    /// Wait until any of the files is changed (or created, or removed).
+    pub fn wait(&self) -> Result<(), Error> {
+        loop {
+            let event = self.events.recv().map_err(|_| {
+                Error::Message("The watching of the input files is stopped.".to_owned())
+            })?;
+            if self.is_change(event.map_err(watch_error)?) {
+                break;
+            }
+        }
+
+        // skip the rest of the events of the change.
+        loop {
+            match self.events.recv_timeout(DEBOUNCE_INTERVAL) {
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::Message(
+                        "The watching of the input files is stopped.".to_owned(),
+                    ))
+                }
+            }
+        }
+    }
+
+    // the other files in the watching directories are ignored.
+    fn is_change(&self, event: Event) -> bool {
+        !event.kind.is_access() && event.paths.iter().any(|p| self.files.contains(p))
+    }
+}
+
+fn watch_error(error: notify::Error) -> Error {
+    Error::Message(format!("Fail to watch the input files: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::{
+        event::{AccessKind, ModifyKind, RemoveKind},
+        Event, EventKind,
+    };
+    use pretty_assertions::assert_eq;
+
+    use super::FileWatcher;
+
+    #[test]
+    fn test_watch_changes() {
+        let dir = std::env::temp_dir().join(format!("aq-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.ason");
+        std::fs::write(&file, "1").unwrap();
+
+        let watcher = FileWatcher::new(&[file.to_string_lossy().into_owned()]).unwrap();
+        let path = std::fs::canonicalize(&file).unwrap();
+        let modify = EventKind::Modify(ModifyKind::Any);
+        assert!(watcher.is_change(Event::new(modify).add_path(path.clone())));
+        let remove = EventKind::Remove(RemoveKind::Any);
+        assert!(watcher.is_change(Event::new(remove).add_path(path.clone())));
+
+        // the reading and the other files of the directory are not changes
+        assert!(!watcher
+            .is_change(Event::new(EventKind::Access(AccessKind::Any)).add_path(path.clone())));
+        assert!(!watcher.is_change(Event::new(modify).add_path(path.with_file_name("b.ason"))));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_watch_missing_file() {
+        let error = match FileWatcher::new(&["missing.ason".to_owned()]) {
+            Ok(_) => panic!("the missing file is watched"),
+            Err(e) => e.to_string(),
+        };
+        assert!(error.starts_with("Fail to watch the file \"missing.ason\": "));
+        assert_eq!(error.lines().count(), 1);
+    }
+}
@@ -0,0 +1,242 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The time-window aggregation of the line-delimited input, i.e.
+// `aq --window 5m --by .time QUERY`, e.g. for `tail -f access.log | aq ...`.
+//
+// The values are grouped into the tumbling windows by the time of each
+// value (the result of `--by`), the windows are aligned to the multiples
+// of the width since the Unix epoch. A window is closed when a value of
+// a later window arrives (or the input ends), then the query is evaluated
+// with the rollup of the window, e.g.
+//
+// `{start: d"2024-01-01T10:05:00Z", end: d"2024-01-01T10:10:00Z", count: 3, items: [...]}`
+//
+// so the count is `.count`, the sum is `.items | map(.bytes) | add`,
+// and any custom fold can be written with `reduce .items[] as $i (...)`.
+//
+// The values of a window which has been closed (i.e. arrive too late)
+// are dropped.
+
+use std::time::Duration;
+
+use chrono::DateTime;
+
+use crate::{
+    error::Error,
+    query::Query,
+    value::{Map, Value},
+};
+
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// Parse the width of the window, e.g. `500ms`, `30s`, `5m`, `1h` and `1d`.
+pub fn parse_window_width(text: &str) -> Result<Duration, Error> {
+    let invalid = || {
+        Error::Message(format!(
+            "Invalid window width \"{}\", expect e.g. \"30s\", \"5m\" or \"1h\".",
+            text
+        ))
+    };
+
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+
+    let width = match unit {
+        "ms" => Duration::from_millis(number),
+        "s" => Duration::from_secs(number),
+        "m" => Duration::from_secs(number * 60),
+        "h" => Duration::from_secs(number * 60 * 60),
+        "d" => Duration::from_secs(number * 60 * 60 * 24),
+        _ => return Err(invalid()),
+    };
+
+    if width.is_zero() {
+        return Err(invalid());
+    }
+    Ok(width)
+}
+
+// the kind of the time values, the start and end of
+// the window are written in the same kind.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum TimeKind {
+    DateTime,
+    Seconds,
+}
+
+struct Window {
+    // the start time in nanoseconds since the Unix epoch.
+    start: i128,
+    kind: TimeKind,
+    items: Vec<Value>,
+}
+
+pub struct WindowAggregator<'q> {
+    width: i128,
+    by: &'q Query,
+    current: Option<Window>,
+
+    /// The number of the values dropped since their windows have been closed.
+    pub dropped: usize,
+}
+
+impl<'q> WindowAggregator<'q> {
+    /// `by` is the query which produces the time of the value, either
+    /// a datetime, or a number of seconds since the Unix epoch.
+    pub fn new(width: Duration, by: &'q Query) -> Self {
+        Self {
+            width: width.as_nanos() as i128,
+            by,
+            current: None,
+            dropped: 0,
+        }
+    }
+
+    /// Add the value, the rollup of the current window is
+    /// returned if the value closes it.
+    pub fn push(&mut self, value: Value) -> Result<Option<Value>, Error> {
+        let (time, kind) = match self.by.run_value(value.clone())?.as_slice() {
+            [Value::DateTime(d)] => (
+                d.timestamp() as i128 * NANOS_PER_SECOND + d.timestamp_subsec_nanos() as i128,
+                TimeKind::DateTime,
+            ),
+            [Value::Number(n)] => (
+                (n.as_f64() * NANOS_PER_SECOND as f64) as i128,
+                TimeKind::Seconds,
+            ),
+            [other] => {
+                return Err(Error::Message(format!(
+                    "The time of the window expects datetime or number, found {}.",
+                    other.type_name()
+                )))
+            }
+            results => {
+                return Err(Error::Message(format!(
+                    "The time of the window expects a single value, found {} values.",
+                    results.len()
+                )))
+            }
+        };
+        let start = time.div_euclid(self.width) * self.width;
+
+        match &mut self.current {
+            Some(window) if start == window.start => {
+                window.items.push(value);
+                Ok(None)
+            }
+            Some(window) if start < window.start => {
+                self.dropped += 1;
+                Ok(None)
+            }
+            _ => {
+                let closed = self.current.replace(Window {
+                    start,
+                    kind,
+                    items: vec![value],
+                });
+                Ok(closed.map(|w| self.rollup(w)))
+            }
+        }
+    }
+
+    /// Close the current window at the end of the input.
+    pub fn finish(&mut self) -> Option<Value> {
+        self.current.take().map(|w| self.rollup(w))
+    }
+
+    fn rollup(&self, window: Window) -> Value {
+        let mut map = Map::new();
+        map.insert("start".to_owned(), time_value(window.start, window.kind));
+        map.insert(
+            "end".to_owned(),
+            time_value(window.start + self.width, window.kind),
+        );
+        map.insert("count".to_owned(), Value::from(window.items.len()));
+        map.insert("items".to_owned(), Value::List(window.items.into()));
+        Value::Object(map)
+    }
+}
+
+fn time_value(nanos: i128, kind: TimeKind) -> Value {
+    let seconds = nanos.div_euclid(NANOS_PER_SECOND);
+    let subsec = nanos.rem_euclid(NANOS_PER_SECOND);
+
+    match kind {
+        TimeKind::DateTime => DateTime::from_timestamp(seconds as i64, subsec as u32)
+            .map(|d| Value::DateTime(d.fixed_offset()))
+            .unwrap_or_else(Value::null),
+        TimeKind::Seconds if subsec == 0 => Value::from(seconds as i64),
+        TimeKind::Seconds => Value::from(nanos as f64 / NANOS_PER_SECOND as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        query::Query,
+        value::Value,
+    };
+
+    use super::{parse_window_width, WindowAggregator};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_window_width() {
+        assert_eq!(parse_window_width("5m").unwrap().as_secs(), 300);
+        assert_eq!(parse_window_width("250ms").unwrap().as_millis(), 250);
+        assert_eq!(parse_window_width("1d").unwrap().as_secs(), 86400);
+        assert_eq!(
+            parse_window_width("0s").unwrap_err().to_string(),
+            "Invalid window width \"0s\", expect e.g. \"30s\", \"5m\" or \"1h\"."
+        );
+        assert!(parse_window_width("5x").is_err());
+        assert!(parse_window_width("m").is_err());
+    }
+
+    #[test]
+    fn test_window_aggregator() {
+        let by = Query::compile(".t").unwrap();
+        let mut aggregator = WindowAggregator::new(parse_window_width("10s").unwrap(), &by);
+        let mut rollups = vec![];
+        for t in ["1", "9.5", "12", "3", "15", "31"] {
+            if let Some(rollup) = aggregator.push(value(&format!("{{t: {}}}", t))).unwrap() {
+                rollups.push(rollup.to_string());
+            }
+        }
+        rollups.push(aggregator.finish().unwrap().to_string());
+
+        assert_eq!(
+            rollups,
+            [
+                "{start:0_i64,end:10_i64,count:2,items:[{t:1},{t:9.5}]}",
+                "{start:10_i64,end:20_i64,count:2,items:[{t:12},{t:15}]}",
+                "{start:30_i64,end:40_i64,count:1,items:[{t:31}]}"
+            ]
+        );
+
+        // `{t: 3}` arrives after its window has been closed
+        assert_eq!(aggregator.dropped, 1);
+        assert_eq!(aggregator.finish(), None);
+
+        assert_eq!(
+            aggregator
+                .push(value("{t: \"x\"}"))
+                .unwrap_err()
+                .to_string(),
+            "The time of the window expects datetime or number, found string."
+        );
+    }
+}
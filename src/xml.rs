@@ -0,0 +1,479 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Reading and writing XML, i.e. `aq --from xml` and `aq --to xml`.
+//
+// The document is converted to an object with a single entry, the key is
+// the name of the root element, and each element is converted as follows:
+//
+// - an element without attributes and child elements is converted to
+//   its text, or `Option::None` if it is empty,
+// - otherwise it is converted to an object, the attributes are the entries
+//   with the prefix `@` (e.g. `@id`), the child elements are the entries
+//   with their names, the repeated child elements are gathered into a list,
+//   and the text (if any) is the entry `#text`.
+//
+// e.g. `<a id="1"><b>x</b><b>y</b></a>` is converted to
+// `{a: {"@id": "1", b: ["x", "y"]}}`.
+//
+// The declaration, comments, processing instructions and the DOCTYPE are
+// ignored, the CDATA sections are text. All attributes and texts are
+// strings since XML has no types, see the functions `xml_attributes`,
+// `xml_children`, `xml_text` and `xml_list` of the prelude for
+// handling the converted values.
+//
+// The writing is the inverse, an object which is not a single entry one is
+// written as the children of the element `root`, the lists are written as
+// the repeated elements.
+
+use crate::{
+    error::Error,
+    format::encode_base64,
+    value::{Map, Value, VariantValue},
+};
+
+pub const ATTRIBUTE_PREFIX: &str = "@";
+pub const TEXT_KEY: &str = "#text";
+
+/// Parse the XML text.
+pub fn parse_xml(text: &str) -> Result<Value, Error> {
+    let mut parser = Parser { text, pos: 0 };
+    parser.skip_misc()?;
+    if !parser.rest().starts_with('<') {
+        return Err(parser.error("Expect the root element of XML"));
+    }
+    let (name, value) = parser.parse_element()?;
+    parser.skip_misc()?;
+    if parser.pos < text.len() {
+        return Err(parser.error("Unexpected content after the root element of XML"));
+    }
+
+    let mut map = Map::new();
+    map.insert(name, value);
+    Ok(Value::Object(map))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> Error {
+        let line = self.text[..self.pos].matches('\n').count() + 1;
+        Error::Message(format!("{} (at line {})", message, line))
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expect \"{}\"", token)))
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    // skip to the end of the terminator, e.g. "-->".
+    fn skip_past(&mut self, terminator: &str, what: &str) -> Result<&'a str, Error> {
+        match self.rest().find(terminator) {
+            Some(idx) => {
+                let content = &self.rest()[..idx];
+                self.pos += idx + terminator.len();
+                Ok(content)
+            }
+            None => Err(self.error(&format!("Unterminated {} of XML", what))),
+        }
+    }
+
+    // the declaration, comments, processing instructions and
+    // the DOCTYPE around the root element.
+    fn skip_misc(&mut self) -> Result<(), Error> {
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if rest.starts_with("<!DOCTYPE") {
+                self.skip_doctype()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    // the DOCTYPE may contain the internal subset, e.g. `<!DOCTYPE a [ ... ]>`.
+    fn skip_doctype(&mut self) -> Result<(), Error> {
+        let mut depth = 0;
+        for (idx, c) in self.rest().char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                '>' if depth == 0 => {
+                    self.pos += idx + 1;
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+        Err(self.error("Unterminated DOCTYPE of XML"))
+    }
+
+    fn parse_name(&mut self) -> Result<String, Error> {
+        let rest = self.rest();
+        let length = rest.find(|c: char| !is_name_char(c)).unwrap_or(rest.len());
+        if length == 0 || !rest.starts_with(is_name_start_char) {
+            return Err(self.error("Expect the name of element or attribute"));
+        }
+        self.pos += length;
+        Ok(rest[..length].to_owned())
+    }
+
+    // the name and the converted value of the element.
+    fn parse_element(&mut self) -> Result<(String, Value), Error> {
+        self.expect("<")?;
+        let name = self.parse_name()?;
+
+        let mut attributes = vec![];
+        let empty = loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.pos += 2;
+                break true;
+            }
+            if self.rest().starts_with('>') {
+                self.pos += 1;
+                break false;
+            }
+            let key = self.parse_name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = match self.rest().chars().next() {
+                Some(c @ ('"' | '\'')) => c,
+                _ => return Err(self.error("Expect the quoted value of attribute")),
+            };
+            self.pos += 1;
+            let raw = self.skip_past(&quote.to_string(), "attribute value")?;
+            let value = decode_entities(raw).map_err(|e| self.error(&e))?;
+            attributes.push((key, value));
+        };
+
+        let mut children = vec![];
+        let mut text = String::new();
+        if empty {
+            return Ok((name, element_value(attributes, children, text)));
+        }
+        loop {
+            let rest = self.rest();
+            if rest.is_empty() {
+                return Err(self.error(&format!("Unclosed element \"{}\" of XML", name)));
+            } else if rest.starts_with("</") {
+                self.pos += 2;
+                let closing = self.parse_name()?;
+                if closing != name {
+                    return Err(self.error(&format!(
+                        "Mismatched closing tag \"{}\", expect \"{}\"",
+                        closing, name
+                    )));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                break;
+            } else if rest.starts_with("<!--") {
+                self.skip_past("-->", "comment")?;
+            } else if rest.starts_with("<![CDATA[") {
+                self.pos += "<![CDATA[".len();
+                text.push_str(self.skip_past("]]>", "CDATA section")?);
+            } else if rest.starts_with("<?") {
+                self.skip_past("?>", "processing instruction")?;
+            } else if rest.starts_with('<') {
+                children.push(self.parse_element()?);
+            } else {
+                let length = rest.find('<').unwrap_or(rest.len());
+                let raw = &rest[..length];
+                let decoded = decode_entities(raw).map_err(|e| self.error(&e))?;
+                self.pos += length;
+                text.push_str(&decoded);
+            }
+        }
+
+        Ok((name, element_value(attributes, children, text)))
+    }
+}
+
+fn element_value(
+    attributes: Vec<(String, String)>,
+    children: Vec<(String, Value)>,
+    text: String,
+) -> Value {
+    // the whitespace between the child elements is not text
+    let text = if text.trim().is_empty() {
+        None
+    } else if children.is_empty() {
+        Some(text)
+    } else {
+        Some(text.trim().to_owned())
+    };
+
+    if attributes.is_empty() && children.is_empty() {
+        return text.map_or_else(Value::null, Value::String);
+    }
+
+    let mut map = Map::new();
+    for (key, value) in attributes {
+        map.insert(format!("{}{}", ATTRIBUTE_PREFIX, key), Value::String(value));
+    }
+    for (key, value) in children {
+        match map.get_mut(&key) {
+            Some(Value::List(items)) => items.push(value),
+            Some(existing) => {
+                let first = std::mem::replace(existing, Value::null());
+                *existing = Value::List(vec![first, value].into());
+            }
+            None => {
+                map.insert(key, value);
+            }
+        }
+    }
+    if let Some(text) = text {
+        map.insert(TEXT_KEY.to_owned(), Value::String(text));
+    }
+    Value::Object(map)
+}
+
+fn is_name_start_char(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == ':'
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | ':' | '-' | '.')
+}
+
+fn decode_entities(raw: &str) -> Result<String, String> {
+    let mut text = String::new();
+    let mut rest = raw;
+    while let Some(idx) = rest.find('&') {
+        text.push_str(&rest[..idx]);
+        let end = rest[idx..]
+            .find(';')
+            .ok_or_else(|| "Unterminated entity reference of XML".to_owned())?;
+        let entity = &rest[idx + 1..idx + end];
+        let c = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity
+                    .strip_prefix('#')
+                    .and_then(|n| n.parse::<u32>().ok())
+                    .and_then(char::from_u32),
+            },
+        };
+        match c {
+            Some(c) => text.push(c),
+            None => return Err(format!("Unknown entity reference \"&{};\" of XML", entity)),
+        }
+        rest = &rest[idx + end + 1..];
+    }
+    text.push_str(rest);
+    Ok(text)
+}
+
+fn escape(text: &str, quote: bool) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' if quote => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write the value as an XML document.
+pub fn to_xml(value: &Value) -> Result<String, Error> {
+    let mut text = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    match value {
+        Value::Object(map) if map.len() == 1 => {
+            let (name, inner) = map.iter().next().unwrap();
+            write_element(&mut text, name, inner, 0)?;
+        }
+        _ => write_element(&mut text, "root", value, 0)?,
+    }
+    Ok(text)
+}
+
+fn write_element(text: &mut String, name: &str, value: &Value, depth: usize) -> Result<(), Error> {
+    if !name.starts_with(is_name_start_char) || !name.chars().all(is_name_char) {
+        return Err(Error::Message(format!(
+            "\"{}\" is not a valid XML element name.",
+            name
+        )));
+    }
+
+    let indent = "  ".repeat(depth);
+    let (attributes, children, content) = match value {
+        Value::List(items) | Value::Tuple(items) => {
+            // the repeated elements
+            for item in items.iter() {
+                write_element(text, name, item, depth)?;
+            }
+            return Ok(());
+        }
+        Value::Variant(v) => match &v.value {
+            VariantValue::Empty if v.type_name == "Option" => (vec![], vec![], None),
+            VariantValue::Empty => (
+                vec![],
+                vec![],
+                Some(format!("{}::{}", v.type_name, v.member_name)),
+            ),
+            VariantValue::Value(inner) => return write_element(text, name, inner, depth),
+            VariantValue::Tuple(items) => {
+                return write_element(text, name, &Value::List(items.clone()), depth)
+            }
+            VariantValue::Object(map) => split_object(map)?,
+        },
+        Value::Object(map) => split_object(map)?,
+        _ => (vec![], vec![], Some(scalar_text(value)?)),
+    };
+
+    text.push_str(&indent);
+    text.push('<');
+    text.push_str(name);
+    for (key, value) in &attributes {
+        text.push_str(&format!(" {}=\"{}\"", key, escape(value, true)));
+    }
+
+    match (children.is_empty(), content) {
+        (true, None) => text.push_str("/>\n"),
+        (true, Some(content)) => {
+            text.push_str(&format!(">{}</{}>\n", escape(&content, false), name));
+        }
+        (false, content) => {
+            text.push_str(">\n");
+            if let Some(content) = content {
+                text.push_str(&format!("{}  {}\n", indent, escape(&content, false)));
+            }
+            for (key, child) in children {
+                write_element(text, key, child, depth + 1)?;
+            }
+            text.push_str(&format!("{}</{}>\n", indent, name));
+        }
+    }
+    Ok(())
+}
+
+type ElementParts<'a> = (
+    Vec<(&'a str, String)>,
+    Vec<(&'a str, &'a Value)>,
+    Option<String>,
+);
+
+// the attributes, the child elements and the text of the object.
+fn split_object(map: &Map) -> Result<ElementParts<'_>, Error> {
+    let mut attributes = vec![];
+    let mut children = vec![];
+    let mut content = None;
+    for (key, value) in map.iter() {
+        if key == TEXT_KEY {
+            content = Some(scalar_text(value)?);
+        } else if let Some(attribute) = key.strip_prefix(ATTRIBUTE_PREFIX) {
+            attributes.push((attribute, scalar_text(value)?));
+        } else {
+            children.push((key.as_str(), value));
+        }
+    }
+    Ok((attributes, children, content))
+}
+
+fn scalar_text(value: &Value) -> Result<String, Error> {
+    let text = match value {
+        _ if value.is_null() => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Char(c) => c.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Number(n) => n.plain_text(),
+        Value::DateTime(d) => d.to_rfc3339(),
+        Value::ByteData(bytes) => encode_base64(bytes),
+        _ => {
+            return Err(Error::Message(format!(
+                "The attributes and texts of XML expect scalars, found {}.",
+                value.type_name()
+            )))
+        }
+    };
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::{Map, Value},
+    };
+
+    use super::{parse_xml, to_xml};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let text = "<?xml version=\"1.0\"?>\n<!DOCTYPE c [<!ENTITY x \"y\">]>\n<!-- c -->\n<catalog lang='en'>\n  <book id=\"1\"><title>A &amp; B</title><tag>x</tag><tag>y</tag></book>\n  <book id=\"2\">Note <![CDATA[<raw>]]><empty/></book>\n  <n>&#65;&#x42;</n>\n</catalog>\n";
+        let document = parse_xml(text).unwrap();
+        assert_eq!(
+            document.to_string(),
+            r##"{catalog:{"@lang":"en",book:[{"@id":"1",title:"A & B",tag:["x","y"]},{"@id":"2",empty:Option::None,"#text":"Note <raw>"}],n:"AB"}}"##
+        );
+
+        let written = to_xml(&document).unwrap();
+        assert_eq!(
+            written,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<catalog lang=\"en\">\n  <book id=\"1\">\n    <title>A &amp; B</title>\n    <tag>x</tag>\n    <tag>y</tag>\n  </book>\n  <book id=\"2\">\n    Note &lt;raw&gt;\n    <empty/>\n  </book>\n  <n>AB</n>\n</catalog>\n"
+        );
+        assert_eq!(parse_xml(&written).unwrap(), document);
+
+        assert!(parse_xml("<a><b></a>").is_err());
+        assert!(parse_xml("<a>&nope;</a>").is_err());
+        assert!(parse_xml("<a/><b/>").is_err());
+    }
+
+    #[test]
+    fn test_to_xml() {
+        // the root element is added for the objects with multiple keys
+        assert_eq!(
+            to_xml(&value("{a: 1, b: [true, 2.5]}")).unwrap(),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<root>\n  <a>1</a>\n  <b>true</b>\n  <b>2.5</b>\n</root>\n"
+        );
+
+        let mut map = Map::new();
+        map.insert("bad key".to_owned(), Value::from(1));
+        assert!(to_xml(&Value::Object(map)).is_err());
+    }
+}
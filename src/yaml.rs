@@ -0,0 +1,1020 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// Reading and writing YAML, i.e. `aq --from yaml` and `aq --to yaml`.
+//
+// The common subset of YAML 1.2 is supported, i.e. the block and flow
+// collections, the plain, quoted and block (`|` and `>`) scalars, the
+// comments, anchors and aliases, and the multiple documents separated
+// by `---` (e.g. the Kubernetes manifests). The plain scalars are resolved
+// with the core schema:
+//
+// - `null`, `~` and the empty value are converted to `Option::None`,
+// - `true` and `false` are booleans,
+// - integers (including `0x` and `0o`) are converted to `i32`, or
+//   `i64`/`u64` if they do not fit, and other numbers (including `.inf`
+//   and `.nan`) are converted to `f64`,
+// - the `!!binary` scalars are converted to byte data,
+// - others are strings.
+//
+// When writing, the types which YAML lacks are converted in the same way
+// as JSON (see `to_json_string`), except that byte data is written as
+// `!!binary`.
+
+use std::collections::HashMap;
+
+use crate::{
+    error::Error,
+    format::{decode_base64, encode_base64},
+    json::integer_number,
+    value::{Map, Number, Value, VariantValue},
+};
+
+/// Parse the documents of the YAML text.
+pub fn parse_yaml_documents(text: &str) -> Result<Vec<Value>, Error> {
+    let mut documents = vec![];
+    let mut current = vec![];
+    let mut started = false;
+
+    for (idx, raw) in text.lines().enumerate() {
+        let number = idx + 1;
+        if raw == "---" || raw.starts_with("--- ") {
+            if started || current.iter().any(|l: &Line| !l.content.is_empty()) {
+                documents.push(std::mem::take(&mut current));
+            }
+            current.clear();
+            started = true;
+
+            // the content after the marker, e.g. `--- |`
+            let rest = raw[3..].trim_start();
+            if !rest.is_empty() {
+                current.push(Line::new(number, rest)?);
+            }
+        } else if raw == "..." {
+            documents.push(std::mem::take(&mut current));
+            started = false;
+        } else if raw.starts_with('%') && current.is_empty() {
+            // the directives, e.g. `%YAML 1.2`
+        } else {
+            current.push(Line::new(number, raw)?);
+        }
+    }
+    if started || current.iter().any(|l| !l.content.is_empty()) {
+        documents.push(current);
+    }
+
+    documents
+        .into_iter()
+        .filter(|lines| lines.iter().any(|l| !l.content.is_empty()))
+        .map(|lines| {
+            let mut parser = YamlParser {
+                lines,
+                pos: 0,
+                anchors: HashMap::new(),
+            };
+            parser.parse_document()
+        })
+        .collect()
+}
+
+struct Line {
+    number: usize,
+    indent: usize,
+
+    // the text after the indentation without the comment.
+    content: String,
+
+    // the whole line, for the block scalars.
+    raw: String,
+}
+
+impl Line {
+    fn new(number: usize, raw: &str) -> Result<Self, Error> {
+        let indent = raw.len() - raw.trim_start_matches(' ').len();
+        let rest = &raw[indent..];
+        if rest.starts_with('\t') && !rest.trim().is_empty() {
+            return Err(Error::Message(format!(
+                "The tabs are not allowed for the indentation of YAML (at line {}).",
+                number
+            )));
+        }
+
+        Ok(Self {
+            number,
+            indent,
+            content: strip_comment(rest).trim_end().to_owned(),
+            raw: raw.to_owned(),
+        })
+    }
+}
+
+// the comment starts with `#` after a whitespace (or at the start),
+// outside the quoted strings.
+fn strip_comment(text: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut prev: Option<char> = None;
+    for (idx, c) in text.char_indices() {
+        match quote {
+            Some('"') if c == '\\' && prev != Some('\\') => {}
+            Some(q) if c == q && !(q == '"' && prev == Some('\\')) => quote = None,
+            Some(_) => {}
+            None => match c {
+                '#' if prev.is_none_or(|p| p.is_whitespace()) => return &text[..idx],
+                '"' | '\'' if prev.is_none_or(|p| p.is_whitespace() || "[{,:-?".contains(p)) => {
+                    quote = Some(c)
+                }
+                _ => {}
+            },
+        }
+        // the escaped backslash does not escape the next char
+        prev = if quote == Some('"') && c == '\\' && prev == Some('\\') {
+            None
+        } else {
+            Some(c)
+        };
+    }
+    text
+}
+
+struct YamlParser {
+    lines: Vec<Line>,
+    pos: usize,
+    anchors: HashMap<String, Value>,
+}
+
+impl YamlParser {
+    fn error(&self, message: &str, number: usize) -> Error {
+        Error::Message(format!("{} (at line {})", message, number))
+    }
+
+    fn parse_document(&mut self) -> Result<Value, Error> {
+        let value = self.parse_block(0)?;
+        self.skip_blank();
+        match self.lines.get(self.pos) {
+            None => Ok(value),
+            Some(line) => Err(self.error(
+                &format!("Unexpected content \"{}\" of YAML.", line.content),
+                line.number,
+            )),
+        }
+    }
+
+    fn skip_blank(&mut self) {
+        while self
+            .lines
+            .get(self.pos)
+            .is_some_and(|l| l.content.is_empty())
+        {
+            self.pos += 1;
+        }
+    }
+
+    // the node whose lines are indented at least `min_indent`,
+    // `null` if there is no such line.
+    fn parse_block(&mut self, min_indent: usize) -> Result<Value, Error> {
+        self.skip_blank();
+        let Some(line) = self.lines.get(self.pos) else {
+            return Ok(Value::null());
+        };
+        if line.indent < min_indent {
+            return Ok(Value::null());
+        }
+
+        let indent = line.indent;
+        let content = line.content.clone();
+        if is_sequence_item(&content) {
+            self.parse_sequence(indent)
+        } else if split_key(&content).is_some() {
+            self.parse_mapping(indent)
+        } else {
+            self.pos += 1;
+            self.parse_value(&content, indent as isize - 1)
+        }
+    }
+
+    fn parse_sequence(&mut self, indent: usize) -> Result<Value, Error> {
+        let mut items = vec![];
+        loop {
+            self.skip_blank();
+            let Some(line) = self.lines.get(self.pos) else {
+                break;
+            };
+            if line.indent != indent || !is_sequence_item(&line.content) {
+                break;
+            }
+
+            let rest = line.content[1..].trim_start().to_owned();
+            let offset = line.content.len() - rest.len();
+            let (_, _, value_text) = take_properties(&rest);
+
+            if rest.is_empty() || !value_text.is_empty() && is_inline_collection_start(value_text) {
+                // e.g. `- key: value` and `- - item`, the rest of the line is
+                // treated as a line which is indented after the dash.
+                if rest.is_empty() {
+                    self.pos += 1;
+                    items.push(self.parse_block(indent + 1)?);
+                } else {
+                    let line = &mut self.lines[self.pos];
+                    line.indent = indent + offset;
+                    line.content = rest;
+                    items.push(self.parse_block(indent + offset)?);
+                }
+            } else {
+                self.pos += 1;
+                items.push(self.parse_value(&rest, indent as isize)?);
+            }
+        }
+        Ok(Value::List(items.into()))
+    }
+
+    fn parse_mapping(&mut self, indent: usize) -> Result<Value, Error> {
+        let mut map = Map::new();
+        loop {
+            self.skip_blank();
+            let Some(line) = self.lines.get(self.pos) else {
+                break;
+            };
+            if line.indent != indent || is_sequence_item(&line.content) {
+                break;
+            }
+
+            let number = line.number;
+            let Some((key_text, rest)) = split_key(&line.content) else {
+                return Err(self.error(
+                    &format!(
+                        "Expect a key of the YAML mapping, found \"{}\".",
+                        line.content
+                    ),
+                    number,
+                ));
+            };
+            let key = match parse_inline(key_text, number)? {
+                Value::String(s) => s,
+                _ => key_text.to_owned(),
+            };
+            let rest = rest.to_owned();
+            self.pos += 1;
+
+            let value = if take_properties(&rest).2.is_empty() {
+                let (anchor, tag, _) = take_properties(&rest);
+                let (anchor, tag) = (anchor.map(str::to_owned), tag.map(str::to_owned));
+
+                // the sequence of the key can be at the same indentation
+                self.skip_blank();
+                let value = match self.lines.get(self.pos) {
+                    Some(l) if l.indent == indent && is_sequence_item(&l.content) => {
+                        self.parse_sequence(indent)?
+                    }
+                    _ => self.parse_block(indent + 1)?,
+                };
+                let value = apply_tag(value, tag.as_deref(), number)?;
+                if let Some(anchor) = anchor {
+                    self.anchors.insert(anchor, value.clone());
+                }
+                value
+            } else {
+                self.parse_value(&rest, indent as isize)?
+            };
+            map.insert(key, value);
+        }
+        Ok(Value::Object(map))
+    }
+
+    // the value of the text of a line (i.e. the rest after the key or
+    // the dash), the lines which continue the value are also consumed.
+    fn parse_value(&mut self, text: &str, parent_indent: isize) -> Result<Value, Error> {
+        let number = self.lines[self.pos.saturating_sub(1)].number;
+        let (anchor, tag, value_text) = take_properties(text);
+
+        let value = if value_text.starts_with('|') || value_text.starts_with('>') {
+            Value::String(self.parse_block_scalar(value_text, parent_indent, number)?)
+        } else {
+            let mut text = value_text.to_owned();
+            while is_incomplete(&text) {
+                match self.lines.get(self.pos) {
+                    Some(l) if l.content.is_empty() => self.pos += 1,
+                    Some(l) if l.indent as isize > parent_indent => {
+                        text.push(' ');
+                        text.push_str(l.content.trim());
+                        self.pos += 1;
+                    }
+                    _ => break,
+                }
+            }
+            parse_inline(&text, number)?
+        };
+
+        let value = apply_tag(value, tag, number)?;
+        if let Some(anchor) = anchor {
+            self.anchors.insert(anchor.to_owned(), value.clone());
+        }
+
+        match value_text.strip_prefix('*') {
+            Some(alias) => self.anchors.get(alias.trim()).cloned().ok_or_else(|| {
+                self.error(&format!("Unknown alias \"{}\" of YAML.", alias), number)
+            }),
+            None => Ok(value),
+        }
+    }
+
+    fn parse_block_scalar(
+        &mut self,
+        header: &str,
+        parent_indent: isize,
+        number: usize,
+    ) -> Result<String, Error> {
+        let folded = header.starts_with('>');
+        let mut chomping = ' ';
+        let mut explicit_indent = None;
+        for c in header[1..].chars() {
+            match c {
+                '-' | '+' => chomping = c,
+                '1'..='9' => explicit_indent = c.to_digit(10).map(|d| d as usize),
+                _ => {
+                    return Err(self.error(
+                        &format!("Invalid block scalar header \"{}\" of YAML.", header),
+                        number,
+                    ))
+                }
+            }
+        }
+
+        let base = (parent_indent + 1).max(0) as usize;
+        let content_indent = match explicit_indent {
+            Some(n) => base + n - 1,
+            None => self.lines[self.pos..]
+                .iter()
+                .find(|l| !l.raw.trim().is_empty())
+                .map_or(base, |l| l.indent.max(base)),
+        };
+
+        let mut lines = vec![];
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.raw.trim().is_empty() {
+                lines.push(String::new());
+            } else if line.indent >= content_indent && line.indent as isize > parent_indent {
+                lines.push(line.raw[content_indent..].to_owned());
+            } else {
+                break;
+            }
+            self.pos += 1;
+        }
+
+        // the trailing empty lines are handled by the chomping.
+        let trailing = lines.iter().rev().take_while(|l| l.is_empty()).count();
+        lines.truncate(lines.len() - trailing);
+
+        let mut text = if folded {
+            let mut text = String::new();
+            for (idx, line) in lines.iter().enumerate() {
+                if idx > 0 {
+                    let more_indented = |l: &str| l.starts_with(' ');
+                    if line.is_empty()
+                        || lines[idx - 1].is_empty()
+                        || more_indented(line)
+                        || more_indented(&lines[idx - 1])
+                    {
+                        text.push('\n');
+                    } else {
+                        text.push(' ');
+                    }
+                }
+                text.push_str(line);
+            }
+            text
+        } else {
+            lines.join("\n")
+        };
+
+        match chomping {
+            '-' => {}
+            '+' => text.push_str(&"\n".repeat(trailing + 1)),
+            _ if !text.is_empty() => text.push('\n'),
+            _ => {}
+        }
+        Ok(text)
+    }
+}
+
+fn is_sequence_item(content: &str) -> bool {
+    content == "-" || content.starts_with("- ")
+}
+
+// whether the rest of a sequence item is a block collection, e.g. `- a: 1`.
+fn is_inline_collection_start(text: &str) -> bool {
+    is_sequence_item(text) || split_key(text).is_some()
+}
+
+// the key and the rest of a mapping entry, i.e. `key: value`,
+// the colon must be followed by a space or the line end.
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    let bytes = content.as_bytes();
+    let mut idx = 0;
+
+    // the quoted keys
+    if let Some(quote @ (b'"' | b'\'')) = bytes.first() {
+        idx = 1;
+        while idx < bytes.len() {
+            if bytes[idx] == b'\\' && *quote == b'"' {
+                idx += 2;
+                continue;
+            }
+            if bytes[idx] == *quote {
+                break;
+            }
+            idx += 1;
+        }
+        idx += 1;
+    } else if matches!(
+        bytes.first(),
+        Some(b'[' | b'{' | b'-' | b'|' | b'>' | b'*' | b'&' | b'!')
+    ) && !content.starts_with("-:")
+    {
+        // the flow collections, the block scalars, aliases etc.
+        if !content.starts_with('-') || content.starts_with("- ") || content == "-" {
+            return None;
+        }
+    }
+
+    while idx < bytes.len() {
+        if bytes[idx] == b':' && (idx + 1 == bytes.len() || bytes[idx + 1] == b' ') {
+            let key = content[..idx].trim();
+            if key.is_empty() {
+                return None;
+            }
+            return Some((key, content[idx + 1..].trim_start()));
+        }
+        idx += 1;
+    }
+    None
+}
+
+// the anchor (`&name`), the tag (`!tag` or `!!tag`) and the rest.
+fn take_properties(text: &str) -> (Option<&str>, Option<&str>, &str) {
+    let mut anchor = None;
+    let mut tag = None;
+    let mut rest = text.trim_start();
+    loop {
+        let (kind, body) = match rest.chars().next() {
+            Some('&') => ('&', &rest[1..]),
+            Some('!') => ('!', rest),
+            _ => break,
+        };
+        let end = body.find(' ').unwrap_or(body.len());
+        if kind == '&' {
+            anchor = Some(&body[..end]);
+        } else {
+            tag = Some(&body[..end]);
+        }
+        rest = body[end..].trim_start();
+    }
+    (anchor, tag, rest)
+}
+
+fn apply_tag(value: Value, tag: Option<&str>, number: usize) -> Result<Value, Error> {
+    match (tag, value) {
+        (Some("!!binary"), Value::String(s)) => decode_base64(&s).map(Value::ByteData),
+        (Some("!!binary"), _) => Err(Error::Message(format!(
+            "The \"!!binary\" of YAML expects base64 text (at line {}).",
+            number
+        ))),
+        (Some("!!str"), v @ Value::String(_)) => Ok(v),
+        (Some("!!str"), v) if v.is_null() => Ok(Value::String(String::new())),
+        (Some("!!str"), Value::Number(n)) => Ok(Value::String(n.plain_text())),
+        (Some("!!str"), Value::Boolean(b)) => Ok(Value::String(b.to_string())),
+        // the other tags (e.g. the application tags) are ignored
+        (_, v) => Ok(v),
+    }
+}
+
+// the inline value is continued on the next lines if it is an unclosed
+// flow collection or quoted string, or a plain scalar.
+fn is_incomplete(text: &str) -> bool {
+    if text.is_empty() || text.starts_with('*') {
+        return false;
+    }
+
+    let mut depth = 0isize;
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth -= 1,
+                _ => {}
+            },
+        }
+    }
+
+    let starts_quoted = text.starts_with(['"', '\'']);
+    let starts_flow = text.starts_with(['[', '{']);
+    if starts_flow {
+        depth > 0 || quote.is_some()
+    } else if starts_quoted {
+        quote.is_some()
+    } else {
+        // the plain scalar folds the more-indented lines
+        true
+    }
+}
+
+// parse a flow node or a scalar of one (joined) line.
+fn parse_inline(text: &str, number: usize) -> Result<Value, Error> {
+    let mut parser = FlowParser {
+        chars: text.chars().collect(),
+        pos: 0,
+        number,
+    };
+    let value = parser.parse_node(false)?;
+    parser.skip_spaces();
+    if parser.pos < parser.chars.len() {
+        let rest: String = parser.chars[parser.pos..].iter().collect();
+        return Err(parser.error(&format!("Unexpected \"{}\" of YAML.", rest)));
+    }
+    Ok(value)
+}
+
+struct FlowParser {
+    chars: Vec<char>,
+    pos: usize,
+    number: usize,
+}
+
+impl FlowParser {
+    fn error(&self, message: &str) -> Error {
+        Error::Message(format!("{} (at line {})", message, self.number))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn parse_node(&mut self, in_flow: bool) -> Result<Value, Error> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => self.parse_flow_sequence(),
+            Some('{') => self.parse_flow_mapping(),
+            Some('"') => self.parse_double_quoted().map(Value::String),
+            Some('\'') => self.parse_single_quoted().map(Value::String),
+            Some('!') => {
+                let start = self.pos;
+                while self.peek().is_some_and(|c| !c.is_whitespace()) {
+                    self.pos += 1;
+                }
+                let tag: String = self.chars[start..self.pos].iter().collect();
+                let value = self.parse_node(in_flow)?;
+                apply_tag(value, Some(&tag), self.number)
+            }
+            _ => Ok(resolve_plain(&self.parse_plain(in_flow))),
+        }
+    }
+
+    fn parse_plain(&mut self, in_flow: bool) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            let next = self.chars.get(self.pos + 1).copied();
+            let ends_key = c == ':'
+                && next.is_none_or(|n| n.is_whitespace() || in_flow && ",[]{}".contains(n));
+            if in_flow && (",[]{}".contains(c) || ends_key) {
+                break;
+            }
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .trim()
+            .to_owned()
+    }
+
+    fn parse_flow_sequence(&mut self) -> Result<Value, Error> {
+        self.pos += 1; // consume '['
+        let mut items = vec![];
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(_) => {
+                    items.push(self.parse_node(true)?);
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some(']') => {}
+                        _ => return Err(self.error("Expect ',' or ']' of the YAML sequence.")),
+                    }
+                }
+                None => return Err(self.error("Unterminated YAML sequence.")),
+            }
+        }
+        Ok(Value::List(items.into()))
+    }
+
+    fn parse_flow_mapping(&mut self) -> Result<Value, Error> {
+        self.pos += 1; // consume '{'
+        let mut map = Map::new();
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(_) => {
+                    let key = match self.parse_node(true)? {
+                        Value::String(s) => s,
+                        v if v.is_null() => String::new(),
+                        Value::Number(n) => n.plain_text(),
+                        Value::Boolean(b) => b.to_string(),
+                        _ => return Err(self.error("The keys of YAML must be scalars.")),
+                    };
+                    self.skip_spaces();
+
+                    // the key without the value, e.g. `{a, b}`
+                    let value = if self.peek() == Some(':') {
+                        self.pos += 1;
+                        self.skip_spaces();
+                        if matches!(self.peek(), Some(',' | '}')) {
+                            Value::null()
+                        } else {
+                            self.parse_node(true)?
+                        }
+                    } else {
+                        Value::null()
+                    };
+                    map.insert(key, value);
+
+                    self.skip_spaces();
+                    match self.peek() {
+                        Some(',') => self.pos += 1,
+                        Some('}') => {}
+                        _ => return Err(self.error("Expect ',' or '}' of the YAML mapping.")),
+                    }
+                }
+                None => return Err(self.error("Unterminated YAML mapping.")),
+            }
+        }
+        Ok(Value::Object(map))
+    }
+
+    fn parse_single_quoted(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.peek() {
+                Some('\'') if self.chars.get(self.pos + 1) == Some(&'\'') => {
+                    s.push('\'');
+                    self.pos += 2;
+                }
+                Some('\'') => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                Some(c) => {
+                    s.push(c);
+                    self.pos += 1;
+                }
+                None => return Err(self.error("Unterminated YAML string.")),
+            }
+        }
+    }
+
+    fn parse_double_quoted(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("Unterminated YAML string."));
+            };
+            self.pos += 1;
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let Some(escaped) = self.peek() else {
+                        return Err(self.error("Unterminated YAML string."));
+                    };
+                    self.pos += 1;
+                    let hex_digits = match escaped {
+                        'x' => 2,
+                        'u' => 4,
+                        'U' => 8,
+                        _ => 0,
+                    };
+                    if hex_digits > 0 {
+                        let digits: String =
+                            self.chars.iter().skip(self.pos).take(hex_digits).collect();
+                        self.pos += hex_digits;
+                        let c = u32::from_str_radix(&digits, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| {
+                                self.error(&format!("Invalid escape \"\\{}{}\".", escaped, digits))
+                            })?;
+                        s.push(c);
+                        continue;
+                    }
+                    s.push(match escaped {
+                        'n' => '\n',
+                        't' => '\t',
+                        'r' => '\r',
+                        '0' => '\0',
+                        'b' => '\u{8}',
+                        'e' => '\u{1b}',
+                        ' ' => ' ',
+                        '/' => '/',
+                        '"' => '"',
+                        '\\' => '\\',
+                        _ => return Err(self.error(&format!("Invalid escape \"\\{}\".", escaped))),
+                    });
+                }
+                _ => s.push(c),
+            }
+        }
+    }
+}
+
+// the core schema of YAML 1.2.
+fn resolve_plain(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::null(),
+        "true" | "True" | "TRUE" => return Value::Boolean(true),
+        "false" | "False" | "FALSE" => return Value::Boolean(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => {
+            return Value::Number(Number::F64(f64::INFINITY))
+        }
+        "-.inf" | "-.Inf" | "-.INF" => return Value::Number(Number::F64(f64::NEG_INFINITY)),
+        ".nan" | ".NaN" | ".NAN" => return Value::Number(Number::F64(f64::NAN)),
+        _ => {}
+    }
+
+    let (negative, body) = match text.as_bytes()[0] {
+        b'-' => (true, &text[1..]),
+        b'+' => (false, &text[1..]),
+        _ => (false, text),
+    };
+    let radix_body = body
+        .strip_prefix("0x")
+        .map(|h| (h, 16))
+        .or_else(|| body.strip_prefix("0o").map(|o| (o, 8)));
+    if let Some((digits, radix)) = radix_body {
+        if let Ok(n) = i128::from_str_radix(digits, radix) {
+            return Value::Number(integer_number(if negative { -n } else { n }));
+        }
+    }
+    if !body.is_empty() && body.bytes().all(|b| b.is_ascii_digit()) {
+        if let Ok(n) = body.parse::<i128>() {
+            return Value::Number(integer_number(if negative { -n } else { n }));
+        }
+    }
+    let is_float = !body.is_empty()
+        && body.bytes().any(|b| b.is_ascii_digit())
+        && body
+            .bytes()
+            .all(|b| b.is_ascii_digit() || matches!(b, b'.' | b'e' | b'E' | b'-' | b'+'))
+        && body
+            .bytes()
+            .next()
+            .is_some_and(|b| b.is_ascii_digit() || b == b'.');
+    if is_float {
+        if let Ok(f) = body.parse::<f64>() {
+            return Value::Number(Number::F64(if negative { -f } else { f }));
+        }
+    }
+    Value::String(text.to_owned())
+}
+
+/// Write the value as a YAML document, the text ends with a line break.
+pub fn to_yaml(value: &Value) -> String {
+    let mut text = String::new();
+    match block_entries(value) {
+        Some(_) => write_block(value, 0, &mut text),
+        None => {
+            text.push_str(&inline_text(value));
+            text.push('\n');
+        }
+    }
+    text
+}
+
+// the entries of the non-empty collections, which are written in the
+// block style, the variants are converted to the mappings first.
+fn block_entries(value: &Value) -> Option<Value> {
+    match value {
+        Value::List(items) | Value::Tuple(items) if !items.is_empty() => Some(value.clone()),
+        Value::Object(map) if !map.is_empty() => Some(value.clone()),
+        Value::Variant(v) if v.type_name == "Option" => match &v.value {
+            VariantValue::Value(inner) => block_entries(inner),
+            _ => None,
+        },
+        Value::Variant(v) => {
+            let payload = match &v.value {
+                VariantValue::Empty => return None,
+                VariantValue::Value(inner) => inner.as_ref().clone(),
+                VariantValue::Tuple(items) => Value::List(items.clone()),
+                VariantValue::Object(map) => Value::Object(map.clone()),
+            };
+            let mut map = Map::new();
+            map.insert(format!("{}::{}", v.type_name, v.member_name), payload);
+            Some(Value::Object(map))
+        }
+        _ => None,
+    }
+}
+
+fn write_block(value: &Value, indent: usize, text: &mut String) {
+    let padding = " ".repeat(indent);
+    match block_entries(value) {
+        Some(Value::List(items) | Value::Tuple(items)) => {
+            for item in items.iter() {
+                if block_entries(item).is_some() {
+                    // the first line of the nested block follows the dash
+                    let mut nested = String::new();
+                    write_block(item, indent + 2, &mut nested);
+                    text.push_str(&padding);
+                    text.push_str("- ");
+                    text.push_str(&nested[indent + 2..]);
+                } else {
+                    text.push_str(&format!("{}- {}\n", padding, inline_text(item)));
+                }
+            }
+        }
+        Some(Value::Object(map)) => {
+            for (key, item) in map.iter() {
+                let key = scalar_text(key);
+                if block_entries(item).is_some() {
+                    text.push_str(&format!("{}{}:\n", padding, key));
+                    write_block(item, indent + 2, text);
+                } else {
+                    text.push_str(&format!("{}{}: {}\n", padding, key, inline_text(item)));
+                }
+            }
+        }
+        _ => unreachable!("only the collections are written in the block style"),
+    }
+}
+
+// the text of the scalars and the empty collections.
+fn inline_text(value: &Value) -> String {
+    match value {
+        _ if value.is_null() => "null".to_owned(),
+        Value::Number(n) if n.is_float() => {
+            let f = n.as_f64();
+            if f.is_nan() {
+                ".nan".to_owned()
+            } else if f.is_infinite() {
+                if f > 0.0 { ".inf" } else { "-.inf" }.to_owned()
+            } else {
+                // keep the fraction, so it is read as a number with fraction
+                format!("{:?}", f)
+            }
+        }
+        Value::Number(n) => n.plain_text(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Char(c) => scalar_text(&c.to_string()),
+        Value::String(s) => scalar_text(s),
+        Value::DateTime(d) => d.to_rfc3339(),
+        Value::ByteData(bytes) => format!("!!binary {}", encode_base64(bytes)),
+        Value::List(_) | Value::Tuple(_) => "[]".to_owned(),
+        Value::Object(_) => "{}".to_owned(),
+        Value::Variant(v) => match &v.value {
+            VariantValue::Value(inner) if v.type_name == "Option" => inline_text(inner),
+            _ => scalar_text(&format!("{}::{}", v.type_name, v.member_name)),
+        },
+    }
+}
+
+// the string is written as a plain scalar if it reads back as the same
+// string, otherwise it is double-quoted.
+fn scalar_text(s: &str) -> String {
+    let plain = !s.is_empty()
+        && s.trim() == s
+        && !s.starts_with([
+            '-', '?', ':', ',', '[', ']', '{', '}', '#', '&', '*', '!', '|', '>', '\'', '"', '%',
+            '@', '`',
+        ])
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.ends_with(':')
+        && !s.chars().any(|c| c.is_control())
+        && matches!(resolve_plain(s), Value::String(_));
+    if plain {
+        return s.to_owned();
+    }
+
+    let mut text = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            _ if c.is_control() => text.push_str(&format!("\\u{:04x}", c as u32)),
+            _ => text.push(c),
+        }
+    }
+    text.push('"');
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use crate::{
+        input::{parse_input, ParseOptions},
+        value::{Map, Value},
+    };
+
+    use super::{parse_yaml_documents, to_yaml};
+
+    fn value(text: &str) -> Value {
+        parse_input(text, None, &ParseOptions::unlimited()).unwrap()
+    }
+
+    #[test]
+    fn test_parse_yaml() {
+        let text = r#"%YAML 1.2
+---
+apiVersion: v1   # the version
+kind: Service
+metadata:
+  name: "web # 1"
+  labels: {app: web, tier: ''}
+  annotations:
+    note: |
+      line one
+      line two
+    folded: >-
+      a
+      b
+spec:
+  ports:
+  - port: 80
+    targetPort: 0x1F90
+    name: http
+  - &p {port: 443, name: https}
+  copy: *p
+  flags: [yes, true, ~, 1.5, .inf, -7, 'it''s']
+  empty:
+  nested:
+    - - 1
+      - 2
+    - []
+  data: !!binary AQI=
+---
+- a
+- b: 1
+  c: long
+    text
+...
+"#;
+        let documents = parse_yaml_documents(text).unwrap();
+        assert_eq!(documents.len(), 2);
+        assert_eq!(
+            documents[0].to_string(),
+            r#"{apiVersion:"v1",kind:"Service",metadata:{name:"web # 1",labels:{app:"web",tier:""},annotations:{note:"line one\nline two\n",folded:"a b"}},spec:{ports:[{port:80,targetPort:8080,name:"http"},{port:443,name:"https"}],copy:{port:443,name:"https"},flags:["yes",true,Option::None,1.5,Inf,-7,"it's"],empty:Option::None,nested:[[1,2],[]],data:h"01 02"}}"#
+        );
+        assert_eq!(documents[1].to_string(), r#"["a",{b:1,c:"long text"}]"#);
+
+        // the written documents read back
+        for document in &documents {
+            let written = to_yaml(document);
+            let back = parse_yaml_documents(&written).unwrap();
+            assert_eq!(&back[0], document, "{}", written);
+        }
+
+        assert!(parse_yaml_documents("a:\n\t- 1").is_err());
+        assert!(parse_yaml_documents("a: *missing").is_err());
+    }
+
+    #[test]
+    fn test_to_yaml() {
+        assert_eq!(
+            to_yaml(&value(r#"{a: [1, {b: "x: y"}], d: "true", e: 2.0}"#)),
+            "a:\n  - 1\n  - b: \"x: y\"\nd: \"true\"\ne: 2.0\n"
+        );
+
+        let mut map = Map::new();
+        map.insert("c".to_owned(), Value::Object(Map::new()));
+        map.insert("data".to_owned(), Value::ByteData(vec![1, 2]));
+        assert_eq!(to_yaml(&Value::Object(map)), "c: {}\ndata: !!binary AQI=\n");
+    }
+}
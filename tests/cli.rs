@@ -0,0 +1,2308 @@
+// Copyright (c) 2024 Hemashushu <hippospark@gmail.com>, All rights reserved.
+//
+// This Source Code Form is subject to the terms of
+// the Mozilla Public License version 2.0 and additional exceptions,
+// more details in file LICENSE, LICENSE.additional and CONTRIBUTING.
+
+// The tests of the command line tool `aq`, e.g.
+//
+// ```
+// let output = aq(&["-c", ".id"], "{id: 123}");
+// assert_eq!(output.stdout, "123\n");
+// ```
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use pretty_assertions::assert_eq;
+
+struct Output {
+    status: i32,
+    stdout: String,
+    stderr: String,
+
+    // the raw bytes of STDOUT, for the binary output
+    bytes: Vec<u8>,
+}
+
+// run `aq` with the arguments and the STDIN text,
+// in the directory of the crate.
+fn aq(args: &[&str], stdin: &str) -> Output {
+    aq_with_env(args, stdin, &[])
+}
+
+// run `aq` with the additional environment variables.
+fn aq_with_env(args: &[&str], stdin: &str, envs: &[(&str, &str)]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_aq"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("NO_COLOR", "1")
+        .envs(envs.iter().copied())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the process may exit without reading the STDIN
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    Output {
+        status: output.status.code().unwrap_or(-1),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        bytes: output.stdout,
+    }
+}
+
+// the temporary directory of the test, it is named after the test to
+// avoid conflicts, and it is removed when it is dropped (also when the
+// test fails).
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(test: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("aq-cli-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+
+    // write the file in the directory.
+    fn file(&self, name: &str, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.0.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn test_from_json5_and_jsonc() {
+    let output = aq(&["-c", "--from-json5", "."], "{a: 'x', b: [1, 2,],}");
+    assert_eq!(output.stdout, "{a:\"x\",b:[1,2]}\n");
+
+    let output = aq(&["-c", "--from-jsonc", ".b"], "{\"b\": 1 /* comment */}");
+    assert_eq!(output.stdout, "1\n");
+
+    let output = aq(&["--from-jsonc", "."], "{b: 1}");
+    assert_eq!(output.status, 1);
+    assert!(!output.stderr.is_empty());
+}
+
+#[test]
+fn test_roundtrip() {
+    let temp = TempDir::new("roundtrip");
+    let file = temp.file("plain.ason", "{\n    id: 123\n}\n");
+    let output = aq(&["roundtrip", file.to_str().unwrap()], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "No information is lost in the round trip.\n");
+
+    // the formatting information is lost, but the values are kept
+    let file = temp.file("comment.ason", "{\n    id: 0x10 // the id\n}\n");
+    let output = aq(&["roundtrip", file.to_str().unwrap()], "");
+    assert_eq!(output.status, 2);
+    assert_eq!(
+        output.stdout,
+        "radix of number 0x10 lost at line 2\ncomment dropped at line 2\n"
+    );
+}
+
+#[test]
+fn test_assert() {
+    let output = aq(&["-c", "--assert", ". > 1", ".a"], "{a: 2}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "2\n");
+
+    // the output is not written if any assertion fails
+    let output = aq(
+        &[
+            "-c",
+            "--assert",
+            "type == \"number\"",
+            "--assert",
+            ". > 5",
+            ".a",
+        ],
+        "{a: 2}",
+    );
+    assert_eq!(output.status, 1);
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "Assertion failed: \". > 5\".\n");
+
+    let output = aq(&["--assert", ".a ==", "."], "{a: 2}");
+    assert_eq!(output.status, 1);
+    assert!(output
+        .stderr
+        .starts_with("Fail to parse the assertion: \".a ==\".\n"));
+}
+
+#[test]
+fn test_export_shell() {
+    let output = aq(
+        &["--export-shell", ".server"],
+        r#"{server: {host: "example.com", port: 8080, note: "it's"}}"#,
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "host='example.com'\nport='8080'\nnote='it'\\''s'\n"
+    );
+
+    let output = aq(&["--export-shell", ".port"], "{port: 8080}");
+    assert_eq!(output.status, 1);
+    assert!(output.stderr.contains("Only an object can be exported"));
+}
+
+#[test]
+fn test_binary_output() {
+    // STDOUT is not a terminal, so the bytes are written as they are
+    let output = aq(&["--to-msgpack", "."], "{a: [1, true]}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.bytes, vec![0x81, 0xa1, b'a', 0x92, 1, 0xc3]);
+
+    let output = aq(&["--to-cbor", ".a"], "{a: [1, true]}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.bytes, vec![0x82, 1, 0xf5]);
+}
+
+#[test]
+fn test_provenance() {
+    let temp = TempDir::new("provenance");
+    let a = temp.file("a.ason", "{port: 80}");
+    let b = temp.file("b.ason", "{host: \"x\"}");
+    let output = aq(
+        &[
+            "-c",
+            "-s",
+            "--provenance",
+            "provenance(.[1].host)",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{{value:\"x\",file:{:?},path:\".host\"}}\n",
+            b.to_str().unwrap()
+        )
+    );
+
+    // each document is from its own file without `--slurp`
+    let output = aq(
+        &[
+            "-c",
+            "--provenance",
+            "provenance(.port)",
+            a.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{{value:80,file:{:?},path:\".port\"}}\n",
+            a.to_str().unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_schema_check() {
+    let temp = TempDir::new("schema-check");
+    let types = temp.file("types.rs", "pub struct Server { host: String, port: u16 }");
+    let good = temp.file("good.ason", r#"{host: "x", port: 80_u16}"#);
+    let bad = temp.file("bad.ason", r#"{host: 1, port: 80_u16}"#);
+
+    let output = aq(
+        &[
+            "schema",
+            "check",
+            "--types",
+            types.to_str().unwrap(),
+            good.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+
+    let output = aq(
+        &[
+            "schema",
+            "check",
+            "--types",
+            types.to_str().unwrap(),
+            bad.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{}: .host: expect a string, found 1\n",
+            bad.to_str().unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_codegen() {
+    let temp = TempDir::new("codegen");
+    let file = temp.file("server.ason", r#"{host: "x", port: 80_u16}"#);
+    let output = aq(&["codegen", "--name", "Server", file.to_str().unwrap()], "");
+    assert_eq!(output.status, 0);
+    assert!(output
+        .stdout
+        .contains("pub struct Server {\n    pub host: String,\n    pub port: u16,\n}"));
+
+    let output = aq(&["codegen", "--lang", "go", file.to_str().unwrap()], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Language \"go\" is not supported, only \"rust\" is available.\n"
+    );
+}
+
+#[test]
+fn test_inputs() {
+    let temp = TempDir::new("inputs");
+    let a = temp.file("a.ason", "{a: 1}");
+    let b = temp.file("b.ason", "{b: 2}");
+    let c = temp.file("c.ason", "{c: 3}");
+    let files = [
+        a.to_str().unwrap(),
+        b.to_str().unwrap(),
+        c.to_str().unwrap(),
+    ];
+
+    let output = aq(&[&["-c", "-n", "[inputs] | add"], &files[..]].concat(), "");
+    assert_eq!(output.stdout, "{a:1,b:2,c:3}\n");
+
+    // the document consumed by `input` is not the input of another run
+    let output = aq(&[&["-c", "[., input]"], &files[..2]].concat(), "");
+    assert_eq!(output.stdout, "[{a:1},{b:2}]\n");
+
+    let output = aq(&[&["-c", "[., input]"], &files[..]].concat(), "");
+    assert_eq!(output.status, 1);
+    assert_eq!(output.stdout, "");
+    assert!(output.stderr.starts_with("No more inputs"));
+    assert!(output.stderr.contains(&format!(
+        "The input document is from the file {:?}.",
+        files[2]
+    )));
+}
+
+#[test]
+fn test_progress() {
+    let temp = TempDir::new("progress");
+    // the progress is written to STDERR, the results are not affected
+    let file = temp.file("a.ason", "{a: 1}");
+    let output = aq(&["-c", "--progress", ".a", file.to_str().unwrap()], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n");
+    assert!(output.stderr.contains("1/1 documents"));
+}
+
+#[test]
+fn test_doctor() {
+    let temp = TempDir::new("doctor");
+    let file = temp.file("a.ason", "{a: 1, a: 2}");
+    let output = aq(&["doctor", file.to_str().unwrap()], "");
+    assert_eq!(output.status, 0);
+    assert!(output.stdout.starts_with("aq "));
+    assert!(output.stdout.contains(&format!(
+        "{}: line 1: warning: the key \"a\" is duplicated\n",
+        file.to_str().unwrap()
+    )));
+
+    // the errors fail the diagnosis
+    let file = temp.file("b.ason", "{a: }");
+    let output = aq(&["doctor", file.to_str().unwrap()], "");
+    assert_eq!(output.status, 1);
+    assert!(output.stdout.contains("error: the file cannot be parsed"));
+}
+
+#[test]
+fn test_convert() {
+    let temp = TempDir::new("convert");
+    let source = temp.file("src/a/x.json5", "{a: 1, // comment\n}");
+    let source_dir = source.parent().unwrap().parent().unwrap();
+    let destination = source_dir.parent().unwrap().join("dest");
+
+    let output = aq(
+        &[
+            "convert",
+            "--to",
+            "json",
+            "-o",
+            destination.to_str().unwrap(),
+            source_dir.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert!(output.stdout.ends_with("1 file(s) converted, 0 failed.\n"));
+
+    let converted = std::fs::read_to_string(destination.join("a/x.json")).unwrap();
+    assert_eq!(
+        converted.split_whitespace().collect::<String>(),
+        r#"{"a":1}"#
+    );
+
+    let output = aq(
+        &[
+            "convert",
+            "--to",
+            "ini",
+            "-o",
+            "out",
+            source_dir.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 1);
+    assert!(output.stderr.starts_with("Format \"ini\" is not supported"));
+}
+
+#[test]
+fn test_explain_empty() {
+    let output = aq(
+        &["--explain-empty", ".users[] | select(.age > 30) | .name"],
+        r#"{users: [{name: "a", age: 20}]}"#,
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        output.stderr,
+        "The query produces no output.
+The stream becomes empty at stage 2: `select(.age > 30)`
+It receives 1 value(s), the first one is:
+{name:\"a\",age:20}
+"
+    );
+}
+
+#[test]
+fn test_grep() {
+    let temp = TempDir::new("grep");
+    let document = r#"{users: [{name: "Alice", email: "alice@example.com"}], example: 1}"#;
+    let output = aq(&["grep", "-i", "ALICE"], document);
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        ".users[0].name: \"Alice\"\n.users[0].email: \"alice@example.com\"\n"
+    );
+
+    // the file names are written when there are multiple files
+    let a = temp.file("a.ason", document);
+    let b = temp.file("b.ason", "{example: 2}");
+    let output = aq(
+        &[
+            "grep",
+            "--keys",
+            "^example$",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{}:.example: 1\n{}:.example: 2\n",
+            a.to_str().unwrap(),
+            b.to_str().unwrap()
+        )
+    );
+
+    // nothing is found
+    let output = aq(&["grep", "nothing"], document);
+    assert_eq!(output.status, 1);
+    assert_eq!(output.stdout, "");
+
+    // the long line and the pattern which backtracks exponentially
+    let line = format!(
+        "{{a: \"{}b\", b: \"{}\"}}",
+        "a".repeat(100_000),
+        "a".repeat(24)
+    );
+    for pattern in ["a.*b", "(a+)+b"] {
+        let output = aq(&["grep", pattern], &line);
+        assert_eq!(output.status, 0);
+        assert!(output.stdout.starts_with(".a: \"aaa"));
+        assert!(!output.stdout.contains(".b:"));
+    }
+}
+
+#[test]
+fn test_debug() {
+    let output = aq(&["-c", r#"[.[] | debug("x: \(.)") | . * 2]"#], "[1, 2]");
+    assert_eq!(output.stdout, "[2,4]\n");
+    assert_eq!(
+        output.stderr,
+        "[\"DEBUG:\",\"x: 1\"]\n[\"DEBUG:\",\"x: 2\"]\n"
+    );
+
+    // `stderr` writes the compact text without the line feed
+    let output = aq(&["-c", ".[] | stderr"], r#"["a", {b: 1}]"#);
+    assert_eq!(output.stdout, "\"a\"\n{b:1}\n");
+    assert_eq!(output.stderr, "\"a\"{b:1}");
+}
+
+#[test]
+fn test_input_filename() {
+    let temp = TempDir::new("input-filename");
+    let a = temp.file("a.ason", "{id: 1}");
+    let b = temp.file("b.ason", "{id: 2}");
+    let output = aq(
+        &[
+            "-c",
+            "{file: input_filename, line: input_line_number, id}",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{{file:{:?},line:1,id:1}}\n{{file:{:?},line:1,id:2}}\n",
+            a.to_str().unwrap(),
+            b.to_str().unwrap()
+        )
+    );
+
+    // the STDIN has no file name, each raw line is a document
+    let output = aq(
+        &["-c", "-R", "[input_filename, input_line_number, .]"],
+        "x\ny\n",
+    );
+    assert_eq!(
+        output.stdout,
+        "[Option::None,1,\"x\"]\n[Option::None,2,\"y\"]\n"
+    );
+}
+
+#[test]
+fn test_import_modules() {
+    let temp = TempDir::new("import");
+    let library = temp.file("lib/text.aql", "def shout: ascii_upcase + \"!\";");
+    let query = temp.file(
+        "query.aql",
+        "import \"text\" as t; import \"local\" as l; .name | t::shout, l::twice",
+    );
+    temp.file("local.aql", "def twice: . + .;");
+    let data = temp.file("data.ason", r#"{name: "ab"}"#);
+
+    // the modules are searched in the library paths and the directory
+    // of the query file
+    let output = aq(
+        &[
+            "-c",
+            "-L",
+            library.parent().unwrap().to_str().unwrap(),
+            "-q",
+            query.to_str().unwrap(),
+            data.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.stdout, "\"AB!\"\n\"abab\"\n");
+}
+
+#[test]
+fn test_distinct() {
+    let temp = TempDir::new("distinct");
+    let a = temp.file("a.ason", "[{id: 1}, {id: 2}]");
+    let b = temp.file("b.ason", "[{id: 2}, {id: 3_u8}, {id: 1.0}]");
+    let output = aq(
+        &[
+            "-c",
+            "--distinct",
+            ".[]",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{id:1}\n{id:2}\n{id:3_u8}\n");
+}
+
+#[test]
+fn test_resource_limits() {
+    let output = aq(&["-c", "--max-output", "2", "range(10)"], "0");
+    assert_eq!(output.status, 3);
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        output.stderr,
+        "The number of results exceeds the limit of 2.\n"
+    );
+
+    let output = aq(&["-c", "--max-depth", "20", "def f: f; f"], "0");
+    assert_eq!(output.status, 3);
+
+    let output = aq(&["-c", "--max-output", "2", "range(2)"], "0");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "0\n1\n");
+}
+
+#[test]
+fn test_merge_sorted_inputs() {
+    let temp = TempDir::new("merge");
+    let a = temp.file("a.ason", "[{t: 1}, {t: 5}]");
+    let b = temp.file("b.ason", "[{t: 2}, {t: 3}]");
+    let output = aq(
+        &[
+            "-c",
+            "--merge-sorted-by",
+            ".t",
+            ".t",
+            a.to_str().unwrap(),
+            b.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n2\n3\n5\n");
+
+    // the files of the concatenated documents, e.g. the log files
+    let d = temp.file("d.ason", "{t: 0}\n{t: 4}\n");
+    let output = aq(
+        &[
+            "-c",
+            "--merge-sorted-by",
+            ".t",
+            "{t, n: input.t}",
+            a.to_str().unwrap(),
+            d.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.stdout, "{t:0,n:1}\n{t:4,n:5}\n");
+
+    let c = temp.file("c.ason", "[{t: 4}, {t: 0}]");
+    let output = aq(
+        &[
+            "-c",
+            "--merge-sorted-by",
+            ".t",
+            ".",
+            a.to_str().unwrap(),
+            c.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_ne!(output.status, 0);
+    assert_eq!(output.stdout, "{t:1}\n");
+    assert_eq!(
+        output.stderr,
+        "Fail to merge the sorted inputs.\n\
+        The input 2 is not sorted by the key, the element 1 is out of order.\n"
+    );
+
+    let output = aq(
+        &[
+            "--from",
+            "yaml",
+            "--merge-sorted-by",
+            ".t",
+            ".",
+            a.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(
+        output.stderr,
+        "The input format \"yaml\" is not supported by \"--merge-sorted-by\".\n"
+    );
+}
+
+#[test]
+fn test_compact_output() {
+    let input = "{a: [1, 2], b: \"x\"}";
+    let output = aq(&["-c", "., .a[]"], input);
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:[1,2],b:\"x\"}\n1\n2\n");
+
+    let output = aq(&["--compact-output", ".b"], input);
+    assert_eq!(output.stdout, "\"x\"\n");
+
+    // the pretty printer spreads the containers over multiple lines
+    let output = aq(&["."], input);
+    assert!(output.stdout.lines().count() > 1);
+}
+
+#[test]
+fn test_window_aggregation() {
+    let input = "{t: 1, n: 2}\n{t: 9, n: 3}\n{t: 12, n: 5}\n{t: 3, n: 1}\n{t: 31, n: 7}\n";
+    let output = aq(
+        &[
+            "-c",
+            "--window",
+            "10s",
+            "--by",
+            ".t",
+            "[.start, .count, (.items | map(.n) | add)]",
+        ],
+        input,
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "[0_i64,2,5]\n[10_i64,1,5]\n[30_i64,1,7]\n");
+    assert!(output.stderr.contains("1 value(s) were dropped"));
+}
+
+#[test]
+fn test_raw_output() {
+    let input = "{name: \"a\\\"b\", n: 1, l: [\"x\"]}";
+    let output = aq(&["-r", ".name"], input);
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "a\"b\n");
+
+    // only the strings are written raw
+    let output = aq(&["-r", "-c", ".n, .l"], input);
+    assert_eq!(output.stdout, "1\n[\"x\"]\n");
+
+    let output = aq(&["--raw-output", ".name"], input);
+    assert_eq!(output.stdout, "a\"b\n");
+}
+
+#[test]
+fn test_output_themes() {
+    // a theme forces the colored output
+    let output = aq(&["-c", "--theme", "light", "."], "{a: 1}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{\x1b[34ma\x1b[0m:\x1b[35m1\x1b[0m}\n");
+
+    let output = aq(&["-c", "--theme", "nope", "."], "1");
+    assert_ne!(output.status, 0);
+    assert!(output.stderr.contains("Unknown theme \"nope\""));
+}
+
+#[test]
+fn test_raw_input() {
+    let output = aq(&["-c", "-R", "."], "a b\n{x: 1}\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "\"a b\"\n\"{x: 1}\"\n");
+
+    let output = aq(&["-c", "--raw-input", "-s", "map(length)"], "a b\nc\n");
+    assert_eq!(output.stdout, "[3,1]\n");
+}
+
+#[test]
+fn test_format_date() {
+    let output = aq(&["-r", "format_date(\"%d %B\"; \"fr-FR\")"], "1709251200");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "01 mars\n");
+}
+
+#[test]
+fn test_null_input() {
+    // the STDIN is not read
+    let output = aq(&["-c", "-n", "{a: [range(3)]}, ."], "not ason");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:[0,1,2]}\nOption::None\n");
+
+    let output = aq(&["-c", "--null-input", "1 + 1"], "");
+    assert_eq!(output.stdout, "2\n");
+}
+
+#[test]
+fn test_tolerant_input() {
+    let output = aq(
+        &["-c", "--from-json5", "--tolerant", "length, .[2].a"],
+        "[\n  {\"a\": 1},\n  {\"a\": },\n  {\"a\": 3}\n]",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "3\n3\n");
+    assert!(output
+        .stderr
+        .starts_with("<stdin>:3: skipped a malformed entry:"));
+
+    // the malformed document fails without `--tolerant`
+    let output = aq(&["-c", "--from-json5", "."], "[{\"a\": }]");
+    assert_ne!(output.status, 0);
+}
+
+#[test]
+fn test_slurp() {
+    let temp = TempDir::new("slurp");
+    let a = temp.file("a.ason", "{n: 1}");
+    let b = temp.file("b.ason", "{n: 2}");
+    let files = [a.to_str().unwrap(), b.to_str().unwrap()];
+
+    // the query runs with each document
+    let output = aq(&[&["-c", ".n"], &files[..]].concat(), "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n2\n");
+
+    let output = aq(&[&["-c", "-s", "map(.n) | add"], &files[..]].concat(), "");
+    assert_eq!(output.stdout, "3\n");
+
+    let output = aq(&["-c", "--slurp", "."], "1");
+    assert_eq!(output.stdout, "[1]\n");
+}
+
+#[test]
+fn test_byte_range() {
+    let temp = TempDir::new("byte_range");
+    let file = temp.file("docs.ason", "{id: 1}\n{id: 2}\n{id: 3}\n");
+    let output = aq(
+        &[
+            "-c",
+            "--offset",
+            "8",
+            "--length",
+            "7",
+            ".id",
+            file.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "2\n");
+
+    // the STDIN is read from the offset to the end
+    let output = aq(&["-c", "--offset", "3", "."], "[1][22]");
+    assert_eq!(output.stdout, "[22]\n");
+}
+
+#[test]
+fn test_query_variables() {
+    let output = aq(
+        &[
+            "-c",
+            "-n",
+            "--arg",
+            "name",
+            "[1]",
+            "--argason",
+            "value",
+            "[1]",
+            "[$name, $value]",
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "[\"[1]\",[1]]\n");
+
+    let output = aq(&["-c", "-n", "--argason", "v", "[1,", "$v"], "");
+    assert_ne!(output.status, 0);
+}
+
+#[test]
+fn test_query_set() {
+    let temp = TempDir::new("query_set");
+    let set = temp.file(
+        "metrics.ason",
+        "{total: \"length\", errors: \"map(select(.level == \\\"error\\\")) | length\"}",
+    );
+    let output = aq(
+        &["-c", "--query-set", set.to_str().unwrap()],
+        "[{level: \"info\"}, {level: \"error\"}, {level: \"error\"}]",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{total:3,errors:2}\n");
+}
+
+#[test]
+fn test_positional_args() {
+    let output = aq(&["-c", "-n", "$ARGS.positional", "--args", "a", "b"], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "[\"a\",\"b\"]\n");
+
+    let output = aq(
+        &["-c", "-n", "$ARGS.positional", "--asonargs", "1", "[2]"],
+        "",
+    );
+    assert_eq!(output.stdout, "[1,[2]]\n");
+
+    // the named arguments are the variables defined by `--arg`
+    let output = aq(&["-c", "-n", "--arg", "x", "1", "$ARGS"], "");
+    assert_eq!(output.stdout, "{positional:[],named:{x:\"1\"}}\n");
+}
+
+#[test]
+fn test_exit_status() {
+    let input = "{enabled: true, disabled: false}";
+    assert_eq!(aq(&["-e", ".enabled"], input).status, 0);
+    assert_eq!(aq(&["-e", ".disabled"], input).status, 1);
+    assert_eq!(aq(&["-e", ".missing"], input).status, 1);
+    assert_eq!(
+        aq(&["--exit-status", ".disabled, .enabled"], input).status,
+        0
+    );
+
+    // no output
+    assert_eq!(aq(&["-e", "empty"], input).status, 4);
+
+    // the status is 0 without `-e`
+    assert_eq!(aq(&[".disabled"], input).status, 0);
+}
+
+#[test]
+fn test_line_width() {
+    let output = aq(
+        &["--width", "20", "."],
+        "{a: [1, 2], b: [\"xxxxxxxx\", \"yyyyyyyy\"]}",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "{\n    a: [1, 2]\n    b: [\n        \"xxxxxxxx\"\n        \"yyyyyyyy\"\n    ]\n}"
+    );
+}
+
+#[test]
+fn test_multiline_strings() {
+    let output = aq(&["--multiline-strings", ".s"], "{s: \"a\\nb\"}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "r\"a\nb\"");
+
+    let output = aq(&["-c", "--multiline-strings", ".s"], "{s: \"a\\nb\"}");
+    assert_eq!(output.stdout, "\"a\\nb\"\n");
+}
+
+#[test]
+fn test_in_place() {
+    let temp = TempDir::new("in_place");
+    let file = temp.file("a.ason", "{version: \"1.0\", n: 1}");
+    let output = aq(
+        &["-c", "-i", ".version = \"2.0\"", file.to_str().unwrap()],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "{version:\"2.0\",n:1}"
+    );
+
+    let output = aq(
+        &["-c", "--in-place=.bak", ".n += 1", file.to_str().unwrap()],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "{version:\"2.0\",n:2}"
+    );
+    let mut backup = file.clone().into_os_string();
+    backup.push(".bak");
+    assert_eq!(
+        std::fs::read_to_string(backup).unwrap(),
+        "{version:\"2.0\",n:1}"
+    );
+}
+
+#[test]
+fn test_color_output() {
+    // `-C` colors the output even if it is not a terminal or `NO_COLOR` is set
+    let output = aq(&["-c", "-C", "."], "{a: 1}");
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "\x1b[38;5;250m{\x1b[0m\x1b[38;5;81ma\x1b[0m\x1b[38;5;250m:\x1b[0m\x1b[38;5;141m1\x1b[0m\x1b[38;5;250m}\x1b[0m\n"
+    );
+
+    let output = aq(&["-c", "-M", "."], "{a: 1}");
+    assert_eq!(output.stdout, "{a:1}\n");
+
+    // the output is not colored if it is not a terminal
+    let output = aq(&["-c", "."], "{a: 1}");
+    assert_eq!(output.stdout, "{a:1}\n");
+}
+
+#[test]
+fn test_version_pragma() {
+    let output = aq(&["-c", "#aq-version \"99.0\"\n1"], "0");
+    assert_ne!(output.status, 0);
+    assert!(output
+        .stderr
+        .contains("The query requires the language version 99.0"));
+}
+
+#[test]
+fn test_indentation() {
+    let output = aq(&["--indent", "2", "."], "{a: [1]}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{\n  a: [\n    1\n  ]\n}");
+
+    let output = aq(&["--tab", "."], "{a: [1]}");
+    assert_eq!(output.stdout, "{\n\ta: [\n\t\t1\n\t]\n}");
+
+    // no indentation is the compact layout
+    let output = aq(&["--indent", "0", "."], "{a: [1]}");
+    assert_eq!(output.stdout, "{a:[1]}");
+}
+
+#[test]
+fn test_migrate_query() {
+    let temp = TempDir::new("migrate_query");
+    let file = temp.file("a.aql", "[leaf_paths]\n");
+    let path = file.to_str().unwrap();
+
+    // `--check` reports the changes only
+    let output = aq(&["migrate-query", "--check", path], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{}: line 1: replace the deprecated \"leaf_paths\" with \"paths(scalars)\"\n",
+            path
+        )
+    );
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "[leaf_paths]\n");
+
+    let output = aq(&["migrate-query", path], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        std::fs::read_to_string(&file).unwrap(),
+        "[paths(scalars)]\n"
+    );
+
+    let output = aq(&["migrate-query", "--check", path], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn test_stream() {
+    let output = aq(&["-c", "--stream", "."], "{a: [1, 2]}");
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "[[\"a\",0],1]\n[[\"a\",1],2]\n[[\"a\",1]]\n[[\"a\"]]\n"
+    );
+
+    let output = aq(
+        &[
+            "-c",
+            "-n",
+            "--stream",
+            "fromstream(1 | truncate_stream(inputs))",
+        ],
+        "{a: [1, 2], b: {c: 3}}",
+    );
+    assert_eq!(output.stdout, "[1,2]\n{c:3}\n");
+}
+
+#[test]
+fn test_prefetch() {
+    let temp = TempDir::new("prefetch");
+    let files: Vec<String> = (0..5)
+        .map(|i| {
+            temp.file(&format!("{}.ason", i), format!("{{n: {}}}", i))
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    let files: Vec<&str> = files.iter().map(String::as_str).collect();
+
+    // the results are in the order of the files
+    let output = aq(&[&["-c", "--prefetch", "3", ".n"], &files[..]].concat(), "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "0\n1\n2\n3\n4\n");
+}
+
+#[test]
+fn test_multiple_documents() {
+    // each result is a document
+    let output = aq(&["1, [2]"], "0");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n[\n    2\n]");
+
+    let output = aq(&["--separator", ";", "1, 2"], "0");
+    assert_eq!(output.stdout, "1;2");
+
+    let output = aq(&["--wrap-tuple", "1, 2"], "0");
+    assert_eq!(output.stdout, "(1, 2)");
+
+    let output = aq(&["-c", "--wrap-tuple", "1, 2"], "0");
+    assert_eq!(output.stdout, "(1,2)\n");
+
+    let output = aq(&["-c", "--wrap-tuple", "1"], "0");
+    assert_eq!(output.stdout, "1\n");
+}
+
+#[test]
+fn test_tabular_output() {
+    let input = "[{id: 1, name: \"a\"}, {id: 2, tags: [\"x\"], n: Option::None}]";
+    let output = aq(&["--to-csv", "."], input);
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "id,name,tags,n\n1,a,,\n2,,\"[\"\"x\"\"]\",\n"
+    );
+
+    let output = aq(
+        &[
+            "--to-csv",
+            "--null-as",
+            "null",
+            "--missing-as",
+            "null",
+            "--nested-as",
+            "empty",
+            ".",
+        ],
+        input,
+    );
+    assert_eq!(
+        output.stdout,
+        "id,name,tags,n\n1,a,null,null\n2,null,,null\n"
+    );
+
+    let output = aq(&["--table", "."], input);
+    assert_eq!(
+        output.stdout,
+        "id  name  tags   n\n1   a\n2         [\"x\"]\n"
+    );
+
+    let output = aq(&["--to-csv", "--null-as", "none", "."], input);
+    assert_ne!(output.status, 0);
+    assert!(output.stderr.contains("Invalid cell style \"none\""));
+}
+
+#[test]
+fn test_result_cache() {
+    let temp = TempDir::new("result-cache");
+    let dir = temp.0.join("cache");
+    let dir_text = dir.to_str().unwrap();
+    let envs = [("AQ_CACHE_DIR", dir_text)];
+
+    let output = aq_with_env(&["-c", "--cache", ".n"], "{n: 1}", &envs);
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n");
+
+    // replace the stored output to check that it is reused
+    let entries: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(entries.len(), 1);
+    std::fs::write(&entries[0], b"AQC1\x00\x00\x00\x00\x00cached\n").unwrap();
+
+    let output = aq_with_env(&["-c", "--cache", ".n"], "{n: 1}", &envs);
+    assert_eq!(output.stdout, "cached\n");
+
+    // a different input is another entry
+    let output = aq_with_env(&["-c", "--cache", ".n"], "{n: 2}", &envs);
+    assert_eq!(output.stdout, "2\n");
+
+    // the content of the variable files, the modules and the
+    // environment variables are parts of the key as well
+    let rawfile = temp.file("raw.txt", "a");
+    let rawfile_args = [
+        "-c",
+        "--cache",
+        "--rawfile",
+        "r",
+        rawfile.to_str().unwrap(),
+        "$r",
+    ];
+    assert_eq!(aq_with_env(&rawfile_args, "1", &envs).stdout, "\"a\"\n");
+    std::fs::write(&rawfile, "b").unwrap();
+    assert_eq!(aq_with_env(&rawfile_args, "1", &envs).stdout, "\"b\"\n");
+
+    let module = temp.file("m.aql", "def f: 1;");
+    let module_dir = module.parent().unwrap().to_str().unwrap();
+    let module_args = ["-c", "--cache", "-L", module_dir, "import \"m\" as m; m::f"];
+    assert_eq!(aq_with_env(&module_args, "1", &envs).stdout, "1\n");
+    std::fs::write(&module, "def f: 2;").unwrap();
+    assert_eq!(aq_with_env(&module_args, "1", &envs).stdout, "2\n");
+
+    let env_args = ["-c", "--cache", "$ENV.AQ_TEST_VALUE"];
+    let output = aq_with_env(&env_args, "1", &[envs[0], ("AQ_TEST_VALUE", "x")]);
+    assert_eq!(output.stdout, "\"x\"\n");
+    let output = aq_with_env(&env_args, "1", &[envs[0], ("AQ_TEST_VALUE", "y")]);
+    assert_eq!(output.stdout, "\"y\"\n");
+
+    // the output larger than the size limit is not stored
+    let count = std::fs::read_dir(&dir).unwrap().count();
+    let output = aq_with_env(
+        &["-c", "--cache", ".n"],
+        "{n: 3}",
+        &[envs[0], ("AQ_CACHE_SIZE", "8")],
+    );
+    assert_eq!(output.stdout, "3\n");
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), count);
+}
+
+#[test]
+fn test_embed() {
+    let temp = TempDir::new("embed");
+    temp.file("certs/a.pem", "PEM");
+    let document = temp.file("bundle.ason", "{cert: {file: \"certs/a.pem\"}}");
+    let output = aq(&["embed", document.to_str().unwrap()], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{\n    cert: \"PEM\"\n}");
+
+    let document = temp.file("missing.ason", "{cert: {file: \"b.pem\"}}");
+    let output = aq(&["embed", document.to_str().unwrap()], "");
+    assert_eq!(output.status, 1);
+    assert!(output
+        .stderr
+        .starts_with("Fail to read the file \"b.pem\" of the placeholder at \"/cert\""));
+}
+
+#[test]
+fn test_extract() {
+    let temp = TempDir::new("extract");
+    let document = temp.file("doc.ason", "{key: \"0123456789\", name: \"x\"}");
+    let output = aq(
+        &[
+            "extract",
+            "--min-size",
+            "8",
+            "--dir",
+            "blobs",
+            document.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "{\n    key: {\n        file: \"blobs/key.txt\"\n    }\n    name: \"x\"\n}"
+    );
+    let blob = document.with_file_name("blobs").join("key.txt");
+    assert_eq!(std::fs::read_to_string(blob).unwrap(), "0123456789");
+}
+
+#[test]
+fn test_json_bridge() {
+    let output = aq(
+        &["--from", "json", "-c", "."],
+        r#"{"a": {"$u8": 3}, "b": null}"#,
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:3_u8,b:Option::None}\n");
+
+    let output = aq(&["--to", "json", "-c", "."], "[1_u8, (1, 'c')]");
+    assert_eq!(
+        output.stdout,
+        "[{\"$u8\":1},{\"$tuple\":[1,{\"$char\":\"c\"}]}]\n"
+    );
+
+    // the output reads back
+    let json = aq(&["--to", "json", "."], "{a: [1_u8, Color::Red]}").stdout;
+    let output = aq(&["--from", "json", "-c", "."], &json);
+    assert_eq!(output.stdout, "{a:[1_u8,Color::Red]}\n");
+
+    let output = aq(&["-r", "tojson"], "[1_u8]");
+    assert_eq!(output.stdout, "[{\"$u8\":1}]\n");
+}
+
+#[test]
+fn test_lines_throttle() {
+    let output = aq(&["--lines", "--sample-rate", "1", "-c", "."], "1\n2\n3\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n2\n3\n");
+
+    let output = aq(&["--lines", "--rate-limit", "1/h", "-c", "."], "1\n2\n3\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(
+        output.stderr,
+        "2 result(s) were dropped by the rate limit.\n"
+    );
+
+    let output = aq(&["--lines", "--sample-rate", "2", "."], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Invalid sample rate \"2\", expect a number between 0 and 1, e.g. \"0.01\".\n"
+    );
+}
+
+#[test]
+fn test_yaml() {
+    let output = aq(&["--from", "yaml", "-c", "."], "a: 1\n---\nb: [x, ~]\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:1}\n{b:[\"x\",Option::None]}\n");
+
+    let output = aq(&["--to", "yaml", "."], "{a: [1, \"x\"], b: Option::None}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "a:\n  - 1\n  - x\nb: null\n");
+
+    let output = aq(&["--from", "yaml", "."], "a: *m\n");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Fail to parse the input YAML text.\nUnknown alias \"m\" of YAML. (at line 1)\n"
+    );
+}
+
+#[test]
+fn test_first_match() {
+    let output = aq(&["--first-match", ".[] | select(. > 1)"], "[1, 2, 3]");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "2\n");
+
+    // the rest of the input is not read
+    let output = aq(&["--lines", "--first-match", "."], "1\n[bad\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n");
+
+    let output = aq(&["--lines", "--first-match", "select(. > 5)"], "1\n2\n");
+    assert_eq!(output.status, 4);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn test_toml() {
+    let output = aq(
+        &["--from", "toml", "-c", ".p"],
+        "[p]\nname = \"x\"\nv = [1, 2]\n",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{name:\"x\",v:[1,2]}\n");
+
+    let output = aq(&["--to", "toml", "."], "{a: 1, t: {x: \"y\"}}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "a = 1\n\n[t]\nx = \"y\"\n");
+
+    let output = aq(&["--to", "toml", "."], "[1]");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "The TOML document must be an object, found list.\n"
+    );
+}
+
+#[test]
+fn test_from_csv() {
+    let output = aq(
+        &["--from", "csv", "-c", "select(.n > 1)"],
+        "n,name\n1,a\n2,\"b, c\"\n",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{n:2,name:\"b, c\"}\n");
+
+    let output = aq(&["--from", "tsv", "--no-header", "-c", "."], "a\tb\n1\tx\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "[\"a\",\"b\"]\n[1,\"x\"]\n");
+}
+
+#[test]
+fn test_from_binary() {
+    let temp = TempDir::new("from-binary");
+    let data = temp.file("data.msgpack", [0x81, 0xa1, b'a', 0x92, 0x01, 0xc3]);
+    let output = aq(
+        &["--from", "msgpack", "-c", ".", data.to_str().unwrap()],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:[1,true]}\n");
+
+    let data = temp.file("data.cbor", [0xa1, 0x61, b'a', 0x82, 0x01, 0xf5]);
+    let output = aq(&["--from", "cbor", "-c", ".", data.to_str().unwrap()], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:[1,true]}\n");
+
+    let data = temp.file("bad.msgpack", [0x92, 0x01]);
+    let output = aq(&["--from", "msgpack", ".", data.to_str().unwrap()], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Fail to decode the input MessagePack data.\nUnexpected end of the MessagePack data (at byte offset 2).\n"
+    );
+}
+
+#[test]
+fn test_ndjson() {
+    let output = aq(
+        &["--from", "ndjson", "-c", ".a"],
+        "{\"a\": 1}\n\n{\"a\": [2]}\n",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n[2]\n");
+
+    let output = aq(&["--to", "ndjson", ".[]"], "[{a: 1}, 2_u8]");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{\"a\":1}\n{\"$u8\":2}\n");
+
+    let output = aq(
+        &["--lines", "--blocks", "-c", "."],
+        "{\n    a: 1\n}\n---\n{b: 2}\n",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:1}\n{b:2}\n");
+}
+
+#[test]
+fn test_xml() {
+    let output = aq(&["--from", "xml", "-c", "."], "<a x='1'><b>t</b></a>");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{a:{\"@x\":\"1\",b:\"t\"}}\n");
+
+    let output = aq(&["--to", "xml", "."], "{a: {b: 1}}");
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<a>\n  <b>1</b>\n</a>\n"
+    );
+
+    let output = aq(&["--from", "xml", "."], "<a>\n");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Fail to parse the input XML text.\nUnclosed element \"a\" of XML (at line 2)\n"
+    );
+}
+
+#[test]
+fn test_sort_keys() {
+    let output = aq(&["-S", "."], "{b: 1, a: {d: 2, c: 3}}");
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "{\n    a: {\n        c: 3\n        d: 2\n    }\n    b: 1\n}"
+    );
+
+    let output = aq(&["--sort-keys", "-c", "."], "{b: 1, a: 2}");
+    assert_eq!(output.stdout, "{a:2,b:1}\n");
+}
+
+#[test]
+fn test_ascii_output() {
+    let output = aq(&["-a", "-c", "."], "{k: \"café 😀\"}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{k:\"caf\\u{e9} \\u{1f600}\"}\n");
+
+    let output = aq(
+        &["--ascii-output", "--to", "json", "-c", "."],
+        "{k: \"café 😀\"}",
+    );
+    assert_eq!(output.stdout, "{\"k\":\"caf\\u00e9 \\ud83d\\ude00\"}\n");
+}
+
+#[test]
+fn test_seq() {
+    // the malformed record is skipped
+    let output = aq(&["--seq", "-c", ".a"], "\x1e{a: 1}\n\x1e{a: \n\x1e{a: 3}\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "\x1e1\n\x1e3\n");
+    assert!(output.stderr.starts_with("Skip the malformed record 2.\n"));
+
+    let output = aq(&["--seq", ".[]"], "[1, {a: 2}]");
+    assert_eq!(output.stdout, "\x1e1\n\x1e{\n    a: 2\n}\n");
+
+    let output = aq(&["--seq", "--lines", "-c", ". + 1"], "\x1e1\n\x1e2\n");
+    assert_eq!(output.stdout, "\x1e2\n\x1e3\n");
+
+    let output = aq(&["--seq", "--to", "yaml", "."], "1");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "The output format \"yaml\" is not supported by \"--seq\".\n"
+    );
+}
+
+#[test]
+fn test_query_from_stdin() {
+    let temp = TempDir::new("query-stdin");
+    let data = temp.file("data.ason", "{a: [1, 2]}");
+    let output = aq(
+        &["-q", "-", "-c", data.to_str().unwrap()],
+        ".a | map(. * 10)",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "[10,20]\n");
+
+    let output = aq(&["-q", "-", "-n", "-c"], "[1] | length");
+    assert_eq!(output.stdout, "1\n");
+
+    let output = aq(&["-q", "-"], ".");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "The input files are required when the query is read from the STDIN.\n"
+    );
+}
+
+#[test]
+fn test_file_variables() {
+    let temp = TempDir::new("file-variables");
+    let object = temp.file("object.ason", "{k: 1}\n");
+    let lines = temp.file("lines.ndjson", "{\"k\": 1}\n{\"k\": 2}\n");
+    let object = object.to_str().unwrap();
+    let output = aq(
+        &[
+            "-n",
+            "-c",
+            "--rawfile",
+            "r",
+            object,
+            "--argfile",
+            "a",
+            object,
+            "--slurpfile",
+            "o",
+            object,
+            "--slurpfile",
+            "s",
+            lines.to_str().unwrap(),
+            "[$r, $a, $o, $s]",
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "[\"{k: 1}\\n\",{k:1},[{k:1}],[{k:1},{k:2}]]\n"
+    );
+
+    let missing = temp.file("x", "").with_file_name("missing.ason");
+    let output = aq(
+        &["-n", "--argfile", "a", missing.to_str().unwrap(), "$a"],
+        "",
+    );
+    assert_eq!(output.status, 1);
+    assert!(output.stderr.starts_with(&format!(
+        "Fail to load the variable \"$a\" from the file: \"{}\".\nFail to read the file: ",
+        missing.display()
+    )));
+}
+
+#[test]
+fn test_split_files() {
+    let temp = TempDir::new("split-files");
+    let dir = temp.file("input.ason", "").with_file_name("out");
+    let template = format!("{}/{{.id}}.ason", dir.display());
+    let output = aq(
+        &["--output-template", &template, ".[]"],
+        "[{id: 1}, {id: 2}]",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+    assert_eq!(
+        std::fs::read_to_string(dir.join("1.ason")).unwrap(),
+        "{\n    id: 1\n}"
+    );
+    assert!(dir.join("2.ason").exists());
+
+    let output = aq(
+        &[
+            "--split-output",
+            "-r",
+            "--arg",
+            "dir",
+            dir.to_str().unwrap(),
+            "{filename: ($dir + \"/x.txt\"), content: .}",
+        ],
+        "\"hi\"",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(std::fs::read_to_string(dir.join("x.txt")).unwrap(), "hi");
+}
+
+#[test]
+fn test_browse_without_terminal() {
+    let temp = TempDir::new("browse");
+    let document = temp.file("doc.ason", "{a: 1}");
+    let output = aq(&["browse", document.to_str().unwrap()], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "The browser requires the STDOUT to be a terminal.\n"
+    );
+}
+
+#[test]
+fn test_explain() {
+    let output = aq(&["explain", ".a[] | $n"], "");
+    assert_eq!(output.status, 0);
+    assert!(output
+        .stdout
+        .starts_with("Stages:\n  1. .a[]\n  2. $n\n\nTree:\npipe |\n"));
+    assert!(output.stdout.ends_with("\nFree variables:\n  $n\n"));
+
+    let output = aq(&["explain", ".a |"], "");
+    assert_eq!(output.status, 1);
+    assert!(output
+        .stderr
+        .starts_with("Unexpected end of query expression: Expect more tokens.\n"));
+}
+
+#[test]
+fn test_follow() {
+    let temp = TempDir::new("follow");
+    let log = temp.file("app.log", "{a: 1}\n");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_aq"))
+        .args(["--follow", "-c", ".a", log.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    assert_eq!(lines.next().unwrap().unwrap(), "1");
+
+    // the appended document is evaluated
+    let mut file = std::fs::OpenOptions::new().append(true).open(&log).unwrap();
+    file.write_all(b"{a: 2}\n").unwrap();
+    assert_eq!(lines.next().unwrap().unwrap(), "2");
+
+    child.kill().unwrap();
+    child.wait().unwrap();
+}
+
+#[test]
+fn test_url_input() {
+    // nothing listens on the port 1
+    let output = aq(
+        &["--http-timeout", "2", ".", "http://127.0.0.1:1/a.ason"],
+        "",
+    );
+    assert_eq!(output.status, 1);
+    assert!(output
+        .stderr
+        .starts_with("Fail to fetch \"http://127.0.0.1:1/a.ason\": "));
+
+    let output = aq(
+        &["--header", "novalue", ".", "http://127.0.0.1:1/a.ason"],
+        "",
+    );
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "The header \"novalue\" expects the form \"NAME: VALUE\".\n"
+    );
+}
+
+#[test]
+fn test_compression() {
+    let temp = TempDir::new("compression");
+    for name in ["gzip", "zstd"] {
+        let output = aq(&["--compress-output", name, "-c", "."], "{a: [1, 2]}");
+        assert_eq!(output.status, 0);
+        assert_ne!(output.bytes, b"{a:[1,2]}\n");
+
+        // the input is decompressed by the magic bytes
+        let data = temp.file(&format!("data.{}", name), &output.bytes);
+        let output = aq(&["-c", ".a", data.to_str().unwrap()], "");
+        assert_eq!(output.status, 0);
+        assert_eq!(output.stdout, "[1,2]\n");
+    }
+
+    // by the extension of the output file
+    let path = temp.file("out.ason.gz", "");
+    let output = aq(&["-c", "-o", path.to_str().unwrap(), "."], "1");
+    assert_eq!(output.status, 0);
+    assert!(std::fs::read(&path).unwrap().starts_with(&[0x1f, 0x8b]));
+    assert_eq!(aq(&["-c", ".", path.to_str().unwrap()], "").stdout, "1\n");
+
+    let output = aq(&["--compress-output", "bz2", "."], "1");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Compression \"bz2\" is not supported, the available ones are: gzip and zstd.\n"
+    );
+}
+
+#[test]
+fn test_raw_byte_data() {
+    let temp = TempDir::new("binary-output");
+    let output = aq(&["--binary-output", ".a[]"], "{a: [h\"00 ff\", h\"0a\"]}");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.bytes, [0x00, 0xff, 0x0a]);
+
+    let path = temp.file("cert.der", "");
+    let output = aq(
+        &["--binary-output", "-o", path.to_str().unwrap(), ".c"],
+        "{c: h\"01 02\"}",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(std::fs::read(&path).unwrap(), [1, 2]);
+
+    let output = aq(&["--binary-output", ".[]"], "[h\"01\", 2]");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "The results of \"--binary-output\" expect byte data, found number.\n"
+    );
+}
+
+#[test]
+fn test_check() {
+    let temp = TempDir::new("check");
+    let valid = temp.file("ok.json", "{\"a\": 1}");
+    let invalid = temp.file("bad.json", "[1,\n oops]");
+    let (valid, invalid) = (valid.to_str().unwrap(), invalid.to_str().unwrap());
+
+    let output = aq(&["check", valid], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "");
+
+    // all files are checked
+    let output = aq(&["check", invalid, valid], "");
+    assert_eq!(output.status, 1);
+    assert!(output.stderr.starts_with(&format!("{}: line 2: ", invalid)));
+
+    let output = aq(&["check", "--report", "ason", valid, invalid], "");
+    assert_eq!(output.status, 1);
+    assert!(output
+        .stdout
+        .starts_with("{\n    files: 2\n    valid: 1\n    invalid: 1\n"));
+}
+
+#[test]
+fn test_fmt() {
+    let temp = TempDir::new("fmt");
+    let output = aq(
+        &[
+            "fmt",
+            "--indent",
+            "2",
+            "--trailing-commas",
+            "--number-style",
+            "grouped",
+        ],
+        "{a: [1, 2], n: 1234567}",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        "{\n  a: [\n    1,\n    2,\n  ],\n  n: 1_234_567,\n}\n"
+    );
+
+    // the formatted files are not listed
+    let formatted = temp.file("formatted.ason", "{\n    a: 1\n}\n");
+    let unformatted = temp.file("unformatted.ason", "{a: 1}");
+    let files = [formatted.to_str().unwrap(), unformatted.to_str().unwrap()];
+    let output = aq(&["fmt", "--check", files[0], files[1]], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(output.stdout, format!("{}\n", files[1]));
+
+    let output = aq(&["fmt", "-w", files[1]], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        std::fs::read_to_string(&unformatted).unwrap(),
+        "{\n    a: 1\n}\n"
+    );
+
+    let output = aq(&["fmt"], "// the comment\n{a: 1}");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "<stdin>: The document can not be formatted without loss: comment dropped at line 1.\n"
+    );
+}
+
+#[test]
+fn test_preserve() {
+    let temp = TempDir::new("preserve");
+    let config = temp.file(
+        "config.ason",
+        "// the config\n{\n  port: 80 // the port\n}\n",
+    );
+    let output = aq(
+        &["-i", "--preserve", ".port = 81", config.to_str().unwrap()],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        std::fs::read_to_string(&config).unwrap(),
+        "// the config\n{\n  port: 81 // the port\n}\n"
+    );
+}
+
+#[test]
+fn test_preserve_order() {
+    // the added entries follow the existing ones
+    let output = aq(
+        &["--preserve-order", "-c", ". + {c: 3, a: 4}"],
+        "{b: 1, a: 2}",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{b:1,a:4,c:3}\n");
+
+    // the last one of the flags takes effect
+    let output = aq(&["-S", "--preserve-order", "-c", "."], "{b: 1, a: 2}");
+    assert_eq!(output.stdout, "{b:1,a:2}\n");
+
+    let output = aq(&["--to", "json", "-c", "."], "{b: 1, a: 2}");
+    assert_eq!(output.stdout, "{\"b\":1,\"a\":2}\n");
+}
+
+#[test]
+fn test_compile_cache() {
+    let temp = TempDir::new("compile_cache");
+    let dir = temp.0.join("cache");
+    let dir_text = dir.to_str().unwrap();
+    let query = temp.file("query.aql", "import \"m\" as m; m::inc");
+    let module = temp.file("m.aql", "def inc: . + 1;");
+    let args = [
+        "-c",
+        "--compile-cache",
+        dir_text,
+        "-q",
+        query.to_str().unwrap(),
+    ];
+
+    let output = aq(&args, "1");
+    assert_eq!(output.stderr, "");
+    assert_eq!(output.stdout, "2\n");
+    let entries: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .collect();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].extension().unwrap(), "aqp");
+
+    // the compiled query is reused
+    let output = aq(&args, "1");
+    assert_eq!(output.stdout, "2\n");
+
+    // the changed module is compiled again
+    std::fs::write(&module, "def inc: . + 2;").unwrap();
+    let output = aq(&args, "1");
+    assert_eq!(output.stdout, "3\n");
+
+    // the damaged entry is ignored
+    std::fs::write(&entries[0], b"AQP1").unwrap();
+    let output = aq(&args, "1");
+    assert_eq!(output.stdout, "3\n");
+}
+
+#[test]
+fn test_incremental() {
+    let output = aq(
+        &["-c", "--incremental", ".id"],
+        "{id: 1, name: \"foo\"}\n{\n    id: 2\n    name: \"bar\"\n}\n",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n2\n");
+
+    let output = aq(
+        &["-c", "--incremental", "--items", ".id"],
+        "[\n    {id: 1},\n    {id: 2}\n]\n",
+    );
+    assert_eq!(output.stdout, "1\n2\n");
+}
+
+#[test]
+fn test_jobs() {
+    let temp = TempDir::new("jobs");
+    let files: Vec<PathBuf> = (0..8)
+        .map(|i| temp.file(&format!("{}.ason", i), format!("{{n: {}}}", i)))
+        .collect();
+    let mut args = vec!["-c", "--jobs", "3", ".n"];
+    args.extend(files.iter().map(|f| f.to_str().unwrap()));
+    let output = aq(&args, "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "0\n1\n2\n3\n4\n5\n6\n7\n");
+
+    // `--jobs 0` is the available parallelism
+    args[2] = "0";
+    let output = aq(&args, "");
+    assert_eq!(output.stdout, "0\n1\n2\n3\n4\n5\n6\n7\n");
+
+    let broken = temp.file("broken.ason", "{n: ");
+    let output = aq(
+        &[
+            "-c",
+            "--jobs",
+            "2",
+            ".n",
+            files[0].to_str().unwrap(),
+            broken.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.stdout, "0\n");
+    assert_ne!(output.status, 0);
+}
+
+#[test]
+fn test_mmap() {
+    let temp = TempDir::new("mmap");
+    let data = temp.file("data.ason", "{name: \"foo\"}");
+    let data = data.to_str().unwrap();
+    let output = aq(&["--mmap", ".name", data], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "\"foo\"");
+
+    let output = aq(&["--no-mmap", ".name", data], "");
+    assert_eq!(output.stdout, "\"foo\"");
+}
+
+#[test]
+fn test_query_diagnostic() {
+    let output = aq(&[".a | .b)"], "{}");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Fail to parse the query expression.
+Unexpected \")\".
+ --> line 1, column 8
+  |
+1 | .a | .b)
+  |        ^
+  = hint: the \")\" has no opening bracket.
+"
+    );
+}
+
+#[test]
+fn test_error_paths() {
+    let temp = TempDir::new("error_paths");
+    let data = temp.file("users.ason", "{users: [{name: \"a\"}, 5]}");
+    let output = aq(&[".users[] | .name", data.to_str().unwrap()], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        format!(
+            "Cannot index number with string (at the path .users[1].name of the input, \
+            in the query stage \".name\").\nThe input document is from the file \"{}\".\n",
+            data.to_str().unwrap()
+        )
+    );
+}
+
+#[test]
+fn test_lint() {
+    let temp = TempDir::new("lint");
+    temp.file("m.aql", "def inc: . + 1;");
+    let query = temp.file("query.aql", "import \"m\" as m;\nm::inc | m::dec | $x");
+    let query = query.to_str().unwrap();
+    let output = aq(&["lint", query], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{0}: line 2: the function \"m::dec/0\" is not defined\n\
+            {0}: line 2: the variable \"$x\" is not defined\n",
+            query
+        )
+    );
+
+    let output = aq(&["lint", "--variable", "x", query], "");
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{}: line 2: the function \"m::dec/0\" is not defined\n",
+            query
+        )
+    );
+
+    let module = temp.file("m.aql", "def inc: . + 1;");
+    let output = aq(&["lint", module.to_str().unwrap()], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+}
+
+#[test]
+fn test_validate() {
+    let temp = TempDir::new("validate");
+    let schema = temp.file(
+        "schema.ason",
+        "{type: \"object\", fields: {age: \"u8\"}, required: [\"name\"]}",
+    );
+    let good = temp.file("good.ason", "{name: \"foo\", age: 42}");
+    let bad = temp.file("bad.ason", "{age: 300}");
+    let (schema, good, bad) = (
+        schema.to_str().unwrap(),
+        good.to_str().unwrap(),
+        bad.to_str().unwrap(),
+    );
+
+    let output = aq(&["validate", "--schema", schema, good], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+
+    let output = aq(&["validate", "--schema", schema, good, bad], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{0}: .: missing key \"name\"\n\
+            {0}: .age: expect an integer of type u8, found 300\n",
+            bad
+        )
+    );
+
+    // the violations are written to the STDERR with the report
+    let output = aq(
+        &["validate", "--schema", schema, "--report", "json", bad],
+        "",
+    );
+    assert_eq!(output.status, 1);
+    assert!(output
+        .stdout
+        .starts_with("{\n    \"files\": 1,\n    \"valid\": 0,\n"));
+    assert!(output.stderr.contains(".age: expect an integer of type u8"));
+
+    let missing = format!("{}.missing", good);
+    let output = aq(&["validate", "--schema", schema, &missing], "");
+    assert_eq!(output.status, 2);
+}
+
+#[test]
+fn test_diff() {
+    let temp = TempDir::new("diff");
+    let old = temp.file("old.ason", "{server: {port: 8080, debug: true}}");
+    let new = temp.file("new.json", r#"{"server": {"port": 8081, "workers": 4}}"#);
+    let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+    let output = aq(&["diff", old, new], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stdout,
+        "~ .server.port: 8080 => 8081\n- .server.debug: true\n+ .server.workers: 4\n"
+    );
+
+    let output = aq(&["diff", "--format", "patch", old, new], "");
+    assert_eq!(output.status, 1);
+    assert!(output
+        .stdout
+        .starts_with("[\n    {\n        op: \"replace\"\n"));
+
+    let output = aq(&["diff", old, old], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+
+    let output = aq(&["diff", "--format", "x", old, new], "");
+    assert_eq!(output.status, 2);
+    assert_eq!(
+        output.stderr,
+        "The diff format \"x\" is not supported, the available ones are: text and patch.\n"
+    );
+}
+
+#[test]
+fn test_patch() {
+    let temp = TempDir::new("patch");
+    let old = temp.file("config.ason", "{server: {port: 8080, debug: true}}");
+    let new = temp.file("new.ason", "{server: {port: 8081, workers: 4}}");
+    let (old, new) = (old.to_str().unwrap(), new.to_str().unwrap());
+
+    // the patch of `aq diff` is applied by `aq patch`
+    let output = aq(&["diff", "--format", "patch", old, new], "");
+    let patch = temp.file("changes.ason", output.stdout);
+    let patched = temp.file("patched.ason", "");
+    let output = aq(
+        &[
+            "patch",
+            old,
+            patch.to_str().unwrap(),
+            "-o",
+            patched.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    let output = aq(&["diff", patched.to_str().unwrap(), new], "");
+    assert_eq!(output.status, 0);
+
+    // the patch is not applied to the changed document
+    let output = aq(&["patch", new, patch.to_str().unwrap()], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "Fail to apply the operation 0 of the patch: \
+        the value at .server.port is 8081, but the patch expects 8080.\n"
+    );
+}
+
+#[test]
+fn test_trace() {
+    let output = aq(
+        &[
+            "-c",
+            "--trace",
+            "def f: .name; .users[] | select(.age > 30) | f",
+        ],
+        "{users: [{name: \"alice\", age: 25}, {name: \"bob\", age: 42}]}",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "\"bob\"\n");
+    assert_eq!(
+        output.stderr,
+        "[trace] stage 1 \".users[]\": {name:\"alice\",age:25}
+[trace] stage 2 \"select(.age > 30)\": nothing for {name:\"alice\",age:25}
+[trace] stage 1 \".users[]\": {name:\"bob\",age:42}
+[trace] stage 2 \"select(.age > 30)\": {name:\"bob\",age:42}
+[trace] stage 3 \"f\": \"bob\"
+"
+    );
+}
+
+#[test]
+fn test_lsp() {
+    let frame = |content: &str| format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+    let messages = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+        r#"{"jsonrpc":"2.0","method":"exit"}"#,
+    ];
+    let stdin: String = messages.iter().map(|content| frame(content)).collect();
+
+    let output = aq(&["lsp"], &stdin);
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout.matches("Content-Length: ").count(), 2);
+    assert!(output.stdout.contains(r#""hoverProvider":true"#));
+
+    // exit without shutdown
+    let output = aq(&["lsp"], &frame(messages[2]));
+    assert_eq!(output.status, 1);
+}
+
+#[test]
+fn test_query_test() {
+    let temp = TempDir::new("query-test");
+    temp.file(
+        "lib/users.aql",
+        "def adults: .users[] | select(.age >= 18);",
+    );
+    let file = temp.file(
+        "tests.aql",
+        r#"# the names of the adults
+import "lib/users" as users; users::adults | .name
+{users: [{name: "alice", age: 25}, {name: "bob", age: 16}]}
+"alice"
+
+.a
+{a: 1}
+2
+
+%%FAIL
+.a
+[1]
+Cannot index
+"#,
+    );
+    let file = file.to_str().unwrap();
+
+    let output = aq(&["test", file], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "FAIL {}: line 6: .a
+    The result 1 is different from the expected:
+        ~ .: 2 => 1
+2 passed, 1 failed.
+",
+            file
+        )
+    );
+
+    let output = aq(&["test", "missing.aql"], "");
+    assert_eq!(output.status, 2);
+    assert!(output
+        .stderr
+        .starts_with("Fail to read the specified test file: \"missing.aql\"."));
+}
+
+#[test]
+fn test_halt() {
+    // the results before `halt` are written
+    let output = aq(&["-c", "1, halt, 2"], "0");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(output.stderr, "");
+
+    let output = aq(&["-c", "halt_error"], "\"port is missing\\n\"");
+    assert_eq!(output.status, 5);
+    assert_eq!(output.stdout, "");
+    assert_eq!(output.stderr, "port is missing\n");
+
+    let output = aq(
+        &["-c", ".[] | if . > 1 then halt_error(3) end"],
+        "[1, {a: 2}]",
+    );
+    assert_eq!(output.status, 3);
+    assert_eq!(output.stdout, "1\n");
+    assert_eq!(output.stderr, "{a:2}\n");
+}
+
+#[test]
+fn test_input_metadata() {
+    let temp = TempDir::new("input-metadata");
+    let a = temp.file("a.ason", "{id: 1}\n");
+    let b = temp.file("b.ason", "{id: 22}\n");
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let output = aq(
+        &[
+            "-c",
+            "$__input__ | {filename, index, size, mtime: (.mtime | type)}",
+            a,
+            b,
+        ],
+        "",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        output.stdout,
+        format!(
+            "{{filename:\"{}\",index:0,size:8,mtime:\"datetime\"}}\n\
+            {{filename:\"{}\",index:1,size:9,mtime:\"datetime\"}}\n",
+            a, b
+        )
+    );
+
+    let output = aq(&["-c", "$__input__ | {filename, size, mtime}"], "{id: 1}");
+    assert_eq!(
+        output.stdout,
+        "{filename:Option::None,size:7,mtime:Option::None}\n"
+    );
+}
+
+#[test]
+fn test_group_by_file() {
+    let temp = TempDir::new("group-by-file");
+    let a = temp.file("a.ason", "{id: 1}");
+    let b = temp.file("b.ason", "{id: 2}");
+    let (a, b) = (a.to_str().unwrap(), b.to_str().unwrap());
+
+    let output = aq(&["-c", "--group-by-file", "[.[] | .id]", a, b], "");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "[1,2]\n");
+
+    let output = aq(&["-c", "--group-by-file", "keys", a, b], "");
+    assert_eq!(output.stdout, format!("[\"{}\",\"{}\"]\n", a, b));
+}
+
+#[test]
+fn test_streaming_output_options() {
+    let temp = TempDir::new("streaming-output-options");
+    // the results are written to the output file one by one
+    let output_file = temp.file("out.ason", "");
+    let out = output_file.to_str().unwrap();
+    let output = aq(&["-c", "--lines", "-o", out, ".a"], "{a: 1}\n{a: 2}\n");
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "");
+    assert_eq!(std::fs::read_to_string(&output_file).unwrap(), "1\n2\n");
+
+    let output = aq(
+        &["-c", "--lines", "--distinct", ".a"],
+        "{a: 1}\n{a: 1}\n{a: 2}\n",
+    );
+    assert_eq!(output.stdout, "1\n2\n");
+
+    // the status is of the last result
+    let output = aq(
+        &["--lines", "--exit-status", ".a"],
+        "{a: true}\n{a: false}\n",
+    );
+    assert_eq!(output.status, 1);
+    let output = aq(
+        &["--incremental", "--exit-status", ".a"],
+        "{a: false}\n{a: true}\n",
+    );
+    assert_eq!(output.status, 0);
+    let output = aq(&["--lines", "--exit-status", "empty"], "1\n");
+    assert_eq!(output.status, 4);
+
+    let output = aq(&["-c", "--lines", "--max-output", "2", "range(3)"], "1\n");
+    assert_ne!(output.status, 0);
+
+    // the window results are written in the line format
+    let output = aq(
+        &[
+            "--to",
+            "json",
+            "--window",
+            "10s",
+            "--by",
+            ".t",
+            "{n: .count}",
+        ],
+        "{t: 1}\n{t: 12}\n",
+    );
+    assert_eq!(output.status, 0);
+    assert_eq!(output.stdout, "{\"n\":1}\n{\"n\":1}\n");
+
+    let files: Vec<String> = (1..=3)
+        .map(|n| {
+            let file = temp.file(&format!("{}.ason", n), format!("{}", n));
+            file.to_str().unwrap().to_owned()
+        })
+        .collect();
+    let mut args = vec!["-c", "--jobs", "2", "-o", out, "--exit-status", ". > 2"];
+    args.extend(files.iter().map(String::as_str));
+    let output = aq(&args, "");
+    assert_eq!(output.status, 0);
+    assert_eq!(
+        std::fs::read_to_string(&output_file).unwrap(),
+        "false\nfalse\ntrue\n"
+    );
+
+    // the options which the modes cannot apply are rejected
+    let output = aq(&["--watch", "--assert", ".", ".", &files[0]], "");
+    assert_eq!(output.status, 1);
+    assert_eq!(
+        output.stderr,
+        "The option \"--assert\" is not supported by \"--watch\".\n"
+    );
+    let output = aq(&["--in-place=.bak", "--distinct", ".", &files[0]], "");
+    assert_eq!(
+        output.stderr,
+        "The option \"--distinct\" is not supported by \"--in-place\".\n"
+    );
+    let output = aq(&["--lines", "--to", "yaml", "."], "1\n");
+    assert_eq!(
+        output.stderr,
+        "The output format \"yaml\" is not supported by \"--lines\".\n"
+    );
+    let output = aq(&["--incremental", "--compress-output", "gzip", "."], "1");
+    assert_eq!(
+        output.stderr,
+        "The option \"--compress-output\" is not supported by \"--incremental\".\n"
+    );
+}
+
+#[test]
+fn test_compressed_input_modes() {
+    let temp = TempDir::new("compressed-input-modes");
+    let compressed = aq(&["--compress-output", "gzip", "-c", "."], "{n: 1}").bytes;
+    let data = temp.file("data.ason.gz", &compressed);
+    let path = data.to_str().unwrap();
+
+    for mode in [
+        &["--jobs", "2"][..],
+        &["--prefetch", "2"],
+        &["--first-match"],
+        &["--lines"],
+    ] {
+        let mut args = vec!["-c"];
+        args.extend(mode);
+        args.extend([".n", path]);
+        let output = aq(&args, "");
+        assert_eq!(output.status, 0, "{:?}", mode);
+        assert_eq!(output.stdout, "1\n", "{:?}", mode);
+    }
+
+    // the STDIN of the first match is decompressed as well
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_aq"))
+        .args(["-c", "--first-match", ".n"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(&compressed).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+
+    // the file is rewritten in the same compression
+    let output = aq(&["-c", "--in-place=.bak", ".n += 1", path], "");
+    assert_eq!(output.status, 0);
+    assert!(std::fs::read(&data).unwrap().starts_with(&[0x1f, 0x8b]));
+    assert_eq!(aq(&["-c", ".", path], "").stdout, "{n:2}\n");
+}